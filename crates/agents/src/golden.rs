@@ -0,0 +1,177 @@
+//! Golden snapshot tests for adapter parse paths.
+//!
+//! Fixtures live in `golden/<venue>/<name>.jsonl` with one raw frame per line.
+//! The expected normalized output sits next to each fixture in
+//! `<name>.snap.jsonl`. Set `UPDATE_GOLDEN=1` to rewrite snapshots from the
+//! current parser output instead of comparing against them. Packs recorded
+//! by `devtools record` also keep the events as the adapter published them,
+//! with their receive timestamps, in `<name>.events.jsonl`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ingest_core::error::IngestError;
+
+use crate::Adapter;
+
+const SNAPSHOT_SUFFIX: &str = ".snap.jsonl";
+const EVENTS_SUFFIX: &str = ".events.jsonl";
+
+/// Run every frame of `raw` through the adapter and render the resulting
+/// events as one JSON document per line.
+pub fn render(adapter: &dyn Adapter, venue: &str, raw: &str) -> Result<String, IngestError> {
+    let mut out = String::new();
+    for frame in raw.lines().filter(|l| !l.trim().is_empty()) {
+        for event in adapter.parse_frame(venue, frame)? {
+            out.push_str(&serde_json::to_string(&event)?);
+            out.push('\n');
+        }
+    }
+    Ok(out)
+}
+
+/// Fixture files in `dir`, i.e. every `.jsonl` file that is not a snapshot
+/// or recorded events.
+pub fn fixtures(dir: &Path) -> Result<Vec<PathBuf>, IngestError> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default();
+        if name.ends_with(".jsonl")
+            && !name.ends_with(SNAPSHOT_SUFFIX)
+            && !name.ends_with(EVENTS_SUFFIX)
+        {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+pub fn snapshot_path(fixture: &Path) -> PathBuf {
+    sibling(fixture, SNAPSHOT_SUFFIX)
+}
+
+/// Where the recorded events of `fixture` live.
+pub fn events_path(fixture: &Path) -> PathBuf {
+    sibling(fixture, EVENTS_SUFFIX)
+}
+
+fn sibling(fixture: &Path, suffix: &str) -> PathBuf {
+    let stem = fixture
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default()
+        .trim_end_matches(".jsonl");
+    fixture.with_file_name(format!("{}{}", stem, suffix))
+}
+
+/// Check every fixture in `dir` against its snapshot, returning the number of
+/// fixtures checked or a report of all mismatches.
+pub fn check_dir(
+    adapter: &dyn Adapter,
+    venue: &str,
+    dir: impl AsRef<Path>,
+) -> Result<usize, String> {
+    let update = std::env::var("UPDATE_GOLDEN").is_ok_and(|v| v == "1");
+    let files = fixtures(dir.as_ref()).map_err(|e| e.to_string())?;
+    let mut failures = Vec::new();
+    for fixture in &files {
+        let snap = snapshot_path(fixture);
+        let result = fs::read_to_string(fixture)
+            .map_err(IngestError::from)
+            .and_then(|raw| render(adapter, venue, &raw));
+        let actual = match result {
+            Ok(actual) => actual,
+            Err(e) => {
+                failures.push(format!("{}: {}", fixture.display(), e));
+                continue;
+            }
+        };
+        if update {
+            if let Err(e) = fs::write(&snap, &actual) {
+                failures.push(format!("{}: {}", snap.display(), e));
+            }
+            continue;
+        }
+        let expected = fs::read_to_string(&snap).unwrap_or_default();
+        if let Some(report) = diff(&expected, &actual) {
+            failures.push(format!("{}:\n{}", snap.display(), report));
+        }
+    }
+    if failures.is_empty() {
+        Ok(files.len())
+    } else {
+        Err(format!(
+            "golden snapshot mismatch (rerun with UPDATE_GOLDEN=1 to accept):\n{}",
+            failures.join("\n")
+        ))
+    }
+}
+
+/// Line-oriented diff of two snapshots, `None` when they match.
+pub fn diff(expected: &str, actual: &str) -> Option<String> {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let mut report = String::new();
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(e), Some(a)) if e == a => {}
+            (e, a) => {
+                report.push_str(&format!("  line {}:\n", i + 1));
+                if let Some(e) = e {
+                    report.push_str(&format!("  - {}\n", e));
+                }
+                if let Some(a) = a {
+                    report.push_str(&format!("  + {}\n", a));
+                }
+            }
+        }
+    }
+    if report.is_empty() {
+        None
+    } else {
+        Some(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_reports_changed_and_missing_lines() {
+        assert_eq!(diff("a\nb\n", "a\nb\n"), None);
+        let report = diff("a\nb\n", "a\nc\nd\n").unwrap();
+        assert!(report.contains("line 2"));
+        assert!(report.contains("- b"));
+        assert!(report.contains("+ c"));
+        assert!(report.contains("+ d"));
+    }
+
+    #[test]
+    fn snapshot_lives_next_to_fixture() {
+        assert_eq!(
+            snapshot_path(Path::new("golden/binance_spot/trades.jsonl")),
+            PathBuf::from("golden/binance_spot/trades.snap.jsonl")
+        );
+        assert_eq!(
+            events_path(Path::new("golden/binance_spot/trades.jsonl")),
+            PathBuf::from("golden/binance_spot/trades.events.jsonl")
+        );
+    }
+
+    #[test]
+    fn recorded_events_are_not_fixtures() {
+        let dir = std::env::temp_dir().join(format!("ingest-golden-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["trades.jsonl", "trades.snap.jsonl", "trades.events.jsonl"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        assert_eq!(fixtures(&dir).unwrap(), vec![dir.join("trades.jsonl")]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
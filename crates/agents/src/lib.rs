@@ -1,8 +1,14 @@
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use futures_util::StreamExt;
 use ingest_core::{
-    canonical_symbol, config::VenueConfig, error::IngestError, event::NormalizedEvent,
+    canonical_symbol,
+    config::VenueConfig,
+    error::IngestError,
+    event::{Decimal, EventKind, NormalizedEvent, Quote, Side, Subscription, Trade},
 };
+use std::collections::BTreeMap;
+use std::sync::Arc;
 use tokio::sync::mpsc::Sender;
 
 #[async_trait]
@@ -12,6 +18,116 @@ pub trait Adapter: Send + Sync {
         cfg: VenueConfig,
         tx: Sender<NormalizedEvent>,
     ) -> Result<(), IngestError>;
+
+    /// Decode one raw frame received from the venue into normalized events.
+    fn parse_frame(&self, venue: &str, frame: &str) -> Result<Vec<NormalizedEvent>, IngestError>;
+
+    /// Connect as [`connect`](Adapter::connect) does, and send every frame
+    /// received to `frames` as well, as `devtools record` does to capture
+    /// golden packs. Adapters that cannot capture refuse.
+    async fn capture(
+        &self,
+        cfg: VenueConfig,
+        tx: Sender<NormalizedEvent>,
+        frames: Sender<Frame>,
+    ) -> Result<(), IngestError> {
+        let _ = (tx, frames);
+        Err(IngestError::Validation(format!(
+            "adapters of type {} cannot capture frames",
+            cfg.venue_type
+        )))
+    }
+
+    /// Check that the venue is reachable with `cfg`, without ingesting, as
+    /// `ingestd check` does, and return the symbols the adapter would
+    /// subscribe to. Adapters that cannot tell succeed with the configured
+    /// symbols.
+    async fn check(&self, cfg: &VenueConfig) -> Result<Vec<String>, IngestError> {
+        Ok(cfg.symbols.clone())
+    }
+
+    /// The symbols the adapter would subscribe to with `cfg`, discovered
+    /// with its filters if it lists none, without connecting to the stream,
+    /// as `devtools symbols` previews. Adapters that cannot discover return
+    /// the configured symbols.
+    async fn symbols(&self, cfg: &VenueConfig) -> Result<Vec<String>, IngestError> {
+        Ok(cfg.symbols.clone())
+    }
+
+    /// The venue streams the adapter would subscribe to for `symbols` with
+    /// the channels of `cfg`. Adapters that cannot tell have one per symbol.
+    fn streams(&self, cfg: &VenueConfig, symbols: &[String]) -> Vec<String> {
+        let _ = cfg;
+        symbols.to_vec()
+    }
+}
+
+pub mod golden;
+
+/// A text frame as the adapter received it from the venue.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub received_at: DateTime<Utc>,
+    pub text: String,
+}
+
+/// The adapters venues can run, by the `type` of their config.
+#[derive(Clone)]
+pub struct AdapterRegistry {
+    adapters: BTreeMap<String, Arc<dyn Adapter>>,
+}
+
+impl AdapterRegistry {
+    /// A registry without adapters.
+    pub fn empty() -> Self {
+        Self {
+            adapters: BTreeMap::new(),
+        }
+    }
+
+    /// The adapters of this crate.
+    pub fn builtin() -> Self {
+        Self::empty().with("binance", binance::BinanceAdapter)
+    }
+
+    /// Serve venues of `venue_type` with `adapter`, in place of any adapter
+    /// registered for it before.
+    pub fn with(mut self, venue_type: &str, adapter: impl Adapter + 'static) -> Self {
+        self.adapters
+            .insert(venue_type.to_string(), Arc::new(adapter));
+        self
+    }
+
+    pub fn get(&self, venue_type: &str) -> Option<Arc<dyn Adapter>> {
+        self.adapters.get(venue_type).cloned()
+    }
+
+    /// The registered venue types, in order.
+    pub fn types(&self) -> impl Iterator<Item = &str> {
+        self.adapters.keys().map(String::as_str)
+    }
+
+    /// A problem for each of `venues` whose type no adapter serves.
+    pub fn unknown(&self, venues: &[VenueConfig]) -> Vec<String> {
+        venues
+            .iter()
+            .filter(|venue| !self.adapters.contains_key(&venue.venue_type))
+            .map(|venue| {
+                format!(
+                    "venue {} has unknown type {:?}, expected one of: {}",
+                    venue.name,
+                    venue.venue_type,
+                    self.types().collect::<Vec<_>>().join(", ")
+                )
+            })
+            .collect()
+    }
+}
+
+impl Default for AdapterRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
 }
 
 /// A helper macro that implements Adapter for empty structs for prototyping.
@@ -29,10 +145,27 @@ macro_rules! simple_adapter {
                     venue: $name.to_string(),
                     symbol: "DUMMY".into(),
                     timestamp: chrono::Utc::now(),
+                    received_at: None,
+                    kind: ingest_core::event::EventKind::Raw,
                     payload: serde_json::json!({"hello": "world"}),
                 };
                 tx.send(evt).await.map_err(|e| ingest_core::error::IngestError::Validation(e.to_string()))
             }
+
+            fn parse_frame(
+                &self,
+                venue: &str,
+                frame: &str,
+            ) -> Result<Vec<ingest_core::event::NormalizedEvent>, ingest_core::error::IngestError> {
+                Ok(vec![ingest_core::event::NormalizedEvent {
+                    venue: venue.to_string(),
+                    symbol: "DUMMY".into(),
+                    timestamp: chrono::Utc::now(),
+                    received_at: None,
+                    kind: ingest_core::event::EventKind::Raw,
+                    payload: serde_json::from_str(frame)?,
+                }])
+            }
         }
     };
 }
@@ -123,6 +256,23 @@ pub mod binance {
         Ok(symbols)
     }
 
+    /// The WebSocket URL of `streams`, on `ws_base` or the public endpoint.
+    fn stream_url(cfg: &VenueConfig, streams: &[String]) -> String {
+        let base = cfg
+            .ws_base
+            .clone()
+            .unwrap_or_else(|| "wss://stream.binance.com:9443/stream".to_string());
+        if streams.len() == 1 {
+            format!("{}/{}", base.trim_end_matches('/'), streams[0])
+        } else {
+            format!(
+                "{}/stream?streams={}",
+                base.trim_end_matches('/'),
+                streams.join("/")
+            )
+        }
+    }
+
     fn build_streams(cfg: &VenueConfig, symbols: &[String]) -> Vec<String> {
         let mut streams = Vec::new();
         if cfg.channels.trades {
@@ -144,160 +294,346 @@ pub mod binance {
                 }
             }
         }
+        if cfg.channels.book_ticker {
+            streams.extend(
+                symbols
+                    .iter()
+                    .map(|s| format!("{}@bookTicker", s.to_lowercase())),
+            );
+        }
         streams
     }
 
-    #[async_trait]
-    impl Adapter for BinanceAdapter {
-        async fn connect(
-            &self,
-            cfg: VenueConfig,
-            tx: Sender<NormalizedEvent>,
-        ) -> Result<(), IngestError> {
-            let mut symbols = cfg.symbols.clone();
-            if symbols.is_empty() {
-                if let Err(e) = discover_symbols(&cfg).await.map(|s| symbols = s) {
+    /// Stream the venue's events to `tx` until it closes, reconnecting with
+    /// backoff, and every frame received to `frames` too if given.
+    async fn stream(
+        cfg: VenueConfig,
+        tx: Sender<NormalizedEvent>,
+        frames: Option<Sender<Frame>>,
+    ) -> Result<(), IngestError> {
+        let mut symbols = cfg.symbols.clone();
+        if symbols.is_empty() {
+            if let Err(e) = discover_symbols(&cfg).await.map(|s| symbols = s) {
+                tracing::warn!(
+                    "symbol discovery failed for {}: {}. Provide a `symbols` list in config to disable discovery",
+                    cfg.name,
+                    e
+                );
+            }
+        }
+        if symbols.is_empty() {
+            return Ok(());
+        }
+        let streams = build_streams(&cfg, &symbols);
+        if streams.is_empty() {
+            return Ok(());
+        }
+        let subscription = Subscription {
+            venue: cfg.name.clone(),
+            discovered: cfg.symbols.is_empty(),
+            symbols,
+        };
+        let _ = tx
+            .send(NormalizedEvent::adapter_subscription(&subscription))
+            .await;
+
+        let url = stream_url(&cfg, &streams);
+        // Frames of a single stream connection do not name their stream.
+        let single = (streams.len() == 1).then(|| streams[0].as_str());
+        let base_backoff_ms = std::env::var("WS_RETRY_BASE_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(500);
+        let max_backoff_ms = std::env::var("WS_RETRY_MAX_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(30_000);
+        let mut backoff = std::time::Duration::from_millis(base_backoff_ms);
+        loop {
+            let (ws_stream, _) = match connect_async(&url).await {
+                Ok(stream) => {
+                    backoff = std::time::Duration::from_millis(base_backoff_ms);
+                    stream
+                }
+                Err(e) => {
                     tracing::warn!(
-                        "symbol discovery failed for {}: {}. Provide a `symbols` list in config to disable discovery",
+                        "connect error for {}: {}. retrying in {:?}",
                         cfg.name,
-                        e
+                        e,
+                        backoff
                     );
+                    tokio::select! {
+                        _ = tokio::time::sleep(backoff) => {},
+                        _ = tx.closed() => return Ok(()),
+                    }
+                    backoff = std::cmp::min(
+                        backoff * 2,
+                        std::time::Duration::from_millis(max_backoff_ms),
+                    );
+                    continue;
                 }
-            }
-            if symbols.is_empty() {
-                return Ok(());
-            }
-            let streams = build_streams(&cfg, &symbols);
-            if streams.is_empty() {
-                return Ok(());
-            }
-
-            // Use ws_base from config or default to public endpoint.
-            let base = cfg
-                .ws_base
-                .clone()
-                .unwrap_or_else(|| "wss://stream.binance.com:9443/stream".to_string());
-            let url = if streams.len() == 1 {
-                format!("{}/{}", base.trim_end_matches('/'), streams[0])
-            } else {
-                format!(
-                    "{}/stream?streams={}",
-                    base.trim_end_matches('/'),
-                    streams.join("/")
-                )
             };
-            let base_backoff_ms = std::env::var("WS_RETRY_BASE_MS")
-                .ok()
-                .and_then(|v| v.parse().ok())
-                .unwrap_or(500);
-            let max_backoff_ms = std::env::var("WS_RETRY_MAX_MS")
-                .ok()
-                .and_then(|v| v.parse().ok())
-                .unwrap_or(30_000);
-            let mut backoff = std::time::Duration::from_millis(base_backoff_ms);
-            loop {
-                let (ws_stream, _) = match connect_async(&url).await {
-                    Ok(stream) => {
-                        backoff = std::time::Duration::from_millis(base_backoff_ms);
-                        stream
-                    }
-                    Err(e) => {
-                        tracing::warn!(
-                            "connect error for {}: {}. retrying in {:?}",
-                            cfg.name,
-                            e,
-                            backoff
-                        );
-                        tokio::select! {
-                            _ = tokio::time::sleep(backoff) => {},
-                            _ = tx.closed() => return Ok(()),
-                        }
-                        backoff = std::cmp::min(
-                            backoff * 2,
-                            std::time::Duration::from_millis(max_backoff_ms),
-                        );
-                        continue;
-                    }
-                };
-                let (_, mut read) = ws_stream.split();
+            let (_, mut read) = ws_stream.split();
+            let _ = tx
+                .send(NormalizedEvent::adapter_status(&cfg.name, true))
+                .await;
 
-                loop {
-                    tokio::select! {
-                        _ = tx.closed() => return Ok(()),
-                        msg = read.next() => {
-                            match msg {
-                                Some(Ok(msg)) => {
-                                    if !msg.is_text() {
-                                        continue;
-                                    }
-                                    let text = msg
-                                        .into_text()
-                                        .map_err(|e| IngestError::Validation(e.to_string()))?;
-                                    let value: serde_json::Value = serde_json::from_str(&text)?;
-
-                                    // Combined stream messages include a `data` field. For aggregated
-                                    // streams `data` may be an array.
-                                    if let Some(data) = value.get("data") {
-                                        if let Some(arr) = data.as_array() {
-                                            for item in arr {
-                                                process_payload(item.clone(), &cfg, &tx).await?;
-                                            }
-                                        } else {
-                                            process_payload(data.clone(), &cfg, &tx).await?;
-                                        }
-                                    } else {
-                                        process_payload(value, &cfg, &tx).await?;
-                                    }
+            loop {
+                tokio::select! {
+                    _ = tx.closed() => return Ok(()),
+                    msg = read.next() => {
+                        match msg {
+                            Some(Ok(msg)) => {
+                                if !msg.is_text() {
+                                    continue;
                                 }
-                                Some(Err(e)) => {
-                                    tracing::warn!("read error for {}: {}", cfg.name, e);
-                                    break;
+                                let text = msg
+                                    .into_text()
+                                    .map_err(|e| IngestError::Validation(e.to_string()))?;
+                                let received_at = Utc::now();
+                                if let Some(frames) = &frames {
+                                    let _ = frames
+                                        .send(Frame {
+                                            received_at,
+                                            text: text.clone(),
+                                        })
+                                        .await;
                                 }
-                                None => {
-                                    tracing::info!("stream for {} closed", cfg.name);
-                                    break;
+                                match parse_stream_frame(&cfg.name, single, &text) {
+                                    Ok(events) => {
+                                        for mut event in events {
+                                            event.received_at = Some(received_at);
+                                            let _ = tx.send(event).await;
+                                        }
+                                    }
+                                    Err(e) => tracing::warn!(
+                                        "dropping undecodable frame from {}: {}",
+                                        cfg.name,
+                                        e
+                                    ),
                                 }
                             }
+                            Some(Err(e)) => {
+                                tracing::warn!("read error for {}: {}", cfg.name, e);
+                                break;
+                            }
+                            None => {
+                                tracing::info!("stream for {} closed", cfg.name);
+                                break;
+                            }
                         }
                     }
                 }
+            }
 
-                tracing::info!("reconnecting to {}", cfg.name);
-                tokio::select! {
-                    _ = tokio::time::sleep(backoff) => {},
-                    _ = tx.closed() => return Ok(()),
+            let _ = tx
+                .send(NormalizedEvent::adapter_status(&cfg.name, false))
+                .await;
+            tracing::info!("reconnecting to {}", cfg.name);
+            tokio::select! {
+                _ = tokio::time::sleep(backoff) => {},
+                _ = tx.closed() => return Ok(()),
+            }
+            backoff = std::cmp::min(
+                backoff * 2,
+                std::time::Duration::from_millis(max_backoff_ms),
+            );
+        }
+    }
+
+    #[async_trait]
+    impl Adapter for BinanceAdapter {
+        async fn connect(
+            &self,
+            cfg: VenueConfig,
+            tx: Sender<NormalizedEvent>,
+        ) -> Result<(), IngestError> {
+            stream(cfg, tx, None).await
+        }
+
+        async fn capture(
+            &self,
+            cfg: VenueConfig,
+            tx: Sender<NormalizedEvent>,
+            frames: Sender<Frame>,
+        ) -> Result<(), IngestError> {
+            stream(cfg, tx, Some(frames)).await
+        }
+
+        fn parse_frame(
+            &self,
+            venue: &str,
+            frame: &str,
+        ) -> Result<Vec<NormalizedEvent>, IngestError> {
+            parse_frame(venue, frame)
+        }
+
+        /// Discover symbols if the config asks to, then open and close the
+        /// stream socket.
+        async fn check(&self, cfg: &VenueConfig) -> Result<Vec<String>, IngestError> {
+            let symbols = self.symbols(cfg).await?;
+            let streams = build_streams(cfg, &symbols);
+            if streams.is_empty() {
+                return Err(IngestError::Validation(format!(
+                    "venue {} has no streams to subscribe to",
+                    cfg.name
+                )));
+            }
+            let url = stream_url(cfg, &streams);
+            let timeout = std::time::Duration::from_secs(cfg.http_timeout_secs.unwrap_or(10));
+            let (mut ws_stream, _) = tokio::time::timeout(timeout, connect_async(&url))
+                .await
+                .map_err(|_| IngestError::Validation(format!("connecting to {} timed out", url)))?
+                .map_err(|e| IngestError::Validation(format!("{}: {}", url, e)))?;
+            let _ = ws_stream.close(None).await;
+            Ok(symbols)
+        }
+
+        async fn symbols(&self, cfg: &VenueConfig) -> Result<Vec<String>, IngestError> {
+            if cfg.symbols.is_empty() {
+                discover_symbols(cfg).await
+            } else {
+                Ok(cfg.symbols.clone())
+            }
+        }
+
+        fn streams(&self, cfg: &VenueConfig, symbols: &[String]) -> Vec<String> {
+            build_streams(cfg, symbols)
+        }
+    }
+
+    /// Decode a raw websocket frame. Combined stream messages wrap the payload
+    /// in a `data` field beside the name of its `stream`, and aggregated
+    /// streams deliver arrays of payloads. Payloads that fail validation are
+    /// dropped with a warning.
+    pub fn parse_frame(venue: &str, text: &str) -> Result<Vec<NormalizedEvent>, IngestError> {
+        parse_stream_frame(venue, None, text)
+    }
+
+    /// Decode a frame received on `stream`, which combined stream messages
+    /// name themselves.
+    fn parse_stream_frame(
+        venue: &str,
+        stream: Option<&str>,
+        text: &str,
+    ) -> Result<Vec<NormalizedEvent>, IngestError> {
+        let value: serde_json::Value = serde_json::from_str(text)?;
+        let mut stream = stream.map(str::to_string);
+        let value = match value {
+            serde_json::Value::Object(mut obj) if obj.contains_key("data") => {
+                if let Some(serde_json::Value::String(name)) = obj.remove("stream") {
+                    stream = Some(name);
                 }
-                backoff = std::cmp::min(
-                    backoff * 2,
-                    std::time::Duration::from_millis(max_backoff_ms),
-                );
+                obj.remove("data").unwrap_or_default()
+            }
+            other => other,
+        };
+        let payloads = match value {
+            serde_json::Value::Array(arr) => arr,
+            other => vec![other],
+        };
+        let mut events = Vec::with_capacity(payloads.len());
+        for payload in payloads {
+            match parse_payload(venue, stream.as_deref(), payload) {
+                Ok(event) => events.push(event),
+                Err(e) => tracing::warn!("dropping malformed payload from {}: {}", venue, e),
             }
         }
+        Ok(events)
     }
 
-    async fn process_payload(
+    /// Normalize a single Binance stream payload, received on `stream` if
+    /// known. Trades and aggregate trades are decoded into the typed
+    /// [`Trade`] payload and book tickers into [`Quote`], keeping prices and
+    /// quantities as the exact decimals Binance sends; other streams are
+    /// passed through as-is. Events are stamped with the trade time `T`
+    /// where there is one, and the event time `E` otherwise.
+    pub fn parse_payload(
+        venue: &str,
+        stream: Option<&str>,
         payload: serde_json::Value,
-        cfg: &VenueConfig,
-        tx: &Sender<NormalizedEvent>,
-    ) -> Result<(), IngestError> {
+    ) -> Result<NormalizedEvent, IngestError> {
         let symbol = payload
             .get("s")
             .and_then(|v| v.as_str())
             .unwrap_or("unknown")
             .to_string();
         let t_ms = payload
-            .get("E")
+            .get("T")
+            .or_else(|| payload.get("E"))
             .and_then(|v| v.as_i64())
             .unwrap_or_else(|| Utc::now().timestamp_millis());
+        #[allow(clippy::redundant_closure)]
         let ts = DateTime::<Utc>::from_timestamp_millis(t_ms).unwrap_or_else(|| Utc::now());
-        let event = NormalizedEvent {
-            venue: cfg.name.clone(),
+        let (kind, payload) = match payload.get("e").and_then(|v| v.as_str()) {
+            Some("trade") => (
+                EventKind::Trade,
+                serde_json::to_value(parse_trade(&payload, "t")?)?,
+            ),
+            // Aggregate trades are numbered apart from trades.
+            Some("aggTrade") => (
+                EventKind::Trade,
+                serde_json::to_value(parse_trade(&payload, "a")?)?,
+            ),
+            Some("24hrTicker") => (EventKind::Ticker, payload),
+            // Spot bookTicker payloads carry no event type, only their
+            // stream names them.
+            None if stream.is_some_and(|s| s.ends_with("@bookTicker")) => (
+                EventKind::Quote,
+                serde_json::to_value(parse_quote(&payload)?)?,
+            ),
+            Some("bookTicker") => (
+                EventKind::Quote,
+                serde_json::to_value(parse_quote(&payload)?)?,
+            ),
+            _ => (EventKind::Raw, payload),
+        };
+        Ok(NormalizedEvent {
+            venue: venue.to_string(),
             symbol: canonical_symbol(&symbol),
             timestamp: ts,
+            received_at: None,
+            kind,
             payload,
-        };
-        let _ = tx.send(event).await;
-        Ok(())
+        })
+    }
+
+    fn decimal_field(payload: &serde_json::Value, field: &str) -> Result<Decimal, IngestError> {
+        payload
+            .get(field)
+            .and_then(|v| v.as_str())
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(|| IngestError::Validation(format!("field `{}` missing or invalid", field)))
+    }
+
+    fn parse_trade(payload: &serde_json::Value, id: &str) -> Result<Trade, IngestError> {
+        let trade_id = payload.get(id).and_then(|v| v.as_u64()).ok_or_else(|| {
+            IngestError::Validation(format!("trade field `{}` missing or invalid", id))
+        })?;
+        let buyer_is_maker = payload
+            .get("m")
+            .and_then(|v| v.as_bool())
+            .ok_or_else(|| IngestError::Validation("trade field `m` missing or invalid".into()))?;
+        Ok(Trade {
+            trade_id,
+            price: decimal_field(payload, "p")?,
+            quantity: decimal_field(payload, "q")?,
+            // When the buyer is the maker the aggressor was the seller.
+            side: if buyer_is_maker {
+                Side::Sell
+            } else {
+                Side::Buy
+            },
+        })
+    }
+
+    fn parse_quote(payload: &serde_json::Value) -> Result<Quote, IngestError> {
+        Ok(Quote {
+            bid_price: decimal_field(payload, "b")?,
+            bid_quantity: decimal_field(payload, "B")?,
+            ask_price: decimal_field(payload, "a")?,
+            ask_quantity: decimal_field(payload, "A")?,
+        })
     }
 
     #[cfg(test)]
@@ -310,6 +646,7 @@ pub mod binance {
         fn base_cfg() -> VenueConfig {
             VenueConfig {
                 name: "binance".into(),
+                venue_type: "binance".into(),
                 symbols: vec!["BTCUSDT".into()],
                 discover: false,
                 ws_base: None,
@@ -321,11 +658,28 @@ pub mod binance {
                         enabled: true,
                         mode: None,
                     }),
+                    book_ticker: false,
                 },
                 discovery: None,
             }
         }
 
+        #[test]
+        fn registry_finds_adapters_by_venue_type() {
+            assert!(crate::AdapterRegistry::builtin().get("binance").is_some());
+            // Not the builtin registry, which scaffolded adapters extend.
+            let registry = crate::AdapterRegistry::empty().with("binance", BinanceAdapter);
+            let unknown = VenueConfig {
+                name: "kraken_spot".into(),
+                venue_type: "kraken".into(),
+                ..base_cfg()
+            };
+            assert_eq!(
+                registry.unknown(&[base_cfg(), unknown]),
+                ["venue kraken_spot has unknown type \"kraken\", expected one of: binance"]
+            );
+        }
+
         #[test]
         fn build_trade_and_ticker_streams() {
             let cfg = base_cfg();
@@ -334,6 +688,99 @@ pub mod binance {
             assert!(streams.contains(&"btcusdt@ticker".to_string()));
         }
 
+        #[test]
+        fn parse_golden_trades() {
+            let data = include_str!("../../../golden/binance_spot/trades.jsonl");
+            let events: Vec<NormalizedEvent> = data
+                .lines()
+                .map(|line| {
+                    parse_payload("binance", None, serde_json::from_str(line).unwrap()).unwrap()
+                })
+                .collect();
+            assert_eq!(events.len(), 2);
+            let first = &events[0];
+            assert_eq!(first.kind, EventKind::Trade);
+            assert_eq!(first.symbol, "BTCUSDT");
+            assert_eq!(first.timestamp.timestamp_millis(), 1672515782136);
+            assert_eq!(
+                first.trade(),
+                Some(Trade {
+                    trade_id: 3370034463,
+                    price: "16541.12".parse().unwrap(),
+                    quantity: "0.0031".parse().unwrap(),
+                    side: Side::Sell,
+                })
+            );
+            assert_eq!(first.payload["price"], "16541.12000000");
+            assert_eq!(events[1].trade().unwrap().side, Side::Buy);
+            // Stamped with the trade time, a millisecond before the event.
+            assert_eq!(events[1].timestamp.timestamp_millis(), 1672515782411);
+        }
+
+        #[test]
+        fn golden_snapshots() {
+            let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../../golden/binance_spot");
+            let checked = crate::golden::check_dir(&BinanceAdapter, "binance_spot", dir)
+                .unwrap_or_else(|e| panic!("{}", e));
+            assert!(checked > 0);
+        }
+
+        #[test]
+        fn combined_frame_with_array_data() {
+            let frame = r#"{"stream":"!ticker@arr","data":[{"e":"24hrTicker","E":1,"s":"BTCUSDT"},{"e":"24hrTicker","E":1,"s":"ETHUSDT"}]}"#;
+            let events = parse_frame("binance", frame).unwrap();
+            assert_eq!(events.len(), 2);
+            assert!(events.iter().all(|e| e.kind == EventKind::Ticker));
+        }
+
+        #[test]
+        fn parse_book_ticker_quote() {
+            let payload = serde_json::json!({"u": 400900217, "s": "BNBUSDT", "b": "25.35190000", "B": "31.21000000", "a": "25.36520000", "A": "40.66000000"});
+            let evt = parse_payload("binance", Some("bnbusdt@bookTicker"), payload).unwrap();
+            assert_eq!(evt.kind, EventKind::Quote);
+            let quote = evt.quote().unwrap();
+            assert_eq!(quote.bid_price, "25.3519".parse::<Decimal>().unwrap());
+            assert_eq!(quote.ask_quantity, "40.66".parse::<Decimal>().unwrap());
+        }
+
+        #[test]
+        fn book_tickers_are_told_by_their_stream() {
+            let data = r#"{"u":400900217,"s":"BNBUSDT","b":"25.35190000","B":"31.21000000","a":"25.36520000","A":"40.66000000"}"#;
+            let frame = format!(r#"{{"stream":"bnbusdt@bookTicker","data":{}}}"#, data);
+            assert_eq!(
+                parse_frame("binance", &frame).unwrap()[0].kind,
+                EventKind::Quote
+            );
+            let frame = format!(r#"{{"stream":"bnbusdt@depth","data":{}}}"#, data);
+            assert_eq!(
+                parse_frame("binance", &frame).unwrap()[0].kind,
+                EventKind::Raw
+            );
+            let events = parse_stream_frame("binance", Some("bnbusdt@bookTicker"), data).unwrap();
+            assert_eq!(events[0].kind, EventKind::Quote);
+        }
+
+        #[test]
+        fn parse_aggregate_trade() {
+            let payload = serde_json::json!({"e": "aggTrade", "E": 1672515782136u64, "s": "BTCUSDT", "a": 26129, "p": "0.01633102", "q": "4.70443515", "f": 27781, "l": 27781, "T": 1672515782136u64, "m": true, "M": true});
+            let evt = parse_payload("binance", None, payload).unwrap();
+            assert_eq!(
+                evt.trade(),
+                Some(Trade {
+                    trade_id: 26129,
+                    price: "0.01633102".parse().unwrap(),
+                    quantity: "4.70443515".parse().unwrap(),
+                    side: Side::Sell,
+                })
+            );
+        }
+
+        #[test]
+        fn malformed_trade_is_rejected() {
+            let payload = serde_json::json!({"e": "trade", "s": "BTCUSDT", "t": 1, "p": "x", "q": "1", "m": false});
+            assert!(parse_payload("binance", None, payload).is_err());
+        }
+
         #[test]
         fn build_aggregate_ticker_stream() {
             let mut cfg = base_cfg();
@@ -381,6 +828,45 @@ pub mod binance {
             (format!("http://{}", addr), handle)
         }
 
+        #[tokio::test]
+        async fn symbols_are_discovered_with_the_filters() {
+            let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let handle = tokio::spawn(async move {
+                let body = r#"{"symbols": [
+                    {"symbol": "BTCUSDT", "quoteAsset": "USDT", "status": "TRADING"},
+                    {"symbol": "ETHUSDT", "quoteAsset": "USDT", "status": "TRADING"},
+                    {"symbol": "LUNAUSDT", "quoteAsset": "USDT", "status": "BREAK"},
+                    {"symbol": "ETHBTC", "quoteAsset": "BTC", "status": "TRADING"}
+                ]}"#;
+                if let Ok((mut socket, _)) = listener.accept().await {
+                    let mut buf = [0u8; 1024];
+                    let _ = socket.read(&mut buf).await;
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                        body.len(),
+                        body
+                    );
+                    let _ = socket.write_all(response.as_bytes()).await;
+                }
+            });
+            let mut cfg = base_cfg();
+            cfg.symbols.clear();
+            cfg.rest_base = Some(format!("http://{}", addr));
+            cfg.discovery = Some(DiscoveryConfig {
+                enabled: true,
+                quote_whitelist: vec!["USDT".into()],
+                symbol_blacklist: vec!["ETHUSDT".into()],
+            });
+            let symbols = BinanceAdapter.symbols(&cfg).await.unwrap();
+            assert_eq!(symbols, vec!["BTCUSDT".to_string()]);
+            assert_eq!(
+                BinanceAdapter.streams(&cfg, &symbols),
+                vec!["btcusdt@trade".to_string(), "btcusdt@ticker".to_string()]
+            );
+            handle.abort();
+        }
+
         #[tokio::test]
         async fn discover_symbols_reports_404() {
             let (base, handle) = start_mock_server(404).await;
@@ -444,5 +930,20 @@ pub mod binance {
             }
             handle.abort();
         }
+
+        #[tokio::test]
+        async fn check_reports_the_stream_it_cannot_open() {
+            let (base, handle) = start_mock_server(404).await;
+            let mut cfg = base_cfg();
+            cfg.ws_base = Some(base.replace("http://", "ws://"));
+            let err = BinanceAdapter.check(&cfg).await.unwrap_err().to_string();
+            assert!(err.contains("btcusdt@trade"), "{}", err);
+            handle.abort();
+
+            cfg.symbols.clear();
+            cfg.channels.trades = false;
+            let err = BinanceAdapter.check(&cfg).await.unwrap_err().to_string();
+            assert!(err.contains("no streams"), "{}", err);
+        }
     }
 }
@@ -0,0 +1,230 @@
+//! Sequence-aware consumers of the broadcast lanes.
+//!
+//! Every published event has a sequence number. A consumer remembers the
+//! sequence of the next event it expects, so after falling behind, or when
+//! subscribed from an earlier sequence, it first catches up from the lanes'
+//! replay buffers or the bus [`History`] and then continues with the live
+//! events, dropping the overlap by sequence. Offsets acknowledged under a
+//! consumer name are kept on the bus, and in a file with
+//! [`EventBus::with_offsets`], for [`EventBus::resume`].
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Weak};
+
+use ingest_core::{error::IngestError, event::NormalizedEvent};
+use tokio_stream::{
+    wrappers::{errors::BroadcastStreamRecvError, BroadcastStream},
+    StreamExt, StreamMap,
+};
+
+use crate::offsets::Offsets;
+use crate::{replay_from, EventBus, Lag, Lanes, Sequenced};
+
+/// Events read from the history per catch-up step.
+const CATCH_UP_CHUNK: usize = 1024;
+
+/// Events older than the replay buffers hold, such as a write-ahead log.
+pub trait History: Send + Sync {
+    /// Up to `limit` events from `sequence` onwards, in order.
+    fn read(&self, sequence: u64, limit: usize)
+        -> Result<Vec<(u64, NormalizedEvent)>, IngestError>;
+}
+
+pub struct EventConsumer {
+    live: StreamMap<usize, BroadcastStream<Sequenced>>,
+    /// Held weakly so consumers do not keep the lanes open.
+    shards: Weak<Lanes>,
+    history: Option<Arc<dyn History>>,
+    lag: Lag,
+    /// Sequence the consumer was started from.
+    from: Option<u64>,
+    /// Last sequence delivered from each lane. Sequences only increase
+    /// within a lane; merged lanes may interleave out of order.
+    seen: Vec<u64>,
+    /// Caught-up events waiting to be delivered, with their lane.
+    backlog: VecDeque<(usize, Sequenced)>,
+    /// Sequence to catch up from before taking live events again.
+    catch_up: Option<u64>,
+    /// Events a lane lagged by while catching up.
+    missed: Option<u64>,
+    /// Set when catching up from `from` failed, until the next live event
+    /// shows how many events were lost.
+    gap: bool,
+    name: Option<String>,
+    offsets: Arc<Offsets>,
+    acked: Option<u64>,
+}
+
+impl EventConsumer {
+    pub(crate) fn new(bus: &EventBus, from: Option<u64>, name: Option<&str>) -> Self {
+        // Subscribe before catching up so nothing published meanwhile is
+        // missed.
+        let mut live = StreamMap::new();
+        for (i, shard) in bus.shards.iter().enumerate() {
+            live.insert(i, BroadcastStream::new(shard.tx.subscribe()));
+        }
+        Self {
+            live,
+            shards: Arc::downgrade(&bus.shards),
+            history: bus.history.clone(),
+            lag: Lag::new(name.unwrap_or("broadcast"), bus.lag_policy, &bus.metrics),
+            from,
+            seen: vec![0; bus.shards.len()],
+            backlog: VecDeque::new(),
+            catch_up: from,
+            missed: None,
+            gap: false,
+            name: name.map(str::to_string),
+            offsets: bus.offsets.clone(),
+            acked: None,
+        }
+    }
+
+    /// The next event, or a gap marker under [`LagPolicy::GapMarker`]. Returns
+    /// `None` once the bus is gone, or after losing events under
+    /// [`LagPolicy::Disconnect`].
+    ///
+    /// [`LagPolicy::GapMarker`]: ingest_core::config::LagPolicy::GapMarker
+    /// [`LagPolicy::Disconnect`]: ingest_core::config::LagPolicy::Disconnect
+    pub async fn recv(&mut self) -> Option<NormalizedEvent> {
+        self.recv_sequenced().await.map(|(_, event)| event)
+    }
+
+    /// Like [`recv`](Self::recv), with the event's sequence number. Gap
+    /// markers have sequence 0.
+    pub async fn recv_sequenced(&mut self) -> Option<(u64, NormalizedEvent)> {
+        loop {
+            if let Some((lane, (sequence, event))) = self.backlog.pop_front() {
+                if self.fresh(lane, sequence) {
+                    self.seen[lane] = sequence;
+                    return Some((sequence, event));
+                }
+                continue;
+            }
+            if let Some(sequence) = self.catch_up.take() {
+                if self.catch_up_from(sequence).await {
+                    continue;
+                }
+                match self.missed.take() {
+                    Some(missed) => match self.lag.lagged(missed) {
+                        Ok(Some(marker)) => return Some((0, marker)),
+                        Ok(None) => continue,
+                        Err(()) => return None,
+                    },
+                    None => {
+                        self.gap = true;
+                        continue;
+                    }
+                }
+            }
+            match self.live.next().await? {
+                (lane, Ok((sequence, event))) => {
+                    if !self.fresh(lane, sequence) {
+                        continue;
+                    }
+                    if std::mem::take(&mut self.gap) {
+                        let missed = sequence.saturating_sub(self.from.unwrap_or(sequence));
+                        if missed > 0 {
+                            self.backlog.push_front((lane, (sequence, event)));
+                            match self.lag.lagged(missed) {
+                                Ok(Some(marker)) => return Some((0, marker)),
+                                Ok(None) => continue,
+                                Err(()) => return None,
+                            }
+                        }
+                    }
+                    self.seen[lane] = sequence;
+                    return Some((sequence, event));
+                }
+                (lane, Err(BroadcastStreamRecvError::Lagged(missed))) => {
+                    let resume = match self.seen[lane] {
+                        0 => self.from,
+                        seen => Some(seen + 1),
+                    };
+                    if let Some(sequence) = resume {
+                        self.catch_up = Some(sequence);
+                        self.missed = Some(missed);
+                        continue;
+                    }
+                    match self.lag.lagged(missed) {
+                        Ok(Some(marker)) => return Some((0, marker)),
+                        Ok(None) => continue,
+                        Err(()) => return None,
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether an event of `lane` has not been delivered yet.
+    fn fresh(&self, lane: usize, sequence: u64) -> bool {
+        sequence >= self.from.unwrap_or(0) && sequence > self.seen[lane]
+    }
+
+    /// Queue the events from `sequence` out of the replay buffers, or else
+    /// the next chunk of them out of the history. Returns false if neither
+    /// holds them.
+    async fn catch_up_from(&mut self, sequence: u64) -> bool {
+        let Some(shards) = self.shards.upgrade() else {
+            return false;
+        };
+        if let Some(events) = replay_from(&shards, sequence) {
+            self.backlog.extend(with_lanes(&shards, events));
+            self.missed = None;
+            return true;
+        }
+        let Some(history) = self.history.clone() else {
+            return false;
+        };
+        match tokio::task::spawn_blocking(move || history.read(sequence, CATCH_UP_CHUNK)).await {
+            Ok(Ok(events)) => {
+                // An exhausted history leaves the rest to the live events.
+                if let Some((last, _)) = events.last() {
+                    self.catch_up = Some(last + 1);
+                } else {
+                    self.missed = None;
+                }
+                self.backlog.extend(with_lanes(&shards, events));
+                true
+            }
+            Ok(Err(e)) => {
+                tracing::warn!("bus consumer failed to read history: {}", e);
+                false
+            }
+            Err(e) => {
+                tracing::warn!("bus consumer failed to read history: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Sequence of the last event received, or the one before the sequence
+    /// the consumer was started from.
+    pub fn last_sequence(&self) -> Option<u64> {
+        let seen = self.seen.iter().copied().max().filter(|&seen| seen > 0);
+        seen.or_else(|| self.from.map(|from| from.saturating_sub(1)))
+    }
+
+    /// Mark everything up to `sequence` as processed. For a consumer
+    /// started with [`EventBus::resume`], the offset is kept on the bus so a
+    /// later consumer of the same name continues after it.
+    pub fn ack(&mut self, sequence: u64) {
+        let acked = self.acked.map_or(sequence, |acked| acked.max(sequence));
+        self.acked = Some(acked);
+        if let Some(name) = &self.name {
+            self.offsets.ack(name, acked);
+        }
+    }
+
+    /// The highest sequence passed to [`ack`](Self::ack).
+    pub fn acked(&self) -> Option<u64> {
+        self.acked
+    }
+}
+
+fn with_lanes(
+    lanes: &Lanes,
+    events: Vec<Sequenced>,
+) -> impl Iterator<Item = (usize, Sequenced)> + '_ {
+    events.into_iter().map(|event| (lanes.of(&event.1), event))
+}
@@ -0,0 +1,261 @@
+//! Request/response control channel alongside the event stream.
+//!
+//! Whatever runs the engine holds the [`ControlRequests`] end and answers
+//! each [`ControlRequest`]; embedders and the ops admin endpoints hold
+//! cloneable [`ControlHandle`]s to query state and pause or resume venues.
+
+use std::collections::BTreeMap;
+
+use ingest_core::{config::ConfigDiff, error::IngestError};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, oneshot};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub enum ControlRequest {
+    /// Configured venues and whether each is paused.
+    Venues,
+    /// Symbols seen on the bus, for one venue or all of them.
+    Symbols { venue: Option<String> },
+    /// Per-stage pipeline counters.
+    PipelineStats,
+    /// Stop the adapter of one venue, or of every venue, until resumed.
+    Pause { venue: Option<String> },
+    /// Undo a [`ControlRequest::Pause`].
+    Resume { venue: Option<String> },
+    /// Change the symbols a venue's adapter subscribes to, answered with the
+    /// resulting list.
+    EditSymbols {
+        venue: String,
+        #[serde(default)]
+        add: Vec<String>,
+        #[serde(default)]
+        remove: Vec<String>,
+    },
+    /// Re-read and validate the config file, and apply what changed.
+    Reload,
+    /// The active log filter directives.
+    LogFilter,
+    /// Replace the log filter, e.g. with `info,sinks::kafka=debug`,
+    /// answered with the directives now active.
+    SetLogFilter { filter: String },
+    /// Stop taking in events, drain the pipeline and the sinks, and exit.
+    Shutdown,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlResponse {
+    Venues(Vec<VenueStatus>),
+    /// Symbols by venue.
+    Symbols(BTreeMap<String, Vec<String>>),
+    PipelineStats(Vec<StageStats>),
+    /// Symbols configured for one venue.
+    VenueSymbols(Vec<String>),
+    Reloaded(ReloadReport),
+    LogFilter(String),
+    /// A command was carried out.
+    Done,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VenueStatus {
+    pub name: String,
+    pub paused: bool,
+}
+
+/// The outcome of a [`ControlRequest::Reload`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct ReloadReport {
+    /// Whether the new config was taken into use, which it is not if there
+    /// are any errors.
+    pub applied: bool,
+    /// Why the config could not be read, parsed or validated.
+    pub errors: Vec<String>,
+    /// Venue changes are applied right away; other sections take effect on
+    /// the next restart.
+    pub changes: ConfigDiff,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StageStats {
+    pub stage: String,
+    pub processed: u64,
+    pub queue_depth: i64,
+    pub restarts: u64,
+}
+
+type Reply = oneshot::Sender<Result<ControlResponse, IngestError>>;
+
+/// Create a control channel holding up to `capacity` unanswered requests.
+pub fn channel(capacity: usize) -> (ControlHandle, ControlRequests) {
+    let (tx, rx) = mpsc::channel(capacity.max(1));
+    (ControlHandle { tx }, ControlRequests { rx })
+}
+
+#[derive(Clone)]
+pub struct ControlHandle {
+    tx: mpsc::Sender<(ControlRequest, Reply)>,
+}
+
+impl ControlHandle {
+    /// Send `request` and wait for its answer.
+    pub async fn request(&self, request: ControlRequest) -> Result<ControlResponse, IngestError> {
+        let (reply, answer) = oneshot::channel();
+        let closed = || IngestError::Control("control channel closed".into());
+        self.tx.send((request, reply)).await.map_err(|_| closed())?;
+        answer.await.map_err(|_| closed())?
+    }
+
+    pub async fn venues(&self) -> Result<Vec<VenueStatus>, IngestError> {
+        match self.request(ControlRequest::Venues).await? {
+            ControlResponse::Venues(venues) => Ok(venues),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    pub async fn symbols(
+        &self,
+        venue: Option<&str>,
+    ) -> Result<BTreeMap<String, Vec<String>>, IngestError> {
+        let venue = venue.map(str::to_string);
+        match self.request(ControlRequest::Symbols { venue }).await? {
+            ControlResponse::Symbols(symbols) => Ok(symbols),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    pub async fn pipeline_stats(&self) -> Result<Vec<StageStats>, IngestError> {
+        match self.request(ControlRequest::PipelineStats).await? {
+            ControlResponse::PipelineStats(stats) => Ok(stats),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    pub async fn pause(&self, venue: Option<&str>) -> Result<(), IngestError> {
+        let venue = venue.map(str::to_string);
+        self.request(ControlRequest::Pause { venue })
+            .await
+            .map(drop)
+    }
+
+    pub async fn resume(&self, venue: Option<&str>) -> Result<(), IngestError> {
+        let venue = venue.map(str::to_string);
+        self.request(ControlRequest::Resume { venue })
+            .await
+            .map(drop)
+    }
+
+    /// Subscribe `venue` to the `add` symbols and drop the `remove` ones,
+    /// returning the symbols it is subscribed to afterwards.
+    pub async fn edit_symbols(
+        &self,
+        venue: &str,
+        add: Vec<String>,
+        remove: Vec<String>,
+    ) -> Result<Vec<String>, IngestError> {
+        let request = ControlRequest::EditSymbols {
+            venue: venue.to_string(),
+            add,
+            remove,
+        };
+        match self.request(request).await? {
+            ControlResponse::VenueSymbols(symbols) => Ok(symbols),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    pub async fn reload(&self) -> Result<ReloadReport, IngestError> {
+        match self.request(ControlRequest::Reload).await? {
+            ControlResponse::Reloaded(report) => Ok(report),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    pub async fn log_filter(&self) -> Result<String, IngestError> {
+        match self.request(ControlRequest::LogFilter).await? {
+            ControlResponse::LogFilter(filter) => Ok(filter),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Fails with [`IngestError::Validation`] if `filter` does not parse.
+    pub async fn set_log_filter(&self, filter: &str) -> Result<String, IngestError> {
+        let filter = filter.to_string();
+        match self
+            .request(ControlRequest::SetLogFilter { filter })
+            .await?
+        {
+            ControlResponse::LogFilter(filter) => Ok(filter),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Answered once the engine has begun shutting down, not once it is
+    /// done.
+    pub async fn shutdown(&self) -> Result<(), IngestError> {
+        self.request(ControlRequest::Shutdown).await.map(drop)
+    }
+}
+
+fn unexpected(response: ControlResponse) -> IngestError {
+    IngestError::Control(format!("unexpected response {:?}", response))
+}
+
+/// The answering end of a control channel.
+pub struct ControlRequests {
+    rx: mpsc::Receiver<(ControlRequest, Reply)>,
+}
+
+impl ControlRequests {
+    /// The next request, or `None` once every handle is gone.
+    pub async fn next(&mut self) -> Option<PendingRequest> {
+        let (request, reply) = self.rx.recv().await?;
+        Some(PendingRequest { request, reply })
+    }
+}
+
+/// A request waiting for its answer.
+pub struct PendingRequest {
+    pub request: ControlRequest,
+    reply: Reply,
+}
+
+impl PendingRequest {
+    pub fn respond(self, response: Result<ControlResponse, IngestError>) {
+        // The caller may have stopped waiting.
+        let _ = self.reply.send(response);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn answers_requests_in_order() {
+        let (handle, mut requests) = channel(4);
+        tokio::spawn(async move {
+            let mut paused = false;
+            while let Some(pending) = requests.next().await {
+                let response = match &pending.request {
+                    ControlRequest::Venues => Ok(ControlResponse::Venues(vec![VenueStatus {
+                        name: "binance_spot".into(),
+                        paused,
+                    }])),
+                    ControlRequest::Pause { .. } => {
+                        paused = true;
+                        Ok(ControlResponse::Done)
+                    }
+                    _ => Err(IngestError::Control("unsupported".into())),
+                };
+                pending.respond(response);
+            }
+        });
+
+        assert!(!handle.venues().await.unwrap()[0].paused);
+        handle.pause(Some("binance_spot")).await.unwrap();
+        assert!(handle.venues().await.unwrap()[0].paused);
+        assert!(handle.pipeline_stats().await.is_err());
+    }
+}
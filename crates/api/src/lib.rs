@@ -1,57 +1,1297 @@
-use ingest_core::event::NormalizedEvent;
-use tokio::sync::broadcast;
-use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
+//! In-process event bus.
+//!
+//! Every event has a topic `<kind>.<venue>.<symbol>`, e.g.
+//! `trades.binance_spot.BTCUSDT`, where the kind is one of `trades`,
+//! `tickers`, `quotes`, `books` and `raw`. Filtered subscribers are indexed by
+//! kind and venue, so publishing only visits the subscribers interested in
+//! that slice and a flood of trades never wakes a subscriber to one venue's
+//! quotes.
+//!
+//! Events travel on one of several logical buses by [`BusKind`] (trades,
+//! books, tickers, derived, raw and control), each with broadcast buffers of
+//! its own, so a burst of book updates cannot evict trades from a shared
+//! buffer. Engine events on the control bus, such as adapter status, reach
+//! filtered subscribers on a priority lane that a full queue cannot hold up.
+//! Each bus can be split further into lanes by symbol hash (see
+//! [`EventBus::with_shards`]) so publishing scales past a single channel.
+//!
+//! The bus also keeps the latest event per venue, symbol and kind, which
+//! [`EventBus::snapshot`] reads back as the current state of the world. Its own
+//! internals are dumped by [`EventBus::state`].
+//!
+//! Filtered subscribers are named and each has a bounded queue of its own,
+//! whose capacity and [`LagPolicy`] can be set per name in the bus config.
+//! A subscriber that falls behind misses events: a broadcast subscriber once
+//! the bus capacity is exceeded, a filtered one once its queue is full. The
+//! policy decides whether it is disconnected, the loss is logged, or it
+//! receives a [`gap_marker`] in place of the missed events. Losses and queue
+//! depths are exported per subscriber name in [`BusMetrics`]. A subscriber
+//! configured with a `spill` writes what does not fit to disk instead, see
+//! [`Spill`]. [`EventBus::subscription`] builds filtered subscribers
+//! fluently, optionally starting from recent events.
 
+pub mod control;
+mod consumer;
+mod offsets;
+mod spill;
+mod state;
+mod subscription;
+
+pub use consumer::{EventConsumer, History};
+pub use spill::Spill;
+pub use state::{BusState, LaneState, SubscriberState};
+pub use subscription::{Subscription, SubscriptionBuilder};
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::task::{Context, Poll};
+
+use offsets::Offsets;
+use spill::{OpenSpill, Overflow, ReadBack};
+use state::RateSample;
+
+use chrono::{DateTime, Utc};
+use ingest_core::{
+    config::{
+        BusConfig, BusKind, BusSpillConfig, LagPolicy, SinkRoute, SubscriberConfig,
+    },
+    error::IngestError,
+    event::{EventKind, NormalizedEvent, ENGINE_VENUE},
+};
+use prometheus::{IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry};
+use tokio::sync::{broadcast, Semaphore};
+use tokio::sync::mpsc::{
+    self,
+    error::{TryRecvError, TrySendError},
+};
+use tokio_stream::{
+    wrappers::{errors::BroadcastStreamRecvError, BroadcastStream},
+    Stream, StreamExt, StreamMap,
+};
+
+/// Which events a filtered subscriber receives. Empty criteria match
+/// everything; symbols may be globs such as `BTC*` or `*USDT`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Filter {
+    venues: HashSet<String>,
+    symbols: Vec<String>,
+    kinds: HashSet<EventKind>,
+    /// Leave out the engine's own events.
+    market_data: bool,
+}
+
+impl Filter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn venues<S: Into<String>>(mut self, venues: impl IntoIterator<Item = S>) -> Self {
+        self.venues.extend(venues.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn symbols<S: Into<String>>(mut self, symbols: impl IntoIterator<Item = S>) -> Self {
+        self.symbols.extend(symbols.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn kinds(mut self, kinds: impl IntoIterator<Item = EventKind>) -> Self {
+        self.kinds.extend(kinds);
+        self
+    }
+
+    /// Leave out the events of the [`ENGINE_VENUE`], such as adapter status
+    /// and gap markers, unless it is among the venues. A subscriber's own
+    /// gap markers still reach it.
+    pub fn market_data(mut self) -> Self {
+        self.market_data = true;
+        self
+    }
+
+    pub fn matches(&self, event: &NormalizedEvent) -> bool {
+        !(self.market_data && self.venues.is_empty() && event.venue == ENGINE_VENUE)
+            && (self.venues.is_empty() || self.venues.contains(&event.venue))
+            && (self.kinds.is_empty() || self.kinds.contains(&event.kind))
+            && (self.symbols.is_empty() || self.symbols.iter().any(|p| glob(p, &event.symbol)))
+    }
+}
+
+/// Parses a topic pattern such as `trades.*.BTCUSDT`: `*` matches any kind
+/// or venue, and the symbol level may be a glob.
+impl FromStr for Filter {
+    type Err = IngestError;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        let invalid = |why: &str| {
+            IngestError::Validation(format!("invalid topic pattern {:?}: {}", pattern, why))
+        };
+        let mut levels = pattern.splitn(3, '.');
+        let (Some(kind), Some(venue), Some(symbol)) = (levels.next(), levels.next(), levels.next())
+        else {
+            return Err(invalid("expected <kind>.<venue>.<symbol>"));
+        };
+        let mut filter = Filter::new();
+        if kind != "*" {
+            let kind = KINDS
+                .iter()
+                .find(|k| topic_kind(**k) == kind)
+                .ok_or_else(|| invalid("unknown kind"))?;
+            filter = filter.kinds([*kind]);
+        }
+        if venue.contains(['*', '?']) && venue != "*" {
+            return Err(invalid("venues cannot be globs"));
+        }
+        if venue != "*" {
+            filter = filter.venues([venue]);
+        }
+        if symbol != "*" {
+            filter = filter.symbols([symbol]);
+        }
+        Ok(filter)
+    }
+}
+
+/// Sinks store market data, so engine events reach them only when a route
+/// names the [`ENGINE_VENUE`].
+impl From<&SinkRoute> for Filter {
+    fn from(route: &SinkRoute) -> Self {
+        Filter::new()
+            .venues(route.venues.iter().cloned())
+            .symbols(route.symbols.iter().cloned())
+            .kinds(route.kinds.iter().copied())
+            .market_data()
+    }
+}
+
+const KINDS: [EventKind; 5] = [
+    EventKind::Trade,
+    EventKind::Ticker,
+    EventKind::Quote,
+    EventKind::Book,
+    EventKind::Raw,
+];
+
+fn topic_kind(kind: EventKind) -> &'static str {
+    match kind {
+        EventKind::Trade => "trades",
+        EventKind::Ticker => "tickers",
+        EventKind::Quote => "quotes",
+        EventKind::Book => "books",
+        EventKind::Raw => "raw",
+    }
+}
+
+/// The topic `event` is published on, e.g. `trades.binance_spot.BTCUSDT`.
+pub fn topic(event: &NormalizedEvent) -> String {
+    format!("{}.{}.{}", topic_kind(event.kind), event.venue, event.symbol)
+}
+
+/// Match `text` against `pattern`, where `*` matches any run of characters
+/// and `?` any single one.
+fn glob(pattern: &str, text: &str) -> bool {
+    let (p, t): (Vec<char>, Vec<char>) = (pattern.chars().collect(), text.chars().collect());
+    let (mut pi, mut ti) = (0, 0);
+    // Position after the last `*` and the text position it is matched up to.
+    let mut star = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi + 1, ti));
+            pi += 1;
+        } else if let Some((after, matched)) = star {
+            pi = after;
+            ti = matched + 1;
+            star = Some((after, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+/// Venue of the gap markers handed to lagging subscribers, the
+/// [`ENGINE_VENUE`].
+pub const GAP_VENUE: &str = ENGINE_VENUE;
+
+/// A raw event standing in for `missed` events a subscriber did not receive,
+/// with the payload `{"gap": {"missed": n}}`.
+pub fn gap_marker(missed: u64) -> NormalizedEvent {
+    NormalizedEvent {
+        venue: GAP_VENUE.into(),
+        timestamp: Utc::now(),
+        kind: EventKind::Raw,
+        payload: serde_json::json!({ "gap": { "missed": missed } }),
+        ..Default::default()
+    }
+}
+
+/// Lag, drop and queue-depth metrics for the bus subscribers, labelled by
+/// subscriber name. Broadcast subscribers are labelled `broadcast`.
+#[derive(Clone)]
+pub struct BusMetrics {
+    pub lagged: IntCounterVec,
+    pub dropped: IntCounterVec,
+    pub disconnects: IntCounterVec,
+    pub spilled: IntCounterVec,
+    pub queue_depth: IntGaugeVec,
+    /// Events published, labelled by logical bus.
+    pub published: IntCounterVec,
+}
+
+impl BusMetrics {
+    pub fn new() -> Self {
+        let counter = |name: &str, help: &str| {
+            IntCounterVec::new(Opts::new(name, help), &["subscriber"]).unwrap()
+        };
+        Self {
+            lagged: counter(
+                "bus_lagged_events_total",
+                "events broadcast subscribers missed by falling behind",
+            ),
+            dropped: counter(
+                "bus_dropped_events_total",
+                "events dropped on a full subscriber queue",
+            ),
+            disconnects: counter(
+                "bus_lag_disconnects_total",
+                "subscribers disconnected for falling behind",
+            ),
+            spilled: counter(
+                "bus_spilled_events_total",
+                "events written to disk on a full subscriber queue",
+            ),
+            queue_depth: IntGaugeVec::new(
+                Opts::new("bus_queue_depth", "events waiting in a subscriber queue"),
+                &["subscriber"],
+            )
+            .unwrap(),
+            published: IntCounterVec::new(
+                Opts::new("bus_published_events_total", "events published on each logical bus"),
+                &["bus"],
+            )
+            .unwrap(),
+        }
+    }
+
+    pub fn register(&self, registry: &Registry) -> prometheus::Result<()> {
+        registry.register(Box::new(self.lagged.clone()))?;
+        registry.register(Box::new(self.dropped.clone()))?;
+        registry.register(Box::new(self.disconnects.clone()))?;
+        registry.register(Box::new(self.spilled.clone()))?;
+        registry.register(Box::new(self.queue_depth.clone()))?;
+        registry.register(Box::new(self.published.clone()))?;
+        Ok(())
+    }
+}
+
+impl Default for BusMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How one subscriber handles missing events, per its [`LagPolicy`].
+#[derive(Clone)]
+struct Lag {
+    name: Arc<str>,
+    policy: LagPolicy,
+    lagged: IntCounter,
+    dropped: IntCounter,
+    disconnects: IntCounter,
+}
+
+impl Lag {
+    fn new(name: &str, policy: LagPolicy, metrics: &BusMetrics) -> Self {
+        let label = [name];
+        Self {
+            name: name.into(),
+            policy,
+            lagged: metrics.lagged.with_label_values(&label),
+            dropped: metrics.dropped.with_label_values(&label),
+            disconnects: metrics.disconnects.with_label_values(&label),
+        }
+    }
+
+    /// Account for `missed` events skipped by a broadcast subscriber. Returns
+    /// `Err` when the subscriber should be disconnected, or the gap marker to
+    /// hand it.
+    fn lagged(&self, missed: u64) -> Result<Option<NormalizedEvent>, ()> {
+        self.lagged.inc_by(missed);
+        match self.policy {
+            LagPolicy::Disconnect => {
+                self.disconnects.inc();
+                tracing::warn!(
+                    "bus subscriber {} disconnected after missing {} events",
+                    self.name,
+                    missed
+                );
+                Err(())
+            }
+            LagPolicy::Log => {
+                tracing::warn!(
+                    "bus subscriber {} fell behind and missed {} events",
+                    self.name,
+                    missed
+                );
+                Ok(None)
+            }
+            LagPolicy::GapMarker => Ok(Some(gap_marker(missed))),
+        }
+    }
+}
+
+/// A filtered subscriber and its queue, shared by every shard it listens on.
+struct Subscriber {
+    filter: Filter,
+    lag: Lag,
+    depth: IntGauge,
+    capacity: usize,
+    queue: Mutex<Queue>,
+    overflow: Option<Overflow>,
+}
+
+struct Queue {
+    /// Taken on disconnect, which ends the stream once it has drained.
+    tx: Option<mpsc::Sender<NormalizedEvent>>,
+    /// Engine events, in a queue of their own that market data cannot fill.
+    priority: Option<mpsc::Sender<NormalizedEvent>>,
+    /// Events dropped since the last one that fit in the queue.
+    missed: u64,
+}
+
+impl Subscriber {
+    /// Queue `event` if it matches. Returns false once the subscriber is gone
+    /// or disconnected.
+    fn deliver(&self, event: &NormalizedEvent) -> bool {
+        let mut queue = self.queue.lock().unwrap();
+        let Some(tx) = queue.tx.clone().filter(|tx| !tx.is_closed()) else {
+            return false;
+        };
+        if !self.filter.matches(event) {
+            return true;
+        }
+        if BusKind::of(event) == BusKind::Control {
+            if let Some(priority) = &queue.priority {
+                if let Err(TrySendError::Full(_)) = self.push(priority, event.clone()) {
+                    self.lag.dropped.inc();
+                    tracing::warn!(
+                        "bus subscriber {} dropped an engine event, its priority lane is full",
+                        self.lag.name
+                    );
+                }
+            }
+            return true;
+        }
+        if let Some(overflow) = self.overflow.as_ref().filter(|overflow| overflow.active()) {
+            overflow.push(event);
+            return true;
+        }
+        if queue.missed > 0 && self.lag.policy == LagPolicy::GapMarker {
+            // The marker and the event must both fit, or the gap grows.
+            if tx.capacity() < 2 {
+                queue.missed += 1;
+                self.lag.dropped.inc();
+                return true;
+            }
+            let _ = self.push(&tx, gap_marker(queue.missed));
+            queue.missed = 0;
+        }
+        match self.push(&tx, event.clone()) {
+            Ok(()) => {
+                if queue.missed > 0 {
+                    tracing::warn!(
+                        "bus subscriber {} caught up after dropping {} events",
+                        self.lag.name,
+                        queue.missed
+                    );
+                    queue.missed = 0;
+                }
+                true
+            }
+            Err(TrySendError::Full(event)) => {
+                if let Some(overflow) = &self.overflow {
+                    overflow.push(&event);
+                    return true;
+                }
+                self.lag.dropped.inc();
+                if self.lag.policy == LagPolicy::Disconnect {
+                    self.lag.disconnects.inc();
+                    tracing::warn!(
+                        "bus subscriber {} disconnected on a full queue",
+                        self.lag.name
+                    );
+                    queue.tx = None;
+                    queue.priority = None;
+                    return false;
+                }
+                queue.missed += 1;
+                true
+            }
+            Err(TrySendError::Closed(_)) => false,
+        }
+    }
+
+    /// Stop queueing events, so the stream ends once it has drained.
+    fn disconnect(&self) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.tx = None;
+        queue.priority = None;
+    }
+
+    fn push(
+        &self,
+        tx: &mpsc::Sender<NormalizedEvent>,
+        event: NormalizedEvent,
+    ) -> Result<(), TrySendError<NormalizedEvent>> {
+        // Count the event before the subscriber can take it off the queue.
+        self.depth.inc();
+        let sent = tx.try_send(event);
+        if sent.is_err() {
+            self.depth.dec();
+        }
+        sent
+    }
+}
+
+/// The queue of one filtered subscriber. Subscribers sharing a name share
+/// its queue-depth gauge, which counts the events waiting in all of them.
+/// Engine events arrive on a priority lane ahead of the queued events.
+pub struct EventStream {
+    rx: mpsc::Receiver<NormalizedEvent>,
+    priority: mpsc::Receiver<NormalizedEvent>,
+    depth: IntGauge,
+    read_back: Option<ReadBack>,
+    /// Weak, as the lanes hold the sender that ends the stream when they go.
+    shards: Weak<Lanes>,
+    /// The subscriber's id in the index of each lane listing it.
+    listed: Vec<(usize, u64)>,
+}
+
+impl Stream for EventStream {
+    type Item = NormalizedEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Poll::Ready(Some(event)) = self.priority.poll_recv(cx) {
+            self.depth.dec();
+            return Poll::Ready(Some(event));
+        }
+        if self.read_back.as_ref().is_some_and(ReadBack::pending) {
+            // Queued events are older than the spilled ones.
+            match self.rx.try_recv() {
+                Ok(event) => {
+                    self.depth.dec();
+                    return Poll::Ready(Some(event));
+                }
+                Err(TryRecvError::Empty | TryRecvError::Disconnected) => {}
+            }
+            // Nothing is queued while spilled events are still to come.
+            let read_back = self.read_back.as_mut().unwrap();
+            match read_back.poll_recv(cx) {
+                Poll::Ready(Some(event)) => return Poll::Ready(Some(event)),
+                Poll::Ready(None) => {}
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        let polled = self.rx.poll_recv(cx);
+        match polled {
+            Poll::Ready(Some(_)) => self.depth.dec(),
+            // The subscriber may have queued an engine event before it went.
+            Poll::Ready(None) => {
+                if let Ok(event) = self.priority.try_recv() {
+                    self.depth.dec();
+                    return Poll::Ready(Some(event));
+                }
+            }
+            Poll::Pending => {}
+        }
+        polled
+    }
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        self.rx.close();
+        self.priority.close();
+        self.depth.sub((self.rx.len() + self.priority.len()) as i64);
+        // Unlisted now rather than on the next event it would match, which
+        // for a quiet topic may never come.
+        if let Some(shards) = self.shards.upgrade() {
+            for &(i, id) in &self.listed {
+                shards[i].filtered.write().unwrap().remove(id);
+            }
+        }
+    }
+}
+
+/// Subscriber ids of one kind (or of every kind), by venue.
+#[derive(Default)]
+struct Venues {
+    venues: HashMap<String, Vec<u64>>,
+    any: Vec<u64>,
+}
+
+impl Venues {
+    fn insert(&mut self, filter: &Filter, id: u64) {
+        if filter.venues.is_empty() {
+            self.any.push(id);
+        } else {
+            for venue in &filter.venues {
+                self.venues.entry(venue.clone()).or_default().push(id);
+            }
+        }
+    }
+
+    fn remove(&mut self, id: u64) {
+        self.venues.retain(|_, ids| {
+            ids.retain(|listed| *listed != id);
+            !ids.is_empty()
+        });
+        self.any.retain(|listed| *listed != id);
+    }
+
+    fn matching<'a>(&'a self, venue: &str) -> impl Iterator<Item = &'a Vec<u64>> {
+        self.venues.get(venue).into_iter().chain(Some(&self.any))
+    }
+}
+
+/// Filtered subscribers indexed by the kind and venue levels of their topic.
+/// A subscriber is listed under every kind and venue it accepts, so each
+/// event reaches it through exactly one list. Publishers only read the
+/// index; subscribers they find gone are unlisted afterwards.
+#[derive(Default)]
+struct Topics {
+    subscribers: HashMap<u64, Arc<Subscriber>>,
+    next_id: u64,
+    kinds: HashMap<EventKind, Venues>,
+    any: Venues,
+}
+
+impl Topics {
+    /// List `subscriber`, returning its id.
+    fn insert(&mut self, subscriber: Arc<Subscriber>) -> u64 {
+        let filter = &subscriber.filter;
+        let id = self.next_id;
+        self.next_id += 1;
+        if filter.kinds.is_empty() {
+            self.any.insert(filter, id);
+        } else {
+            for kind in &filter.kinds {
+                self.kinds.entry(*kind).or_default().insert(filter, id);
+            }
+        }
+        self.subscribers.insert(id, subscriber);
+        id
+    }
+
+    fn remove(&mut self, id: u64) {
+        if self.subscribers.remove(&id).is_none() {
+            return;
+        }
+        for venues in self.kinds.values_mut().chain(Some(&mut self.any)) {
+            venues.remove(id);
+        }
+    }
+
+    /// Unlist every subscriber.
+    fn clear(&mut self) {
+        self.subscribers.clear();
+        self.kinds.clear();
+        self.any = Venues::default();
+    }
+
+    /// Queue `event` for the subscribers it matches, returning the ids of
+    /// those found gone.
+    fn publish(&self, event: &NormalizedEvent) -> Vec<u64> {
+        let mut gone = Vec::new();
+        let kind = self.kinds.get(&event.kind).into_iter().chain(Some(&self.any));
+        for ids in kind.flat_map(|venues| venues.matching(&event.venue)) {
+            for id in ids {
+                let subscriber = self.subscribers.get(id);
+                if subscriber.is_some_and(|subscriber| !subscriber.deliver(event)) {
+                    gone.push(*id);
+                }
+            }
+        }
+        gone
+    }
+}
+
+/// An event and the sequence number it was published under.
+type Sequenced = (u64, NormalizedEvent);
+
+/// Engine events a filtered subscriber may have waiting on its priority
+/// lane. They are rare, so only a subscriber that stopped reading fills it.
+const PRIORITY_CAPACITY: usize = 1024;
+
+/// Sequences read from history at a time when backfilling a subscriber.
+const BACKFILL_CHUNK: u64 = 1024;
+/// Chunks of history a backfill looks back through at most.
+const BACKFILL_CHUNKS: usize = 64;
+/// Backfills reading history on the blocking pool at once; later ones wait.
+const BACKFILLS: usize = 4;
+
+/// The recent events a new subscriber starts with.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Backfill {
+    /// At most this many, the newest.
+    pub(crate) last: usize,
+    /// Only those received at or after this.
+    pub(crate) since: Option<DateTime<Utc>>,
+}
+
+impl Backfill {
+    fn covers(&self, event: &NormalizedEvent) -> bool {
+        self.since.is_none_or(|since| received(event) >= since)
+    }
+}
+
+/// When `event` was received, or stamped by the venue if that is unknown.
+/// Unlike venue time, receive time follows publish order.
+fn received(event: &NormalizedEvent) -> DateTime<Utc> {
+    event.received_at.unwrap_or(event.timestamp)
+}
+
+/// The events a new subscriber starts with as found in the lanes, and what
+/// to look for in history before them.
+pub(crate) struct Replaying {
+    replayed: Vec<Sequenced>,
+    filter: Filter,
+    backfill: Backfill,
+    /// Highest sequence each lane no longer holds, which history covers.
+    evicted: HashMap<usize, u64>,
+}
+
+/// One lane of the bus, carrying the events of one logical bus for the
+/// symbols hashed to it.
+struct Shard {
+    tx: broadcast::Sender<Sequenced>,
+    published: IntCounter,
+    /// Read by publishers, written by subscribers coming and going.
+    filtered: RwLock<Topics>,
+    last: Mutex<Latest>,
+    replay_capacity: usize,
+    /// The most recent events, for consumers resuming from a sequence. Left
+    /// alone without a replay buffer.
+    replay: Mutex<VecDeque<Sequenced>>,
+    /// Highest sequence no longer held in `replay`, set under its lock while
+    /// there is a replay buffer.
+    evicted: AtomicU64,
+}
+
+/// The latest event per venue, symbol and kind. Nested, so an event
+/// replaces its predecessor without copying its venue and symbol.
+#[derive(Default)]
+struct Latest(HashMap<String, HashMap<String, HashMap<EventKind, NormalizedEvent>>>);
+
+impl Latest {
+    fn record(&mut self, event: &NormalizedEvent) {
+        if !self.0.contains_key(&event.venue) {
+            self.0.insert(event.venue.clone(), HashMap::new());
+        }
+        let symbols = self.0.get_mut(&event.venue).unwrap();
+        if !symbols.contains_key(&event.symbol) {
+            symbols.insert(event.symbol.clone(), HashMap::new());
+        }
+        let kinds = symbols.get_mut(&event.symbol).unwrap();
+        match kinds.get_mut(&event.kind) {
+            Some(last) => {
+                last.timestamp = event.timestamp;
+                last.received_at = event.received_at;
+                last.payload.clone_from(&event.payload);
+            }
+            None => {
+                kinds.insert(event.kind, event.clone());
+            }
+        }
+    }
+
+    fn events(&self) -> impl Iterator<Item = &NormalizedEvent> {
+        self.0.values().flat_map(HashMap::values).flat_map(HashMap::values)
+    }
+}
+
+impl Shard {
+    /// Unlist the subscribers a publisher found gone.
+    fn unlist(&self, gone: Vec<u64>) {
+        if gone.is_empty() {
+            return;
+        }
+        let mut filtered = self.filtered.write().unwrap();
+        for id in gone {
+            filtered.remove(id);
+        }
+    }
+}
+
+/// The lanes of every logical bus, `shards` per bus in [`BusKind::ALL`]
+/// order.
+struct Lanes {
+    lanes: Box<[Shard]>,
+    shards: usize,
+}
+
+impl Lanes {
+    /// The lane carrying `event`.
+    fn of(&self, event: &NormalizedEvent) -> usize {
+        BusKind::of(event) as usize * self.shards + self.shard_of(&event.symbol)
+    }
+
+    /// The lanes carrying `symbol`, one per logical bus.
+    fn of_symbol(&self, symbol: &str) -> impl Iterator<Item = usize> + '_ {
+        let shard = self.shard_of(symbol);
+        (0..BusKind::ALL.len()).map(move |bus| bus * self.shards + shard)
+    }
+
+    fn shard_of(&self, symbol: &str) -> usize {
+        let mut hasher = DefaultHasher::new();
+        symbol.hash(&mut hasher);
+        (hasher.finish() % self.shards as u64) as usize
+    }
+}
+
+impl std::ops::Deref for Lanes {
+    type Target = [Shard];
+
+    fn deref(&self) -> &[Shard] {
+        &self.lanes
+    }
+}
+
+type Shards = Arc<Lanes>;
+
+#[derive(Clone)]
 pub struct EventBus {
-    tx: broadcast::Sender<NormalizedEvent>,
+    shards: Shards,
+    metrics: BusMetrics,
+    /// Defaults for subscribers without their own settings.
+    capacity: usize,
+    lag_policy: LagPolicy,
+    subscribers: Arc<BTreeMap<String, SubscriberConfig>>,
+    next_sequence: Arc<AtomicU64>,
+    history: Option<Arc<dyn History>>,
+    open_spill: Option<OpenSpill>,
+    /// Last sequence acknowledged by each named consumer.
+    offsets: Arc<Offsets>,
+    rate: Arc<Mutex<RateSample>>,
+    /// Set by [`EventBus::close`].
+    closed: Arc<AtomicBool>,
+    /// Permits for backfills reading history on the blocking pool.
+    backfills: Arc<Semaphore>,
 }
 
 impl EventBus {
     pub fn new(capacity: usize) -> Self {
-        let (tx, _rx) = broadcast::channel(capacity);
-        Self { tx }
+        Self::with_shards(capacity, 1)
+    }
+
+    /// A bus split into `shards` lanes per logical bus keyed by symbol hash,
+    /// each with its own broadcast channel and subscriber index, so
+    /// publishers of different symbols do not contend. Events of one symbol
+    /// and [`BusKind`] stay in order; a broadcast subscriber merges the
+    /// lanes, so other events may arrive out of publishing order.
+    pub fn with_shards(capacity: usize, shards: usize) -> Self {
+        let cfg = BusConfig {
+            capacity,
+            shards,
+            replay_buffer: 0,
+            ..BusConfig::default()
+        };
+        Self::from_config(&cfg, &BusMetrics::new())
+    }
+
+    /// A bus sized and configured by `cfg`, counting losses in `metrics`.
+    pub fn from_config(cfg: &BusConfig, metrics: &BusMetrics) -> Self {
+        let shards = cfg.shards.max(1);
+        let lanes = BusKind::ALL
+            .iter()
+            .flat_map(|bus| {
+                let overrides = cfg.kinds.get(bus);
+                let capacity = overrides.and_then(|o| o.capacity).unwrap_or(cfg.capacity);
+                let replay_capacity =
+                    overrides.and_then(|o| o.replay_buffer).unwrap_or(cfg.replay_buffer);
+                let published = metrics.published.with_label_values(&[bus.as_str()]);
+                (0..shards).map(move |_| Shard {
+                    tx: broadcast::channel(capacity.max(1)).0,
+                    published: published.clone(),
+                    filtered: RwLock::default(),
+                    last: Mutex::default(),
+                    replay_capacity,
+                    replay: Mutex::default(),
+                    evicted: AtomicU64::new(0),
+                })
+            })
+            .collect();
+        Self {
+            shards: Arc::new(Lanes { lanes, shards }),
+            metrics: metrics.clone(),
+            capacity: cfg.capacity,
+            lag_policy: cfg.lag_policy,
+            subscribers: Arc::new(cfg.subscribers.clone()),
+            next_sequence: Arc::new(AtomicU64::new(1)),
+            history: None,
+            open_spill: None,
+            offsets: Arc::default(),
+            rate: Arc::new(Mutex::new(RateSample::new(metrics))),
+            closed: Arc::default(),
+            backfills: Arc::new(Semaphore::new(BACKFILLS)),
+        }
+    }
+
+    /// Serve resumes from sequences older than the replay buffer holds out of
+    /// `history`, e.g. the write-ahead log. Sequences must then be assigned
+    /// with [`EventPublisher::publish_sequenced`] to match it.
+    pub fn with_history(mut self, history: impl History + 'static) -> Self {
+        self.history = Some(Arc::new(history));
+        self
+    }
+
+    /// Keep the offsets of named consumers in the file at `path`, starting
+    /// from those a previous run left there, so [`resume`](Self::resume)
+    /// continues across restarts. They are written by
+    /// [`persist_offsets`](Self::persist_offsets).
+    pub fn with_offsets(mut self, path: impl Into<PathBuf>) -> Result<Self, IngestError> {
+        self.offsets = Arc::new(Offsets::open(path.into())?);
+        Ok(self)
+    }
+
+    /// Write the offsets acknowledged since the last call to the file given
+    /// to [`with_offsets`](Self::with_offsets), if any. Blocks on the disk.
+    pub fn persist_offsets(&self) -> Result<(), IngestError> {
+        self.offsets.persist()
+    }
+
+    /// Open the spills of subscribers configured with one through `open`,
+    /// e.g. a write-ahead log.
+    pub fn with_spill<F>(mut self, open: F) -> Self
+    where
+        F: Fn(&BusSpillConfig) -> Result<Box<dyn Spill>, IngestError> + Send + Sync + 'static,
+    {
+        self.open_spill = Some(Arc::new(open));
+        self
+    }
+
+    /// Sequence of the latest published event, 0 before the first.
+    pub fn last_sequence(&self) -> u64 {
+        self.next_sequence.load(Ordering::Relaxed) - 1
     }
 
     pub fn publisher(&self) -> EventPublisher {
-        EventPublisher { tx: self.tx.clone() }
+        EventPublisher {
+            shards: self.shards.clone(),
+            next_sequence: self.next_sequence.clone(),
+        }
     }
 
+    /// Consume live events. Once it has received an event, a consumer that
+    /// falls behind catches up from the replay buffer or history when they
+    /// still hold what it missed.
     pub fn subscribe(&self) -> EventConsumer {
-        EventConsumer { rx: self.tx.subscribe() }
+        EventConsumer::new(self, None, None)
+    }
+
+    /// Consume every event from `sequence` onwards, out of the replay buffer
+    /// or history, and then the live events.
+    pub fn subscribe_from(&self, sequence: u64) -> EventConsumer {
+        EventConsumer::new(self, Some(sequence), None)
+    }
+
+    /// Consume the events after the last one acknowledged by a consumer
+    /// named `name`, or live events if there is none, so a restarted
+    /// consumer picks up where its predecessor left off.
+    pub fn resume(&self, name: &str) -> EventConsumer {
+        let acked = self.offsets.get(name);
+        EventConsumer::new(self, acked.map(|seq| seq + 1), Some(name))
+    }
+
+    /// Build a filtered subscription, e.g.
+    /// `bus.subscription().venue("binance").kinds([Trade]).build()`.
+    pub fn subscription(&self) -> SubscriptionBuilder<'_> {
+        SubscriptionBuilder::new(self)
     }
 
-    /// Subscribe to events as an asynchronous stream.
+    /// Subscribe to events as an asynchronous stream. Missed events are
+    /// handled per the bus lag policy.
     pub fn subscribe_stream(&self) -> impl Stream<Item = NormalizedEvent> {
-        BroadcastStream::new(self.tx.subscribe()).filter_map(|res| res.ok())
+        self.broadcast()
+    }
+
+    /// Every lane merged into one stream.
+    fn broadcast(&self) -> impl Stream<Item = NormalizedEvent> + Send + 'static {
+        let mut lanes = StreamMap::new();
+        for (i, shard) in self.shards.iter().enumerate() {
+            lanes.insert(i, BroadcastStream::new(shard.tx.subscribe()));
+        }
+        let lag = self.broadcast_lag();
+        lanes
+            .map_while(move |(_, res)| match res {
+                Ok((_, event)) => Some(Some(event)),
+                Err(BroadcastStreamRecvError::Lagged(missed)) => lag.lagged(missed).ok(),
+            })
+            .filter_map(|event| event)
+    }
+
+    fn broadcast_lag(&self) -> Lag {
+        Lag::new("broadcast", self.lag_policy, &self.metrics)
+    }
+
+    /// Subscribe to the events matching `filter` under the name `filtered`;
+    /// see [`subscribe_named`](Self::subscribe_named).
+    pub fn subscribe_filtered(&self, filter: Filter) -> EventStream {
+        self.subscribe_named("filtered", filter)
+    }
+
+    /// Subscribe to the events matching `filter` with a queue of its own,
+    /// sized and handling lag as configured for `name` in the bus config, or
+    /// as the bus defaults. The filter is applied when publishing, so the
+    /// subscriber is only woken for events it wants. With a `spill` in its
+    /// config, events that do not fit the queue are spilled instead.
+    pub fn subscribe_named(&self, name: &str, filter: Filter) -> EventStream {
+        self.subscribe_replaying(name, filter, Backfill::default()).0
+    }
+
+    /// Like [`subscribe_named`](Self::subscribe_named), also returning the
+    /// matching events `backfill` asks for out of the replay buffers, to be
+    /// completed by [`replayed`](Self::replayed). The stream starts with the
+    /// event after them.
+    pub(crate) fn subscribe_replaying(
+        &self,
+        name: &str,
+        filter: Filter,
+        backfill: Backfill,
+    ) -> (EventStream, Replaying) {
+        let cfg = self.subscribers.get(name);
+        let capacity = cfg.and_then(|cfg| cfg.capacity).unwrap_or(self.capacity);
+        let policy = cfg.and_then(|cfg| cfg.lag_policy).unwrap_or(self.lag_policy);
+        let spill = cfg.and_then(|cfg| cfg.spill.as_ref());
+        self.queue(name, filter, capacity, policy, spill, backfill)
+    }
+
+    /// Like [`subscribe_named`](Self::subscribe_named), with the queue
+    /// capacity and lag policy given explicitly.
+    pub fn subscribe_queue(
+        &self,
+        name: &str,
+        filter: Filter,
+        capacity: usize,
+        policy: LagPolicy,
+    ) -> EventStream {
+        self.queue(name, filter, capacity, policy, None, Backfill::default()).0
+    }
+
+    fn queue(
+        &self,
+        name: &str,
+        filter: Filter,
+        capacity: usize,
+        policy: LagPolicy,
+        spill: Option<&BusSpillConfig>,
+        backfill: Backfill,
+    ) -> (EventStream, Replaying) {
+        let capacity = capacity.max(1);
+        let (tx, rx) = mpsc::channel(capacity);
+        let (priority_tx, priority) = mpsc::channel(PRIORITY_CAPACITY);
+        let depth = self.metrics.queue_depth.with_label_values(&[name]);
+        // Exact symbols live on known shards; globs may match on any.
+        let globs = filter.symbols.iter().any(|s| s.contains(['*', '?']));
+        let shards: BTreeSet<usize> = if filter.symbols.is_empty() || globs {
+            (0..self.shards.len()).collect()
+        } else {
+            filter.symbols.iter().flat_map(|s| self.shards.of_symbol(s)).collect()
+        };
+        let lag = Lag::new(name, policy, &self.metrics);
+        let (overflow, read_back) = spill
+            .and_then(|cfg| self.overflow(&lag, cfg))
+            .unzip();
+        let subscriber = Arc::new(Subscriber {
+            filter,
+            lag,
+            depth: depth.clone(),
+            capacity,
+            queue: Mutex::new(Queue {
+                tx: Some(tx),
+                priority: Some(priority_tx),
+                missed: 0,
+            }),
+            overflow,
+        });
+        let mut replayed: Vec<Sequenced> = Vec::new();
+        let mut evicted = HashMap::new();
+        let mut listed = Vec::new();
+        for i in shards {
+            let shard = &self.shards[i];
+            // Publishers fill the replay buffer under the index lock, so each
+            // event is either replayed or queued.
+            let mut filtered = shard.filtered.write().unwrap();
+            listed.push((i, filtered.insert(subscriber.clone())));
+            if backfill.last > 0 {
+                let held = shard.replay.lock().unwrap();
+                let matching = held.iter().rev();
+                let matching = matching
+                    .filter(|(_, event)| subscriber.filter.matches(event) && backfill.covers(event));
+                replayed.extend(matching.take(backfill.last).cloned());
+                evicted.insert(i, shard.evicted.load(Ordering::Relaxed));
+            }
+        }
+        // Either `close` finds the subscriber listed, or it is seen closing.
+        if self.closed.load(Ordering::SeqCst) {
+            subscriber.disconnect();
+        }
+        let stream = EventStream {
+            rx,
+            priority,
+            depth,
+            read_back,
+            shards: Arc::downgrade(&self.shards),
+            listed,
+        };
+        let replaying = Replaying {
+            replayed,
+            filter: subscriber.filter.clone(),
+            backfill,
+            evicted,
+        };
+        (stream, replaying)
+    }
+
+    /// The events `replaying` asks for, oldest first, looking through the
+    /// history for those the lanes no longer hold.
+    pub(crate) fn replayed(&self, replaying: Replaying) -> Vec<NormalizedEvent> {
+        let Replaying { mut replayed, filter, backfill, evicted } = replaying;
+        if let Some(history) = &self.history {
+            replayed.extend(self.backfill(history.as_ref(), &filter, backfill, &evicted));
+        }
+        replayed.sort_by_key(|(seq, _)| *seq);
+        let skip = replayed.len().saturating_sub(backfill.last);
+        replayed.into_iter().skip(skip).map(|(_, event)| event).collect()
+    }
+
+    /// Like [`replayed`](Self::replayed), reading history on the blocking
+    /// pool, a few backfills at a time, so async tasks are not held up by
+    /// the disk.
+    pub(crate) async fn replayed_blocking(&self, replaying: Replaying) -> Vec<NormalizedEvent> {
+        if self.history.is_none() || replaying.evicted.is_empty() {
+            return self.replayed(replaying);
+        }
+        let Ok(permit) = self.backfills.clone().acquire_owned().await else {
+            return self.replayed(replaying);
+        };
+        let bus = self.clone();
+        let replayed = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            bus.replayed(replaying)
+        });
+        replayed.await.unwrap_or_else(|e| {
+            tracing::warn!("cannot backfill from history: {}", e);
+            Vec::new()
+        })
+    }
+
+    /// Matching events of `history` no longer held in the lanes they were
+    /// published on, newest first, as many as `backfill` asks for.
+    fn backfill(
+        &self,
+        history: &dyn History,
+        filter: &Filter,
+        backfill: Backfill,
+        evicted: &HashMap<usize, u64>,
+    ) -> Vec<Sequenced> {
+        let mut found = Vec::new();
+        let Some(&newest) = evicted.values().max() else {
+            return found;
+        };
+        let mut end = newest;
+        for _ in 0..BACKFILL_CHUNKS {
+            if end == 0 || found.len() >= backfill.last {
+                break;
+            }
+            let start = end.saturating_sub(BACKFILL_CHUNK - 1);
+            let chunk = match history.read(start, (end - start + 1) as usize) {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    tracing::warn!("cannot backfill from history: {}", e);
+                    break;
+                }
+            };
+            // History is in publish order, and so about in order of receipt,
+            // so once a chunk starts before `since` the ones before it do too.
+            let older = backfill
+                .since
+                .is_some_and(|since| chunk.first().is_some_and(|(_, event)| received(event) < since));
+            for (seq, event) in chunk.into_iter().rev() {
+                let lane = self.shards.of(&event);
+                let unheld = seq <= end && evicted.get(&lane).is_some_and(|evicted| seq <= *evicted);
+                if unheld && filter.matches(&event) && backfill.covers(&event) {
+                    found.push((seq, event));
+                    if found.len() >= backfill.last {
+                        break;
+                    }
+                }
+            }
+            if older || start == 0 {
+                break;
+            }
+            end = start - 1;
+        }
+        found
+    }
+
+    /// Disconnect every filtered subscriber, ending each stream once it has
+    /// drained what is queued, and end the streams of later subscribers
+    /// right away. Broadcast consumers are not affected.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        for shard in self.shards.iter() {
+            let mut filtered = shard.filtered.write().unwrap();
+            for subscriber in filtered.subscribers.values() {
+                subscriber.disconnect();
+            }
+            filtered.clear();
+        }
+    }
+
+    /// Whether [`EventBus::close`] was called.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    fn overflow(&self, lag: &Lag, cfg: &BusSpillConfig) -> Option<(Overflow, ReadBack)> {
+        let Some(open) = &self.open_spill else {
+            tracing::warn!(
+                "bus subscriber {} has a spill configured, but this bus cannot spill",
+                lag.name
+            );
+            return None;
+        };
+        let spilled = self.metrics.spilled.with_label_values(&[&lag.name]);
+        let started = open(cfg)
+            .and_then(|spill| spill::overflow(lag.name.clone(), spill, spilled, lag.dropped.clone()));
+        match started {
+            Ok(overflow) => Some(overflow),
+            Err(e) => {
+                tracing::warn!(
+                    "bus subscriber {} runs without its spill at {}: {}",
+                    lag.name,
+                    cfg.path,
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    /// Subscribe to a topic pattern such as `trades.*.BTCUSDT`; see
+    /// [`subscribe_filtered`](Self::subscribe_filtered).
+    pub fn subscribe_topic(
+        &self,
+        pattern: &str,
+    ) -> Result<EventStream, IngestError> {
+        Ok(self.subscribe_filtered(pattern.parse()?))
+    }
+
+    /// The latest event of every venue, symbol and kind matching `filter`,
+    /// ordered by venue, symbol and kind.
+    pub fn snapshot(&self, filter: &Filter) -> Vec<NormalizedEvent> {
+        let mut events: Vec<NormalizedEvent> = Vec::new();
+        for shard in self.shards.iter() {
+            let last = shard.last.lock().unwrap();
+            events.extend(last.events().filter(|event| filter.matches(event)).cloned());
+        }
+        events.sort_by(|a, b| {
+            (&a.venue, &a.symbol, a.kind.as_str()).cmp(&(&b.venue, &b.symbol, b.kind.as_str()))
+        });
+        events
     }
 }
 
 #[derive(Clone)]
 pub struct EventPublisher {
-    tx: broadcast::Sender<NormalizedEvent>,
+    shards: Shards,
+    next_sequence: Arc<AtomicU64>,
 }
 
 impl EventPublisher {
+    /// Publish `event` under the next sequence number.
     pub fn publish(&self, event: NormalizedEvent) {
-        let _ = self.tx.send(event);
+        let sequence = self.next_sequence.fetch_add(1, Ordering::Relaxed);
+        self.send(sequence, event);
+    }
+
+    /// Publish `event` under a sequence number assigned by the caller, such
+    /// as its write-ahead log sequence. Sequences must increase.
+    pub fn publish_sequenced(&self, sequence: u64, event: NormalizedEvent) {
+        self.next_sequence.fetch_max(sequence + 1, Ordering::Relaxed);
+        self.send(sequence, event);
+    }
+
+    /// Deliver a logged `event` again under the `sequence` it was logged
+    /// with, e.g. for a replay from the write-ahead log. The bus sequence
+    /// does not advance and the event enters neither the replay buffers nor
+    /// the latest events, so consumers tracking sequences skip it as seen
+    /// while filtered subscribers, such as sinks, get it again.
+    pub fn republish(&self, sequence: u64, event: NormalizedEvent) {
+        let shard = &self.shards[self.shards.of(&event)];
+        shard.published.inc();
+        let gone = shard.filtered.read().unwrap().publish(&event);
+        shard.unlist(gone);
+        let _ = shard.tx.send((sequence, event));
     }
-}
 
-pub struct EventConsumer {
-    rx: broadcast::Receiver<NormalizedEvent>,
+    fn send(&self, sequence: u64, event: NormalizedEvent) {
+        let shard = &self.shards[self.shards.of(&event)];
+        shard.published.inc();
+        shard.last.lock().unwrap().record(&event);
+        let gone = {
+            // Publishers fill the replay buffer under the index lock, shared
+            // between them, so a subscriber listing itself sees each event
+            // either replayed or queued.
+            let filtered = shard.filtered.read().unwrap();
+            let gone = filtered.publish(&event);
+            if shard.replay_capacity == 0 {
+                shard.evicted.fetch_max(sequence, Ordering::Relaxed);
+            } else {
+                let mut replay = shard.replay.lock().unwrap();
+                if replay.len() >= shard.replay_capacity {
+                    if let Some((evicted, _)) = replay.pop_front() {
+                        shard.evicted.fetch_max(evicted, Ordering::Relaxed);
+                    }
+                }
+                replay.push_back((sequence, event.clone()));
+            }
+            gone
+        };
+        shard.unlist(gone);
+        let _ = shard.tx.send((sequence, event));
+    }
 }
 
-impl EventConsumer {
-    pub async fn recv(&mut self) -> Option<NormalizedEvent> {
-        self.rx.recv().await.ok()
+/// Events from `sequence` onwards still held in the replay buffers, in
+/// order, or `None` if some have been evicted.
+fn replay_from(shards: &[Shard], sequence: u64) -> Option<Vec<Sequenced>> {
+    let mut events = Vec::new();
+    for shard in shards {
+        let replay = shard.replay.lock().unwrap();
+        if shard.evicted.load(Ordering::Relaxed) >= sequence {
+            return None;
+        }
+        events.extend(replay.iter().filter(|(seq, _)| *seq >= sequence).cloned());
     }
+    events.sort_by_key(|(seq, _)| *seq);
+    Some(events)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use chrono::Utc;
-    use tokio_stream::StreamExt;
+    use ingest_core::config::KindBusConfig;
 
     #[tokio::test]
     async fn queue_roundtrip() {
@@ -62,8 +1302,679 @@ mod tests {
             venue: "x".into(),
             symbol: "y".into(),
             timestamp: Utc::now(),
+            received_at: None,
+            kind: ingest_core::event::EventKind::Raw,
             payload: serde_json::json!({}),
         });
         assert!(stream.next().await.is_some());
     }
+
+    #[tokio::test]
+    async fn filtered_subscribers_only_get_matches() {
+        let bus = EventBus::new(16);
+        let pubr = bus.publisher();
+        let filter = Filter::new()
+            .venues(["binance_spot"])
+            .symbols(["BTC*"])
+            .kinds([EventKind::Trade]);
+        let stream = bus.subscribe_filtered(filter);
+        let event = |venue: &str, symbol: &str, kind| NormalizedEvent {
+            venue: venue.into(),
+            symbol: symbol.into(),
+            kind,
+            ..Default::default()
+        };
+        pubr.publish(event("binance_spot", "ETHUSDT", EventKind::Trade));
+        pubr.publish(event("coinbase", "BTCUSD", EventKind::Trade));
+        pubr.publish(event("binance_spot", "BTCUSDT", EventKind::Quote));
+        pubr.publish(event("binance_spot", "BTCUSDT", EventKind::Trade));
+        drop(bus);
+        drop(pubr);
+        let received: Vec<_> = stream.collect().await;
+        assert_eq!(received, vec![event("binance_spot", "BTCUSDT", EventKind::Trade)]);
+    }
+
+    #[tokio::test]
+    async fn topic_subscribers_only_get_their_slice() {
+        let bus = EventBus::new(16);
+        let pubr = bus.publisher();
+        let btc_trades = bus.subscribe_topic("trades.*.BTCUSDT").unwrap();
+        let coinbase = bus.subscribe_topic("*.coinbase.*").unwrap();
+        let event = |venue: &str, symbol: &str, kind| NormalizedEvent {
+            venue: venue.into(),
+            symbol: symbol.into(),
+            kind,
+            ..Default::default()
+        };
+        let published = [
+            event("binance_spot", "BTCUSDT", EventKind::Trade),
+            event("coinbase", "BTCUSDT", EventKind::Trade),
+            event("coinbase", "BTC-USD", EventKind::Quote),
+            event("binance_spot", "BTCUSDT", EventKind::Quote),
+        ];
+        for e in &published {
+            pubr.publish(e.clone());
+        }
+        drop(bus);
+        drop(pubr);
+        assert_eq!(btc_trades.collect::<Vec<_>>().await, published[..2]);
+        assert_eq!(coinbase.collect::<Vec<_>>().await, published[1..3]);
+        assert_eq!(topic(&published[2]), "quotes.coinbase.BTC-USD");
+    }
+
+    #[test]
+    fn snapshot_keeps_the_latest_event_per_key() {
+        let bus = EventBus::new(16);
+        let pubr = bus.publisher();
+        let event = |symbol: &str, kind, price: f64| NormalizedEvent {
+            venue: "binance_spot".into(),
+            symbol: symbol.into(),
+            kind,
+            payload: serde_json::json!({ "price": price }),
+            ..Default::default()
+        };
+        pubr.publish(event("ETHUSDT", EventKind::Trade, 1.0));
+        pubr.publish(event("BTCUSDT", EventKind::Trade, 2.0));
+        pubr.publish(event("BTCUSDT", EventKind::Quote, 3.0));
+        pubr.publish(event("BTCUSDT", EventKind::Trade, 4.0));
+        assert_eq!(
+            bus.snapshot(&Filter::new()),
+            vec![
+                event("BTCUSDT", EventKind::Quote, 3.0),
+                event("BTCUSDT", EventKind::Trade, 4.0),
+                event("ETHUSDT", EventKind::Trade, 1.0),
+            ]
+        );
+        assert_eq!(
+            bus.snapshot(&"trades.*.BTC*".parse().unwrap()),
+            vec![event("BTCUSDT", EventKind::Trade, 4.0)]
+        );
+    }
+
+    #[test]
+    fn parses_topic_patterns() {
+        assert_eq!("*.*.*".parse::<Filter>().unwrap(), Filter::new());
+        assert_eq!(
+            "books.kraken.XBT/USD".parse::<Filter>().unwrap(),
+            Filter::new().kinds([EventKind::Book]).venues(["kraken"]).symbols(["XBT/USD"])
+        );
+        assert_eq!(
+            "trades.*.BRK.B".parse::<Filter>().unwrap().symbols,
+            vec!["BRK.B".to_string()]
+        );
+        assert!("trades.binance_spot".parse::<Filter>().is_err());
+        assert!("fills.*.*".parse::<Filter>().is_err());
+        assert!("trades.bin*.*".parse::<Filter>().is_err());
+    }
+
+    fn bus(capacity: usize, lag_policy: LagPolicy) -> (EventBus, BusMetrics) {
+        let metrics = BusMetrics::new();
+        let cfg = BusConfig {
+            capacity,
+            lag_policy,
+            shards: 1,
+            replay_buffer: 0,
+            kinds: BTreeMap::new(),
+            subscribers: BTreeMap::new(),
+        };
+        (EventBus::from_config(&cfg, &metrics), metrics)
+    }
+
+    fn numbered(n: u64) -> NormalizedEvent {
+        NormalizedEvent {
+            venue: "binance_spot".into(),
+            symbol: format!("S{}", n),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn lagging_consumer_gets_a_gap_marker() {
+        let (bus, metrics) = bus(2, LagPolicy::GapMarker);
+        let pubr = bus.publisher();
+        let mut consumer = bus.subscribe();
+        for n in 0..5 {
+            pubr.publish(numbered(n));
+        }
+        let marker = consumer.recv().await.unwrap();
+        assert_eq!(marker.venue, GAP_VENUE);
+        assert_eq!(marker.payload["gap"]["missed"], 3);
+        assert_eq!(consumer.recv().await.unwrap(), numbered(3));
+        assert_eq!(metrics.lagged.with_label_values(&["broadcast"]).get(), 3);
+    }
+
+    #[tokio::test]
+    async fn lagging_stream_logs_and_carries_on() {
+        let (bus, metrics) = bus(2, LagPolicy::Log);
+        let pubr = bus.publisher();
+        let stream = bus.subscribe_stream();
+        for n in 0..5 {
+            pubr.publish(numbered(n));
+        }
+        drop(bus);
+        drop(pubr);
+        assert_eq!(stream.collect::<Vec<_>>().await, [numbered(3), numbered(4)]);
+        assert_eq!(metrics.lagged.with_label_values(&["broadcast"]).get(), 3);
+        assert_eq!(metrics.disconnects.with_label_values(&["broadcast"]).get(), 0);
+    }
+
+    #[tokio::test]
+    async fn full_filtered_queue_disconnects() {
+        let (bus, metrics) = bus(1, LagPolicy::Disconnect);
+        let pubr = bus.publisher();
+        let stream = bus.subscribe_filtered(Filter::new());
+        pubr.publish(numbered(0));
+        pubr.publish(numbered(1));
+        // The stream ends even though the publisher is still alive.
+        assert_eq!(stream.collect::<Vec<_>>().await, [numbered(0)]);
+        assert_eq!(metrics.dropped.with_label_values(&["filtered"]).get(), 1);
+        assert_eq!(metrics.disconnects.with_label_values(&["filtered"]).get(), 1);
+    }
+
+    #[tokio::test]
+    async fn closing_ends_filtered_streams_once_drained() {
+        let (bus, _) = bus(4, LagPolicy::Disconnect);
+        let pubr = bus.publisher();
+        let stream = bus.subscribe_filtered(Filter::new());
+        pubr.publish(numbered(0));
+        bus.close();
+        pubr.publish(numbered(1));
+        assert_eq!(stream.collect::<Vec<_>>().await, [numbered(0)]);
+        let late = bus.subscribe_filtered(Filter::new());
+        assert_eq!(late.collect::<Vec<_>>().await, []);
+    }
+
+    #[tokio::test]
+    async fn full_filtered_queue_leaves_a_gap_marker() {
+        let (bus, metrics) = bus(2, LagPolicy::GapMarker);
+        let pubr = bus.publisher();
+        let mut stream = Box::pin(bus.subscribe_filtered(Filter::new()));
+        for n in 0..4 {
+            pubr.publish(numbered(n));
+        }
+        assert_eq!(stream.next().await.unwrap(), numbered(0));
+        assert_eq!(stream.next().await.unwrap(), numbered(1));
+        pubr.publish(numbered(4));
+        let marker = stream.next().await.unwrap();
+        assert_eq!(marker.payload["gap"]["missed"], 2);
+        assert_eq!(stream.next().await.unwrap(), numbered(4));
+        assert_eq!(metrics.dropped.with_label_values(&["filtered"]).get(), 2);
+    }
+
+    #[tokio::test]
+    async fn engine_events_skip_a_full_queue() {
+        let (bus, metrics) = bus(2, LagPolicy::Disconnect);
+        let pubr = bus.publisher();
+        let mut stream = Box::pin(bus.subscribe_filtered(Filter::new()));
+        pubr.publish(numbered(0));
+        pubr.publish(numbered(1));
+        let status = NormalizedEvent::adapter_status("binance_spot", false);
+        pubr.publish(status.clone());
+        assert_eq!(stream.next().await.unwrap(), status);
+        assert_eq!(stream.next().await.unwrap(), numbered(0));
+        assert_eq!(metrics.dropped.with_label_values(&["filtered"]).get(), 0);
+        assert_eq!(metrics.queue_depth.with_label_values(&["filtered"]).get(), 1);
+    }
+
+    #[tokio::test]
+    async fn state_lists_subscribers_and_lanes() {
+        let cfg = BusConfig { shards: 2, replay_buffer: 4, ..BusConfig::default() };
+        let bus = EventBus::from_config(&cfg, &BusMetrics::new());
+        let pubr = bus.publisher();
+        let _all = bus.subscribe_queue("all", Filter::new(), 2, LagPolicy::Log);
+        let _btc = bus.subscribe_named("btc", Filter::new().symbols(["BTCUSDT"]));
+        let _consumer = bus.subscribe();
+        for n in 0..3 {
+            pubr.publish(numbered(n));
+        }
+        let state = bus.state();
+        assert_eq!(state.last_sequence, 3);
+        assert_eq!(state.published[&BusKind::Raw], 3);
+        assert_eq!(state.published[&BusKind::Trades], 0);
+        assert_eq!(state.lanes.len(), BusKind::ALL.len() * 2);
+        assert!(state.lanes.iter().all(|lane| lane.receivers == 1));
+        assert_eq!(state.lanes.iter().map(|lane| lane.replay_len).sum::<usize>(), 3);
+        let names: Vec<_> = state.subscribers.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["all", "btc"]);
+        let all = &state.subscribers[0];
+        assert_eq!((all.queued, all.capacity, all.missed), (2, 2, 1));
+        assert_eq!(all.lanes, BusKind::ALL.len() * 2);
+        assert_eq!(state.subscribers[1].lanes, BusKind::ALL.len());
+    }
+
+    #[tokio::test]
+    async fn dropped_streams_leave_the_bus_at_once() {
+        let bus = EventBus::with_shards(16, 2);
+        let quiet = bus.subscribe_named("sse", Filter::new().venues(["quiet"]));
+        let _all = bus.subscribe_named("all", Filter::new());
+        assert_eq!(bus.state().subscribers.len(), 2);
+        drop(quiet);
+        let names: Vec<_> = bus.state().subscribers.iter().map(|s| s.name.clone()).collect();
+        assert_eq!(names, ["all"]);
+    }
+
+    #[tokio::test]
+    async fn named_subscribers_have_their_own_queues() {
+        let metrics = BusMetrics::new();
+        let slow = SubscriberConfig {
+            capacity: Some(1),
+            lag_policy: Some(LagPolicy::Log),
+            spill: None,
+        };
+        let cfg = BusConfig {
+            capacity: 4,
+            lag_policy: LagPolicy::Disconnect,
+            shards: 1,
+            replay_buffer: 0,
+            kinds: BTreeMap::new(),
+            subscribers: BTreeMap::from([("slow".to_string(), slow)]),
+        };
+        let bus = EventBus::from_config(&cfg, &metrics);
+        let pubr = bus.publisher();
+        let mut slow = bus.subscribe_named("slow", Filter::new());
+        let mut fast = bus.subscribe_named("fast", Filter::new());
+        for n in 0..3 {
+            pubr.publish(numbered(n));
+        }
+        let depth = |name: &str| metrics.queue_depth.with_label_values(&[name]).get();
+        assert_eq!((depth("slow"), depth("fast")), (1, 3));
+        assert_eq!(metrics.dropped.with_label_values(&["slow"]).get(), 2);
+        assert_eq!(slow.next().await.unwrap(), numbered(0));
+        assert_eq!(fast.next().await.unwrap(), numbered(0));
+        assert_eq!((depth("slow"), depth("fast")), (0, 2));
+        drop(fast);
+        assert_eq!(depth("fast"), 0);
+    }
+
+    #[tokio::test]
+    async fn sharded_bus_merges_lanes() {
+        let bus = EventBus::with_shards(64, 4);
+        let pubr = bus.publisher();
+        let mut consumer = bus.subscribe();
+        let exact = bus.subscribe_filtered(Filter::new().symbols(["S3", "S7"]));
+        let globbed = bus.subscribe_filtered(Filter::new().symbols(["S1*"]));
+        let published: Vec<_> = (0..2).flat_map(|_| (0..12).map(numbered)).collect();
+        for event in &published {
+            pubr.publish(event.clone());
+        }
+
+        let mut received = Vec::new();
+        for _ in 0..published.len() {
+            received.push(consumer.recv().await.unwrap());
+        }
+        // Lanes interleave, so only check that every event arrived.
+        for n in 0..12 {
+            let symbol = format!("S{}", n);
+            let count = received.iter().filter(|e| e.symbol == symbol).count();
+            assert_eq!(count, 2, "{}", symbol);
+        }
+        assert_eq!(bus.snapshot(&Filter::new()).len(), 12);
+
+        drop(bus);
+        drop(pubr);
+        let symbols = |events: Vec<NormalizedEvent>| -> Vec<String> {
+            events.into_iter().map(|e| e.symbol).collect()
+        };
+        assert_eq!(symbols(exact.collect().await), ["S3", "S7", "S3", "S7"]);
+        assert_eq!(
+            symbols(globbed.collect().await),
+            ["S1", "S10", "S11", "S1", "S10", "S11"]
+        );
+    }
+
+    #[tokio::test]
+    async fn kind_buses_buffer_separately() {
+        let metrics = BusMetrics::new();
+        let books = KindBusConfig {
+            capacity: Some(2),
+            replay_buffer: None,
+        };
+        let cfg = BusConfig {
+            capacity: 4,
+            lag_policy: LagPolicy::GapMarker,
+            shards: 1,
+            replay_buffer: 0,
+            kinds: BTreeMap::from([(BusKind::Books, books)]),
+            subscribers: BTreeMap::new(),
+        };
+        let bus = EventBus::from_config(&cfg, &metrics);
+        let pubr = bus.publisher();
+        let mut consumer = bus.subscribe();
+        let trade = NormalizedEvent {
+            kind: EventKind::Trade,
+            ..numbered(0)
+        };
+        pubr.publish(trade.clone());
+        for n in 1..6 {
+            pubr.publish(NormalizedEvent {
+                kind: EventKind::Book,
+                ..numbered(n)
+            });
+        }
+        // The book burst overflows its own bus only.
+        let mut received = Vec::new();
+        for _ in 0..4 {
+            received.push(consumer.recv().await.unwrap());
+        }
+        assert!(received.contains(&trade));
+        assert!(received
+            .iter()
+            .any(|e| e.venue == GAP_VENUE && e.payload["gap"]["missed"] == 3));
+        assert_eq!(metrics.published.with_label_values(&["trades"]).get(), 1);
+        assert_eq!(metrics.published.with_label_values(&["books"]).get(), 5);
+    }
+
+    #[derive(Clone, Default)]
+    struct MemorySpill(Arc<Mutex<VecDeque<NormalizedEvent>>>);
+
+    impl Spill for MemorySpill {
+        fn append(&mut self, event: &NormalizedEvent) -> Result<(), IngestError> {
+            self.0.lock().unwrap().push_back(event.clone());
+            Ok(())
+        }
+
+        fn pop(&mut self) -> Result<Option<NormalizedEvent>, IngestError> {
+            Ok(self.0.lock().unwrap().pop_front())
+        }
+
+        fn len(&self) -> u64 {
+            self.0.lock().unwrap().len() as u64
+        }
+    }
+
+    #[tokio::test]
+    async fn subscription_replays_the_last_matching_events() {
+        let cfg = BusConfig { shards: 4, ..BusConfig::default() };
+        let bus = EventBus::from_config(&cfg, &BusMetrics::new());
+        let pubr = bus.publisher();
+        let trade = |symbol: &str, id: u64| NormalizedEvent {
+            venue: "binance".into(),
+            symbol: symbol.into(),
+            kind: EventKind::Trade,
+            payload: serde_json::json!({ "id": id }),
+            ..Default::default()
+        };
+        for id in 0..5 {
+            pubr.publish(trade("BTC-USD", id));
+            pubr.publish(trade("ETH-USD", id));
+        }
+        let mut trades = bus
+            .subscription()
+            .venue("binance")
+            .symbols(["BTC-USD"])
+            .kinds([EventKind::Trade])
+            .replay_last(3)
+            .build();
+        assert_eq!(trades.replaying(), 3);
+        pubr.publish(trade("BTC-USD", 5));
+        pubr.publish(trade("ETH-USD", 5));
+        drop(bus);
+        drop(pubr);
+        let received: Vec<_> = (&mut trades).collect().await;
+        assert_eq!(received, (2..6).map(|id| trade("BTC-USD", id)).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn full_queue_spills_and_reads_back_in_order() {
+        let metrics = BusMetrics::new();
+        let archive = SubscriberConfig {
+            capacity: Some(1),
+            lag_policy: None,
+            spill: Some(BusSpillConfig {
+                path: "archive".into(),
+                max_bytes: 1024,
+                max_age_secs: None,
+            }),
+        };
+        let cfg = BusConfig {
+            capacity: 4,
+            lag_policy: LagPolicy::Disconnect,
+            shards: 1,
+            replay_buffer: 0,
+            kinds: BTreeMap::new(),
+            subscribers: BTreeMap::from([("archive".to_string(), archive)]),
+        };
+        let spill = MemorySpill::default();
+        let opened = spill.clone();
+        let bus = EventBus::from_config(&cfg, &metrics)
+            .with_spill(move |_| Ok(Box::new(opened.clone()) as Box<dyn Spill>));
+        let pubr = bus.publisher();
+        let mut archive = bus.subscribe_named("archive", Filter::new());
+        for n in 0..4 {
+            pubr.publish(numbered(n));
+        }
+        while metrics.spilled.with_label_values(&["archive"]).get() < 3 {
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+        for n in 0..2 {
+            assert_eq!(archive.next().await, Some(numbered(n)));
+        }
+        // Still spilling until the spill has been read back.
+        pubr.publish(numbered(4));
+        for n in 2..5 {
+            assert_eq!(archive.next().await, Some(numbered(n)));
+        }
+        tokio::select! {
+            biased;
+            event = archive.next() => panic!("unexpected {:?}", event),
+            _ = std::future::ready(()) => {}
+        }
+        pubr.publish(numbered(5));
+        assert!(spill.0.lock().unwrap().is_empty());
+        assert_eq!(archive.next().await, Some(numbered(5)));
+        assert_eq!(metrics.spilled.with_label_values(&["archive"]).get(), 4);
+        assert_eq!(metrics.dropped.with_label_values(&["archive"]).get(), 0);
+        assert_eq!(metrics.disconnects.with_label_values(&["archive"]).get(), 0);
+    }
+
+    fn replaying_bus(
+        capacity: usize,
+        replay_buffer: usize,
+        shards: usize,
+    ) -> (EventBus, BusMetrics) {
+        let metrics = BusMetrics::new();
+        let cfg = BusConfig {
+            capacity,
+            lag_policy: LagPolicy::GapMarker,
+            shards,
+            replay_buffer,
+            kinds: BTreeMap::new(),
+            subscribers: BTreeMap::new(),
+        };
+        (EventBus::from_config(&cfg, &metrics), metrics)
+    }
+
+    async fn sequences(consumer: &mut EventConsumer, n: usize) -> Vec<u64> {
+        let mut sequences = Vec::new();
+        for _ in 0..n {
+            sequences.push(consumer.recv_sequenced().await.unwrap().0);
+        }
+        sequences
+    }
+
+    #[tokio::test]
+    async fn consumer_starts_from_a_buffered_sequence() {
+        let (bus, _) = replaying_bus(16, 8, 2);
+        let pubr = bus.publisher();
+        for n in 0..4 {
+            pubr.publish(numbered(n));
+        }
+        let mut consumer = bus.subscribe_from(2);
+        pubr.publish(numbered(4));
+        assert_eq!(sequences(&mut consumer, 4).await, [2, 3, 4, 5]);
+        assert_eq!(consumer.last_sequence(), Some(5));
+    }
+
+    #[tokio::test]
+    async fn named_consumer_resumes_after_its_ack() {
+        let (bus, _) = replaying_bus(16, 8, 1);
+        let pubr = bus.publisher();
+        let mut consumer = bus.resume("reader");
+        for n in 0..3 {
+            pubr.publish(numbered(n));
+        }
+        assert_eq!(sequences(&mut consumer, 2).await, [1, 2]);
+        consumer.ack(2);
+        drop(consumer);
+
+        pubr.publish(numbered(3));
+        let mut consumer = bus.resume("reader");
+        assert_eq!(consumer.last_sequence(), Some(2));
+        assert_eq!(sequences(&mut consumer, 2).await, [3, 4]);
+        assert_eq!(consumer.acked(), None);
+    }
+
+    #[tokio::test]
+    async fn republished_events_keep_their_logged_sequence() {
+        let (bus, _) = replaying_bus(16, 8, 1);
+        let pubr = bus.publisher();
+        let mut consumer = bus.subscribe();
+        let mut filtered = bus.subscribe_filtered(Filter::new());
+        for n in 0..2 {
+            pubr.publish(numbered(n));
+        }
+        pubr.republish(1, numbered(0));
+        pubr.publish(numbered(2));
+        assert_eq!(bus.last_sequence(), 3);
+        assert_eq!(sequences(&mut consumer, 3).await, [1, 2, 3]);
+        let symbols: Vec<_> = (&mut filtered).take(4).map(|event| event.symbol).collect().await;
+        assert_eq!(symbols, ["S0", "S1", "S0", "S2"]);
+    }
+
+    #[tokio::test]
+    async fn lagging_consumer_catches_up_from_the_replay_buffer() {
+        let (bus, metrics) = replaying_bus(2, 8, 1);
+        let pubr = bus.publisher();
+        let mut consumer = bus.subscribe();
+        pubr.publish(numbered(0));
+        assert_eq!(sequences(&mut consumer, 1).await, [1]);
+        for n in 1..6 {
+            pubr.publish(numbered(n));
+        }
+        assert_eq!(sequences(&mut consumer, 5).await, [2, 3, 4, 5, 6]);
+        assert_eq!(metrics.lagged.with_label_values(&["broadcast"]).get(), 0);
+    }
+
+    struct Logged(Vec<Sequenced>);
+
+    impl History for Logged {
+        fn read(&self, sequence: u64, limit: usize) -> Result<Vec<Sequenced>, IngestError> {
+            let events = self.0.iter().filter(|(seq, _)| *seq >= sequence);
+            Ok(events.take(limit).cloned().collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn consumer_catches_up_from_history() {
+        let logged = (1..=3).map(|seq| (seq, numbered(seq - 1))).collect();
+        let (bus, _) = replaying_bus(16, 0, 1);
+        let bus = bus.with_history(Logged(logged));
+        let mut consumer = bus.subscribe_from(1);
+        bus.publisher().publish_sequenced(4, numbered(3));
+        assert_eq!(sequences(&mut consumer, 4).await, [1, 2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn subscription_backfills_from_history_past_the_replay_buffer() {
+        let stamped = |n: u64| NormalizedEvent {
+            timestamp: DateTime::from_timestamp(n as i64, 0).unwrap(),
+            ..numbered(n)
+        };
+        let logged: Vec<_> = (1..=6).map(|seq| (seq, stamped(seq))).collect();
+        let (bus, _) = replaying_bus(16, 2, 1);
+        let bus = bus.with_history(Logged(logged.clone()));
+        for (seq, event) in logged {
+            bus.publisher().publish_sequenced(seq, event);
+        }
+        let last = bus.subscription().replay_last(4).build();
+        assert_eq!(last.replaying(), 4);
+        let since = bus.subscription().replay_since(stamped(2).timestamp).build();
+        let both = bus.subscription().replay_since(stamped(2).timestamp).replay_last(2).build();
+        bus.close();
+        assert_eq!(last.collect::<Vec<_>>().await, (3..=6).map(stamped).collect::<Vec<_>>());
+        assert_eq!(since.collect::<Vec<_>>().await, (2..=6).map(stamped).collect::<Vec<_>>());
+        assert_eq!(both.collect::<Vec<_>>().await, (5..=6).map(stamped).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn started_subscription_backfills_by_receive_time() {
+        // Venue times run backwards, as across venues with skewed clocks.
+        let received = |n: u64| NormalizedEvent {
+            timestamp: DateTime::from_timestamp(100 - n as i64, 0).unwrap(),
+            received_at: DateTime::from_timestamp(n as i64, 0),
+            ..numbered(n)
+        };
+        let logged: Vec<_> = (1..=6).map(|seq| (seq, received(seq))).collect();
+        let (bus, _) = replaying_bus(16, 2, 1);
+        let bus = bus.with_history(Logged(logged.clone()));
+        for (seq, event) in logged {
+            bus.publisher().publish_sequenced(seq, event);
+        }
+        let since = DateTime::from_timestamp(3, 0).unwrap();
+        let started = bus.subscription().replay_since(since).start().await;
+        bus.close();
+        assert_eq!(started.collect::<Vec<_>>().await, (3..=6).map(received).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn unbuffered_start_gets_a_gap_marker() {
+        let (bus, _) = replaying_bus(16, 0, 1);
+        let pubr = bus.publisher();
+        for n in 0..3 {
+            pubr.publish(numbered(n));
+        }
+        let mut consumer = bus.subscribe_from(1);
+        pubr.publish(numbered(3));
+        let (sequence, marker) = consumer.recv_sequenced().await.unwrap();
+        assert_eq!(sequence, 0);
+        assert_eq!(marker.payload["gap"]["missed"], 3);
+        assert_eq!(consumer.recv_sequenced().await.unwrap(), (4, numbered(3)));
+    }
+
+    #[test]
+    fn sink_routes_leave_out_engine_events() {
+        let status = NormalizedEvent::adapter_status("binance_spot", false);
+        let everything = Filter::from(&SinkRoute::default());
+        assert!(everything.matches(&numbered(0)));
+        assert!(!everything.matches(&status));
+        assert!(!everything.matches(&gap_marker(3)));
+        let engine = SinkRoute {
+            venues: vec![ENGINE_VENUE.into()],
+            ..SinkRoute::default()
+        };
+        assert!(Filter::from(&engine).matches(&status));
+    }
+
+    #[test]
+    fn sink_routes_match_symbol_globs() {
+        let route = SinkRoute {
+            venues: vec!["binance_spot".into()],
+            symbols: vec!["BTC*".into()],
+            kinds: vec![EventKind::Trade],
+        };
+        let filter = Filter::from(&route);
+        let mut event = NormalizedEvent {
+            venue: "binance_spot".into(),
+            symbol: "BTCUSDT".into(),
+            kind: EventKind::Trade,
+            ..Default::default()
+        };
+        assert!(filter.matches(&event));
+        event.kind = EventKind::Quote;
+        assert!(!filter.matches(&event));
+        event.kind = EventKind::Trade;
+        event.symbol = "ETHUSDT".into();
+        assert!(!filter.matches(&event));
+    }
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob("*", ""));
+        assert!(glob("BTC*", "BTCUSDT"));
+        assert!(glob("*USDT", "BTCUSDT"));
+        assert!(glob("B?C*T", "BTCUSDT"));
+        assert!(glob("*US*T", "BTCUSDT"));
+        assert!(!glob("ETH*", "BTCUSDT"));
+        assert!(!glob("BTC", "BTCUSDT"));
+    }
 }
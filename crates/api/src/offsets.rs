@@ -0,0 +1,105 @@
+//! Offsets of named consumers, optionally kept in a file so that
+//! [`EventBus::resume`](crate::EventBus::resume) continues where a consumer
+//! of the previous run left off.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use ingest_core::error::IngestError;
+
+/// Last sequence acknowledged by each named consumer.
+#[derive(Default)]
+pub(crate) struct Offsets {
+    acked: Mutex<HashMap<String, u64>>,
+    /// Where [`persist`](Self::persist) writes them, if anywhere.
+    path: Option<PathBuf>,
+    /// Acknowledged since they were last written.
+    dirty: AtomicBool,
+}
+
+impl Offsets {
+    /// The offsets written to `path` by a previous run, if any, to be
+    /// written there again.
+    pub(crate) fn open(path: PathBuf) -> Result<Self, IngestError> {
+        let acked = match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data).map_err(|e| {
+                IngestError::Validation(format!("consumer offsets {}: {}", path.display(), e))
+            })?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            acked: Mutex::new(acked),
+            path: Some(path),
+            dirty: AtomicBool::new(false),
+        })
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<u64> {
+        self.acked.lock().unwrap().get(name).copied()
+    }
+
+    pub(crate) fn ack(&self, name: &str, sequence: u64) {
+        let mut acked = self.acked.lock().unwrap();
+        let offset = acked.entry(name.to_string()).or_default();
+        *offset = (*offset).max(sequence);
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// Every offset, by consumer name.
+    pub(crate) fn all(&self) -> BTreeMap<String, u64> {
+        let acked = self.acked.lock().unwrap();
+        acked
+            .iter()
+            .map(|(name, seq)| (name.clone(), *seq))
+            .collect()
+    }
+
+    /// Write the offsets if any changed, replacing the file at once so a
+    /// crash leaves either the old offsets or the new ones.
+    pub(crate) fn persist(&self) -> Result<(), IngestError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let data = serde_json::to_vec(&self.all())?;
+        let written = (|| {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let tmp = path.with_extension("tmp");
+            fs::write(&tmp, data)?;
+            fs::rename(&tmp, path)
+        })();
+        if written.is_err() {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        Ok(written?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offsets_survive_a_restart() {
+        let dir = std::env::temp_dir().join(format!("offsets-{}", std::process::id()));
+        let path = dir.join("offsets.json");
+        let offsets = Offsets::open(path.clone()).unwrap();
+        assert_eq!(offsets.get("reader"), None);
+        offsets.ack("reader", 7);
+        offsets.ack("reader", 5);
+        offsets.persist().unwrap();
+
+        let reopened = Offsets::open(path).unwrap();
+        assert_eq!(reopened.get("reader"), Some(7));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
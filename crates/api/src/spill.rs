@@ -0,0 +1,275 @@
+//! Disk overflow for named subscribers that fall behind.
+//!
+//! Once a subscriber's queue is full, its events go to a [`Spill`] instead of
+//! being dropped, and keep going there until the subscriber has read the
+//! spill back, so it still sees every event in order. Spills are opened by
+//! the function given to [`EventBus::with_spill`](crate::EventBus::with_spill)
+//! for subscribers configured with a `spill` section.
+//!
+//! Each spill is written and read back on a thread of its own, so neither the
+//! publishers nor the subscriber's stream wait on the disk: publishers hand
+//! events to the thread over a bounded channel, and the thread reads them
+//! back into a bounded queue the stream takes them from.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc as std_mpsc, Arc};
+use std::time::Duration;
+
+use ingest_core::{config::BusSpillConfig, error::IngestError, event::NormalizedEvent};
+use prometheus::IntCounter;
+use tokio::sync::mpsc;
+
+/// Events on their way to the spill thread.
+const WRITE_AHEAD: usize = 4096;
+/// Events read back ahead of the stream.
+const READ_AHEAD: usize = 256;
+/// How long the spill thread waits for events while it has nothing to read
+/// back, and while the stream has no room for what it read back.
+const IDLE: Duration = Duration::from_millis(100);
+const BUSY: Duration = Duration::from_millis(5);
+
+/// Storage for the backlog of one subscriber.
+pub trait Spill: Send {
+    fn append(&mut self, event: &NormalizedEvent) -> Result<(), IngestError>;
+
+    /// The oldest event not read back yet.
+    fn pop(&mut self) -> Result<Option<NormalizedEvent>, IngestError>;
+
+    /// Events waiting, including any left by a previous run.
+    fn len(&self) -> u64;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Events discarded to keep within the size or age bounds since the last
+    /// call.
+    fn take_lost(&mut self) -> u64 {
+        0
+    }
+}
+
+pub(crate) type OpenSpill =
+    Arc<dyn Fn(&BusSpillConfig) -> Result<Box<dyn Spill>, IngestError> + Send + Sync>;
+
+/// Spilled events the stream has not taken yet, whether still on their way
+/// to the spill, in it or read back.
+#[derive(Clone, Default)]
+struct Backlog(Arc<AtomicU64>);
+
+impl Backlog {
+    fn get(&self) -> u64 {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    fn add(&self, events: u64) {
+        self.0.fetch_add(events, Ordering::SeqCst);
+    }
+
+    fn sub(&self, events: u64) {
+        let _ = self
+            .0
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |backlog| {
+                Some(backlog.saturating_sub(events))
+            });
+    }
+}
+
+/// The publishers' side of a subscriber's spill.
+pub(crate) struct Overflow {
+    name: Arc<str>,
+    tx: std_mpsc::SyncSender<NormalizedEvent>,
+    backlog: Backlog,
+    dropped: IntCounter,
+}
+
+/// The stream's side of a subscriber's spill.
+pub(crate) struct ReadBack {
+    rx: mpsc::Receiver<NormalizedEvent>,
+    backlog: Backlog,
+}
+
+/// Start the thread running `spill` for the subscriber `name`.
+pub(crate) fn overflow(
+    name: Arc<str>,
+    spill: Box<dyn Spill>,
+    spilled: IntCounter,
+    dropped: IntCounter,
+) -> Result<(Overflow, ReadBack), IngestError> {
+    let (tx, appends) = std_mpsc::sync_channel(WRITE_AHEAD);
+    let (back, rx) = mpsc::channel(READ_AHEAD);
+    let backlog = Backlog::default();
+    backlog.add(spill.len());
+    let writer = Writer {
+        name: name.clone(),
+        spill,
+        appends,
+        back,
+        backlog: backlog.clone(),
+        spilled,
+        dropped: dropped.clone(),
+        broken: false,
+    };
+    std::thread::Builder::new()
+        .name(format!("spill-{}", name))
+        .spawn(move || writer.run())?;
+    let overflow = Overflow {
+        name,
+        tx,
+        backlog: backlog.clone(),
+        dropped,
+    };
+    Ok((overflow, ReadBack { rx, backlog }))
+}
+
+impl Overflow {
+    /// Whether events are spilled, so newer ones must follow them there.
+    pub(crate) fn active(&self) -> bool {
+        self.backlog.get() > 0
+    }
+
+    pub(crate) fn push(&self, event: &NormalizedEvent) {
+        self.backlog.add(1);
+        if self.tx.try_send(event.clone()).is_err() {
+            self.backlog.sub(1);
+            self.dropped.inc();
+            tracing::warn!(
+                "bus subscriber {} dropped an event, its spill is behind",
+                self.name
+            );
+        }
+    }
+}
+
+impl ReadBack {
+    /// Whether spilled events are still to come, ahead of any queued later.
+    pub(crate) fn pending(&self) -> bool {
+        self.backlog.get() > 0
+    }
+
+    pub(crate) fn poll_recv(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<NormalizedEvent>> {
+        let polled = self.rx.poll_recv(cx);
+        match &polled {
+            std::task::Poll::Ready(Some(_)) => self.backlog.sub(1),
+            // The spill thread is gone, and with it what it held.
+            std::task::Poll::Ready(None) => self.backlog.sub(u64::MAX),
+            std::task::Poll::Pending => {}
+        }
+        polled
+    }
+}
+
+/// Runs a spill: appends what the publishers send and reads it back as the
+/// stream makes room.
+struct Writer {
+    name: Arc<str>,
+    spill: Box<dyn Spill>,
+    appends: std_mpsc::Receiver<NormalizedEvent>,
+    back: mpsc::Sender<NormalizedEvent>,
+    backlog: Backlog,
+    spilled: IntCounter,
+    dropped: IntCounter,
+    /// The spill failed to read back, so it takes no more events.
+    broken: bool,
+}
+
+impl Writer {
+    fn run(mut self) {
+        // Read back, waiting for room in the stream's queue.
+        let mut unsent: Option<NormalizedEvent> = None;
+        let mut closed = false;
+        loop {
+            if self.back.is_closed() {
+                return;
+            }
+            let wait = if unsent.is_some() || !self.broken && !self.spill.is_empty() {
+                BUSY
+            } else {
+                IDLE
+            };
+            if closed {
+                if unsent.is_none() && (self.broken || self.spill.is_empty()) {
+                    return;
+                }
+                std::thread::sleep(wait);
+            } else {
+                match self.appends.recv_timeout(wait) {
+                    Ok(event) => {
+                        self.append(&event);
+                        while let Ok(event) = self.appends.try_recv() {
+                            self.append(&event);
+                        }
+                    }
+                    Err(std_mpsc::RecvTimeoutError::Timeout) => {}
+                    Err(std_mpsc::RecvTimeoutError::Disconnected) => closed = true,
+                }
+            }
+            loop {
+                let event = match unsent.take() {
+                    Some(event) => event,
+                    None => match self.pop() {
+                        Some(event) => event,
+                        None => break,
+                    },
+                };
+                match self.back.try_send(event) {
+                    Ok(()) => {}
+                    Err(mpsc::error::TrySendError::Full(event)) => {
+                        unsent = Some(event);
+                        break;
+                    }
+                    Err(mpsc::error::TrySendError::Closed(_)) => return,
+                }
+            }
+        }
+    }
+
+    fn append(&mut self, event: &NormalizedEvent) {
+        if self.broken {
+            self.backlog.sub(1);
+            self.dropped.inc();
+            return;
+        }
+        match self.spill.append(event) {
+            Ok(()) => self.spilled.inc(),
+            Err(e) => {
+                self.backlog.sub(1);
+                self.dropped.inc();
+                tracing::warn!(
+                    "bus subscriber {} failed to spill an event: {}",
+                    self.name,
+                    e
+                );
+            }
+        }
+    }
+
+    /// The next spilled event, or `None` once the spill has been read back.
+    fn pop(&mut self) -> Option<NormalizedEvent> {
+        if self.broken {
+            return None;
+        }
+        let event = self.spill.pop();
+        let lost = self.spill.take_lost();
+        self.backlog.sub(lost);
+        self.dropped.inc_by(lost);
+        match event {
+            Ok(event) => event,
+            Err(e) => {
+                // What is left cannot be read back, so publishers queue
+                // again rather than wait on it.
+                self.broken = true;
+                self.backlog.sub(u64::MAX);
+                tracing::error!(
+                    "bus subscriber {} gave up its spill, failed to read it back: {}",
+                    self.name,
+                    e
+                );
+                None
+            }
+        }
+    }
+}
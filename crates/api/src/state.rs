@@ -0,0 +1,153 @@
+//! A structured dump of the bus internals, for debugging stuck consumers.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Instant;
+
+use ingest_core::config::{BusKind, LagPolicy};
+use serde::{Deserialize, Serialize};
+
+use crate::spill::Overflow;
+use crate::{BusMetrics, EventBus, Subscriber};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BusState {
+    /// Sequence of the latest published event.
+    pub last_sequence: u64,
+    /// Events published on each logical bus.
+    pub published: BTreeMap<BusKind, u64>,
+    /// Events per second across every bus since the previous dump, or since
+    /// the bus was created.
+    pub publish_rate: f64,
+    pub lanes: Vec<LaneState>,
+    /// Filtered subscribers, by name.
+    pub subscribers: Vec<SubscriberState>,
+    /// Last sequence acknowledged by each named consumer.
+    pub acked: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LaneState {
+    pub bus: BusKind,
+    pub shard: usize,
+    /// Broadcast subscribers and consumers reading the lane.
+    pub receivers: usize,
+    /// Events in the broadcast buffer not yet read by every receiver.
+    pub buffered: usize,
+    pub replay_len: usize,
+    pub replay_capacity: usize,
+    /// Filtered subscribers listening on the lane.
+    pub subscribers: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubscriberState {
+    pub name: String,
+    pub policy: LagPolicy,
+    pub queued: usize,
+    pub capacity: usize,
+    /// Events dropped since the last one that fit in the queue.
+    pub missed: u64,
+    pub spilling: bool,
+    /// Whether the subscriber was disconnected or its stream dropped.
+    pub closed: bool,
+    pub lanes: usize,
+}
+
+/// Published events as of the previous dump.
+pub(crate) struct RateSample {
+    at: Instant,
+    published: u64,
+}
+
+impl RateSample {
+    pub(crate) fn new(metrics: &BusMetrics) -> Self {
+        Self {
+            at: Instant::now(),
+            published: BusKind::ALL
+                .iter()
+                .map(|bus| published(metrics, *bus))
+                .sum(),
+        }
+    }
+}
+
+fn published(metrics: &BusMetrics, bus: BusKind) -> u64 {
+    metrics.published.with_label_values(&[bus.as_str()]).get()
+}
+
+impl EventBus {
+    /// The subscribers, queue depths, buffer occupancy and publish counts of
+    /// the bus as they stand.
+    pub fn state(&self) -> BusState {
+        let published: BTreeMap<BusKind, u64> = BusKind::ALL
+            .iter()
+            .map(|bus| (*bus, published(&self.metrics, *bus)))
+            .collect();
+        let total: u64 = published.values().sum();
+        let publish_rate = {
+            let mut sample = self.rate.lock().unwrap();
+            let elapsed = sample.at.elapsed().as_secs_f64();
+            let events = total.saturating_sub(sample.published);
+            *sample = RateSample {
+                at: Instant::now(),
+                published: total,
+            };
+            if elapsed > 0.0 {
+                events as f64 / elapsed
+            } else {
+                0.0
+            }
+        };
+
+        let mut lanes = Vec::with_capacity(self.shards.len());
+        let mut subscribers: HashMap<*const Subscriber, SubscriberState> = HashMap::new();
+        for (i, shard) in self.shards.iter().enumerate() {
+            let filtered = shard.filtered.read().unwrap();
+            for subscriber in filtered.subscribers.values() {
+                subscribers
+                    .entry(Arc::as_ptr(subscriber))
+                    .or_insert_with(|| subscriber.state())
+                    .lanes += 1;
+            }
+            let replay = shard.replay.lock().unwrap();
+            lanes.push(LaneState {
+                bus: BusKind::ALL[i / self.shards.shards],
+                shard: i % self.shards.shards,
+                receivers: shard.tx.receiver_count(),
+                buffered: shard.tx.len(),
+                replay_len: replay.len(),
+                replay_capacity: shard.replay_capacity,
+                subscribers: filtered.subscribers.len(),
+            });
+        }
+        let mut subscribers: Vec<_> = subscribers.into_values().collect();
+        subscribers.sort_by(|a, b| a.name.cmp(&b.name));
+
+        BusState {
+            last_sequence: self.last_sequence(),
+            published,
+            publish_rate,
+            lanes,
+            subscribers,
+            acked: self.offsets.all(),
+        }
+    }
+}
+
+impl Subscriber {
+    fn state(&self) -> SubscriberState {
+        let queue = self.queue.lock().unwrap();
+        let tx = queue.tx.as_ref().filter(|tx| !tx.is_closed());
+        SubscriberState {
+            name: self.lag.name.to_string(),
+            policy: self.lag.policy,
+            queued: tx.map_or(0, |tx| self.capacity - tx.capacity()),
+            capacity: self.capacity,
+            missed: queue.missed,
+            spilling: self.overflow.as_ref().is_some_and(Overflow::active),
+            closed: tx.is_none(),
+            lanes: 0,
+        }
+    }
+}
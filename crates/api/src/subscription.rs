@@ -0,0 +1,160 @@
+//! Fluent subscriptions for applications embedding the bus.
+//!
+//! ```
+//! # use api::EventBus;
+//! # use ingest_core::event::EventKind;
+//! # let bus = EventBus::new(1024);
+//! let trades = bus
+//!     .subscription()
+//!     .venue("binance")
+//!     .symbols(["BTC-USD"])
+//!     .kinds([EventKind::Trade])
+//!     .replay_last(100)
+//!     .build();
+//! ```
+//!
+//! A subscription is a named filtered subscriber (see
+//! [`EventBus::subscribe_named`]) that can start with the most recent
+//! matching events, out of the replay buffers and, for older ones, the
+//! bus history (see [`EventBus::with_history`]).
+
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use chrono::{DateTime, Utc};
+use ingest_core::event::{EventKind, NormalizedEvent};
+use tokio_stream::Stream;
+
+use crate::{Backfill, EventBus, EventStream, Filter, Replaying};
+
+/// Name of subscriptions built without [`SubscriptionBuilder::name`].
+const DEFAULT_NAME: &str = "subscription";
+
+/// Builds a [`Subscription`]; see [`EventBus::subscription`].
+#[must_use]
+pub struct SubscriptionBuilder<'a> {
+    bus: &'a EventBus,
+    name: String,
+    filter: Filter,
+    replay: Option<usize>,
+    since: Option<DateTime<Utc>>,
+}
+
+impl<'a> SubscriptionBuilder<'a> {
+    pub(crate) fn new(bus: &'a EventBus) -> Self {
+        Self {
+            bus,
+            name: DEFAULT_NAME.into(),
+            filter: Filter::new(),
+            replay: None,
+            since: None,
+        }
+    }
+
+    /// The subscriber name its queue settings and metrics go by.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    pub fn venue(self, venue: impl Into<String>) -> Self {
+        self.venues([venue])
+    }
+
+    pub fn venues<S: Into<String>>(mut self, venues: impl IntoIterator<Item = S>) -> Self {
+        self.filter = self.filter.venues(venues);
+        self
+    }
+
+    /// Symbols may be globs such as `BTC*`.
+    pub fn symbol(self, symbol: impl Into<String>) -> Self {
+        self.symbols([symbol])
+    }
+
+    pub fn symbols<S: Into<String>>(mut self, symbols: impl IntoIterator<Item = S>) -> Self {
+        self.filter = self.filter.symbols(symbols);
+        self
+    }
+
+    pub fn kind(self, kind: EventKind) -> Self {
+        self.kinds([kind])
+    }
+
+    pub fn kinds(mut self, kinds: impl IntoIterator<Item = EventKind>) -> Self {
+        self.filter = self.filter.kinds(kinds);
+        self
+    }
+
+    /// Start with up to the last `events` matching events, as far as the
+    /// replay buffers and history still hold them.
+    pub fn replay_last(mut self, events: usize) -> Self {
+        self.replay = Some(events);
+        self
+    }
+
+    /// Start with the matching events received at or after `since`, or with
+    /// the last of them if [`replay_last`](Self::replay_last) is given too.
+    /// History is looked back through for a bounded number of events.
+    pub fn replay_since(mut self, since: DateTime<Utc>) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    /// Subscribe, reading any history to replay on the calling thread.
+    pub fn build(self) -> Subscription {
+        let bus = self.bus;
+        let (live, replaying) = self.subscribe();
+        Subscription {
+            replayed: bus.replayed(replaying).into(),
+            live,
+        }
+    }
+
+    /// Subscribe, reading any history to replay on the blocking pool, a
+    /// few subscriptions at a time, for async tasks that must not wait on
+    /// the disk.
+    pub async fn start(self) -> Subscription {
+        let bus = self.bus;
+        let (live, replaying) = self.subscribe();
+        Subscription {
+            replayed: bus.replayed_blocking(replaying).await.into(),
+            live,
+        }
+    }
+
+    fn subscribe(self) -> (EventStream, Replaying) {
+        let backfill = Backfill {
+            last: self
+                .replay
+                .unwrap_or(if self.since.is_some() { usize::MAX } else { 0 }),
+            since: self.since,
+        };
+        self.bus
+            .subscribe_replaying(&self.name, self.filter, backfill)
+    }
+}
+
+/// The replayed events of a subscription, then its live ones.
+pub struct Subscription {
+    replayed: VecDeque<NormalizedEvent>,
+    live: EventStream,
+}
+
+impl Subscription {
+    /// Replayed events not read yet.
+    pub fn replaying(&self) -> usize {
+        self.replayed.len()
+    }
+}
+
+impl Stream for Subscription {
+    type Item = NormalizedEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(event) = self.replayed.pop_front() {
+            return Poll::Ready(Some(event));
+        }
+        Pin::new(&mut self.live).poll_next(cx)
+    }
+}
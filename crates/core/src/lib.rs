@@ -1,244 +1,3437 @@
 pub mod event {
     use chrono::{DateTime, Utc};
+    use schemars::JsonSchema;
     use serde::{Deserialize, Serialize};
 
-    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    /// Exact decimal for prices and quantities, carried as the venue's
+    /// decimal string, e.g. `"16541.12"`, rather than a binary float.
+    pub use bigdecimal::BigDecimal as Decimal;
+
+    /// What an event's payload describes.
+    #[derive(
+        Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Hash, Default,
+    )]
+    #[serde(rename_all = "snake_case")]
+    pub enum EventKind {
+        Trade,
+        Ticker,
+        /// Best bid and offer.
+        Quote,
+        Book,
+        /// Venue payload passed through without typed normalization.
+        #[default]
+        Raw,
+    }
+
+    impl EventKind {
+        pub fn as_str(&self) -> &'static str {
+            match self {
+                EventKind::Trade => "trade",
+                EventKind::Ticker => "ticker",
+                EventKind::Quote => "quote",
+                EventKind::Book => "book",
+                EventKind::Raw => "raw",
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Default)]
     pub struct NormalizedEvent {
         pub venue: String,
         pub symbol: String,
+        /// Time of the event at the venue.
         pub timestamp: DateTime<Utc>,
+        /// Local time the frame carrying this event was received.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub received_at: Option<DateTime<Utc>>,
+        #[serde(default)]
+        pub kind: EventKind,
+        /// A `Trade` or `Quote` for those kinds, the venue's payload otherwise.
         pub payload: serde_json::Value,
     }
-}
 
-pub mod config {
-    use serde::{Deserialize, Serialize};
+    impl NormalizedEvent {
+        /// Decode the typed trade carried by a `Trade` event.
+        pub fn trade(&self) -> Option<Trade> {
+            if self.kind != EventKind::Trade {
+                return None;
+            }
+            serde_json::from_value(self.payload.clone()).ok()
+        }
 
-    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
-    pub struct Config {
-        pub venues: Vec<VenueConfig>,
+        /// Decode the typed best bid/offer carried by a `Quote` event.
+        pub fn quote(&self) -> Option<Quote> {
+            if self.kind != EventKind::Quote {
+                return None;
+            }
+            serde_json::from_value(self.payload.clone()).ok()
+        }
+
+        /// An engine event reporting that the adapter of `venue` connected
+        /// (`up`) or lost its connection.
+        pub fn adapter_status(venue: &str, up: bool) -> Self {
+            let state = if up { "up" } else { "down" };
+            NormalizedEvent {
+                venue: ENGINE_VENUE.into(),
+                timestamp: Utc::now(),
+                kind: EventKind::Raw,
+                payload: serde_json::json!({ "adapter": { "venue": venue, "state": state } }),
+                ..Default::default()
+            }
+        }
+
+        /// The venue and whether it is up, for an event made by
+        /// [`adapter_status`](Self::adapter_status).
+        pub fn as_adapter_status(&self) -> Option<(&str, bool)> {
+            if self.venue != ENGINE_VENUE {
+                return None;
+            }
+            let adapter = self.payload.get("adapter")?;
+            let up = match adapter.get("state")?.as_str()? {
+                "up" => true,
+                "down" => false,
+                _ => return None,
+            };
+            Some((adapter.get("venue")?.as_str()?, up))
+        }
+
+        /// An engine event reporting the symbols the adapter of a venue
+        /// subscribed to.
+        pub fn adapter_subscription(subscription: &Subscription) -> Self {
+            NormalizedEvent {
+                venue: ENGINE_VENUE.into(),
+                timestamp: Utc::now(),
+                kind: EventKind::Raw,
+                payload: serde_json::json!({ "subscription": subscription }),
+                ..Default::default()
+            }
+        }
+
+        /// The subscription of an event made by
+        /// [`adapter_subscription`](Self::adapter_subscription).
+        pub fn as_adapter_subscription(&self) -> Option<Subscription> {
+            if self.venue != ENGINE_VENUE {
+                return None;
+            }
+            serde_json::from_value(self.payload.get("subscription")?.clone()).ok()
+        }
+
+        /// Roughly the bytes the event holds in memory, counting the
+        /// strings and the payload it owns.
+        pub fn size_hint(&self) -> usize {
+            std::mem::size_of::<Self>()
+                + self.venue.len()
+                + self.symbol.len()
+                + value_size(&self.payload)
+        }
+    }
+
+    fn value_size(value: &serde_json::Value) -> usize {
+        use serde_json::Value;
+
+        std::mem::size_of::<Value>()
+            + match value {
+                Value::String(s) => s.len(),
+                Value::Array(items) => items.iter().map(value_size).sum(),
+                Value::Object(fields) => fields
+                    .iter()
+                    .map(|(key, value)| key.len() + value_size(value))
+                    .sum(),
+                _ => 0,
+            }
+    }
+
+    /// The JSON Schema of events as the `json` encoding carries them, with
+    /// the payloads of trades and quotes among its definitions.
+    pub fn schema() -> serde_json::Value {
+        let mut generator = schemars::SchemaGenerator::default();
+        generator.subschema_for::<Trade>();
+        generator.subschema_for::<Quote>();
+        serde_json::to_value(generator.into_root_schema_for::<NormalizedEvent>())
+            .expect("schemas serialize")
     }
 
+    /// Symbols a venue's adapter subscribed to.
     #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
-    pub struct VenueConfig {
-        pub name: String,
+    pub struct Subscription {
+        pub venue: String,
         pub symbols: Vec<String>,
+        /// Whether the symbols were discovered rather than configured.
+        pub discovered: bool,
+    }
+
+    /// Venue name used for feeds consolidated across venues.
+    pub const COMPOSITE_VENUE: &str = "COMPOSITE";
+
+    /// Venue name of events emitted by the engine itself rather than a
+    /// venue, such as adapter status changes and bus gap markers.
+    pub const ENGINE_VENUE: &str = "_bus";
+
+    /// Side of the taker in a trade.
+    #[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Hash)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Side {
+        Buy,
+        Sell,
+    }
+
+    /// Payload of a `Trade` event.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    pub struct Trade {
+        pub trade_id: u64,
+        pub price: Decimal,
+        pub quantity: Decimal,
+        pub side: Side,
+    }
+
+    /// Payload of a `Quote` event.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    pub struct Quote {
+        pub bid_price: Decimal,
+        pub bid_quantity: Decimal,
+        pub ask_price: Decimal,
+        pub ask_quantity: Decimal,
+    }
+}
+
+pub mod config {
+    use std::collections::BTreeMap;
+
+    use schemars::JsonSchema;
+    use serde::{de, Deserialize, Serialize};
+
+    use crate::event::{EventKind, NormalizedEvent, COMPOSITE_VENUE, ENGINE_VENUE};
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    pub struct Config {
+        pub venues: Vec<VenueConfig>,
         #[serde(default)]
-        pub discover: bool,
+        pub pipeline: PipelineConfig,
         #[serde(default)]
-        pub ws_base: Option<String>,
+        pub bus: BusConfig,
+        /// Named output sinks, e.g. `[sinks.trades_kafka]`.
         #[serde(default)]
-        pub rest_base: Option<String>,
+        pub sinks: BTreeMap<String, SinkConfig>,
+        /// Write-ahead log of published events; disabled when absent.
         #[serde(default)]
-        pub http_timeout_secs: Option<u64>,
+        pub wal: Option<WalConfig>,
+        /// gRPC streaming server for consumers; disabled when absent.
         #[serde(default)]
-        pub channels: ChannelConfig,
+        pub grpc: Option<GrpcConfig>,
+        /// WebSocket fan-out on the ops server at `/ws`; disabled when absent.
         #[serde(default)]
-        pub discovery: Option<DiscoveryConfig>,
-    }
-
-    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
-    pub struct DiscoveryConfig {
+        pub ws: Option<WsConfig>,
+        /// Arrow Flight server; disabled when absent.
         #[serde(default)]
-        pub enabled: bool,
+        pub flight: Option<FlightConfig>,
+        /// Event metrics exported on the ops server.
         #[serde(default)]
-        pub quote_whitelist: Vec<String>,
+        pub metrics: MetricsConfig,
         #[serde(default)]
-        pub symbol_blacklist: Vec<String>,
+        pub ops: OpsConfig,
+        #[serde(default)]
+        pub log: LogConfig,
+        #[serde(default)]
+        pub shutdown: ShutdownConfig,
+        /// Restarts adapters that stop delivering events; disabled when
+        /// absent.
+        #[serde(default)]
+        pub watchdog: Option<WatchdogConfig>,
+        #[serde(default)]
+        pub supervisor: SupervisorConfig,
+        /// Runs venue adapters in worker processes; in the engine process
+        /// when absent.
+        #[serde(default)]
+        pub workers: Option<WorkersConfig>,
+        /// Sheds low-priority events once buffered events take more memory
+        /// than budgeted; unbounded but for queue capacities when absent.
+        #[serde(default)]
+        pub memory: Option<MemoryConfig>,
+        /// Shares the venues among several instances, each ingested by the
+        /// one holding its lease; every venue is ingested here when absent.
+        #[serde(default)]
+        pub cluster: Option<ClusterConfig>,
+        /// Runs this instance as one of an active/standby pair, ingesting
+        /// only while it is the active one; active when absent.
+        #[serde(default)]
+        pub standby: Option<StandbyConfig>,
+        /// Webhook notifications of unhealthy venues, subscribers and sinks;
+        /// disabled when absent.
+        #[serde(default)]
+        pub alerts: Option<AlertsConfig>,
+        /// Tuning of the Tokio runtime `ingestd` builds; read at start only.
+        #[serde(default)]
+        pub runtime: RuntimeConfig,
     }
 
-    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
-    pub struct ChannelConfig {
-        #[serde(default = "default_trades")]
-        pub trades: bool,
-        #[serde(default)]
-        pub ticker: Option<TickerConfig>,
+    /// What changed between two configs; see [`Config::diff`].
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Default)]
+    pub struct ConfigDiff {
+        pub venues_added: Vec<String>,
+        pub venues_removed: Vec<String>,
+        /// Venues present in both whose settings differ.
+        pub venues_changed: Vec<String>,
+        /// Other top-level sections that differ, e.g. `sinks`.
+        pub sections_changed: Vec<String>,
     }
 
-    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
-    pub struct TickerConfig {
-        pub enabled: bool,
+    impl ConfigDiff {
+        pub fn is_empty(&self) -> bool {
+            self.venues_added.is_empty()
+                && self.venues_removed.is_empty()
+                && self.venues_changed.is_empty()
+                && self.sections_changed.is_empty()
+        }
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    pub struct BusConfig {
+        /// Events buffered per subscriber before it lags.
+        #[serde(default = "default_bus_capacity")]
+        pub capacity: usize,
         #[serde(default)]
-        pub mode: Option<String>,
+        pub lag_policy: LagPolicy,
+        /// Lanes the bus is split into by symbol hash.
+        #[serde(default = "default_bus_shards")]
+        pub shards: usize,
+        /// Events kept per lane for consumers catching up by sequence.
+        #[serde(default = "default_bus_replay_buffer")]
+        pub replay_buffer: usize,
+        /// Buffer settings for the logical buses, e.g. `[bus.kinds.books]`.
+        #[serde(default)]
+        pub kinds: BTreeMap<BusKind, KindBusConfig>,
+        /// Queue settings for named subscribers, e.g. `[bus.subscribers."sink.archive"]`.
+        #[serde(default)]
+        pub subscribers: BTreeMap<String, SubscriberConfig>,
     }
 
-    const fn default_trades() -> bool {
-        true
+    /// A logical bus. Each has broadcast buffers of its own, so a burst of
+    /// book updates cannot evict trades that slower subscribers still need.
+    #[derive(
+        Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq, PartialOrd, Ord, Hash,
+    )]
+    #[serde(rename_all = "snake_case")]
+    pub enum BusKind {
+        Trades,
+        Books,
+        /// Tickers and quotes.
+        Tickers,
+        /// Events synthesized by the pipeline, such as the composite feed.
+        Derived,
+        /// Raw venue payloads.
+        Raw,
+        /// Engine events such as adapter status and gap markers. Filtered
+        /// subscribers get them on a priority lane a full queue cannot hold
+        /// back, and sinks only when their route asks for them.
+        Control,
     }
 
-    impl Default for ChannelConfig {
-        fn default() -> Self {
-            Self {
-                trades: default_trades(),
-                ticker: None,
+    impl BusKind {
+        pub const ALL: [BusKind; 6] = [
+            BusKind::Trades,
+            BusKind::Books,
+            BusKind::Tickers,
+            BusKind::Derived,
+            BusKind::Raw,
+            BusKind::Control,
+        ];
+
+        /// The bus `event` travels on.
+        pub fn of(event: &NormalizedEvent) -> Self {
+            match event.venue.as_str() {
+                ENGINE_VENUE => return BusKind::Control,
+                COMPOSITE_VENUE => return BusKind::Derived,
+                _ => {}
+            }
+            match event.kind {
+                EventKind::Trade => BusKind::Trades,
+                EventKind::Book => BusKind::Books,
+                EventKind::Ticker | EventKind::Quote => BusKind::Tickers,
+                EventKind::Raw => BusKind::Raw,
             }
         }
-    }
 
-    impl Default for TickerConfig {
-        fn default() -> Self {
-            Self {
-                enabled: false,
-                mode: None,
+        pub fn as_str(&self) -> &'static str {
+            match self {
+                BusKind::Trades => "trades",
+                BusKind::Books => "books",
+                BusKind::Tickers => "tickers",
+                BusKind::Derived => "derived",
+                BusKind::Raw => "raw",
+                BusKind::Control => "control",
             }
         }
     }
 
-    impl Config {
-        /// Parse configuration from TOML, supporting both the simple `[[venues]]`
-        /// format and the more advanced `[venue.<name>]` style used by
-        /// `config/binance.toml`.
-        pub fn from_str(data: &str) -> Result<Self, toml::de::Error> {
-            // First attempt to deserialize using the simple struct format.
-            if let Ok(cfg) = toml::from_str::<Config>(data) {
-                return Ok(cfg);
-            }
+    /// Overrides of the bus defaults for one logical bus.
+    #[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    pub struct KindBusConfig {
+        #[serde(default)]
+        pub capacity: Option<usize>,
+        #[serde(default)]
+        pub replay_buffer: Option<usize>,
+    }
 
-            // Fallback to parsing `[venue.*]` tables manually.
-            let value: toml::Value = toml::from_str(data)?;
-            let global_discovery: DiscoveryConfig = value
-                .get("discovery")
-                .cloned()
-                .map(|v| v.try_into().unwrap_or_default())
-                .unwrap_or_default();
-            if let Some(table) = value.get("venue").and_then(|v| v.as_table()) {
-                let mut venues = Vec::new();
-                for (name, cfg) in table {
-                    if cfg
-                        .get("enabled")
-                        .and_then(|v| v.as_bool())
-                        .unwrap_or(false)
-                    {
-                        let mut discover = false;
-                        let symbols = match cfg.get("symbols") {
-                            Some(toml::Value::Array(arr)) => arr
-                                .iter()
-                                .filter_map(|v| v.as_str().map(|s| s.to_string()))
-                                .collect(),
-                            Some(toml::Value::String(s)) if s == "ALL" => {
-                                discover = true;
-                                Vec::new()
-                            }
-                            _ => Vec::new(),
-                        };
-                        let ws_base = cfg
-                            .get("ws_base")
-                            .and_then(|v| v.as_str())
-                            .map(|s| s.to_string());
-                        let rest_base = cfg
-                            .get("rest_base")
-                            .and_then(|v| v.as_str())
-                            .map(|s| s.to_string());
-                        let http_timeout_secs = cfg
-                            .get("http_timeout_secs")
-                            .and_then(|v| v.as_integer())
-                            .map(|v| v as u64);
-                        let channels: ChannelConfig = cfg
-                            .get("channels")
-                            .cloned()
-                            .map(|v| v.try_into().unwrap_or_default())
-                            .unwrap_or_default();
-                        let discovery: Option<DiscoveryConfig> = cfg
-                            .get("discovery")
-                            .cloned()
-                            .map(|v| v.try_into().unwrap_or_default())
-                            .or_else(|| {
-                                if discover {
-                                    Some(global_discovery.clone())
-                                } else {
-                                    None
-                                }
-                            });
+    /// Overrides of the bus defaults for one named subscriber.
+    #[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    pub struct SubscriberConfig {
+        #[serde(default)]
+        pub capacity: Option<usize>,
+        #[serde(default)]
+        pub lag_policy: Option<LagPolicy>,
+        /// Write events that do not fit the queue to disk instead of
+        /// dropping them; the lag policy then no longer applies.
+        #[serde(default)]
+        pub spill: Option<BusSpillConfig>,
+    }
 
-                        venues.push(VenueConfig {
-                            name: name.clone(),
-                            symbols,
-                            discover,
-                            ws_base,
-                            rest_base,
-                            http_timeout_secs,
-                            channels,
-                            discovery,
-                        });
-                    }
-                }
-                return Ok(Config { venues });
-            }
+    /// Disk overflow of a named bus subscriber, e.g.
+    /// `[bus.subscribers."sink.archive".spill]`.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    pub struct BusSpillConfig {
+        /// Directory holding the spilled events.
+        pub path: String,
+        /// Discard the oldest spilled events beyond roughly this size.
+        #[serde(default = "default_bus_spill_bytes")]
+        pub max_bytes: u64,
+        /// Discard events spilled longer ago than this when reading them
+        /// back.
+        #[serde(default)]
+        pub max_age_secs: Option<u64>,
+    }
 
-            Ok(Config { venues: Vec::new() })
-        }
+    /// What happens when a bus subscriber falls behind and misses events.
+    #[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Default)]
+    #[serde(rename_all = "snake_case")]
+    pub enum LagPolicy {
+        /// End the subscription.
+        Disconnect,
+        /// Log the loss and carry on with the next event.
+        #[default]
+        Log,
+        /// Hand the subscriber a gap marker event counting the missed events.
+        GapMarker,
     }
-}
 
-pub mod error {
-    use thiserror::Error;
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    pub struct GrpcConfig {
+        #[serde(default = "default_grpc_addr")]
+        pub addr: String,
+        /// Live events buffered per subscriber before it is dropped as lagging.
+        #[serde(default = "default_grpc_buffer")]
+        pub buffer: usize,
+    }
 
-    #[derive(Debug, Error)]
-    pub enum IngestError {
-        #[error("validation failed: {0}")]
-        Validation(String),
-        #[error("io error: {0}")]
-        Io(#[from] std::io::Error),
-        #[error("serde error: {0}")]
-        Serde(#[from] serde_json::Error),
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    pub struct WalConfig {
+        /// Directory holding the segment files.
+        pub path: String,
+        #[serde(default)]
+        pub fsync: FsyncPolicy,
+        /// How often `interval` fsyncs the active segment.
+        #[serde(default = "default_wal_fsync_ms")]
+        pub fsync_interval_ms: u64,
+        /// Start a new segment once the active one reaches this size.
+        #[serde(default = "default_wal_segment_bytes")]
+        pub segment_bytes: u64,
+        /// Keep at most this many segments, deleting the oldest first.
+        #[serde(default)]
+        pub retention_segments: Option<usize>,
     }
-}
 
-/// Canonicalize a symbol to our uppercase format.
-pub fn canonical_symbol(input: &str) -> String {
-    input.to_uppercase()
-}
+    /// When the write-ahead log forces appended records to disk.
+    #[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Default)]
+    #[serde(rename_all = "snake_case")]
+    pub enum FsyncPolicy {
+        /// After every record.
+        Always,
+        /// At most every `fsync_interval_ms`.
+        #[default]
+        Interval,
+        /// Leave flushing to the operating system.
+        Never,
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::{canonical_symbol, config::Config};
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    pub struct FlightConfig {
+        #[serde(default = "default_flight_addr")]
+        pub addr: String,
+        /// Maximum rows per record batch.
+        #[serde(default = "default_flight_batch_rows")]
+        pub batch_rows: usize,
+        /// How long live tailing holds a partial batch before sending it.
+        #[serde(default = "default_flight_live_flush_ms")]
+        pub live_flush_ms: u64,
+    }
 
-    #[test]
-    fn symbol_uppercase() {
-        assert_eq!(canonical_symbol("btcusdt"), "BTCUSDT");
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    pub struct MetricsConfig {
+        /// Busiest venue and symbol pairs with a `symbol_events_total` series
+        /// of their own.
+        #[serde(default = "default_top_symbols")]
+        pub top_symbols: usize,
+        /// Upper bounds of the ingest latency histogram buckets.
+        #[serde(default = "default_latency_buckets_seconds")]
+        pub latency_buckets_seconds: Vec<f64>,
+        /// Push metrics to an OpenTelemetry collector; disabled when absent.
+        #[serde(default)]
+        pub otlp: Option<OtlpConfig>,
+        /// Push metrics to a Prometheus Pushgateway; disabled when absent.
+        #[serde(default)]
+        pub pushgateway: Option<PushgatewayConfig>,
     }
 
-    #[test]
-    fn parse_simple_config() {
-        let data = "[[venues]]\nname=\"binance\"\nsymbols=[\"BTCUSDT\"]\n";
-        let cfg = Config::from_str(data).unwrap();
-        assert_eq!(cfg.venues.len(), 1);
-        assert_eq!(cfg.venues[0].name, "binance");
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    pub struct OtlpConfig {
+        /// OTLP/gRPC endpoint of the collector.
+        #[serde(default = "default_otlp_endpoint")]
+        pub endpoint: String,
+        #[serde(default = "default_otlp_interval_secs")]
+        pub interval_secs: u64,
+        /// `service.name` of the exported resource.
+        #[serde(default = "default_otlp_service_name")]
+        pub service_name: String,
+        /// gRPC metadata sent with every export, e.g. an API key.
+        #[serde(default)]
+        pub headers: BTreeMap<String, String>,
     }
 
-    #[test]
-    fn parse_binance_style_config() {
-        let data = r#"
-[venue.binance_spot]
-enabled = true
-ws_base = "wss://example"
-rest_base = "https://rest.example"
-symbols = ["BTCUSDT", "ETHUSDT"]
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    pub struct PushgatewayConfig {
+        /// Base URL of the Pushgateway, e.g. `http://pushgateway:9091`.
+        pub url: String,
+        #[serde(default = "default_pushgateway_job")]
+        pub job: String,
+        /// `instance` grouping label, telling apart engines pushing as one
+        /// job.
+        #[serde(default)]
+        pub instance: Option<String>,
+        /// Further grouping labels.
+        #[serde(default)]
+        pub labels: BTreeMap<String, String>,
+        #[serde(default = "default_pushgateway_interval_secs")]
+        pub interval_secs: u64,
+    }
 
-[venue.binance_spot.channels]
-trades = true
+    impl PushgatewayConfig {
+        /// The grouping labels, `job` first, in the order of the push URL.
+        pub fn grouping(&self) -> Vec<(&str, &str)> {
+            let mut grouping = vec![("job", self.job.as_str())];
+            grouping.extend(
+                self.instance
+                    .as_deref()
+                    .map(|instance| ("instance", instance)),
+            );
+            grouping.extend(
+                self.labels
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), value.as_str())),
+            );
+            grouping
+        }
+    }
 
-[venue.binance_usdm]
-enabled = false
-"#;
-        let cfg = Config::from_str(data).unwrap();
-        assert_eq!(cfg.venues.len(), 1);
-        assert_eq!(cfg.venues[0].name, "binance_spot");
-        assert_eq!(cfg.venues[0].ws_base.as_deref(), Some("wss://example"));
-        assert!(cfg.venues[0].channels.trades);
-        assert!(!cfg.venues[0].discover);
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    pub struct LogConfig {
+        #[serde(default)]
+        pub format: LogFormat,
+        /// Level of every module without one of its own.
+        #[serde(default = "default_log_level")]
+        pub level: String,
+        /// Levels by module path, e.g. `"sinks::kafka" = "debug"`. The most
+        /// specific path applies.
+        #[serde(default)]
+        pub modules: BTreeMap<String, String>,
+        /// Logs to a file instead of stderr; stderr when absent.
+        #[serde(default)]
+        pub file: Option<LogFileConfig>,
     }
 
-    #[test]
-    fn parse_all_symbols_as_discovery() {
+    /// A log file, rotated by size, by day or both. A rotated file is
+    /// renamed to `<path>.<UTC time>` and the oldest beyond `retain` are
+    /// removed.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    pub struct LogFileConfig {
+        pub path: String,
+        /// Rotate before a line would take the file past this size.
+        #[serde(default)]
+        pub max_bytes: Option<u64>,
+        /// Rotate on the first line of each UTC day.
+        #[serde(default)]
+        pub daily: bool,
+        /// Rotated files kept.
+        #[serde(default = "default_log_retain")]
+        pub retain: usize,
+    }
+
+    /// How the engine stops on `SIGTERM`, Ctrl-C or `POST /admin/shutdown`.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    pub struct ShutdownConfig {
+        /// Seconds the pipeline and the sinks get to drain. Sinks still
+        /// delivering then keep what they hold in their spill log for the
+        /// next run; the engine exits with an error if any events are lost.
+        #[serde(default = "default_shutdown_timeout_secs")]
+        pub timeout_secs: u64,
+    }
+
+    /// When an adapter counts as stuck, e.g. on a socket that went dead
+    /// without closing, and how often it is restarted while it stays so.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    pub struct WatchdogConfig {
+        /// Seconds without an event from a venue after which its adapter
+        /// is restarted.
+        #[serde(default = "default_watchdog_stale_secs")]
+        pub stale_secs: u64,
+        /// Extra wait before the second restart in a row, doubling with
+        /// each one after up to `max_backoff_ms`.
+        #[serde(default = "default_watchdog_initial_ms")]
+        pub initial_backoff_ms: u64,
+        #[serde(default = "default_watchdog_max_ms")]
+        pub max_backoff_ms: u64,
+    }
+
+    /// How adapters that fail or stop are restarted. Restarts back off
+    /// exponentially, and after `max_failures` failures within
+    /// `window_secs` the venue's circuit opens: its adapter is left stopped
+    /// for `open_secs`, then tried once more, and the circuit opens again
+    /// if that attempt fails within `window_secs` too.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    pub struct SupervisorConfig {
+        /// Wait before the first restart, doubling with each failure in
+        /// the window up to `max_backoff_ms`.
+        #[serde(default = "default_supervisor_initial_ms")]
+        pub initial_backoff_ms: u64,
+        #[serde(default = "default_supervisor_max_ms")]
+        pub max_backoff_ms: u64,
+        #[serde(default = "default_supervisor_max_failures")]
+        pub max_failures: u32,
+        /// Seconds a failure counts towards opening the circuit. An adapter
+        /// that runs this long before failing starts over with no failures.
+        #[serde(default = "default_supervisor_window_secs")]
+        pub window_secs: u64,
+        #[serde(default = "default_supervisor_open_secs")]
+        pub open_secs: u64,
+    }
+
+    /// Sizes the Tokio runtime to the host: the defaults suit neither a
+    /// one-core VM nor a capture host with dozens of cores.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    pub struct RuntimeConfig {
+        /// Threads running tasks, one per core when absent.
+        #[serde(default)]
+        pub worker_threads: Option<usize>,
+        /// Most threads for blocking work such as file IO, started as
+        /// needed.
+        #[serde(default = "default_runtime_max_blocking_threads")]
+        pub max_blocking_threads: usize,
+        /// Tasks a worker polls between checks for IO and timer events.
+        /// Lower favours latency, higher throughput.
+        #[serde(default = "default_runtime_event_interval")]
+        pub event_interval: u32,
+    }
+
+    /// A budget for the memory of events waiting in the bus, pipeline and
+    /// sink queues, estimated from their depths and the average size of
+    /// recent events. Over budget, events of the first bus in `shed` are
+    /// dropped before they reach the bus, and those of one more bus for
+    /// every further tenth of the budget. Engine events are never shed.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    pub struct MemoryConfig {
+        pub budget_bytes: u64,
+        /// Logical buses in the order they are shed, lowest priority first.
+        #[serde(default = "default_memory_shed")]
+        pub shed: Vec<BusKind>,
+    }
+
+    /// Instances sharing the venues through leases in Consul: each venue is
+    /// ingested by the instance whose session holds the key
+    /// `<prefix>/<venue>`. Sessions expire `ttl_secs` after their instance
+    /// stops renewing them, and the venues they held go to another
+    /// instance.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    pub struct ClusterConfig {
+        /// Name of this instance, stored in the keys it holds.
+        pub node: String,
+        /// Base URL of the Consul agent.
+        #[serde(default = "default_cluster_consul")]
+        pub consul: String,
+        #[serde(default = "default_cluster_prefix")]
+        pub prefix: String,
+        /// Consul ACL token.
+        #[serde(default)]
+        pub token: Option<String>,
+        /// Session TTL; Consul accepts 10 to 86400 seconds.
+        #[serde(default = "default_cluster_ttl_secs")]
+        pub ttl_secs: u64,
+    }
+
+    /// One instance of an active/standby pair. It polls the
+    /// `/healthz/active` endpoint of the other instance every
+    /// `heartbeat_ms`. The standby promotes itself once `missed` polls in a
+    /// row go unanswered after one was answered, continuing the sequence
+    /// numbers from the last one the active instance reported; the
+    /// `primary` takes over at once from a standby it finds standing by.
+    /// Each promotion takes an epoch above the last one seen, and an active
+    /// instance steps down when it finds the other one promoted over it. A
+    /// warm standby runs its adapters all along and publishes, on
+    /// promotion, the events it received since the last answered poll; a
+    /// dormant one starts them on its first promotion.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    pub struct StandbyConfig {
+        /// Base URL of the other instance's ops server: the active one's, or
+        /// on the primary its standby's.
+        #[serde(alias = "peer")]
+        pub active: String,
+        /// Bearer token for the other instance's health routes.
+        #[serde(default)]
+        pub token: Option<String>,
+        #[serde(default = "default_standby_heartbeat_ms")]
+        pub heartbeat_ms: u64,
+        #[serde(default = "default_standby_missed")]
+        pub missed: u32,
+        #[serde(default = "default_standby_warm")]
+        pub warm: bool,
+        /// This is the instance meant to be active. It takes over from a
+        /// standby that is not active, or after `missed` unanswered polls,
+        /// and steps down for a standby promoted at the same epoch.
+        #[serde(default)]
+        pub primary: bool,
+    }
+
+    /// Worker processes running the venue adapters, one per venue or per
+    /// group, so a crashing parser takes down only its own worker. Each
+    /// worker sends its events to the engine over a Unix socket in
+    /// `socket_dir`, and is restarted like an adapter when it exits.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    pub struct WorkersConfig {
+        pub socket_dir: String,
+        /// Venues sharing a worker, by worker name, e.g.
+        /// `binance = ["binance_spot", "binance_futures"]`. Every other
+        /// venue gets a worker named after it.
+        #[serde(default)]
+        pub groups: BTreeMap<String, Vec<String>>,
+    }
+
+    impl WorkersConfig {
+        /// The worker that runs `venue`.
+        pub fn worker_of<'a>(&'a self, venue: &'a str) -> &'a str {
+            self.groups
+                .iter()
+                .find(|(_, venues)| venues.iter().any(|name| name == venue))
+                .map_or(venue, |(worker, _)| worker.as_str())
+        }
+    }
+
+    /// When alerts fire and where they are posted. Each rule is off unless
+    /// set. An alert is posted once when it fires and once when it
+    /// resolves, and does not fire again within `cooldown_secs` of its last
+    /// notification.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    pub struct AlertsConfig {
+        /// Seconds between checks of the rules.
+        #[serde(default = "default_alerts_interval_secs")]
+        pub interval_secs: u64,
+        #[serde(default = "default_alerts_cooldown_secs")]
+        pub cooldown_secs: u64,
+        /// Fire when a venue's adapter has been disconnected this many
+        /// seconds.
+        #[serde(default)]
+        pub adapter_down_secs: Option<u64>,
+        /// Fire when a bus subscriber misses events in a burst.
+        #[serde(default)]
+        pub gap_burst: Option<GapBurstConfig>,
+        /// Fire when this many events wait in a sink's queue.
+        #[serde(default)]
+        pub sink_backlog: Option<i64>,
+        pub webhooks: Vec<WebhookConfig>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    pub struct GapBurstConfig {
+        /// Events missed, by falling behind or on a full queue, within
+        /// `window_secs`.
+        pub missed: u64,
+        #[serde(default = "default_gap_burst_window_secs")]
+        pub window_secs: u64,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    pub struct WebhookConfig {
+        pub url: String,
+        #[serde(default)]
+        pub format: WebhookFormat,
+        /// Integration key of the PagerDuty service, for the `pagerduty`
+        /// format.
+        #[serde(default)]
+        pub routing_key: Option<String>,
+    }
+
+    /// The body posted to a webhook.
+    #[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Default)]
+    #[serde(rename_all = "snake_case")]
+    pub enum WebhookFormat {
+        /// The alert as a JSON object.
+        #[default]
+        Json,
+        /// A Slack incoming-webhook message.
+        Slack,
+        /// A PagerDuty Events API v2 event, resolved with the alert.
+        Pagerduty,
+    }
+
+    impl LogConfig {
+        /// The filter directives, such as `info,sinks::kafka=debug`.
+        pub fn directives(&self) -> String {
+            let mut directives = vec![self.level.clone()];
+            directives.extend(
+                self.modules
+                    .iter()
+                    .map(|(module, level)| format!("{}={}", module, level)),
+            );
+            directives.join(",")
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Default)]
+    #[serde(rename_all = "snake_case")]
+    pub enum LogFormat {
+        /// One JSON object per line.
+        #[default]
+        Json,
+        /// Human-readable lines, for running locally.
+        Text,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Default)]
+    pub struct OpsConfig {
+        #[serde(default)]
+        pub auth: AuthConfig,
+        /// Serve HTTPS instead of plain HTTP; disabled when absent.
+        #[serde(default)]
+        pub tls: Option<TlsConfig>,
+        #[serde(default)]
+        pub metrics: OpsMetricsConfig,
+        #[serde(default)]
+        pub streams: StreamLimitsConfig,
+        #[serde(default)]
+        pub sse: SseConfig,
+        /// Let browser pages on other origins call the ops server; disabled
+        /// when absent.
+        #[serde(default)]
+        pub cors: Option<CorsConfig>,
+        /// Serve `POST /publish`, which injects events into the pipeline as
+        /// if a venue had sent them. Off by default.
+        #[serde(default)]
+        pub publish: bool,
+    }
+
+    /// The server-sent events of `GET /events`.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    pub struct SseConfig {
+        /// Seconds between heartbeat comments on a stream, which keep
+        /// proxies from timing it out and find clients that went away.
+        #[serde(default = "default_sse_keepalive_secs")]
+        pub keepalive_secs: u64,
+    }
+
+    /// Which cross-origin browser requests the ops server allows.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    pub struct CorsConfig {
+        /// Origins such as `https://dash.example.com`, or `*` for any.
+        pub allowed_origins: Vec<String>,
+        /// Request headers pages may send, or `*` for any.
+        #[serde(default = "default_cors_allowed_headers")]
+        pub allowed_headers: Vec<String>,
+        /// Let pages send cookies and `Authorization` and read the
+        /// response. Needs explicit origins and headers, not `*`.
+        #[serde(default)]
+        pub allow_credentials: bool,
+        /// Seconds browsers may cache a preflight answer.
+        #[serde(default = "default_cors_max_age_secs")]
+        pub max_age_secs: u64,
+    }
+
+    /// Limits on the streaming endpoints, `GET /events` and `GET /ws`, with
+    /// clients told apart by IP address. Each is off when absent.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    pub struct StreamLimitsConfig {
+        /// Open streams across every client; more are refused with 503.
+        #[serde(default)]
+        pub max_connections: Option<usize>,
+        /// Open streams per client; more are refused with 429.
+        #[serde(default)]
+        pub max_client_connections: Option<usize>,
+        /// Streams a client may open per second on average; faster ones
+        /// are refused with 429.
+        #[serde(default)]
+        pub client_connect_rate: Option<f64>,
+        /// Streams a client may open at once before its rate applies.
+        #[serde(default = "default_client_connect_burst")]
+        pub client_connect_burst: u32,
+    }
+
+    /// How the ops server's metrics are named and bucketed.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Default)]
+    #[serde(deny_unknown_fields)]
+    pub struct OpsMetricsConfig {
+        /// Namespace prepended to every metric name, as `<prefix>_<name>`.
+        #[serde(default)]
+        pub prefix: Option<String>,
+        /// Labels added to every series, e.g. `region` and `instance`.
+        #[serde(default)]
+        pub labels: BTreeMap<String, String>,
+        /// Bucket upper bounds by histogram name, overriding its defaults.
+        #[serde(default)]
+        pub buckets: BTreeMap<String, Vec<f64>>,
+    }
+
+    impl OpsMetricsConfig {
+        /// The buckets of the histogram `name`, or `default` without
+        /// buckets of its own.
+        pub fn buckets_or(&self, name: &str, default: &[f64]) -> Vec<f64> {
+            self.buckets
+                .get(name)
+                .map_or_else(|| default.to_vec(), Clone::clone)
+        }
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    pub struct TlsConfig {
+        /// PEM certificate chain, leaf first.
+        pub cert_path: String,
+        /// PEM private key, PKCS#8, PKCS#1 or SEC1.
+        pub key_path: String,
+        /// PEM CA certificates client certificates must chain to. When set,
+        /// every client needs a certificate.
+        #[serde(default)]
+        pub client_ca_path: Option<String>,
+    }
+
+    /// Who may call the ops server's routes.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Default)]
+    #[serde(deny_unknown_fields)]
+    pub struct AuthConfig {
+        /// Bearer tokens accepted by protected routes. Without any, every
+        /// route is open and the control and admin routes are not served.
+        #[serde(default)]
+        pub tokens: Vec<String>,
+        /// Policies overriding [`RouteGroup::default_policy`].
+        #[serde(default)]
+        pub routes: BTreeMap<RouteGroup, AuthPolicy>,
+    }
+
+    impl AuthConfig {
+        pub fn policy(&self, group: RouteGroup) -> AuthPolicy {
+            if self.tokens.is_empty() {
+                return AuthPolicy::Open;
+            }
+            self.routes
+                .get(&group)
+                .copied()
+                .unwrap_or_else(|| group.default_policy())
+        }
+    }
+
+    /// Ops server routes sharing an auth policy.
+    #[derive(
+        Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq, PartialOrd, Ord, Hash,
+    )]
+    #[serde(rename_all = "snake_case")]
+    pub enum RouteGroup {
+        /// `/health`, `/ready` and the `/healthz/*` probes.
+        Health,
+        /// `/metrics`.
+        Metrics,
+        /// Event and bus data: `/events`, `/ws`, `/snapshot` and
+        /// `/bus/state`.
+        Data,
+        /// `/control/*`, `/replay` and `/publish`, which are never open.
+        Control,
+        /// `/admin/*`, which is never open.
+        Admin,
+    }
+
+    impl RouteGroup {
+        /// Probes and scrapes are open; the rest need a token.
+        pub const fn default_policy(self) -> AuthPolicy {
+            match self {
+                RouteGroup::Health | RouteGroup::Metrics => AuthPolicy::Open,
+                _ => AuthPolicy::Token,
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+    #[serde(rename_all = "snake_case")]
+    pub enum AuthPolicy {
+        Open,
+        /// Requests need `Authorization: Bearer <token>` with one of the
+        /// configured tokens.
+        Token,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    pub struct WsConfig {
+        /// Frames queued per client before it counts as slow.
+        #[serde(default = "default_ws_send_buffer")]
+        pub send_buffer: usize,
+        #[serde(default)]
+        pub slow_client: SlowClientPolicy,
+    }
+
+    /// What happens to a WebSocket client whose send buffer is full.
+    #[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Default)]
+    #[serde(rename_all = "snake_case")]
+    pub enum SlowClientPolicy {
+        /// Close the connection.
+        #[default]
+        Disconnect,
+        /// Keep the connection and skip events until the buffer drains.
+        DropEvents,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    pub struct SinkConfig {
+        /// Capacity of the queue between the bus and the sink.
+        #[serde(default = "default_sink_queue_capacity")]
+        pub queue_capacity: usize,
+        /// Which events the sink receives; everything when absent.
+        #[serde(default)]
+        pub route: SinkRoute,
+        /// At-least-once delivery; events the sink rejects are dropped when
+        /// absent.
+        #[serde(default)]
+        pub delivery: Option<DeliveryConfig>,
+        #[serde(flatten)]
+        pub kind: SinkKind,
+    }
+
+    /// Retry policy for a sink, e.g. `[sinks.trades.delivery]`. Events are
+    /// held until a flush of the sink confirms them and retried with
+    /// exponential backoff.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    pub struct DeliveryConfig {
+        /// Events held in memory awaiting delivery.
+        #[serde(default = "default_retry_buffer")]
+        pub retry_buffer: usize,
+        /// Attempts per event before it goes to the dead-letter log.
+        #[serde(default = "default_delivery_attempts")]
+        pub max_attempts: u32,
+        #[serde(default = "default_retry_initial_ms")]
+        pub initial_backoff_ms: u64,
+        #[serde(default = "default_retry_max_ms")]
+        pub max_backoff_ms: u64,
+        /// Longest time events the sink accepted wait for a flush to
+        /// confirm them. Until then they are held, and sent again if the
+        /// flush fails.
+        #[serde(default = "default_ack_interval_ms")]
+        pub ack_interval_ms: u64,
+        /// Write-ahead log taking the overflow once the retry buffer is full.
+        /// Without it the sink stops reading and its queue fills up instead.
+        #[serde(default)]
+        pub spill: Option<WalConfig>,
+        /// Write-ahead log receiving events that failed `max_attempts`
+        /// times. Without it they are dropped.
+        #[serde(default)]
+        pub dead_letter: Option<WalConfig>,
+    }
+
+    /// Routing rule for a sink, e.g. `[sinks.trades.route]`. Empty lists
+    /// match everything; symbols may be globs such as `BTC*`. The bus
+    /// applies it as a subscription filter.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Default)]
+    #[serde(deny_unknown_fields)]
+    pub struct SinkRoute {
+        #[serde(default)]
+        pub venues: Vec<String>,
+        #[serde(default)]
+        pub symbols: Vec<String>,
+        #[serde(default)]
+        pub kinds: Vec<EventKind>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    #[serde(tag = "type", rename_all = "snake_case")]
+    pub enum SinkKind {
+        Kafka(KafkaSinkConfig),
+        Parquet(ParquetSinkConfig),
+        Postgres(PostgresSinkConfig),
+        Influx(InfluxSinkConfig),
+        Archive(ArchiveSinkConfig),
+        Pubsub(PubSubSinkConfig),
+        Kinesis(KinesisSinkConfig),
+        EventHubs(EventHubsSinkConfig),
+        Duckdb(DuckDbSinkConfig),
+        UnixSocket(UnixSocketSinkConfig),
+        Shm(ShmSinkConfig),
+    }
+
+    /// Wire encoding of events written by sinks.
+    #[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Default)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Encoding {
+        #[default]
+        Json,
+        Avro,
+        Proto,
+    }
+
+    /// Payload compression applied by sinks.
+    #[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Default)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Compression {
+        #[default]
+        None,
+        Gzip,
+        Zstd,
+        Lz4,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    pub struct KafkaSinkConfig {
+        pub brokers: String,
+        /// Topic template; `{venue}`, `{kind}` and `{symbol}` are substituted.
+        #[serde(default = "default_kafka_topic")]
+        pub topic: String,
+        #[serde(default)]
+        pub encoding: Encoding,
+        /// Maximum messages buffered inside the producer.
+        #[serde(default = "default_kafka_buffer")]
+        pub max_buffered_messages: usize,
+        /// Producer compression, set as `compression.type`.
+        #[serde(default)]
+        pub compression: Compression,
+        /// Messages per produce batch (`batch.num.messages`); librdkafka's
+        /// default when absent.
+        #[serde(default)]
+        pub batch_size: Option<usize>,
+        /// How long the producer waits to fill a batch (`linger.ms`).
+        #[serde(default)]
+        pub flush_interval_ms: Option<u64>,
+        /// Extra librdkafka properties passed through verbatim.
+        #[serde(default)]
+        pub properties: BTreeMap<String, String>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    pub struct ParquetSinkConfig {
+        /// Root directory; files land under `date=/venue=/kind=` partitions.
+        pub path: String,
+        /// Rows buffered before a row group is written.
+        #[serde(default = "default_parquet_batch")]
+        pub batch_size: usize,
+        /// Roll to a new file once the current one reaches this size.
+        #[serde(default = "default_parquet_max_bytes")]
+        pub max_file_bytes: u64,
+        /// Roll to a new file once the current one is this old.
+        #[serde(default = "default_parquet_max_age")]
+        pub max_file_age_secs: u64,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    pub struct PostgresSinkConfig {
+        /// Connection string, e.g. `postgres://ingest@localhost/marketdata`.
+        pub url: String,
+        #[serde(default = "default_postgres_pool")]
+        pub pool_size: usize,
+        /// Rows buffered per table before they are copied in.
+        #[serde(default = "default_postgres_batch")]
+        pub batch_size: usize,
+        /// Copy out a partial batch once it has waited this long.
+        #[serde(default = "default_postgres_flush_ms")]
+        pub flush_interval_ms: u64,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    pub struct DuckDbSinkConfig {
+        /// Database file, created on first use.
+        pub path: String,
+        /// Rows buffered per table before they are appended.
+        #[serde(default = "default_duckdb_batch")]
+        pub batch_size: usize,
+        /// Append a partial batch once it has waited this long.
+        #[serde(default = "default_duckdb_flush_ms")]
+        pub flush_interval_ms: u64,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    pub struct UnixSocketSinkConfig {
+        /// Socket path, or a pipe name such as `\\.\pipe\ingest` on Windows.
+        pub path: String,
+        #[serde(default)]
+        pub encoding: Encoding,
+        /// Frames buffered per client before it is disconnected as too slow.
+        #[serde(default = "default_socket_client_buffer")]
+        pub client_buffer: usize,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    pub struct ShmSinkConfig {
+        /// Ring file, usually under `/dev/shm`; recreated on start.
+        pub path: String,
+        #[serde(default)]
+        pub encoding: Encoding,
+        /// Size of the ring's data region. Readers further behind than this
+        /// are lapped and skip ahead.
+        #[serde(default = "default_shm_capacity")]
+        pub capacity_bytes: u64,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    pub struct InfluxSinkConfig {
+        /// Base URL of the InfluxDB 2.x server, e.g. `http://localhost:8086`.
+        pub url: String,
+        pub org: String,
+        pub bucket: String,
+        pub token: String,
+        /// Points buffered before a write request is sent.
+        #[serde(default = "default_influx_batch")]
+        pub batch_size: usize,
+        /// Send a partial batch once it has waited this long.
+        #[serde(default = "default_influx_flush_ms")]
+        pub flush_interval_ms: u64,
+        /// Request body compression; InfluxDB accepts `gzip` only.
+        #[serde(default)]
+        pub compression: Compression,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    pub struct PubSubSinkConfig {
+        pub project: String,
+        pub topic: String,
+        #[serde(default)]
+        pub encoding: Encoding,
+        /// Ordering key template, substituted like a Kafka topic. Empty
+        /// publishes without ordering.
+        #[serde(default = "default_pubsub_ordering_key")]
+        pub ordering_key: String,
+        /// Service account key file; without one, tokens come from the GCE
+        /// metadata server.
+        #[serde(default)]
+        pub credentials_file: Option<String>,
+        /// API endpoint. Ordered delivery needs a regional endpoint such as
+        /// `https://europe-west1-pubsub.googleapis.com`.
+        #[serde(default = "default_pubsub_endpoint")]
+        pub endpoint: String,
+        /// Messages per publish request, at most 1000.
+        #[serde(default = "default_pubsub_batch")]
+        pub batch_size: usize,
+        /// Publish a partial batch once it has waited this long.
+        #[serde(default = "default_pubsub_flush_ms")]
+        pub flush_interval_ms: u64,
+        /// Compression of message data, named in a `content_encoding`
+        /// attribute.
+        #[serde(default)]
+        pub compression: Compression,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    pub struct KinesisSinkConfig {
+        pub stream: String,
+        pub region: String,
+        /// Override the regional endpoint, e.g. for LocalStack.
+        #[serde(default)]
+        pub endpoint: Option<String>,
+        #[serde(default)]
+        pub encoding: Encoding,
+        /// Partition key template, substituted like a Kafka topic.
+        #[serde(default = "default_kinesis_partition_key")]
+        pub partition_key: String,
+        /// Static credentials; the `AWS_ACCESS_KEY_ID`,
+        /// `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN` environment
+        /// variables are used when these are absent.
+        #[serde(default)]
+        pub access_key_id: Option<String>,
+        #[serde(default)]
+        pub secret_access_key: Option<String>,
+        #[serde(default)]
+        pub session_token: Option<String>,
+        /// Records per `PutRecords` request, at most 500.
+        #[serde(default = "default_kinesis_batch")]
+        pub batch_size: usize,
+        /// Send a partial batch once it has waited this long.
+        #[serde(default = "default_kinesis_flush_ms")]
+        pub flush_interval_ms: u64,
+        /// Attempts per record before it is dropped.
+        #[serde(default = "default_kinesis_attempts")]
+        pub max_attempts: u32,
+        /// Compression of each record's data.
+        #[serde(default)]
+        pub compression: Compression,
+    }
+
+    /// Authenticates with `connection_string` (SAS key or signature), with
+    /// `namespace` plus `sas_token`, or with `namespace` alone through Azure
+    /// AD (environment, managed identity or Azure CLI credentials).
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    pub struct EventHubsSinkConfig {
+        #[serde(default)]
+        pub connection_string: Option<String>,
+        /// Fully qualified namespace, e.g. `marketdata.servicebus.windows.net`.
+        #[serde(default)]
+        pub namespace: Option<String>,
+        #[serde(default)]
+        pub sas_token: Option<String>,
+        pub event_hub: String,
+        #[serde(default)]
+        pub encoding: Encoding,
+        /// Partition key template, substituted like a Kafka topic.
+        #[serde(default = "default_eventhubs_partition_key")]
+        pub partition_key: String,
+        /// Events buffered before a send.
+        #[serde(default = "default_eventhubs_batch")]
+        pub batch_size: usize,
+        /// Send partial batches once they have waited this long.
+        #[serde(default = "default_eventhubs_flush_ms")]
+        pub flush_interval_ms: u64,
+        /// Compression of each event body.
+        #[serde(default)]
+        pub compression: Compression,
+    }
+
+    /// Segment format of the archival sink.
+    #[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Default)]
+    #[serde(rename_all = "snake_case")]
+    pub enum ArchiveFormat {
+        /// JSON lines, compressed per `compression`.
+        #[default]
+        Jsonl,
+        Parquet,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    pub struct ArchiveSinkConfig {
+        /// Destination such as `s3://bucket/prefix`, `gs://bucket`,
+        /// `az://container/prefix` or `file:///srv/archive`.
+        pub url: String,
+        #[serde(default)]
+        pub format: ArchiveFormat,
+        /// Compression of JSON lines segments; Parquet files use Snappy.
+        #[serde(default = "default_archive_compression")]
+        pub compression: Compression,
+        /// Local directory where segments are staged until uploaded.
+        pub spool_dir: String,
+        /// Close a segment once it holds this many bytes.
+        #[serde(default = "default_archive_segment_bytes")]
+        pub segment_bytes: u64,
+        /// Close a segment once it is this old.
+        #[serde(default = "default_archive_segment_age")]
+        pub segment_age_secs: u64,
+        /// Segments larger than this are uploaded in parts of this size.
+        #[serde(default = "default_archive_part_size")]
+        pub part_size: usize,
+        /// Store options passed to `object_store`, e.g. `aws_region`.
+        #[serde(default)]
+        pub options: BTreeMap<String, String>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    pub struct PipelineConfig {
+        #[serde(default = "default_queue_capacity")]
+        pub queue_capacity: usize,
+        #[serde(default)]
+        pub transforms: Vec<TransformConfig>,
+        #[serde(default)]
+        pub clock_skew: ClockSkewConfig,
+        #[serde(default)]
+        pub composite: CompositeConfig,
+    }
+
+    /// Consolidated cross-venue feeds published under the `COMPOSITE` venue.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    pub struct CompositeConfig {
+        #[serde(default)]
+        pub enabled: bool,
+        /// Canonical symbols to consolidate; empty means all.
+        #[serde(default)]
+        pub symbols: Vec<String>,
+        /// Venue quotes older than this are left out of the consolidated BBO.
+        #[serde(default = "default_quote_ttl_ms")]
+        pub quote_ttl_ms: u64,
+    }
+
+    /// How the clock-skew stage treats venues whose clocks drift.
+    #[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Default)]
+    #[serde(rename_all = "snake_case")]
+    pub enum SkewMode {
+        /// Only export skew gauges.
+        #[default]
+        Observe,
+        /// Add a `clock_skew_ms` field to payloads of drifting venues.
+        Annotate,
+        /// Shift event timestamps of drifting venues onto the local clock.
+        Correct,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    pub struct ClockSkewConfig {
+        #[serde(default)]
+        pub mode: SkewMode,
+        /// Skew beyond which a venue is considered drifting.
+        #[serde(default = "default_skew_threshold_ms")]
+        pub threshold_ms: i64,
+        /// Window over which the minimum receive offset is tracked.
+        #[serde(default = "default_skew_window_secs")]
+        pub window_secs: u64,
+    }
+
+    /// A user supplied WASM transform run as a pipeline stage.
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    pub struct TransformConfig {
+        pub name: String,
+        pub module: String,
+        /// Instruction budget per event; 10 million when unset.
+        #[serde(default)]
+        pub fuel: Option<u64>,
+        #[serde(default)]
+        pub max_memory_bytes: Option<usize>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    pub struct VenueConfig {
+        pub name: String,
+        /// Which adapter serves the venue, e.g. `binance`.
+        #[serde(rename = "type", default = "default_venue_type")]
+        pub venue_type: String,
+        pub symbols: Vec<String>,
+        #[serde(default)]
+        pub discover: bool,
+        #[serde(default)]
+        pub ws_base: Option<String>,
+        #[serde(default)]
+        pub rest_base: Option<String>,
+        #[serde(default)]
+        pub http_timeout_secs: Option<u64>,
+        #[serde(default)]
+        pub channels: ChannelConfig,
+        #[serde(default)]
+        pub discovery: Option<DiscoveryConfig>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Default)]
+    pub struct DiscoveryConfig {
+        #[serde(default)]
+        pub enabled: bool,
+        #[serde(default)]
+        pub quote_whitelist: Vec<String>,
+        #[serde(default)]
+        pub symbol_blacklist: Vec<String>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    pub struct ChannelConfig {
+        #[serde(default = "default_trades")]
+        pub trades: bool,
+        #[serde(default)]
+        pub ticker: Option<TickerConfig>,
+        /// Best bid/offer updates.
+        #[serde(default)]
+        pub book_ticker: bool,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+    pub struct TickerConfig {
+        pub enabled: bool,
+        #[serde(default)]
+        pub mode: Option<String>,
+    }
+
+    fn default_venue_type() -> String {
+        "binance".into()
+    }
+
+    const fn default_trades() -> bool {
+        true
+    }
+
+    const fn default_bus_capacity() -> usize {
+        1024
+    }
+
+    const fn default_bus_shards() -> usize {
+        1
+    }
+
+    const fn default_bus_replay_buffer() -> usize {
+        1024
+    }
+
+    const fn default_bus_spill_bytes() -> u64 {
+        1024 * 1024 * 1024
+    }
+
+    const fn default_top_symbols() -> usize {
+        20
+    }
+
+    fn increasing(buckets: &[f64]) -> bool {
+        buckets.windows(2).all(|w| w[0] < w[1])
+    }
+
+    /// Whether `name` matches `[a-zA-Z_:][a-zA-Z0-9_:]*`.
+    fn metric_name(name: &str) -> bool {
+        let mut chars = name.chars();
+        chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == ':')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+    }
+
+    fn default_latency_buckets_seconds() -> Vec<f64> {
+        vec![
+            0.001, 0.002, 0.005, 0.01, 0.02, 0.05, 0.1, 0.2, 0.5, 1.0, 2.0, 5.0,
+        ]
+    }
+
+    fn default_log_level() -> String {
+        "info".into()
+    }
+
+    const fn default_log_retain() -> usize {
+        7
+    }
+
+    const fn default_shutdown_timeout_secs() -> u64 {
+        30
+    }
+
+    const fn default_alerts_interval_secs() -> u64 {
+        10
+    }
+
+    const fn default_alerts_cooldown_secs() -> u64 {
+        300
+    }
+
+    const fn default_gap_burst_window_secs() -> u64 {
+        60
+    }
+
+    const fn default_watchdog_stale_secs() -> u64 {
+        60
+    }
+
+    const fn default_watchdog_initial_ms() -> u64 {
+        1_000
+    }
+
+    const fn default_watchdog_max_ms() -> u64 {
+        300_000
+    }
+
+    fn default_cluster_consul() -> String {
+        "http://127.0.0.1:8500".into()
+    }
+
+    fn default_cluster_prefix() -> String {
+        "ingest/venues".into()
+    }
+
+    const fn default_cluster_ttl_secs() -> u64 {
+        15
+    }
+
+    const fn default_standby_heartbeat_ms() -> u64 {
+        500
+    }
+
+    const fn default_standby_missed() -> u32 {
+        6
+    }
+
+    const fn default_standby_warm() -> bool {
+        true
+    }
+
+    fn default_memory_shed() -> Vec<BusKind> {
+        vec![BusKind::Raw, BusKind::Books, BusKind::Tickers]
+    }
+
+    const fn default_supervisor_initial_ms() -> u64 {
+        1_000
+    }
+
+    const fn default_supervisor_max_ms() -> u64 {
+        60_000
+    }
+
+    const fn default_supervisor_max_failures() -> u32 {
+        5
+    }
+
+    const fn default_supervisor_window_secs() -> u64 {
+        300
+    }
+
+    const fn default_supervisor_open_secs() -> u64 {
+        600
+    }
+
+    const fn default_runtime_max_blocking_threads() -> usize {
+        512
+    }
+
+    const fn default_runtime_event_interval() -> u32 {
+        61
+    }
+
+    fn default_otlp_endpoint() -> String {
+        "http://127.0.0.1:4317".into()
+    }
+
+    const fn default_otlp_interval_secs() -> u64 {
+        10
+    }
+
+    fn default_pushgateway_job() -> String {
+        "ingestd".into()
+    }
+
+    const fn default_pushgateway_interval_secs() -> u64 {
+        15
+    }
+
+    fn default_otlp_service_name() -> String {
+        "ingestd".into()
+    }
+
+    const fn default_queue_capacity() -> usize {
+        1024
+    }
+
+    const fn default_skew_threshold_ms() -> i64 {
+        500
+    }
+
+    const fn default_skew_window_secs() -> u64 {
+        60
+    }
+
+    const fn default_quote_ttl_ms() -> u64 {
+        5_000
+    }
+
+    const fn default_sink_queue_capacity() -> usize {
+        10_000
+    }
+
+    const fn default_retry_buffer() -> usize {
+        10_000
+    }
+
+    const fn default_delivery_attempts() -> u32 {
+        5
+    }
+
+    const fn default_retry_initial_ms() -> u64 {
+        100
+    }
+
+    const fn default_retry_max_ms() -> u64 {
+        10_000
+    }
+
+    const fn default_ack_interval_ms() -> u64 {
+        1_000
+    }
+
+    fn default_kafka_topic() -> String {
+        "{venue}.{kind}".to_string()
+    }
+
+    const fn default_kafka_buffer() -> usize {
+        100_000
+    }
+
+    const fn default_parquet_batch() -> usize {
+        10_000
+    }
+
+    const fn default_parquet_max_bytes() -> u64 {
+        128 * 1024 * 1024
+    }
+
+    const fn default_parquet_max_age() -> u64 {
+        3_600
+    }
+
+    const fn default_postgres_pool() -> usize {
+        4
+    }
+
+    const fn default_postgres_batch() -> usize {
+        1_000
+    }
+
+    const fn default_postgres_flush_ms() -> u64 {
+        1_000
+    }
+
+    fn default_pubsub_ordering_key() -> String {
+        "{symbol}".to_string()
+    }
+
+    fn default_pubsub_endpoint() -> String {
+        "https://pubsub.googleapis.com".to_string()
+    }
+
+    const fn default_pubsub_batch() -> usize {
+        1_000
+    }
+
+    const fn default_pubsub_flush_ms() -> u64 {
+        100
+    }
+
+    fn default_kinesis_partition_key() -> String {
+        "{symbol}".to_string()
+    }
+
+    const fn default_kinesis_batch() -> usize {
+        500
+    }
+
+    const fn default_kinesis_flush_ms() -> u64 {
+        200
+    }
+
+    const fn default_kinesis_attempts() -> u32 {
+        5
+    }
+
+    fn default_eventhubs_partition_key() -> String {
+        "{symbol}".to_string()
+    }
+
+    const fn default_eventhubs_batch() -> usize {
+        500
+    }
+
+    const fn default_eventhubs_flush_ms() -> u64 {
+        100
+    }
+
+    const fn default_duckdb_batch() -> usize {
+        10_000
+    }
+
+    const fn default_duckdb_flush_ms() -> u64 {
+        1_000
+    }
+
+    const fn default_socket_client_buffer() -> usize {
+        1024
+    }
+
+    const fn default_shm_capacity() -> u64 {
+        64 * 1024 * 1024
+    }
+
+    const fn default_influx_batch() -> usize {
+        5_000
+    }
+
+    const fn default_influx_flush_ms() -> u64 {
+        1_000
+    }
+
+    const fn default_wal_fsync_ms() -> u64 {
+        1_000
+    }
+
+    const fn default_wal_segment_bytes() -> u64 {
+        128 * 1024 * 1024
+    }
+
+    fn default_grpc_addr() -> String {
+        "127.0.0.1:50051".to_string()
+    }
+
+    const fn default_grpc_buffer() -> usize {
+        1024
+    }
+
+    fn default_flight_addr() -> String {
+        "127.0.0.1:50052".to_string()
+    }
+
+    const fn default_flight_batch_rows() -> usize {
+        8192
+    }
+
+    const fn default_flight_live_flush_ms() -> u64 {
+        100
+    }
+
+    const fn default_client_connect_burst() -> u32 {
+        10
+    }
+
+    const fn default_sse_keepalive_secs() -> u64 {
+        15
+    }
+
+    fn default_cors_allowed_headers() -> Vec<String> {
+        vec![
+            "authorization".into(),
+            "content-type".into(),
+            "last-event-id".into(),
+        ]
+    }
+
+    const fn default_cors_max_age_secs() -> u64 {
+        600
+    }
+
+    const fn default_ws_send_buffer() -> usize {
+        1024
+    }
+
+    const fn default_archive_compression() -> Compression {
+        Compression::Gzip
+    }
+
+    const fn default_archive_segment_bytes() -> u64 {
+        64 * 1024 * 1024
+    }
+
+    const fn default_archive_segment_age() -> u64 {
+        900
+    }
+
+    const fn default_archive_part_size() -> usize {
+        8 * 1024 * 1024
+    }
+
+    impl Default for BusConfig {
+        fn default() -> Self {
+            Self {
+                capacity: default_bus_capacity(),
+                lag_policy: LagPolicy::default(),
+                shards: default_bus_shards(),
+                replay_buffer: default_bus_replay_buffer(),
+                kinds: BTreeMap::new(),
+                subscribers: BTreeMap::new(),
+            }
+        }
+    }
+
+    impl Default for LogConfig {
+        fn default() -> Self {
+            Self {
+                format: LogFormat::default(),
+                level: default_log_level(),
+                modules: BTreeMap::new(),
+                file: None,
+            }
+        }
+    }
+
+    impl Default for ShutdownConfig {
+        fn default() -> Self {
+            Self {
+                timeout_secs: default_shutdown_timeout_secs(),
+            }
+        }
+    }
+
+    impl Default for SupervisorConfig {
+        fn default() -> Self {
+            Self {
+                initial_backoff_ms: default_supervisor_initial_ms(),
+                max_backoff_ms: default_supervisor_max_ms(),
+                max_failures: default_supervisor_max_failures(),
+                window_secs: default_supervisor_window_secs(),
+                open_secs: default_supervisor_open_secs(),
+            }
+        }
+    }
+
+    impl Default for RuntimeConfig {
+        fn default() -> Self {
+            Self {
+                worker_threads: None,
+                max_blocking_threads: default_runtime_max_blocking_threads(),
+                event_interval: default_runtime_event_interval(),
+            }
+        }
+    }
+
+    impl Default for StreamLimitsConfig {
+        fn default() -> Self {
+            Self {
+                max_connections: None,
+                max_client_connections: None,
+                client_connect_rate: None,
+                client_connect_burst: default_client_connect_burst(),
+            }
+        }
+    }
+
+    impl Default for SseConfig {
+        fn default() -> Self {
+            Self {
+                keepalive_secs: default_sse_keepalive_secs(),
+            }
+        }
+    }
+
+    impl Default for MetricsConfig {
+        fn default() -> Self {
+            Self {
+                top_symbols: default_top_symbols(),
+                latency_buckets_seconds: default_latency_buckets_seconds(),
+                otlp: None,
+                pushgateway: None,
+            }
+        }
+    }
+
+    impl Default for PipelineConfig {
+        fn default() -> Self {
+            Self {
+                queue_capacity: default_queue_capacity(),
+                transforms: Vec::new(),
+                clock_skew: ClockSkewConfig::default(),
+                composite: CompositeConfig::default(),
+            }
+        }
+    }
+
+    impl Default for CompositeConfig {
+        fn default() -> Self {
+            Self {
+                enabled: false,
+                symbols: Vec::new(),
+                quote_ttl_ms: default_quote_ttl_ms(),
+            }
+        }
+    }
+
+    impl Default for ClockSkewConfig {
+        fn default() -> Self {
+            Self {
+                mode: SkewMode::default(),
+                threshold_ms: default_skew_threshold_ms(),
+                window_secs: default_skew_window_secs(),
+            }
+        }
+    }
+
+    impl Default for ChannelConfig {
+        fn default() -> Self {
+            Self {
+                trades: default_trades(),
+                ticker: None,
+                book_ticker: false,
+            }
+        }
+    }
+
+    #[allow(clippy::derivable_impls)]
+    impl Default for TickerConfig {
+        fn default() -> Self {
+            Self {
+                enabled: false,
+                mode: None,
+            }
+        }
+    }
+
+    impl Config {
+        /// Parse configuration from TOML, supporting both the simple `[[venues]]`
+        /// format and the more advanced `[venue.<name>]` style used by
+        /// `config/binance.toml`.
+        #[allow(clippy::should_implement_trait)]
+        pub fn from_str(data: &str) -> Result<Self, toml::de::Error> {
+            Self::from_str_with_profile(data, None)
+        }
+
+        /// Parse configuration as [`Config::from_str`] does, with the
+        /// overrides of `profile` from its `[profiles.<name>]` table applied
+        /// first. Tables merge key by key, anything else is replaced, and
+        /// `[profiles.<name>.venues.<venue>]` overrides the `[[venues]]`
+        /// entry of that name. Without a profile the `[profiles]` tables are
+        /// ignored.
+        pub fn from_str_with_profile(
+            data: &str,
+            profile: Option<&str>,
+        ) -> Result<Self, toml::de::Error> {
+            let mut value: toml::Value = toml::from_str(data)?;
+            let profiles = value.as_table_mut().and_then(|t| t.remove("profiles"));
+            if let Some(name) = profile {
+                let overrides = profiles
+                    .as_ref()
+                    .and_then(|profiles| profiles.get(name))
+                    .ok_or_else(|| de::Error::custom(format!("no profile {}", name)))?;
+                merge(&mut value, overrides.clone())
+                    .map_err(|e| de::Error::custom(format!("profile {}: {}", name, e)))?;
+            }
+            resolve(&mut value, "").map_err(de::Error::custom)?;
+
+            // First attempt to deserialize using the simple struct format.
+            if let Ok(cfg) = value.clone().try_into::<Config>() {
+                return Ok(cfg);
+            }
+
+            // Fallback to parsing `[venue.*]` tables manually. Every other
+            // section is deserialized through `Config` itself.
+            let venue_tables = value.as_table_mut().and_then(|t| {
+                let venues = t.remove("venue");
+                t.insert("venues".into(), toml::Value::Array(Vec::new()));
+                venues
+            });
+            let mut config: Config = value.clone().try_into()?;
+            let global_discovery: DiscoveryConfig = value
+                .get("discovery")
+                .cloned()
+                .map(|v| v.try_into().unwrap_or_default())
+                .unwrap_or_default();
+            if let Some(table) = venue_tables.as_ref().and_then(|v| v.as_table()) {
+                for (name, cfg) in table {
+                    if cfg
+                        .get("enabled")
+                        .and_then(|v| v.as_bool())
+                        .unwrap_or(false)
+                    {
+                        let mut discover = false;
+                        let symbols = match cfg.get("symbols") {
+                            Some(toml::Value::Array(arr)) => arr
+                                .iter()
+                                .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                                .collect(),
+                            Some(toml::Value::String(s)) if s == "ALL" => {
+                                discover = true;
+                                Vec::new()
+                            }
+                            _ => Vec::new(),
+                        };
+                        let ws_base = cfg
+                            .get("ws_base")
+                            .and_then(|v| v.as_str())
+                            .map(|s| s.to_string());
+                        let rest_base = cfg
+                            .get("rest_base")
+                            .and_then(|v| v.as_str())
+                            .map(|s| s.to_string());
+                        let http_timeout_secs = cfg
+                            .get("http_timeout_secs")
+                            .and_then(|v| v.as_integer())
+                            .map(|v| v as u64);
+                        let channels: ChannelConfig = cfg
+                            .get("channels")
+                            .cloned()
+                            .map(|v| v.try_into().unwrap_or_default())
+                            .unwrap_or_default();
+                        let discovery: Option<DiscoveryConfig> = cfg
+                            .get("discovery")
+                            .cloned()
+                            .map(|v| v.try_into().unwrap_or_default())
+                            .or_else(|| {
+                                if discover {
+                                    Some(global_discovery.clone())
+                                } else {
+                                    None
+                                }
+                            });
+
+                        let venue_type = cfg
+                            .get("type")
+                            .and_then(|v| v.as_str())
+                            .map_or_else(default_venue_type, |s| s.to_string());
+                        config.venues.push(VenueConfig {
+                            name: name.clone(),
+                            venue_type,
+                            symbols,
+                            discover,
+                            ws_base,
+                            rest_base,
+                            http_timeout_secs,
+                            channels,
+                            discovery,
+                        });
+                    }
+                }
+            }
+            Ok(config)
+        }
+
+        /// The JSON Schema of the config file, in its `[[venues]]` format.
+        pub fn schema() -> serde_json::Value {
+            let mut schema =
+                serde_json::to_value(schemars::schema_for!(Config)).expect("schemas serialize");
+            // Profiles are partial configs, merged before deserializing.
+            schema["properties"]["profiles"] = serde_json::json!({
+                "description": "Overrides by profile name, selected with `--profile`.",
+                "type": "object",
+                "additionalProperties": { "type": "object" },
+            });
+            schema
+        }
+
+        /// Problems parsing does not catch, one message each.
+        pub fn validate(&self) -> Vec<String> {
+            let mut problems = Vec::new();
+            let mut names = std::collections::BTreeSet::new();
+            for venue in &self.venues {
+                if venue.name.is_empty() {
+                    problems.push("venue without a name".to_string());
+                } else if !names.insert(venue.name.as_str()) {
+                    problems.push(format!("venue {} configured twice", venue.name));
+                }
+                if venue.discover && venue.rest_base.is_none() {
+                    problems.push(format!(
+                        "venue {} discovers its symbols but sets no rest_base",
+                        venue.name
+                    ));
+                }
+                if venue.http_timeout_secs == Some(0) {
+                    problems.push(format!(
+                        "venue {} http_timeout_secs must be positive",
+                        venue.name
+                    ));
+                }
+            }
+            let addrs = [
+                ("grpc", self.grpc.as_ref().map(|grpc| &grpc.addr)),
+                ("flight", self.flight.as_ref().map(|flight| &flight.addr)),
+            ];
+            for (section, addr) in addrs {
+                if let Some(Err(e)) = addr.map(|addr| addr.parse::<std::net::SocketAddr>()) {
+                    problems.push(format!("{} addr: {}", section, e));
+                }
+            }
+            if self
+                .metrics
+                .otlp
+                .as_ref()
+                .is_some_and(|otlp| otlp.interval_secs == 0)
+            {
+                problems.push("metrics otlp interval_secs must be positive".to_string());
+            }
+            if let Some(pushgateway) = &self.metrics.pushgateway {
+                if pushgateway.interval_secs == 0 {
+                    problems.push("metrics pushgateway interval_secs must be positive".to_string());
+                }
+                // Values go into the URL path, where a `/` cannot be escaped.
+                for (name, value) in pushgateway.grouping() {
+                    if !metric_name(name) || name.starts_with("__") {
+                        problems.push(format!(
+                            "metrics pushgateway label {} is not a valid label name",
+                            name
+                        ));
+                    }
+                    if value.is_empty() || value.contains('/') {
+                        problems.push(format!(
+                            "metrics pushgateway label {} must be non-empty and without `/`: {:?}",
+                            name, value
+                        ));
+                    }
+                }
+            }
+            if self.ops.publish && self.ops.auth.tokens.is_empty() {
+                problems.push("ops publish needs auth tokens".to_string());
+            }
+            for (group, name) in [
+                (RouteGroup::Control, "control"),
+                (RouteGroup::Admin, "admin"),
+            ] {
+                if self.ops.auth.routes.get(&group) == Some(&AuthPolicy::Open) {
+                    problems.push(format!("{} routes cannot be open", name));
+                }
+            }
+            if !increasing(&self.metrics.latency_buckets_seconds) {
+                problems.push(format!(
+                    "metrics latency buckets must increase: {:?}",
+                    self.metrics.latency_buckets_seconds
+                ));
+            }
+            let ops_metrics = &self.ops.metrics;
+            if let Some(prefix) = &ops_metrics.prefix {
+                if !metric_name(prefix) {
+                    problems.push(format!(
+                        "ops metrics prefix {:?} is not a metric name",
+                        prefix
+                    ));
+                }
+            }
+            for label in ops_metrics.labels.keys() {
+                if !metric_name(label) || label.contains(':') || label.starts_with("__") {
+                    problems.push(format!("ops metrics label {:?} is not a label name", label));
+                }
+            }
+            for (histogram, buckets) in &ops_metrics.buckets {
+                if buckets.is_empty() || !increasing(buckets) {
+                    problems.push(format!(
+                        "ops metrics buckets of {} must increase: {:?}",
+                        histogram, buckets
+                    ));
+                }
+            }
+            let streams = &self.ops.streams;
+            if streams.max_connections == Some(0) || streams.max_client_connections == Some(0) {
+                problems.push("ops streams connection caps must be positive".to_string());
+            }
+            if streams.client_connect_rate.is_some_and(|rate| rate <= 0.0)
+                || streams.client_connect_burst == 0
+            {
+                problems
+                    .push("ops streams client connect rate and burst must be positive".to_string());
+            }
+            if self.ops.sse.keepalive_secs == 0 {
+                problems.push("ops sse keepalive_secs must be positive".to_string());
+            }
+            if let Some(cors) = &self.ops.cors {
+                let any = |values: &[String]| values.iter().any(|value| value == "*");
+                if cors.allowed_origins.is_empty() {
+                    problems.push("ops cors allows no origins".to_string());
+                }
+                if cors.allow_credentials
+                    && (any(&cors.allowed_origins) || any(&cors.allowed_headers))
+                {
+                    problems.push(
+                        "ops cors allow_credentials needs explicit origins and headers, not `*`"
+                            .to_string(),
+                    );
+                }
+            }
+            if let Some(file) = &self.log.file {
+                if file.path.is_empty() {
+                    problems.push("log file path must not be empty".to_string());
+                }
+                if file.max_bytes == Some(0) {
+                    problems.push("log file max_bytes must be positive".to_string());
+                }
+            }
+            if self.shutdown.timeout_secs == 0 {
+                problems.push("shutdown timeout_secs must be positive".to_string());
+            }
+            if let Some(watchdog) = &self.watchdog {
+                if watchdog.stale_secs == 0 || watchdog.initial_backoff_ms == 0 {
+                    problems.push(
+                        "watchdog stale_secs and initial_backoff_ms must be positive".to_string(),
+                    );
+                }
+                if watchdog.max_backoff_ms < watchdog.initial_backoff_ms {
+                    problems.push(
+                        "watchdog max_backoff_ms must be at least initial_backoff_ms".to_string(),
+                    );
+                }
+            }
+            let supervisor = &self.supervisor;
+            if supervisor.initial_backoff_ms == 0
+                || supervisor.max_failures == 0
+                || supervisor.window_secs == 0
+            {
+                problems.push(
+                    "supervisor initial_backoff_ms, max_failures and window_secs must be positive"
+                        .to_string(),
+                );
+            }
+            if supervisor.max_backoff_ms < supervisor.initial_backoff_ms {
+                problems.push(
+                    "supervisor max_backoff_ms must be at least initial_backoff_ms".to_string(),
+                );
+            }
+            let runtime = &self.runtime;
+            if runtime.worker_threads == Some(0)
+                || runtime.max_blocking_threads == 0
+                || runtime.event_interval == 0
+            {
+                problems.push(
+                    "runtime worker_threads, max_blocking_threads and event_interval must be positive"
+                        .to_string(),
+                );
+            }
+            if let Some(memory) = &self.memory {
+                if memory.budget_bytes == 0 {
+                    problems.push("memory budget_bytes must be positive".to_string());
+                }
+                if memory.shed.contains(&BusKind::Control) {
+                    problems.push("memory cannot shed the control bus".to_string());
+                }
+            }
+            if let Some(cluster) = &self.cluster {
+                if cluster.node.is_empty() {
+                    problems.push("cluster node must not be empty".to_string());
+                }
+                if !(10..=86400).contains(&cluster.ttl_secs) {
+                    problems.push("cluster ttl_secs must be between 10 and 86400".to_string());
+                }
+            }
+            if let Some(standby) = &self.standby {
+                if standby.active.is_empty() {
+                    problems.push("standby active must not be empty".to_string());
+                }
+                if standby.heartbeat_ms == 0 {
+                    problems.push("standby heartbeat_ms must be greater than zero".to_string());
+                }
+                if standby.missed == 0 {
+                    problems.push("standby missed must be greater than zero".to_string());
+                }
+                if self.cluster.is_some() {
+                    problems.push("standby and cluster cannot be combined".to_string());
+                }
+            }
+            if let Some(workers) = &self.workers {
+                if workers.socket_dir.is_empty() {
+                    problems.push("workers socket_dir must not be empty".to_string());
+                }
+                let mut grouped = std::collections::BTreeSet::new();
+                for (worker, venues) in &workers.groups {
+                    if names.contains(worker.as_str()) && !venues.contains(worker) {
+                        problems.push(format!(
+                            "workers group {} is named after a venue outside it",
+                            worker
+                        ));
+                    }
+                    for venue in venues {
+                        if !names.contains(venue.as_str()) {
+                            problems.push(format!(
+                                "workers group {} names unknown venue {}",
+                                worker, venue
+                            ));
+                        } else if !grouped.insert(venue.as_str()) {
+                            problems.push(format!("venue {} is in two worker groups", venue));
+                        }
+                    }
+                }
+            }
+            if let Some(alerts) = &self.alerts {
+                if alerts.interval_secs == 0 {
+                    problems.push("alerts interval_secs must be positive".to_string());
+                }
+                if alerts.adapter_down_secs.is_none()
+                    && alerts.gap_burst.is_none()
+                    && alerts.sink_backlog.is_none()
+                {
+                    problems.push("alerts set no rules".to_string());
+                }
+                if let Some(gap_burst) = &alerts.gap_burst {
+                    if gap_burst.missed == 0 || gap_burst.window_secs == 0 {
+                        problems.push(
+                            "alerts gap_burst missed and window_secs must be positive".to_string(),
+                        );
+                    }
+                }
+                if alerts.sink_backlog.is_some_and(|backlog| backlog <= 0) {
+                    problems.push("alerts sink_backlog must be positive".to_string());
+                }
+                if alerts.webhooks.is_empty() {
+                    problems.push("alerts set no webhooks".to_string());
+                }
+                for webhook in &alerts.webhooks {
+                    if webhook.format == WebhookFormat::Pagerduty && webhook.routing_key.is_none() {
+                        problems.push(format!(
+                            "alerts webhook {} needs a routing_key for the pagerduty format",
+                            webhook.url
+                        ));
+                    }
+                }
+            }
+            problems
+        }
+
+        /// What changed from `self` to `next`. Venues are matched by name.
+        pub fn diff(&self, next: &Config) -> ConfigDiff {
+            let venues = |cfg: &Config| -> BTreeMap<String, VenueConfig> {
+                cfg.venues
+                    .iter()
+                    .map(|venue| (venue.name.clone(), venue.clone()))
+                    .collect()
+            };
+            let (before, after) = (venues(self), venues(next));
+            let mut diff = ConfigDiff::default();
+            for (name, venue) in &after {
+                match before.get(name) {
+                    None => diff.venues_added.push(name.clone()),
+                    Some(old) if old != venue => diff.venues_changed.push(name.clone()),
+                    Some(_) => {}
+                }
+            }
+            diff.venues_removed = before
+                .keys()
+                .filter(|name| !after.contains_key(*name))
+                .cloned()
+                .collect();
+
+            let sections = |cfg: &Config| match serde_json::to_value(cfg) {
+                Ok(serde_json::Value::Object(sections)) => sections,
+                _ => serde_json::Map::new(),
+            };
+            let (before, after) = (sections(self), sections(next));
+            diff.sections_changed = after
+                .iter()
+                .filter(|(name, section)| *name != "venues" && before.get(*name) != Some(section))
+                .map(|(name, _)| name.clone())
+                .collect();
+            diff
+        }
+    }
+
+    /// Replace the `${NAME}` references in the strings of `value` with the
+    /// environment variable `NAME`, and the `${file:PATH}` ones with the
+    /// contents of the file at `PATH`, trimmed, as secrets are mounted.
+    /// `$${` stands for a literal `${`. `path` names `value` in errors.
+    fn resolve(value: &mut toml::Value, path: &str) -> Result<(), String> {
+        let child = |key: &dyn std::fmt::Display| {
+            if path.is_empty() {
+                key.to_string()
+            } else {
+                format!("{}.{}", path, key)
+            }
+        };
+        match value {
+            toml::Value::String(text) if text.contains("${") => {
+                *text = interpolate(text).map_err(|e| format!("{}: {}", path, e))?;
+            }
+            toml::Value::Array(items) => {
+                for (i, item) in items.iter_mut().enumerate() {
+                    resolve(item, &format!("{}[{}]", path, i))?;
+                }
+            }
+            toml::Value::Table(table) => {
+                for (key, item) in table.iter_mut() {
+                    resolve(item, &child(key))?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn interpolate(text: &str) -> Result<String, String> {
+        let mut resolved = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find("${") {
+            if rest[..start].ends_with('$') {
+                resolved.push_str(&rest[..start - 1]);
+                resolved.push_str("${");
+                rest = &rest[start + 2..];
+                continue;
+            }
+            resolved.push_str(&rest[..start]);
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("unterminated reference in {:?}", text))?;
+            let reference = &rest[start + 2..start + end];
+            let value = match reference.strip_prefix("file:") {
+                Some(file) => std::fs::read_to_string(file)
+                    .map(|secret| secret.trim().to_string())
+                    .map_err(|e| format!("secret file {}: {}", file, e))?,
+                None => std::env::var(reference)
+                    .map_err(|_| format!("environment variable {} is not set", reference))?,
+            };
+            resolved.push_str(&value);
+            rest = &rest[start + end + 1..];
+        }
+        resolved.push_str(rest);
+        Ok(resolved)
+    }
+
+    /// Apply the profile `overrides` onto `base`: tables merge key by key,
+    /// a `venues` table overrides the `[[venues]]` entries by name, and
+    /// anything else is replaced.
+    fn merge(base: &mut toml::Value, overrides: toml::Value) -> Result<(), String> {
+        let toml::Value::Table(overrides) = overrides else {
+            *base = overrides;
+            return Ok(());
+        };
+        let toml::Value::Table(table) = base else {
+            *base = toml::Value::Table(overrides);
+            return Ok(());
+        };
+        for (key, value) in overrides {
+            match (table.get_mut(&key), value) {
+                (Some(toml::Value::Array(venues)), toml::Value::Table(by_name))
+                    if key == "venues" =>
+                {
+                    for (name, value) in by_name {
+                        let venue = venues
+                            .iter_mut()
+                            .find(|venue| venue.get("name").and_then(|v| v.as_str()) == Some(&name))
+                            .ok_or_else(|| format!("no venue {} to override", name))?;
+                        merge(venue, value)?;
+                    }
+                }
+                (Some(existing), value) => merge(existing, value)?,
+                (None, value) => {
+                    table.insert(key, value);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub mod error {
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    pub enum IngestError {
+        #[error("validation failed: {0}")]
+        Validation(String),
+        #[error("io error: {0}")]
+        Io(#[from] std::io::Error),
+        #[error("serde error: {0}")]
+        Serde(#[from] serde_json::Error),
+        #[error("transform error: {0}")]
+        Transform(String),
+        #[error("sink error: {0}")]
+        Sink(String),
+        #[error("wal error: {0}")]
+        Wal(String),
+        #[error("control error: {0}")]
+        Control(String),
+    }
+}
+
+/// Canonicalize a symbol to our uppercase format.
+pub fn canonical_symbol(input: &str) -> String {
+    input.to_uppercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        canonical_symbol,
+        config::{
+            AuthPolicy, BusKind, Config, Encoding, LogFormat, RouteGroup, RuntimeConfig, SinkKind,
+            SinkRoute, SupervisorConfig, WebhookFormat,
+        },
+        event::{EventKind, NormalizedEvent, Side, Subscription, Trade, COMPOSITE_VENUE},
+    };
+
+    #[test]
+    fn symbol_uppercase() {
+        assert_eq!(canonical_symbol("btcusdt"), "BTCUSDT");
+    }
+
+    #[test]
+    fn trade_payload_roundtrip() {
+        let trade = Trade {
+            trade_id: 7,
+            price: "101.50".parse().unwrap(),
+            quantity: "0.25".parse().unwrap(),
+            side: Side::Sell,
+        };
+        let evt = NormalizedEvent {
+            venue: "binance".into(),
+            symbol: "BTCUSDT".into(),
+            kind: EventKind::Trade,
+            payload: serde_json::to_value(&trade).unwrap(),
+            ..Default::default()
+        };
+        // Prices travel as the venue's decimal strings.
+        assert_eq!(evt.payload["price"], "101.50");
+        assert_eq!(evt.trade(), Some(trade));
+        let raw = NormalizedEvent {
+            kind: EventKind::Raw,
+            ..evt
+        };
+        assert_eq!(raw.trade(), None);
+    }
+
+    #[test]
+    fn parse_simple_config() {
+        let data = "[[venues]]\nname=\"binance\"\nsymbols=[\"BTCUSDT\"]\n";
+        let cfg = Config::from_str(data).unwrap();
+        assert_eq!(cfg.venues.len(), 1);
+        assert_eq!(cfg.venues[0].name, "binance");
+    }
+
+    #[test]
+    fn parse_binance_style_config() {
+        let data = r#"
+[venue.binance_spot]
+enabled = true
+ws_base = "wss://example"
+rest_base = "https://rest.example"
+symbols = ["BTCUSDT", "ETHUSDT"]
+
+[venue.binance_spot.channels]
+trades = true
+
+[venue.binance_usdm]
+enabled = false
+"#;
+        let cfg = Config::from_str(data).unwrap();
+        assert_eq!(cfg.venues.len(), 1);
+        assert_eq!(cfg.venues[0].name, "binance_spot");
+        assert_eq!(cfg.venues[0].ws_base.as_deref(), Some("wss://example"));
+        assert!(cfg.venues[0].channels.trades);
+        assert!(!cfg.venues[0].discover);
+    }
+
+    #[test]
+    fn parse_all_symbols_as_discovery() {
+        let data = r#"
+[venue.binance_spot]
+enabled = true
+symbols = "ALL"
+"#;
+        let cfg = Config::from_str(data).unwrap();
+        assert_eq!(cfg.venues.len(), 1);
+        assert!(cfg.venues[0].discover);
+        assert!(cfg.venues[0].symbols.is_empty());
+    }
+
+    #[test]
+    fn parse_metrics() {
+        let cfg = Config::from_str("venues = []").unwrap();
+        assert_eq!(cfg.metrics.top_symbols, 20);
+        let data = r#"
+venues = []
+
+[metrics]
+latency_buckets_seconds = [0.0005, 0.001, 0.002]
+
+[metrics.otlp]
+endpoint = "http://collector:4317"
+"#;
+        let cfg = Config::from_str(data).unwrap();
+        assert_eq!(cfg.metrics.latency_buckets_seconds, [0.0005, 0.001, 0.002]);
+        let otlp = cfg.metrics.otlp.unwrap();
+        assert_eq!(otlp.endpoint, "http://collector:4317");
+        assert_eq!(otlp.interval_secs, 10);
+    }
+
+    #[test]
+    fn parse_metrics_pushgateway() {
+        let data = r#"
+venues = []
+
+[metrics.pushgateway]
+url = "http://pushgateway:9091"
+instance = "ingest-1"
+labels = { region = "eu-west-1" }
+"#;
+        let cfg = Config::from_str(data).unwrap();
+        let pushgateway = cfg.metrics.pushgateway.as_ref().unwrap();
+        assert_eq!(pushgateway.interval_secs, 15);
+        assert_eq!(
+            pushgateway.grouping(),
+            [
+                ("job", "ingestd"),
+                ("instance", "ingest-1"),
+                ("region", "eu-west-1")
+            ]
+        );
+        assert!(cfg.validate().is_empty());
+
+        let data = r#"
+venues = []
+
+[metrics.pushgateway]
+url = "http://pushgateway:9091"
+job = "ingest/prod"
+labels = { "1zone" = "a" }
+interval_secs = 0
+"#;
+        let problems = Config::from_str(data).unwrap().validate();
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+    }
+
+    #[test]
+    fn diff_reports_changed_venues_and_sections() {
+        let before = Config::from_str(
+            r#"
+[[venues]]
+name = "binance_spot"
+symbols = ["BTCUSDT"]
+
+[[venues]]
+name = "binance_usdm"
+symbols = ["BTCUSDT"]
+"#,
+        )
+        .unwrap();
+        let after = Config::from_str(
+            r#"
+[[venues]]
+name = "binance_spot"
+symbols = ["BTCUSDT", "ETHUSDT"]
+
+[[venues]]
+name = "kraken"
+symbols = ["XBTUSD"]
+
+[bus]
+capacity = 64
+"#,
+        )
+        .unwrap();
+        let diff = before.diff(&after);
+        assert_eq!(diff.venues_added, ["kraken"]);
+        assert_eq!(diff.venues_removed, ["binance_usdm"]);
+        assert_eq!(diff.venues_changed, ["binance_spot"]);
+        assert_eq!(diff.sections_changed, ["bus"]);
+        assert!(after.diff(&after).is_empty());
+    }
+
+    #[test]
+    fn validate_reports_duplicate_venues_and_bad_addrs() {
+        let cfg = Config::from_str(
+            r#"
+[[venues]]
+name = "binance_spot"
+symbols = []
+
+[[venues]]
+name = "binance_spot"
+symbols = []
+
+[grpc]
+addr = "localhost"
+"#,
+        )
+        .unwrap();
+        let problems = cfg.validate();
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0], "venue binance_spot configured twice");
+        assert!(problems[1].starts_with("grpc addr"));
+        assert!(Config::from_str("venues = []")
+            .unwrap()
+            .validate()
+            .is_empty());
+        let discovering = r#"
+[[venues]]
+name = "binance_spot"
+symbols = []
+discover = true
+"#;
+        assert_eq!(
+            Config::from_str(discovering).unwrap().validate(),
+            ["venue binance_spot discovers its symbols but sets no rest_base"]
+        );
+    }
+
+    #[test]
+    fn parse_ops_metrics() {
+        let data = r#"
+venues = []
+
+[ops.metrics]
+prefix = "ingest"
+labels = { region = "eu-west-1", instance = "ingest-1" }
+buckets = { receive_to_publish_latency_seconds = [0.1, 1, 10] }
+"#;
+        let cfg = Config::from_str(data).unwrap();
+        let metrics = &cfg.ops.metrics;
+        assert_eq!(metrics.prefix.as_deref(), Some("ingest"));
+        assert_eq!(metrics.labels["region"], "eu-west-1");
+        assert_eq!(
+            metrics.buckets_or("receive_to_publish_latency_seconds", &[5.0]),
+            vec![0.1, 1.0, 10.0]
+        );
+        assert_eq!(
+            metrics.buckets_or("venue_events_per_second", &[5.0]),
+            vec![5.0]
+        );
+        assert!(cfg.validate().is_empty());
+
+        let data = r#"
+venues = []
+
+[ops.metrics]
+prefix = "ingest-prod"
+labels = { "__name" = "x", "zone" = "a" }
+buckets = { receive_to_publish_latency_seconds = [10, 1] }
+"#;
+        let problems = Config::from_str(data).unwrap().validate();
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+    }
+
+    #[test]
+    fn parse_alerts() {
+        let data = r#"
+venues = []
+
+[alerts]
+adapter_down_secs = 30
+gap_burst = { missed = 100 }
+
+[[alerts.webhooks]]
+url = "https://hooks.slack.com/services/T0/B0/x"
+format = "slack"
+"#;
+        let cfg = Config::from_str(data).unwrap();
+        let alerts = cfg.alerts.as_ref().unwrap();
+        assert_eq!((alerts.interval_secs, alerts.cooldown_secs), (10, 300));
+        assert_eq!(alerts.gap_burst.as_ref().unwrap().window_secs, 60);
+        assert_eq!(alerts.sink_backlog, None);
+        assert_eq!(alerts.webhooks[0].format, WebhookFormat::Slack);
+        assert!(cfg.validate().is_empty());
+
+        let data = r#"
+venues = []
+
+[alerts]
+sink_backlog = 0
+
+[[alerts.webhooks]]
+url = "https://events.pagerduty.com/v2/enqueue"
+format = "pagerduty"
+"#;
+        let problems = Config::from_str(data).unwrap().validate();
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+    }
+
+    #[test]
+    fn parse_watchdog() {
+        let cfg = Config::from_str("venues = []").unwrap();
+        assert!(cfg.watchdog.is_none());
+        let data = r#"
+venues = []
+
+[watchdog]
+stale_secs = 30
+"#;
+        let cfg = Config::from_str(data).unwrap();
+        let watchdog = cfg.watchdog.as_ref().unwrap();
+        assert_eq!(
+            (
+                watchdog.stale_secs,
+                watchdog.initial_backoff_ms,
+                watchdog.max_backoff_ms
+            ),
+            (30, 1_000, 300_000)
+        );
+        assert!(cfg.validate().is_empty());
+
+        let data = r#"
+venues = []
+
+[watchdog]
+stale_secs = 0
+initial_backoff_ms = 5000
+max_backoff_ms = 1000
+"#;
+        let problems = Config::from_str(data).unwrap().validate();
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+    }
+
+    #[test]
+    fn schema_describes_sections() {
+        let schema = Config::schema();
+        let properties = &schema["properties"];
+        assert_eq!(properties["venues"]["type"], "array");
+        assert!(properties["supervisor"].is_object());
+    }
+
+    #[test]
+    fn event_schema_describes_payloads() {
+        let schema = crate::event::schema();
+        assert_eq!(schema["properties"]["timestamp"]["format"], "date-time");
+        assert!(schema["required"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|field| field != "received_at"));
+        let definitions = &schema["$defs"];
+        assert_eq!(
+            definitions["Trade"]["properties"]["side"]["$ref"],
+            "#/$defs/Side"
+        );
+        assert!(definitions["Quote"]["properties"]["bid_price"].is_object());
+    }
+
+    #[test]
+    fn parse_supervisor() {
+        let cfg = Config::from_str("venues = []").unwrap();
+        assert_eq!(cfg.supervisor, SupervisorConfig::default());
+        let data = r#"
+venues = []
+
+[supervisor]
+max_failures = 3
+open_secs = 60
+"#;
+        let cfg = Config::from_str(data).unwrap();
+        assert_eq!(
+            (
+                cfg.supervisor.initial_backoff_ms,
+                cfg.supervisor.max_failures,
+                cfg.supervisor.open_secs
+            ),
+            (1_000, 3, 60)
+        );
+        assert!(cfg.validate().is_empty());
+
+        let data = r#"
+venues = []
+
+[supervisor]
+max_failures = 0
+initial_backoff_ms = 5000
+max_backoff_ms = 1000
+"#;
+        let problems = Config::from_str(data).unwrap().validate();
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+    }
+
+    #[test]
+    fn parse_cluster() {
+        let cfg = Config::from_str("venues = []").unwrap();
+        assert!(cfg.cluster.is_none());
+        let data = r#"
+venues = []
+
+[cluster]
+node = "ingest-a"
+"#;
+        let cfg = Config::from_str(data).unwrap();
+        let cluster = cfg.cluster.as_ref().unwrap();
+        assert_eq!(
+            (
+                cluster.consul.as_str(),
+                cluster.prefix.as_str(),
+                cluster.ttl_secs
+            ),
+            ("http://127.0.0.1:8500", "ingest/venues", 15)
+        );
+        assert!(cfg.validate().is_empty());
+
+        let data = r#"
+venues = []
+
+[cluster]
+node = ""
+ttl_secs = 5
+"#;
+        let problems = Config::from_str(data).unwrap().validate();
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+    }
+
+    #[test]
+    fn parse_standby() {
+        let cfg = Config::from_str("venues = []").unwrap();
+        assert!(cfg.standby.is_none());
+        let data = r#"
+venues = []
+
+[standby]
+active = "http://ingest-a:3000"
+"#;
+        let cfg = Config::from_str(data).unwrap();
+        let standby = cfg.standby.as_ref().unwrap();
+        assert_eq!(
+            (
+                standby.heartbeat_ms,
+                standby.missed,
+                standby.warm,
+                standby.primary
+            ),
+            (500, 6, true, false)
+        );
+        assert!(cfg.validate().is_empty());
+
+        let data = r#"
+venues = []
+
+[standby]
+peer = "http://ingest-b:3000"
+primary = true
+"#;
+        let cfg = Config::from_str(data).unwrap();
+        let standby = cfg.standby.as_ref().unwrap();
+        assert_eq!(standby.active, "http://ingest-b:3000");
+        assert!(standby.primary);
+
+        let data = r#"
+venues = []
+
+[standby]
+active = ""
+missed = 0
+
+[cluster]
+node = "ingest-b"
+"#;
+        let problems = Config::from_str(data).unwrap().validate();
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+    }
+
+    #[test]
+    fn parse_runtime() {
+        let cfg = Config::from_str("venues = []").unwrap();
+        assert_eq!(cfg.runtime, RuntimeConfig::default());
+        let data = r#"
+venues = []
+
+[runtime]
+worker_threads = 2
+event_interval = 31
+"#;
+        let cfg = Config::from_str(data).unwrap();
+        assert_eq!(
+            (
+                cfg.runtime.worker_threads,
+                cfg.runtime.max_blocking_threads,
+                cfg.runtime.event_interval
+            ),
+            (Some(2), 512, 31)
+        );
+        assert!(cfg.validate().is_empty());
+
+        let data = r#"
+venues = []
+
+[runtime]
+worker_threads = 0
+"#;
+        assert_eq!(Config::from_str(data).unwrap().validate().len(), 1);
+    }
+
+    #[test]
+    fn parse_profiles() {
+        let data = r#"
+[[venues]]
+name = "binance_spot"
+symbols = ["BTCUSDT", "ETHUSDT", "SOLUSDT"]
+
+[log]
+level = "info"
+
+[profiles.dev.log]
+level = "debug"
+
+[profiles.dev.venues.binance_spot]
+ws_base = "wss://testnet.binance.vision/ws"
+symbols = ["BTCUSDT"]
+"#;
+        let cfg = Config::from_str(data).unwrap();
+        assert_eq!(cfg.log.level, "info");
+        assert_eq!(cfg.venues[0].symbols.len(), 3);
+
+        let dev = Config::from_str_with_profile(data, Some("dev")).unwrap();
+        assert_eq!(dev.log.level, "debug");
+        let venue = &dev.venues[0];
+        assert_eq!(venue.symbols, vec!["BTCUSDT".to_string()]);
+        assert_eq!(
+            venue.ws_base.as_deref(),
+            Some("wss://testnet.binance.vision/ws")
+        );
+        assert_eq!(
+            cfg.diff(&dev).venues_changed,
+            vec!["binance_spot".to_string()]
+        );
+
+        assert!(Config::from_str_with_profile(data, Some("prod")).is_err());
+        let unknown = format!("{}\n[profiles.staging.venues.kraken]\nsymbols = []\n", data);
+        assert!(Config::from_str_with_profile(&unknown, Some("staging")).is_err());
+    }
+
+    #[test]
+    fn parse_references() {
+        std::env::set_var("INGEST_TEST_WS_HOST", "stream.example");
+        let secret = std::env::temp_dir().join(format!("ingest-secret-{}", std::process::id()));
+        std::fs::write(&secret, "s3cret\n").unwrap();
+        let data = format!(
+            r#"
+[[venues]]
+name = "binance_spot"
+symbols = ["BTCUSDT"]
+ws_base = "wss://${{INGEST_TEST_WS_HOST}}/ws"
+rest_base = "https://$${{literal}}"
+
+[ops.auth]
+tokens = ["${{file:{}}}"]
+"#,
+            secret.display()
+        );
+        let cfg = Config::from_str(&data).unwrap();
+        std::fs::remove_file(&secret).unwrap();
+        let venue = &cfg.venues[0];
+        assert_eq!(venue.ws_base.as_deref(), Some("wss://stream.example/ws"));
+        assert_eq!(venue.rest_base.as_deref(), Some("https://${literal}"));
+        assert_eq!(cfg.ops.auth.tokens, ["s3cret"]);
+
+        let e = Config::from_str(&data).unwrap_err().to_string();
+        assert!(e.contains("ops.auth.tokens[0]: secret file"), "{}", e);
+        let unset = "venues = []\n[log]\nlevel = \"${INGEST_TEST_UNSET}\"\n";
+        let e = Config::from_str(unset).unwrap_err().to_string();
+        assert!(
+            e.contains("log.level: environment variable INGEST_TEST_UNSET is not set"),
+            "{}",
+            e
+        );
+    }
+
+    #[test]
+    fn parse_memory() {
+        let cfg = Config::from_str("venues = []").unwrap();
+        assert!(cfg.memory.is_none());
+        let data = r#"
+venues = []
+
+[memory]
+budget_bytes = 1073741824
+"#;
+        let cfg = Config::from_str(data).unwrap();
+        let memory = cfg.memory.as_ref().unwrap();
+        assert_eq!(
+            memory.shed,
+            [BusKind::Raw, BusKind::Books, BusKind::Tickers]
+        );
+        assert!(cfg.validate().is_empty());
+
+        let data = r#"
+venues = []
+
+[memory]
+budget_bytes = 0
+shed = ["raw", "control"]
+"#;
+        let problems = Config::from_str(data).unwrap().validate();
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+    }
+
+    #[test]
+    fn parse_workers() {
+        let cfg = Config::from_str("venues = []").unwrap();
+        assert!(cfg.workers.is_none());
+        let data = r#"
+[[venues]]
+name = "binance_spot"
+symbols = []
+
+[[venues]]
+name = "binance_futures"
+symbols = []
+
+[[venues]]
+name = "coinbase"
+symbols = []
+
+[workers]
+socket_dir = "/run/ingest/workers"
+groups = { binance = ["binance_spot", "binance_futures"] }
+"#;
+        let cfg = Config::from_str(data).unwrap();
+        assert!(cfg.validate().is_empty());
+        let workers = cfg.workers.as_ref().unwrap();
+        assert_eq!(workers.worker_of("binance_futures"), "binance");
+        assert_eq!(workers.worker_of("coinbase"), "coinbase");
+
+        let data = r#"
+[[venues]]
+name = "coinbase"
+symbols = []
+
+[workers]
+socket_dir = ""
+groups = { a = ["coinbase", "kraken"], b = ["coinbase"] }
+"#;
+        let problems = Config::from_str(data).unwrap().validate();
+        assert_eq!(
+            problems,
+            [
+                "workers socket_dir must not be empty",
+                "workers group a names unknown venue kraken",
+                "venue coinbase is in two worker groups",
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_ops_cors() {
+        let cfg = Config::from_str("venues = []").unwrap();
+        assert!(cfg.ops.cors.is_none());
+        assert_eq!(cfg.ops.sse.keepalive_secs, 15);
+        let data = r#"
+venues = []
+
+[ops.cors]
+allowed_origins = ["https://dash.example.com"]
+allow_credentials = true
+"#;
+        let cfg = Config::from_str(data).unwrap();
+        let cors = cfg.ops.cors.as_ref().unwrap();
+        assert_eq!(
+            cors.allowed_headers,
+            ["authorization", "content-type", "last-event-id"]
+        );
+        assert_eq!(cors.max_age_secs, 600);
+        assert!(cfg.validate().is_empty());
+
+        let data = r#"
+venues = []
+
+[ops.cors]
+allowed_origins = ["*"]
+allow_credentials = true
+"#;
+        let problems = Config::from_str(data).unwrap().validate();
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+    }
+
+    #[test]
+    fn parse_ops_auth() {
+        let cfg = Config::from_str("venues = []").unwrap();
+        assert_eq!(cfg.ops.auth.policy(RouteGroup::Admin), AuthPolicy::Open);
+        let data = r#"
+venues = []
+
+[ops.auth]
+tokens = ["s3cret"]
+routes = { metrics = "token", data = "open" }
+"#;
+        let cfg = Config::from_str(data).unwrap();
+        let policy = |group| cfg.ops.auth.policy(group);
+        assert_eq!(policy(RouteGroup::Health), AuthPolicy::Open);
+        assert_eq!(policy(RouteGroup::Metrics), AuthPolicy::Token);
+        assert_eq!(policy(RouteGroup::Data), AuthPolicy::Open);
+        assert_eq!(policy(RouteGroup::Admin), AuthPolicy::Token);
+        assert!(cfg.validate().is_empty());
+        let open_admin = data.replace("data = ", "admin = ");
+        let problems = Config::from_str(&open_admin).unwrap().validate();
+        assert_eq!(problems, ["admin routes cannot be open"]);
+        let open_control = data.replace("data = ", "control = ");
+        let problems = Config::from_str(&open_control).unwrap().validate();
+        assert_eq!(problems, ["control routes cannot be open"]);
+
+        let tls = r#"
+venues = []
+
+[ops.tls]
+cert_path = "/etc/ingest/ops.pem"
+key_path = "/etc/ingest/ops.key"
+"#;
+        let tls = Config::from_str(tls).unwrap().ops.tls.unwrap();
+        assert_eq!(tls.key_path, "/etc/ingest/ops.key");
+        assert_eq!(tls.client_ca_path, None);
+    }
+
+    #[test]
+    fn ops_publish_needs_tokens() {
+        let data = "venues = []\n\n[ops]\npublish = true\n";
+        let problems = Config::from_str(data).unwrap().validate();
+        assert_eq!(problems, ["ops publish needs auth tokens"]);
+        let with_tokens = format!("{}\n[ops.auth]\ntokens = [\"s3cret\"]\n", data);
+        assert!(Config::from_str(&with_tokens)
+            .unwrap()
+            .validate()
+            .is_empty());
+    }
+
+    #[test]
+    fn parse_log() {
+        let cfg = Config::from_str("venues = []").unwrap();
+        assert_eq!(cfg.log.format, LogFormat::Json);
+        assert_eq!(cfg.log.directives(), "info");
+        let data = r#"
+venues = []
+
+[log]
+format = "text"
+level = "warn"
+modules = { "sinks::kafka" = "debug", ops = "info" }
+"#;
+        let cfg = Config::from_str(data).unwrap();
+        assert_eq!(cfg.log.format, LogFormat::Text);
+        assert_eq!(cfg.log.directives(), "warn,ops=info,sinks::kafka=debug");
+        assert!(cfg.log.file.is_none());
+
+        let data = r#"
+venues = []
+
+[log.file]
+path = "/var/log/ingest/ingestd.log"
+max_bytes = 104857600
+daily = true
+"#;
+        let cfg = Config::from_str(data).unwrap();
+        let file = cfg.log.file.as_ref().unwrap();
+        assert_eq!(
+            (file.max_bytes, file.daily, file.retain),
+            (Some(104_857_600), true, 7)
+        );
+        assert!(cfg.validate().is_empty());
+
+        let data = r#"
+venues = []
+
+[log.file]
+path = ""
+max_bytes = 0
+"#;
+        let problems = Config::from_str(data).unwrap().validate();
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+    }
+
+    #[test]
+    fn parse_kind_buses() {
+        let data = r#"
+[bus]
+capacity = 512
+
+[bus.kinds.books]
+capacity = 8192
+"#;
+        let cfg = Config::from_str(data).unwrap();
+        assert_eq!(cfg.bus.capacity, 512);
+        assert_eq!(cfg.bus.kinds[&BusKind::Books].capacity, Some(8192));
+        assert!(!cfg.bus.kinds.contains_key(&BusKind::Trades));
+
+        let event = |venue: &str, kind| NormalizedEvent {
+            venue: venue.into(),
+            kind,
+            ..Default::default()
+        };
+        assert_eq!(
+            BusKind::of(&event("kraken", EventKind::Quote)),
+            BusKind::Tickers
+        );
+        assert_eq!(BusKind::of(&event("kraken", EventKind::Raw)), BusKind::Raw);
+        let status = NormalizedEvent::adapter_status("kraken", false);
+        assert_eq!(BusKind::of(&status), BusKind::Control);
+        assert_eq!(status.as_adapter_status(), Some(("kraken", false)));
+        let subscription = Subscription {
+            venue: "kraken".into(),
+            symbols: vec!["XBTUSD".into()],
+            discovered: true,
+        };
+        let subscribed = NormalizedEvent::adapter_subscription(&subscription);
+        assert_eq!(BusKind::of(&subscribed), BusKind::Control);
+        assert_eq!(subscribed.as_adapter_subscription(), Some(subscription));
+        assert_eq!(subscribed.as_adapter_status(), None);
+        assert_eq!(status.as_adapter_subscription(), None);
+        assert_eq!(
+            BusKind::of(&event(COMPOSITE_VENUE, EventKind::Trade)),
+            BusKind::Derived
+        );
+    }
+
+    #[test]
+    fn parse_kafka_sink() {
         let data = r#"
 [venue.binance_spot]
 enabled = true
-symbols = "ALL"
+symbols = ["BTCUSDT"]
+
+[sinks.trades]
+type = "kafka"
+brokers = "localhost:9092"
+encoding = "proto"
+queue_capacity = 500
 "#;
         let cfg = Config::from_str(data).unwrap();
         assert_eq!(cfg.venues.len(), 1);
-        assert!(cfg.venues[0].discover);
-        assert!(cfg.venues[0].symbols.is_empty());
+        let sink = &cfg.sinks["trades"];
+        assert_eq!(sink.queue_capacity, 500);
+        match &sink.kind {
+            SinkKind::Kafka(kafka) => {
+                assert_eq!(kafka.topic, "{venue}.{kind}");
+                assert_eq!(kafka.encoding, Encoding::Proto);
+            }
+            other => panic!("unexpected sink {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_sink_route() {
+        let data = r#"
+[venue.binance_spot]
+enabled = true
+
+[sinks.trades]
+type = "kafka"
+brokers = "localhost:9092"
+
+[sinks.trades.route]
+venues = ["binance_spot"]
+kinds = ["trade"]
+"#;
+        let cfg = Config::from_str(data).unwrap();
+        assert_eq!(
+            cfg.sinks["trades"].route,
+            SinkRoute {
+                venues: vec!["binance_spot".into()],
+                symbols: vec![],
+                kinds: vec![EventKind::Trade],
+            }
+        );
+    }
+
+    #[test]
+    fn parse_parquet_sink() {
+        let data = r#"
+[venue.binance_spot]
+enabled = true
+symbols = ["BTCUSDT"]
+
+[sinks.archive]
+type = "parquet"
+path = "/var/lib/ingest/archive"
+max_file_age_secs = 600
+"#;
+        let cfg = Config::from_str(data).unwrap();
+        match &cfg.sinks["archive"].kind {
+            SinkKind::Parquet(parquet) => {
+                assert_eq!(parquet.path, "/var/lib/ingest/archive");
+                assert_eq!(parquet.batch_size, 10_000);
+                assert_eq!(parquet.max_file_age_secs, 600);
+            }
+            other => panic!("unexpected sink {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_repo_configs() {
+        let binance = Config::from_str(include_str!("../../../config/binance.toml")).unwrap();
+        assert_eq!(binance.venues.len(), 3);
+        let example = Config::from_str(include_str!("../../../config/example.toml")).unwrap();
+        assert_eq!(example.venues[0].symbols, vec!["BTCUSDT", "ETHUSDT"]);
+    }
+
+    #[test]
+    fn invalid_section_is_an_error() {
+        let data = "[venue.binance_spot]\nenabled = true\n[sinks.bad]\ntype = \"nope\"\n";
+        assert!(Config::from_str(data).is_err());
+    }
+
+    #[test]
+    fn parse_pipeline_transforms() {
+        let data = r#"
+[[venues]]
+name = "binance"
+symbols = ["BTCUSDT"]
+
+[pipeline]
+queue_capacity = 64
+
+[[pipeline.transforms]]
+name = "enrich"
+module = "transforms/enrich.wasm"
+fuel = 1000000
+"#;
+        let cfg = Config::from_str(data).unwrap();
+        assert_eq!(cfg.pipeline.queue_capacity, 64);
+        assert_eq!(cfg.pipeline.transforms.len(), 1);
+        assert_eq!(cfg.pipeline.transforms[0].fuel, Some(1_000_000));
     }
 }
@@ -0,0 +1,313 @@
+//! `devtools backfill`: fetch history from Binance's REST API as the stream
+//! payloads it would have sent, for packs and sinks.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use agents::{golden, Adapter};
+use api::Filter;
+use chrono::{DateTime, TimeDelta, Timelike, Utc};
+use ingest_core::config::Config;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+use sinks::{Sink, SinkMetrics};
+
+/// What to fetch.
+#[derive(Clone)]
+pub enum History {
+    /// Klines of an interval such as `1m`, as final `kline` payloads.
+    Klines(String),
+    /// Aggregate trades, as `aggTrade` payloads.
+    AggTrades,
+}
+
+/// Rows per request, the most Binance returns.
+const LIMIT: usize = 1000;
+
+/// Longest span of one aggregate trades request by time.
+const AGG_TRADES_WINDOW: TimeDelta = TimeDelta::hours(1);
+
+/// Pages through the history of a symbol between two times, oldest first,
+/// keeping the request weight used per minute under a budget.
+pub struct Backfill {
+    client: Client,
+    rest_base: String,
+    symbol: String,
+    history: History,
+    to: DateTime<Utc>,
+    /// Where the next page starts.
+    cursor: DateTime<Utc>,
+    /// The next aggregate trade, once the first is known.
+    from_id: Option<u64>,
+    max_weight: u64,
+    done: bool,
+}
+
+impl Backfill {
+    pub fn new(
+        rest_base: &str,
+        symbol: &str,
+        history: History,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        max_weight: u64,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            client: Client::builder().timeout(Duration::from_secs(30)).build()?,
+            rest_base: rest_base.trim_end_matches('/').to_string(),
+            symbol: symbol.to_uppercase(),
+            history,
+            to,
+            cursor: from,
+            from_id: None,
+            max_weight,
+            done: from >= to,
+        })
+    }
+
+    /// How far the history has been fetched.
+    pub fn cursor(&self) -> DateTime<Utc> {
+        self.cursor
+    }
+
+    /// The stream payloads of the next page, or `None` past the end.
+    pub async fn next(&mut self) -> Result<Option<Vec<Value>>, Box<dyn std::error::Error>> {
+        while !self.done {
+            let payloads = match self.history.clone() {
+                History::Klines(interval) => self.klines(&interval).await?,
+                History::AggTrades => self.agg_trades().await?,
+            };
+            if !payloads.is_empty() {
+                return Ok(Some(payloads));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn klines(&mut self, interval: &str) -> Result<Vec<Value>, Box<dyn std::error::Error>> {
+        let rows = self
+            .get(
+                "klines",
+                &[
+                    ("interval", interval.to_string()),
+                    ("startTime", self.cursor.timestamp_millis().to_string()),
+                    // The end is exclusive, as for the other history.
+                    ("endTime", (self.to.timestamp_millis() - 1).to_string()),
+                ],
+            )
+            .await?;
+        let rows = rows.as_array().cloned().unwrap_or_default();
+        if rows.len() < LIMIT {
+            self.done = true;
+        }
+        let mut payloads = Vec::with_capacity(rows.len());
+        for row in rows {
+            let field = |i: usize| row.get(i).cloned().unwrap_or(Value::Null);
+            let close_time = field(6).as_i64().unwrap_or_default();
+            payloads.push(json!({
+                "e": "kline",
+                "E": close_time,
+                "s": self.symbol,
+                "k": {
+                    "t": field(0), "T": close_time, "s": self.symbol, "i": interval,
+                    "o": field(1), "c": field(4), "h": field(2), "l": field(3),
+                    "v": field(5), "n": field(8), "x": true, "q": field(7),
+                    "V": field(9), "Q": field(10),
+                },
+            }));
+            self.cursor = millis(close_time + 1)?;
+        }
+        if self.cursor >= self.to {
+            self.done = true;
+        }
+        Ok(payloads)
+    }
+
+    /// Aggregate trades are found by time a window at a time, then paged
+    /// through by id.
+    async fn agg_trades(&mut self) -> Result<Vec<Value>, Box<dyn std::error::Error>> {
+        let query = match self.from_id {
+            Some(id) => vec![("fromId", id.to_string())],
+            None => {
+                let end = (self.cursor + AGG_TRADES_WINDOW).min(self.to);
+                vec![
+                    ("startTime", self.cursor.timestamp_millis().to_string()),
+                    ("endTime", (end.timestamp_millis() - 1).to_string()),
+                ]
+            }
+        };
+        let rows = self.get("aggTrades", &query).await?;
+        let rows = rows.as_array().cloned().unwrap_or_default();
+        if self.from_id.is_none() && rows.is_empty() {
+            self.cursor += AGG_TRADES_WINDOW;
+            self.done = self.cursor >= self.to;
+            return Ok(Vec::new());
+        }
+        if rows.len() < LIMIT && self.from_id.is_some() {
+            self.done = true;
+        }
+        let mut payloads = Vec::with_capacity(rows.len());
+        for row in rows {
+            let time = row.get("T").and_then(Value::as_i64).unwrap_or_default();
+            if time >= self.to.timestamp_millis() {
+                self.done = true;
+                break;
+            }
+            self.from_id = row.get("a").and_then(Value::as_u64).map(|id| id + 1);
+            self.cursor = millis(time)?;
+            let mut payload = json!({"e": "aggTrade", "E": time, "s": self.symbol});
+            if let (Value::Object(payload), Value::Object(row)) = (&mut payload, row) {
+                payload.extend(row);
+            }
+            payloads.push(payload);
+        }
+        Ok(payloads)
+    }
+
+    /// `GET <rest_base>/<path>` for the symbol, waiting out the rate limits:
+    /// until the next minute once the weight used in this one reaches the
+    /// budget, and as long as the venue asks when it refuses a request.
+    async fn get(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<Value, Box<dyn std::error::Error>> {
+        let url = format!("{}/{}", self.rest_base, path);
+        loop {
+            let response = self
+                .client
+                .get(&url)
+                .query(&[("symbol", self.symbol.as_str())])
+                .query(&[("limit", LIMIT.to_string())])
+                .query(query)
+                .send()
+                .await?;
+            let header = |name: &str| -> Option<u64> {
+                response.headers().get(name)?.to_str().ok()?.parse().ok()
+            };
+            // 418 once the venue bans an address that ignored its 429s.
+            if [StatusCode::TOO_MANY_REQUESTS, StatusCode::IM_A_TEAPOT].contains(&response.status())
+            {
+                let wait = header("retry-after").unwrap_or(60);
+                eprintln!(
+                    "{} refused with {}, waiting {}s",
+                    url,
+                    response.status(),
+                    wait
+                );
+                tokio::time::sleep(Duration::from_secs(wait)).await;
+                continue;
+            }
+            if header("x-mbx-used-weight-1m").is_some_and(|used| used >= self.max_weight) {
+                let now = Utc::now();
+                let wait = 60 - u64::from(now.second()) + 1;
+                eprintln!("request weight budget used, waiting {}s", wait);
+                tokio::time::sleep(Duration::from_secs(wait)).await;
+            }
+            let status = response.status();
+            if !status.is_success() {
+                let body = response.text().await.unwrap_or_default();
+                return Err(
+                    format!("request to {} failed with status {}: {}", url, status, body).into(),
+                );
+            }
+            return Ok(response.json().await?);
+        }
+    }
+}
+
+fn millis(ms: i64) -> Result<DateTime<Utc>, String> {
+    DateTime::from_timestamp_millis(ms).ok_or_else(|| format!("time {}ms out of range", ms))
+}
+
+/// A sink by name, with the events it takes.
+pub type Routed = (String, Filter, Box<dyn Sink>);
+
+/// Where backfilled history goes.
+pub struct Outputs {
+    /// A pack directory and the name of the fixture to write there, with
+    /// the venue its snapshot attributes events to.
+    pub pack: Option<(PathBuf, String, String)>,
+    /// Sinks of a config, and the venue to attribute their events to.
+    pub sinks: Vec<Routed>,
+    pub venue: String,
+}
+
+/// The sinks of the config at `path` named in `names`, or all of them, and
+/// the name of its venue of type `venue_type`, if it has one.
+pub fn sinks(
+    path: &Path,
+    names: &[String],
+    venue_type: &str,
+) -> Result<(Option<String>, Vec<Routed>), Box<dyn std::error::Error>> {
+    let cfg = Config::from_str_with_profile(&fs::read_to_string(path)?, None)
+        .map_err(|e| format!("{}: {}", path.display(), e.message()))?;
+    if let Some(unknown) = names.iter().find(|name| !cfg.sinks.contains_key(*name)) {
+        return Err(format!("{} has no sink {}", path.display(), unknown).into());
+    }
+    let metrics = SinkMetrics::new();
+    let mut sinks = Vec::new();
+    for (name, sink) in &cfg.sinks {
+        if names.is_empty() || names.contains(name) {
+            let built = sinks::build(name, sink, &metrics)?;
+            sinks.push((name.clone(), Filter::from(&sink.route), built));
+        }
+    }
+    let venue = cfg
+        .venues
+        .iter()
+        .find(|venue| venue.venue_type == venue_type)
+        .map(|venue| venue.name.clone());
+    Ok((venue, sinks))
+}
+
+/// Fetch every page of `backfill` and write its payloads as raw frames to
+/// the pack of `outputs`, with their snapshot, and the events `adapter`
+/// decodes from them to its sinks. Returns the payloads and events written.
+pub async fn run(
+    mut backfill: Backfill,
+    adapter: &dyn Adapter,
+    outputs: &mut Outputs,
+) -> Result<(usize, usize), Box<dyn std::error::Error>> {
+    let (mut raw, mut payloads, mut events) = (String::new(), 0, 0);
+    while let Some(page) = backfill.next().await? {
+        for payload in page {
+            let frame = payload.to_string();
+            payloads += 1;
+            if outputs.pack.is_some() {
+                raw.push_str(&frame);
+                raw.push('\n');
+            }
+            if outputs.sinks.is_empty() {
+                continue;
+            }
+            for event in adapter.parse_frame(&outputs.venue, &frame)? {
+                for (name, route, sink) in &mut outputs.sinks {
+                    if route.matches(&event) {
+                        sink.send(&event)
+                            .await
+                            .map_err(|e| format!("sink {}: {}", name, e))?;
+                    }
+                }
+                events += 1;
+            }
+        }
+        eprintln!("{} payloads, up to {}", payloads, backfill.cursor());
+    }
+    for (name, _, sink) in &mut outputs.sinks {
+        sink.flush()
+            .await
+            .map_err(|e| format!("sink {}: {}", name, e))?;
+    }
+    if let Some((dir, name, venue)) = &outputs.pack {
+        let fixture = dir.join(format!("{}.jsonl", name));
+        fs::create_dir_all(dir)?;
+        fs::write(
+            golden::snapshot_path(&fixture),
+            golden::render(adapter, venue, &raw)?,
+        )?;
+        fs::write(&fixture, &raw)?;
+    }
+    Ok((payloads, events))
+}
@@ -0,0 +1,123 @@
+//! `devtools bench`: how fast the adapter decodes a golden pack and the
+//! pipeline stages process its events, and how much they allocate.
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use agents::Adapter;
+use ingest_core::{config::PipelineConfig, event::NormalizedEvent};
+use pipeline::{Canonicalize, ClockSkew, PipelineMetrics, Stage};
+
+use crate::alloc;
+
+/// Time and allocations spent in one phase, per event.
+#[derive(Default)]
+struct Phase {
+    latencies: Vec<Duration>,
+    allocations: u64,
+}
+
+impl Phase {
+    /// `normalize: 812345 events/s, p99 2.1µs, 4.0 allocations/event`.
+    fn report(&mut self, name: &str) {
+        let events = self.latencies.len().max(1);
+        let total: Duration = self.latencies.iter().sum();
+        self.latencies.sort();
+        let p99 = self
+            .latencies
+            .get((events * 99).div_ceil(100).saturating_sub(1))
+            .copied()
+            .unwrap_or_default();
+        println!(
+            "{}: {:.0} events/s, p99 {:?}, {:.1} allocations/event",
+            name,
+            events as f64 / total.as_secs_f64().max(f64::EPSILON),
+            p99,
+            self.allocations as f64 / events as f64
+        );
+    }
+}
+
+/// Decode every frame of `fixtures`, each with the venue its pack is named
+/// after, with `adapter` and run the events through the pipeline stages the
+/// engine runs by default, `iterations` times after one warm-up, then print
+/// the throughput, p99 latency and allocations per event of both.
+pub fn bench(
+    fixtures: &[(PathBuf, String)],
+    adapter: &dyn Adapter,
+    iterations: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut frames = Vec::new();
+    for (fixture, venue) in fixtures {
+        for frame in fs::read_to_string(fixture)?.lines() {
+            if !frame.trim().is_empty() {
+                frames.push((venue.as_str(), frame.to_string()));
+            }
+        }
+    }
+    let cfg = PipelineConfig::default();
+    let mut stages: Vec<Box<dyn Stage>> = vec![
+        Box::new(Canonicalize),
+        Box::new(ClockSkew::new(
+            cfg.clock_skew,
+            PipelineMetrics::new().clock_skew_ms,
+        )),
+    ];
+    let mut events = 0;
+    for (venue, frame) in &frames {
+        for event in adapter.parse_frame(venue, frame)? {
+            process(&mut stages, event);
+            events += 1;
+        }
+    }
+    println!(
+        "bench: {} frames, {} events, {} iterations",
+        frames.len(),
+        events,
+        iterations
+    );
+
+    let mut normalize = Phase {
+        latencies: Vec::with_capacity(events * iterations),
+        ..Default::default()
+    };
+    let mut pipeline = Phase {
+        latencies: Vec::with_capacity(events * iterations),
+        ..Default::default()
+    };
+    for _ in 0..iterations {
+        for (venue, frame) in &frames {
+            let (allocations, start) = (alloc::allocations(), Instant::now());
+            let decoded = adapter.parse_frame(venue, frame)?;
+            let elapsed = start.elapsed();
+            normalize.allocations += alloc::allocations() - allocations;
+            // A frame's decoding is shared by its events.
+            let each = elapsed / decoded.len().max(1) as u32;
+            normalize
+                .latencies
+                .extend(std::iter::repeat_n(each, decoded.len()));
+            for event in decoded {
+                let (allocations, start) = (alloc::allocations(), Instant::now());
+                process(&mut stages, event);
+                pipeline.latencies.push(start.elapsed());
+                pipeline.allocations += alloc::allocations() - allocations;
+            }
+        }
+    }
+    normalize.report("normalize");
+    pipeline.report("pipeline");
+    Ok(())
+}
+
+/// Run `event` through `stages` in order.
+fn process(stages: &mut [Box<dyn Stage>], event: NormalizedEvent) -> Vec<NormalizedEvent> {
+    let mut events = vec![event];
+    for stage in stages.iter_mut() {
+        events = events
+            .into_iter()
+            .flat_map(|event| stage.process(event))
+            .collect();
+    }
+    events
+}
@@ -0,0 +1,276 @@
+//! `devtools compare-venues`: align the trades and quotes of one symbol
+//! across the packs of several venues and measure how far their prices
+//! deviate, to catch adapters that scale prices or invert sides.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use agents::Adapter;
+use bigdecimal::ToPrimitive;
+use chrono::{DateTime, Utc};
+use ingest_core::event::{Quote, Side};
+
+/// Quote currencies that track the US dollar, compared as `USD`.
+const DOLLARS: &[&str] = &["USDT", "USDC", "BUSD", "FDUSD"];
+
+/// Fewest trades with a price tick needed to judge their sides.
+const MIN_TICKS: usize = 20;
+
+/// Share of trades agreeing with their price ticks below which sides look
+/// inverted. Aggressive buys lift the price more often than not, so real
+/// feeds agree well above half the time.
+const INVERTED_BELOW: f64 = 0.4;
+
+/// The fixtures of one venue, each with the venue its events are
+/// attributed to, and the adapter decoding them.
+pub struct Pack {
+    pub label: String,
+    pub fixtures: Vec<(PathBuf, String)>,
+    pub adapter: Arc<dyn Adapter>,
+}
+
+/// `BTC-USD`, `btc/usd` and `BTCUSDT` alike as `BTCUSD`: the symbol without
+/// separators, with a dollar stablecoin quote as `USD`.
+fn symbol_key(symbol: &str) -> String {
+    let key: String = symbol
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect::<String>()
+        .to_uppercase();
+    for dollar in DOLLARS {
+        if let Some(base) = key.strip_suffix(dollar) {
+            if !base.is_empty() {
+                return format!("{}USD", base);
+            }
+        }
+    }
+    key
+}
+
+/// The trades and quotes of the symbol in one pack, oldest first.
+#[derive(Default)]
+struct Prices {
+    trades: Vec<(DateTime<Utc>, f64, Side)>,
+    quotes: Vec<(DateTime<Utc>, Quote)>,
+}
+
+impl Prices {
+    fn load(pack: &Pack, key: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut prices = Self::default();
+        for (fixture, venue) in &pack.fixtures {
+            for frame in fs::read_to_string(fixture)?.lines() {
+                if frame.trim().is_empty() {
+                    continue;
+                }
+                for event in pack.adapter.parse_frame(venue, frame)? {
+                    if symbol_key(&event.symbol) != key {
+                        continue;
+                    }
+                    if let Some(trade) = event.trade() {
+                        let price = trade.price.to_f64().unwrap_or(f64::NAN);
+                        prices.trades.push((event.timestamp, price, trade.side));
+                    } else if let Some(quote) = event.quote() {
+                        prices.quotes.push((event.timestamp, quote));
+                    }
+                }
+            }
+        }
+        prices.trades.sort_by_key(|trade| trade.0);
+        prices.quotes.sort_by_key(|quote| quote.0);
+        Ok(prices)
+    }
+
+    /// Mid prices of the quotes.
+    fn mids(&self) -> Vec<(DateTime<Utc>, f64)> {
+        self.quotes
+            .iter()
+            .map(|(at, quote)| {
+                let mid = (&quote.bid_price + &quote.ask_price).half();
+                (*at, mid.to_f64().unwrap_or(f64::NAN))
+            })
+            .collect()
+    }
+
+    /// Quotes whose bid is above their ask.
+    fn crossed(&self) -> usize {
+        self.quotes
+            .iter()
+            .filter(|(_, quote)| quote.bid_price > quote.ask_price)
+            .count()
+    }
+
+    /// Of the trades that moved the price, how many went the way their
+    /// side says, buys up and sells down, and how many moved it at all.
+    fn tick_agreement(&self) -> (usize, usize) {
+        let (mut agree, mut ticks) = (0, 0);
+        let mut last = None;
+        for (_, price, side) in &self.trades {
+            if let Some(last) = last.filter(|last| last != price) {
+                ticks += 1;
+                let up = *price > last;
+                if up == matches!(side, Side::Buy) {
+                    agree += 1;
+                }
+            }
+            last = Some(*price);
+        }
+        (agree, ticks)
+    }
+}
+
+/// The price of `series` nearest to `at`, if one is within `tolerance`.
+fn nearest(series: &[(DateTime<Utc>, f64)], at: DateTime<Utc>, tolerance: Duration) -> Option<f64> {
+    let after = series.partition_point(|(time, _)| *time < at);
+    let candidates = [after.checked_sub(1), Some(after)];
+    let (time, price) = candidates
+        .into_iter()
+        .flatten()
+        .filter_map(|i| series.get(i))
+        .min_by_key(|(time, _)| (*time - at).abs())?;
+    let gap = (*time - at).abs().to_std().ok()?;
+    (gap <= tolerance).then_some(*price)
+}
+
+/// Deviations of one series from the reference's, in basis points.
+struct Deviation {
+    bps: Vec<f64>,
+    /// Ratios of the prices, for telling a scaling apart from noise.
+    ratios: Vec<f64>,
+}
+
+impl Deviation {
+    fn between(
+        series: &[(DateTime<Utc>, f64)],
+        reference: &[(DateTime<Utc>, f64)],
+        tolerance: Duration,
+    ) -> Self {
+        let (mut bps, mut ratios) = (Vec::new(), Vec::new());
+        for (at, price) in series {
+            let Some(reference) = nearest(reference, *at, tolerance) else {
+                continue;
+            };
+            if reference > 0.0 && *price > 0.0 {
+                bps.push((price - reference) / reference * 10_000.0);
+                ratios.push(price / reference);
+            }
+        }
+        bps.sort_by(f64::total_cmp);
+        ratios.sort_by(f64::total_cmp);
+        Self { bps, ratios }
+    }
+
+    fn quantile(sorted: &[f64], q: f64) -> f64 {
+        let rank = (sorted.len() as f64 * q).ceil() as usize;
+        sorted[rank.saturating_sub(1)]
+    }
+
+    /// The median deviation, or `None` without aligned prices.
+    fn median(&self) -> Option<f64> {
+        (!self.bps.is_empty()).then(|| Self::quantile(&self.bps, 0.5))
+    }
+
+    /// `trades: 120 aligned, mean 1.2bps, p50 ..., max |40.1|bps`, and a
+    /// warning when the prices differ by a power of ten.
+    fn report(&self, name: &str) {
+        let Some(median) = self.median() else {
+            println!("  {}: none within the tolerance", name);
+            return;
+        };
+        let mean = self.bps.iter().sum::<f64>() / self.bps.len() as f64;
+        let max = self.bps.iter().map(|bps| bps.abs()).fold(0.0, f64::max);
+        println!(
+            "  {}: {} aligned, mean {:.2}bps, p1 {:.2}bps, p50 {:.2}bps, p99 {:.2}bps, max |{:.2}|bps",
+            name,
+            self.bps.len(),
+            mean,
+            Self::quantile(&self.bps, 0.01),
+            median,
+            Self::quantile(&self.bps, 0.99),
+            max
+        );
+        let exponent = Self::quantile(&self.ratios, 0.5).log10().round();
+        if exponent != 0.0 {
+            println!("  {}: prices look scaled by 1e{}", name, exponent);
+        }
+    }
+}
+
+/// Decode every pack, keep the trades and quotes of `symbol`, and compare
+/// the prices of each pack to those of the first at about the same time,
+/// within `tolerance`. Prints the deviation of trade and mid prices, the
+/// quotes crossed and how often trades' sides agree with their price ticks,
+/// then fails if a median deviation exceeds `max_deviation` basis points
+/// or the sides of a pack look inverted.
+pub fn compare(
+    packs: &[Pack],
+    symbol: &str,
+    tolerance: Duration,
+    max_deviation: f64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let key = symbol_key(symbol);
+    let mut loaded = Vec::with_capacity(packs.len());
+    for pack in packs {
+        loaded.push(Prices::load(pack, &key)?);
+    }
+    let reference = &loaded[0];
+    let reference_trades: Vec<_> = reference.trades.iter().map(|t| (t.0, t.1)).collect();
+    let reference_mids = reference.mids();
+
+    let mut problems = Vec::new();
+    for (i, (pack, prices)) in packs.iter().zip(&loaded).enumerate() {
+        println!(
+            "{}: {} trades, {} quotes of {}",
+            pack.label,
+            prices.trades.len(),
+            prices.quotes.len(),
+            key
+        );
+        if i > 0 {
+            let trades: Vec<_> = prices.trades.iter().map(|t| (t.0, t.1)).collect();
+            let deviations = [
+                (
+                    "trades",
+                    Deviation::between(&trades, &reference_trades, tolerance),
+                ),
+                (
+                    "mids",
+                    Deviation::between(&prices.mids(), &reference_mids, tolerance),
+                ),
+            ];
+            for (name, deviation) in &deviations {
+                deviation.report(name);
+                if let Some(median) = deviation.median().filter(|m| m.abs() > max_deviation) {
+                    problems.push(format!(
+                        "{} {} deviate by {:.2}bps from {}",
+                        pack.label, name, median, packs[0].label
+                    ));
+                }
+            }
+        }
+        let crossed = prices.crossed();
+        if crossed > 0 {
+            println!("  {} quotes crossed, bid above ask", crossed);
+            problems.push(format!("{} has {} crossed quotes", pack.label, crossed));
+        }
+        let (agree, ticks) = prices.tick_agreement();
+        if ticks > 0 {
+            println!(
+                "  sides agree with price ticks for {:.1}% of {} trades",
+                agree as f64 * 100.0 / ticks as f64,
+                ticks
+            );
+        }
+        if ticks >= MIN_TICKS && (agree as f64) < ticks as f64 * INVERTED_BELOW {
+            problems.push(format!("{} trade sides look inverted", pack.label));
+        }
+    }
+    if problems.is_empty() {
+        return Ok(());
+    }
+    for problem in &problems {
+        eprintln!("{}", problem);
+    }
+    Err("the venues disagree".into())
+}
@@ -0,0 +1,210 @@
+//! `devtools convert`: translate captured events between JSON lines,
+//! Parquet, the sinks' binary encodings and postcard, to exchange them with
+//! analytics tooling.
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+use agents::Adapter;
+use chrono::{DateTime, Utc};
+use ingest_core::{
+    config::Encoding,
+    event::{EventKind, NormalizedEvent},
+};
+use serde::{Deserialize, Serialize};
+
+/// A file format of events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// One normalized event per line; raw frames too when read.
+    Jsonl,
+    /// A standalone file with a row per event.
+    Parquet,
+    /// Protobuf `EventRecord`s, each preceded by its length as a varint.
+    Proto,
+    /// Avro datums, framed as `Proto` is.
+    Avro,
+    /// Postcard [`PostcardEvent`]s, framed as `Proto` is.
+    Postcard,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "jsonl" | "json" | "ndjson" => Ok(Format::Jsonl),
+            "parquet" => Ok(Format::Parquet),
+            "proto" | "pb" | "binpb" => Ok(Format::Proto),
+            "avro" => Ok(Format::Avro),
+            "postcard" | "pc" => Ok(Format::Postcard),
+            _ => Err(format!(
+                "unknown format {:?}, expected jsonl, parquet, proto, avro or postcard",
+                text
+            )),
+        }
+    }
+}
+
+impl Format {
+    /// The format `path` is in by its extension.
+    pub fn of(path: &Path) -> Result<Self, String> {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .ok_or_else(|| format!("{} has no extension to tell its format", path.display()))?
+            .parse()
+    }
+}
+
+/// Encodes an event into one frame of a framed format.
+type Encode = fn(&NormalizedEvent) -> Result<Vec<u8>, Box<dyn std::error::Error>>;
+/// Decodes one frame of a framed format.
+type Decode = fn(&[u8]) -> Result<NormalizedEvent, Box<dyn std::error::Error>>;
+
+/// An event as the postcard format holds it. Postcard does not describe its
+/// data, so it cannot hold the payload's JSON value as such; the payload is
+/// kept as JSON text instead.
+#[derive(Serialize, Deserialize)]
+struct PostcardEvent {
+    venue: String,
+    symbol: String,
+    timestamp: DateTime<Utc>,
+    received_at: Option<DateTime<Utc>>,
+    kind: EventKind,
+    payload: String,
+}
+
+fn to_postcard(event: &NormalizedEvent) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    Ok(postcard::to_stdvec(&PostcardEvent {
+        venue: event.venue.clone(),
+        symbol: event.symbol.clone(),
+        timestamp: event.timestamp,
+        received_at: event.received_at,
+        kind: event.kind,
+        payload: event.payload.to_string(),
+    })?)
+}
+
+fn from_postcard(frame: &[u8]) -> Result<NormalizedEvent, Box<dyn std::error::Error>> {
+    let event: PostcardEvent = postcard::from_bytes(frame)?;
+    Ok(NormalizedEvent {
+        venue: event.venue,
+        symbol: event.symbol,
+        timestamp: event.timestamp,
+        received_at: event.received_at,
+        kind: event.kind,
+        payload: serde_json::from_str(&event.payload)?,
+    })
+}
+
+fn to_proto(event: &NormalizedEvent) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    Ok(sinks::codec::encode(event, Encoding::Proto)?)
+}
+
+fn from_proto(frame: &[u8]) -> Result<NormalizedEvent, Box<dyn std::error::Error>> {
+    Ok(sinks::codec::decode(frame, Encoding::Proto)?)
+}
+
+fn to_avro(event: &NormalizedEvent) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    Ok(sinks::codec::encode(event, Encoding::Avro)?)
+}
+
+fn from_avro(frame: &[u8]) -> Result<NormalizedEvent, Box<dyn std::error::Error>> {
+    Ok(sinks::codec::decode(frame, Encoding::Avro)?)
+}
+
+/// Read the events of `input` in format `from`, decoding raw frames of JSON
+/// lines with `adapter` as events of `venue`, write them to `output` in
+/// format `to`, and return how many there were.
+pub fn convert(
+    input: &Path,
+    from: Format,
+    output: &Path,
+    to: Format,
+    adapter: &dyn Adapter,
+    venue: &str,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let events = match from {
+        Format::Jsonl => crate::replay::load(input, adapter, venue)?,
+        Format::Parquet => read_parquet(input)?,
+        Format::Proto => read_framed(input, from_proto)?,
+        Format::Avro => read_framed(input, from_avro)?,
+        Format::Postcard => read_framed(input, from_postcard)?,
+    };
+    match to {
+        Format::Jsonl => {
+            let mut out = std::io::BufWriter::new(fs::File::create(output)?);
+            for event in &events {
+                writeln!(out, "{}", serde_json::to_string(event)?)?;
+            }
+            out.flush()?;
+        }
+        Format::Parquet => write_parquet(output, &events)?,
+        Format::Proto => write_framed(output, &events, to_proto)?,
+        Format::Avro => write_framed(output, &events, to_avro)?,
+        Format::Postcard => write_framed(output, &events, to_postcard)?,
+    }
+    Ok(events.len())
+}
+
+fn read_framed(
+    path: &Path,
+    decode: Decode,
+) -> Result<Vec<NormalizedEvent>, Box<dyn std::error::Error>> {
+    let data = fs::read(path)?;
+    let mut rest = data.as_slice();
+    let mut events = Vec::new();
+    while !rest.is_empty() {
+        let len = prost::encoding::decode_varint(&mut rest)? as usize;
+        if len > rest.len() {
+            return Err(format!("{} ends within an event", path.display()).into());
+        }
+        let (frame, tail) = rest.split_at(len);
+        events.push(decode(frame)?);
+        rest = tail;
+    }
+    Ok(events)
+}
+
+fn write_framed(
+    path: &Path,
+    events: &[NormalizedEvent],
+    encode: Encode,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut data = Vec::new();
+    for event in events {
+        let frame = encode(event)?;
+        prost::encoding::encode_varint(frame.len() as u64, &mut data);
+        data.extend_from_slice(&frame);
+    }
+    fs::write(path, data)?;
+    Ok(())
+}
+
+#[cfg(feature = "parquet")]
+fn read_parquet(path: &Path) -> Result<Vec<NormalizedEvent>, Box<dyn std::error::Error>> {
+    Ok(sinks::parquet::read_events(path)?)
+}
+
+#[cfg(feature = "parquet")]
+fn write_parquet(
+    path: &Path,
+    events: &[NormalizedEvent],
+) -> Result<(), Box<dyn std::error::Error>> {
+    Ok(sinks::parquet::write_events(path, events)?)
+}
+
+#[cfg(not(feature = "parquet"))]
+fn read_parquet(_path: &Path) -> Result<Vec<NormalizedEvent>, Box<dyn std::error::Error>> {
+    Err("parquet requires devtools built with the `parquet` feature".into())
+}
+
+#[cfg(not(feature = "parquet"))]
+fn write_parquet(
+    _path: &Path,
+    _events: &[NormalizedEvent],
+) -> Result<(), Box<dyn std::error::Error>> {
+    Err("parquet requires devtools built with the `parquet` feature".into())
+}
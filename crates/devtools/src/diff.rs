@@ -0,0 +1,164 @@
+//! `devtools diff`: run a golden pack through the current normalizer and a
+//! baseline and report how their events differ, field by field.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use agents::{golden, Adapter};
+use serde_json::Value;
+
+/// What the current events are compared with.
+pub enum Baseline {
+    /// A `devtools` binary of the baseline, whose `replay` decodes the pack.
+    Binary(PathBuf),
+    /// A snapshot, or a directory holding the pack's snapshots by name.
+    Snapshot(PathBuf),
+}
+
+impl Baseline {
+    /// A directory or `.jsonl` file is a snapshot, anything else a binary.
+    pub fn new(path: PathBuf) -> Self {
+        if path.is_dir() || path.extension().is_some_and(|ext| ext == "jsonl") {
+            Baseline::Snapshot(path)
+        } else {
+            Baseline::Binary(path)
+        }
+    }
+
+    /// The baseline's events of `fixture`, one JSON document per line.
+    fn render(
+        &self,
+        fixture: &Path,
+        venue_type: &str,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        match self {
+            Baseline::Binary(binary) => {
+                let output = Command::new(binary)
+                    .arg("replay")
+                    .arg(fixture)
+                    .args(["--venue", venue_type, "--speed", "max"])
+                    .output()?;
+                if !output.status.success() {
+                    return Err(format!(
+                        "{} failed on {}: {}",
+                        binary.display(),
+                        fixture.display(),
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    )
+                    .into());
+                }
+                Ok(String::from_utf8(output.stdout)?)
+            }
+            Baseline::Snapshot(dir) if dir.is_dir() => {
+                let snapshot = golden::snapshot_path(fixture);
+                Ok(fs::read_to_string(
+                    dir.join(snapshot.file_name().unwrap_or_default()),
+                )?)
+            }
+            Baseline::Snapshot(snapshot) => Ok(fs::read_to_string(snapshot)?),
+        }
+    }
+}
+
+/// Decode `fixture` with `adapter` as events of `venue` and print how the
+/// baseline's events differ. Returns how many events differ.
+pub fn diff(
+    fixture: &Path,
+    adapter: &dyn Adapter,
+    venue_type: &str,
+    venue: &str,
+    baseline: &Baseline,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let current = lines(&golden::render(
+        adapter,
+        venue,
+        &fs::read_to_string(fixture)?,
+    )?)?;
+    let before = lines(&baseline.render(fixture, venue_type)?)?;
+    let mut differing = 0;
+    for n in 0..before.len().max(current.len()) {
+        let report = match (before.get(n), current.get(n)) {
+            (Some(before), Some(current)) => {
+                let mut fields = Vec::new();
+                differences("", before, current, &mut fields);
+                if fields.is_empty() {
+                    continue;
+                }
+                format!("{}:\n    {}", label(n, current), fields.join("\n    "))
+            }
+            (Some(before), None) => format!("{}: only in the baseline", label(n, before)),
+            (None, Some(current)) => format!("{}: only in the current output", label(n, current)),
+            (None, None) => unreachable!("within both lengths"),
+        };
+        println!("  {}", report);
+        differing += 1;
+    }
+    println!(
+        "{}: {} events, {} differ",
+        fixture.display(),
+        current.len(),
+        differing
+    );
+    Ok(differing)
+}
+
+/// Each non-empty line of `text` as JSON.
+fn lines(text: &str) -> Result<Vec<Value>, serde_json::Error> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(serde_json::from_str)
+        .collect()
+}
+
+/// `event 3 (BTCUSDT trade)`, counting from 1.
+fn label(n: usize, event: &Value) -> String {
+    let field = |name: &str| {
+        event
+            .get(name)
+            .and_then(Value::as_str)
+            .unwrap_or("?")
+            .to_string()
+    };
+    format!("event {} ({} {})", n + 1, field("symbol"), field("kind"))
+}
+
+/// Push `path: before -> after` for every field under `path` whose value
+/// differs, a missing field counting as null.
+fn differences(path: &str, before: &Value, after: &Value, out: &mut Vec<String>) {
+    let field = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", path, key)
+        }
+    };
+    match (before, after) {
+        (Value::Object(before), Value::Object(after)) => {
+            let keys: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+            for key in keys {
+                differences(
+                    &field(key),
+                    before.get(key).unwrap_or(&Value::Null),
+                    after.get(key).unwrap_or(&Value::Null),
+                    out,
+                );
+            }
+        }
+        (Value::Array(before), Value::Array(after)) => {
+            for i in 0..before.len().max(after.len()) {
+                differences(
+                    &format!("{}[{}]", path, i),
+                    before.get(i).unwrap_or(&Value::Null),
+                    after.get(i).unwrap_or(&Value::Null),
+                    out,
+                );
+            }
+        }
+        (before, after) if before != after => {
+            out.push(format!("{}: {} -> {}", path, before, after));
+        }
+        _ => {}
+    }
+}
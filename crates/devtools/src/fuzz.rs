@@ -0,0 +1,271 @@
+//! `devtools fuzz`: throw mutated venue payloads at the adapters' parsers
+//! and the pipeline normalizer, which consume untrusted network input, and
+//! report the inputs that panic or allocate without bound.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use agents::Adapter;
+use pipeline::{Canonicalize, Stage};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde_json::Value;
+
+use crate::alloc;
+
+/// Longest input to build; mutations that would grow one further are
+/// skipped.
+const MAX_INPUT: usize = 1 << 20;
+
+/// Fragments that tend to upset parsers.
+const TOKENS: &[&str] = &[
+    "\"",
+    "\\",
+    "{",
+    "}",
+    "[",
+    "]",
+    ":",
+    ",",
+    "null",
+    "true",
+    "-",
+    "-1",
+    "0",
+    "1e308",
+    "-1e309",
+    "NaN",
+    "18446744073709551616",
+    "\\u0000",
+    "\\ud800",
+    "é",
+    "🦀",
+    "\u{0}",
+    " ",
+];
+
+/// A value to put in place of a field's.
+fn value(rng: &mut StdRng) -> Value {
+    match rng.gen_range(0..10) {
+        0 => Value::Null,
+        1 => Value::Bool(rng.gen()),
+        2 => Value::from(rng.gen::<i64>()),
+        3 => Value::from(u64::MAX),
+        4 => Value::from(f64::MAX),
+        5 => Value::from(""),
+        6 => Value::from("9".repeat(rng.gen_range(1..10_000))),
+        7 => Value::from(rng.gen::<f64>().to_string()),
+        8 => Value::Array(Vec::new()),
+        _ => Value::Object(Default::default()),
+    }
+}
+
+/// Adapters to fuzz, with their venue types.
+pub type Adapters = Vec<(String, Arc<dyn Adapter>)>;
+
+pub struct Options {
+    pub iterations: usize,
+    pub seed: u64,
+    /// Most bytes one input may allocate.
+    pub max_memory: usize,
+    /// Where to save the inputs that fail.
+    pub crashes: PathBuf,
+}
+
+/// Mutate the frames of `seeds`, each with the venue its pack is named
+/// after, `iterations` times and feed every input to each of `adapters` and
+/// to the pipeline normalizer. Saves each input failing in a new way to
+/// `crashes` and returns how many there were.
+pub fn fuzz(
+    seeds: &[(String, String)],
+    adapters: &Adapters,
+    options: &Options,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    if seeds.is_empty() {
+        return Err("no frames to start from".into());
+    }
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let mut failures = BTreeSet::new();
+    // Panics are reported with the input that caused them instead.
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    for iteration in 0..options.iterations {
+        let (venue, frame) = seeds.choose(&mut rng).expect("seeds are not empty");
+        let mut input = frame.clone();
+        for _ in 0..rng.gen_range(1..=4) {
+            input = mutate(&mut rng, &input, seeds);
+        }
+        for (venue_type, adapter) in adapters {
+            let Some(failure) = check(&**adapter, venue, &input, options.max_memory) else {
+                continue;
+            };
+            if failures.insert(format!("{}: {}", venue_type, failure)) {
+                fs::create_dir_all(&options.crashes)?;
+                let path = options
+                    .crashes
+                    .join(format!("{}-{}.txt", venue_type, iteration));
+                fs::write(&path, &input)?;
+                eprintln!("{} {}: {}", venue_type, failure, path.display());
+            }
+        }
+    }
+    panic::set_hook(hook);
+    println!(
+        "fuzz: {} inputs from {} frames, seed {}, {} failures",
+        options.iterations,
+        seeds.len(),
+        options.seed,
+        failures.len()
+    );
+    Ok(failures.len())
+}
+
+/// The frames of `fixtures`, each with the venue its pack is named after.
+pub fn seeds(fixtures: &[(PathBuf, String)]) -> Result<Vec<(String, String)>, std::io::Error> {
+    let mut seeds = Vec::new();
+    for (fixture, venue) in fixtures {
+        for frame in fs::read_to_string(fixture)?.lines() {
+            if !frame.trim().is_empty() {
+                seeds.push((venue.clone(), frame.to_string()));
+            }
+        }
+    }
+    Ok(seeds)
+}
+
+/// How parsing and normalizing `input` fails, if it does: a panic, or
+/// allocating more than `max_memory` bytes at once.
+fn check(adapter: &dyn Adapter, venue: &str, input: &str, max_memory: usize) -> Option<String> {
+    let before = alloc::reset_peak();
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut events = adapter.parse_frame(venue, input).unwrap_or_default();
+        events.extend(pipeline::normalize(venue, input, input));
+        events
+            .into_iter()
+            .flat_map(|event| Canonicalize.process(event))
+            .count()
+    }));
+    let peak = alloc::peak(before);
+    match outcome {
+        Err(panic) => Some(format!(
+            "panicked: {}",
+            panic
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_default()
+        )),
+        Ok(_) if peak > max_memory => Some(format!("allocated more than {} bytes", max_memory)),
+        Ok(_) => None,
+    }
+}
+
+/// `input` mutated once: bytes flipped, inserted, deleted or repeated, part
+/// of another seed spliced in, or a field of the JSON replaced.
+fn mutate(rng: &mut StdRng, input: &str, seeds: &[(String, String)]) -> String {
+    let mut bytes = input.as_bytes().to_vec();
+    let at = |rng: &mut StdRng, bytes: &[u8]| rng.gen_range(0..=bytes.len());
+    match rng.gen_range(0..7) {
+        0 if !bytes.is_empty() => {
+            let i = rng.gen_range(0..bytes.len());
+            bytes[i] ^= 1 << rng.gen_range(0..8);
+        }
+        1 => {
+            let i = at(rng, &bytes);
+            let token = TOKENS.choose(rng).expect("tokens are not empty");
+            bytes.splice(i..i, token.bytes());
+        }
+        2 => {
+            let (a, b) = (at(rng, &bytes), at(rng, &bytes));
+            bytes.drain(a.min(b)..a.max(b));
+        }
+        3 => {
+            let (a, b) = (at(rng, &bytes), at(rng, &bytes));
+            let part = bytes[a.min(b)..a.max(b)].to_vec();
+            for _ in 0..rng.gen_range(1..=8) {
+                if bytes.len() + part.len() > MAX_INPUT {
+                    break;
+                }
+                let i = at(rng, &bytes);
+                bytes.splice(i..i, part.iter().copied());
+            }
+        }
+        4 => {
+            let (_, other) = seeds.choose(rng).expect("seeds are not empty");
+            let other = other.as_bytes();
+            let (a, b) = (at(rng, &bytes), at(rng, other));
+            bytes.truncate(a);
+            bytes.extend_from_slice(&other[b..]);
+        }
+        5 | 6 => {
+            if let Ok(mut json) = serde_json::from_str::<Value>(input) {
+                if let Some(field) = field(rng, &mut json) {
+                    *field = value(rng);
+                }
+                if let Ok(text) = serde_json::to_string(&json) {
+                    bytes = text.into_bytes();
+                }
+            }
+        }
+        _ => {}
+    }
+    if bytes.len() > MAX_INPUT {
+        return input.to_string();
+    }
+    // Venues send text frames, which are UTF-8.
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// A field at random somewhere in `value`.
+fn field<'a>(rng: &mut StdRng, value: &'a mut Value) -> Option<&'a mut Value> {
+    let mut children: Vec<&'a mut Value> = match value {
+        Value::Object(map) => map.values_mut().collect(),
+        Value::Array(items) => items.iter_mut().collect(),
+        _ => return None,
+    };
+    if children.is_empty() {
+        return None;
+    }
+    let child = children.swap_remove(rng.gen_range(0..children.len()));
+    if rng.gen_bool(0.5) && matches!(child, Value::Object(_) | Value::Array(_)) {
+        field(rng, child)
+    } else {
+        Some(child)
+    }
+}
+
+/// The adapters to fuzz: `venue_type`'s, or every builtin one.
+pub fn adapters(venue_type: Option<&str>) -> Result<Adapters, String> {
+    let registry = agents::AdapterRegistry::builtin();
+    match venue_type {
+        Some(venue_type) => Ok(vec![(venue_type.to_string(), crate::adapter(venue_type)?)]),
+        None => Ok(registry
+            .types()
+            .filter_map(|venue_type| {
+                registry
+                    .get(venue_type)
+                    .map(|adapter| (venue_type.to_string(), adapter))
+            })
+            .collect()),
+    }
+}
+
+/// The fixtures of every pack in `golden`, each with its pack's venue.
+pub fn packs(golden: &Path) -> Result<Vec<(PathBuf, String)>, Box<dyn std::error::Error>> {
+    let mut fixtures = Vec::new();
+    for entry in fs::read_dir(golden)? {
+        let dir = entry?.path();
+        if dir.is_dir() {
+            let venue = crate::pack_venue(&dir, "unknown");
+            for fixture in agents::golden::fixtures(&dir)? {
+                fixtures.push((fixture, venue.clone()));
+            }
+        }
+    }
+    fixtures.sort();
+    Ok(fixtures)
+}
@@ -0,0 +1,170 @@
+//! `devtools inspect`: what a golden pack holds, to tell whether it is
+//! representative test data.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::path::PathBuf;
+
+use agents::Adapter;
+use chrono::{DateTime, SecondsFormat, Utc};
+use ingest_core::event::NormalizedEvent;
+
+/// Events of one symbol and kind.
+#[derive(Default)]
+struct Series {
+    events: usize,
+    first: Option<DateTime<Utc>>,
+    last: Option<DateTime<Utc>>,
+}
+
+impl Series {
+    fn add(&mut self, at: DateTime<Utc>) {
+        self.events += 1;
+        self.first = Some(self.first.map_or(at, |first| first.min(at)));
+        self.last = Some(self.last.map_or(at, |last| last.max(at)));
+    }
+
+    /// `1.23s`, from the first event to the last.
+    fn span(&self) -> f64 {
+        match (self.first, self.last) {
+            (Some(first), Some(last)) => (last - first).num_milliseconds() as f64 / 1000.0,
+            _ => 0.0,
+        }
+    }
+
+    /// Events per second over the span, when it has one.
+    fn rate(&self) -> String {
+        let span = self.span();
+        if span > 0.0 {
+            format!("{:.1}/s", self.events as f64 / span)
+        } else {
+            "-".to_string()
+        }
+    }
+}
+
+/// Decode every frame of `fixtures`, each with the venue its pack is named
+/// after, with `adapter` and print the events per symbol and kind with their
+/// rates, the time range, the gaps and duplicates among trade ids, duplicate
+/// events, the longest pause between events and the frame sizes.
+pub fn inspect(
+    fixtures: &[(PathBuf, String)],
+    adapter: &dyn Adapter,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut sizes = Vec::new();
+    let mut all = Series::default();
+    let mut series: BTreeMap<(String, &'static str), Series> = BTreeMap::new();
+    let mut trade_ids: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+    let mut seen = HashSet::new();
+    let mut duplicates = 0;
+    let mut times = Vec::new();
+    for (fixture, venue) in fixtures {
+        for frame in fs::read_to_string(fixture)?.lines() {
+            if frame.trim().is_empty() {
+                continue;
+            }
+            sizes.push(frame.len());
+            for event in adapter.parse_frame(venue, frame)? {
+                all.add(event.timestamp);
+                times.push(event.timestamp);
+                series
+                    .entry((event.symbol.clone(), event.kind.as_str()))
+                    .or_default()
+                    .add(event.timestamp);
+                if let Some(trade) = event.trade() {
+                    trade_ids
+                        .entry(event.symbol.clone())
+                        .or_default()
+                        .push(trade.trade_id);
+                }
+                // The same event received twice is a duplicate too.
+                let event = NormalizedEvent {
+                    received_at: None,
+                    ..event
+                };
+                if !seen.insert(serde_json::to_string(&event)?) {
+                    duplicates += 1;
+                }
+            }
+        }
+    }
+
+    println!(
+        "{} fixtures, {} frames, {} events",
+        fixtures.len(),
+        sizes.len(),
+        all.events
+    );
+    if let (Some(first), Some(last)) = (all.first, all.last) {
+        println!(
+            "time range: {} .. {} ({:.3}s), {}",
+            first.to_rfc3339_opts(SecondsFormat::Millis, true),
+            last.to_rfc3339_opts(SecondsFormat::Millis, true),
+            all.span(),
+            all.rate()
+        );
+    }
+    times.sort();
+    if let Some(pause) = times.windows(2).map(|pair| pair[1] - pair[0]).max() {
+        println!(
+            "longest pause: {:.3}s",
+            pause.num_milliseconds() as f64 / 1000.0
+        );
+    }
+    println!();
+    let width = series
+        .keys()
+        .map(|(symbol, _)| symbol.len())
+        .max()
+        .unwrap_or(0)
+        .max("symbol".len());
+    println!(
+        "{:<width$}  {:<6}  {:>8}  {:>10}  {:>9}",
+        "symbol", "kind", "events", "rate", "span"
+    );
+    for ((symbol, kind), series) in &series {
+        println!(
+            "{:<width$}  {:<6}  {:>8}  {:>10}  {:>8.3}s",
+            symbol,
+            kind,
+            series.events,
+            series.rate(),
+            series.span()
+        );
+    }
+    println!();
+
+    for (symbol, ids) in &trade_ids {
+        let unique: BTreeSet<u64> = ids.iter().copied().collect();
+        let (gaps, missing) = unique
+            .iter()
+            .zip(unique.iter().skip(1))
+            .filter(|(a, b)| **b > **a + 1)
+            .fold((0, 0), |(gaps, missing), (a, b)| {
+                (gaps + 1, missing + b - a - 1)
+            });
+        println!(
+            "{} trade ids: {} gaps ({} missing), {} duplicates",
+            symbol,
+            gaps,
+            missing,
+            ids.len() - unique.len()
+        );
+    }
+    println!("duplicate events: {}", duplicates);
+
+    sizes.sort_unstable();
+    if let (Some(min), Some(max)) = (sizes.first(), sizes.last()) {
+        let quantile = |q: usize| sizes[(sizes.len() * q).div_ceil(100).saturating_sub(1)];
+        println!(
+            "frame bytes: min {}, p50 {}, p90 {}, p99 {}, max {}, mean {:.0}",
+            min,
+            quantile(50),
+            quantile(90),
+            quantile(99),
+            max,
+            sizes.iter().sum::<usize>() as f64 / sizes.len() as f64
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,90 @@
+//! `devtools latency-probe`: how late a live venue's events are when they
+//! are received, and when they reach the bus, to size deployments and
+//! choose where to colocate.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use agents::Adapter;
+use api::EventBus;
+use chrono::Utc;
+use ingest_core::{config::VenueConfig, event::ENGINE_VENUE};
+use tokio::sync::mpsc;
+
+/// Latencies of one hop, in microseconds.
+#[derive(Default)]
+struct Hop {
+    samples: Vec<i64>,
+}
+
+impl Hop {
+    /// `exchange -> receive: 1234 samples, min 2.1ms, p50 ..., max 40.2ms`.
+    fn report(&mut self, name: &str) {
+        self.samples.sort_unstable();
+        let Some((min, max)) = self.samples.first().zip(self.samples.last()) else {
+            println!("{}: no samples", name);
+            return;
+        };
+        let ms = |us: i64| format!("{:.2}ms", us as f64 / 1000.0);
+        let quantile = |q: f64| {
+            let rank = (self.samples.len() as f64 * q).ceil() as usize;
+            ms(self.samples[rank.saturating_sub(1)])
+        };
+        println!(
+            "{}: {} samples, min {}, p50 {}, p90 {}, p99 {}, p99.9 {}, max {}",
+            name,
+            self.samples.len(),
+            ms(*min),
+            quantile(0.5),
+            quantile(0.9),
+            quantile(0.99),
+            quantile(0.999),
+            ms(*max)
+        );
+    }
+}
+
+/// Run `adapter` against the venue of `cfg` for `window`, publishing its
+/// events onto a bus as the engine does, and print the percentiles of the
+/// time from each event's exchange timestamp to its receipt, and from its
+/// receipt to its delivery by the bus.
+pub async fn probe(
+    adapter: Arc<dyn Adapter>,
+    cfg: VenueConfig,
+    window: Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let bus = EventBus::new(65_536);
+    let mut consumer = bus.subscribe();
+    let publisher = bus.publisher();
+    let (tx, mut rx) = mpsc::channel(1024);
+    let connect = tokio::spawn(async move { adapter.connect(cfg, tx).await });
+    tokio::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            publisher.publish(event);
+        }
+    });
+
+    let (mut exchange, mut bus_hop) = (Hop::default(), Hop::default());
+    let deadline = tokio::time::sleep(window);
+    tokio::pin!(deadline);
+    loop {
+        tokio::select! {
+            () = &mut deadline => break,
+            event = consumer.recv() => {
+                let Some(event) = event else { break };
+                let Some(received_at) = event.received_at else { continue };
+                if event.venue == ENGINE_VENUE {
+                    continue;
+                }
+                let now = Utc::now();
+                // Negative when the venue's clock runs ahead of ours.
+                exchange.samples.push((received_at - event.timestamp).num_microseconds().unwrap_or_default());
+                bus_hop.samples.push((now - received_at).num_microseconds().unwrap_or_default());
+            }
+        }
+    }
+    connect.abort();
+    exchange.report("exchange -> receive");
+    bus_hop.report("receive -> bus");
+    Ok(())
+}
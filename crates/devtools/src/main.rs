@@ -1,7 +1,35 @@
+use agents::{Adapter, AdapterRegistry};
+use chrono::{DateTime, Utc};
 use clap::{Parser, Subcommand};
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use pipeline;
+use ingest_core::config::VenueConfig;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+mod alloc;
+mod backfill;
+mod bench;
+mod compare;
+mod convert;
+mod diff;
+mod fuzz;
+mod inspect;
+mod latency;
+mod mock_exchange;
+mod record;
+mod replay;
+mod scaffold;
+mod scrub;
+mod soak;
+mod symbols;
+mod tail;
+mod top;
+mod validate;
+
+/// Counts allocations for `bench`.
+#[global_allocator]
+static ALLOCATOR: alloc::Counting = alloc::Counting;
 
 #[derive(Parser)]
 #[command(name = "devtools")]
@@ -13,28 +41,790 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Scaffold a new adapter from spec
-    Scaffold { spec: String },
-    /// Replay a golden data pack
-    Replay { file: String },
+    /// Generate an adapter module from a spec and register it
+    Scaffold {
+        spec: PathBuf,
+        /// The agents crate to add the module to
+        #[arg(long, default_value = "crates/agents")]
+        agents: PathBuf,
+        /// Directory of the golden packs
+        #[arg(long, default_value = "golden")]
+        golden: PathBuf,
+    },
+    /// Replay a golden data pack at the pace its events happened
+    Replay {
+        file: PathBuf,
+        /// Adapter type decoding raw frames, e.g. binance
+        #[arg(long, default_value = "binance")]
+        venue: String,
+        /// Multiple of the original pace, e.g. 10x, or max for no pauses
+        #[arg(long, default_value = "1x")]
+        speed: replay::Speed,
+        /// Start over at the end, until interrupted
+        #[arg(long = "loop")]
+        looped: bool,
+        /// Publish into the engine whose ops server is at this URL instead
+        /// of printing
+        #[arg(long)]
+        publish: Option<String>,
+        /// Bearer token for the ops server's control routes
+        #[arg(long)]
+        token: Option<String>,
+    },
+    /// Show a live dashboard of a running engine's venues, symbols,
+    /// pipeline and sinks
+    Top {
+        /// The engine's ops server
+        #[arg(long, default_value = "http://127.0.0.1:3000")]
+        url: String,
+        /// Bearer token for the ops server's metrics routes, if protected
+        #[arg(long)]
+        token: Option<String>,
+        /// How often to redraw, e.g. 1s or 500ms
+        #[arg(long, default_value = "1s", value_parser = parse_duration)]
+        interval: Duration,
+        /// How many of the busiest symbols to show
+        #[arg(long, default_value_t = 10)]
+        symbols: usize,
+    },
+    /// Follow the events of a running engine
+    Tail {
+        /// The engine's ops server: http(s) to stream server-sent events,
+        /// ws(s) for the WebSocket endpoint
+        #[arg(long, default_value = "http://127.0.0.1:3000")]
+        url: String,
+        /// Venues to follow, comma separated; all by default
+        #[arg(long, value_delimiter = ',')]
+        venue: Vec<String>,
+        /// Symbols to follow, comma separated, globs allowed; all by default
+        #[arg(long, value_delimiter = ',')]
+        symbol: Vec<String>,
+        /// Kinds to follow, comma separated, e.g. trade,quote; all by default
+        #[arg(long, value_delimiter = ',')]
+        kind: Vec<String>,
+        /// Start with up to this many recent events, over server-sent events
+        #[arg(long)]
+        replay: Option<usize>,
+        /// Print JSON lines instead of one readable line per event
+        #[arg(long)]
+        json: bool,
+        /// Bearer token for the ops server's data routes
+        #[arg(long)]
+        token: Option<String>,
+    },
+    /// Check a config file and print the effective config
+    ValidateConfig {
+        file: PathBuf,
+        /// Apply the overrides of this `[profiles.<name>]` table
+        #[arg(long)]
+        profile: Option<String>,
+        /// Print tokens and keys instead of masking them
+        #[arg(long)]
+        show_secrets: bool,
+    },
+    /// Rewrite account ids, order ids and addresses in a golden pack so it
+    /// can be shared
+    Scrub {
+        /// A fixture, or a pack directory for all of its fixtures
+        pack: PathBuf,
+        /// Adapter type decoding raw frames, e.g. binance
+        #[arg(long, default_value = "binance")]
+        venue: String,
+        /// Directory to write the scrubbed pack to; the pack itself by default
+        #[arg(long)]
+        out: Option<PathBuf>,
+        /// Remove sensitive fields instead of rewriting their values
+        #[arg(long)]
+        strip: bool,
+    },
+    /// Run the engine for hours, failing on memory or file descriptor leaks
+    /// and stalls
+    Soak {
+        /// Config file of the engine
+        #[arg(long)]
+        config: PathBuf,
+        /// Apply the overrides of this `[profiles.<name>]` table
+        #[arg(long)]
+        profile: Option<String>,
+        /// How long to run
+        #[arg(long, default_value_t = 24.0)]
+        hours: f64,
+        /// Serve this pack as a mock exchange for the config's venues
+        #[arg(long)]
+        pack: Option<PathBuf>,
+        /// Where the mock exchange listens
+        #[arg(long, default_value = "127.0.0.1:9443")]
+        mock_addr: SocketAddr,
+        /// Time for caches and buffers to fill before growth counts
+        #[arg(long, default_value = "10m", value_parser = parse_duration)]
+        warmup: Duration,
+        /// How often to sample and report
+        #[arg(long, default_value = "60s", value_parser = parse_duration)]
+        interval: Duration,
+        /// Most MiB the heap may grow by after the warmup
+        #[arg(long, default_value_t = 64)]
+        max_growth: usize,
+        /// Most file descriptors that may be opened after the warmup
+        #[arg(long, default_value_t = 64)]
+        max_fd_growth: usize,
+        /// Longest time without events
+        #[arg(long, default_value = "2m", value_parser = parse_duration)]
+        stall: Duration,
+    },
+    /// Print the symbols a venue of a config subscribes to, discovering them
+    Symbols {
+        /// Config file of the engine
+        #[arg(long)]
+        config: PathBuf,
+        /// Name of the venue in the config
+        #[arg(long)]
+        venue: String,
+        /// Apply the overrides of this `[profiles.<name>]` table
+        #[arg(long)]
+        profile: Option<String>,
+    },
+    /// Print events from a write-ahead log directory as JSON lines
+    Wal {
+        dir: String,
+        /// Start at this sequence number
+        #[arg(long, default_value_t = 0)]
+        from_sequence: u64,
+        /// Only events timestamped at or after this RFC 3339 time
+        #[arg(long)]
+        from: Option<DateTime<Utc>>,
+        /// Only events timestamped before this RFC 3339 time
+        #[arg(long)]
+        to: Option<DateTime<Utc>>,
+    },
+    /// Compare the events of a golden pack with those of a baseline
+    Diff {
+        /// A fixture, or a pack directory for all of its fixtures
+        pack: PathBuf,
+        /// A snapshot, a directory of snapshots, or a devtools binary whose
+        /// replay decodes the pack
+        #[arg(long)]
+        baseline: PathBuf,
+        /// Adapter type decoding raw frames, e.g. binance
+        #[arg(long, default_value = "binance")]
+        venue: String,
+    },
+    /// Measure how fast a golden pack is decoded and processed
+    Bench {
+        /// A fixture, or a pack directory for all of its fixtures
+        #[arg(long)]
+        pack: PathBuf,
+        /// Times to run through the pack
+        #[arg(long, default_value_t = 1000)]
+        iterations: usize,
+        /// Adapter type decoding raw frames, e.g. binance
+        #[arg(long, default_value = "binance")]
+        venue: String,
+    },
+    /// Convert captured events between JSON lines, Parquet, proto, avro and
+    /// postcard
+    Convert {
+        /// Events, or raw frames, to convert
+        #[arg(long = "in")]
+        input: PathBuf,
+        #[arg(long = "out")]
+        output: PathBuf,
+        /// Format of the input, by its extension by default
+        #[arg(long)]
+        from: Option<convert::Format>,
+        /// Format of the output, by its extension by default
+        #[arg(long)]
+        to: Option<convert::Format>,
+        /// Adapter type decoding raw frames, e.g. binance
+        #[arg(long, default_value = "binance")]
+        venue: String,
+    },
+    /// Report what a golden pack holds: events per symbol and kind, rates,
+    /// gaps, duplicates and frame sizes
+    Inspect {
+        /// A fixture, or a pack directory for all of its fixtures
+        pack: PathBuf,
+        /// Adapter type decoding raw frames, e.g. binance
+        #[arg(long, default_value = "binance")]
+        venue: String,
+    },
+    /// Compare the prices of a symbol across venues' packs, to catch
+    /// adapters that scale prices or invert sides
+    CompareVenues {
+        /// A fixture, or a pack directory for all of its fixtures; the
+        /// first is the reference the others are compared to
+        #[arg(long, required = true)]
+        pack: Vec<PathBuf>,
+        /// Adapter type of each pack, in order; the last applies to the
+        /// packs after it
+        #[arg(long, default_values_t = vec!["binance".to_string()])]
+        venue: Vec<String>,
+        /// Symbol to compare, e.g. BTC-USD, which also matches BTCUSDT
+        #[arg(long)]
+        symbol: String,
+        /// Longest time between events aligned across packs
+        #[arg(long, default_value = "1s", value_parser = parse_duration)]
+        tolerance: Duration,
+        /// Largest median price deviation from the reference, in basis points
+        #[arg(long, default_value_t = 50.0)]
+        max_deviation: f64,
+    },
+    /// Print the schema of events in an encoding, for consumers' codegen
+    GenSchema {
+        /// jsonschema for the json encoding, proto or avro for theirs
+        #[arg(long, value_parser = ["proto", "jsonschema", "avro"])]
+        format: String,
+    },
+    /// Serve a golden pack as a fake exchange, for end-to-end tests
+    MockExchange {
+        /// Exchange to imitate; only binance is supported
+        #[arg(long, default_value = "binance")]
+        venue: String,
+        /// A fixture, or a pack directory for all of its fixtures
+        #[arg(long)]
+        pack: PathBuf,
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:9443")]
+        addr: SocketAddr,
+        /// Multiple of the original pace, e.g. 10x, or max for no pauses
+        #[arg(long, default_value = "1x")]
+        speed: replay::Speed,
+        /// Start over at the end of the pack
+        #[arg(long = "loop")]
+        looped: bool,
+        /// Stamp payloads with the time they are sent
+        #[arg(long)]
+        restamp: bool,
+        /// More symbols for exchangeInfo to list, comma separated
+        #[arg(long, value_delimiter = ',')]
+        symbols: Vec<String>,
+        #[command(flatten)]
+        chaos: mock_exchange::Chaos,
+    },
+    /// Feed mutated payloads to the adapters and the pipeline normalizer,
+    /// failing on panics and unbounded allocations
+    Fuzz {
+        /// A fixture, or a pack directory, to mutate; every pack in golden/
+        /// by default
+        #[arg(long)]
+        pack: Option<PathBuf>,
+        /// Adapter type to fuzz, e.g. binance; every builtin one by default
+        #[arg(long)]
+        venue: Option<String>,
+        /// Inputs to try
+        #[arg(long, default_value_t = 100_000)]
+        iterations: usize,
+        /// Seed of the mutations, to reproduce a run; random by default
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Most MiB one input may allocate
+        #[arg(long, default_value_t = 64)]
+        max_memory: usize,
+        /// Directory to save failing inputs to
+        #[arg(long, default_value = "fuzz/artifacts/devtools")]
+        crashes: PathBuf,
+    },
+    /// Measure how late a live venue's events are received and published
+    LatencyProbe {
+        /// Adapter type of the venue, e.g. binance
+        #[arg(long, default_value = "binance")]
+        venue: String,
+        /// Symbols to subscribe to, comma separated
+        #[arg(long, value_delimiter = ',', default_value = "BTCUSDT")]
+        symbols: Vec<String>,
+        /// How long to sample, e.g. 60s or 5m
+        #[arg(long, default_value = "60s", value_parser = parse_duration)]
+        duration: Duration,
+        /// Stream base URL, e.g. a testnet's; the venue's by default
+        #[arg(long)]
+        ws_base: Option<String>,
+    },
+    /// Fetch a symbol's history over the venue's REST API into a pack or
+    /// the sinks of a config
+    Backfill {
+        /// Venue to fetch from; only binance is supported
+        #[arg(long, default_value = "binance")]
+        venue: String,
+        #[arg(long)]
+        symbol: String,
+        /// Kline interval, e.g. 1m or 1h
+        #[arg(long, default_value = "1m")]
+        interval: String,
+        /// Fetch aggregate trades instead of klines
+        #[arg(long)]
+        trades: bool,
+        /// Start of the history, an RFC 3339 time
+        #[arg(long)]
+        from: DateTime<Utc>,
+        /// End of the history, an RFC 3339 time; now by default
+        #[arg(long)]
+        to: Option<DateTime<Utc>>,
+        /// REST base URL, e.g. a testnet's
+        #[arg(long, default_value = "https://api.binance.com/api/v3")]
+        rest_base: String,
+        /// Request weight to use per minute before waiting for the next
+        #[arg(long, default_value_t = 1000)]
+        max_weight: u64,
+        /// Pack directory to write the payloads to as a fixture
+        #[arg(long)]
+        out: Option<PathBuf>,
+        /// Name of the fixture in the pack
+        #[arg(long, default_value = "backfill")]
+        name: String,
+        /// Config whose sinks to deliver the events to
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Only these sinks of the config, comma separated
+        #[arg(long, value_delimiter = ',')]
+        sink: Vec<String>,
+    },
+    /// Record a golden pack from a live venue through its adapter
+    Record {
+        /// Adapter type of the venue, e.g. binance
+        #[arg(long)]
+        venue: String,
+        /// Symbols to subscribe to, comma separated
+        #[arg(long, value_delimiter = ',', required = true)]
+        symbols: Vec<String>,
+        /// How long to record, e.g. 60s or 5m
+        #[arg(long, default_value = "60s", value_parser = parse_duration)]
+        duration: Duration,
+        /// Directory of the pack, named after the venue as in `golden/`
+        #[arg(long)]
+        out: PathBuf,
+        /// Name of the fixture in the pack
+        #[arg(long, default_value = "recorded")]
+        name: String,
+        /// Stream base URL, e.g. a testnet's; the venue's by default
+        #[arg(long)]
+        ws_base: Option<String>,
+    },
+}
+
+/// The builtin adapter of `venue_type`.
+fn adapter(venue_type: &str) -> Result<Arc<dyn Adapter>, String> {
+    AdapterRegistry::builtin()
+        .get(venue_type)
+        .ok_or_else(|| format!("no adapter for venue type {:?}", venue_type))
+}
+
+/// The venue events of the pack in `dir` are attributed to, as the golden
+/// tests render them: the one the directory is named after, or `default`.
+fn pack_venue(dir: &Path, default: &str) -> String {
+    dir.file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(default)
+        .to_string()
+}
+
+/// The fixtures of `pack`: the file itself, or every fixture in the
+/// directory.
+fn pack_fixtures(pack: &Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    if pack.is_dir() {
+        Ok(agents::golden::fixtures(pack)?)
+    } else {
+        Ok(vec![pack.to_path_buf()])
+    }
+}
+
+/// The fixtures of `pack`, each with the venue its events are attributed
+/// to, as [`pack_venue`] names it.
+fn venue_fixtures(
+    pack: &Path,
+    default: &str,
+) -> Result<Vec<(PathBuf, String)>, Box<dyn std::error::Error>> {
+    Ok(pack_fixtures(pack)?
+        .into_iter()
+        .map(|fixture| {
+            let venue = pack_venue(fixture.parent().unwrap_or(&fixture), default);
+            (fixture, venue)
+        })
+        .collect())
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// A duration such as `500ms`, `60s`, `5m` or `1h`.
+fn parse_duration(text: &str) -> Result<Duration, String> {
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (count, unit) = text.split_at(split);
+    let count: u64 = count
+        .parse()
+        .map_err(|_| format!("{:?} does not start with a number", text))?;
+    match unit {
+        "ms" => Ok(Duration::from_millis(count)),
+        "s" | "" => Ok(Duration::from_secs(count)),
+        "m" => Ok(Duration::from_secs(count * 60)),
+        "h" => Ok(Duration::from_secs(count * 3600)),
+        _ => Err(format!("unknown unit {:?}, expected ms, s, m or h", unit)),
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
     match cli.command {
-        Commands::Scaffold { spec } => {
-            let text = std::fs::read_to_string(spec)?;
-            println!("// adapter spec\n{}", text);
-        }
-        Commands::Replay { file } => {
-            let f = File::open(file)?;
-            let reader = BufReader::new(f);
-            for line in reader.lines() {
-                let raw = line?;
-                let evt = pipeline::normalize("binance", "TEST", &raw)?;
-                println!("{}", serde_json::to_string(&evt)?);
+        Commands::Scaffold {
+            spec,
+            agents,
+            golden,
+        } => {
+            for path in scaffold::scaffold(&spec, &agents, &golden)? {
+                println!("wrote {}", path.display());
+            }
+        }
+        Commands::Replay {
+            file,
+            venue,
+            speed,
+            looped,
+            publish,
+            token,
+        } => {
+            let adapter = adapter(&venue)?;
+            let venue_name = pack_venue(file.parent().unwrap_or(&file), &venue);
+            let events = replay::load(&file, &*adapter, &venue_name)?;
+            let output = match publish {
+                Some(url) => replay::Output::Publish {
+                    client: reqwest::Client::new(),
+                    url,
+                    token,
+                },
+                None => replay::Output::Print,
+            };
+            replay::replay(&events, speed, looped, &output).await?;
+        }
+        Commands::Top {
+            url,
+            token,
+            interval,
+            symbols,
+        } => top::top(&url, token.as_deref(), interval, symbols).await?,
+        Commands::Tail {
+            url,
+            venue,
+            symbol,
+            kind,
+            replay,
+            json,
+            token,
+        } => {
+            let filter = tail::Filter {
+                venues: venue,
+                symbols: symbol,
+                kinds: kind,
+                replay,
+            };
+            tail::tail(&url, &filter, token.as_deref(), json).await?;
+        }
+        Commands::ValidateConfig {
+            file,
+            profile,
+            show_secrets,
+        } => validate::validate_config(&file, profile.as_deref(), show_secrets)?,
+        Commands::Scrub {
+            pack,
+            venue,
+            out,
+            strip,
+        } => {
+            let adapter = adapter(&venue)?;
+            let mut scrubber = scrub::Scrubber::new(strip);
+            for (fixture, venue) in venue_fixtures(&pack, &venue)? {
+                let scrubbed =
+                    scrub::scrub(&fixture, &venue, &*adapter, out.as_deref(), &mut scrubber)?;
+                println!(
+                    "{}: {} fields and {} addresses scrubbed in {} lines",
+                    fixture.display(),
+                    scrubbed.fields,
+                    scrubbed.addresses,
+                    scrubbed.frames
+                );
             }
         }
+        Commands::Soak {
+            config,
+            profile,
+            hours,
+            pack,
+            mock_addr,
+            warmup,
+            interval,
+            max_growth,
+            max_fd_growth,
+            stall,
+        } => {
+            if let Some(pack) = pack {
+                let exchange = mock_exchange::Exchange::load(
+                    &pack_fixtures(&pack)?,
+                    &[],
+                    replay::Speed(Some(1.0)),
+                    true,
+                    true,
+                )?;
+                tokio::spawn(async move {
+                    if let Err(e) = exchange.serve(mock_addr).await {
+                        eprintln!("mock exchange: {}", e);
+                    }
+                });
+            }
+            let thresholds = soak::Thresholds {
+                max_growth: max_growth * 1024 * 1024,
+                max_fd_growth,
+                stall,
+            };
+            soak::soak(
+                &config,
+                profile.as_deref(),
+                Duration::from_secs_f64(hours * 3600.0),
+                warmup,
+                interval,
+                &thresholds,
+            )
+            .await?;
+        }
+        Commands::Symbols {
+            config,
+            venue,
+            profile,
+        } => symbols::symbols(&config, profile.as_deref(), &venue).await?,
+        Commands::Wal {
+            dir,
+            from_sequence,
+            from,
+            to,
+        } => {
+            let reader = wal::WalReader::open(dir);
+            for entry in reader.from_sequence(from_sequence)? {
+                let entry = entry?;
+                let ts = entry.event.timestamp;
+                if from.is_some_and(|from| ts < from) || to.is_some_and(|to| ts >= to) {
+                    continue;
+                }
+                println!("{}", serde_json::to_string(&entry.event)?);
+            }
+        }
+        Commands::Diff {
+            pack,
+            baseline,
+            venue,
+        } => {
+            let adapter = adapter(&venue)?;
+            let baseline = diff::Baseline::new(baseline);
+            let mut differing = 0;
+            for fixture in pack_fixtures(&pack)? {
+                let venue_name = pack_venue(fixture.parent().unwrap_or(&fixture), &venue);
+                differing += diff::diff(&fixture, &*adapter, &venue, &venue_name, &baseline)?;
+            }
+            if differing > 0 {
+                return Err(format!("{} events differ from the baseline", differing).into());
+            }
+        }
+        Commands::Bench {
+            pack,
+            iterations,
+            venue,
+        } => {
+            let adapter = adapter(&venue)?;
+            let fixtures = venue_fixtures(&pack, &venue)?;
+            bench::bench(&fixtures, &*adapter, iterations)?;
+        }
+        Commands::Convert {
+            input,
+            output,
+            from,
+            to,
+            venue,
+        } => {
+            let from = from.map_or_else(|| convert::Format::of(&input), Ok)?;
+            let to = to.map_or_else(|| convert::Format::of(&output), Ok)?;
+            let adapter = adapter(&venue)?;
+            let venue_name = pack_venue(input.parent().unwrap_or(&input), &venue);
+            let events = convert::convert(&input, from, &output, to, &*adapter, &venue_name)?;
+            println!("converted {} events to {}", events, output.display());
+        }
+        Commands::Inspect { pack, venue } => {
+            let adapter = adapter(&venue)?;
+            let fixtures = venue_fixtures(&pack, &venue)?;
+            inspect::inspect(&fixtures, &*adapter)?;
+        }
+        Commands::CompareVenues {
+            pack,
+            venue,
+            symbol,
+            tolerance,
+            max_deviation,
+        } => {
+            if pack.len() < 2 {
+                return Err("compare-venues needs two --pack or more".into());
+            }
+            let mut packs = Vec::with_capacity(pack.len());
+            for (i, path) in pack.iter().enumerate() {
+                let venue = &venue[i.min(venue.len() - 1)];
+                packs.push(compare::Pack {
+                    label: path.display().to_string(),
+                    fixtures: venue_fixtures(path, venue)?,
+                    adapter: adapter(venue)?,
+                });
+            }
+            compare::compare(&packs, &symbol, tolerance, max_deviation)?;
+        }
+        Commands::GenSchema { format } => match format.as_str() {
+            "proto" => print!("{}", sinks::codec::PROTO_SCHEMA),
+            "avro" => println!("{}", sinks::codec::AVRO_SCHEMA),
+            _ => println!(
+                "{}",
+                serde_json::to_string_pretty(&ingest_core::event::schema())?
+            ),
+        },
+        Commands::MockExchange {
+            venue,
+            pack,
+            addr,
+            speed,
+            looped,
+            restamp,
+            symbols,
+            chaos,
+        } => {
+            if venue != "binance" {
+                return Err(format!("cannot imitate {}, only binance", venue).into());
+            }
+            chaos.check()?;
+            let exchange = mock_exchange::Exchange::load(
+                &pack_fixtures(&pack)?,
+                &symbols,
+                speed,
+                looped,
+                restamp,
+            )?
+            .with_chaos(chaos);
+            exchange.serve(addr).await?;
+        }
+        Commands::Fuzz {
+            pack,
+            venue,
+            iterations,
+            seed,
+            max_memory,
+            crashes,
+        } => {
+            let adapters = fuzz::adapters(venue.as_deref())?;
+            let fixtures = match pack {
+                Some(pack) => venue_fixtures(&pack, "unknown")?,
+                None => fuzz::packs(Path::new("golden"))?,
+            };
+            let options = fuzz::Options {
+                iterations,
+                seed: seed.unwrap_or_else(rand::random),
+                max_memory: max_memory << 20,
+                crashes,
+            };
+            let failures = fuzz::fuzz(&fuzz::seeds(&fixtures)?, &adapters, &options)?;
+            if failures > 0 {
+                return Err(format!("{} inputs failed", failures).into());
+            }
+        }
+        Commands::LatencyProbe {
+            venue,
+            symbols,
+            duration,
+            ws_base,
+        } => {
+            let adapter = adapter(&venue)?;
+            let cfg = VenueConfig {
+                name: venue.clone(),
+                venue_type: venue,
+                symbols,
+                discover: false,
+                ws_base,
+                rest_base: None,
+                http_timeout_secs: None,
+                channels: Default::default(),
+                discovery: None,
+            };
+            latency::probe(adapter, cfg, duration).await?;
+        }
+        Commands::Backfill {
+            venue,
+            symbol,
+            interval,
+            trades,
+            from,
+            to,
+            rest_base,
+            max_weight,
+            out,
+            name,
+            config,
+            sink,
+        } => {
+            if venue != "binance" {
+                return Err(format!("cannot backfill {}, only binance", venue).into());
+            }
+            if out.is_none() && config.is_none() {
+                return Err("backfill needs --out or --config".into());
+            }
+            let adapter = adapter(&venue)?;
+            let mut outputs = backfill::Outputs {
+                pack: out.map(|out| {
+                    let venue = pack_venue(&out, &venue);
+                    (out, name, venue)
+                }),
+                sinks: Vec::new(),
+                venue: venue.clone(),
+            };
+            if let Some(path) = config {
+                let (configured, sinks) = backfill::sinks(&path, &sink, &venue)?;
+                outputs.venue = configured.unwrap_or(outputs.venue);
+                outputs.sinks = sinks;
+            }
+            let history = if trades {
+                backfill::History::AggTrades
+            } else {
+                backfill::History::Klines(interval)
+            };
+            let pages = backfill::Backfill::new(
+                &rest_base,
+                &symbol,
+                history,
+                from,
+                to.unwrap_or_else(Utc::now),
+                max_weight,
+            )?;
+            let (payloads, events) = backfill::run(pages, &*adapter, &mut outputs).await?;
+            println!(
+                "backfilled {} payloads, {} events to sinks",
+                payloads, events
+            );
+        }
+        Commands::Record {
+            venue,
+            symbols,
+            duration,
+            out,
+            name,
+            ws_base,
+        } => {
+            let adapter = adapter(&venue)?;
+            let cfg = VenueConfig {
+                name: pack_venue(&out, &venue),
+                venue_type: venue,
+                symbols,
+                discover: false,
+                ws_base,
+                rest_base: None,
+                http_timeout_secs: None,
+                channels: Default::default(),
+                discovery: None,
+            };
+            let recorded = record::record(adapter, cfg, duration, &out, &name).await?;
+            println!(
+                "recorded {} frames, {} events into {}",
+                recorded.frames,
+                recorded.events,
+                out.join(format!("{}.jsonl", name)).display()
+            );
+        }
     }
     Ok(())
 }
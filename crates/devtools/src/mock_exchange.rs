@@ -0,0 +1,355 @@
+//! `devtools mock-exchange`: serve a golden pack the way Binance serves its
+//! streams, with an `exchangeInfo` endpoint for discovery, so ingestd can be
+//! tested end to end without the internet, and faults to inject into it.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, State};
+use axum::http::{StatusCode, Uri};
+use axum::response::Response;
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::Utc;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::replay::Speed;
+
+/// A payload of the pack and the stream it belongs to.
+struct Payload {
+    stream: String,
+    data: Value,
+    /// Event time, in epoch milliseconds, to pace by.
+    at: Option<i64>,
+}
+
+/// Faults to inject, each the probability of it hitting a frame sent, or
+/// a response for `slow`. `GET /chaos` shows them and `PUT /chaos` replaces
+/// them while the exchange runs.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, clap::Args)]
+#[serde(default, deny_unknown_fields)]
+pub struct Chaos {
+    /// Probability of dropping the connection instead of sending a frame
+    #[arg(long, default_value_t = 0.0)]
+    pub disconnect: f64,
+    /// Probability of sending a frame cut short, so it is not valid JSON
+    #[arg(long, default_value_t = 0.0)]
+    pub malformed: f64,
+    /// Probability of sending a frame twice
+    #[arg(long, default_value_t = 0.0)]
+    pub duplicate: f64,
+    /// Probability of holding a frame back and sending it after the next
+    #[arg(long, default_value_t = 0.0)]
+    pub reorder: f64,
+    /// Probability of waiting --delay-ms before a frame or response
+    #[arg(long, default_value_t = 0.0)]
+    pub slow: f64,
+    #[arg(long, default_value_t = 1000)]
+    pub delay_ms: u64,
+}
+
+impl Default for Chaos {
+    fn default() -> Self {
+        Self {
+            disconnect: 0.0,
+            malformed: 0.0,
+            duplicate: 0.0,
+            reorder: 0.0,
+            slow: 0.0,
+            delay_ms: 1000,
+        }
+    }
+}
+
+impl Chaos {
+    /// Refuse probabilities outside `0..=1`.
+    pub fn check(&self) -> Result<(), String> {
+        for (name, probability) in [
+            ("disconnect", self.disconnect),
+            ("malformed", self.malformed),
+            ("duplicate", self.duplicate),
+            ("reorder", self.reorder),
+            ("slow", self.slow),
+        ] {
+            if !(0.0..=1.0).contains(&probability) {
+                return Err(format!(
+                    "{} must be between 0 and 1, not {}",
+                    name, probability
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait `delay_ms` if a slow response is due.
+    async fn pause(&self, rng: &mut StdRng) {
+        if rng.gen_bool(self.slow) {
+            tokio::time::sleep(Duration::from_millis(self.delay_ms)).await;
+        }
+    }
+}
+
+/// What the server serves, and how.
+pub struct Exchange {
+    payloads: Vec<Payload>,
+    symbols: BTreeSet<String>,
+    speed: Speed,
+    looped: bool,
+    restamp: bool,
+    chaos: Mutex<Chaos>,
+}
+
+/// Quote assets, most specific first, to split symbols into base and quote.
+const QUOTES: &[&str] = &[
+    "FDUSD", "USDT", "USDC", "BUSD", "TUSD", "USD", "BTC", "ETH", "BNB", "EUR", "TRY",
+];
+
+impl Exchange {
+    /// The payloads of the raw frames in `fixtures`, as recorded from
+    /// Binance: combined stream frames keep their stream name, and single
+    /// stream payloads are named after their symbol and event type.
+    /// `symbols` are listed by `exchangeInfo` along with those of the pack.
+    pub fn load(
+        fixtures: &[PathBuf],
+        symbols: &[String],
+        speed: Speed,
+        looped: bool,
+        restamp: bool,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut payloads = Vec::new();
+        let mut listed: BTreeSet<String> = symbols.iter().map(|s| s.to_uppercase()).collect();
+        for fixture in fixtures {
+            for line in fs::read_to_string(fixture)?.lines() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let frame: Value = serde_json::from_str(line)?;
+                let (stream, data) = match (frame.get("stream"), frame.get("data")) {
+                    (Some(Value::String(stream)), Some(data)) => (stream.clone(), data.clone()),
+                    _ => (stream_of(&frame), frame),
+                };
+                let items = match &data {
+                    Value::Array(items) => items.iter().collect(),
+                    data => vec![data],
+                };
+                listed.extend(
+                    items
+                        .iter()
+                        .filter_map(|item| item.get("s").and_then(Value::as_str))
+                        .map(str::to_string),
+                );
+                let at = items.first().and_then(|item| item.get("E")?.as_i64());
+                payloads.push(Payload { stream, data, at });
+            }
+        }
+        Ok(Self {
+            payloads,
+            symbols: listed,
+            speed,
+            looped,
+            restamp,
+            chaos: Mutex::default(),
+        })
+    }
+
+    /// Inject `chaos` from the start.
+    pub fn with_chaos(self, chaos: Chaos) -> Self {
+        *self.chaos.lock().unwrap() = chaos;
+        self
+    }
+
+    fn chaos(&self) -> Chaos {
+        *self.chaos.lock().unwrap()
+    }
+
+    /// Serve `GET /api/v3/exchangeInfo`, the streams and `/chaos` on
+    /// `addr` until the process is stopped.
+    pub async fn serve(self, addr: SocketAddr) -> Result<(), Box<dyn std::error::Error>> {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        println!(
+            "mock exchange: {} payloads, {} symbols; rest_base = \"http://{}/api/v3\", ws_base = \"ws://{}/ws\"",
+            self.payloads.len(),
+            self.symbols.len(),
+            addr,
+            addr
+        );
+        let app = Router::new()
+            .route("/api/v3/exchangeInfo", get(exchange_info))
+            .route("/chaos", get(chaos).put(set_chaos))
+            .fallback(stream)
+            .with_state(Arc::new(self));
+        axum::serve(listener, app).await?;
+        Ok(())
+    }
+}
+
+/// `btcusdt@trade`, `btcusdt@ticker` or `btcusdt@bookTicker` for a single
+/// stream payload, `!ticker@arr` for an array of tickers.
+fn stream_of(payload: &Value) -> String {
+    if payload.is_array() {
+        return "!ticker@arr".to_string();
+    }
+    let symbol = payload
+        .get("s")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_lowercase();
+    let channel = match payload.get("e").and_then(Value::as_str) {
+        Some("trade") => "trade",
+        Some("24hrTicker") => "ticker",
+        Some(other) => other,
+        None => "bookTicker",
+    };
+    format!("{}@{}", symbol, channel)
+}
+
+async fn chaos(State(exchange): State<Arc<Exchange>>) -> Json<Chaos> {
+    Json(exchange.chaos())
+}
+
+async fn set_chaos(
+    State(exchange): State<Arc<Exchange>>,
+    Json(chaos): Json<Chaos>,
+) -> Result<Json<Chaos>, (StatusCode, String)> {
+    chaos
+        .check()
+        .map_err(|message| (StatusCode::BAD_REQUEST, message))?;
+    *exchange.chaos.lock().unwrap() = chaos;
+    Ok(Json(chaos))
+}
+
+async fn exchange_info(State(exchange): State<Arc<Exchange>>) -> Json<Value> {
+    exchange.chaos().pause(&mut StdRng::from_entropy()).await;
+    let symbols: Vec<Value> = exchange
+        .symbols
+        .iter()
+        .map(|symbol| {
+            let quote = QUOTES
+                .iter()
+                .find(|quote| symbol.len() > quote.len() && symbol.ends_with(*quote))
+                .copied()
+                .unwrap_or_default();
+            json!({
+                "symbol": symbol,
+                "status": "TRADING",
+                "baseAsset": &symbol[..symbol.len() - quote.len()],
+                "quoteAsset": quote,
+            })
+        })
+        .collect();
+    Json(json!({
+        "timezone": "UTC",
+        "serverTime": Utc::now().timestamp_millis(),
+        "symbols": symbols,
+    }))
+}
+
+/// A stream socket, as Binance names them: `/ws/<stream>` for a raw stream,
+/// `/stream?streams=<a>/<b>` for combined ones, under any base path.
+async fn stream(
+    State(exchange): State<Arc<Exchange>>,
+    uri: Uri,
+    Query(query): Query<std::collections::HashMap<String, String>>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let (streams, combined) = match query.get("streams") {
+        Some(streams) => (streams.split('/').map(str::to_string).collect(), true),
+        None => {
+            let last = uri.path().rsplit('/').next().unwrap_or_default();
+            let streams = if last.contains('@') {
+                BTreeSet::from([last.to_string()])
+            } else {
+                BTreeSet::new()
+            };
+            (streams, false)
+        }
+    };
+    ws.on_upgrade(move |socket| send(socket, exchange, streams, combined))
+}
+
+/// Send the payloads of `streams`, or of every stream when empty, paced by
+/// their event times and subject to the exchange's chaos, then keep the
+/// socket open until the client leaves.
+async fn send(
+    mut socket: WebSocket,
+    exchange: Arc<Exchange>,
+    streams: BTreeSet<String>,
+    combined: bool,
+) {
+    let mut rng = StdRng::from_entropy();
+    let mut held: Option<String> = None;
+    loop {
+        let mut previous: Option<i64> = None;
+        for payload in &exchange.payloads {
+            if !streams.is_empty() && !streams.contains(&payload.stream) {
+                continue;
+            }
+            if let (Some(factor), Some(previous), Some(at)) =
+                (exchange.speed.0, previous, payload.at)
+            {
+                let pause = (at - previous).max(0) as f64 / factor;
+                tokio::time::sleep(Duration::from_secs_f64(pause / 1000.0)).await;
+            }
+            previous = payload.at.or(previous);
+            let mut data = payload.data.clone();
+            if exchange.restamp {
+                restamp(&mut data);
+            }
+            let frame = if combined {
+                json!({"stream": payload.stream, "data": data})
+            } else {
+                data
+            };
+            let mut frame = frame.to_string();
+
+            let chaos = exchange.chaos();
+            if rng.gen_bool(chaos.disconnect) {
+                return;
+            }
+            chaos.pause(&mut rng).await;
+            if rng.gen_bool(chaos.malformed) {
+                frame.truncate(frame.len() / 2);
+            }
+            if held.is_none() && rng.gen_bool(chaos.reorder) {
+                held = Some(frame);
+                continue;
+            }
+            let copies = if rng.gen_bool(chaos.duplicate) { 2 } else { 1 };
+            let frames = std::iter::repeat_n(frame, copies).chain(held.take());
+            for frame in frames {
+                if socket.send(Message::Text(frame)).await.is_err() {
+                    return;
+                }
+            }
+        }
+        if !exchange.looped {
+            break;
+        }
+    }
+    while let Some(Ok(_)) = socket.recv().await {}
+}
+
+/// Set the event and trade times of `data` to now, so events look fresh.
+fn restamp(data: &mut Value) {
+    let now = Utc::now().timestamp_millis();
+    match data {
+        Value::Array(items) => items.iter_mut().for_each(restamp),
+        Value::Object(fields) => {
+            for field in ["E", "T"] {
+                if fields.contains_key(field) {
+                    fields.insert(field.to_string(), now.into());
+                }
+            }
+        }
+        _ => {}
+    }
+}
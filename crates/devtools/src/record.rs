@@ -0,0 +1,77 @@
+//! `devtools record`: capture a golden pack from a live venue through its
+//! real adapter.
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use agents::{golden, Adapter};
+use ingest_core::{config::VenueConfig, event::ENGINE_VENUE};
+use tokio::sync::mpsc;
+
+/// What a recording captured.
+pub struct Recorded {
+    pub frames: usize,
+    pub events: usize,
+}
+
+/// Run `adapter` against the venue of `cfg` for `duration` and write a pack
+/// to `dir`: the raw frames as the fixture `<name>.jsonl`, their snapshot as
+/// the golden tests render it, and the events the adapter published, with
+/// their receive timestamps, as `<name>.events.jsonl`.
+pub async fn record(
+    adapter: Arc<dyn Adapter>,
+    cfg: VenueConfig,
+    duration: Duration,
+    dir: &Path,
+    name: &str,
+) -> Result<Recorded, Box<dyn std::error::Error>> {
+    let venue = cfg.name.clone();
+    let (tx, mut events) = mpsc::channel(1024);
+    let (frames_tx, mut frames) = mpsc::channel(1024);
+    let capture = tokio::spawn({
+        let adapter = adapter.clone();
+        async move { adapter.capture(cfg, tx, frames_tx).await }
+    });
+
+    let (mut raw, mut published) = (String::new(), String::new());
+    let mut recorded = Recorded {
+        frames: 0,
+        events: 0,
+    };
+    let deadline = tokio::time::sleep(duration);
+    tokio::pin!(deadline);
+    loop {
+        tokio::select! {
+            () = &mut deadline => break,
+            Some(frame) = frames.recv() => {
+                // A fixture holds one frame per line; JSON needs no newlines.
+                raw.push_str(&frame.text.replace(['\r', '\n'], " "));
+                raw.push('\n');
+                recorded.frames += 1;
+            }
+            Some(event) = events.recv() => {
+                if event.venue != ENGINE_VENUE {
+                    published.push_str(&serde_json::to_string(&event)?);
+                    published.push('\n');
+                    recorded.events += 1;
+                }
+            }
+            else => break,
+        }
+    }
+    // The adapter stops once nobody listens.
+    drop((frames, events));
+    capture.await??;
+
+    let fixture = dir.join(format!("{}.jsonl", name));
+    fs::create_dir_all(dir)?;
+    fs::write(&fixture, &raw)?;
+    fs::write(
+        golden::snapshot_path(&fixture),
+        golden::render(&*adapter, &venue, &raw)?,
+    )?;
+    fs::write(golden::events_path(&fixture), &published)?;
+    Ok(recorded)
+}
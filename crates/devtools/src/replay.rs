@@ -0,0 +1,138 @@
+//! `devtools replay`: play a golden pack back at its original pace, or a
+//! multiple of it, printing the events or publishing them into a running
+//! engine.
+
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Instant;
+
+use agents::Adapter;
+use chrono::{DateTime, Utc};
+use ingest_core::event::NormalizedEvent;
+
+/// How fast to replay: a multiple of the original pace, or as fast as
+/// possible when `None`.
+#[derive(Debug, Clone, Copy)]
+pub struct Speed(pub Option<f64>);
+
+impl FromStr for Speed {
+    type Err = String;
+
+    /// `10x`, `0.5x` or `max`.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        if text == "max" {
+            return Ok(Speed(None));
+        }
+        match text.trim_end_matches('x').parse::<f64>() {
+            Ok(factor) if factor > 0.0 && factor.is_finite() => Ok(Speed(Some(factor))),
+            _ => Err(format!(
+                "{:?} is not a positive speed such as 10x, or max",
+                text
+            )),
+        }
+    }
+}
+
+/// Where replayed events go.
+pub enum Output {
+    /// Stdout, as JSON lines.
+    Print,
+    /// `POST /publish` of the engine's ops server at `url`.
+    Publish {
+        client: reqwest::Client,
+        url: String,
+        token: Option<String>,
+    },
+}
+
+impl Output {
+    /// Send and clear `events`.
+    async fn emit(
+        &self,
+        events: &mut Vec<NormalizedEvent>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        match self {
+            Output::Print => {
+                for event in events.iter() {
+                    println!("{}", serde_json::to_string(event)?);
+                }
+            }
+            Output::Publish { client, url, token } => {
+                let mut request = client
+                    .post(format!("{}/publish", url.trim_end_matches('/')))
+                    .json(&events);
+                if let Some(token) = token {
+                    request = request.bearer_auth(token);
+                }
+                request.send().await?.error_for_status()?;
+            }
+        }
+        events.clear();
+        Ok(())
+    }
+}
+
+/// The events of the pack at `path`. Lines holding normalized events, such
+/// as snapshots and recorded events, are taken as they are; other lines are
+/// raw frames, decoded by `adapter` as events of `venue`.
+pub fn load(
+    path: &Path,
+    adapter: &dyn Adapter,
+    venue: &str,
+) -> Result<Vec<NormalizedEvent>, Box<dyn std::error::Error>> {
+    let mut events = Vec::new();
+    for line in fs::read_to_string(path)?.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<NormalizedEvent>(line) {
+            Ok(event) => events.push(event),
+            Err(_) => events.extend(adapter.parse_frame(venue, line)?),
+        }
+    }
+    Ok(events)
+}
+
+/// When `event` happened for pacing: when it was received if recorded, as
+/// the venue stamped it otherwise.
+fn at(event: &NormalizedEvent) -> DateTime<Utc> {
+    event.received_at.unwrap_or(event.timestamp)
+}
+
+/// Send `events` to `output`, each as long after the first as it happened
+/// after it, divided by `speed`, and start over at the end if `looped`.
+/// Events that are due together are sent together.
+pub async fn replay(
+    events: &[NormalizedEvent],
+    speed: Speed,
+    looped: bool,
+    output: &Output,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(first) = events.first().map(at) else {
+        return Ok(());
+    };
+    loop {
+        let start = Instant::now();
+        let mut due = Vec::new();
+        for event in events {
+            if let Some(factor) = speed.0 {
+                // Events stamped out of order are sent at once.
+                let offset = (at(event) - first).to_std().unwrap_or_default();
+                let target = start + offset.div_f64(factor);
+                if target > Instant::now() {
+                    output.emit(&mut due).await?;
+                    tokio::time::sleep_until(target.into()).await;
+                }
+            }
+            due.push(event.clone());
+        }
+        output.emit(&mut due).await?;
+        if !looped {
+            return Ok(());
+        }
+    }
+}
@@ -0,0 +1,269 @@
+//! `devtools scaffold`: generate an adapter module from a declarative spec
+//! and register it with the builtin adapters.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use agents::AdapterRegistry;
+use serde::Deserialize;
+
+const ADAPTER: &str = include_str!("../templates/adapter.rs.tmpl");
+const TRADE: &str = include_str!("../templates/trade.rs.tmpl");
+const QUOTE: &str = include_str!("../templates/quote.rs.tmpl");
+const NUMBER: &str = include_str!("../templates/number.rs.tmpl");
+const DISPATCH: &str = include_str!("../templates/dispatch.rs.tmpl");
+
+/// An adapter to generate, as in `crates/agents/specs/`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Spec {
+    /// Venue the adapter is for, e.g. `kraken_spot`, naming its golden pack.
+    name: String,
+    /// Adapter type venues select it by, naming its module; the name up to
+    /// its first `_` by default.
+    #[serde(rename = "type", default)]
+    venue_type: Option<String>,
+    /// Stream endpoints, the first of which is the default stream base.
+    endpoints: Vec<String>,
+    /// The venue's name of each canonical channel to subscribe to. `trades`
+    /// and `quotes` are parsed, others pass through as raw events.
+    #[serde(default)]
+    channels: BTreeMap<String, String>,
+    /// Field of a message naming its channel; without one every message
+    /// passes through as a raw event.
+    #[serde(default)]
+    channel_field: Option<String>,
+    #[serde(default)]
+    fields: Fields,
+}
+
+/// The venue's name of each field of the normalized events.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Fields {
+    symbol: String,
+    /// Epoch milliseconds.
+    timestamp: String,
+    trades: TradeFields,
+    quotes: QuoteFields,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TradeFields {
+    trade_id: String,
+    price: String,
+    quantity: String,
+    /// `buy`/`sell`, or a boolean telling whether the buyer was the maker.
+    side: String,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct QuoteFields {
+    bid_price: String,
+    bid_quantity: String,
+    ask_price: String,
+    ask_quantity: String,
+}
+
+impl Default for Fields {
+    fn default() -> Self {
+        Self {
+            symbol: "symbol".into(),
+            timestamp: "timestamp".into(),
+            trades: TradeFields::default(),
+            quotes: QuoteFields::default(),
+        }
+    }
+}
+
+impl Default for TradeFields {
+    fn default() -> Self {
+        Self {
+            trade_id: "trade_id".into(),
+            price: "price".into(),
+            quantity: "quantity".into(),
+            side: "side".into(),
+        }
+    }
+}
+
+impl Default for QuoteFields {
+    fn default() -> Self {
+        Self {
+            bid_price: "bid_price".into(),
+            bid_quantity: "bid_quantity".into(),
+            ask_price: "ask_price".into(),
+            ask_quantity: "ask_quantity".into(),
+        }
+    }
+}
+
+/// Generate the adapter of the spec at `spec_path` as a module of the agents
+/// crate at `agents`, register it in `AdapterRegistry::builtin`, and create
+/// its golden pack directory under `golden`. Returns the files written.
+pub fn scaffold(
+    spec_path: &Path,
+    agents: &Path,
+    golden: &Path,
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let spec: Spec = toml::from_str(&fs::read_to_string(spec_path)?)?;
+    let venue_type = spec
+        .venue_type
+        .clone()
+        .unwrap_or_else(|| spec.name.split('_').next().unwrap_or_default().to_string());
+    let identifier = |name: &str| {
+        name.starts_with(|c: char| c.is_ascii_lowercase())
+            && name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    };
+    if !identifier(&venue_type) || !identifier(&spec.name) {
+        return Err(format!(
+            "name {:?} and type {:?} must be lowercase identifiers",
+            spec.name, venue_type
+        )
+        .into());
+    }
+    let Some(endpoint) = spec.endpoints.first() else {
+        return Err("the spec names no endpoints".into());
+    };
+    let lib = agents.join("src/lib.rs");
+    let module = agents.join(format!("src/{}.rs", venue_type));
+    let source = fs::read_to_string(&lib)?;
+    if AdapterRegistry::builtin().get(&venue_type).is_some()
+        || module.exists()
+        || source.contains(&format!("mod {} ", venue_type))
+        || source.contains(&format!("mod {};", venue_type))
+    {
+        return Err(format!("an adapter of type {} exists already", venue_type).into());
+    }
+
+    let camel: String = venue_type
+        .split('_')
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect();
+    let mut arms = String::new();
+    let mut parsers = String::new();
+    // Channels are told apart by the channel field only.
+    let parsed = |channel| spec.channel_field.is_some() && spec.channels.contains_key(channel);
+    if parsed("trades") {
+        arms.push_str("        Some(\"trades\") => parse_trade(venue, &payload)?,\n");
+        let trades = &spec.fields.trades;
+        parsers.push_str(&render(
+            TRADE,
+            &[
+                ("trade_id", &trades.trade_id),
+                ("price", &trades.price),
+                ("quantity", &trades.quantity),
+                ("side", &trades.side),
+            ],
+        ));
+    }
+    if parsed("quotes") {
+        arms.push_str("        Some(\"quotes\") => parse_quote(venue, &payload)?,\n");
+        let quotes = &spec.fields.quotes;
+        parsers.push_str(&render(
+            QUOTE,
+            &[
+                ("bid_price", &quotes.bid_price),
+                ("bid_quantity", &quotes.bid_quantity),
+                ("ask_price", &quotes.ask_price),
+                ("ask_quantity", &quotes.ask_quantity),
+            ],
+        ));
+    }
+    if !parsers.is_empty() {
+        parsers.push_str(NUMBER);
+    }
+    let channels = spec
+        .channels
+        .iter()
+        .map(|(canonical, name)| format!("({:?}, {:?})", canonical, name))
+        .collect::<Vec<_>>()
+        .join(", ");
+    // Without a parsed channel every message is raw, so there is nothing to
+    // dispatch on.
+    let (channel_field, dispatch) = match &spec.channel_field {
+        Some(field) if !arms.is_empty() => (
+            format!(
+                "\n/// Field of a message naming its channel.\nconst CHANNEL_FIELD: &str = {:?};\n",
+                field
+            ),
+            render(DISPATCH, &[("arms", &arms)]),
+        ),
+        _ => (
+            String::new(),
+            "    let event = event(venue, &payload, EventKind::Raw, payload.clone());\n"
+                .to_string(),
+        ),
+    };
+    let code = render(
+        ADAPTER,
+        &[
+            ("Camel", &camel),
+            ("spec", &spec_path.display().to_string()),
+            ("name", &spec.name),
+            ("type", &venue_type),
+            ("endpoint", endpoint),
+            ("channels", &channels),
+            ("channel_field", &channel_field),
+            ("dispatch", &dispatch),
+            ("parsers", &parsers),
+            ("symbol", &spec.fields.symbol),
+            ("timestamp", &spec.fields.timestamp),
+        ],
+    );
+    fs::write(&module, code)?;
+    fs::write(&lib, register(&source, &venue_type, &camel)?)?;
+    let pack = golden.join(&spec.name);
+    fs::create_dir_all(&pack)?;
+    // Keeps the empty pack directory in git until fixtures are recorded.
+    let keep = pack.join(".gitkeep");
+    fs::write(&keep, "")?;
+
+    // Best effort: the generated code is valid unformatted too.
+    let _ = Command::new("rustfmt")
+        .args(["--edition", "2021"])
+        .arg(&module)
+        .arg(&lib)
+        .status();
+    Ok(vec![module, lib, keep])
+}
+
+/// `template` with each `{{key}}` replaced by its value.
+fn render(template: &str, values: &[(&str, &str)]) -> String {
+    values
+        .iter()
+        .fold(template.to_string(), |text, (key, value)| {
+            text.replace(&format!("{{{{{}}}}}", key), value)
+        })
+}
+
+/// `lib`, the agents crate root, declaring the module of `venue_type` and
+/// registering its adapter with the builtin ones.
+fn register(lib: &str, venue_type: &str, camel: &str) -> Result<String, String> {
+    const GOLDEN: &str = "pub mod golden;\n";
+    const BUILTIN: &str = "    pub fn builtin() -> Self {\n";
+    let unexpected = || "crates/agents/src/lib.rs is not laid out as expected".to_string();
+    let mut lib = lib.replacen(GOLDEN, &format!("{}pub mod {};\n", GOLDEN, venue_type), 1);
+    let body = lib.find(BUILTIN).ok_or_else(unexpected)? + BUILTIN.len();
+    let end = body + lib[body..].find("\n    }\n").ok_or_else(unexpected)?;
+    lib.insert_str(
+        end,
+        &format!(
+            "\n            .with({:?}, {}::{}Adapter)",
+            venue_type, venue_type, camel
+        ),
+    );
+    Ok(lib)
+}
@@ -0,0 +1,232 @@
+//! `devtools scrub`: rewrite what identifies an account in a golden pack,
+//! so real captures can be shared as fixtures.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use agents::{golden, Adapter};
+use serde_json::{Map, Value};
+
+/// Fields that identify an account, one of its orders or a host, in any
+/// frame or event.
+const FIELDS: &[&str] = &[
+    "account",
+    "accountId",
+    "subAccountId",
+    "uid",
+    "userId",
+    "email",
+    "apiKey",
+    "listenKey",
+    "orderId",
+    "clientOrderId",
+    "origClientOrderId",
+    "orderListId",
+    "listClientOrderId",
+    "ip",
+    "clientIp",
+    "ipAddress",
+    "remoteAddr",
+];
+
+/// The user data events of Binance's private streams, by their `e`, with
+/// the abbreviated fields that name the account's orders.
+const PRIVATE: &[(&str, &[&str])] = &[
+    ("executionReport", &["i", "c", "C", "g"]),
+    ("listStatus", &["g", "C", "i", "c"]),
+];
+
+/// What scrubbing a fixture changed.
+#[derive(Default)]
+pub struct Scrubbed {
+    pub frames: usize,
+    pub fields: usize,
+    pub addresses: usize,
+}
+
+/// Replaces sensitive values with pseudonyms, the same one for every
+/// occurrence of a value, so that frames still refer to each other.
+pub struct Scrubber {
+    strip: bool,
+    pseudonyms: HashMap<String, usize>,
+    addresses: HashMap<String, usize>,
+    scrubbed: Scrubbed,
+}
+
+impl Scrubber {
+    /// A scrubber rewriting sensitive fields, or removing them if `strip`.
+    pub fn new(strip: bool) -> Self {
+        Self {
+            strip,
+            pseudonyms: HashMap::new(),
+            addresses: HashMap::new(),
+            scrubbed: Scrubbed::default(),
+        }
+    }
+
+    /// Scrub every line of `text`, keeping lines that need nothing as they
+    /// are, byte for byte.
+    fn lines(&mut self, text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        for line in text.lines() {
+            let scrubbed = match serde_json::from_str::<Value>(line) {
+                Ok(mut value) => {
+                    if self.value(&mut value, &[]) {
+                        serde_json::to_string(&value).unwrap_or_default()
+                    } else {
+                        line.to_string()
+                    }
+                }
+                // Not JSON, so only addresses can be found in it.
+                Err(_) => self.text(line),
+            };
+            if scrubbed != line {
+                self.scrubbed.frames += 1;
+            }
+            out.push_str(&scrubbed);
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Scrub `value`, whose object fields named in `private` are sensitive
+    /// too, and return whether it changed.
+    fn value(&mut self, value: &mut Value, private: &[&str]) -> bool {
+        match value {
+            Value::Object(object) => self.object(object, private),
+            Value::Array(items) => {
+                let mut changed = false;
+                for item in items {
+                    changed |= self.value(item, private);
+                }
+                changed
+            }
+            Value::String(text) => {
+                let scrubbed = self.text(text);
+                let changed = scrubbed != *text;
+                *text = scrubbed;
+                changed
+            }
+            _ => false,
+        }
+    }
+
+    fn object(&mut self, object: &mut Map<String, Value>, private: &[&str]) -> bool {
+        let event = object.get("e").and_then(Value::as_str).unwrap_or_default();
+        let private = PRIVATE
+            .iter()
+            .find(|(name, _)| *name == event)
+            .map_or(private, |(_, fields)| *fields);
+        let sensitive: Vec<String> = object
+            .keys()
+            .filter(|key| FIELDS.contains(&key.as_str()) || private.contains(&key.as_str()))
+            .cloned()
+            .collect();
+
+        let mut changed = false;
+        for key in &sensitive {
+            if self.strip {
+                object.remove(key);
+                self.scrubbed.fields += 1;
+                changed = true;
+            } else if let Some(value) = object.get_mut(key) {
+                changed |= self.pseudonym(value);
+            }
+        }
+        for (key, value) in object.iter_mut() {
+            if !sensitive.contains(key) {
+                changed |= self.value(value, private);
+            }
+        }
+        changed
+    }
+
+    /// Replace the sensitive `value` with its pseudonym, of the same type.
+    fn pseudonym(&mut self, value: &mut Value) -> bool {
+        let next = self.pseudonyms.len() + 1;
+        let scrubbed = match value {
+            // Zero and negative ids mean none, e.g. `"g": -1` outside lists.
+            Value::Number(n) if n.as_i64().is_some_and(|n| n <= 0) => return false,
+            Value::Number(n) => Value::from(*self.pseudonyms.entry(n.to_string()).or_insert(next)),
+            Value::String(s) if s.is_empty() => return false,
+            Value::String(s) if is_ipv4(s) => Value::from(self.text(s)),
+            Value::String(s) => {
+                let n = *self.pseudonyms.entry(s.clone()).or_insert(next);
+                Value::from(format!("scrubbed-{}", n))
+            }
+            Value::Null | Value::Bool(_) => return false,
+            // Structures under a sensitive field are dropped whole.
+            Value::Array(_) | Value::Object(_) => Value::Null,
+        };
+        self.scrubbed.fields += 1;
+        *value = scrubbed;
+        true
+    }
+
+    /// `text` with every IPv4 address in it replaced by one reserved for
+    /// documentation, 192.0.2.0/24.
+    fn text(&mut self, text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find(|c: char| c.is_ascii_digit()) {
+            let end = rest[start..]
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .map_or(rest.len(), |end| start + end);
+            let candidate = rest[start..end].trim_end_matches('.');
+            let end = start + candidate.len();
+            out.push_str(&rest[..start]);
+            if is_ipv4(candidate) {
+                let next = self.addresses.len() + 1;
+                let n = *self.addresses.entry(candidate.to_string()).or_insert(next);
+                out.push_str(&format!("192.0.2.{}", n % 256));
+                self.scrubbed.addresses += 1;
+            } else {
+                out.push_str(candidate);
+            }
+            rest = &rest[end..];
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+fn is_ipv4(text: &str) -> bool {
+    let octets: Vec<&str> = text.split('.').collect();
+    octets.len() == 4
+        && octets
+            .iter()
+            .all(|octet| !octet.is_empty() && octet.len() <= 3 && octet.parse::<u8>().is_ok())
+}
+
+/// Scrub the frames of `fixture` and the events recorded with it, then
+/// render its snapshot anew with `adapter` for `venue`. Writes the pack's
+/// files to `out` if given, over the originals otherwise.
+pub fn scrub(
+    fixture: &Path,
+    venue: &str,
+    adapter: &dyn Adapter,
+    out: Option<&Path>,
+    scrubber: &mut Scrubber,
+) -> Result<Scrubbed, Box<dyn std::error::Error>> {
+    let dir = match out {
+        Some(dir) => dir,
+        None => fixture.parent().unwrap_or(Path::new(".")),
+    };
+    let target = dir.join(fixture.file_name().unwrap_or_default());
+    fs::create_dir_all(dir)?;
+    scrubber.scrubbed = Scrubbed::default();
+
+    let raw = scrubber.lines(&fs::read_to_string(fixture)?);
+    let events = golden::events_path(fixture);
+    if events.exists() {
+        let scrubbed = scrubber.lines(&fs::read_to_string(&events)?);
+        fs::write(golden::events_path(&target), scrubbed)?;
+    }
+    fs::write(
+        golden::snapshot_path(&target),
+        golden::render(adapter, venue, &raw)?,
+    )?;
+    fs::write(&target, raw)?;
+    Ok(std::mem::take(&mut scrubber.scrubbed))
+}
@@ -0,0 +1,147 @@
+//! `devtools soak`: run the engine for hours, watching for leaks and
+//! stalls, before a release.
+
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use ingest_core::{config::Config, event::ENGINE_VENUE};
+use ingest_engine::{logging, Engine};
+use tokio::time::Instant;
+
+use crate::alloc;
+
+/// When a soak run fails.
+pub struct Thresholds {
+    /// Most bytes the heap may grow by after the warmup.
+    pub max_growth: usize,
+    /// Most file descriptors the process may open after the warmup.
+    pub max_fd_growth: usize,
+    /// Longest time without events.
+    pub stall: Duration,
+}
+
+/// The process at one point of the run.
+struct Sample {
+    heap: usize,
+    rss: Option<usize>,
+    fds: Option<usize>,
+}
+
+impl Sample {
+    fn take() -> Self {
+        Self {
+            heap: alloc::live(),
+            rss: rss(),
+            fds: fds(),
+        }
+    }
+}
+
+/// Resident set size, from procfs where there is one.
+fn rss() -> Option<usize> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kib: usize = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// Open file descriptors, from procfs where there is one.
+fn fds() -> Option<usize> {
+    Some(fs::read_dir("/proc/self/fd").ok()?.count())
+}
+
+fn mib(bytes: usize) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+fn elapsed(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}h{:02}m{:02}s", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// Run the engine with the config at `path` for `duration`, printing the
+/// heap, resident memory, open files and event rate every `interval`.
+/// Growth is measured from the end of `warmup`, when caches and buffers
+/// have filled. Fails as soon as a threshold is crossed.
+pub async fn soak(
+    path: &Path,
+    profile: Option<&str>,
+    duration: Duration,
+    warmup: Duration,
+    interval: Duration,
+    thresholds: &Thresholds,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let data = fs::read_to_string(path)?;
+    let cfg = Config::from_str_with_profile(&data, profile)
+        .map_err(|e| format!("{}: {}", path.display(), e.message()))?;
+    let _log_filter = logging::init(&cfg.log)?;
+    let engine = Engine::builder().config(cfg).build()?;
+    let mut events = engine.bus().subscribe();
+    let engine = engine.start()?;
+
+    let started = Instant::now();
+    let mut baseline: Option<Sample> = None;
+    let mut ticks = tokio::time::interval_at(started + interval, interval);
+    let (mut total, mut counted, mut last_event) = (0u64, 0u64, started);
+    let result = loop {
+        tokio::select! {
+            event = events.recv() => {
+                let Some(event) = event else {
+                    break Err("the bus closed".to_string());
+                };
+                if event.venue != ENGINE_VENUE {
+                    counted += 1;
+                    last_event = Instant::now();
+                }
+            }
+            now = ticks.tick() => {
+                let sample = Sample::take();
+                let since = now - started;
+                total += counted;
+                println!(
+                    "[{}] heap {}, rss {}, {} fds, {:.1} events/s, {} events",
+                    elapsed(since),
+                    mib(sample.heap),
+                    sample.rss.map_or("-".into(), mib),
+                    sample.fds.map_or("-".into(), |fds| fds.to_string()),
+                    counted as f64 / interval.as_secs_f64(),
+                    total
+                );
+                counted = 0;
+                if now - last_event >= thresholds.stall {
+                    break Err(format!("no events for {}", elapsed(now - last_event)));
+                }
+                match &baseline {
+                    None if since >= warmup => baseline = Some(sample),
+                    None => {}
+                    Some(baseline) => {
+                        let growth = sample.heap.saturating_sub(baseline.heap);
+                        if growth > thresholds.max_growth {
+                            break Err(format!("heap grew by {} since the warmup", mib(growth)));
+                        }
+                        if let (Some(fds), Some(before)) = (sample.fds, baseline.fds) {
+                            if fds.saturating_sub(before) > thresholds.max_fd_growth {
+                                break Err(format!(
+                                    "{} file descriptors opened since the warmup",
+                                    fds - before
+                                ));
+                            }
+                        }
+                    }
+                }
+                if since >= duration {
+                    break Ok(());
+                }
+            }
+        }
+    };
+    engine.shutdown().await?;
+    result?;
+    println!(
+        "soak passed: {} events in {}",
+        total,
+        elapsed(started.elapsed())
+    );
+    Ok(())
+}
@@ -0,0 +1,57 @@
+//! `devtools symbols`: preview the symbols and streams a venue of a config
+//! subscribes to, discovering them as the engine would.
+
+use std::fs;
+use std::path::Path;
+
+use agents::AdapterRegistry;
+use ingest_core::config::Config;
+
+/// Print the symbols `venue` of the config at `path` would subscribe to,
+/// one per line, discovered with its filters when it lists none, then how
+/// many streams they make up to stderr.
+pub async fn symbols(
+    path: &Path,
+    profile: Option<&str>,
+    venue: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let data = fs::read_to_string(path)?;
+    let cfg = Config::from_str_with_profile(&data, profile)
+        .map_err(|e| format!("{}: {}", path.display(), e.message()))?;
+    let Some(venue) = cfg.venues.iter().find(|v| v.name == venue) else {
+        return Err(format!(
+            "{} has no venue {}, expected one of: {}",
+            path.display(),
+            venue,
+            cfg.venues
+                .iter()
+                .map(|v| v.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+        .into());
+    };
+    let registry = AdapterRegistry::builtin();
+    let Some(adapter) = registry.get(&venue.venue_type) else {
+        return Err(registry
+            .unknown(std::slice::from_ref(venue))
+            .join("\n")
+            .into());
+    };
+
+    let symbols = adapter.symbols(venue).await?;
+    for symbol in &symbols {
+        println!("{}", symbol);
+    }
+    eprintln!(
+        "{} {} symbols, {} streams",
+        if venue.symbols.is_empty() {
+            "discovered"
+        } else {
+            "configured"
+        },
+        symbols.len(),
+        adapter.streams(venue, &symbols).len()
+    );
+    Ok(())
+}
@@ -0,0 +1,132 @@
+//! `devtools tail`: follow the events of a running engine through its
+//! server-sent events or WebSocket endpoint, annotated with their latency.
+
+use chrono::Utc;
+use futures_util::StreamExt;
+use ingest_core::event::NormalizedEvent;
+use tokio_tungstenite::tungstenite::{client::IntoClientRequest, Message};
+
+/// Which events to follow, as the `GET /events` and `GET /ws` query
+/// parameters take them.
+pub struct Filter {
+    pub venues: Vec<String>,
+    pub symbols: Vec<String>,
+    pub kinds: Vec<String>,
+    /// Start with up to this many recent events; server-sent events only.
+    pub replay: Option<usize>,
+}
+
+impl Filter {
+    fn query(&self) -> String {
+        let mut params = Vec::new();
+        for (name, values) in [
+            ("venue", &self.venues),
+            ("symbol", &self.symbols),
+            ("kind", &self.kinds),
+        ] {
+            if !values.is_empty() {
+                params.push(format!("{}={}", name, values.join(",")));
+            }
+        }
+        if let Some(replay) = self.replay {
+            params.push(format!("replay={}", replay));
+        }
+        params.join("&")
+    }
+}
+
+/// Follow the events matching `filter` from the engine whose ops server is
+/// at `url`, through `GET /events` for an `http(s)` URL and `GET /ws` for a
+/// `ws(s)` one, printing each as JSON when `json` and as a line otherwise,
+/// until the stream ends.
+pub async fn tail(
+    url: &str,
+    filter: &Filter,
+    token: Option<&str>,
+    json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let base = url.trim_end_matches('/');
+    if base.starts_with("ws://") || base.starts_with("wss://") {
+        // Passing the encoding subscribes as the socket opens, even to
+        // everything.
+        let mut request =
+            format!("{}/ws?{}&encoding=json", base, filter.query()).into_client_request()?;
+        if let Some(token) = token {
+            request
+                .headers_mut()
+                .insert("authorization", format!("Bearer {}", token).parse()?);
+        }
+        let (mut socket, _) = tokio_tungstenite::connect_async(request).await?;
+        while let Some(message) = socket.next().await {
+            if let Message::Text(text) = message? {
+                print(&serde_json::from_str(&text)?, json);
+            }
+        }
+        return Ok(());
+    }
+
+    let mut request = reqwest::Client::new().get(format!("{}/events?{}", base, filter.query()));
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let mut response = request.send().await?.error_for_status()?;
+    // Server-sent events: `data:` lines, ended by a blank line. Comments
+    // and event names are not needed, as the data names the kind.
+    let (mut buffer, mut data) = (String::new(), String::new());
+    while let Some(chunk) = response.chunk().await? {
+        buffer.push_str(&String::from_utf8_lossy(&chunk));
+        while let Some(end) = buffer.find('\n') {
+            let line: String = buffer.drain(..=end).collect();
+            let line = line.trim_end_matches(['\n', '\r']);
+            if let Some(value) = line.strip_prefix("data:") {
+                data.push_str(value.strip_prefix(' ').unwrap_or(value));
+            } else if line.is_empty() && !data.is_empty() {
+                print(&serde_json::from_str(&data)?, json);
+                data.clear();
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Print `event` with its latency: as JSON with a `latency_ms` field, or as
+/// `12:00:00.123 binance_spot BTC-USD trade price=101.5 quantity=0.25
+/// side=buy (latency 14ms, venue 9ms)`.
+fn print(event: &NormalizedEvent, json: bool) {
+    let now = Utc::now();
+    let latency = (now - event.timestamp).num_milliseconds();
+    // How long the venue took to deliver it, when the adapter stamped it.
+    let venue = event
+        .received_at
+        .map(|received_at| (received_at - event.timestamp).num_milliseconds());
+    if json {
+        let mut value = serde_json::to_value(event).unwrap_or_default();
+        value["latency_ms"] = latency.into();
+        println!("{}", value);
+        return;
+    }
+    let payload = match &event.payload {
+        serde_json::Value::Object(fields) => fields
+            .iter()
+            .map(|(name, value)| match value {
+                serde_json::Value::String(text) => format!("{}={}", name, text),
+                value => format!("{}={}", name, value),
+            })
+            .collect::<Vec<_>>()
+            .join(" "),
+        payload => payload.to_string(),
+    };
+    let venue = venue
+        .map(|venue| format!(", venue {}ms", venue))
+        .unwrap_or_default();
+    println!(
+        "{} {} {} {} {} (latency {}ms{})",
+        event.timestamp.format("%H:%M:%S%.3f"),
+        event.venue,
+        event.symbol,
+        event.kind.as_str(),
+        payload,
+        latency,
+        venue
+    );
+}
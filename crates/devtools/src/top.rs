@@ -0,0 +1,279 @@
+//! `devtools top`: a live dashboard of a running engine in the terminal,
+//! redrawn from its `/status` and `/metrics` endpoints.
+//!
+//! ratatui, the usual widget library for this, is not in the offline registry
+//! this workspace builds from, so the dashboard is laid out as fixed-width
+//! text and drawn with crossterm's screen commands instead.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::io::Write as _;
+use std::time::Duration;
+
+use crossterm::{
+    cursor::MoveTo,
+    terminal::{Clear, ClearType},
+};
+use ingest_core::event::ENGINE_VENUE;
+use serde::Deserialize;
+use tokio::time::Instant;
+
+/// The parts of `GET /status` the dashboard shows.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Status {
+    version: String,
+    uptime_secs: u64,
+    events_per_second: f64,
+    venues: Vec<VenueStatus>,
+    pipeline: Vec<StageStatus>,
+    sinks: Vec<SinkStatus>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct VenueStatus {
+    name: String,
+    paused: bool,
+    connected: bool,
+    reconnects: u64,
+    restarts: u64,
+    circuit_open: bool,
+    last_message_age_secs: f64,
+    symbols: usize,
+    events_per_second: f64,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct StageStatus {
+    stage: String,
+    processed: u64,
+    queue_depth: i64,
+    restarts: u64,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct SinkStatus {
+    name: String,
+    queue_depth: i64,
+    delivered: u64,
+    errors: u64,
+    dropped: u64,
+}
+
+/// Events counted, by venue and symbol.
+type Counts = HashMap<(String, String), f64>;
+
+/// The counts of `symbol_events_total`, under any configured metric prefix,
+/// of events from venues.
+fn symbol_counts(metrics: &str) -> Counts {
+    let mut counts = HashMap::new();
+    for line in metrics.lines().filter(|line| !line.starts_with('#')) {
+        let Some((series, value)) = line.rsplit_once(' ') else {
+            continue;
+        };
+        let Some((name, labels)) = series.split_once('{') else {
+            continue;
+        };
+        if name != "symbol_events_total" && !name.ends_with("_symbol_events_total") {
+            continue;
+        }
+        let labels = parse_labels(labels.trim_end_matches('}'));
+        if let (Some(venue), Some(symbol), Ok(value)) =
+            (labels.get("venue"), labels.get("symbol"), value.parse())
+        {
+            if venue == ENGINE_VENUE {
+                continue;
+            }
+            counts.insert((venue.clone(), symbol.clone()), value);
+        }
+    }
+    counts
+}
+
+/// `a="1",b="x\"y"` as a map, unescaping the values.
+fn parse_labels(text: &str) -> HashMap<String, String> {
+    let mut labels = HashMap::new();
+    let mut rest = text;
+    while let Some((name, after)) = rest.split_once("=\"") {
+        let mut value = String::new();
+        let mut chars = after.char_indices();
+        let mut end = after.len();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, other)) => value.push(other),
+                    None => {}
+                },
+                '"' => {
+                    end = i + 1;
+                    break;
+                }
+                c => value.push(c),
+            }
+        }
+        labels.insert(name.trim_start_matches(',').trim().to_string(), value);
+        rest = &after[end..];
+    }
+    labels
+}
+
+/// Redraw the dashboard of the engine whose ops server is at `url` every
+/// `interval`, with the `symbols` busiest symbols, until interrupted.
+pub async fn top(
+    url: &str,
+    token: Option<&str>,
+    interval: Duration,
+    symbols: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let base = url.trim_end_matches('/');
+    let client = reqwest::Client::builder()
+        .timeout(interval.max(Duration::from_secs(2)))
+        .build()?;
+    let get = |path: &str| {
+        let request = client.get(format!("{}{}", base, path));
+        match token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    };
+    let mut previous: Option<(Instant, Counts)> = None;
+    let mut ticks = tokio::time::interval(interval);
+    loop {
+        ticks.tick().await;
+        let status = async {
+            let response = get("/status").send().await?.error_for_status()?;
+            response.json::<Status>().await
+        };
+        let metrics = async {
+            let response = get("/metrics").send().await?.error_for_status()?;
+            response.text().await
+        };
+        let (status, metrics) = tokio::join!(status, metrics);
+        let now = Instant::now();
+        let counts = metrics.as_deref().map(symbol_counts).unwrap_or_default();
+        let rates = match &previous {
+            Some((at, before)) => rates(&counts, before, now - *at),
+            None => Vec::new(),
+        };
+        previous = Some((now, counts));
+
+        let mut screen = String::new();
+        match &status {
+            Ok(status) => render(&mut screen, base, status, &rates, symbols)?,
+            Err(e) => writeln!(screen, "{}: {}", base, e)?,
+        }
+        if let Err(e) = &metrics {
+            writeln!(screen, "\nmetrics: {}", e)?;
+        }
+        let mut out = std::io::stdout().lock();
+        crossterm::queue!(out, Clear(ClearType::All), MoveTo(0, 0))?;
+        out.write_all(screen.as_bytes())?;
+        out.flush()?;
+    }
+}
+
+/// Events per second of each venue and symbol between two counts, busiest
+/// first. Symbols that just entered the top have no rate yet.
+fn rates(counts: &Counts, before: &Counts, elapsed: Duration) -> Vec<(String, String, f64)> {
+    let mut rates: Vec<_> = counts
+        .iter()
+        .filter_map(|(key, count)| {
+            let before = before.get(key)?;
+            let rate = (count - before).max(0.0) / elapsed.as_secs_f64();
+            Some((key.0.clone(), key.1.clone(), rate))
+        })
+        .collect();
+    rates.sort_by(|a, b| {
+        b.2.total_cmp(&a.2)
+            .then_with(|| (&a.0, &a.1).cmp(&(&b.0, &b.1)))
+    });
+    rates
+}
+
+fn render(
+    screen: &mut String,
+    base: &str,
+    status: &Status,
+    rates: &[(String, String, f64)],
+    symbols: usize,
+) -> std::fmt::Result {
+    let up = status.uptime_secs;
+    writeln!(
+        screen,
+        "{}  ingestd {}  up {}h{:02}m{:02}s  {:.1} events/s",
+        base,
+        status.version,
+        up / 3600,
+        up / 60 % 60,
+        up % 60,
+        status.events_per_second
+    )?;
+
+    writeln!(
+        screen,
+        "\n{:<20} {:<12} {:>10} {:>8} {:>10} {:>8} {:>10}",
+        "VENUE", "STATE", "EVENTS/S", "SYMBOLS", "RECONNECTS", "RESTARTS", "LAST MSG"
+    )?;
+    for venue in &status.venues {
+        let state = if venue.paused {
+            "paused"
+        } else if venue.circuit_open {
+            "circuit open"
+        } else if venue.connected {
+            "connected"
+        } else {
+            "down"
+        };
+        writeln!(
+            screen,
+            "{:<20} {:<12} {:>10.1} {:>8} {:>10} {:>8} {:>9.1}s",
+            venue.name,
+            state,
+            venue.events_per_second,
+            venue.symbols,
+            venue.reconnects,
+            venue.restarts,
+            venue.last_message_age_secs
+        )?;
+    }
+
+    writeln!(
+        screen,
+        "\n{:<20} {:<20} {:>10}",
+        "VENUE", "SYMBOL", "EVENTS/S"
+    )?;
+    for (venue, symbol, rate) in rates.iter().take(symbols) {
+        writeln!(screen, "{:<20} {:<20} {:>10.1}", venue, symbol, rate)?;
+    }
+
+    writeln!(
+        screen,
+        "\n{:<20} {:>12} {:>14} {:>8}",
+        "STAGE", "QUEUE DEPTH", "PROCESSED", "RESTARTS"
+    )?;
+    for stage in &status.pipeline {
+        writeln!(
+            screen,
+            "{:<20} {:>12} {:>14} {:>8}",
+            stage.stage, stage.queue_depth, stage.processed, stage.restarts
+        )?;
+    }
+
+    writeln!(
+        screen,
+        "\n{:<20} {:>12} {:>14} {:>8} {:>8}",
+        "SINK", "LAG", "DELIVERED", "ERRORS", "DROPPED"
+    )?;
+    for sink in &status.sinks {
+        writeln!(
+            screen,
+            "{:<20} {:>12} {:>14} {:>8} {:>8}",
+            sink.name, sink.queue_depth, sink.delivered, sink.errors, sink.dropped
+        )?;
+    }
+    Ok(())
+}
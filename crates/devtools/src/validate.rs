@@ -0,0 +1,138 @@
+//! `devtools validate-config`: parse a config file as the engine does,
+//! resolve its references, check it and print the effective config.
+
+use std::fs;
+use std::path::Path;
+
+use agents::AdapterRegistry;
+use ingest_core::config::Config;
+
+/// Keys whose values are masked in the printed config.
+const SECRETS: &[&str] = &[
+    "token",
+    "tokens",
+    "password",
+    "secret_access_key",
+    "session_token",
+    "sas_token",
+    "routing_key",
+];
+
+/// Parse the config at `path` with `profile` applied and its references
+/// resolved, print what the engine ignores in it to stderr, the effective
+/// config to stdout, secrets masked unless `show_secrets`, then its
+/// problems. Fails if there are any.
+pub fn validate_config(
+    path: &Path,
+    profile: Option<&str>,
+    show_secrets: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let data = fs::read_to_string(path)?;
+    let cfg = Config::from_str_with_profile(&data, profile)
+        .map_err(|e| format!("{}: {}", path.display(), e.message()))?;
+    for key in ignored(&toml::from_str(&data)?) {
+        eprintln!("ignored: {}", key);
+    }
+
+    let mut effective = toml::Value::try_from(&cfg)?;
+    if !show_secrets {
+        mask(&mut effective);
+    }
+    print!("{}", toml::to_string_pretty(&effective)?);
+
+    let mut problems = cfg.validate();
+    problems.extend(AdapterRegistry::builtin().unknown(&cfg.venues));
+    for problem in &problems {
+        eprintln!("problem: {}", problem);
+    }
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{} is invalid", path.display()).into())
+    }
+}
+
+/// The keys of `raw` the engine does not read: unknown sections, and
+/// unknown keys of venues and their channels.
+fn ignored(raw: &toml::Value) -> Vec<String> {
+    let schema = Config::schema();
+    let known = |definition: Option<&str>| -> Vec<String> {
+        let properties = match definition {
+            Some(name) => &schema["$defs"][name]["properties"],
+            None => &schema["properties"],
+        };
+        properties
+            .as_object()
+            .map(|properties| properties.keys().cloned().collect())
+            .unwrap_or_default()
+    };
+    let (sections, venue_keys, channel_keys) = (
+        known(None),
+        known(Some("VenueConfig")),
+        known(Some("ChannelConfig")),
+    );
+    let mut ignored = Vec::new();
+    let Some(table) = raw.as_table() else {
+        return ignored;
+    };
+    let mut venues = Vec::new();
+    for (key, value) in table {
+        match key.as_str() {
+            "venues" => venues.extend(
+                value
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .enumerate()
+                    .map(|(i, venue)| (format!("venues[{}]", i), venue, false)),
+            ),
+            // The `[venue.<name>]` format, and its defaults.
+            "venue" => venues.extend(
+                value
+                    .as_table()
+                    .into_iter()
+                    .flatten()
+                    .map(|(name, venue)| (format!("venue.{}", name), venue, true)),
+            ),
+            "discovery" if table.contains_key("venue") => {}
+            key if !sections.iter().any(|section| section == key) => ignored.push(key.to_string()),
+            _ => {}
+        }
+    }
+    for (path, venue, legacy) in venues {
+        for (key, value) in venue.as_table().into_iter().flatten() {
+            if key == "channels" {
+                for channel in value.as_table().into_iter().flatten().map(|(k, _)| k) {
+                    if !channel_keys.contains(channel) {
+                        ignored.push(format!("{}.channels.{}", path, channel));
+                    }
+                }
+            } else if !(venue_keys.contains(key) || legacy && key == "enabled") {
+                ignored.push(format!("{}.{}", path, key));
+            }
+        }
+    }
+    ignored
+}
+
+/// `value` with the values of the [`SECRETS`] keys masked.
+fn mask(value: &mut toml::Value) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter_mut() {
+                if SECRETS.contains(&key.as_str()) {
+                    *value = match value {
+                        toml::Value::Array(items) => {
+                            toml::Value::Array(items.iter().map(|_| "***".into()).collect())
+                        }
+                        _ => "***".into(),
+                    };
+                } else {
+                    mask(value);
+                }
+            }
+        }
+        toml::Value::Array(items) => items.iter_mut().for_each(mask),
+        _ => {}
+    }
+}
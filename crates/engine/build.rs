@@ -0,0 +1,56 @@
+use std::env;
+use std::process::Command;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+/// Record what the engine is built from for `ingest_engine::BUILD`: the
+/// commit (`INGEST_BUILD` overrides it, for builds outside a checkout), when
+/// it was built (`SOURCE_DATE_EPOCH` pins it, for reproducible builds) and
+/// the enabled features.
+fn main() {
+    println!("cargo:rerun-if-env-changed=INGEST_BUILD");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    if let Some(head) = git(&["rev-parse", "--git-path", "HEAD"]) {
+        println!("cargo:rerun-if-changed={head}");
+    }
+    if let Some(refs) = git(&["rev-parse", "--symbolic-full-name", "HEAD"])
+        .and_then(|name| git(&["rev-parse", "--git-path", &name]))
+    {
+        println!("cargo:rerun-if-changed={refs}");
+    }
+
+    let commit = env::var("INGEST_BUILD")
+        .ok()
+        .or_else(|| git(&["rev-parse", "--short=12", "HEAD"]));
+    let built_at = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse().ok())
+        .and_then(|secs| DateTime::from_timestamp(secs, 0))
+        .unwrap_or_else(Utc::now);
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(name, _)| {
+            let feature = name.strip_prefix("CARGO_FEATURE_")?;
+            Some(feature.to_lowercase().replace('_', "-"))
+        })
+        .collect();
+    features.sort();
+
+    println!(
+        "cargo:rustc-env=INGEST_COMMIT={}",
+        commit.unwrap_or_default()
+    );
+    println!(
+        "cargo:rustc-env=INGEST_BUILT_AT={}",
+        built_at.to_rfc3339_opts(SecondsFormat::Secs, true)
+    );
+    println!("cargo:rustc-env=INGEST_FEATURES={}", features.join(","));
+}
+
+/// Output of `git args`, or `None` outside a checkout or without git.
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8(output.stdout).ok()?.trim().to_string())
+}
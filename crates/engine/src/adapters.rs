@@ -0,0 +1,393 @@
+//! The venue adapters the engine runs, in its own process or in workers.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::time::Instant;
+
+use agents::AdapterRegistry;
+use ingest_core::{
+    config::{SupervisorConfig, VenueConfig},
+    error::IngestError,
+    event::NormalizedEvent,
+};
+use ops::AdapterMetrics;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+
+use crate::cluster::Cluster;
+use crate::standby::Promotion;
+use crate::supervisor;
+use crate::systemd::Systemd;
+use crate::watchdog::Watchdog;
+use crate::workers::Workers;
+
+/// Running venue adapters, each restarted when it fails, when its settings
+/// change or, with a watchdog, when it stops delivering events. With
+/// workers, adapters run in worker processes and each of those restarts
+/// restarts the venue's worker. In a cluster, only the venues this instance
+/// leads run, and in a dormant standby none until promoted. Paused venues
+/// have their adapters stopped until resumed.
+pub(crate) struct Adapters {
+    tx: mpsc::Sender<NormalizedEvent>,
+    metrics: AdapterMetrics,
+    pub(crate) registry: AdapterRegistry,
+    supervisor: SupervisorConfig,
+    /// In the order they were configured.
+    pub(crate) venues: Vec<VenueConfig>,
+    /// By venue, or by worker with workers.
+    tasks: HashMap<String, JoinHandle<()>>,
+    watchdog: Option<Watchdog>,
+    systemd: Systemd,
+    workers: Option<Workers>,
+    cluster: Option<Cluster>,
+    /// Until promoted, as a dormant standby.
+    dormant: Option<watch::Receiver<Option<Promotion>>>,
+    /// The venues led, in a cluster or once promoted.
+    leading: BTreeSet<String>,
+    paused: Paused,
+}
+
+/// Venues paused through the control channel.
+#[derive(Default)]
+struct Paused {
+    all: bool,
+    venues: HashSet<String>,
+}
+
+impl Paused {
+    fn contains(&self, venue: &str) -> bool {
+        self.all || self.venues.contains(venue)
+    }
+}
+
+impl Adapters {
+    pub(crate) fn new(
+        tx: mpsc::Sender<NormalizedEvent>,
+        metrics: AdapterMetrics,
+        registry: AdapterRegistry,
+        supervisor: SupervisorConfig,
+        watchdog: Option<Watchdog>,
+        systemd: Systemd,
+        workers: Option<Workers>,
+    ) -> Self {
+        Self {
+            tx,
+            metrics,
+            registry,
+            supervisor,
+            venues: Vec::new(),
+            tasks: HashMap::new(),
+            watchdog,
+            systemd,
+            workers,
+            cluster: None,
+            dormant: None,
+            leading: BTreeSet::new(),
+            paused: Paused::default(),
+        }
+    }
+
+    /// Run only the venues this instance leads in `cluster`.
+    pub(crate) fn with_cluster(mut self, cluster: Cluster) -> Self {
+        self.cluster = Some(cluster);
+        self
+    }
+
+    /// Run no venue until `promoted`.
+    pub(crate) fn dormant_until(mut self, promoted: watch::Receiver<Option<Promotion>>) -> Self {
+        self.dormant = Some(promoted);
+        self
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&VenueConfig> {
+        self.venues.iter().find(|venue| venue.name == name)
+    }
+
+    /// Run the adapter of `venue`'s type, in place of one already running
+    /// for it. Types are checked along with the config, so every venue
+    /// started has one.
+    fn start(&mut self, venue: VenueConfig) {
+        self.start_all(vec![venue]);
+    }
+
+    /// Start `venues` as [`start`](Self::start) does, restarting each
+    /// worker they run in once.
+    pub(crate) fn start_all(&mut self, venues: Vec<VenueConfig>) {
+        if self.workers.is_none() {
+            for venue in venues {
+                if self.runs(&venue.name) {
+                    self.start_adapter(venue);
+                } else {
+                    self.keep(venue);
+                }
+            }
+            return;
+        }
+        let mut started = BTreeSet::new();
+        for venue in venues {
+            if self.runs(&venue.name) {
+                self.metrics.track(&venue.name);
+            }
+            if let Some(workers) = &self.workers {
+                started.insert(workers.worker_of(&venue.name).to_string());
+            }
+            self.keep(venue);
+        }
+        for worker in started {
+            self.start_worker(&worker);
+        }
+    }
+
+    fn start_adapter(&mut self, venue: VenueConfig) {
+        let Some(adapter) = self.registry.get(&venue.venue_type) else {
+            tracing::error!("venue {} has unknown type {}", venue.name, venue.venue_type);
+            return;
+        };
+        if let Some(task) = self.tasks.remove(&venue.name) {
+            task.abort();
+            // Stopped mid-connection, the adapter could not report it.
+            let _ = self
+                .tx
+                .try_send(NormalizedEvent::adapter_status(&venue.name, false));
+        }
+        self.metrics.track(&venue.name);
+        let task = tokio::spawn(supervisor::supervise(
+            adapter,
+            venue.clone(),
+            self.tx.clone(),
+            self.supervisor.clone(),
+            self.metrics.clone(),
+        ));
+        self.tasks.insert(venue.name.clone(), task);
+        self.keep(venue);
+    }
+
+    /// Run the worker `name` with the venues it runs, in place of one
+    /// already running, or only stop it if it has none left.
+    fn start_worker(&mut self, name: &str) {
+        let Some(workers) = &self.workers else {
+            return;
+        };
+        let venues: Vec<_> = self
+            .venues
+            .iter()
+            .filter(|venue| workers.worker_of(&venue.name) == name && self.runs(&venue.name))
+            .cloned()
+            .collect();
+        if let Some(task) = self.tasks.remove(name) {
+            task.abort();
+            // Killed mid-connection, its adapters could not report it.
+            for venue in &venues {
+                let _ = self
+                    .tx
+                    .try_send(NormalizedEvent::adapter_status(&venue.name, false));
+            }
+        }
+        if venues.is_empty() {
+            return;
+        }
+        let task = workers.spawn(
+            name,
+            venues,
+            self.tx.clone(),
+            self.supervisor.clone(),
+            self.metrics.clone(),
+        );
+        self.tasks.insert(name.to_string(), task);
+    }
+
+    /// Record `venue` as running, in place of its previous settings.
+    fn keep(&mut self, venue: VenueConfig) {
+        if let Some(cluster) = &self.cluster {
+            cluster.campaign(&venue.name);
+        }
+        match self
+            .venues
+            .iter_mut()
+            .find(|running| running.name == venue.name)
+        {
+            Some(running) => *running = venue,
+            None => self.venues.push(venue),
+        }
+    }
+
+    /// Stop the adapter of `name` and forget the venue.
+    pub(crate) fn stop(&mut self, name: &str) {
+        self.venues.retain(|venue| venue.name != name);
+        self.paused.venues.remove(name);
+        self.metrics.untrack(name);
+        if let Some(cluster) = &self.cluster {
+            cluster.resign(name);
+        }
+        match self
+            .workers
+            .as_ref()
+            .map(|workers| workers.worker_of(name).to_string())
+        {
+            Some(worker) => self.start_worker(&worker),
+            None => {
+                if let Some(task) = self.tasks.remove(name) {
+                    task.abort();
+                }
+            }
+        }
+    }
+
+    /// Whether this instance runs `venue`: unless it is paused, always
+    /// outside a cluster, unless a dormant standby.
+    fn runs(&self, venue: &str) -> bool {
+        let leads =
+            (self.cluster.is_none() && self.dormant.is_none()) || self.leading.contains(venue);
+        leads && !self.paused.contains(venue)
+    }
+
+    /// The venues whose adapters run.
+    fn running(&self) -> BTreeSet<String> {
+        self.venues
+            .iter()
+            .filter(|venue| self.runs(&venue.name))
+            .map(|venue| venue.name.clone())
+            .collect()
+    }
+
+    pub(crate) fn paused(&self, venue: &str) -> bool {
+        self.paused.contains(venue)
+    }
+
+    /// Stop the adapter of `venue`, or of every venue without one, until
+    /// resumed, or start it again when `pause` is unset. Resuming every
+    /// venue resumes those paused one by one too.
+    pub(crate) fn pause(&mut self, venue: Option<&str>, pause: bool) -> Result<(), IngestError> {
+        let running = self.running();
+        match venue {
+            Some(venue) if self.get(venue).is_none() => {
+                return Err(IngestError::Control(format!("unknown venue {}", venue)));
+            }
+            Some(venue) if pause => {
+                self.paused.venues.insert(venue.to_string());
+            }
+            Some(venue) => {
+                self.paused.venues.remove(venue);
+            }
+            None => {
+                self.paused.all = pause;
+                if !pause {
+                    self.paused.venues.clear();
+                }
+            }
+        }
+        let changed = running
+            .symmetric_difference(&self.running())
+            .cloned()
+            .collect();
+        self.restart(changed);
+        Ok(())
+    }
+
+    /// Wait for the venues this instance leads to change, which outside a
+    /// cluster they never do but once a dormant standby is promoted.
+    pub(crate) async fn led(&mut self) -> BTreeSet<String> {
+        if let Some(promoted) = self.dormant.as_mut() {
+            if promoted.wait_for(Option::is_some).await.is_err() {
+                std::future::pending::<()>().await;
+            }
+            self.dormant = None;
+            return self.venues.iter().map(|venue| venue.name.clone()).collect();
+        }
+        match self.cluster.as_mut() {
+            Some(cluster) => cluster.changed().await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Start the adapters of the venues in `led` and stop the others.
+    pub(crate) fn lead(&mut self, led: BTreeSet<String>) {
+        let changed: Vec<String> = led.symmetric_difference(&self.leading).cloned().collect();
+        self.leading = led;
+        self.restart(changed);
+    }
+
+    /// Start or stop the adapters of the `changed` venues, as this instance
+    /// now runs them or not.
+    fn restart(&mut self, changed: Vec<String>) {
+        let mut workers = BTreeSet::new();
+        for name in changed {
+            let Some(venue) = self.get(&name).cloned() else {
+                continue;
+            };
+            let runs = self.runs(&name);
+            if let Some(workers_cfg) = &self.workers {
+                workers.insert(workers_cfg.worker_of(&name).to_string());
+                if runs {
+                    self.metrics.track(&name);
+                } else {
+                    self.metrics.untrack(&name);
+                }
+            } else if runs {
+                self.start_adapter(venue);
+            } else if let Some(task) = self.tasks.remove(&name) {
+                task.abort();
+                let _ = self
+                    .tx
+                    .try_send(NormalizedEvent::adapter_status(&name, false));
+                self.metrics.untrack(&name);
+            }
+        }
+        for worker in workers {
+            self.start_worker(&worker);
+        }
+    }
+
+    /// Restart the adapters the watchdog finds stuck, and keep systemd
+    /// posted: ready once every adapter has connected, and alive while
+    /// this runs.
+    pub(crate) fn watch(&mut self) {
+        self.systemd.ready(self.metrics.disconnected().is_empty());
+        self.systemd.keepalive();
+        let Some(watchdog) = self.watchdog.as_mut() else {
+            return;
+        };
+        for name in watchdog.due(&self.metrics.states(), Instant::now()) {
+            let Some(venue) = self.get(&name).cloned() else {
+                continue;
+            };
+            tracing::warn!("restarting adapter of {}, which delivers no events", name);
+            self.metrics.restarts.with_label_values(&[&name]).inc();
+            self.start(venue);
+        }
+    }
+
+    /// Add and remove symbols of `venue`, restarting its adapter if that
+    /// changes them, and return the symbols it now has.
+    pub(crate) fn edit_symbols(
+        &mut self,
+        venue: &str,
+        add: &[String],
+        remove: &[String],
+    ) -> Result<Vec<String>, IngestError> {
+        let Some(running) = self.get(venue) else {
+            return Err(IngestError::Control(format!("unknown venue {}", venue)));
+        };
+        let mut cfg = running.clone();
+        cfg.symbols.retain(|symbol| !remove.contains(symbol));
+        for symbol in add {
+            if !cfg.symbols.contains(symbol) {
+                cfg.symbols.push(symbol.clone());
+            }
+        }
+        let symbols = cfg.symbols.clone();
+        if symbols != running.symbols {
+            self.start(cfg);
+        }
+        Ok(symbols)
+    }
+}
+
+impl Drop for Adapters {
+    /// Stop every adapter, so the pipeline input closes once the adapters
+    /// are gone.
+    fn drop(&mut self) {
+        for task in self.tasks.values() {
+            task.abort();
+        }
+    }
+}
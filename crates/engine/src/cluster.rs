@@ -0,0 +1,260 @@
+//! Venues shared among instances through leases in Consul, so each is
+//! ingested by exactly one instance and taken over when that one dies.
+//!
+//! Each instance keeps a Consul session alive, renewing it a few times per
+//! TTL, and tries to acquire the key `<prefix>/<venue>` of every venue it
+//! runs with that session. Acquiring is idempotent, so the venues an
+//! instance leads are those whose keys its session holds after each round.
+//! When an instance dies its session expires and the keys are deleted, and
+//! the next instance to try acquires them. An instance that cannot renew
+//! its session for a TTL steps down from every venue.
+
+use std::collections::BTreeSet;
+use std::time::{Duration, Instant};
+
+use ingest_core::{config::ClusterConfig, error::IngestError};
+use serde_json::json;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// This instance's part in the cluster.
+pub struct Cluster {
+    /// The venues this instance runs and campaigns for.
+    venues: watch::Sender<BTreeSet<String>>,
+    /// The venues it leads.
+    led: watch::Receiver<BTreeSet<String>>,
+    consul: Consul,
+    session: watch::Receiver<Option<String>>,
+    task: JoinHandle<()>,
+}
+
+impl Cluster {
+    /// Join the cluster, leading no venue until the first round is done.
+    pub fn join(cfg: ClusterConfig) -> Result<Self, IngestError> {
+        let consul = Consul::new(cfg)?;
+        let (venues, campaigned) = watch::channel(BTreeSet::new());
+        let (leading, led) = watch::channel(BTreeSet::new());
+        let (session_tx, session) = watch::channel(None);
+        let task = tokio::spawn(campaign(consul.clone(), campaigned, leading, session_tx));
+        Ok(Self {
+            venues,
+            led,
+            consul,
+            session,
+            task,
+        })
+    }
+
+    /// Campaign for `venue` as well.
+    pub fn campaign(&self, venue: &str) {
+        self.venues
+            .send_if_modified(|venues| venues.insert(venue.to_string()));
+    }
+
+    /// Stop campaigning for `venue`, releasing it if led.
+    pub fn resign(&self, venue: &str) {
+        self.venues.send_if_modified(|venues| venues.remove(venue));
+    }
+
+    /// Wait for the venues led to change, and return them.
+    pub async fn changed(&mut self) -> BTreeSet<String> {
+        if self.led.changed().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+        self.led.borrow_and_update().clone()
+    }
+}
+
+impl Drop for Cluster {
+    /// Stop campaigning and destroy the session, so the venues led go to
+    /// other instances at once rather than once it expires.
+    fn drop(&mut self) {
+        self.task.abort();
+        if let Some(session) = self.session.borrow().clone() {
+            let consul = self.consul.clone();
+            tokio::spawn(async move { consul.destroy(&session).await });
+        }
+    }
+}
+
+/// Renew the session and acquire the venues of `campaigned` a few times per
+/// TTL, and whenever they change, publishing the venues led to `leading`.
+async fn campaign(
+    consul: Consul,
+    mut campaigned: watch::Receiver<BTreeSet<String>>,
+    leading: watch::Sender<BTreeSet<String>>,
+    session: watch::Sender<Option<String>>,
+) {
+    let ttl = Duration::from_secs(consul.cfg.ttl_secs);
+    let mut tick = tokio::time::interval(ttl / 3);
+    let mut renewed = Instant::now();
+    loop {
+        tokio::select! {
+            _ = tick.tick() => {}
+            changed = campaigned.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+            }
+        }
+        let venues = campaigned.borrow_and_update().clone();
+        let led = leading.borrow().clone();
+        match consul.round(&session, &venues, &led).await {
+            Ok(now_led) => {
+                renewed = Instant::now();
+                publish(&leading, now_led);
+            }
+            Err(e) => {
+                tracing::warn!("cluster round failed: {}", e);
+                if renewed.elapsed() >= ttl && !led.is_empty() {
+                    tracing::error!("cluster session lapsed, stepping down from every venue");
+                    session.send_replace(None);
+                    publish(&leading, BTreeSet::new());
+                }
+            }
+        }
+    }
+}
+
+fn publish(leading: &watch::Sender<BTreeSet<String>>, led: BTreeSet<String>) {
+    leading.send_if_modified(|previous| {
+        if *previous == led {
+            return false;
+        }
+        for venue in led.difference(previous) {
+            tracing::info!(venue = venue.as_str(), "leading venue");
+        }
+        for venue in previous.difference(&led) {
+            tracing::info!(venue = venue.as_str(), "no longer leading venue");
+        }
+        *previous = led;
+        true
+    });
+}
+
+/// The Consul HTTP API.
+#[derive(Clone)]
+struct Consul {
+    cfg: ClusterConfig,
+    client: reqwest::Client,
+}
+
+impl Consul {
+    fn new(cfg: ClusterConfig) -> Result<Self, IngestError> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(cfg.ttl_secs / 3))
+            .build()
+            .map_err(|e| IngestError::Validation(format!("cluster: {}", e)))?;
+        Ok(Self { cfg, client })
+    }
+
+    /// Keep a live session in `session` and hold the keys of `venues` with
+    /// it, releasing those of `led` no longer among them. Returns the
+    /// venues whose keys the session holds.
+    async fn round(
+        &self,
+        session: &watch::Sender<Option<String>>,
+        venues: &BTreeSet<String>,
+        led: &BTreeSet<String>,
+    ) -> Result<BTreeSet<String>, IngestError> {
+        let current = session.borrow().clone();
+        let id = match current {
+            Some(id) if self.renew(&id).await? => id,
+            _ => {
+                let id = self.create().await?;
+                session.send_replace(Some(id.clone()));
+                id
+            }
+        };
+        for venue in led.difference(venues) {
+            self.lock(venue, "release", &id).await?;
+        }
+        let mut held = BTreeSet::new();
+        for venue in venues {
+            if self.lock(venue, "acquire", &id).await? {
+                held.insert(venue.clone());
+            }
+        }
+        Ok(held)
+    }
+
+    async fn create(&self) -> Result<String, IngestError> {
+        let ttl = format!("{}s", self.cfg.ttl_secs);
+        let body = json!({
+            "Name": format!("ingestd {}", self.cfg.node),
+            "TTL": ttl,
+            "LockDelay": ttl,
+            "Behavior": "delete",
+        });
+        let created: serde_json::Value = self
+            .send(
+                self.request(reqwest::Method::PUT, "session/create")
+                    .json(&body),
+            )
+            .await?
+            .json()
+            .await
+            .map_err(consul_error)?;
+        created["ID"]
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| IngestError::Control("consul created a session without an ID".into()))
+    }
+
+    /// Whether session `id` was renewed; false once it has expired.
+    async fn renew(&self, id: &str) -> Result<bool, IngestError> {
+        let path = format!("session/renew/{}", id);
+        let response = self
+            .request(reqwest::Method::PUT, &path)
+            .send()
+            .await
+            .map_err(consul_error)?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+        response.error_for_status().map_err(consul_error)?;
+        Ok(true)
+    }
+
+    async fn destroy(&self, id: &str) {
+        let path = format!("session/destroy/{}", id);
+        if let Err(e) = self.send(self.request(reqwest::Method::PUT, &path)).await {
+            tracing::warn!("cluster session not destroyed: {}", e);
+        }
+    }
+
+    /// `acquire` or `release` the key of `venue` with session `id`,
+    /// returning whether that succeeded.
+    async fn lock(&self, venue: &str, action: &str, id: &str) -> Result<bool, IngestError> {
+        let path = format!("kv/{}/{}", self.cfg.prefix.trim_matches('/'), venue);
+        let request = self
+            .request(reqwest::Method::PUT, &path)
+            .query(&[(action, id)])
+            .body(self.cfg.node.clone());
+        self.send(request).await?.json().await.map_err(consul_error)
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let url = format!("{}/v1/{}", self.cfg.consul.trim_end_matches('/'), path);
+        let request = self.client.request(method, url);
+        match &self.cfg.token {
+            Some(token) => request.header("X-Consul-Token", token),
+            None => request,
+        }
+    }
+
+    async fn send(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, IngestError> {
+        request
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(consul_error)
+    }
+}
+
+fn consul_error(e: reqwest::Error) -> IngestError {
+    IngestError::Control(format!("consul: {}", e))
+}
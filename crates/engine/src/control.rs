@@ -0,0 +1,201 @@
+//! Answers to control requests, from the ops server or an embedding
+//! application.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+
+use api::control::{
+    ControlRequest, ControlRequests, ControlResponse, ReloadReport, StageStats, VenueStatus,
+};
+use api::{EventBus, Filter};
+use ingest_core::{config::Config, error::IngestError};
+use pipeline::PipelineMetrics;
+use prometheus::core::Collector;
+
+use crate::adapters::Adapters;
+use crate::logging::LogFilter;
+use crate::watchdog;
+
+/// The config file the engine was started with, if any, and the config it
+/// runs.
+pub(crate) struct ConfigFile {
+    pub(crate) path: Option<String>,
+    /// The profile applied to the file.
+    pub(crate) profile: Option<String>,
+    /// Keeps the sections other than venues as they were started, so they
+    /// are reported as changed until the next restart.
+    pub(crate) running: Config,
+}
+
+/// Re-read the config file and, if it is valid, start, stop and restart
+/// adapters to match its venues.
+fn reload(config: &mut ConfigFile, adapters: &mut Adapters) -> ReloadReport {
+    let ConfigFile {
+        path,
+        profile,
+        running,
+    } = config;
+    let Some(path) = path else {
+        return ReloadReport {
+            errors: vec!["no config file to reload".to_string()],
+            ..Default::default()
+        };
+    };
+    let next = fs::read_to_string(&*path)
+        .map_err(|e| format!("cannot read {}: {}", path, e))
+        .and_then(|data| {
+            Config::from_str_with_profile(&data, profile.as_deref()).map_err(|e| e.to_string())
+        });
+    let next = match next {
+        Ok(next) => next,
+        Err(e) => {
+            return ReloadReport {
+                errors: vec![e],
+                ..Default::default()
+            }
+        }
+    };
+    // Symbol edits made since count as running.
+    running.venues = adapters.venues.clone();
+    let changes = running.diff(&next);
+    let mut errors = next.validate();
+    errors.extend(adapters.registry.unknown(&next.venues));
+    if !errors.is_empty() {
+        return ReloadReport {
+            applied: false,
+            errors,
+            changes,
+        };
+    }
+    for name in &changes.venues_removed {
+        adapters.stop(name);
+    }
+    adapters.start_all(
+        next.venues
+            .iter()
+            .filter(|venue| {
+                changes.venues_added.contains(&venue.name)
+                    || changes.venues_changed.contains(&venue.name)
+            })
+            .cloned()
+            .collect(),
+    );
+    running.venues = next.venues;
+    ReloadReport {
+        applied: true,
+        errors,
+        changes,
+    }
+}
+
+/// Answer control requests until every handle is gone or one asks to shut
+/// down.
+pub(crate) async fn answer_control(
+    mut requests: ControlRequests,
+    mut config: ConfigFile,
+    mut adapters: Adapters,
+    mut log_filter: Option<LogFilter>,
+    bus: EventBus,
+    metrics: PipelineMetrics,
+) {
+    let mut watchdog_tick = tokio::time::interval(watchdog::CHECK_INTERVAL);
+    loop {
+        let pending = tokio::select! {
+            pending = requests.next() => pending,
+            _ = watchdog_tick.tick() => {
+                adapters.watch();
+                continue;
+            }
+            led = adapters.led() => {
+                adapters.lead(led);
+                continue;
+            }
+        };
+        let Some(pending) = pending else { break };
+        let response = match &pending.request {
+            ControlRequest::Venues => Ok(ControlResponse::Venues(
+                adapters
+                    .venues
+                    .iter()
+                    .map(|venue| VenueStatus {
+                        name: venue.name.clone(),
+                        paused: adapters.paused(&venue.name),
+                    })
+                    .collect(),
+            )),
+            ControlRequest::Symbols { venue } => {
+                let filter = Filter::new().venues(venue.clone());
+                let mut symbols: BTreeMap<String, Vec<String>> = BTreeMap::new();
+                for event in bus.snapshot(&filter) {
+                    let seen = symbols.entry(event.venue).or_default();
+                    if seen.last() != Some(&event.symbol) {
+                        seen.push(event.symbol);
+                    }
+                }
+                Ok(ControlResponse::Symbols(symbols))
+            }
+            ControlRequest::PipelineStats => {
+                Ok(ControlResponse::PipelineStats(stage_stats(&metrics)))
+            }
+            ControlRequest::EditSymbols { venue, add, remove } => adapters
+                .edit_symbols(venue, add, remove)
+                .map(ControlResponse::VenueSymbols),
+            ControlRequest::Reload => Ok(ControlResponse::Reloaded(reload(
+                &mut config,
+                &mut adapters,
+            ))),
+            ControlRequest::LogFilter => match &log_filter {
+                Some(log_filter) => Ok(ControlResponse::LogFilter(
+                    log_filter.directives().to_string(),
+                )),
+                None => Err(no_log_filter()),
+            },
+            ControlRequest::SetLogFilter { filter } => match log_filter.as_mut() {
+                Some(log_filter) => log_filter
+                    .set(filter)
+                    .map(|()| ControlResponse::LogFilter(filter.clone())),
+                None => Err(no_log_filter()),
+            },
+            ControlRequest::Shutdown => {
+                pending.respond(Ok(ControlResponse::Done));
+                return;
+            }
+            ControlRequest::Pause { venue } => adapters
+                .pause(venue.as_deref(), true)
+                .map(|()| ControlResponse::Done),
+            ControlRequest::Resume { venue } => adapters
+                .pause(venue.as_deref(), false)
+                .map(|()| ControlResponse::Done),
+        };
+        pending.respond(response);
+    }
+}
+
+fn no_log_filter() -> IngestError {
+    IngestError::Control("the log filter is not the engine's to change".into())
+}
+
+/// Counters of every stage that has reported to `metrics`.
+fn stage_stats(metrics: &PipelineMetrics) -> Vec<StageStats> {
+    let stages: BTreeSet<String> = metrics
+        .processed
+        .collect()
+        .iter()
+        .flat_map(|family| family.get_metric())
+        .flat_map(|metric| metric.get_label())
+        .filter(|label| label.get_name() == "stage")
+        .map(|label| label.get_value().to_string())
+        .collect();
+    stages
+        .into_iter()
+        .map(|stage| {
+            let label = [stage.as_str()];
+            StageStats {
+                processed: metrics.processed.with_label_values(&label).get(),
+                queue_depth: metrics.queue_depth.with_label_values(&label).get(),
+                restarts: metrics.restarts.with_label_values(&label).get(),
+                stage,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,175 @@
+//! Pipeline output onto the WAL, the gRPC feed and the bus.
+
+use std::time::Duration;
+
+use api::EventPublisher;
+use ingest_core::event::{NormalizedEvent, ENGINE_VENUE};
+use ingest_grpc::Feed;
+use ops::{AdapterMetrics, EventMetrics};
+use tokio::sync::mpsc;
+use wal::Wal;
+
+use crate::memory::MemoryGuard;
+use crate::standby::{Held, Promotion};
+
+/// What holds events back from the bus: shedding them over the memory
+/// budget, or standing by.
+pub(crate) struct Gate {
+    pub(crate) memory: Option<MemoryGuard>,
+    /// While standing by, as one of an active/standby pair.
+    pub(crate) standby: Option<Held>,
+}
+
+impl Gate {
+    /// Whether `event` goes on to the bus. Engine events always do.
+    fn admits(&self, event: &NormalizedEvent) -> bool {
+        if event.venue == ENGINE_VENUE {
+            return true;
+        }
+        !self
+            .memory
+            .as_ref()
+            .is_some_and(|memory| memory.sheds(event))
+    }
+
+    /// Wait for the role of a standby pair instance to change, which it
+    /// never does otherwise.
+    async fn role_changed(&mut self) -> Option<Promotion> {
+        match self.standby.as_mut() {
+            Some(held) => held.changed().await,
+            None => std::future::pending().await,
+        }
+    }
+}
+
+/// Publish pipeline output onto the bus, logging each event to the WAL first
+/// so everything subscribers saw can be replayed. gRPC subscribers get the
+/// WAL sequence number, or a process-local one when the WAL is disabled, and
+/// the bus is sequenced the same way so consumers can resume from the WAL.
+/// Engine events are published but not logged, and events the WAL fails to
+/// log are dropped, so subscribers only see what can be replayed. Events
+/// the gate holds back are dropped; engine events always pass. A standby holds events until
+/// promoted, then continues the sequence of the instance it replaces with
+/// the held events that instance may have missed, and holds them again if
+/// it steps down.
+/// Published events are counted in `metrics`, and every event received,
+/// dropped or not, updates the adapter state in `adapters`. The WAL is synced
+/// once the pipeline output ends.
+pub(crate) async fn forward(
+    mut rx: mpsc::Receiver<NormalizedEvent>,
+    publisher: EventPublisher,
+    wal: Option<Wal>,
+    feed: Option<Feed>,
+    mut gate: Gate,
+    metrics: EventMetrics,
+    adapters: AdapterMetrics,
+) {
+    let mut sync_tick = tokio::time::interval(Duration::from_millis(100));
+    let mut output = Output {
+        sequence: wal.as_ref().map_or(0, |wal| wal.next_sequence() - 1),
+        rejected: 0,
+        publisher,
+        wal,
+        feed,
+        metrics,
+    };
+    loop {
+        tokio::select! {
+            evt = rx.recv() => {
+                let Some(evt) = evt else { break };
+                adapters.observe(&evt);
+                if let Some(held) = gate.standby.as_mut().filter(|held| held.standing_by()) {
+                    held.hold(evt);
+                    continue;
+                }
+                if gate.admits(&evt) {
+                    output.publish(evt);
+                }
+            }
+            role = gate.role_changed() => {
+                let Some(held) = gate.standby.as_mut() else { continue };
+                let Some(promotion) = role else {
+                    held.stand_by();
+                    continue;
+                };
+                let released = held.release(promotion);
+                output.resume(promotion.sequence);
+                for evt in released {
+                    if gate.admits(&evt) {
+                        output.publish(evt);
+                    }
+                }
+            }
+            _ = sync_tick.tick() => {
+                if let Some(Err(e)) = output.wal.as_mut().map(Wal::sync_if_due) {
+                    tracing::error!("wal sync failed: {e}");
+                }
+            }
+        }
+    }
+    if let Some(Err(e)) = output.wal.as_mut().map(Wal::sync) {
+        tracing::error!("wal sync failed: {e}");
+    }
+}
+
+/// Where [`forward`] publishes, under one sequence.
+struct Output {
+    /// Sequence of the latest published event.
+    sequence: u64,
+    /// Events the WAL failed to log since it last logged one.
+    rejected: u64,
+    publisher: EventPublisher,
+    wal: Option<Wal>,
+    feed: Option<Feed>,
+    metrics: EventMetrics,
+}
+
+impl Output {
+    fn publish(&mut self, evt: NormalizedEvent) {
+        match self.wal.as_mut() {
+            // Engine events describe the live feed and are not replayed, so
+            // they are left out of the log under a sequence of their own.
+            Some(wal) if evt.venue == ENGINE_VENUE => self.sequence = wal.skip(),
+            Some(wal) => match wal.append(&evt) {
+                Ok(seq) => {
+                    if self.rejected > 0 {
+                        tracing::warn!(
+                            "wal appends recovered after dropping {} events",
+                            self.rejected
+                        );
+                        self.rejected = 0;
+                    }
+                    self.sequence = seq;
+                }
+                Err(e) => {
+                    if self.rejected == 0 {
+                        tracing::error!(
+                            "wal append failed, dropping events until it recovers: {e}"
+                        );
+                    }
+                    self.rejected += 1;
+                    return;
+                }
+            },
+            None => self.sequence += 1,
+        }
+        if let Some(feed) = &self.feed {
+            feed.publish(self.sequence, &evt);
+        }
+        self.metrics.observe(&evt);
+        self.publisher.publish_sequenced(self.sequence, evt);
+    }
+
+    /// Continue after `sequence`, if that is ahead.
+    fn resume(&mut self, sequence: u64) {
+        match self.wal.as_mut() {
+            Some(wal) => {
+                if let Err(e) = wal.skip_to(sequence + 1) {
+                    tracing::error!("wal skip failed: {e}");
+                }
+                self.sequence = wal.next_sequence() - 1;
+            }
+            None => self.sequence = self.sequence.max(sequence),
+        }
+    }
+}
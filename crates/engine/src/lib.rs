@@ -0,0 +1,810 @@
+//! The ingestion engine: venue adapters feeding the pipeline, the WAL, the
+//! bus and the sinks, with the ops server, the control channel and whatever
+//! else the config enables. `ingestd` runs it as a daemon; applications can
+//! run it in-process and consume the bus directly:
+//!
+//! ```no_run
+//! use ingest_core::{config::Config, error::IngestError};
+//! use ingest_engine::Engine;
+//!
+//! async fn embed(cfg: Config) -> Result<(), IngestError> {
+//!     let engine = Engine::builder().config(cfg).build()?;
+//!     let mut events = engine.bus().subscribe();
+//!     let engine = engine.start()?;
+//!     while let Some(event) = events.recv().await {
+//!         println!("{} {} {}", event.venue, event.symbol, event.kind.as_str());
+//!     }
+//!     engine.shutdown().await
+//! }
+//! ```
+
+use std::collections::BTreeSet;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+
+use agents::AdapterRegistry;
+use api::control::ControlHandle;
+use api::{BusMetrics, EventBus, Spill};
+use ingest_core::{
+    config::{
+        Config, FlightConfig, OtlpConfig, PipelineConfig, SinkRoute, TransformConfig, WalConfig,
+    },
+    error::IngestError,
+};
+use ingest_grpc::GrpcServer;
+use ops::{
+    Active, AdapterMetrics, AlertSources, EventMetrics, Notifier, OpsServer, PushgatewayExporter,
+    StatusSources,
+};
+use pipeline::{Canonicalize, ClockSkew, Composite, Pipeline, PipelineBuilder, PipelineMetrics};
+use sinks::{Sink, SinkMetrics, SinkSet};
+use tokio::task::JoinHandle;
+use wal::{SpillLog, Wal, WalReader};
+
+mod adapters;
+mod cluster;
+mod control;
+mod forward;
+mod logfile;
+pub mod logging;
+mod memory;
+mod standby;
+mod supervisor;
+mod systemd;
+mod watchdog;
+mod workers;
+
+use adapters::Adapters;
+use cluster::Cluster;
+use control::ConfigFile;
+use forward::{forward, Gate};
+use logging::LogFilter;
+use memory::MemoryGuard;
+use standby::Held;
+use systemd::Systemd;
+use watchdog::Watchdog;
+use workers::Workers;
+
+pub use ops::BuildInfo;
+pub use workers::serve as serve_worker;
+
+/// Where the ops server listens unless the builder says otherwise.
+const OPS_ADDR: &str = "127.0.0.1:3000";
+
+/// Offsets of named bus consumers, kept in the WAL directory.
+const OFFSETS_FILE: &str = "offsets.json";
+
+/// How often acknowledged consumer offsets are written.
+const OFFSETS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a sink counting errors may go without delivering anything before
+/// the engine stops reporting ready.
+const SINK_STALL: Duration = Duration::from_secs(60);
+
+/// What this engine was built from, recorded by the build script.
+pub fn build_info() -> BuildInfo {
+    let known = |value: &str| (!value.is_empty()).then(|| value.to_string());
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION").into(),
+        commit: known(env!("INGEST_COMMIT")),
+        built_at: known(env!("INGEST_BUILT_AT")),
+        features: env!("INGEST_FEATURES")
+            .split(',')
+            .filter_map(known)
+            .collect(),
+    }
+}
+
+/// Problems of `cfg`, including venues `registry` has no adapter for.
+pub fn validate(cfg: &Config, registry: &AdapterRegistry) -> Result<(), IngestError> {
+    let mut problems = cfg.validate();
+    problems.extend(registry.unknown(&cfg.venues));
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(IngestError::Validation(problems.join("; ")))
+    }
+}
+
+/// Sets up an [`Engine`].
+#[derive(Default)]
+pub struct EngineBuilder {
+    cfg: Option<Config>,
+    path: Option<String>,
+    profile: Option<String>,
+    registry: Option<AdapterRegistry>,
+    sinks: Vec<(String, SinkRoute, Box<dyn Sink>)>,
+    log_filter: Option<LogFilter>,
+    ops_addr: Option<SocketAddr>,
+    without_ops: bool,
+}
+
+impl EngineBuilder {
+    pub fn config(mut self, cfg: Config) -> Self {
+        self.cfg = Some(cfg);
+        self
+    }
+
+    /// The file the config was read from, which reload requests read again.
+    /// Without one they are refused.
+    pub fn config_path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// The profile the config was read with, which reload requests apply
+    /// again.
+    pub fn profile(mut self, profile: impl Into<String>) -> Self {
+        self.profile = Some(profile.into());
+        self
+    }
+
+    /// The adapters by venue type, [`AdapterRegistry::builtin`] by default.
+    pub fn registry(mut self, registry: AdapterRegistry) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    /// Deliver the events `route` matches to `sink` too, alongside the
+    /// configured sinks, queueing up to the pipeline queue capacity.
+    pub fn with_sink(mut self, name: &str, route: SinkRoute, sink: impl Sink + 'static) -> Self {
+        self.sinks.push((name.to_string(), route, Box::new(sink)));
+        self
+    }
+
+    /// The filter of the installed log subscriber, from [`logging::init`],
+    /// which control requests may then replace. Without one they are
+    /// refused.
+    pub fn log_filter(mut self, log_filter: LogFilter) -> Self {
+        self.log_filter = Some(log_filter);
+        self
+    }
+
+    /// Where the ops server listens, `127.0.0.1:3000` by default.
+    pub fn ops_addr(mut self, addr: SocketAddr) -> Self {
+        self.ops_addr = Some(addr);
+        self
+    }
+
+    /// Run without the ops server, e.g. when embedded in an application
+    /// that serves its own endpoints. Metrics are still gathered, and the
+    /// control requests still answered through
+    /// [`RunningEngine::control`].
+    pub fn without_ops(mut self) -> Self {
+        self.without_ops = true;
+        self
+    }
+
+    /// Validate the config and set up the bus.
+    pub fn build(self) -> Result<Engine, IngestError> {
+        let cfg = self
+            .cfg
+            .ok_or_else(|| IngestError::Validation("engine built without a config".into()))?;
+        let registry = self.registry.unwrap_or_else(AdapterRegistry::builtin);
+        validate(&cfg, &registry)?;
+        let mut names: BTreeSet<&str> = cfg.sinks.keys().map(String::as_str).collect();
+        for (name, _, _) in &self.sinks {
+            if !names.insert(name) {
+                return Err(IngestError::Validation(format!(
+                    "sink {} added twice",
+                    name
+                )));
+            }
+        }
+        let bus_metrics = BusMetrics::new();
+        let mut bus = EventBus::from_config(&cfg.bus, &bus_metrics)
+            .with_spill(|spill| Ok(Box::new(SpillLog::open(spill)?) as Box<dyn Spill>));
+        if let Some(wal_cfg) = &cfg.wal {
+            bus = bus
+                .with_history(WalReader::open(&wal_cfg.path))
+                .with_offsets(Path::new(&wal_cfg.path).join(OFFSETS_FILE))?;
+        }
+        Ok(Engine {
+            cfg,
+            path: self.path,
+            profile: self.profile,
+            registry,
+            sinks: self.sinks,
+            log_filter: self.log_filter,
+            ops_addr: (!self.without_ops)
+                .then(|| self.ops_addr.unwrap_or_else(|| OPS_ADDR.parse().unwrap())),
+            bus,
+            bus_metrics,
+        })
+    }
+}
+
+/// An engine set up from a config, not yet running.
+pub struct Engine {
+    cfg: Config,
+    path: Option<String>,
+    profile: Option<String>,
+    registry: AdapterRegistry,
+    sinks: Vec<(String, SinkRoute, Box<dyn Sink>)>,
+    log_filter: Option<LogFilter>,
+    /// Where the ops server listens, if it runs.
+    ops_addr: Option<SocketAddr>,
+    bus: EventBus,
+    bus_metrics: BusMetrics,
+}
+
+impl Engine {
+    pub fn builder() -> EngineBuilder {
+        EngineBuilder::default()
+    }
+
+    /// The bus events are published on. Subscribers taken before
+    /// [`start`](Self::start) see every event.
+    pub fn bus(&self) -> &EventBus {
+        &self.bus
+    }
+
+    /// Start the sinks, the pipeline, the servers and the adapters. Must be
+    /// called from within a Tokio runtime.
+    pub fn start(self) -> Result<RunningEngine, IngestError> {
+        let Engine {
+            cfg,
+            path,
+            profile,
+            registry,
+            sinks: added_sinks,
+            log_filter,
+            ops_addr,
+            bus,
+            bus_metrics,
+        } = self;
+        let publisher = bus.publisher();
+        let mut consumer = bus.subscribe();
+        let log_handle = tokio::spawn(async move {
+            while let Some(evt) = consumer.recv().await {
+                tracing::debug!("event: {:?}", evt);
+            }
+        });
+
+        let (control_handle, control_requests) = api::control::channel(64);
+        let mut ops = OpsServer::namespaced(&cfg.ops.metrics)?
+            .with_snapshot(bus.clone())
+            .with_bus_state(bus.clone())
+            .with_sse(bus.clone())
+            .with_sse_config(cfg.ops.sse.clone())
+            .with_control(control_handle.clone())
+            .with_auth(cfg.ops.auth.clone())
+            .with_stream_limits(cfg.ops.streams.clone());
+        if !cfg.ops.auth.tokens.is_empty() {
+            ops = ops.with_admin(control_handle.clone());
+        }
+        if let Some(tls_cfg) = &cfg.ops.tls {
+            ops = ops.with_tls(tls_cfg)?;
+        }
+        if let Some(cors_cfg) = &cfg.ops.cors {
+            ops = ops.with_cors(cors_cfg)?;
+        }
+        let active = Active::new(cfg.standby.is_none());
+        ops = ops.with_active(active.clone(), bus.clone());
+        let promoted = cfg
+            .standby
+            .clone()
+            .map(|standby_cfg| standby::spawn(standby_cfg, active))
+            .transpose()?;
+        let wal = match &cfg.wal {
+            Some(wal_cfg) => {
+                ops = ops.with_replay(WalReader::open(&wal_cfg.path), bus.publisher());
+                Some(Wal::open(wal_cfg)?)
+            }
+            None => None,
+        };
+        if let Some(flight_cfg) = &cfg.flight {
+            spawn_flight(flight_cfg, &bus, cfg.wal.as_ref())?;
+        }
+        if let Some(ws_cfg) = &cfg.ws {
+            ops = ops.with_ws(bus.clone(), ws_cfg.clone());
+        }
+        let pipeline_metrics = PipelineMetrics::new();
+        bus_metrics.register(&ops.registry).map_err(metrics_error)?;
+        let build = build_info();
+        build.register(&ops.registry).map_err(metrics_error)?;
+        let event_metrics =
+            EventMetrics::new(&cfg.metrics, &cfg.ops.metrics).map_err(metrics_error)?;
+        event_metrics
+            .register(&ops.registry)
+            .map_err(metrics_error)?;
+        tokio::spawn(event_metrics.clone().run());
+        if let Some(otlp_cfg) = &cfg.metrics.otlp {
+            spawn_otlp(otlp_cfg, &ops.registry)?;
+        }
+        if let Some(pushgateway_cfg) = &cfg.metrics.pushgateway {
+            let exporter = PushgatewayExporter::new(pushgateway_cfg, ops.registry.clone())?;
+            tokio::spawn(exporter.run());
+        }
+        let adapter_metrics = AdapterMetrics::new();
+        adapter_metrics
+            .register(&ops.registry)
+            .map_err(metrics_error)?;
+        tokio::spawn(adapter_metrics.clone().run());
+        pipeline_metrics
+            .register(&ops.registry)
+            .map_err(metrics_error)?;
+        let sink_metrics = SinkMetrics::new();
+        sink_metrics
+            .register(&ops.registry)
+            .map_err(metrics_error)?;
+        let mut sinks = SinkSet::start(
+            &cfg.sinks,
+            |name, route| bus.subscribe_named(&format!("sink.{}", name), route.into()),
+            &sink_metrics,
+        )?;
+        for (name, route, sink) in added_sinks {
+            let events = bus.subscribe_named(&format!("sink.{}", name), (&route).into());
+            sinks.add(
+                &name,
+                sink,
+                events,
+                cfg.pipeline.queue_capacity,
+                &sink_metrics,
+            );
+        }
+        let memory = cfg.memory.clone().map(|memory_cfg| {
+            let queues = vec![
+                bus_metrics.queue_depth.clone(),
+                pipeline_metrics.queue_depth.clone(),
+                sink_metrics.queue_depth.clone(),
+            ];
+            MemoryGuard::new(memory_cfg, queues)
+        });
+        if let Some(memory) = &memory {
+            memory.register(&ops.registry).map_err(metrics_error)?;
+            tokio::spawn(memory.clone().run());
+        }
+        if let Some(alerts_cfg) = &cfg.alerts {
+            let sources = AlertSources {
+                adapters: adapter_metrics.clone(),
+                bus: bus_metrics,
+                sinks: sink_metrics.clone(),
+            };
+            tokio::spawn(Notifier::new(alerts_cfg.clone(), sources).run());
+        }
+        let feed = match &cfg.grpc {
+            Some(grpc_cfg) => {
+                let mut server = GrpcServer::new(grpc_cfg.buffer);
+                if let Some(wal_cfg) = &cfg.wal {
+                    server = server.with_wal(WalReader::open(&wal_cfg.path));
+                }
+                let feed = server.feed();
+                let addr: SocketAddr = grpc_cfg
+                    .addr
+                    .parse()
+                    .map_err(|e| IngestError::Validation(format!("grpc addr: {}", e)))?;
+                tokio::spawn(async move {
+                    if let Err(e) = server.run(addr).await {
+                        tracing::error!("grpc server error: {e}");
+                    }
+                });
+                Some(feed)
+            }
+            None => None,
+        };
+        let pipeline = build_pipeline(&cfg.pipeline, pipeline_metrics.clone())?.spawn();
+        let (pipeline_health, sink_health) = (pipeline.health(), sinks.health());
+        if cfg.ops.publish {
+            ops = ops.with_publish(pipeline.input.clone());
+        }
+        let adapters = adapter_metrics.clone();
+        let ops = ops
+            .with_ready_check("adapters", move || {
+                none_of("adapters not connected", adapters.disconnected())
+            })
+            .with_ready_check("pipeline", move || {
+                none_of("stages stopped", pipeline_health.stopped())
+            })
+            .with_ready_check("sinks", move || {
+                none_of("sinks stopped", sink_health.stopped())?;
+                none_of("sinks failing", sink_health.failing(SINK_STALL))
+            })
+            .with_symbols(adapter_metrics.clone())
+            .with_status(StatusSources {
+                build,
+                adapters: adapter_metrics.clone(),
+                events: event_metrics.clone(),
+                sinks: sink_metrics,
+                control: control_handle.clone(),
+            });
+        let startup = ops.startup();
+        let ops_handle = ops_addr.map(|addr| {
+            tokio::spawn(async move {
+                if let Err(e) = ops.run(addr).await {
+                    tracing::error!("ops server error: {e}");
+                }
+            })
+        });
+
+        let held = match (&cfg.standby, &promoted) {
+            (Some(standby_cfg), Some(promoted)) => Some(Held::new(
+                standby_cfg,
+                promoted.clone(),
+                cfg.pipeline.queue_capacity,
+            )),
+            _ => None,
+        };
+        let Pipeline {
+            input: tx,
+            output: rx,
+            ..
+        } = pipeline;
+        let forward_handle = tokio::spawn(forward(
+            rx,
+            publisher,
+            wal,
+            feed,
+            Gate {
+                memory,
+                standby: held,
+            },
+            event_metrics,
+            adapter_metrics.clone(),
+        ));
+
+        let watchdog = cfg.watchdog.clone().map(Watchdog::new);
+        let systemd = Systemd::from_env();
+        let workers = cfg
+            .workers
+            .clone()
+            .map(|workers_cfg| Workers::new(workers_cfg, &cfg.log, cfg.pipeline.queue_capacity))
+            .transpose()?;
+        let mut adapters = Adapters::new(
+            tx,
+            adapter_metrics,
+            registry,
+            cfg.supervisor.clone(),
+            watchdog,
+            systemd.clone(),
+            workers,
+        );
+        if let Some(cluster_cfg) = &cfg.cluster {
+            adapters = adapters.with_cluster(Cluster::join(cluster_cfg.clone())?);
+        }
+        if let (Some(standby_cfg), Some(promoted)) = (&cfg.standby, promoted) {
+            if !standby_cfg.warm {
+                adapters = adapters.dormant_until(promoted);
+            }
+        }
+        adapters.start_all(cfg.venues.clone());
+        startup.complete();
+        let shutdown_timeout = Duration::from_secs(cfg.shutdown.timeout_secs);
+        let control = tokio::spawn(control::answer_control(
+            control_requests,
+            ConfigFile {
+                path,
+                profile,
+                running: cfg,
+            },
+            adapters,
+            log_filter,
+            bus.clone(),
+            pipeline_metrics,
+        ));
+        let offsets = tokio::spawn(persist_offsets(bus.clone()));
+        Ok(RunningEngine {
+            bus,
+            control_handle,
+            control,
+            forward: forward_handle,
+            sinks,
+            ops: ops_handle,
+            log: log_handle,
+            offsets,
+            systemd,
+            shutdown_timeout,
+        })
+    }
+}
+
+/// A started engine.
+pub struct RunningEngine {
+    bus: EventBus,
+    control_handle: ControlHandle,
+    /// Answers control requests and owns the adapters.
+    control: JoinHandle<()>,
+    forward: JoinHandle<()>,
+    sinks: SinkSet,
+    ops: Option<JoinHandle<()>>,
+    log: JoinHandle<()>,
+    /// Writes the offsets of named bus consumers.
+    offsets: JoinHandle<()>,
+    systemd: Systemd,
+    shutdown_timeout: Duration,
+}
+
+impl RunningEngine {
+    pub fn bus(&self) -> &EventBus {
+        &self.bus
+    }
+
+    /// A handle to send the requests of the ops server's control routes.
+    pub fn control(&self) -> ControlHandle {
+        self.control_handle.clone()
+    }
+
+    /// Wait until a control request asks the engine to shut down, after
+    /// which [`shutdown`](Self::shutdown) drains it.
+    pub async fn stopped(&mut self) {
+        let _ = (&mut self.control).await;
+    }
+
+    /// Stop the adapters, then drain the pipeline into the bus and the WAL
+    /// and the bus into the sinks within the configured shutdown timeout.
+    /// Sinks still busy at the deadline keep what they hold in their spill
+    /// logs.
+    pub async fn shutdown(mut self) -> Result<(), IngestError> {
+        // The adapters go with the control task, which closes the pipeline
+        // input.
+        if !self.control.is_finished() {
+            self.control.abort();
+            let _ = (&mut self.control).await;
+        }
+        self.systemd.stopping();
+        tracing::info!("shutting down");
+        self.log.abort();
+        let deadline = tokio::time::Instant::now() + self.shutdown_timeout;
+        let forwarded = tokio::time::timeout_at(deadline, &mut self.forward).await;
+        if forwarded.is_err() {
+            self.forward.abort();
+        }
+        self.bus.close();
+        let lost = self.sinks.join(deadline).await;
+        self.offsets.abort();
+        write_offsets(&self.bus).await;
+        if let Some(ops) = &self.ops {
+            ops.abort();
+        }
+        if forwarded.is_err() {
+            return Err(IngestError::Control(format!(
+                "pipeline did not drain within {}s",
+                self.shutdown_timeout.as_secs()
+            )));
+        }
+        if lost > 0 {
+            return Err(IngestError::Control(format!(
+                "sinks gave up {} undelivered events at shutdown",
+                lost
+            )));
+        }
+        tracing::info!("shut down");
+        Ok(())
+    }
+}
+
+/// Write the offsets `bus` consumers acknowledge every [`OFFSETS_INTERVAL`].
+async fn persist_offsets(bus: EventBus) {
+    let mut ticks = tokio::time::interval(OFFSETS_INTERVAL);
+    loop {
+        ticks.tick().await;
+        write_offsets(&bus).await;
+    }
+}
+
+/// Write the offsets acknowledged on `bus`, off the runtime's workers.
+async fn write_offsets(bus: &EventBus) {
+    let bus = bus.clone();
+    match tokio::task::spawn_blocking(move || bus.persist_offsets()).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => tracing::error!("cannot persist consumer offsets: {e}"),
+        Err(e) => tracing::error!("cannot persist consumer offsets: {e}"),
+    }
+}
+
+fn metrics_error(e: prometheus::Error) -> IngestError {
+    IngestError::Validation(format!("metrics: {}", e))
+}
+
+/// A readiness check failing with `problem` and the `names` it applies to,
+/// if there are any.
+fn none_of(problem: &str, names: Vec<String>) -> Result<(), String> {
+    if names.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{}: {}", problem, names.join(", ")))
+    }
+}
+
+fn build_pipeline(
+    cfg: &PipelineConfig,
+    metrics: PipelineMetrics,
+) -> Result<PipelineBuilder, IngestError> {
+    let skew_cfg = cfg.clock_skew.clone();
+    let skew_gauge = metrics.clock_skew_ms.clone();
+    let builder = Pipeline::builder()
+        .capacity(cfg.queue_capacity)
+        .metrics(metrics)
+        .stage("canonicalize", || Canonicalize)
+        .stage("clock_skew", move || {
+            ClockSkew::new(skew_cfg.clone(), skew_gauge.clone())
+        });
+    let mut builder = add_transforms(builder, &cfg.transforms)?;
+    if cfg.composite.enabled {
+        let composite_cfg = cfg.composite.clone();
+        builder = builder.stage("composite", move || Composite::new(composite_cfg.clone()));
+    }
+    Ok(builder)
+}
+
+#[cfg(feature = "wasm")]
+fn add_transforms(
+    mut builder: PipelineBuilder,
+    transforms: &[TransformConfig],
+) -> Result<PipelineBuilder, IngestError> {
+    for cfg in transforms {
+        let transform = pipeline::wasm::WasmTransform::from_config(cfg)?;
+        builder = builder.stage(&cfg.name, move || transform.stage());
+    }
+    Ok(builder)
+}
+
+#[cfg(not(feature = "wasm"))]
+fn add_transforms(
+    builder: PipelineBuilder,
+    transforms: &[TransformConfig],
+) -> Result<PipelineBuilder, IngestError> {
+    if transforms.is_empty() {
+        Ok(builder)
+    } else {
+        Err(IngestError::Validation(
+            "wasm transforms configured but ingestd was built without the `wasm` feature".into(),
+        ))
+    }
+}
+
+#[cfg(feature = "otlp")]
+fn spawn_otlp(cfg: &OtlpConfig, registry: &prometheus::Registry) -> Result<(), IngestError> {
+    let exporter = ops::OtlpExporter::new(cfg, registry.clone())?;
+    tokio::spawn(exporter.run());
+    Ok(())
+}
+
+#[cfg(not(feature = "otlp"))]
+fn spawn_otlp(_cfg: &OtlpConfig, _registry: &prometheus::Registry) -> Result<(), IngestError> {
+    Err(IngestError::Validation(
+        "metrics.otlp configured but ingestd was built without the `otlp` feature".into(),
+    ))
+}
+
+#[cfg(feature = "flight")]
+fn spawn_flight(
+    cfg: &FlightConfig,
+    bus: &EventBus,
+    wal: Option<&WalConfig>,
+) -> Result<(), IngestError> {
+    let mut server = ingest_flight::FlightServer::new(bus.clone(), cfg.clone());
+    if let Some(wal_cfg) = wal {
+        server = server.with_wal(WalReader::open(&wal_cfg.path));
+    }
+    let addr: SocketAddr = cfg
+        .addr
+        .parse()
+        .map_err(|e| IngestError::Validation(format!("flight addr: {}", e)))?;
+    tokio::spawn(async move {
+        if let Err(e) = server.run(addr).await {
+            tracing::error!("flight server error: {e}");
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(feature = "flight"))]
+fn spawn_flight(
+    _cfg: &FlightConfig,
+    _bus: &EventBus,
+    _wal: Option<&WalConfig>,
+) -> Result<(), IngestError> {
+    Err(IngestError::Validation(
+        "flight configured but ingestd was built without the `flight` feature".into(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use ingest_core::event::{EventKind, NormalizedEvent};
+    use tokio::sync::mpsc::{self, Sender};
+
+    /// Sends one trade per symbol, then stays connected.
+    struct OneTrade;
+
+    #[async_trait]
+    impl agents::Adapter for OneTrade {
+        async fn connect(
+            &self,
+            cfg: ingest_core::config::VenueConfig,
+            tx: Sender<NormalizedEvent>,
+        ) -> Result<(), IngestError> {
+            for symbol in &cfg.symbols {
+                let event = NormalizedEvent {
+                    venue: cfg.name.clone(),
+                    symbol: symbol.clone(),
+                    kind: EventKind::Trade,
+                    ..Default::default()
+                };
+                let _ = tx.send(event).await;
+            }
+            std::future::pending().await
+        }
+
+        fn parse_frame(&self, _: &str, _: &str) -> Result<Vec<NormalizedEvent>, IngestError> {
+            Ok(Vec::new())
+        }
+    }
+
+    struct Collect(mpsc::UnboundedSender<NormalizedEvent>);
+
+    #[async_trait]
+    impl Sink for Collect {
+        async fn send(&mut self, event: &NormalizedEvent) -> Result<(), IngestError> {
+            let _ = self.0.send(event.clone());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn embedded_engine_feeds_the_bus_and_added_sinks() {
+        let cfg = Config::from_str(
+            r#"
+[[venues]]
+name = "test"
+type = "one_trade"
+symbols = ["BTCUSDT", "ETHUSDT"]
+"#,
+        )
+        .unwrap();
+        let (tx, mut delivered) = mpsc::unbounded_channel();
+        let trades = SinkRoute {
+            kinds: vec![EventKind::Trade],
+            ..Default::default()
+        };
+        let engine = Engine::builder()
+            .config(cfg)
+            .registry(AdapterRegistry::empty().with("one_trade", OneTrade))
+            .with_sink("app", trades, Collect(tx))
+            .ops_addr("127.0.0.1:3041".parse().unwrap())
+            .build()
+            .unwrap();
+        let mut events = engine.bus().subscribe();
+        let engine = engine.start().unwrap();
+
+        let mut symbols = Vec::new();
+        while symbols.len() < 2 {
+            let event = events.recv().await.unwrap();
+            if event.kind == EventKind::Trade {
+                symbols.push(event.symbol);
+            }
+        }
+        symbols.sort();
+        assert_eq!(symbols, ["BTCUSDT", "ETHUSDT"]);
+        engine.shutdown().await.unwrap();
+        let mut sunk = 0;
+        while delivered.recv().await.is_some() {
+            sunk += 1;
+        }
+        assert_eq!(sunk, 2);
+    }
+
+    #[test]
+    fn sinks_added_under_a_configured_name_are_refused() {
+        let cfg = Config::from_str(
+            r#"
+venues = []
+
+[sinks.app]
+type = "unix_socket"
+path = "/tmp/ingest-engine-test.sock"
+"#,
+        )
+        .unwrap();
+        let (tx, _) = mpsc::unbounded_channel();
+        let built = Engine::builder()
+            .config(cfg)
+            .with_sink("app", SinkRoute::default(), Collect(tx))
+            .build();
+        assert!(built.is_err());
+    }
+}
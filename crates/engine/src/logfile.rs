@@ -0,0 +1,108 @@
+//! A log file that rotates by size and by UTC day, for deployments without
+//! journald or a log collector.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{NaiveDate, Utc};
+use ingest_core::{config::LogFileConfig, error::IngestError};
+
+pub struct RollingFile {
+    cfg: LogFileConfig,
+    path: PathBuf,
+    file: File,
+    /// Bytes in the current file.
+    written: u64,
+    /// The UTC day the current file was opened on.
+    opened: NaiveDate,
+}
+
+impl RollingFile {
+    /// Append to `cfg.path`, creating it and its directory if needed.
+    pub fn open(cfg: &LogFileConfig) -> Result<Self, IngestError> {
+        let path = PathBuf::from(&cfg.path);
+        let invalid =
+            |e: io::Error| IngestError::Validation(format!("log file {}: {}", cfg.path, e));
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(invalid)?;
+        }
+        let file = append(&path).map_err(invalid)?;
+        let written = file.metadata().map_err(invalid)?.len();
+        Ok(Self {
+            cfg: cfg.clone(),
+            path,
+            file,
+            written,
+            opened: Utc::now().date_naive(),
+        })
+    }
+
+    fn due(&self, len: usize) -> bool {
+        let full = self
+            .cfg
+            .max_bytes
+            .is_some_and(|max| self.written > 0 && self.written + len as u64 > max);
+        full || (self.cfg.daily && Utc::now().date_naive() != self.opened)
+    }
+
+    /// Move the current file aside, start a new one and prune old ones.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let stamp = Utc::now().format("%Y%m%dT%H%M%S%.3fZ");
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(format!(".{}", stamp));
+        fs::rename(&self.path, &rotated)?;
+        self.file = append(&self.path)?;
+        self.written = 0;
+        self.opened = Utc::now().date_naive();
+        self.prune()
+    }
+
+    /// Remove rotated files beyond `retain`, oldest first.
+    fn prune(&self) -> io::Result<()> {
+        let (Some(dir), Some(name)) = (self.path.parent(), self.path.file_name()) else {
+            return Ok(());
+        };
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        let prefix = format!("{}.", name.to_string_lossy());
+        let mut rotated: Vec<PathBuf> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+            .map(|entry| entry.path())
+            .collect();
+        // The UTC stamps sort in time order.
+        rotated.sort();
+        let excess = rotated.len().saturating_sub(self.cfg.retain);
+        for path in &rotated[..excess] {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for RollingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.due(buf.len()) {
+            // Keep logging to the current file rather than lose lines.
+            if let Err(e) = self.rotate() {
+                eprintln!("log file {} rotation failed: {}", self.path.display(), e);
+            }
+        }
+        let written = self.file.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
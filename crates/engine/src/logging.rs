@@ -0,0 +1,70 @@
+//! Structured logs, with a filter the control channel can replace while
+//! ingestd runs.
+
+use ingest_core::{
+    config::{LogConfig, LogFormat},
+    error::IngestError,
+};
+use std::sync::Mutex;
+
+use tracing_subscriber::{
+    fmt::{self, writer::BoxMakeWriter},
+    layer::SubscriberExt,
+    reload,
+    util::SubscriberInitExt,
+    EnvFilter, Registry,
+};
+
+use crate::logfile::RollingFile;
+
+pub struct LogFilter {
+    handle: reload::Handle<EnvFilter, Registry>,
+    directives: String,
+}
+
+/// Log to stderr, or to the file of `cfg`, as `cfg` says, for the rest of
+/// the process.
+pub fn init(cfg: &LogConfig) -> Result<LogFilter, IngestError> {
+    let directives = cfg.directives();
+    let (filter, handle) = reload::Layer::new(parse(&directives)?);
+    let registry = tracing_subscriber::registry().with(filter);
+    let (writer, ansi) = match &cfg.file {
+        Some(file) => (
+            BoxMakeWriter::new(Mutex::new(RollingFile::open(file)?)),
+            false,
+        ),
+        None => (BoxMakeWriter::new(std::io::stderr), true),
+    };
+    match cfg.format {
+        LogFormat::Json => registry
+            .with(fmt::layer().json().flatten_event(true).with_writer(writer))
+            .try_init(),
+        LogFormat::Text => registry
+            .with(fmt::layer().with_ansi(ansi).with_writer(writer))
+            .try_init(),
+    }
+    .map_err(|e| IngestError::Validation(format!("log: {}", e)))?;
+    Ok(LogFilter { handle, directives })
+}
+
+fn parse(directives: &str) -> Result<EnvFilter, IngestError> {
+    EnvFilter::builder()
+        .parse(directives)
+        .map_err(|e| IngestError::Validation(format!("log filter {}: {}", directives, e)))
+}
+
+impl LogFilter {
+    pub fn directives(&self) -> &str {
+        &self.directives
+    }
+
+    /// Filter with `directives` from now on; the old filter stays if they do
+    /// not parse.
+    pub fn set(&mut self, directives: &str) -> Result<(), IngestError> {
+        self.handle
+            .reload(parse(directives)?)
+            .map_err(|e| IngestError::Control(format!("log filter: {}", e)))?;
+        self.directives = directives.to_string();
+        Ok(())
+    }
+}
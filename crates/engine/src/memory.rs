@@ -0,0 +1,133 @@
+//! Shedding of low-priority events once the events waiting in the bus,
+//! pipeline and sink queues take more memory than budgeted, so a slow
+//! consumer cannot grow the engine until it is killed.
+//!
+//! The memory is estimated rather than counted: the depths of every queue
+//! times the average size of recent events.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use ingest_core::{
+    config::{BusKind, MemoryConfig},
+    event::NormalizedEvent,
+};
+use prometheus::{core::Collector, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry};
+
+/// How often the buffered memory is estimated.
+const CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Weight of each new event in the average event size, as a power of two.
+const AVERAGE_SHIFT: u32 = 6;
+
+#[derive(Clone)]
+pub struct MemoryGuard {
+    cfg: Arc<MemoryConfig>,
+    /// Queue-depth gauges of the bus, pipeline and sinks.
+    queues: Vec<IntGaugeVec>,
+    /// Recent average event size in bytes.
+    average: Arc<AtomicU64>,
+    /// How many buses of `cfg.shed` are shed now.
+    level: Arc<AtomicUsize>,
+    buffered: IntGauge,
+    shed: IntCounterVec,
+}
+
+impl MemoryGuard {
+    pub fn new(cfg: MemoryConfig, queues: Vec<IntGaugeVec>) -> Self {
+        Self {
+            cfg: Arc::new(cfg),
+            queues,
+            average: Arc::new(AtomicU64::new(std::mem::size_of::<NormalizedEvent>() as u64)),
+            level: Arc::new(AtomicUsize::new(0)),
+            buffered: IntGauge::new(
+                "memory_buffered_bytes",
+                "estimated memory of the events waiting in queues",
+            )
+            .unwrap(),
+            shed: IntCounterVec::new(
+                Opts::new(
+                    "memory_shed_events_total",
+                    "events dropped to keep queued events within the memory budget",
+                ),
+                &["bus"],
+            )
+            .unwrap(),
+        }
+    }
+
+    pub fn register(&self, registry: &Registry) -> prometheus::Result<()> {
+        registry.register(Box::new(self.buffered.clone()))?;
+        registry.register(Box::new(self.shed.clone()))?;
+        Ok(())
+    }
+
+    /// Take the size of `event` into the average, and tell whether it is
+    /// to be shed.
+    pub fn sheds(&self, event: &NormalizedEvent) -> bool {
+        let size = event.size_hint() as u64;
+        let average = self.average.load(Ordering::Relaxed);
+        let next = average - (average >> AVERAGE_SHIFT) + (size >> AVERAGE_SHIFT);
+        self.average.store(next, Ordering::Relaxed);
+        let level = self.level.load(Ordering::Relaxed);
+        let bus = BusKind::of(event);
+        if !self.cfg.shed[..level].contains(&bus) {
+            return false;
+        }
+        self.shed.with_label_values(&[bus.as_str()]).inc();
+        true
+    }
+
+    /// Estimate the buffered memory and adjust what is shed, for as long
+    /// as the task runs.
+    pub async fn run(self) {
+        let mut tick = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            tick.tick().await;
+            self.check();
+        }
+    }
+
+    fn check(&self) {
+        let depth: i64 = self.queues.iter().map(total).sum();
+        let bytes = depth.max(0) as u64 * self.average.load(Ordering::Relaxed);
+        self.buffered.set(bytes as i64);
+        let budget = self.cfg.budget_bytes;
+        let level = level(bytes, budget, self.cfg.shed.len());
+        let previous = self.level.swap(level, Ordering::Relaxed);
+        if level > previous {
+            let shed: Vec<_> = self.cfg.shed[..level].iter().map(BusKind::as_str).collect();
+            tracing::warn!(
+                "{} of {} budgeted bytes buffered, shedding {}",
+                bytes,
+                budget,
+                shed.join(", ")
+            );
+        } else if level == 0 && previous > 0 {
+            tracing::info!("buffered events back within the memory budget");
+        }
+    }
+}
+
+/// How many buses to shed with `buffered` bytes against `budget`: none
+/// within it, then one more for every started tenth of it over, up to
+/// `buses`.
+fn level(buffered: u64, budget: u64, buses: usize) -> usize {
+    if buffered <= budget {
+        return 0;
+    }
+    let step = (budget / 10).max(1);
+    let over = 1 + (buffered - budget - 1) / step;
+    over.min(buses as u64) as usize
+}
+
+/// The sum of every gauge in `gauges`.
+fn total(gauges: &IntGaugeVec) -> i64 {
+    gauges
+        .collect()
+        .iter()
+        .flat_map(|family| family.get_metric())
+        .map(|metric| metric.get_gauge().get_value() as i64)
+        .sum()
+}
@@ -0,0 +1,372 @@
+//! One instance of an active/standby pair, which takes over once the other
+//! stops answering and steps down when the other was promoted over it.
+//!
+//! Each instance polls `GET /healthz/active` on the other, which answers
+//! with its role, the sequence of the latest event it published and the
+//! epoch of its latest promotion. Once enough polls in a row go unanswered,
+//! a standby that heard from the active instance at least once promotes
+//! itself: its own `/healthz/active` answers from then on, its sequence
+//! numbers continue from the last one the active instance reported, and a
+//! warm standby publishes the events its adapters delivered since the last
+//! answered poll, so the gap is the time since the active instance
+//! published its last event rather than the time it took to notice it had
+//! stopped. Events from that last heartbeat on may be published by both
+//! instances. The primary instance takes over at once from a standby that
+//! is not active.
+//!
+//! A promotion takes the epoch after the last one the other instance
+//! reported. Both instances are active while partitioned from each other;
+//! once they hear from each other again, the one with the lower epoch, or
+//! the primary at the same epoch, steps down and stands by again.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use ingest_core::{config::StandbyConfig, error::IngestError, event::NormalizedEvent};
+use ops::Active;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tokio::sync::watch;
+use tokio::time::Interval;
+
+/// Where the promoted instance takes over.
+#[derive(Clone, Copy, Debug)]
+pub struct Promotion {
+    /// The last sequence the other instance reported.
+    pub sequence: u64,
+    /// When it last answered.
+    pub beat: Instant,
+    /// Above any the other instance reported.
+    pub epoch: u64,
+}
+
+/// The heartbeat of the other instance, the body of its
+/// `GET /healthz/active`.
+#[derive(Deserialize)]
+struct Beat {
+    active: bool,
+    sequence: u64,
+    #[serde(default)]
+    epoch: u64,
+}
+
+impl Beat {
+    /// Taking over from the instance that just answered with this.
+    fn promotion(&self) -> Promotion {
+        Promotion {
+            sequence: self.sequence,
+            beat: Instant::now(),
+            epoch: self.epoch + 1,
+        }
+    }
+}
+
+/// Poll the other instance as `cfg` says, marking `active` and sending the
+/// promotion when this one takes over, and `None` when it steps down.
+pub fn spawn(
+    cfg: StandbyConfig,
+    active: Active,
+) -> Result<watch::Receiver<Option<Promotion>>, IngestError> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_millis(cfg.heartbeat_ms))
+        .build()
+        .map_err(|e| IngestError::Validation(format!("standby: {}", e)))?;
+    let (role, promoted) = watch::channel(None);
+    tokio::spawn(async move {
+        let url = format!("{}/healthz/active", cfg.active.trim_end_matches('/'));
+        let mut tick = tokio::time::interval(Duration::from_millis(cfg.heartbeat_ms));
+        tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut last = None;
+        loop {
+            let promotion = stand_by(&cfg, &client, &url, &mut tick, last).await;
+            tracing::warn!(
+                "taking over at epoch {}, from sequence {}",
+                promotion.epoch,
+                promotion.sequence + 1
+            );
+            active.promote(promotion.epoch);
+            role.send_replace(Some(promotion));
+
+            let other = lead(&cfg, &client, &url, &mut tick, promotion.epoch).await;
+            tracing::warn!(
+                "other instance active at epoch {}, standing by",
+                other.epoch
+            );
+            active.demote();
+            role.send_replace(None);
+            last = Some(other.promotion());
+        }
+    });
+    Ok(promoted)
+}
+
+/// Poll until this instance should take over: once `cfg.missed` polls in a
+/// row go unanswered after one was answered, `last` or a later one, or on
+/// the primary as soon as the other instance answers as a standby or
+/// `cfg.missed` polls went unanswered.
+async fn stand_by(
+    cfg: &StandbyConfig,
+    client: &reqwest::Client,
+    url: &str,
+    tick: &mut Interval,
+    mut last: Option<Promotion>,
+) -> Promotion {
+    let mut missed = 0;
+    loop {
+        tick.tick().await;
+        match beat(client, url, cfg.token.as_deref()).await {
+            Ok(other) => {
+                if missed > 0 {
+                    tracing::info!("other instance answering again");
+                }
+                missed = 0;
+                let promotion = other.promotion();
+                if cfg.primary && !other.active {
+                    return promotion;
+                }
+                last = Some(promotion);
+            }
+            Err(e) => {
+                missed += 1;
+                if missed > cfg.missed {
+                    continue;
+                }
+                tracing::warn!("other instance missed {} of {}: {}", missed, cfg.missed, e);
+                if missed < cfg.missed {
+                    continue;
+                }
+                match last {
+                    Some(last) => return last,
+                    None if cfg.primary => {
+                        return Promotion {
+                            sequence: 0,
+                            beat: Instant::now(),
+                            epoch: 1,
+                        }
+                    }
+                    None => {
+                        tracing::error!("never heard from the active instance, not taking over")
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Poll while this instance is active at `epoch`, until the other one
+/// answers as promoted over it: at a later epoch, or at the same one when
+/// this is the primary. Returns its heartbeat.
+async fn lead(
+    cfg: &StandbyConfig,
+    client: &reqwest::Client,
+    url: &str,
+    tick: &mut Interval,
+    epoch: u64,
+) -> Beat {
+    loop {
+        tick.tick().await;
+        let Ok(other) = beat(client, url, cfg.token.as_deref()).await else {
+            continue;
+        };
+        if other.active && (other.epoch > epoch || (other.epoch == epoch && cfg.primary)) {
+            return other;
+        }
+    }
+}
+
+/// The heartbeat of the other instance, active or standing by, or why it
+/// did not answer.
+async fn beat(
+    client: &reqwest::Client,
+    url: &str,
+    token: Option<&str>,
+) -> Result<Beat, IngestError> {
+    let request = client.get(url);
+    let request = match token {
+        Some(token) => request.bearer_auth(token),
+        None => request,
+    };
+    let response = request
+        .send()
+        .await
+        .map_err(|e| IngestError::Control(e.to_string()))?;
+    if response.status() != StatusCode::SERVICE_UNAVAILABLE {
+        response
+            .error_for_status_ref()
+            .map_err(|e| IngestError::Control(e.to_string()))?;
+    }
+    response
+        .json()
+        .await
+        .map_err(|e| IngestError::Control(e.to_string()))
+}
+
+/// Events a warm standby receives while it stands by, of which it keeps
+/// those a promotion could publish.
+pub struct Held {
+    role: watch::Receiver<Option<Promotion>>,
+    /// How long before the promotion the active instance can have last
+    /// answered.
+    window: Duration,
+    capacity: usize,
+    events: VecDeque<(Instant, NormalizedEvent)>,
+    standing_by: bool,
+}
+
+impl Held {
+    /// Hold up to `capacity` events while standing by, until `role` says
+    /// the instance was promoted, as `cfg` says.
+    pub fn new(
+        cfg: &StandbyConfig,
+        role: watch::Receiver<Option<Promotion>>,
+        capacity: usize,
+    ) -> Self {
+        let window = Duration::from_millis(cfg.heartbeat_ms) * (cfg.missed + 1);
+        Self {
+            role,
+            window,
+            capacity,
+            events: VecDeque::new(),
+            standing_by: true,
+        }
+    }
+
+    /// Whether events are held rather than published.
+    pub fn standing_by(&self) -> bool {
+        self.standing_by
+    }
+
+    pub fn hold(&mut self, event: NormalizedEvent) {
+        let now = Instant::now();
+        while self.events.len() >= self.capacity.max(1)
+            || self
+                .events
+                .front()
+                .is_some_and(|(at, _)| now.duration_since(*at) > self.window)
+        {
+            self.events.pop_front();
+        }
+        self.events.push_back((now, event));
+    }
+
+    /// Wait for the role to change: the promotion, or `None` on stepping
+    /// down. Never returns once the sender is gone.
+    pub async fn changed(&mut self) -> Option<Promotion> {
+        if self.role.changed().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+        let promotion = *self.role.borrow_and_update();
+        promotion
+    }
+
+    /// Stop holding events, returning those received since the active
+    /// instance last answered.
+    pub fn release(&mut self, promotion: Promotion) -> Vec<NormalizedEvent> {
+        self.standing_by = false;
+        self.events
+            .drain(..)
+            .filter(|(at, _)| *at >= promotion.beat)
+            .map(|(_, event)| event)
+            .collect()
+    }
+
+    /// Hold events again, having stepped down.
+    pub fn stand_by(&mut self) {
+        self.standing_by = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// The role and epoch the other instance answers with, or `None` for
+    /// a failing answer.
+    type Other = Arc<Mutex<Option<(bool, u64)>>>;
+
+    fn config(port: u16, primary: bool) -> StandbyConfig {
+        StandbyConfig {
+            active: format!("http://127.0.0.1:{}", port),
+            token: None,
+            heartbeat_ms: 20,
+            missed: 2,
+            warm: true,
+            primary,
+        }
+    }
+
+    /// Answer `GET /healthz/active` on `port` as the other instance,
+    /// closing each connection so a failing answer is noticed at once.
+    async fn other(port: u16) -> Other {
+        let other = Other::default();
+        let listener = tokio::net::TcpListener::bind(("127.0.0.1", port))
+            .await
+            .unwrap();
+        let state = other.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut conn, _) = listener.accept().await.unwrap();
+                let mut request = [0; 1024];
+                let _ = conn.read(&mut request).await;
+                let answer = *state.lock().unwrap();
+                let (status, body) = match answer {
+                    Some((true, epoch)) => ("200 OK", epoch),
+                    Some((false, epoch)) => ("503 Service Unavailable", epoch),
+                    None => ("500 Internal Server Error", 0),
+                };
+                let body = format!(
+                    r#"{{"active":{},"sequence":7,"epoch":{}}}"#,
+                    answer.is_some_and(|(active, _)| active),
+                    body
+                );
+                let response = format!(
+                    "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = conn.write_all(response.as_bytes()).await;
+            }
+        });
+        other
+    }
+
+    #[tokio::test]
+    async fn takes_over_only_after_a_heartbeat_and_steps_down_for_a_later_epoch() {
+        let other = other(3042).await;
+        let ours = Active::new(false);
+        let mut role = spawn(config(3042, false), ours.clone()).unwrap();
+        // A standby that never heard from the active instance does not take
+        // over.
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!ours.is_active());
+
+        *other.lock().unwrap() = Some((true, 0));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        *other.lock().unwrap() = None;
+        let promotion = role.wait_for(Option::is_some).await.unwrap().unwrap();
+        assert_eq!((promotion.sequence, promotion.epoch), (7, 1));
+        assert!(ours.is_active());
+
+        // Back after a partition, promoted over this instance.
+        *other.lock().unwrap() = Some((true, 2));
+        role.wait_for(Option::is_none).await.unwrap();
+        assert!(!ours.is_active());
+    }
+
+    #[tokio::test]
+    async fn the_primary_waits_for_an_active_standby_to_stand_by() {
+        let other = other(3043).await;
+        *other.lock().unwrap() = Some((true, 1));
+        let primary = Active::new(false);
+        let mut role = spawn(config(3043, true), primary.clone()).unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!primary.is_active());
+
+        *other.lock().unwrap() = Some((false, 1));
+        role.wait_for(Option::is_some).await.unwrap();
+        assert_eq!(primary.epoch(), 2);
+    }
+}
@@ -0,0 +1,160 @@
+//! Restarts of adapter tasks that fail, panic or stop, so a venue does not
+//! go missing after one error, with a circuit breaker that holds back an
+//! adapter failing over and over.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use agents::Adapter;
+use ingest_core::{
+    config::{SupervisorConfig, VenueConfig},
+    error::IngestError,
+    event::NormalizedEvent,
+};
+use ops::AdapterMetrics;
+use tokio::{sync::mpsc, task::JoinHandle};
+
+/// The failures of one venue's adapter that count towards opening its
+/// circuit.
+struct Breaker {
+    cfg: SupervisorConfig,
+    failures: Vec<Instant>,
+    /// Whether the circuit opened and the adapter has not run through a
+    /// window since, so one more failure opens it again.
+    half_open: bool,
+}
+
+enum Retry {
+    After(Duration),
+    /// The circuit opened.
+    Open(Duration),
+}
+
+impl Breaker {
+    fn new(cfg: SupervisorConfig) -> Self {
+        Self {
+            cfg,
+            failures: Vec::new(),
+            half_open: false,
+        }
+    }
+
+    /// Note a failure at `now` of an attempt made at `started`.
+    fn fail(&mut self, started: Instant, now: Instant) -> Retry {
+        let window = Duration::from_secs(self.cfg.window_secs);
+        if now.duration_since(started) >= window {
+            self.failures.clear();
+            self.half_open = false;
+        }
+        self.failures.retain(|at| now.duration_since(*at) < window);
+        self.failures.push(now);
+        if self.half_open || self.failures.len() >= self.cfg.max_failures as usize {
+            self.failures.clear();
+            self.half_open = true;
+            return Retry::Open(Duration::from_secs(self.cfg.open_secs));
+        }
+        let ms = self
+            .cfg
+            .initial_backoff_ms
+            .saturating_mul(1 << (self.failures.len() - 1).min(32))
+            .min(self.cfg.max_backoff_ms);
+        Retry::After(Duration::from_millis(ms))
+    }
+}
+
+/// Aborts an attempt along with the supervisor running it.
+struct Attempt(JoinHandle<Result<(), IngestError>>);
+
+impl Drop for Attempt {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Run `adapter` for `venue` until aborted, restarting it whenever it
+/// fails or stops, unless the pipeline input has closed.
+pub async fn supervise(
+    adapter: Arc<dyn Adapter>,
+    venue: VenueConfig,
+    tx: mpsc::Sender<NormalizedEvent>,
+    cfg: SupervisorConfig,
+    metrics: AdapterMetrics,
+) {
+    let venues = [venue.name.clone()];
+    let attempt = {
+        let tx = tx.clone();
+        move || {
+            let (adapter, venue, tx) = (adapter.clone(), venue.clone(), tx.clone());
+            async move { adapter.connect(venue, tx).await }
+        }
+    };
+    restart(&venues, tx, cfg, metrics, attempt).await
+}
+
+/// Run what `attempt` starts for `venues` until aborted, starting it over
+/// whenever it fails or stops, unless the pipeline input has closed. A
+/// failure counts against each of `venues`.
+pub async fn restart<F, A>(
+    venues: &[String],
+    tx: mpsc::Sender<NormalizedEvent>,
+    cfg: SupervisorConfig,
+    metrics: AdapterMetrics,
+    mut attempt: F,
+) where
+    F: FnMut() -> A,
+    A: Future<Output = Result<(), IngestError>> + Send + 'static,
+{
+    let mut breaker = Breaker::new(cfg);
+    let name = venues.join(",");
+    loop {
+        let started = Instant::now();
+        let mut running = Attempt(tokio::spawn(attempt()));
+        let reason = match (&mut running.0).await {
+            Ok(Ok(())) => "adapter stopped".to_string(),
+            Ok(Err(e)) => e.to_string(),
+            Err(e) => e.to_string(),
+        };
+        drop(running);
+        if tx.is_closed() {
+            return;
+        }
+        let mut failures = 0;
+        for venue in venues {
+            // A failed adapter may not have reported the connection it lost.
+            let _ = tx.try_send(NormalizedEvent::adapter_status(venue, false));
+            let counter = metrics.failures.with_label_values(&[venue]);
+            counter.inc();
+            failures = failures.max(counter.get());
+        }
+        let name = name.as_str();
+        match breaker.fail(started, Instant::now()) {
+            Retry::After(wait) => {
+                tracing::warn!(
+                    venue = name,
+                    failures,
+                    reason,
+                    "adapter failed, restarting in {}ms",
+                    wait.as_millis()
+                );
+                tokio::time::sleep(wait).await;
+            }
+            Retry::Open(wait) => {
+                tracing::error!(
+                    venue = name,
+                    failures,
+                    reason,
+                    "adapter failed repeatedly, holding it stopped for {}s",
+                    wait.as_secs()
+                );
+                let open: Vec<_> = venues
+                    .iter()
+                    .map(|venue| metrics.circuit_open.with_label_values(&[venue]))
+                    .collect();
+                open.iter().for_each(|gauge| gauge.set(1));
+                tokio::time::sleep(wait).await;
+                open.iter().for_each(|gauge| gauge.set(0));
+            }
+        }
+    }
+}
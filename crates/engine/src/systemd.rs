@@ -0,0 +1,138 @@
+//! Notifications for systemd units with `Type=notify`, sent to the socket
+//! named by `$NOTIFY_SOCKET`: `READY=1` once every adapter has connected,
+//! `WATCHDOG=1` from the control loop while `WatchdogSec=` is set, and
+//! `STOPPING=1` when shutdown starts. Outside systemd nothing is sent.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Keepalives are sent every [`watchdog::CHECK_INTERVAL`], so the
+/// watchdog must allow at least twice that.
+///
+/// [`watchdog::CHECK_INTERVAL`]: crate::watchdog::CHECK_INTERVAL
+const MIN_WATCHDOG: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Default)]
+pub struct Systemd {
+    socket: Option<Arc<Socket>>,
+    /// Whether systemd expects keepalives from this process.
+    watchdog: bool,
+    ready: bool,
+}
+
+impl Systemd {
+    pub fn from_env() -> Self {
+        let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+            return Self::default();
+        };
+        let socket = match Socket::open(&path.to_string_lossy()) {
+            Ok(socket) => Arc::new(socket),
+            Err(e) => {
+                tracing::warn!("systemd notify socket {:?}: {}", path, e);
+                return Self::default();
+            }
+        };
+        let ours =
+            std::env::var("WATCHDOG_PID").map_or(true, |pid| pid == std::process::id().to_string());
+        let timeout = std::env::var("WATCHDOG_USEC")
+            .ok()
+            .and_then(|usec| usec.parse().ok())
+            .map(Duration::from_micros);
+        if let Some(timeout) = timeout.filter(|timeout| ours && *timeout < MIN_WATCHDOG) {
+            tracing::warn!(
+                "systemd WatchdogSec of {:?} is below {:?}; keepalives may come too late",
+                timeout,
+                MIN_WATCHDOG
+            );
+        }
+        Self {
+            socket: Some(socket),
+            watchdog: ours && timeout.is_some(),
+            ready: false,
+        }
+    }
+
+    /// Report the engine ready, the first time `connected` holds.
+    pub fn ready(&mut self, connected: bool) {
+        if connected && !self.ready {
+            self.ready = true;
+            self.send("READY=1");
+        }
+    }
+
+    pub fn keepalive(&self) {
+        if self.watchdog {
+            self.send("WATCHDOG=1");
+        }
+    }
+
+    pub fn stopping(&self) {
+        self.send("STOPPING=1");
+    }
+
+    fn send(&self, state: &str) {
+        let Some(socket) = &self.socket else {
+            return;
+        };
+        if let Err(e) = socket.send(state) {
+            tracing::warn!("systemd notify {} failed: {}", state, e);
+        }
+    }
+}
+
+#[cfg(unix)]
+struct Socket {
+    datagram: std::os::unix::net::UnixDatagram,
+    addr: std::os::unix::net::SocketAddr,
+}
+
+#[cfg(unix)]
+impl Socket {
+    /// A path, or an abstract name after `@`.
+    fn open(path: &str) -> std::io::Result<Self> {
+        use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+        let addr = match path.strip_prefix('@') {
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                SocketAddr::from_abstract_name(name.as_bytes())?
+            }
+            #[cfg(not(target_os = "linux"))]
+            Some(_) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "abstract sockets need Linux",
+                ))
+            }
+            None => SocketAddr::from_pathname(path)?,
+        };
+        Ok(Self {
+            datagram: UnixDatagram::unbound()?,
+            addr,
+        })
+    }
+
+    fn send(&self, state: &str) -> std::io::Result<()> {
+        self.datagram
+            .send_to_addr(state.as_bytes(), &self.addr)
+            .map(drop)
+    }
+}
+
+#[cfg(not(unix))]
+enum Socket {}
+
+#[cfg(not(unix))]
+impl Socket {
+    fn open(_path: &str) -> std::io::Result<Self> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "notify sockets need Unix",
+        ))
+    }
+
+    fn send(&self, _state: &str) -> std::io::Result<()> {
+        match *self {}
+    }
+}
@@ -0,0 +1,80 @@
+//! Restarts of adapters whose task runs on but delivers nothing, such as one
+//! reading a socket that went dead without closing.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+use ingest_core::config::WatchdogConfig;
+use ops::AdapterState;
+
+/// How often venues are checked.
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct Watchdog {
+    cfg: WatchdogConfig,
+    /// Venues restarted since their last event.
+    stalled: HashMap<String, Stall>,
+}
+
+struct Stall {
+    restarted: Instant,
+    /// Restarts in a row without an event in between.
+    restarts: u32,
+}
+
+impl Watchdog {
+    pub fn new(cfg: WatchdogConfig) -> Self {
+        Self {
+            cfg,
+            stalled: HashMap::new(),
+        }
+    }
+
+    /// Venues of `adapters` to restart now. A venue is due once it has
+    /// gone `stale_secs` without an event, and again while it stays silent
+    /// after a backoff that doubles with each restart in a row.
+    pub fn due(&mut self, adapters: &BTreeMap<String, AdapterState>, now: Instant) -> Vec<String> {
+        let stale = Duration::from_secs(self.cfg.stale_secs);
+        self.stalled.retain(|venue, _| {
+            adapters.get(venue).is_some_and(|adapter| {
+                Duration::from_secs_f64(adapter.last_message_age_secs) >= stale
+            })
+        });
+        let mut due = Vec::new();
+        for (venue, adapter) in adapters {
+            if Duration::from_secs_f64(adapter.last_message_age_secs) < stale {
+                continue;
+            }
+            match self.stalled.get_mut(venue) {
+                None => {
+                    self.stalled.insert(
+                        venue.clone(),
+                        Stall {
+                            restarted: now,
+                            restarts: 1,
+                        },
+                    );
+                }
+                Some(stall)
+                    if now.duration_since(stall.restarted)
+                        >= stale + backoff(&self.cfg, stall.restarts) =>
+                {
+                    stall.restarted = now;
+                    stall.restarts += 1;
+                }
+                Some(_) => continue,
+            }
+            due.push(venue.clone());
+        }
+        due
+    }
+}
+
+/// Extra wait after `restarts` restarts in a row.
+fn backoff(cfg: &WatchdogConfig, restarts: u32) -> Duration {
+    let ms = cfg
+        .initial_backoff_ms
+        .saturating_mul(1 << (restarts - 1).min(32))
+        .min(cfg.max_backoff_ms);
+    Duration::from_millis(ms)
+}
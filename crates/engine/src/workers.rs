@@ -0,0 +1,318 @@
+//! Venue adapters run in worker processes, so a parser that crashes takes
+//! down only its own worker's venues.
+//!
+//! For each worker the engine binds `<socket_dir>/<worker>.sock`, starts
+//! `ingestd worker <worker>` and writes it a [`Spec`] as one JSON line on
+//! stdin. The worker connects, runs the adapters of its venues and writes
+//! their events to the socket framed as the Unix socket sink frames them:
+//!
+//! ```text
+//! len: u32 (big-endian) | protobuf-encoded event
+//! ```
+//!
+//! The engine keeps the worker's stdin open while it runs it, and the worker
+//! exits once stdin closes, so workers do not outlive the engine. A worker
+//! that exits is restarted like a failing adapter.
+
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+
+use ingest_core::{
+    config::{Encoding, LogConfig, SupervisorConfig, VenueConfig, WorkersConfig},
+    error::IngestError,
+    event::NormalizedEvent,
+};
+use ops::AdapterMetrics;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, BufWriter};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::{logging, supervisor};
+
+/// What a worker runs, sent to it on stdin.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Spec {
+    /// The socket the engine listens on for this worker.
+    pub socket: PathBuf,
+    pub venues: Vec<VenueConfig>,
+    pub supervisor: SupervisorConfig,
+    pub log: LogConfig,
+    /// Events buffered between the adapters and the socket.
+    pub capacity: usize,
+}
+
+/// Starts the workers of the engine.
+pub struct Workers {
+    cfg: WorkersConfig,
+    exe: PathBuf,
+    log: LogConfig,
+    capacity: usize,
+}
+
+impl Workers {
+    /// Workers that log like the engine, but to stderr, and buffer
+    /// `capacity` events.
+    pub fn new(cfg: WorkersConfig, log: &LogConfig, capacity: usize) -> Result<Self, IngestError> {
+        create_private_dir(Path::new(&cfg.socket_dir))?;
+        Ok(Self {
+            cfg,
+            exe: std::env::current_exe()?,
+            log: LogConfig {
+                file: None,
+                ..log.clone()
+            },
+            capacity,
+        })
+    }
+
+    /// The worker that runs `venue`.
+    pub fn worker_of<'a>(&'a self, venue: &'a str) -> &'a str {
+        self.cfg.worker_of(venue)
+    }
+
+    /// Run the worker `name` with `venues`, feeding their events into `tx`,
+    /// and restart it whenever it exits until aborted.
+    pub fn spawn(
+        &self,
+        name: &str,
+        venues: Vec<VenueConfig>,
+        tx: mpsc::Sender<NormalizedEvent>,
+        cfg: SupervisorConfig,
+        metrics: AdapterMetrics,
+    ) -> JoinHandle<()> {
+        let names: Vec<String> = venues.iter().map(|venue| venue.name.clone()).collect();
+        let spec = Spec {
+            socket: Path::new(&self.cfg.socket_dir).join(format!("{}.sock", name)),
+            venues,
+            supervisor: cfg.clone(),
+            log: self.log.clone(),
+            capacity: self.capacity,
+        };
+        let (exe, name) = (self.exe.clone(), name.to_string());
+        let attempt = {
+            let tx = tx.clone();
+            move || run(exe.clone(), name.clone(), spec.clone(), tx.clone())
+        };
+        tokio::spawn(async move { supervisor::restart(&names, tx, cfg, metrics, attempt).await })
+    }
+}
+
+/// Create `dir` accessible to this user only, as anyone who can connect to
+/// a worker's socket first can feed the engine events.
+#[cfg(unix)]
+fn create_private_dir(dir: &Path) -> Result<(), IngestError> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)?;
+    let mode = std::fs::metadata(dir)?.permissions().mode();
+    if mode & 0o077 != 0 {
+        tracing::warn!(
+            "worker socket_dir {} is accessible to other users (mode {:o})",
+            dir.display(),
+            mode & 0o777
+        );
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(dir: &Path) -> Result<(), IngestError> {
+    Ok(std::fs::create_dir_all(dir)?)
+}
+
+/// Start the worker `name` and relay its events into `tx` until it exits.
+#[cfg(unix)]
+async fn run(
+    exe: PathBuf,
+    name: String,
+    spec: Spec,
+    tx: mpsc::Sender<NormalizedEvent>,
+) -> Result<(), IngestError> {
+    // A socket left behind by a previous run would make bind fail.
+    let _ = std::fs::remove_file(&spec.socket);
+    let listener = tokio::net::UnixListener::bind(&spec.socket)?;
+    let mut child = tokio::process::Command::new(exe)
+        .arg("worker")
+        .arg(&name)
+        .stdin(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    // Held until the worker is done with, which tells it to exit.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let mut line = serde_json::to_vec(&spec)?;
+    line.push(b'\n');
+    stdin.write_all(&line).await?;
+    let stream = tokio::select! {
+        accepted = listener.accept() => accepted?.0,
+        status = child.wait() => return Err(exited(&name, status?)),
+    };
+    drop(listener);
+    let _ = std::fs::remove_file(&spec.socket);
+    if let Err(e) = relay(&name, stream, &tx).await {
+        // The worker cannot be read any further, so it is restarted.
+        let _ = child.kill().await;
+        return Err(e);
+    }
+    if tx.is_closed() {
+        return Ok(());
+    }
+    let status = child.wait().await?;
+    Err(exited(&name, status))
+}
+
+#[cfg(not(unix))]
+async fn run(
+    _exe: PathBuf,
+    _name: String,
+    _spec: Spec,
+    _tx: mpsc::Sender<NormalizedEvent>,
+) -> Result<(), IngestError> {
+    Err(IngestError::Validation(
+        "workers need Unix sockets, which this platform lacks".into(),
+    ))
+}
+
+fn exited(name: &str, status: ExitStatus) -> IngestError {
+    IngestError::Control(format!("worker {} exited: {}", name, status))
+}
+
+/// Largest frame a worker may send. Events are far smaller, so a longer one
+/// means the stream is corrupt.
+const MAX_FRAME: u32 = 16 << 20;
+
+/// Feed the events framed on `stream` by the worker `name` into `tx` until
+/// the worker closes it or the pipeline input closes. Fails on a frame over
+/// [`MAX_FRAME`].
+async fn relay<R>(
+    name: &str,
+    stream: R,
+    tx: &mpsc::Sender<NormalizedEvent>,
+) -> Result<(), IngestError>
+where
+    R: tokio::io::AsyncRead + Unpin,
+{
+    let mut stream = BufReader::new(stream);
+    let mut body = Vec::new();
+    loop {
+        let len = match stream.read_u32().await {
+            Ok(len) => len,
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        if len > MAX_FRAME {
+            return Err(IngestError::Control(format!(
+                "worker {} sent a frame of {} bytes, over the {} byte limit",
+                name, len, MAX_FRAME
+            )));
+        }
+        body.resize(len as usize, 0);
+        stream.read_exact(&mut body).await?;
+        let event = sinks::codec::decode(&body, Encoding::Proto)?;
+        if tx.send(event).await.is_err() {
+            return Ok(());
+        }
+    }
+}
+
+/// Frame `event` onto `out` as [`relay`] reads it.
+async fn write<W>(out: &mut W, event: &NormalizedEvent) -> Result<(), IngestError>
+where
+    W: tokio::io::AsyncWrite + Unpin,
+{
+    let body = sinks::codec::encode(event, Encoding::Proto)?;
+    out.write_u32(body.len() as u32).await?;
+    out.write_all(&body).await?;
+    Ok(())
+}
+
+/// Run as the worker `name`: read the spec from stdin, run the adapters of
+/// its venues and send their events to the engine until stdin closes.
+#[cfg(unix)]
+pub async fn serve(name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut stdin = BufReader::new(tokio::io::stdin());
+    let mut line = String::new();
+    stdin.read_line(&mut line).await?;
+    let spec: Spec = serde_json::from_str(&line)?;
+    let _log_filter = logging::init(&spec.log)?;
+    let registry = agents::AdapterRegistry::builtin();
+    let mut out = BufWriter::new(tokio::net::UnixStream::connect(&spec.socket).await?);
+    let (tx, mut rx) = mpsc::channel(spec.capacity);
+    let metrics = AdapterMetrics::new();
+    let mut tasks = Vec::with_capacity(spec.venues.len());
+    for venue in spec.venues {
+        let Some(adapter) = registry.get(&venue.venue_type) else {
+            return Err(IngestError::Validation(format!(
+                "venue {} has unknown type {}",
+                venue.name, venue.venue_type
+            ))
+            .into());
+        };
+        tasks.push(tokio::spawn(supervisor::supervise(
+            adapter,
+            venue,
+            tx.clone(),
+            spec.supervisor.clone(),
+            metrics.clone(),
+        )));
+    }
+    drop(tx);
+    tracing::info!("worker {} running {} venues", name, tasks.len());
+    let closed = async move {
+        let mut rest = Vec::new();
+        let _ = stdin.read_to_end(&mut rest).await;
+    };
+    tokio::pin!(closed);
+    let result = loop {
+        tokio::select! {
+            event = rx.recv() => {
+                let Some(event) = event else { break Ok(()) };
+                if let Err(e) = write(&mut out, &event).await {
+                    break Err(e);
+                }
+                if rx.is_empty() {
+                    if let Err(e) = out.flush().await {
+                        break Err(e.into());
+                    }
+                }
+            }
+            _ = &mut closed => break Ok(()),
+        }
+    };
+    for task in tasks {
+        task.abort();
+    }
+    Ok(result?)
+}
+
+#[cfg(not(unix))]
+pub async fn serve(_name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    Err(
+        IngestError::Validation("workers need Unix sockets, which this platform lacks".into())
+            .into(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn relay_rejects_an_oversized_frame() {
+        let event = NormalizedEvent {
+            venue: "binance_spot".into(),
+            symbol: "BTCUSDT".into(),
+            ..Default::default()
+        };
+        let mut framed = Vec::new();
+        write(&mut framed, &event).await.unwrap();
+        framed.extend_from_slice(&(MAX_FRAME + 1).to_be_bytes());
+        let (tx, mut rx) = mpsc::channel(4);
+        let relayed = relay("binance", framed.as_slice(), &tx).await;
+        assert!(matches!(relayed, Err(IngestError::Control(_))));
+        assert_eq!(rx.recv().await.unwrap(), event);
+    }
+}
@@ -0,0 +1,478 @@
+//! Arrow Flight server for columnar consumers.
+//!
+//! Both calls take the same JSON query, as the `DoGet` ticket or as the
+//! descriptor command of the first `DoExchange` message:
+//!
+//! ```json
+//! {"from": "2024-05-01T00:00:00Z", "to": "2024-05-01T01:00:00Z",
+//!  "from_sequence": 1, "venues": ["binance_spot"], "symbols": ["BTCUSDT"], "kinds": ["trade"]}
+//! ```
+//!
+//! Every field is optional and an empty query matches everything; symbols may
+//! be globs such as `BTC*`. `DoGet` streams the matching events from the
+//! write-ahead log and ends; `DoExchange` tails the live bus until the client
+//! goes away. Batches follow [`schema`].
+
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use api::{EventBus, Filter};
+use arrow_array::{
+    builder::{StringBuilder, TimestampMicrosecondBuilder, UInt64Builder},
+    ArrayRef, RecordBatch,
+};
+use arrow_flight::{
+    encode::FlightDataEncoderBuilder,
+    error::FlightError,
+    flight_service_server::{FlightService, FlightServiceServer},
+    Action, ActionType, Criteria, Empty, FlightData, FlightDescriptor, FlightInfo,
+    HandshakeRequest, HandshakeResponse, PollInfo, PutResult, SchemaResult, Ticket,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use chrono::{DateTime, Utc};
+use futures::{stream::BoxStream, Stream, StreamExt, TryStreamExt};
+use ingest_core::{
+    config::FlightConfig,
+    error::IngestError,
+    event::{EventKind, NormalizedEvent},
+};
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status, Streaming};
+use wal::WalReader;
+
+type Batches = mpsc::Sender<Result<RecordBatch, FlightError>>;
+
+/// Columns of every batch the server sends. `sequence` is the WAL sequence
+/// number and is null for live events.
+pub fn schema() -> SchemaRef {
+    let timestamp = DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()));
+    Arc::new(Schema::new(vec![
+        Field::new("sequence", DataType::UInt64, true),
+        Field::new("venue", DataType::Utf8, false),
+        Field::new("symbol", DataType::Utf8, false),
+        Field::new("kind", DataType::Utf8, false),
+        Field::new("timestamp", timestamp.clone(), false),
+        Field::new("received_at", timestamp, true),
+        Field::new("payload", DataType::Utf8, false),
+    ]))
+}
+
+pub struct FlightServer {
+    bus: EventBus,
+    wal: Option<WalReader>,
+    cfg: FlightConfig,
+}
+
+impl FlightServer {
+    pub fn new(bus: EventBus, cfg: FlightConfig) -> Self {
+        Self {
+            bus,
+            wal: None,
+            cfg,
+        }
+    }
+
+    /// Serve `DoGet` from `reader`; without a log only `DoExchange` works.
+    pub fn with_wal(mut self, reader: WalReader) -> Self {
+        self.wal = Some(reader);
+        self
+    }
+
+    pub async fn run(self, addr: SocketAddr) -> Result<(), IngestError> {
+        tonic::transport::Server::builder()
+            .add_service(FlightServiceServer::new(self))
+            .serve(addr)
+            .await
+            .map_err(|e| IngestError::Io(std::io::Error::other(e)))
+    }
+
+    fn encode(&self, rx: mpsc::Receiver<Result<RecordBatch, FlightError>>) -> FlightStream {
+        let stream = FlightDataEncoderBuilder::new()
+            .with_schema(schema())
+            .build(ReceiverStream::new(rx))
+            .map_err(Status::from);
+        Box::pin(stream)
+    }
+}
+
+type FlightStream = Pin<Box<dyn Stream<Item = Result<FlightData, Status>> + Send>>;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Query {
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    from_sequence: Option<u64>,
+    #[serde(default)]
+    venues: HashSet<String>,
+    #[serde(default)]
+    symbols: HashSet<String>,
+    #[serde(default)]
+    kinds: HashSet<EventKind>,
+    #[serde(skip)]
+    filter: Filter,
+}
+
+impl Query {
+    fn parse(bytes: &[u8]) -> Result<Self, Status> {
+        if bytes.is_empty() {
+            return Ok(Self::default());
+        }
+        let mut query: Self = serde_json::from_slice(bytes)
+            .map_err(|e| Status::invalid_argument(format!("invalid query: {}", e)))?;
+        query.filter = Filter::new()
+            .venues(query.venues.iter().cloned())
+            .symbols(query.symbols.iter().cloned())
+            .kinds(query.kinds.iter().copied());
+        Ok(query)
+    }
+
+    fn matches(&self, event: &NormalizedEvent) -> bool {
+        self.from.is_none_or(|from| event.timestamp >= from)
+            && self.to.is_none_or(|to| event.timestamp < to)
+            && self.filter.matches(event)
+    }
+}
+
+/// Column builders for one batch at a time.
+struct Rows {
+    schema: SchemaRef,
+    sequence: UInt64Builder,
+    venue: StringBuilder,
+    symbol: StringBuilder,
+    kind: StringBuilder,
+    timestamp: TimestampMicrosecondBuilder,
+    received_at: TimestampMicrosecondBuilder,
+    payload: StringBuilder,
+    len: usize,
+}
+
+impl Rows {
+    fn new() -> Self {
+        Self {
+            schema: schema(),
+            sequence: UInt64Builder::new(),
+            venue: StringBuilder::new(),
+            symbol: StringBuilder::new(),
+            kind: StringBuilder::new(),
+            timestamp: TimestampMicrosecondBuilder::new().with_timezone("UTC"),
+            received_at: TimestampMicrosecondBuilder::new().with_timezone("UTC"),
+            payload: StringBuilder::new(),
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, sequence: Option<u64>, event: &NormalizedEvent) {
+        self.sequence.append_option(sequence);
+        self.venue.append_value(&event.venue);
+        self.symbol.append_value(&event.symbol);
+        self.kind.append_value(event.kind.as_str());
+        self.timestamp
+            .append_value(event.timestamp.timestamp_micros());
+        self.received_at
+            .append_option(event.received_at.map(|t| t.timestamp_micros()));
+        self.payload.append_value(event.payload.to_string());
+        self.len += 1;
+    }
+
+    /// Take the rows pushed so far as a batch.
+    fn finish(&mut self) -> Result<RecordBatch, FlightError> {
+        self.len = 0;
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.sequence.finish()),
+            Arc::new(self.venue.finish()),
+            Arc::new(self.symbol.finish()),
+            Arc::new(self.kind.finish()),
+            Arc::new(self.timestamp.finish()),
+            Arc::new(self.received_at.finish()),
+            Arc::new(self.payload.finish()),
+        ];
+        RecordBatch::try_new(self.schema.clone(), columns).map_err(FlightError::from)
+    }
+}
+
+/// Stream the logged events matching `query` as batches of `batch_rows`.
+fn read_wal(wal: WalReader, query: Query, batch_rows: usize, tx: Batches) {
+    let from = query.from.unwrap_or(DateTime::<Utc>::MIN_UTC);
+    let to = query.to.unwrap_or(DateTime::<Utc>::MAX_UTC);
+    let entries = match query.from_sequence {
+        Some(sequence) => wal
+            .from_sequence(sequence)
+            .map(|entries| entries.window(from, to)),
+        None => wal.range(from, to),
+    };
+    let entries = match entries {
+        Ok(entries) => entries,
+        Err(e) => {
+            let _ = tx.blocking_send(Err(external(e)));
+            return;
+        }
+    };
+    let mut rows = Rows::new();
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                let _ = tx.blocking_send(Err(external(e)));
+                return;
+            }
+        };
+        if !query.matches(&entry.event) {
+            continue;
+        }
+        rows.push(Some(entry.sequence), &entry.event);
+        if rows.len >= batch_rows && tx.blocking_send(rows.finish()).is_err() {
+            return;
+        }
+    }
+    if rows.len > 0 {
+        let _ = tx.blocking_send(rows.finish());
+    }
+}
+
+/// Batch live events matching `query` until the client goes away, sending a
+/// partial batch once it is `flush` old.
+async fn tail(
+    events: impl Stream<Item = NormalizedEvent>,
+    query: Query,
+    batch_rows: usize,
+    flush: Duration,
+    tx: Batches,
+) {
+    let mut events = std::pin::pin!(events);
+    let mut rows = Rows::new();
+    let mut tick = tokio::time::interval(flush);
+    loop {
+        tokio::select! {
+            event = events.next() => {
+                let Some(event) = event else { break };
+                if !query.matches(&event) {
+                    continue;
+                }
+                rows.push(None, &event);
+                if rows.len >= batch_rows && tx.send(rows.finish()).await.is_err() {
+                    return;
+                }
+            }
+            _ = tick.tick() => {
+                if rows.len > 0 && tx.send(rows.finish()).await.is_err() {
+                    return;
+                }
+            }
+            _ = tx.closed() => return,
+        }
+    }
+    if rows.len > 0 {
+        let _ = tx.send(rows.finish()).await;
+    }
+}
+
+fn external(e: IngestError) -> FlightError {
+    FlightError::ExternalError(Box::new(e))
+}
+
+#[tonic::async_trait]
+impl FlightService for FlightServer {
+    type HandshakeStream = BoxStream<'static, Result<HandshakeResponse, Status>>;
+    type ListFlightsStream = BoxStream<'static, Result<FlightInfo, Status>>;
+    type DoGetStream = FlightStream;
+    type DoPutStream = BoxStream<'static, Result<PutResult, Status>>;
+    type DoExchangeStream = FlightStream;
+    type DoActionStream = BoxStream<'static, Result<arrow_flight::Result, Status>>;
+    type ListActionsStream = BoxStream<'static, Result<ActionType, Status>>;
+
+    async fn do_get(
+        &self,
+        request: Request<Ticket>,
+    ) -> Result<Response<Self::DoGetStream>, Status> {
+        let wal = self.wal.clone().ok_or_else(|| {
+            Status::failed_precondition("historical reads require the write-ahead log")
+        })?;
+        let query = Query::parse(&request.into_inner().ticket)?;
+        let (tx, rx) = mpsc::channel(2);
+        let batch_rows = self.cfg.batch_rows;
+        tokio::task::spawn_blocking(move || read_wal(wal, query, batch_rows, tx));
+        Ok(Response::new(self.encode(rx)))
+    }
+
+    async fn do_exchange(
+        &self,
+        request: Request<Streaming<FlightData>>,
+    ) -> Result<Response<Self::DoExchangeStream>, Status> {
+        let first = request
+            .into_inner()
+            .message()
+            .await?
+            .ok_or_else(|| Status::invalid_argument("expected a message with the query"))?;
+        let cmd = first
+            .flight_descriptor
+            .map(|descriptor| descriptor.cmd)
+            .unwrap_or_default();
+        let query = Query::parse(&cmd)?;
+        let (tx, rx) = mpsc::channel(2);
+        tokio::spawn(tail(
+            self.bus.subscribe_named("flight", query.filter.clone()),
+            query,
+            self.cfg.batch_rows,
+            Duration::from_millis(self.cfg.live_flush_ms),
+            tx,
+        ));
+        Ok(Response::new(self.encode(rx)))
+    }
+
+    async fn handshake(
+        &self,
+        _request: Request<Streaming<HandshakeRequest>>,
+    ) -> Result<Response<Self::HandshakeStream>, Status> {
+        Err(Status::unimplemented("handshake"))
+    }
+
+    async fn list_flights(
+        &self,
+        _request: Request<Criteria>,
+    ) -> Result<Response<Self::ListFlightsStream>, Status> {
+        Err(Status::unimplemented("list_flights"))
+    }
+
+    async fn get_flight_info(
+        &self,
+        _request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        Err(Status::unimplemented("get_flight_info"))
+    }
+
+    async fn poll_flight_info(
+        &self,
+        _request: Request<FlightDescriptor>,
+    ) -> Result<Response<PollInfo>, Status> {
+        Err(Status::unimplemented("poll_flight_info"))
+    }
+
+    async fn get_schema(
+        &self,
+        _request: Request<FlightDescriptor>,
+    ) -> Result<Response<SchemaResult>, Status> {
+        Err(Status::unimplemented("get_schema"))
+    }
+
+    async fn do_put(
+        &self,
+        _request: Request<Streaming<FlightData>>,
+    ) -> Result<Response<Self::DoPutStream>, Status> {
+        Err(Status::unimplemented("do_put"))
+    }
+
+    async fn do_action(
+        &self,
+        _request: Request<Action>,
+    ) -> Result<Response<Self::DoActionStream>, Status> {
+        Err(Status::unimplemented("do_action"))
+    }
+
+    async fn list_actions(
+        &self,
+        _request: Request<Empty>,
+    ) -> Result<Response<Self::ListActionsStream>, Status> {
+        Err(Status::unimplemented("list_actions"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::{cast::AsArray, types::UInt64Type};
+    use arrow_flight::FlightClient;
+    use ingest_core::config::WalConfig;
+
+    fn cfg() -> FlightConfig {
+        FlightConfig {
+            addr: String::new(),
+            batch_rows: 2,
+            live_flush_ms: 50,
+        }
+    }
+
+    fn event(symbol: &str, secs: i64) -> NormalizedEvent {
+        NormalizedEvent {
+            venue: "binance_spot".into(),
+            symbol: symbol.into(),
+            timestamp: DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap(),
+            received_at: None,
+            kind: EventKind::Trade,
+            payload: serde_json::json!({"price": 1.5}),
+        }
+    }
+
+    async fn client(server: FlightServer, port: u16) -> FlightClient {
+        let addr: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+        tokio::spawn(server.run(addr));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let channel = tonic::transport::Channel::from_shared(format!("http://{}", addr))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        FlightClient::new(channel)
+    }
+
+    #[tokio::test]
+    async fn do_get_reads_wal_range_in_batches() {
+        let dir = std::env::temp_dir().join(format!("ingest-flight-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut log = wal::Wal::open(&WalConfig {
+            path: dir.to_string_lossy().into_owned(),
+            fsync: Default::default(),
+            fsync_interval_ms: 1_000,
+            segment_bytes: 1024 * 1024,
+            retention_segments: None,
+        })
+        .unwrap();
+        for secs in 0..5 {
+            log.append(&event("BTCUSDT", secs)).unwrap();
+        }
+        log.append(&event("ETHUSDT", 2)).unwrap();
+
+        let server = FlightServer::new(EventBus::new(16), cfg()).with_wal(WalReader::open(&dir));
+        let mut client = client(server, 50161).await;
+        let query = r#"{"from": "2023-11-14T22:13:21Z", "symbols": ["BTCUSDT"]}"#;
+        let batches: Vec<RecordBatch> = client
+            .do_get(Ticket::new(query))
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(batches.len(), 2);
+        let sequences: Vec<u64> = batches
+            .iter()
+            .flat_map(|b| b.column(0).as_primitive::<UInt64Type>().values().to_vec())
+            .collect();
+        assert_eq!(sequences, vec![2, 3, 4, 5]);
+        drop(log);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn do_exchange_tails_live_events() {
+        let bus = EventBus::new(16);
+        let publisher = bus.publisher();
+        let mut client = client(FlightServer::new(bus, cfg()), 50162).await;
+        let query = FlightData::new()
+            .with_descriptor(FlightDescriptor::new_cmd(r#"{"symbols": ["ETHUSDT"]}"#));
+        let mut batches = client
+            .do_exchange(futures::stream::iter([Ok(query)]))
+            .await
+            .unwrap();
+        publisher.publish(event("BTCUSDT", 0));
+        publisher.publish(event("ETHUSDT", 1));
+        let batch = batches.next().await.unwrap().unwrap();
+        assert_eq!(batch.num_rows(), 1);
+        assert_eq!(batch.column(2).as_string::<i32>().value(0), "ETHUSDT");
+        assert!(batch.column(0).is_null(0));
+    }
+}
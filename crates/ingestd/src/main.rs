@@ -1,47 +1,239 @@
-use std::{env, fs, net::SocketAddr};
-
-use agents::{binance::BinanceAdapter, Adapter};
-use api::EventBus;
-use ingest_core::config::Config;
-use ops::OpsServer;
-use tokio::sync::mpsc;
-
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cfg_path = env::args().nth(1).expect("config path required");
-    let data = fs::read_to_string(cfg_path)?;
-    let cfg = Config::from_str(&data)?;
-
-    let bus = EventBus::new(1024);
-    let publisher = bus.publisher();
-    let mut consumer = bus.subscribe();
-    let log_handle = tokio::spawn(async move {
-        while let Some(evt) = consumer.recv().await {
-            println!("event: {:?}", evt);
-        }
-    });
-
-    let ops = OpsServer::new();
-    let ops_addr: SocketAddr = "127.0.0.1:3000".parse().unwrap();
-    let ops_handle = tokio::spawn(ops.run(ops_addr));
-
-    let (tx, mut rx) = mpsc::channel(100);
-    let forward_handle = tokio::spawn(async move {
-        while let Some(evt) = rx.recv().await {
-            publisher.publish(evt);
-        }
-    });
-
-    for venue in cfg.venues {
-        let tx = tx.clone();
-        tokio::spawn(async move {
-            let adapter = BinanceAdapter;
-            if let Err(e) = adapter.connect(venue, tx).await {
-                eprintln!("adapter error: {e}");
+use std::fs;
+use std::sync::OnceLock;
+
+use agents::AdapterRegistry;
+use clap::{Parser, Subcommand};
+use ingest_core::{
+    config::{Config, RuntimeConfig},
+    error::IngestError,
+};
+use ingest_engine::{logging, validate, Engine};
+
+/// Allocate through jemalloc, so `/debug/pprof/heap` can profile the heap.
+#[cfg(feature = "jemalloc")]
+#[global_allocator]
+static ALLOCATOR: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+/// Sample an allocation every 512 KiB on average from startup, so heap
+/// profiles cover what was allocated before they were asked for. The
+/// `_RJEM_MALLOC_CONF` environment variable overrides it.
+#[cfg(feature = "jemalloc")]
+#[export_name = "_rjem_malloc_conf"]
+static MALLOC_CONF: &[u8; 45] = b"prof:true,prof_active:true,lg_prof_sample:19\0";
+
+#[derive(Parser)]
+#[command(name = "ingestd")]
+#[command(about = "Market data ingestion engine")]
+#[command(version = version())]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the engine with a config file
+    Run {
+        config: String,
+        /// Apply the overrides of this `[profiles.<name>]` table
+        #[arg(long)]
+        profile: Option<String>,
+        /// Check the venues and sinks, print a summary and exit without
+        /// ingesting
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Validate a config file and check that its venues are reachable
+    Check {
+        config: String,
+        /// Apply the overrides of this `[profiles.<name>]` table
+        #[arg(long)]
+        profile: Option<String>,
+    },
+    /// Print the JSON Schema of the config file
+    PrintSchema,
+    /// Print the version and build
+    Version,
+    /// Run the adapters of a worker process, as started by the engine
+    #[command(hide = true)]
+    Worker { name: String },
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    match Cli::parse().command {
+        Command::Run {
+            config: path,
+            profile,
+            dry_run,
+        } => {
+            // Validated before the runtime is sized by it.
+            let cfg = load(&path, profile.as_deref())?;
+            validate(&cfg, &AdapterRegistry::builtin())?;
+            let runtime = runtime(&cfg.runtime)?;
+            if dry_run {
+                runtime.block_on(self::dry_run(cfg))
+            } else {
+                runtime.block_on(run(cfg, path, profile))
+            }
+        }
+        Command::Check { config, profile } => {
+            tokio::runtime::Runtime::new()?.block_on(check(&config, profile.as_deref()))
+        }
+        Command::PrintSchema => {
+            println!("{}", serde_json::to_string_pretty(&Config::schema())?);
+            Ok(())
+        }
+        Command::Version => {
+            println!("ingestd {}", version());
+            Ok(())
+        }
+        Command::Worker { name } => {
+            tokio::runtime::Runtime::new()?.block_on(ingest_engine::serve_worker(&name))
+        }
+    }
+}
+
+/// A multi-threaded Tokio runtime sized by `cfg`.
+fn runtime(cfg: &RuntimeConfig) -> std::io::Result<tokio::runtime::Runtime> {
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    builder
+        .enable_all()
+        .max_blocking_threads(cfg.max_blocking_threads)
+        .event_interval(cfg.event_interval);
+    if let Some(threads) = cfg.worker_threads {
+        builder.worker_threads(threads);
+    }
+    builder.build()
+}
+
+/// The version with the commit, build time and features, for `--version`.
+fn version() -> &'static str {
+    static VERSION: OnceLock<String> = OnceLock::new();
+    VERSION.get_or_init(|| ingest_engine::build_info().to_string())
+}
+
+/// Read the config at `path`, with the overrides of `profile`.
+fn load(path: &str, profile: Option<&str>) -> Result<Config, Box<dyn std::error::Error>> {
+    let data = fs::read_to_string(path)?;
+    Ok(Config::from_str_with_profile(&data, profile)?)
+}
+
+/// Validate the config at `path`, then check each venue, printing how it
+/// went.
+async fn check(path: &str, profile: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let cfg = load(path, profile)?;
+    let registry = AdapterRegistry::builtin();
+    validate(&cfg, &registry)?;
+    println!("config ok: {} venues", cfg.venues.len());
+    let (reachable, _) = check_venues(&cfg, &registry).await;
+    unreachable("venues", reachable, cfg.venues.len())
+}
+
+/// Check the venues of the validated `cfg` as [`check`] does, and each
+/// sink too, then print a summary. Nothing is ingested or delivered.
+async fn dry_run(cfg: Config) -> Result<(), Box<dyn std::error::Error>> {
+    let registry = AdapterRegistry::builtin();
+    println!(
+        "config ok: {} venues, {} sinks",
+        cfg.venues.len(),
+        cfg.sinks.len()
+    );
+    let (venues, symbols) = check_venues(&cfg, &registry).await;
+    let mut sinks = 0;
+    for (name, sink_cfg) in &cfg.sinks {
+        match sinks::check(name, sink_cfg).await {
+            Ok(()) => {
+                println!("sink {}: ok", name);
+                sinks += 1;
             }
-        });
+            Err(e) => println!("sink {}: {}", name, e),
+        }
+    }
+    println!(
+        "dry run: {} of {} venues reachable with {} symbols, {} of {} sinks reachable",
+        venues,
+        cfg.venues.len(),
+        symbols,
+        sinks,
+        cfg.sinks.len()
+    );
+    unreachable("venues", venues, cfg.venues.len())?;
+    unreachable("sinks", sinks, cfg.sinks.len())
+}
+
+/// Check each venue of `cfg`, printing how it went, and return how many
+/// are reachable and the symbols they would subscribe to.
+async fn check_venues(cfg: &Config, registry: &AdapterRegistry) -> (usize, usize) {
+    let (mut reachable, mut symbols) = (0, 0);
+    for venue in &cfg.venues {
+        let Some(adapter) = registry.get(&venue.venue_type) else {
+            continue;
+        };
+        match adapter.check(venue).await {
+            Ok(subscribed) => {
+                println!("venue {}: ok, {} symbols", venue.name, subscribed.len());
+                reachable += 1;
+                symbols += subscribed.len();
+            }
+            Err(e) => println!("venue {}: {}", venue.name, e),
+        }
+    }
+    (reachable, symbols)
+}
+
+fn unreachable(
+    what: &str,
+    reachable: usize,
+    total: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if reachable < total {
+        return Err(IngestError::Validation(format!(
+            "{} of {} {} unreachable",
+            total - reachable,
+            total,
+            what
+        ))
+        .into());
     }
+    Ok(())
+}
 
-    let _ = tokio::join!(ops_handle, forward_handle, log_handle);
+async fn run(
+    cfg: Config,
+    cfg_path: String,
+    profile: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let log_filter = logging::init(&cfg.log)?;
+    let mut builder = Engine::builder()
+        .config(cfg)
+        .config_path(cfg_path)
+        .log_filter(log_filter);
+    if let Some(profile) = profile {
+        builder = builder.profile(profile);
+    }
+    let mut engine = builder.build()?.start()?;
+    // Answering a shutdown request stops the engine; on a signal it is
+    // stopped here.
+    tokio::select! {
+        signalled = shutdown_signal() => signalled?,
+        () = engine.stopped() => {}
+    }
+    engine.shutdown().await?;
     Ok(())
 }
+
+/// Wait for `SIGTERM` or Ctrl-C.
+async fn shutdown_signal() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            interrupted = tokio::signal::ctrl_c() => interrupted,
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await
+}
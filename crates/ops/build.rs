@@ -0,0 +1,14 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "otlp")]
+    {
+        // Use the bundled protoc so building does not need one installed.
+        std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+        tonic_prost_build::configure()
+            .build_server(false)
+            .compile_protos(
+                &["proto/opentelemetry/proto/collector/metrics/v1/metrics_service.proto"],
+                &["proto"],
+            )?;
+    }
+    Ok(())
+}
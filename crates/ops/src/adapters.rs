@@ -0,0 +1,315 @@
+//! Adapter connection state and subscriptions, driven by the status events
+//! adapters publish (see [`NormalizedEvent::adapter_status`] and
+//! [`NormalizedEvent::adapter_subscription`]).
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use ingest_core::canonical_symbol;
+use ingest_core::event::NormalizedEvent;
+use prometheus::{GaugeVec, IntCounterVec, IntGaugeVec, Opts, Registry};
+use serde::Serialize;
+
+/// How often the message ages are refreshed.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone)]
+pub struct AdapterMetrics {
+    pub connected: IntGaugeVec,
+    pub last_message_age: GaugeVec,
+    pub reconnects: IntCounterVec,
+    /// Restarts by the watchdog of adapters that stopped delivering.
+    pub restarts: IntCounterVec,
+    /// Adapter tasks that failed or stopped, restarted by their supervisor.
+    pub failures: IntCounterVec,
+    /// 1 while the supervisor holds the venue's adapter stopped.
+    pub circuit_open: IntGaugeVec,
+    adapters: Arc<Mutex<HashMap<String, Adapter>>>,
+}
+
+struct Adapter {
+    connected: bool,
+    /// Whether it has connected before, so the next connect is a reconnect.
+    seen_up: bool,
+    /// The last event from the venue, or when tracking started.
+    last_message: Instant,
+    /// What the adapter last reported subscribing to.
+    subscription: Option<Subscribed>,
+}
+
+struct Subscribed {
+    discovered: bool,
+    at: DateTime<Utc>,
+    /// When the last event of each symbol was received, by canonical
+    /// symbol.
+    symbols: BTreeMap<String, Option<DateTime<Utc>>>,
+}
+
+/// The connection of a venue's adapter.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AdapterState {
+    pub connected: bool,
+    /// Connections after the first.
+    pub reconnects: u64,
+    /// Restarts by the watchdog.
+    pub restarts: u64,
+    /// Failures of the adapter task.
+    pub failures: u64,
+    /// Whether the adapter is held stopped after failing repeatedly.
+    pub circuit_open: bool,
+    /// Seconds since the last event from the venue.
+    pub last_message_age_secs: f64,
+}
+
+/// The symbols a venue's adapter subscribed to.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VenueSubscription {
+    /// Whether the symbols were discovered rather than configured.
+    pub discovered: bool,
+    /// When the adapter reported subscribing.
+    pub subscribed_at: DateTime<Utc>,
+    pub symbols: Vec<SymbolUpdate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SymbolUpdate {
+    pub symbol: String,
+    /// When the latest event of the symbol was received, if any has been.
+    pub last_update: Option<DateTime<Utc>>,
+}
+
+impl Adapter {
+    fn new() -> Self {
+        Self {
+            connected: false,
+            seen_up: false,
+            last_message: Instant::now(),
+            subscription: None,
+        }
+    }
+}
+
+impl AdapterMetrics {
+    pub fn new() -> Self {
+        let connected = IntGaugeVec::new(
+            Opts::new(
+                "adapter_connected",
+                "1 while the venue adapter is connected",
+            ),
+            &["venue"],
+        )
+        .unwrap();
+        let last_message_age = GaugeVec::new(
+            Opts::new(
+                "adapter_last_message_age_seconds",
+                "seconds since the last event from the venue",
+            ),
+            &["venue"],
+        )
+        .unwrap();
+        let reconnects = IntCounterVec::new(
+            Opts::new(
+                "adapter_reconnects_total",
+                "connections made by the venue adapter after its first",
+            ),
+            &["venue"],
+        )
+        .unwrap();
+        let restarts = IntCounterVec::new(
+            Opts::new(
+                "adapter_restarts_total",
+                "venue adapters restarted by the watchdog for delivering no events",
+            ),
+            &["venue"],
+        )
+        .unwrap();
+        let failures = IntCounterVec::new(
+            Opts::new(
+                "adapter_failures_total",
+                "venue adapter tasks that failed or stopped",
+            ),
+            &["venue"],
+        )
+        .unwrap();
+        let circuit_open = IntGaugeVec::new(
+            Opts::new(
+                "adapter_circuit_open",
+                "1 while the venue adapter is held stopped after repeated failures",
+            ),
+            &["venue"],
+        )
+        .unwrap();
+        Self {
+            connected,
+            last_message_age,
+            reconnects,
+            restarts,
+            failures,
+            circuit_open,
+            adapters: Arc::default(),
+        }
+    }
+
+    pub fn register(&self, registry: &Registry) -> prometheus::Result<()> {
+        registry.register(Box::new(self.connected.clone()))?;
+        registry.register(Box::new(self.last_message_age.clone()))?;
+        registry.register(Box::new(self.reconnects.clone()))?;
+        registry.register(Box::new(self.restarts.clone()))?;
+        registry.register(Box::new(self.failures.clone()))?;
+        registry.register(Box::new(self.circuit_open.clone()))?;
+        Ok(())
+    }
+
+    /// Report `venue` as disconnected until its adapter says otherwise, so
+    /// a venue that never connects still shows up, and as subscribed to
+    /// nothing until its adapter reports what it subscribed to.
+    pub fn track(&self, venue: &str) {
+        let mut adapters = self.adapters.lock().unwrap();
+        adapters
+            .entry(venue.to_string())
+            .or_insert_with(Adapter::new)
+            .subscription = None;
+        self.connected.with_label_values(&[venue]).set(0);
+        self.reconnects.with_label_values(&[venue]);
+        self.restarts.with_label_values(&[venue]);
+        self.failures.with_label_values(&[venue]);
+        self.circuit_open.with_label_values(&[venue]).set(0);
+    }
+
+    /// Stop reporting `venue`, once it is no longer configured.
+    pub fn untrack(&self, venue: &str) {
+        self.adapters.lock().unwrap().remove(venue);
+        let _ = self.connected.remove_label_values(&[venue]);
+        let _ = self.last_message_age.remove_label_values(&[venue]);
+        let _ = self.reconnects.remove_label_values(&[venue]);
+        let _ = self.restarts.remove_label_values(&[venue]);
+        let _ = self.failures.remove_label_values(&[venue]);
+        let _ = self.circuit_open.remove_label_values(&[venue]);
+    }
+
+    /// Apply a status event, or note a message from the event's venue.
+    pub fn observe(&self, event: &NormalizedEvent) {
+        let mut adapters = self.adapters.lock().unwrap();
+        if let Some(subscription) = event.as_adapter_subscription() {
+            if let Some(adapter) = adapters.get_mut(&subscription.venue) {
+                adapter.subscription = Some(Subscribed {
+                    discovered: subscription.discovered,
+                    at: event.timestamp,
+                    symbols: subscription
+                        .symbols
+                        .iter()
+                        .map(|symbol| (canonical_symbol(symbol), None))
+                        .collect(),
+                });
+            }
+            return;
+        }
+        let Some((venue, up)) = event.as_adapter_status() else {
+            if let Some(adapter) = adapters.get_mut(&event.venue) {
+                adapter.last_message = Instant::now();
+                let update = adapter
+                    .subscription
+                    .as_mut()
+                    .and_then(|subscribed| subscribed.symbols.get_mut(&event.symbol));
+                if let Some(update) = update {
+                    *update = Some(event.received_at.unwrap_or(event.timestamp));
+                }
+            }
+            return;
+        };
+        let adapter = adapters
+            .entry(venue.to_string())
+            .or_insert_with(Adapter::new);
+        if up && !adapter.connected && adapter.seen_up {
+            self.reconnects.with_label_values(&[venue]).inc();
+        }
+        adapter.connected = up;
+        adapter.seen_up |= up;
+        self.connected.with_label_values(&[venue]).set(up as i64);
+    }
+
+    /// Tracked venues whose adapter is not connected, in order.
+    pub fn disconnected(&self) -> Vec<String> {
+        let adapters = self.adapters.lock().unwrap();
+        let mut venues: Vec<_> = adapters
+            .iter()
+            .filter(|(_, adapter)| !adapter.connected)
+            .map(|(venue, _)| venue.clone())
+            .collect();
+        venues.sort();
+        venues
+    }
+
+    /// The state of every tracked venue's adapter, by venue.
+    pub fn states(&self) -> BTreeMap<String, AdapterState> {
+        let adapters = self.adapters.lock().unwrap();
+        adapters
+            .iter()
+            .map(|(venue, adapter)| {
+                let state = AdapterState {
+                    connected: adapter.connected,
+                    reconnects: self.reconnects.with_label_values(&[venue]).get(),
+                    restarts: self.restarts.with_label_values(&[venue]).get(),
+                    failures: self.failures.with_label_values(&[venue]).get(),
+                    circuit_open: self.circuit_open.with_label_values(&[venue]).get() == 1,
+                    last_message_age_secs: adapter.last_message.elapsed().as_secs_f64(),
+                };
+                (venue.clone(), state)
+            })
+            .collect()
+    }
+
+    /// The subscription of every tracked venue, by venue, or `None` for a
+    /// venue whose adapter has not reported one yet.
+    pub fn subscriptions(&self) -> BTreeMap<String, Option<VenueSubscription>> {
+        let adapters = self.adapters.lock().unwrap();
+        adapters
+            .iter()
+            .map(|(venue, adapter)| {
+                let subscription =
+                    adapter
+                        .subscription
+                        .as_ref()
+                        .map(|subscribed| VenueSubscription {
+                            discovered: subscribed.discovered,
+                            subscribed_at: subscribed.at,
+                            symbols: subscribed
+                                .symbols
+                                .iter()
+                                .map(|(symbol, last_update)| SymbolUpdate {
+                                    symbol: symbol.clone(),
+                                    last_update: *last_update,
+                                })
+                                .collect(),
+                        });
+                (venue.clone(), subscription)
+            })
+            .collect()
+    }
+
+    /// Refresh the message ages every second, for as long as the task runs.
+    pub async fn run(self) {
+        let mut tick = tokio::time::interval(SAMPLE_INTERVAL);
+        loop {
+            tick.tick().await;
+            self.sample();
+        }
+    }
+
+    pub fn sample(&self) {
+        let adapters = self.adapters.lock().unwrap();
+        for (venue, adapter) in adapters.iter() {
+            self.last_message_age
+                .with_label_values(&[venue])
+                .set(adapter.last_message.elapsed().as_secs_f64());
+        }
+    }
+}
+
+impl Default for AdapterMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,143 @@
+//! Admin endpoints under `/admin`, answered through a [`ControlHandle`] and
+//! only served behind a token.
+//!
+//! - `POST /admin/venues/{name}/pause` and `POST /admin/venues/{name}/resume`:
+//!   stop or restart the adapter of one venue
+//! - `POST /admin/venues/{name}/symbols` with `{"add": [..], "remove": [..]}`:
+//!   change the symbols the venue's adapter subscribes to, answered with the
+//!   resulting list
+//! - `POST /admin/reload`: re-read the config file and apply what changed,
+//!   answered with a [`ReloadReport`], or 422 with the report if the new
+//!   config is invalid
+//! - `GET /admin/log-level` and `PUT /admin/log-level` with
+//!   `{"filter": "info,sinks::kafka=debug"}`: read or replace the log filter,
+//!   answered with the active one, or 400 for a filter that does not parse
+//! - `POST /admin/shutdown`: stop taking in events, drain the pipeline and
+//!   the sinks and exit, answered with 202 once shutdown has begun
+
+use api::control::{ControlHandle, ReloadReport};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use ingest_core::error::IngestError;
+use serde::{Deserialize, Serialize};
+
+use crate::control::internal;
+
+#[derive(Deserialize)]
+struct SymbolEdit {
+    #[serde(default)]
+    add: Vec<String>,
+    #[serde(default)]
+    remove: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LogFilter {
+    filter: String,
+}
+
+#[derive(Serialize)]
+struct VenueSymbols {
+    venue: String,
+    symbols: Vec<String>,
+}
+
+pub(crate) fn routes(handle: ControlHandle) -> Router {
+    Router::new()
+        .route(
+            "/venues/:name/pause",
+            post(|State(handle), Path(name)| pause(handle, name, true)),
+        )
+        .route(
+            "/venues/:name/resume",
+            post(|State(handle), Path(name)| pause(handle, name, false)),
+        )
+        .route("/venues/:name/symbols", post(edit_symbols))
+        .route("/reload", post(reload))
+        .route("/log-level", get(log_filter).put(set_log_filter))
+        .route("/shutdown", post(shutdown))
+        .with_state(handle)
+}
+
+async fn pause(
+    handle: ControlHandle,
+    name: String,
+    pause: bool,
+) -> Result<StatusCode, (StatusCode, String)> {
+    known(&handle, &name).await?;
+    let result = if pause {
+        handle.pause(Some(&name)).await
+    } else {
+        handle.resume(Some(&name)).await
+    };
+    result.map_err(internal)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn edit_symbols(
+    State(handle): State<ControlHandle>,
+    Path(name): Path<String>,
+    Json(edit): Json<SymbolEdit>,
+) -> Result<Json<VenueSymbols>, (StatusCode, String)> {
+    known(&handle, &name).await?;
+    let symbols = handle
+        .edit_symbols(&name, edit.add, edit.remove)
+        .await
+        .map_err(internal)?;
+    Ok(Json(VenueSymbols {
+        venue: name,
+        symbols,
+    }))
+}
+
+/// Answer 404 for a venue that is not configured.
+pub(crate) async fn known(handle: &ControlHandle, name: &str) -> Result<(), (StatusCode, String)> {
+    let venues = handle.venues().await.map_err(internal)?;
+    if venues.iter().any(|venue| venue.name == name) {
+        Ok(())
+    } else {
+        Err((StatusCode::NOT_FOUND, format!("unknown venue {}", name)))
+    }
+}
+
+async fn reload(
+    State(handle): State<ControlHandle>,
+) -> Result<(StatusCode, Json<ReloadReport>), (StatusCode, String)> {
+    let report = handle.reload().await.map_err(internal)?;
+    let status = if report.applied {
+        StatusCode::OK
+    } else {
+        StatusCode::UNPROCESSABLE_ENTITY
+    };
+    Ok((status, Json(report)))
+}
+
+async fn log_filter(
+    State(handle): State<ControlHandle>,
+) -> Result<Json<LogFilter>, (StatusCode, String)> {
+    let filter = handle.log_filter().await.map_err(internal)?;
+    Ok(Json(LogFilter { filter }))
+}
+
+async fn set_log_filter(
+    State(handle): State<ControlHandle>,
+    Json(request): Json<LogFilter>,
+) -> Result<Json<LogFilter>, (StatusCode, String)> {
+    let filter = handle
+        .set_log_filter(&request.filter)
+        .await
+        .map_err(|e| match e {
+            IngestError::Validation(reason) => (StatusCode::BAD_REQUEST, reason),
+            e => internal(e),
+        })?;
+    Ok(Json(LogFilter { filter }))
+}
+
+async fn shutdown(State(handle): State<ControlHandle>) -> Result<StatusCode, (StatusCode, String)> {
+    handle.shutdown().await.map_err(internal)?;
+    Ok(StatusCode::ACCEPTED)
+}
@@ -0,0 +1,518 @@
+//! Webhook alerts of an engine in trouble: venues whose adapter stays
+//! disconnected, bus subscribers missing events in bursts, and sinks
+//! falling behind.
+//!
+//! Each alert is keyed by its rule and what it is about, such as
+//! `adapter_down:binance_spot`, and is posted once when it fires and once
+//! when it resolves. One that fires again within the cooldown of its last
+//! notification waits out the cooldown, so a flapping venue does not page
+//! on every flap.
+//!
+//! Each webhook is posted to by a task of its own, in order, so a slow or
+//! failing webhook holds up neither the checks nor the other webhooks. A
+//! notification stays queued until its webhook takes it, retried with
+//! backoff, unless the webhook rejects it as a bad request.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use api::BusMetrics;
+use chrono::Utc;
+use ingest_core::config::{AlertsConfig, WebhookConfig, WebhookFormat};
+use reqwest::{Client, StatusCode};
+use serde::Serialize;
+use sinks::SinkMetrics;
+use tokio::sync::mpsc;
+
+use crate::{label_values, AdapterMetrics};
+
+/// Notifications waiting for one webhook beyond which new ones are dropped.
+const OUTBOX: usize = 256;
+/// Wait before retrying a failed post, doubled per failure up to
+/// [`MAX_RETRY_DELAY`].
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+
+/// A notification on its way to a webhook: the alert's key and the body.
+type Outgoing = (String, serde_json::Value);
+
+/// Where the alert rules read the engine's state from.
+pub struct AlertSources {
+    pub adapters: AdapterMetrics,
+    pub bus: BusMetrics,
+    pub sinks: SinkMetrics,
+}
+
+pub struct Notifier {
+    cfg: AlertsConfig,
+    sources: AlertSources,
+    client: Client,
+    /// When each disconnected venue was first seen down.
+    down: HashMap<String, Instant>,
+    /// Samples of the events each bus subscriber missed in total, over the
+    /// gap burst window.
+    missed: HashMap<String, VecDeque<(Instant, u64)>>,
+    alerts: HashMap<String, Notified>,
+}
+
+struct Notified {
+    alert: Alert,
+    /// Whether the webhooks were last told the alert fired.
+    firing: bool,
+    at: Instant,
+}
+
+/// A condition one of the rules found.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Alert {
+    pub key: String,
+    /// The rule, e.g. `adapter_down`.
+    pub rule: &'static str,
+    pub summary: String,
+    /// What the alert is about, e.g. `venue`.
+    pub labels: BTreeMap<&'static str, String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertStatus {
+    Firing,
+    Resolved,
+}
+
+impl Notifier {
+    pub fn new(cfg: AlertsConfig, sources: AlertSources) -> Self {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .unwrap();
+        Self {
+            cfg,
+            sources,
+            client,
+            down: HashMap::new(),
+            missed: HashMap::new(),
+            alerts: HashMap::new(),
+        }
+    }
+
+    /// Check the rules every `interval_secs` and queue what changed for
+    /// each webhook, for as long as the task runs.
+    pub async fn run(mut self) {
+        let outboxes: Vec<_> = self
+            .cfg
+            .webhooks
+            .iter()
+            .map(|webhook| {
+                let (tx, rx) = mpsc::channel(OUTBOX);
+                tokio::spawn(deliver(self.client.clone(), webhook.url.clone(), rx));
+                (webhook.clone(), tx)
+            })
+            .collect();
+        let mut tick = tokio::time::interval(Duration::from_secs(self.cfg.interval_secs.max(1)));
+        loop {
+            tick.tick().await;
+            for (alert, status) in self.evaluate(Instant::now()) {
+                for (webhook, outbox) in &outboxes {
+                    let outgoing = (alert.key.clone(), body(webhook, &alert, status));
+                    if outbox.try_send(outgoing).is_err() {
+                        tracing::warn!(
+                            "alert {} to {} dropped, {} notifications are waiting for it",
+                            alert.key,
+                            webhook.url,
+                            OUTBOX
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// The alerts to notify of at `now`: those that fired, unless within
+    /// the cooldown, and those that resolved.
+    pub fn evaluate(&mut self, now: Instant) -> Vec<(Alert, AlertStatus)> {
+        let active = self.check(now);
+        let cooldown = Duration::from_secs(self.cfg.cooldown_secs);
+        let mut notify = Vec::new();
+        for alert in &active {
+            let notified = self.alerts.get(&alert.key);
+            let due = notified.is_none_or(|notified| {
+                !notified.firing && now.duration_since(notified.at) >= cooldown
+            });
+            if due {
+                let firing = Notified {
+                    alert: alert.clone(),
+                    firing: true,
+                    at: now,
+                };
+                self.alerts.insert(alert.key.clone(), firing);
+                notify.push((alert.clone(), AlertStatus::Firing));
+            }
+        }
+        let mut resolved = Vec::new();
+        for (key, notified) in &mut self.alerts {
+            if notified.firing && !active.iter().any(|alert| &alert.key == key) {
+                notified.firing = false;
+                notified.at = now;
+                let summary = format!("{} resolved", key);
+                resolved.push(Alert {
+                    summary,
+                    ..notified.alert.clone()
+                });
+            }
+        }
+        resolved.sort_by(|a, b| a.key.cmp(&b.key));
+        notify.extend(
+            resolved
+                .into_iter()
+                .map(|alert| (alert, AlertStatus::Resolved)),
+        );
+        notify
+    }
+
+    /// Every condition a rule finds at `now`, in order.
+    fn check(&mut self, now: Instant) -> Vec<Alert> {
+        let mut active = Vec::new();
+        if let Some(secs) = self.cfg.adapter_down_secs {
+            let states = self.sources.adapters.states();
+            self.down
+                .retain(|venue, _| states.get(venue).is_some_and(|state| !state.connected));
+            for (venue, state) in &states {
+                if state.connected {
+                    continue;
+                }
+                let since = *self.down.entry(venue.clone()).or_insert(now);
+                let down = now.duration_since(since);
+                if down >= Duration::from_secs(secs) {
+                    active.push(alert(
+                        "adapter_down",
+                        ("venue", venue),
+                        format!("{} adapter down for {}s", venue, down.as_secs()),
+                    ));
+                }
+            }
+        }
+        if let Some(gap_burst) = &self.cfg.gap_burst {
+            let bus = &self.sources.bus;
+            let window = Duration::from_secs(gap_burst.window_secs);
+            let subscribers = label_values(&bus.lagged, "subscriber")
+                .into_iter()
+                .chain(label_values(&bus.dropped, "subscriber"));
+            let mut totals = BTreeMap::new();
+            for subscriber in subscribers {
+                let label = [subscriber.as_str()];
+                let total = bus.lagged.with_label_values(&label).get()
+                    + bus.dropped.with_label_values(&label).get();
+                totals.insert(subscriber, total);
+            }
+            self.missed
+                .retain(|subscriber, _| totals.contains_key(subscriber));
+            for (subscriber, total) in totals {
+                let samples = self.missed.entry(subscriber.clone()).or_default();
+                samples.push_back((now, total));
+                // Keep the newest sample from before the window as the base.
+                while samples
+                    .get(1)
+                    .is_some_and(|(at, _)| now.duration_since(*at) >= window)
+                {
+                    samples.pop_front();
+                }
+                let missed = total.saturating_sub(samples[0].1);
+                if missed >= gap_burst.missed {
+                    active.push(alert(
+                        "gap_burst",
+                        ("subscriber", &subscriber),
+                        format!(
+                            "bus subscriber {} missed {} events in {}s",
+                            subscriber,
+                            missed,
+                            window.as_secs()
+                        ),
+                    ));
+                }
+            }
+        }
+        if let Some(backlog) = self.cfg.sink_backlog {
+            let queue_depth = &self.sources.sinks.queue_depth;
+            for sink in label_values(queue_depth, "sink") {
+                let depth = queue_depth.with_label_values(&[&sink]).get();
+                if depth >= backlog {
+                    active.push(alert(
+                        "sink_backlog",
+                        ("sink", &sink),
+                        format!("sink {} has {} events queued", sink, depth),
+                    ));
+                }
+            }
+        }
+        active
+    }
+}
+
+/// Post what arrives on `outbox` to `url` in order. A post is retried with
+/// backoff until it succeeds, and given up only when the webhook answers
+/// with a client error other than 429.
+async fn deliver(client: Client, url: String, mut outbox: mpsc::Receiver<Outgoing>) {
+    while let Some((key, body)) = outbox.recv().await {
+        let mut delay = RETRY_DELAY;
+        loop {
+            let posted = async {
+                client
+                    .post(&url)
+                    .json(&body)
+                    .send()
+                    .await?
+                    .error_for_status()
+            };
+            match posted.await {
+                Ok(_) => break,
+                Err(e) if e.status().is_some_and(rejected) => {
+                    tracing::error!("alert {} to {} rejected: {}", key, url, e);
+                    break;
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "alert {} to {} failed, retrying in {}s: {}",
+                        key,
+                        url,
+                        delay.as_secs(),
+                        e
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(MAX_RETRY_DELAY);
+                }
+            }
+        }
+    }
+}
+
+/// Whether a webhook answering `status` will not take the post on a retry.
+fn rejected(status: StatusCode) -> bool {
+    status.is_client_error() && status != StatusCode::TOO_MANY_REQUESTS
+}
+
+fn alert(rule: &'static str, (name, value): (&'static str, &str), summary: String) -> Alert {
+    Alert {
+        key: format!("{}:{}", rule, value),
+        rule,
+        summary,
+        labels: [(name, value.to_string())].into(),
+    }
+}
+
+/// What is posted to `webhook` for `alert`, in its format.
+pub fn body(webhook: &WebhookConfig, alert: &Alert, status: AlertStatus) -> serde_json::Value {
+    match webhook.format {
+        WebhookFormat::Json => serde_json::json!({
+            "alert": alert.key,
+            "rule": alert.rule,
+            "status": status,
+            "summary": alert.summary,
+            "labels": alert.labels,
+            "at": Utc::now(),
+        }),
+        WebhookFormat::Slack => {
+            let status = match status {
+                AlertStatus::Firing => "FIRING",
+                AlertStatus::Resolved => "RESOLVED",
+            };
+            serde_json::json!({ "text": format!("[{}] {}", status, alert.summary) })
+        }
+        WebhookFormat::Pagerduty => serde_json::json!({
+            "routing_key": webhook.routing_key,
+            "event_action": match status {
+                AlertStatus::Firing => "trigger",
+                AlertStatus::Resolved => "resolve",
+            },
+            "dedup_key": alert.key,
+            "payload": {
+                "summary": alert.summary,
+                "source": "ingestd",
+                "severity": "error",
+                "custom_details": alert.labels,
+            },
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ingest_core::config::GapBurstConfig;
+    use ingest_core::event::NormalizedEvent;
+
+    fn notifier(cfg: AlertsConfig) -> Notifier {
+        let sources = AlertSources {
+            adapters: AdapterMetrics::new(),
+            bus: BusMetrics::new(),
+            sinks: SinkMetrics::new(),
+        };
+        Notifier::new(cfg, sources)
+    }
+
+    fn keys(notify: &[(Alert, AlertStatus)]) -> Vec<(&str, AlertStatus)> {
+        notify
+            .iter()
+            .map(|(alert, status)| (alert.key.as_str(), *status))
+            .collect()
+    }
+
+    #[test]
+    fn alerts_fire_once_resolve_and_cool_down() {
+        let mut notifier = notifier(AlertsConfig {
+            interval_secs: 10,
+            cooldown_secs: 300,
+            adapter_down_secs: Some(30),
+            gap_burst: None,
+            sink_backlog: None,
+            webhooks: Vec::new(),
+        });
+        let adapters = notifier.sources.adapters.clone();
+        adapters.track("binance_spot");
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert!(notifier.evaluate(at(0)).is_empty());
+        let fired = notifier.evaluate(at(30));
+        assert_eq!(
+            keys(&fired),
+            [("adapter_down:binance_spot", AlertStatus::Firing)]
+        );
+        assert_eq!(fired[0].0.labels["venue"], "binance_spot");
+        assert!(notifier.evaluate(at(40)).is_empty());
+
+        adapters.observe(&NormalizedEvent::adapter_status("binance_spot", true));
+        let resolved = notifier.evaluate(at(50));
+        assert_eq!(
+            keys(&resolved),
+            [("adapter_down:binance_spot", AlertStatus::Resolved)]
+        );
+        assert_eq!(resolved[0].0.labels["venue"], "binance_spot");
+
+        // Down again within the cooldown of the resolution.
+        adapters.observe(&NormalizedEvent::adapter_status("binance_spot", false));
+        assert!(notifier.evaluate(at(60)).is_empty());
+        assert!(notifier.evaluate(at(100)).is_empty());
+        assert_eq!(
+            keys(&notifier.evaluate(at(350))),
+            [("adapter_down:binance_spot", AlertStatus::Firing)]
+        );
+    }
+
+    #[test]
+    fn gap_bursts_and_sink_backlogs_fire() {
+        let mut notifier = notifier(AlertsConfig {
+            interval_secs: 10,
+            cooldown_secs: 0,
+            adapter_down_secs: None,
+            gap_burst: Some(GapBurstConfig {
+                missed: 100,
+                window_secs: 60,
+            }),
+            sink_backlog: Some(1000),
+            webhooks: Vec::new(),
+        });
+        let (bus, sinks) = (notifier.sources.bus.clone(), notifier.sources.sinks.clone());
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        bus.lagged.with_label_values(&["ws"]).inc_by(80);
+        sinks.queue_depth.with_label_values(&["kafka"]).set(10);
+        assert!(notifier.evaluate(at(0)).is_empty());
+        bus.dropped.with_label_values(&["ws"]).inc_by(120);
+        sinks.queue_depth.with_label_values(&["kafka"]).set(1000);
+        assert_eq!(
+            keys(&notifier.evaluate(at(30))),
+            [
+                ("gap_burst:ws", AlertStatus::Firing),
+                ("sink_backlog:kafka", AlertStatus::Firing)
+            ]
+        );
+        // The burst falls out of the window.
+        assert_eq!(
+            keys(&notifier.evaluate(at(100))),
+            [("gap_burst:ws", AlertStatus::Resolved)]
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_posts_are_retried_until_taken() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let posts = Arc::new(AtomicUsize::new(0));
+        let (taken_tx, mut taken) = mpsc::unbounded_channel();
+        let hook = {
+            let posts = posts.clone();
+            move |body: axum::Json<serde_json::Value>| {
+                let (posts, taken_tx) = (posts.clone(), taken_tx.clone());
+                async move {
+                    // The first post fails.
+                    if posts.fetch_add(1, Ordering::SeqCst) == 0 {
+                        return axum::http::StatusCode::SERVICE_UNAVAILABLE;
+                    }
+                    let _ = taken_tx.send(body.0);
+                    axum::http::StatusCode::OK
+                }
+            }
+        };
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:3028")
+            .await
+            .unwrap();
+        let app = axum::Router::new().route("/hook", axum::routing::post(hook));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let (tx, rx) = mpsc::channel(OUTBOX);
+        let url = "http://127.0.0.1:3028/hook".to_string();
+        tokio::spawn(deliver(Client::new(), url, rx));
+        for n in 0..2 {
+            tx.send(("sink_backlog:kafka".into(), serde_json::json!({ "n": n })))
+                .await
+                .unwrap();
+        }
+        assert_eq!(taken.recv().await.unwrap()["n"], 0);
+        assert_eq!(taken.recv().await.unwrap()["n"], 1);
+        assert_eq!(posts.load(Ordering::SeqCst), 3);
+        assert!(rejected(StatusCode::BAD_REQUEST));
+        assert!(!rejected(StatusCode::TOO_MANY_REQUESTS));
+    }
+
+    #[test]
+    fn bodies_follow_the_webhook_format() {
+        let alert = alert(
+            "sink_backlog",
+            ("sink", "kafka"),
+            "sink kafka has 1000 events queued".into(),
+        );
+        let webhook = |format, routing_key: Option<&str>| WebhookConfig {
+            url: "http://127.0.0.1/hook".into(),
+            format,
+            routing_key: routing_key.map(String::from),
+        };
+
+        let json = body(
+            &webhook(WebhookFormat::Json, None),
+            &alert,
+            AlertStatus::Firing,
+        );
+        assert_eq!(json["alert"], "sink_backlog:kafka");
+        assert_eq!(json["status"], "firing");
+        assert_eq!(json["labels"]["sink"], "kafka");
+
+        let slack = body(
+            &webhook(WebhookFormat::Slack, None),
+            &alert,
+            AlertStatus::Resolved,
+        );
+        assert_eq!(
+            slack["text"],
+            "[RESOLVED] sink kafka has 1000 events queued"
+        );
+
+        let pagerduty = webhook(WebhookFormat::Pagerduty, Some("key"));
+        let event = body(&pagerduty, &alert, AlertStatus::Resolved);
+        assert_eq!(event["routing_key"], "key");
+        assert_eq!(event["event_action"], "resolve");
+        assert_eq!(event["dedup_key"], "sink_backlog:kafka");
+    }
+}
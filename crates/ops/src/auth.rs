@@ -0,0 +1,68 @@
+//! Bearer-token auth for route groups, by the policies of an [`AuthConfig`].
+
+use std::sync::Arc;
+
+use axum::{
+    extract::Request,
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Router,
+};
+use ingest_core::config::{AuthConfig, AuthPolicy, RouteGroup};
+
+pub(crate) struct Auth {
+    cfg: AuthConfig,
+    tokens: Arc<[String]>,
+}
+
+impl Auth {
+    pub(crate) fn new(cfg: AuthConfig) -> Self {
+        let tokens = cfg.tokens.clone().into();
+        Self { cfg, tokens }
+    }
+
+    /// Whether `group` has routes behind a token.
+    pub(crate) fn protects(&self, group: RouteGroup) -> bool {
+        self.cfg.policy(group) == AuthPolicy::Token
+    }
+
+    /// `routes` with the policy of `group` applied.
+    pub(crate) fn guard(&self, group: RouteGroup, routes: Router) -> Router {
+        if !self.protects(group) || !routes.has_routes() {
+            return routes;
+        }
+        let tokens = self.tokens.clone();
+        routes.route_layer(middleware::from_fn(move |request, next| {
+            authorize(tokens.clone(), request, next)
+        }))
+    }
+}
+
+async fn authorize(tokens: Arc<[String]>, request: Request, next: Next) -> Response {
+    let bearer = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match bearer {
+        Some(bearer)
+            if tokens
+                .iter()
+                .any(|token| same(bearer.as_bytes(), token.as_bytes())) =>
+        {
+            next.run(request).await
+        }
+        _ => (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+        )
+            .into_response(),
+    }
+}
+
+/// Compare without returning early, so the time taken does not tell how
+/// much of a guessed token was right.
+fn same(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
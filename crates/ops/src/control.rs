@@ -0,0 +1,90 @@
+//! Admin endpoints under `/control`, answered through a [`ControlHandle`].
+//!
+//! - `GET /control/venues`: configured venues and whether each is paused
+//! - `GET /control/symbols?venue=..`: symbols seen on the bus, by venue
+//! - `GET /control/pipeline`: per-stage pipeline counters
+//! - `POST /control/pause?venue=..` and `POST /control/resume?venue=..`:
+//!   stop or restart the adapter of one venue, or of every venue without
+//!   `venue`; 404 for a venue that is not configured
+
+use api::control::{ControlHandle, ControlRequest, ControlResponse};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+
+use crate::admin::known;
+
+#[derive(Deserialize)]
+struct VenueQuery {
+    venue: Option<String>,
+}
+
+pub(crate) fn routes(handle: ControlHandle) -> Router {
+    Router::new()
+        .route(
+            "/venues",
+            get(|State(handle)| answer(handle, ControlRequest::Venues)),
+        )
+        .route(
+            "/symbols",
+            get(|State(handle), Query(query): Query<VenueQuery>| {
+                answer(handle, ControlRequest::Symbols { venue: query.venue })
+            }),
+        )
+        .route(
+            "/pipeline",
+            get(|State(handle)| answer(handle, ControlRequest::PipelineStats)),
+        )
+        .route(
+            "/pause",
+            post(|State(handle), Query(query): Query<VenueQuery>| {
+                command(handle, ControlRequest::Pause { venue: query.venue })
+            }),
+        )
+        .route(
+            "/resume",
+            post(|State(handle), Query(query): Query<VenueQuery>| {
+                command(handle, ControlRequest::Resume { venue: query.venue })
+            }),
+        )
+        .with_state(handle)
+}
+
+/// Answer a query with the payload of its response.
+async fn answer(
+    handle: ControlHandle,
+    request: ControlRequest,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let response = handle.request(request).await.map_err(internal)?;
+    let body = match response {
+        ControlResponse::Venues(venues) => serde_json::to_value(venues),
+        ControlResponse::Symbols(symbols) => serde_json::to_value(symbols),
+        ControlResponse::PipelineStats(stats) => serde_json::to_value(stats),
+        ControlResponse::VenueSymbols(symbols) => serde_json::to_value(symbols),
+        ControlResponse::Reloaded(report) => serde_json::to_value(report),
+        ControlResponse::LogFilter(filter) => serde_json::to_value(filter),
+        ControlResponse::Done => Ok(serde_json::Value::Null),
+    };
+    Ok(Json(body.map_err(internal)?))
+}
+
+async fn command(
+    handle: ControlHandle,
+    request: ControlRequest,
+) -> Result<StatusCode, (StatusCode, String)> {
+    if let ControlRequest::Pause { venue: Some(venue) }
+    | ControlRequest::Resume { venue: Some(venue) } = &request
+    {
+        known(&handle, venue).await?;
+    }
+    handle.request(request).await.map_err(internal)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(crate) fn internal(e: impl std::fmt::Display) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
@@ -0,0 +1,53 @@
+//! CORS for browser dashboards on other origins, so their pages can read
+//! `/events`, `/status` and the rest without a proxy. Preflights are
+//! answered before auth, which only sees the request that follows.
+
+use std::time::Duration;
+
+use axum::http::{HeaderName, HeaderValue, Method};
+use ingest_core::{config::CorsConfig, error::IngestError};
+use tower_http::cors::{AllowHeaders, AllowOrigin, CorsLayer};
+
+/// The methods ops routes answer to.
+const METHODS: [Method; 4] = [Method::GET, Method::HEAD, Method::POST, Method::PUT];
+
+/// Fails on an origin or header that is not a valid header value or name,
+/// or on credentials allowed for any origin or header.
+pub(crate) fn layer(cfg: &CorsConfig) -> Result<CorsLayer, IngestError> {
+    let invalid = |what: &str, value: &str| {
+        IngestError::Validation(format!("ops cors {} {:?} is invalid", what, value))
+    };
+    let any = |values: &[String]| values.iter().any(|value| value == "*");
+    if cfg.allow_credentials && (any(&cfg.allowed_origins) || any(&cfg.allowed_headers)) {
+        return Err(IngestError::Validation(
+            "ops cors allow_credentials needs explicit origins and headers, not `*`".into(),
+        ));
+    }
+
+    let origins = if any(&cfg.allowed_origins) {
+        AllowOrigin::any()
+    } else {
+        let origins = cfg
+            .allowed_origins
+            .iter()
+            .map(|origin| HeaderValue::from_str(origin).map_err(|_| invalid("origin", origin)))
+            .collect::<Result<Vec<_>, _>>()?;
+        AllowOrigin::list(origins)
+    };
+    let headers = if any(&cfg.allowed_headers) {
+        AllowHeaders::any()
+    } else {
+        let headers = cfg
+            .allowed_headers
+            .iter()
+            .map(|header| HeaderName::try_from(header).map_err(|_| invalid("header", header)))
+            .collect::<Result<Vec<_>, _>>()?;
+        AllowHeaders::list(headers)
+    };
+    Ok(CorsLayer::new()
+        .allow_origin(origins)
+        .allow_headers(headers)
+        .allow_methods(METHODS)
+        .allow_credentials(cfg.allow_credentials)
+        .max_age(Duration::from_secs(cfg.max_age_secs)))
+}
@@ -0,0 +1,256 @@
+//! Per-venue and per-symbol event metrics, counted as events are published,
+//! and the ingest latency of each venue.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use ingest_core::{
+    config::{MetricsConfig, OpsMetricsConfig},
+    event::{NormalizedEvent, ENGINE_VENUE},
+};
+use prometheus::{exponential_buckets, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry};
+use serde::Serialize;
+
+/// How often venue rates are sampled and the busiest symbols re-ranked.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+const RATE: &str = "venue_events_per_second";
+const EXCHANGE_LATENCY: &str = "exchange_to_publish_latency_seconds";
+const RECEIVE_LATENCY: &str = "receive_to_publish_latency_seconds";
+
+#[derive(Clone)]
+pub struct EventMetrics {
+    pub events: IntCounterVec,
+    pub rate: HistogramVec,
+    pub symbol_events: IntCounterVec,
+    /// Exchange timestamp to publish.
+    pub exchange_latency: HistogramVec,
+    /// Adapter receive time to publish.
+    pub receive_latency: HistogramVec,
+    top_symbols: usize,
+    counts: Arc<Mutex<Counts>>,
+}
+
+#[derive(Default)]
+struct Counts {
+    /// Events per venue since the last sample.
+    venues: HashMap<String, u64>,
+    /// Events per venue and symbol since start.
+    symbols: HashMap<String, HashMap<String, u64>>,
+    /// What `symbol_events_total` shows for the current top symbols.
+    exported: HashMap<(String, String), u64>,
+    /// Events per second of each venue at the last sample.
+    rates: HashMap<String, f64>,
+}
+
+/// What a venue has published, as of the last sample.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VenueActivity {
+    /// Symbols with at least one event since start.
+    pub symbols: usize,
+    pub events_per_second: f64,
+}
+
+impl EventMetrics {
+    /// Fails if buckets are configured for a histogram other than these, or
+    /// any configured buckets are not increasing.
+    pub fn new(cfg: &MetricsConfig, ops: &OpsMetricsConfig) -> prometheus::Result<Self> {
+        if let Some(name) = ops
+            .buckets
+            .keys()
+            .find(|name| ![RATE, EXCHANGE_LATENCY, RECEIVE_LATENCY].contains(&name.as_str()))
+        {
+            return Err(prometheus::Error::Msg(format!(
+                "buckets configured for unknown histogram {}",
+                name
+            )));
+        }
+        let events = IntCounterVec::new(
+            Opts::new("events_total", "events published per venue and kind"),
+            &["venue", "kind"],
+        )
+        .unwrap();
+        // A bucket at zero tells a quiet venue apart from a slow one.
+        let mut buckets = vec![0.0];
+        buckets.extend(exponential_buckets(1.0, 4.0, 10).unwrap());
+        let rate = histogram(
+            RATE,
+            "events published per venue and second, sampled every second",
+            ops.buckets_or(RATE, &buckets),
+        )?;
+        let symbol_events = IntCounterVec::new(
+            Opts::new(
+                "symbol_events_total",
+                "events published for the busiest venue and symbol pairs",
+            ),
+            &["venue", "symbol"],
+        )
+        .unwrap();
+        let latency = |name: &str, help: &str| {
+            histogram(
+                name,
+                help,
+                ops.buckets_or(name, &cfg.latency_buckets_seconds),
+            )
+        };
+        let exchange_latency = latency(
+            EXCHANGE_LATENCY,
+            "time from the exchange timestamp of an event to its publication",
+        )?;
+        let receive_latency = latency(
+            RECEIVE_LATENCY,
+            "time from the adapter receiving an event to its publication",
+        )?;
+        Ok(Self {
+            events,
+            rate,
+            symbol_events,
+            exchange_latency,
+            receive_latency,
+            top_symbols: cfg.top_symbols,
+            counts: Arc::default(),
+        })
+    }
+
+    pub fn register(&self, registry: &Registry) -> prometheus::Result<()> {
+        registry.register(Box::new(self.events.clone()))?;
+        registry.register(Box::new(self.rate.clone()))?;
+        registry.register(Box::new(self.symbol_events.clone()))?;
+        registry.register(Box::new(self.exchange_latency.clone()))?;
+        registry.register(Box::new(self.receive_latency.clone()))?;
+        Ok(())
+    }
+
+    /// Count `event` as published now.
+    pub fn observe(&self, event: &NormalizedEvent) {
+        self.observe_at(event, Utc::now());
+    }
+
+    /// Count `event` as published at `now`. Latencies below zero, from venue
+    /// clocks running ahead, count as zero.
+    pub fn observe_at(&self, event: &NormalizedEvent, now: DateTime<Utc>) {
+        self.events
+            .with_label_values(&[&event.venue, event.kind.as_str()])
+            .inc();
+        if event.venue != ENGINE_VENUE {
+            let latency = |from: DateTime<Utc>| {
+                (now - from).num_microseconds().unwrap_or(0).max(0) as f64 / 1_000_000.0
+            };
+            let venue = [event.venue.as_str()];
+            self.exchange_latency
+                .with_label_values(&venue)
+                .observe(latency(event.timestamp));
+            if let Some(received_at) = event.received_at {
+                self.receive_latency
+                    .with_label_values(&venue)
+                    .observe(latency(received_at));
+            }
+        }
+        let mut counts = self.counts.lock().unwrap();
+        let Counts {
+            venues, symbols, ..
+        } = &mut *counts;
+        match venues.get_mut(&event.venue) {
+            Some(count) => *count += 1,
+            None => {
+                venues.insert(event.venue.clone(), 1);
+            }
+        }
+        let symbols = match symbols.get_mut(&event.venue) {
+            Some(symbols) => symbols,
+            None => symbols.entry(event.venue.clone()).or_default(),
+        };
+        match symbols.get_mut(&event.symbol) {
+            Some(count) => *count += 1,
+            None => {
+                symbols.insert(event.symbol.clone(), 1);
+            }
+        }
+    }
+
+    /// Sample venue rates and re-rank the busiest symbols every second, for
+    /// as long as the task runs.
+    pub async fn run(self) {
+        let mut tick = tokio::time::interval(SAMPLE_INTERVAL);
+        let mut last = Instant::now();
+        loop {
+            tick.tick().await;
+            let now = Instant::now();
+            self.sample(now - last);
+            last = now;
+        }
+    }
+
+    /// Observe the venue rates over the `elapsed` time since the previous
+    /// sample, and export the counts of the busiest symbols. A venue that has
+    /// gone quiet keeps being sampled at zero.
+    pub fn sample(&self, elapsed: Duration) {
+        let mut counts = self.counts.lock().unwrap();
+        let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+        let Counts { venues, rates, .. } = &mut *counts;
+        for (venue, count) in venues.iter_mut() {
+            let rate = *count as f64 / secs;
+            self.rate.with_label_values(&[venue]).observe(rate);
+            rates.insert(venue.clone(), rate);
+            *count = 0;
+        }
+
+        let mut ranked: Vec<_> = counts
+            .symbols
+            .iter()
+            .flat_map(|(venue, symbols)| {
+                symbols
+                    .iter()
+                    .map(move |(symbol, count)| (venue, symbol, *count))
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| (a.0, a.1).cmp(&(b.0, b.1))));
+        let top: HashMap<(String, String), u64> = ranked
+            .into_iter()
+            .take(self.top_symbols)
+            .map(|(venue, symbol, count)| ((venue.clone(), symbol.clone()), count))
+            .collect();
+        for key in counts.exported.keys() {
+            if !top.contains_key(key) {
+                let _ = self.symbol_events.remove_label_values(&[&key.0, &key.1]);
+            }
+        }
+        for (key, count) in &top {
+            let shown = counts.exported.get(key).copied().unwrap_or(0);
+            self.symbol_events
+                .with_label_values(&[&key.0, &key.1])
+                .inc_by(count - shown);
+        }
+        counts.exported = top;
+    }
+
+    /// Each venue's symbols and rate, by venue.
+    pub fn activity(&self) -> BTreeMap<String, VenueActivity> {
+        let counts = self.counts.lock().unwrap();
+        counts
+            .symbols
+            .iter()
+            .map(|(venue, symbols)| {
+                let activity = VenueActivity {
+                    symbols: symbols.len(),
+                    events_per_second: counts.rates.get(venue).copied().unwrap_or(0.0),
+                };
+                (venue.clone(), activity)
+            })
+            .collect()
+    }
+}
+
+/// A histogram by venue. Fails if `buckets` are not increasing, which
+/// histograms would otherwise only check once a venue first reports.
+fn histogram(name: &str, help: &str, buckets: Vec<f64>) -> prometheus::Result<HistogramVec> {
+    if !buckets.windows(2).all(|w| w[0] < w[1]) {
+        return Err(prometheus::Error::Msg(format!(
+            "buckets of {} must increase: {:?}",
+            name, buckets
+        )));
+    }
+    HistogramVec::new(HistogramOpts::new(name, help).buckets(buckets), &["venue"])
+}
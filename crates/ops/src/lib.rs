@@ -1,37 +1,462 @@
-use axum::{routing::get, Router};
-use prometheus::{Encoder, TextEncoder, Registry, IntCounter};
+mod adapters;
+mod alerts;
+mod admin;
+mod auth;
+mod control;
+mod cors;
+mod events;
+mod limits;
+mod openmetrics;
+#[cfg(feature = "otlp")]
+mod otlp;
+mod probes;
+#[cfg(any(feature = "pprof", feature = "jemalloc"))]
+mod profiling;
+mod pushgateway;
+mod sse;
+mod status;
+mod symbols;
+mod tls;
+mod ws;
+
+pub use adapters::{AdapterMetrics, AdapterState, SymbolUpdate, VenueSubscription};
+pub use alerts::{Alert, AlertSources, AlertStatus, Notifier};
+pub use events::{EventMetrics, VenueActivity};
+#[cfg(feature = "otlp")]
+pub use otlp::OtlpExporter;
+pub use probes::{Active, Startup};
+pub use pushgateway::PushgatewayExporter;
+pub use status::{BuildInfo, StatusSources};
+
+use api::{control::ControlHandle, EventBus, EventPublisher, Filter};
+use axum::{
+    extract::Query,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use ingest_core::config::{
+    AuthConfig, CorsConfig, OpsMetricsConfig, RouteGroup, SseConfig, StreamLimitsConfig, TlsConfig,
+    WsConfig,
+};
+use ingest_core::error::IngestError;
+use ingest_core::event::NormalizedEvent;
+use prometheus::{core::Collector, Encoder, TextEncoder, Registry, IntCounter};
+use serde::Deserialize;
+use std::collections::BTreeSet;
 use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tower_http::compression::CompressionLayer;
+use wal::WalReader;
 
 pub struct OpsServer {
     pub registry: Registry,
     pub requests: IntCounter,
+    replay: Option<Arc<ReplaySource>>,
+    publish: Option<mpsc::Sender<NormalizedEvent>>,
+    ws: Option<Arc<ws::WsSource>>,
+    snapshot: Option<EventBus>,
+    bus_state: Option<EventBus>,
+    sse: Option<EventBus>,
+    control: Option<ControlHandle>,
+    status: Option<StatusSources>,
+    /// When the server was created, which `/status` counts uptime from.
+    started: Instant,
+    symbols: Option<AdapterMetrics>,
+    admin: Option<ControlHandle>,
+    auth: AuthConfig,
+    stream_limits: StreamLimitsConfig,
+    sse_config: SseConfig,
+    tls: Option<tokio_rustls::TlsAcceptor>,
+    ready: Vec<(String, ReadyCheck)>,
+    startup: Startup,
+    active: Option<(Active, EventBus)>,
+    cors: Option<tower_http::cors::CorsLayer>,
+}
+
+/// Reports why a component is not ready, if it is not.
+type ReadyCheck = Arc<dyn Fn() -> Result<(), String> + Send + Sync>;
+
+struct ReplaySource {
+    reader: WalReader,
+    publisher: EventPublisher,
 }
 
 impl OpsServer {
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
-        let registry = Registry::new();
+        Self::with_registry(Registry::new())
+    }
+
+    /// A server whose metrics are named with the prefix of `cfg` and carry
+    /// its labels.
+    pub fn namespaced(cfg: &OpsMetricsConfig) -> Result<Self, IngestError> {
+        let labels = (!cfg.labels.is_empty()).then(|| cfg.labels.clone().into_iter().collect());
+        let registry = Registry::new_custom(cfg.prefix.clone(), labels)
+            .map_err(|e| IngestError::Validation(format!("ops metrics: {}", e)))?;
+        Ok(Self::with_registry(registry))
+    }
+
+    /// A server exporting the metrics of `registry`.
+    pub fn with_registry(registry: Registry) -> Self {
         let requests = IntCounter::new("requests_total", "total requests").unwrap();
         registry.register(Box::new(requests.clone())).unwrap();
-        Self { registry, requests }
+        Self {
+            registry,
+            requests,
+            replay: None,
+            publish: None,
+            ws: None,
+            snapshot: None,
+            bus_state: None,
+            sse: None,
+            control: None,
+            status: None,
+            started: Instant::now(),
+            symbols: None,
+            admin: None,
+            auth: AuthConfig::default(),
+            stream_limits: StreamLimitsConfig::default(),
+            sse_config: SseConfig::default(),
+            tls: None,
+            ready: Vec::new(),
+            startup: Startup::default(),
+            active: None,
+            cors: None,
+        }
+    }
+
+    /// Serve `POST /replay?from=..&to=..`, republishing logged events whose
+    /// timestamps fall in the RFC 3339 range onto the bus. Only served once
+    /// [`OpsServer::with_auth`] sets tokens.
+    pub fn with_replay(mut self, reader: WalReader, publisher: EventPublisher) -> Self {
+        self.replay = Some(Arc::new(ReplaySource { reader, publisher }));
+        self
+    }
+
+    /// Serve `POST /publish`, sending the JSON array of events in the body
+    /// into `pipeline`, as `devtools replay --publish` does, so they are
+    /// logged and sequenced like venue events. Only served once
+    /// [`OpsServer::with_auth`] sets tokens.
+    pub fn with_publish(mut self, pipeline: mpsc::Sender<NormalizedEvent>) -> Self {
+        self.publish = Some(pipeline);
+        self
+    }
+
+    /// Serve `GET /ws`, streaming bus events to WebSocket subscribers.
+    pub fn with_ws(mut self, bus: EventBus, cfg: WsConfig) -> Self {
+        self.ws = Some(Arc::new(ws::WsSource::new(bus, cfg, &self.registry)));
+        self
+    }
+
+    /// Serve `GET /snapshot?topic=..`, returning the latest event of every
+    /// venue, symbol and kind matching the topic pattern (all by default).
+    pub fn with_snapshot(mut self, bus: EventBus) -> Self {
+        self.snapshot = Some(bus);
+        self
+    }
+
+    /// Serve `GET /bus/state`, dumping the subscribers, queue depths, replay
+    /// buffer occupancy and publish rate of the bus.
+    pub fn with_bus_state(mut self, bus: EventBus) -> Self {
+        self.bus_state = Some(bus);
+        self
+    }
+
+    /// Serve `GET /events?venue=..&symbol=..&kind=..`, streaming the matching
+    /// bus events as server-sent events.
+    pub fn with_sse(mut self, bus: EventBus) -> Self {
+        self.sse = Some(bus);
+        self
+    }
+
+    /// Have `GET /ready` and `GET /healthz/ready` answer 503 while `check`
+    /// reports `component` as not ready, with the reason in the JSON body.
+    pub fn with_ready_check<F>(mut self, component: &str, check: F) -> Self
+    where
+        F: Fn() -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.ready.push((component.to_string(), Arc::new(check)));
+        self
+    }
+
+    /// What marks the engine as started: `GET /healthz/startup` answers
+    /// 503 until [`Startup::complete`] is called on it.
+    pub fn startup(&self) -> Startup {
+        self.startup.clone()
+    }
+
+    /// Serve `GET /healthz/active`, answering 200 while `active` is and 503
+    /// otherwise, with the last sequence published on `bus` either way.
+    pub fn with_active(mut self, active: Active, bus: EventBus) -> Self {
+        self.active = Some((active, bus));
+        self
+    }
+
+    /// Serve the admin endpoints under `/control`, answered through `handle`.
+    /// They are only served once [`OpsServer::with_auth`] sets tokens.
+    pub fn with_control(mut self, handle: ControlHandle) -> Self {
+        self.control = Some(handle);
+        self
+    }
+
+    /// Serve `GET /status`, summing up the engine from `sources`.
+    pub fn with_status(mut self, sources: StatusSources) -> Self {
+        self.status = Some(sources);
+        self
     }
 
-    pub async fn run(self, addr: SocketAddr) {
+    /// Serve `GET /symbols?venue=..`, listing the symbols each venue's
+    /// adapter subscribed to as `adapters` saw them reported.
+    pub fn with_symbols(mut self, adapters: AdapterMetrics) -> Self {
+        self.symbols = Some(adapters);
+        self
+    }
+
+    /// Serve the admin endpoints under `/admin`, answered through `handle`.
+    /// They are only served once [`OpsServer::with_auth`] sets tokens.
+    pub fn with_admin(mut self, handle: ControlHandle) -> Self {
+        self.admin = Some(handle);
+        self
+    }
+
+    /// Require bearer tokens on the route groups `cfg` protects.
+    pub fn with_auth(mut self, cfg: AuthConfig) -> Self {
+        self.auth = cfg;
+        self
+    }
+
+    /// Cap the streams of `GET /events` and `GET /ws`, and how fast each
+    /// client opens them.
+    pub fn with_stream_limits(mut self, cfg: StreamLimitsConfig) -> Self {
+        self.stream_limits = cfg;
+        self
+    }
+
+    /// Send the heartbeats of `GET /events` as `cfg` says.
+    pub fn with_sse_config(mut self, cfg: SseConfig) -> Self {
+        self.sse_config = cfg;
+        self
+    }
+
+    /// Serve HTTPS with the certificates `cfg` names, loaded now so a bad
+    /// path or key fails here rather than on the first connection.
+    pub fn with_tls(mut self, cfg: &TlsConfig) -> Result<Self, IngestError> {
+        self.tls = Some(tls::acceptor(cfg)?);
+        Ok(self)
+    }
+
+    /// Answer cross-origin browser requests from the origins `cfg` allows,
+    /// failing here on an origin or header that does not parse.
+    pub fn with_cors(mut self, cfg: &CorsConfig) -> Result<Self, IngestError> {
+        self.cors = Some(cors::layer(cfg)?);
+        Ok(self)
+    }
+
+    /// Serve on `addr` until the task is dropped. Fails if `addr` cannot be
+    /// bound or the server stops accepting connections.
+    pub async fn run(self, addr: SocketAddr) -> Result<(), IngestError> {
         let registry = self.registry.clone();
-        let app = Router::new()
+        let checks: Arc<[(String, ReadyCheck)]> = self.ready.into();
+        let heartbeat = Arc::new(probes::Heartbeat::new());
+        let ready_checks = checks.clone();
+        let mut health = Router::new()
             .route("/health", get(|| async { "ok" }))
-            .route("/ready", get(|| async { "ready" }))
-            .route("/metrics", get(move || metrics(registry.clone())));
-        let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-        axum::serve(listener, app).await.unwrap();
+            .route("/ready", get(move || ready(checks.clone())))
+            .route("/healthz/startup", get(probes::startup).with_state(self.startup))
+            .route("/healthz/live", get(probes::live).with_state(heartbeat.clone()))
+            .route("/healthz/ready", get(move || ready(ready_checks.clone())));
+        if let Some(active) = self.active {
+            health = health.route("/healthz/active", get(probes::active).with_state(active));
+        }
+        let mut metrics =
+            Router::new().route("/metrics", get(move |headers| metrics(registry.clone(), headers)));
+        if let Some(sources) = self.status {
+            let source = Arc::new(status::StatusSource { sources, started: self.started });
+            metrics = metrics.route("/status", get(status::status).with_state(source));
+        }
+        if let Some(adapters) = self.symbols {
+            metrics = metrics.route("/symbols", get(symbols::symbols).with_state(adapters));
+        }
+        let metrics = metrics.layer(compression());
+
+        let limits = Arc::new(limits::StreamLimits::new(self.stream_limits, &self.registry));
+        let mut data = Router::new();
+        if let Some(source) = self.ws {
+            data = data.route("/ws", get(ws::upgrade).with_state((source, limits.clone())));
+        }
+        if let Some(bus) = self.snapshot {
+            let snapshot = get(move |query| snapshot(bus.clone(), query)).layer(compression());
+            data = data.route("/snapshot", snapshot);
+        }
+        if let Some(bus) = self.bus_state {
+            data = data.route("/bus/state", get(move || async move { Json(bus.state()) }));
+        }
+        if let Some(bus) = self.sse {
+            let keepalive = Duration::from_secs(self.sse_config.keepalive_secs.max(1));
+            data = data.route("/events", get(sse::events).with_state((bus, limits, keepalive)));
+        }
+
+        let auth = auth::Auth::new(self.auth);
+        let mut control = Router::new();
+        if auth.protects(RouteGroup::Control) {
+            if let Some(source) = self.replay {
+                control = control.route("/replay", post(move |query| replay(source.clone(), query)));
+            }
+            if let Some(pipeline) = self.publish {
+                control = control.route("/publish", post(move |events| publish(pipeline.clone(), events)));
+            }
+            if let Some(handle) = self.control {
+                control = control.nest("/control", control::routes(handle));
+            }
+        } else if self.replay.is_some() || self.publish.is_some() || self.control.is_some() {
+            tracing::warn!("control routes not served: no auth tokens configured");
+        }
+
+        let mut admin = Router::new();
+        if auth.protects(RouteGroup::Admin) {
+            if let Some(handle) = self.admin {
+                admin = admin.nest("/admin", admin::routes(handle));
+            }
+            #[cfg(any(feature = "pprof", feature = "jemalloc"))]
+            {
+                admin = admin.nest("/debug/pprof", profiling::routes());
+            }
+        } else if self.admin.is_some() || cfg!(any(feature = "pprof", feature = "jemalloc")) {
+            tracing::warn!("admin routes not served: no auth tokens configured");
+        }
+
+        let app = Router::new()
+            .merge(auth.guard(RouteGroup::Health, health))
+            .merge(auth.guard(RouteGroup::Metrics, metrics))
+            .merge(auth.guard(RouteGroup::Data, data))
+            .merge(auth.guard(RouteGroup::Control, control))
+            .merge(auth.guard(RouteGroup::Admin, admin));
+        let app = match self.cors {
+            Some(cors) => app.layer(cors),
+            None => app,
+        };
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        let serve = async {
+            match self.tls {
+                Some(acceptor) => {
+                    tls::serve(listener, acceptor, app).await;
+                    Ok(())
+                }
+                None => axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await,
+            }
+        };
+        // The heartbeat shares the server's task, so it is late exactly
+        // when the server is.
+        tokio::select! {
+            served = serve => Ok(served?),
+            () = heartbeat.run() => Ok(()),
+        }
     }
 }
 
-async fn metrics(registry: Registry) -> String {
+/// The registry in the text format, or in OpenMetrics if `headers` accept it.
+async fn metrics(registry: Registry, headers: HeaderMap) -> Response {
+    let mf = registry.gather();
+    let accept = headers.get(header::ACCEPT).and_then(|value| value.to_str().ok());
+    if accept.is_some_and(openmetrics::accepted) {
+        return ([(header::CONTENT_TYPE, openmetrics::CONTENT_TYPE)], openmetrics::encode(&mf))
+            .into_response();
+    }
     let mut buffer = Vec::new();
     let encoder = TextEncoder::new();
-    let mf = registry.gather();
     encoder.encode(&mf, &mut buffer).unwrap();
-    String::from_utf8(buffer).unwrap()
+    String::from_utf8(buffer).unwrap().into_response()
+}
+
+/// Gzip or zstd, as the client accepts, for the documents that grow with the
+/// series and symbols ingested.
+fn compression() -> CompressionLayer {
+    CompressionLayer::new().gzip(true).zstd(true)
+}
+
+/// The values the series of `metric` take for `label`, in order.
+pub(crate) fn label_values(metric: &impl Collector, label: &str) -> BTreeSet<String> {
+    metric
+        .collect()
+        .iter()
+        .flat_map(|family| family.get_metric())
+        .flat_map(|metric| metric.get_label())
+        .filter(|pair| pair.get_name() == label)
+        .map(|pair| pair.get_value().to_string())
+        .collect()
+}
+
+/// Every component's readiness, `200` if all are ready and `503` if not.
+async fn ready(checks: Arc<[(String, ReadyCheck)]>) -> (StatusCode, Json<serde_json::Value>) {
+    let mut ready = true;
+    let mut components = serde_json::Map::new();
+    for (component, check) in checks.iter() {
+        let state = match check() {
+            Ok(()) => serde_json::json!({ "ready": true }),
+            Err(reason) => {
+                ready = false;
+                serde_json::json!({ "ready": false, "reason": reason })
+            }
+        };
+        components.insert(component.clone(), state);
+    }
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(serde_json::json!({ "ready": ready, "components": components })))
+}
+
+#[derive(Deserialize)]
+struct ReplayRange {
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+}
+
+async fn replay(
+    source: Arc<ReplaySource>,
+    Query(range): Query<ReplayRange>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let replayed = tokio::task::spawn_blocking(move || {
+        wal::replay(&source.reader, range.from, range.to, &source.publisher)
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(serde_json::json!({ "replayed": replayed })))
+}
+
+async fn publish(
+    pipeline: mpsc::Sender<NormalizedEvent>,
+    Json(events): Json<Vec<NormalizedEvent>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let published = events.len();
+    for event in events {
+        if pipeline.send(event).await.is_err() {
+            return Err((StatusCode::SERVICE_UNAVAILABLE, "the pipeline stopped".to_string()));
+        }
+    }
+    Ok(Json(serde_json::json!({ "published": published })))
+}
+
+#[derive(Deserialize)]
+struct SnapshotQuery {
+    topic: Option<String>,
+}
+
+async fn snapshot(
+    bus: EventBus,
+    Query(query): Query<SnapshotQuery>,
+) -> Result<Json<Vec<ingest_core::event::NormalizedEvent>>, (StatusCode, String)> {
+    let filter = match query.topic {
+        Some(topic) => topic
+            .parse::<Filter>()
+            .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?,
+        None => Filter::new(),
+    };
+    Ok(Json(bus.snapshot(&filter)))
 }
 
 #[cfg(test)]
@@ -47,4 +472,901 @@ mod tests {
         let body = reqwest::get("http://127.0.0.1:3001/health").await.unwrap().text().await.unwrap();
         assert_eq!(body, "ok");
     }
+
+    #[tokio::test]
+    async fn run_fails_on_a_taken_address() {
+        let _taken = std::net::TcpListener::bind("127.0.0.1:3029").unwrap();
+        let served = OpsServer::new().run("127.0.0.1:3029".parse().unwrap()).await;
+        assert!(matches!(served, Err(IngestError::Io(_))));
+    }
+
+    #[tokio::test]
+    async fn replay_republishes_wal_range() {
+        let dir = std::env::temp_dir().join(format!("ingest-ops-replay-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let wal_cfg = ingest_core::config::WalConfig {
+            path: dir.to_string_lossy().into_owned(),
+            fsync: Default::default(),
+            fsync_interval_ms: 1_000,
+            segment_bytes: 1024 * 1024,
+            retention_segments: None,
+        };
+        let mut log = wal::Wal::open(&wal_cfg).unwrap();
+        for secs in 0..3 {
+            log.append(&ingest_core::event::NormalizedEvent {
+                venue: "binance_spot".into(),
+                symbol: "BTCUSDT".into(),
+                timestamp: DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap(),
+                ..Default::default()
+            })
+            .unwrap();
+        }
+
+        let bus = api::EventBus::new(16);
+        let mut consumer = bus.subscribe();
+        let auth = AuthConfig { tokens: vec!["s3cret".into()], ..Default::default() };
+        let server = OpsServer::new().with_replay(WalReader::open(&dir), bus.publisher()).with_auth(auth);
+        tokio::spawn(server.run("127.0.0.1:3002".parse().unwrap()));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let body: serde_json::Value = reqwest::Client::new()
+            .post("http://127.0.0.1:3002/replay?from=2023-11-14T22:13:21Z&to=2023-11-14T22:14:00Z")
+            .bearer_auth("s3cret")
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(body["replayed"], 2);
+        let first = consumer.recv().await.unwrap();
+        assert_eq!(first.timestamp.timestamp(), 1_700_000_001);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn ws_streams_filtered_events() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message;
+
+        let bus = api::EventBus::new(16);
+        let publisher = bus.publisher();
+        let cfg = WsConfig { send_buffer: 8, slow_client: Default::default() };
+        let server = OpsServer::new().with_ws(bus, cfg);
+        tokio::spawn(server.run("127.0.0.1:3003".parse().unwrap()));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let (mut socket, _) = tokio_tungstenite::connect_async("ws://127.0.0.1:3003/ws").await.unwrap();
+        socket
+            .send(Message::Text(r#"{"symbols": ["ETHUSDT"], "kinds": ["trade"]}"#.into()))
+            .await
+            .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        for symbol in ["BTCUSDT", "ETHUSDT"] {
+            publisher.publish(ingest_core::event::NormalizedEvent {
+                venue: "binance_spot".into(),
+                symbol: symbol.into(),
+                kind: ingest_core::event::EventKind::Trade,
+                ..Default::default()
+            });
+        }
+        let frame = socket.next().await.unwrap().unwrap();
+        let event: serde_json::Value = serde_json::from_str(frame.to_text().unwrap()).unwrap();
+        assert_eq!(event["symbol"], "ETHUSDT");
+    }
+
+    #[tokio::test]
+    async fn ws_subscribes_from_query_and_resubscribes() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message;
+
+        let bus = api::EventBus::new(16);
+        let publisher = bus.publisher();
+        let cfg = WsConfig { send_buffer: 8, slow_client: Default::default() };
+        let server = OpsServer::new().with_ws(bus, cfg);
+        tokio::spawn(server.run("127.0.0.1:3009".parse().unwrap()));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let bad = tokio_tungstenite::connect_async("ws://127.0.0.1:3009/ws?kind=fill").await;
+        assert!(bad.is_err());
+        let url = "ws://127.0.0.1:3009/ws?symbol=ETHUSDT&kind=trade";
+        let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let publish = |symbol: &str| {
+            publisher.publish(ingest_core::event::NormalizedEvent {
+                venue: "binance_spot".into(),
+                symbol: symbol.into(),
+                kind: ingest_core::event::EventKind::Trade,
+                ..Default::default()
+            });
+        };
+        publish("BTCUSDT");
+        publish("ETHUSDT");
+        let frame = socket.next().await.unwrap().unwrap();
+        let event: serde_json::Value = serde_json::from_str(frame.to_text().unwrap()).unwrap();
+        assert_eq!(event["symbol"], "ETHUSDT");
+
+        socket.send(Message::Text(r#"{"symbols": ["BTCUSDT"]}"#.into())).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        publish("ETHUSDT");
+        publish("BTCUSDT");
+        let frame = socket.next().await.unwrap().unwrap();
+        let event: serde_json::Value = serde_json::from_str(frame.to_text().unwrap()).unwrap();
+        assert_eq!(event["symbol"], "BTCUSDT");
+
+        socket.send(Message::Text("not json".into())).await.unwrap();
+        let frame = socket.next().await.unwrap().unwrap();
+        assert!(matches!(frame, Message::Close(Some(close)) if u16::from(close.code) == 1008));
+    }
+
+    #[tokio::test]
+    async fn snapshot_returns_latest_events() {
+        let bus = api::EventBus::new(16);
+        let publisher = bus.publisher();
+        for kind in [ingest_core::event::EventKind::Trade, ingest_core::event::EventKind::Quote] {
+            publisher.publish(ingest_core::event::NormalizedEvent {
+                venue: "binance_spot".into(),
+                symbol: "BTCUSDT".into(),
+                kind,
+                ..Default::default()
+            });
+        }
+        let server = OpsServer::new().with_snapshot(bus);
+        tokio::spawn(server.run("127.0.0.1:3004".parse().unwrap()));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let all: Vec<serde_json::Value> = reqwest::get("http://127.0.0.1:3004/snapshot").await.unwrap().json().await.unwrap();
+        assert_eq!(all.len(), 2);
+        let trades: Vec<serde_json::Value> = reqwest::get("http://127.0.0.1:3004/snapshot?topic=trades.*.*").await.unwrap().json().await.unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0]["kind"], "trade");
+        let bad = reqwest::get("http://127.0.0.1:3004/snapshot?topic=fills").await.unwrap();
+        assert_eq!(bad.status(), reqwest::StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn bus_state_lists_subscribers() {
+        let bus = api::EventBus::new(16);
+        let _sink = bus.subscribe_named("sink.archive", Filter::new());
+        bus.publisher().publish(ingest_core::event::NormalizedEvent::default());
+        let server = OpsServer::new().with_bus_state(bus);
+        tokio::spawn(server.run("127.0.0.1:3006".parse().unwrap()));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let state: serde_json::Value = reqwest::get("http://127.0.0.1:3006/bus/state").await.unwrap().json().await.unwrap();
+        assert_eq!(state["last_sequence"], 1);
+        assert_eq!(state["published"]["raw"], 1);
+        assert_eq!(state["subscribers"][0]["name"], "sink.archive");
+        assert_eq!(state["subscribers"][0]["queued"], 1);
+    }
+
+    #[test]
+    fn event_metrics_count_venues_and_the_busiest_symbols() {
+        use ingest_core::event::{EventKind, NormalizedEvent};
+        use prometheus::core::Collector;
+
+        let cfg = ingest_core::config::MetricsConfig { top_symbols: 1, ..Default::default() };
+        let metrics = EventMetrics::new(&cfg, &Default::default()).unwrap();
+        let event = |venue: &str, symbol: &str| NormalizedEvent {
+            venue: venue.into(),
+            symbol: symbol.into(),
+            kind: EventKind::Trade,
+            ..Default::default()
+        };
+        for _ in 0..3 {
+            metrics.observe(&event("binance_spot", "BTCUSDT"));
+        }
+        metrics.observe(&event("coinbase", "BTC-USD"));
+        metrics.sample(std::time::Duration::from_secs(1));
+        assert_eq!(metrics.events.with_label_values(&["binance_spot", "trade"]).get(), 3);
+        assert_eq!(metrics.symbol_events.with_label_values(&["binance_spot", "BTCUSDT"]).get(), 3);
+        assert_eq!(metrics.rate.with_label_values(&["coinbase"]).get_sample_sum(), 1.0);
+
+        for _ in 0..4 {
+            metrics.observe(&event("coinbase", "BTC-USD"));
+        }
+        metrics.sample(std::time::Duration::from_secs(1));
+        assert_eq!(metrics.symbol_events.with_label_values(&["coinbase", "BTC-USD"]).get(), 5);
+        let exported = metrics.symbol_events.collect()[0].get_metric().len();
+        assert_eq!(exported, 1);
+        // Quiet venues are sampled at zero.
+        let quiet = metrics.rate.with_label_values(&["binance_spot"]);
+        assert_eq!(quiet.get_sample_count(), 2);
+        assert_eq!(quiet.get_sample_sum(), 3.0);
+    }
+
+    #[test]
+    fn event_metrics_measure_ingest_latency() {
+        use prometheus::core::Metric;
+
+        let cfg = ingest_core::config::MetricsConfig {
+            latency_buckets_seconds: vec![0.01, 0.1],
+            ..Default::default()
+        };
+        let metrics = EventMetrics::new(&cfg, &Default::default()).unwrap();
+        let now = Utc::now();
+        let event = ingest_core::event::NormalizedEvent {
+            venue: "binance_spot".into(),
+            timestamp: now - chrono::Duration::milliseconds(50),
+            received_at: Some(now - chrono::Duration::milliseconds(5)),
+            ..Default::default()
+        };
+        metrics.observe_at(&event, now);
+        let exchange = metrics.exchange_latency.with_label_values(&["binance_spot"]);
+        assert_eq!(exchange.get_sample_sum(), 0.05);
+        let receive = metrics.receive_latency.with_label_values(&["binance_spot"]);
+        assert_eq!(receive.get_sample_sum(), 0.005);
+        let buckets = receive.metric().get_histogram().get_bucket().to_vec();
+        assert_eq!(buckets[0].get_cumulative_count(), 1);
+
+        let unordered = ingest_core::config::MetricsConfig {
+            latency_buckets_seconds: vec![0.1, 0.01],
+            ..Default::default()
+        };
+        assert!(EventMetrics::new(&unordered, &Default::default()).is_err());
+    }
+
+    #[test]
+    fn metrics_take_configured_prefix_labels_and_buckets() {
+        let ops = OpsMetricsConfig {
+            prefix: Some("ingest".into()),
+            labels: [("region".to_string(), "eu-west-1".to_string())].into(),
+            buckets: [("receive_to_publish_latency_seconds".to_string(), vec![0.001, 0.002])].into(),
+        };
+        let server = OpsServer::namespaced(&ops).unwrap();
+        let metrics = EventMetrics::new(&Default::default(), &ops).unwrap();
+        metrics.register(&server.registry).unwrap();
+        server.requests.inc();
+        let event = ingest_core::event::NormalizedEvent {
+            venue: "binance_spot".into(),
+            received_at: Some(Utc::now()),
+            ..Default::default()
+        };
+        metrics.observe(&event);
+        let families = server.registry.gather();
+        let family = |name: &str| families.iter().find(|family| family.get_name() == name).unwrap();
+        let requests = &family("ingest_requests_total").get_metric()[0];
+        assert_eq!(requests.get_label()[0].get_name(), "region");
+        assert_eq!(requests.get_label()[0].get_value(), "eu-west-1");
+        let receive = &family("ingest_receive_to_publish_latency_seconds").get_metric()[0];
+        assert_eq!(receive.get_histogram().get_bucket().len(), 2);
+        let exchange = &family("ingest_exchange_to_publish_latency_seconds").get_metric()[0];
+        assert_eq!(exchange.get_histogram().get_bucket().len(), 12);
+
+        let unknown = OpsMetricsConfig {
+            buckets: [("latency".to_string(), vec![1.0])].into(),
+            ..Default::default()
+        };
+        assert!(EventMetrics::new(&Default::default(), &unknown).is_err());
+    }
+
+    #[test]
+    fn adapter_metrics_follow_status_events() {
+        use ingest_core::event::NormalizedEvent;
+
+        let metrics = AdapterMetrics::new();
+        metrics.track("binance_spot");
+        metrics.track("coinbase");
+        let connected = |venue| metrics.connected.with_label_values(&[venue]).get();
+        assert_eq!(connected("binance_spot"), 0);
+
+        for up in [true, false, true] {
+            metrics.observe(&NormalizedEvent::adapter_status("binance_spot", up));
+        }
+        assert_eq!(connected("binance_spot"), 1);
+        assert_eq!(connected("coinbase"), 0);
+        assert_eq!(metrics.reconnects.with_label_values(&["binance_spot"]).get(), 1);
+
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        metrics.observe(&NormalizedEvent { venue: "binance_spot".into(), ..Default::default() });
+        metrics.sample();
+        let age = |venue| metrics.last_message_age.with_label_values(&[venue]).get();
+        assert!(age("binance_spot") < age("coinbase"));
+        assert!(age("coinbase") >= 0.02);
+
+        assert_eq!(metrics.disconnected(), ["coinbase"]);
+        metrics.untrack("coinbase");
+        assert!(metrics.disconnected().is_empty());
+    }
+
+    #[tokio::test]
+    async fn ready_reports_components_that_are_not() {
+        let adapters = AdapterMetrics::new();
+        adapters.track("binance_spot");
+        let tracked = adapters.clone();
+        let server = OpsServer::new()
+            .with_ready_check("pipeline", || Ok(()))
+            .with_ready_check("adapters", move || match tracked.disconnected() {
+                venues if venues.is_empty() => Ok(()),
+                venues => Err(format!("not connected: {}", venues.join(", "))),
+            });
+        tokio::spawn(server.run("127.0.0.1:3007".parse().unwrap()));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let res = reqwest::get("http://127.0.0.1:3007/ready").await.unwrap();
+        assert_eq!(res.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        let body: serde_json::Value = res.json().await.unwrap();
+        assert_eq!(body["ready"], false);
+        assert_eq!(body["components"]["pipeline"]["ready"], true);
+        assert_eq!(body["components"]["adapters"]["reason"], "not connected: binance_spot");
+
+        adapters.observe(&ingest_core::event::NormalizedEvent::adapter_status("binance_spot", true));
+        let res = reqwest::get("http://127.0.0.1:3007/ready").await.unwrap();
+        assert_eq!(res.status(), reqwest::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn healthz_probes_follow_startup_liveness_and_readiness() {
+        let ready = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let check = ready.clone();
+        let server = OpsServer::new().with_ready_check("sinks", move || {
+            if check.load(std::sync::atomic::Ordering::Relaxed) { Ok(()) } else { Err("sinks stopped".into()) }
+        });
+        let startup = server.startup();
+        tokio::spawn(server.run("127.0.0.1:3021".parse().unwrap()));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let status = |path: &'static str| async move {
+            reqwest::get(format!("http://127.0.0.1:3021{}", path)).await.unwrap().status()
+        };
+
+        assert_eq!(status("/healthz/startup").await, reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(status("/healthz/live").await, reqwest::StatusCode::OK);
+        assert_eq!(status("/healthz/ready").await, reqwest::StatusCode::SERVICE_UNAVAILABLE);
+
+        startup.complete();
+        ready.store(true, std::sync::atomic::Ordering::Relaxed);
+        assert_eq!(status("/healthz/startup").await, reqwest::StatusCode::OK);
+        assert_eq!(status("/healthz/ready").await, reqwest::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn healthz_active_reports_the_role_and_last_sequence() {
+        let bus = api::EventBus::new(16);
+        let active = Active::new(false);
+        let server = OpsServer::new().with_active(active.clone(), bus.clone());
+        tokio::spawn(server.run("127.0.0.1:3026".parse().unwrap()));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let beat = || async {
+            let res = reqwest::get("http://127.0.0.1:3026/healthz/active").await.unwrap();
+            (res.status(), res.json::<serde_json::Value>().await.unwrap())
+        };
+
+        let (status, body) = beat().await;
+        assert_eq!(status, reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["sequence"], 0);
+
+        bus.publisher().publish_sequenced(41, ingest_core::event::NormalizedEvent::default());
+        active.promote(2);
+        let (status, body) = beat().await;
+        assert_eq!(status, reqwest::StatusCode::OK);
+        assert_eq!(body, serde_json::json!({"active": true, "sequence": 41, "epoch": 2}));
+
+        active.demote();
+        let (status, body) = beat().await;
+        assert_eq!(status, reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["epoch"], 2);
+    }
+
+    #[tokio::test]
+    async fn sse_streams_only_the_requested_events() {
+        use ingest_core::event::{EventKind, NormalizedEvent};
+
+        let bus = api::EventBus::new(16);
+        let publisher = bus.publisher();
+        let server = OpsServer::new().with_sse(bus);
+        tokio::spawn(server.run("127.0.0.1:3008".parse().unwrap()));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let bad = reqwest::get("http://127.0.0.1:3008/events?kind=fill").await.unwrap();
+        assert_eq!(bad.status(), reqwest::StatusCode::BAD_REQUEST);
+        let mut res = reqwest::get("http://127.0.0.1:3008/events?venue=binance&symbol=BTC-USD,ETH-USD&kind=trade")
+            .await
+            .unwrap();
+        let event = |venue: &str, symbol: &str, kind| NormalizedEvent {
+            venue: venue.into(),
+            symbol: symbol.into(),
+            kind,
+            ..Default::default()
+        };
+        publisher.publish(event("coinbase", "BTC-USD", EventKind::Trade));
+        publisher.publish(event("binance", "BTC-USD", EventKind::Quote));
+        publisher.publish(event("binance", "SOL-USD", EventKind::Trade));
+        publisher.publish(event("binance", "ETH-USD", EventKind::Trade));
+        let mut body = String::new();
+        while !body.contains("\n\n") {
+            body.push_str(std::str::from_utf8(&res.chunk().await.unwrap().unwrap()).unwrap());
+        }
+        let (name, data) = body.trim().split_once('\n').unwrap();
+        assert_eq!(name, "event: trade");
+        let data: serde_json::Value = serde_json::from_str(data.strip_prefix("data: ").unwrap()).unwrap();
+        assert_eq!((data["venue"].as_str(), data["symbol"].as_str()), (Some("binance"), Some("ETH-USD")));
+    }
+
+    #[tokio::test]
+    async fn sse_releases_its_subscriber_when_the_client_goes_away() {
+        let bus = api::EventBus::new(16);
+        let server = OpsServer::new().with_sse(bus.clone());
+        tokio::spawn(server.run("127.0.0.1:3016".parse().unwrap()));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let sse = |bus: &api::EventBus| bus.state().subscribers.into_iter().find(|subscriber| subscriber.name == "sse");
+        let res = reqwest::get("http://127.0.0.1:3016/events").await.unwrap();
+        assert_eq!(sse(&bus).map(|subscriber| subscriber.closed), Some(false));
+        drop(res);
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert!(sse(&bus).is_none());
+    }
+
+    #[tokio::test]
+    async fn sse_sends_heartbeats_while_idle() {
+        let bus = api::EventBus::new(16);
+        let server = OpsServer::new().with_sse(bus.clone()).with_sse_config(SseConfig { keepalive_secs: 1 });
+        tokio::spawn(server.run("127.0.0.1:3025".parse().unwrap()));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let mut res = reqwest::get("http://127.0.0.1:3025/events").await.unwrap();
+        let chunk = tokio::time::timeout(std::time::Duration::from_secs(3), res.chunk()).await.unwrap().unwrap().unwrap();
+        assert_eq!(&chunk[..], b": keepalive\n\n");
+    }
+
+    #[tokio::test]
+    async fn sse_ends_with_a_shutdown_event_when_the_bus_closes() {
+        let bus = api::EventBus::new(16);
+        let server = OpsServer::new().with_sse(bus.clone());
+        tokio::spawn(server.run("127.0.0.1:3017".parse().unwrap()));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let res = reqwest::get("http://127.0.0.1:3017/events").await.unwrap();
+        bus.publisher().publish(ingest_core::event::NormalizedEvent {
+            venue: "binance_spot".into(),
+            kind: ingest_core::event::EventKind::Trade,
+            ..Default::default()
+        });
+        bus.close();
+        let body = tokio::time::timeout(std::time::Duration::from_secs(5), res.text()).await.unwrap().unwrap();
+        assert!(body.starts_with("event: trade\n"), "{}", body);
+        assert!(body.ends_with("event: shutdown\ndata: shutting down\n\n"), "{}", body);
+    }
+
+    #[tokio::test]
+    async fn symbols_lists_subscriptions_with_their_last_updates() {
+        use ingest_core::event::{NormalizedEvent, Subscription};
+
+        let adapters = AdapterMetrics::new();
+        adapters.track("binance_spot");
+        adapters.track("coinbase");
+        let subscription = Subscription { venue: "binance_spot".into(), symbols: vec!["ethusdt".into(), "BTCUSDT".into()], discovered: true };
+        adapters.observe(&NormalizedEvent::adapter_subscription(&subscription));
+        let received_at = "2026-01-02T03:04:05Z".parse().unwrap();
+        adapters.observe(&NormalizedEvent { venue: "binance_spot".into(), symbol: "ETHUSDT".into(), received_at: Some(received_at), ..Default::default() });
+        adapters.observe(&NormalizedEvent { venue: "binance_spot".into(), symbol: "SOLUSDT".into(), ..Default::default() });
+        let server = OpsServer::new().with_symbols(adapters);
+        tokio::spawn(server.run("127.0.0.1:3018".parse().unwrap()));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let venues: serde_json::Value = reqwest::get("http://127.0.0.1:3018/symbols").await.unwrap().json().await.unwrap();
+        assert_eq!(venues[0]["venue"], "binance_spot");
+        assert_eq!(venues[0]["discovered"], true);
+        assert!(venues[0]["subscribed_at"].is_string());
+        assert_eq!(
+            venues[0]["symbols"],
+            serde_json::json!([{"symbol": "BTCUSDT", "last_update": null}, {"symbol": "ETHUSDT", "last_update": "2026-01-02T03:04:05Z"}])
+        );
+        assert_eq!(venues[1], serde_json::json!({"venue": "coinbase", "discovered": false, "subscribed_at": null, "symbols": []}));
+        let one: serde_json::Value = reqwest::get("http://127.0.0.1:3018/symbols?venue=coinbase").await.unwrap().json().await.unwrap();
+        assert_eq!(one.as_array().unwrap().len(), 1);
+        let unknown = reqwest::get("http://127.0.0.1:3018/symbols?venue=kraken").await.unwrap();
+        assert_eq!(unknown.status(), reqwest::StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn sse_replays_recent_events_before_live_ones() {
+        let bus = api::EventBus::from_config(&Default::default(), &api::BusMetrics::new());
+        let server = OpsServer::new().with_sse(bus.clone());
+        tokio::spawn(server.run("127.0.0.1:3019".parse().unwrap()));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let trade = |id: u64| ingest_core::event::NormalizedEvent {
+            venue: "binance_spot".into(),
+            kind: ingest_core::event::EventKind::Trade,
+            payload: serde_json::json!({ "id": id }),
+            ..Default::default()
+        };
+        for id in 0..3 {
+            bus.publisher().publish(trade(id));
+        }
+        let res = reqwest::get("http://127.0.0.1:3019/events?kind=trade&replay=2").await.unwrap();
+        bus.publisher().publish(trade(3));
+        bus.close();
+        let body = tokio::time::timeout(std::time::Duration::from_secs(5), res.text()).await.unwrap().unwrap();
+        let ids: Vec<u64> = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .filter_map(|data| serde_json::from_str::<serde_json::Value>(data).ok())
+            .map(|event| event["payload"]["id"].as_u64().unwrap())
+            .collect();
+        assert_eq!(ids, [1, 2, 3]);
+
+        let too_many = reqwest::get("http://127.0.0.1:3019/events?replay=10001").await.unwrap();
+        assert_eq!(too_many.status(), reqwest::StatusCode::BAD_REQUEST);
+        let bad_since = reqwest::get("http://127.0.0.1:3019/events?since=yesterday").await.unwrap();
+        assert_eq!(bad_since.status(), reqwest::StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn pushgateway_exporter_replaces_its_group() {
+        use axum::http::{Method, Uri};
+        use ingest_core::config::PushgatewayConfig;
+
+        let (pushed_tx, mut pushed) = tokio::sync::mpsc::unbounded_channel();
+        let gateway = Router::new().fallback(move |method: Method, uri: Uri, body: String| {
+            let _ = pushed_tx.send((method, uri.path().to_string(), body));
+            async { StatusCode::OK }
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:3020").await.unwrap();
+        tokio::spawn(async move { axum::serve(listener, gateway).await });
+
+        let server = OpsServer::new();
+        server.requests.inc_by(3);
+        let cfg = PushgatewayConfig {
+            url: "http://127.0.0.1:3020/".into(),
+            job: "ingestd".into(),
+            instance: Some("ingest 1".into()),
+            labels: [("region".to_string(), "eu-west-1".to_string())].into(),
+            interval_secs: 15,
+        };
+        let exporter = PushgatewayExporter::new(&cfg, server.registry.clone()).unwrap();
+        exporter.push().await.unwrap();
+        let (method, path, body) = pushed.recv().await.unwrap();
+        assert_eq!(method, Method::PUT);
+        assert_eq!(path, "/metrics/job/ingestd/instance/ingest%201/region/eu-west-1");
+        assert!(body.contains("requests_total 3"), "{}", body);
+
+        let unparsable = PushgatewayConfig { url: "pushgateway:9091".into(), ..cfg };
+        assert!(PushgatewayExporter::new(&unparsable, Registry::new()).is_err());
+    }
+
+    #[tokio::test]
+    async fn metrics_are_compressed_as_accepted() {
+        let server = OpsServer::new();
+        server.requests.inc();
+        tokio::spawn(server.run("127.0.0.1:3023".parse().unwrap()));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let client = reqwest::Client::new();
+        let encoding = |accept: &'static str| {
+            let request = client.get("http://127.0.0.1:3023/metrics").header("accept-encoding", accept);
+            async move { request.send().await.unwrap().headers().get("content-encoding").cloned() }
+        };
+
+        assert_eq!(encoding("gzip").await.unwrap(), "gzip");
+        assert_eq!(encoding("zstd").await.unwrap(), "zstd");
+        assert!(encoding("identity").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn metrics_are_openmetrics_when_accepted() {
+        let server = OpsServer::new();
+        server.requests.inc();
+        tokio::spawn(server.run("127.0.0.1:3024".parse().unwrap()));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let client = reqwest::Client::new();
+        let accept = "application/openmetrics-text;version=1.0.0,text/plain;version=0.0.4;q=0.5";
+        let res = client.get("http://127.0.0.1:3024/metrics").header("accept", accept).send().await.unwrap();
+        assert!(res.headers()["content-type"].to_str().unwrap().starts_with("application/openmetrics-text"));
+        let body = res.text().await.unwrap();
+        assert!(body.contains("# TYPE requests counter\n"), "{}", body);
+        assert!(body.contains("\nrequests_total 1\n"), "{}", body);
+        assert!(body.ends_with("\n# EOF\n"), "{}", body);
+
+        let text = reqwest::get("http://127.0.0.1:3024/metrics").await.unwrap().text().await.unwrap();
+        assert!(text.contains("# TYPE requests_total counter\n"), "{}", text);
+    }
+
+    #[tokio::test]
+    async fn cors_answers_allowed_origins_only() {
+        let cfg = CorsConfig {
+            allowed_origins: vec!["https://dash.example.com".into()],
+            allowed_headers: vec!["authorization".into()],
+            allow_credentials: true,
+            max_age_secs: 600,
+        };
+        let server = OpsServer::new().with_cors(&cfg).unwrap();
+        tokio::spawn(server.run("127.0.0.1:3022".parse().unwrap()));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let client = reqwest::Client::new();
+
+        let preflight = client
+            .request(reqwest::Method::OPTIONS, "http://127.0.0.1:3022/metrics")
+            .header("origin", "https://dash.example.com")
+            .header("access-control-request-method", "GET")
+            .header("access-control-request-headers", "authorization")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(preflight.status(), reqwest::StatusCode::OK);
+        let headers = preflight.headers();
+        assert_eq!(headers["access-control-allow-origin"], "https://dash.example.com");
+        assert_eq!(headers["access-control-allow-credentials"], "true");
+        assert_eq!(headers["access-control-allow-headers"], "authorization");
+
+        let res = client.get("http://127.0.0.1:3022/health").header("origin", "https://evil.example.com").send().await.unwrap();
+        assert_eq!(res.status(), reqwest::StatusCode::OK);
+        assert!(res.headers().get("access-control-allow-origin").is_none());
+
+        let bad = CorsConfig { allowed_origins: vec!["*".into()], ..cfg };
+        assert!(OpsServer::new().with_cors(&bad).is_err());
+    }
+
+    #[tokio::test]
+    async fn streams_beyond_the_client_cap_are_refused() {
+        let limits = StreamLimitsConfig { max_client_connections: Some(1), ..Default::default() };
+        let server = OpsServer::new().with_sse(api::EventBus::new(16)).with_stream_limits(limits);
+        tokio::spawn(server.run("127.0.0.1:3014".parse().unwrap()));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let url = "http://127.0.0.1:3014/events";
+        let open = reqwest::get(url).await.unwrap();
+        assert_eq!(open.status(), reqwest::StatusCode::OK);
+        let refused = reqwest::get(url).await.unwrap();
+        assert_eq!(refused.status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+        drop(open);
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert_eq!(reqwest::get(url).await.unwrap().status(), reqwest::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn status_sums_up_venues_pipeline_and_sinks() {
+        use api::control::{self, ControlRequest, ControlResponse, StageStats, VenueStatus};
+        use ingest_core::event::NormalizedEvent;
+
+        let (handle, mut requests) = control::channel(4);
+        tokio::spawn(async move {
+            while let Some(pending) = requests.next().await {
+                let response = match pending.request {
+                    ControlRequest::Venues => ControlResponse::Venues(vec![VenueStatus { name: "binance_spot".into(), paused: true }]),
+                    _ => ControlResponse::PipelineStats(vec![StageStats { stage: "canonicalize".into(), processed: 2, queue_depth: 1, restarts: 0 }]),
+                };
+                pending.respond(Ok(response));
+            }
+        });
+        let adapters = AdapterMetrics::new();
+        adapters.track("binance_spot");
+        adapters.observe(&NormalizedEvent::adapter_status("binance_spot", true));
+        let events = EventMetrics::new(&Default::default(), &Default::default()).unwrap();
+        for symbol in ["BTCUSDT", "ETHUSDT"] {
+            events.observe(&NormalizedEvent { venue: "binance_spot".into(), symbol: symbol.into(), ..Default::default() });
+        }
+        events.sample(std::time::Duration::from_secs(1));
+        let sinks = sinks::SinkMetrics::new();
+        sinks.queue_depth.with_label_values(&["archive"]).set(7);
+        let server = OpsServer::new().with_status(StatusSources {
+            build: BuildInfo { version: "1.2.3".into(), commit: Some("4f2c9e1".into()), features: vec!["kafka".into()], ..Default::default() },
+            adapters,
+            events,
+            sinks,
+            control: handle,
+        });
+        tokio::spawn(server.run("127.0.0.1:3015".parse().unwrap()));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let status: serde_json::Value = reqwest::get("http://127.0.0.1:3015/status").await.unwrap().json().await.unwrap();
+        assert_eq!(status["version"], "1.2.3");
+        assert_eq!((status["commit"].as_str(), status["features"][0].as_str()), (Some("4f2c9e1"), Some("kafka")));
+        assert_eq!(status["events_per_second"], 2.0);
+        let venue = &status["venues"][0];
+        assert_eq!((venue["name"].as_str(), venue["paused"].as_bool(), venue["connected"].as_bool()), (Some("binance_spot"), Some(true), Some(true)));
+        assert_eq!((venue["symbols"].as_u64(), venue["events_per_second"].as_f64()), (Some(2), Some(2.0)));
+        assert_eq!(status["pipeline"][0]["queue_depth"], 1);
+        assert_eq!((status["sinks"][0]["name"].as_str(), status["sinks"][0]["queue_depth"].as_i64()), (Some("archive"), Some(7)));
+    }
+
+    #[test]
+    fn build_info_is_a_gauge_of_one_labelled_with_the_build() {
+        let build = BuildInfo {
+            version: "1.2.3".into(),
+            commit: Some("4f2c9e1".into()),
+            built_at: Some("2026-01-02T03:04:05Z".into()),
+            features: vec!["kafka".into(), "wasm".into()],
+        };
+        assert_eq!(build.to_string(), "1.2.3 (4f2c9e1, built 2026-01-02T03:04:05Z, features: kafka, wasm)");
+        let server = OpsServer::new();
+        build.register(&server.registry).unwrap();
+        let families = server.registry.gather();
+        let info = families.iter().find(|family| family.get_name() == "build_info").unwrap();
+        let metric = &info.get_metric()[0];
+        assert_eq!(metric.get_gauge().get_value(), 1.0);
+        let labels: Vec<_> = metric.get_label().iter().map(|label| (label.get_name(), label.get_value())).collect();
+        assert!(labels.contains(&("commit", "4f2c9e1")) && labels.contains(&("features", "kafka,wasm")));
+    }
+
+    #[tokio::test]
+    async fn publish_sends_posted_events_into_the_pipeline() {
+        let (pipeline, mut events) = mpsc::channel(16);
+        let auth = AuthConfig { tokens: vec!["s3cret".into()], ..Default::default() };
+        let server = OpsServer::new().with_publish(pipeline).with_auth(auth);
+        tokio::spawn(server.run("127.0.0.1:3027".parse().unwrap()));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let event = ingest_core::event::NormalizedEvent { venue: "binance_spot".into(), symbol: "BTCUSDT".into(), ..Default::default() };
+        let published: serde_json::Value = reqwest::Client::new()
+            .post("http://127.0.0.1:3027/publish")
+            .bearer_auth("s3cret")
+            .json(&[&event, &event])
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(published["published"], 2);
+        assert_eq!(events.recv().await.unwrap().symbol, "BTCUSDT");
+    }
+
+    #[tokio::test]
+    async fn control_endpoints_go_through_the_handle() {
+        use api::control::{self, ControlRequest, ControlResponse, VenueStatus};
+
+        let (handle, mut requests) = control::channel(4);
+        tokio::spawn(async move {
+            let mut paused = false;
+            while let Some(pending) = requests.next().await {
+                let response = match pending.request {
+                    ControlRequest::Venues => ControlResponse::Venues(vec![VenueStatus { name: "binance_spot".into(), paused }]),
+                    ControlRequest::Pause { .. } => {
+                        paused = true;
+                        ControlResponse::Done
+                    }
+                    _ => ControlResponse::Done,
+                };
+                pending.respond(Ok(response));
+            }
+        });
+        let auth = AuthConfig { tokens: vec!["s3cret".into()], ..Default::default() };
+        let server = OpsServer::new().with_control(handle).with_auth(auth);
+        tokio::spawn(server.run("127.0.0.1:3005".parse().unwrap()));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let client = reqwest::Client::new();
+        let anonymous = client.post("http://127.0.0.1:3005/control/pause?venue=binance_spot").send().await.unwrap();
+        assert_eq!(anonymous.status(), reqwest::StatusCode::UNAUTHORIZED);
+        let paused = client.post("http://127.0.0.1:3005/control/pause?venue=binance_spot").bearer_auth("s3cret").send().await.unwrap();
+        assert_eq!(paused.status(), reqwest::StatusCode::NO_CONTENT);
+        let venues: serde_json::Value =
+            client.get("http://127.0.0.1:3005/control/venues").bearer_auth("s3cret").send().await.unwrap().json().await.unwrap();
+        assert_eq!(venues, serde_json::json!([{"name": "binance_spot", "paused": true}]));
+        let unknown = client.post("http://127.0.0.1:3005/control/pause?venue=kraken").bearer_auth("s3cret").send().await.unwrap();
+        assert_eq!(unknown.status(), reqwest::StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn admin_endpoints_require_the_token() {
+        use api::control::{self, ControlRequest, ControlResponse, VenueStatus};
+
+        let (handle, mut requests) = control::channel(4);
+        tokio::spawn(async move {
+            let mut symbols = vec!["BTCUSDT".to_string()];
+            while let Some(pending) = requests.next().await {
+                let response = match &pending.request {
+                    ControlRequest::Venues => ControlResponse::Venues(vec![VenueStatus { name: "binance_spot".into(), paused: false }]),
+                    ControlRequest::EditSymbols { add, remove, .. } => {
+                        symbols.retain(|symbol| !remove.contains(symbol));
+                        symbols.extend(add.iter().cloned());
+                        ControlResponse::VenueSymbols(symbols.clone())
+                    }
+                    ControlRequest::Reload => ControlResponse::Reloaded(control::ReloadReport {
+                        errors: vec!["venue binance_spot configured twice".into()],
+                        ..Default::default()
+                    }),
+                    ControlRequest::SetLogFilter { filter } if filter.contains('!') => {
+                        let invalid = ingest_core::error::IngestError::Validation("invalid filter".into());
+                        pending.respond(Err(invalid));
+                        continue;
+                    }
+                    ControlRequest::SetLogFilter { filter } => ControlResponse::LogFilter(filter.clone()),
+                    _ => ControlResponse::Done,
+                };
+                pending.respond(Ok(response));
+            }
+        });
+        let auth = AuthConfig { tokens: vec!["s3cret".into()], ..Default::default() };
+        let server = OpsServer::new().with_admin(handle).with_auth(auth);
+        tokio::spawn(server.run("127.0.0.1:3010".parse().unwrap()));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let client = reqwest::Client::new();
+        let base = "http://127.0.0.1:3010/admin/venues";
+        let anonymous = client.post(format!("{}/binance_spot/pause", base)).send().await.unwrap();
+        assert_eq!(anonymous.status(), reqwest::StatusCode::UNAUTHORIZED);
+        let wrong = client.post(format!("{}/binance_spot/pause", base)).bearer_auth("guess").send().await.unwrap();
+        assert_eq!(wrong.status(), reqwest::StatusCode::UNAUTHORIZED);
+        let paused = client.post(format!("{}/binance_spot/pause", base)).bearer_auth("s3cret").send().await.unwrap();
+        assert_eq!(paused.status(), reqwest::StatusCode::NO_CONTENT);
+        let unknown = client.post(format!("{}/kraken/resume", base)).bearer_auth("s3cret").send().await.unwrap();
+        assert_eq!(unknown.status(), reqwest::StatusCode::NOT_FOUND);
+        let edited: serde_json::Value = client
+            .post(format!("{}/binance_spot/symbols", base))
+            .bearer_auth("s3cret")
+            .json(&serde_json::json!({"add": ["ETHUSDT"], "remove": ["BTCUSDT"]}))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(edited, serde_json::json!({"venue": "binance_spot", "symbols": ["ETHUSDT"]}));
+        let reload = client.post("http://127.0.0.1:3010/admin/reload").bearer_auth("s3cret").send().await.unwrap();
+        assert_eq!(reload.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+        let report: serde_json::Value = reload.json().await.unwrap();
+        assert_eq!(report["applied"], false);
+        assert_eq!(report["errors"][0], "venue binance_spot configured twice");
+
+        let log_level = "http://127.0.0.1:3010/admin/log-level";
+        let set = client.put(log_level).bearer_auth("s3cret").json(&serde_json::json!({"filter": "info,sinks=debug"})).send().await.unwrap();
+        let set: serde_json::Value = set.json().await.unwrap();
+        assert_eq!(set["filter"], "info,sinks=debug");
+        let bad = client.put(log_level).bearer_auth("s3cret").json(&serde_json::json!({"filter": "!"})).send().await.unwrap();
+        assert_eq!(bad.status(), reqwest::StatusCode::BAD_REQUEST);
+        let shutdown = client.post("http://127.0.0.1:3010/admin/shutdown").bearer_auth("s3cret").send().await.unwrap();
+        assert_eq!(shutdown.status(), reqwest::StatusCode::ACCEPTED);
+    }
+
+    #[tokio::test]
+    async fn tls_requires_a_client_certificate_when_configured() {
+        let fixture = |name: &str| format!("{}/fixtures/tls/{}", env!("CARGO_MANIFEST_DIR"), name);
+        let cfg = TlsConfig {
+            cert_path: fixture("server.pem"),
+            key_path: fixture("server.key"),
+            client_ca_path: Some(fixture("ca.pem")),
+        };
+        let server = OpsServer::new().with_tls(&cfg).unwrap();
+        tokio::spawn(server.run("127.0.0.1:3013".parse().unwrap()));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let ca = reqwest::Certificate::from_pem(&std::fs::read(fixture("ca.pem")).unwrap()).unwrap();
+        let client = |identity: Option<reqwest::Identity>| {
+            let builder = reqwest::Client::builder().use_rustls_tls().add_root_certificate(ca.clone());
+            match identity {
+                Some(identity) => builder.identity(identity),
+                None => builder,
+            }
+            .build()
+            .unwrap()
+        };
+        assert!(client(None).get("https://127.0.0.1:3013/health").send().await.is_err());
+        let mut pem = std::fs::read(fixture("client.key")).unwrap();
+        pem.extend(std::fs::read(fixture("client.pem")).unwrap());
+        let identity = reqwest::Identity::from_pem(&pem).unwrap();
+        let res = client(Some(identity)).get("https://127.0.0.1:3013/health").send().await.unwrap();
+        assert_eq!(res.text().await.unwrap(), "ok");
+
+        let missing = TlsConfig { key_path: fixture("missing.key"), ..cfg };
+        assert!(OpsServer::new().with_tls(&missing).is_err());
+    }
+
+    #[tokio::test]
+    async fn auth_policies_apply_per_route_group() {
+        let auth = AuthConfig { tokens: vec!["s3cret".into()], ..Default::default() };
+        let (handle, _requests) = api::control::channel(1);
+        let server = OpsServer::new().with_snapshot(api::EventBus::new(16)).with_auth(auth);
+        tokio::spawn(server.run("127.0.0.1:3011".parse().unwrap()));
+        let unprotected = OpsServer::new().with_admin(handle);
+        tokio::spawn(unprotected.run("127.0.0.1:3012".parse().unwrap()));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let client = reqwest::Client::new();
+        let status = |path: &str, token: Option<&str>| {
+            let mut request = client.get(format!("http://127.0.0.1:3011{}", path));
+            if let Some(token) = token {
+                request = request.bearer_auth(token);
+            }
+            async move { request.send().await.unwrap().status() }
+        };
+        assert_eq!(status("/health", None).await, reqwest::StatusCode::OK);
+        assert_eq!(status("/metrics", None).await, reqwest::StatusCode::OK);
+        assert_eq!(status("/snapshot", None).await, reqwest::StatusCode::UNAUTHORIZED);
+        assert_eq!(status("/snapshot", Some("s3cret")).await, reqwest::StatusCode::OK);
+
+        let admin = client.post("http://127.0.0.1:3012/admin/reload").send().await.unwrap();
+        assert_eq!(admin.status(), reqwest::StatusCode::NOT_FOUND);
+    }
 }
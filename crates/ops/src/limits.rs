@@ -0,0 +1,273 @@
+//! Connection caps and per-client connect rates of the streaming endpoints,
+//! `GET /events` and `GET /ws`, by the limits of a [`StreamLimitsConfig`].
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use ingest_core::config::StreamLimitsConfig;
+use prometheus::{IntCounterVec, IntGauge, Opts, Registry};
+
+/// How often admitting a stream also forgets idle clients, such as those
+/// refused for their rate that never came back.
+const PRUNE_EVERY: Duration = Duration::from_secs(1);
+
+pub(crate) struct StreamLimits {
+    cfg: StreamLimitsConfig,
+    open: IntGauge,
+    rejected: IntCounterVec,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    open: usize,
+    clients: HashMap<IpAddr, Client>,
+    pruned: Option<Instant>,
+}
+
+struct Client {
+    open: usize,
+    /// Streams the client may still open right away.
+    tokens: f64,
+    refilled: Instant,
+}
+
+/// Why a stream was refused.
+#[derive(Debug)]
+pub(crate) enum Rejected {
+    /// Every stream the server allows is open.
+    Full,
+    /// Every stream the client may have is open.
+    ClientFull,
+    /// The client opens streams too fast, and may open the next in this
+    /// many seconds.
+    TooFast(u64),
+}
+
+impl Rejected {
+    fn reason(&self) -> &'static str {
+        match self {
+            Rejected::Full => "connections",
+            Rejected::ClientFull => "client_connections",
+            Rejected::TooFast(_) => "client_rate",
+        }
+    }
+}
+
+impl IntoResponse for Rejected {
+    fn into_response(self) -> Response {
+        match self {
+            Rejected::Full => {
+                (StatusCode::SERVICE_UNAVAILABLE, "too many open streams").into_response()
+            }
+            Rejected::ClientFull => {
+                (StatusCode::TOO_MANY_REQUESTS, "too many open streams").into_response()
+            }
+            Rejected::TooFast(secs) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, secs.to_string())],
+                "streams opened too fast",
+            )
+                .into_response(),
+        }
+    }
+}
+
+impl StreamLimits {
+    pub(crate) fn new(cfg: StreamLimitsConfig, registry: &Registry) -> Self {
+        let open = IntGauge::new("stream_connections", "open sse and websocket streams").unwrap();
+        let rejected = IntCounterVec::new(
+            Opts::new(
+                "stream_rejections_total",
+                "sse and websocket streams refused, by the limit they hit",
+            ),
+            &["reason"],
+        )
+        .unwrap();
+        registry.register(Box::new(open.clone())).unwrap();
+        registry.register(Box::new(rejected.clone())).unwrap();
+        Self {
+            cfg,
+            open,
+            rejected,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Let `client` open a stream, which stays counted as open until the
+    /// permit is dropped.
+    pub(crate) fn acquire(self: &Arc<Self>, client: IpAddr) -> Result<Permit, Rejected> {
+        self.admit(client, Instant::now()).inspect_err(|rejected| {
+            self.rejected.with_label_values(&[rejected.reason()]).inc();
+        })?;
+        self.open.inc();
+        Ok(Permit {
+            limits: self.clone(),
+            client,
+        })
+    }
+
+    fn admit(&self, client: IpAddr, now: Instant) -> Result<(), Rejected> {
+        let burst = self.cfg.client_connect_burst as f64;
+        let mut state = self.state.lock().unwrap();
+        if state
+            .pruned
+            .is_none_or(|pruned| now.duration_since(pruned) >= PRUNE_EVERY)
+        {
+            self.prune(&mut state, now);
+        }
+        if self
+            .cfg
+            .max_connections
+            .is_some_and(|max| state.open >= max)
+        {
+            return Err(Rejected::Full);
+        }
+        let entry = state.clients.entry(client).or_insert(Client {
+            open: 0,
+            tokens: burst,
+            refilled: now,
+        });
+        if self
+            .cfg
+            .max_client_connections
+            .is_some_and(|max| entry.open >= max)
+        {
+            return Err(Rejected::ClientFull);
+        }
+        if let Some(rate) = self.cfg.client_connect_rate {
+            let elapsed = now.duration_since(entry.refilled).as_secs_f64();
+            entry.tokens = (entry.tokens + elapsed * rate).min(burst);
+            entry.refilled = now;
+            if entry.tokens < 1.0 {
+                return Err(Rejected::TooFast(
+                    ((1.0 - entry.tokens) / rate).ceil() as u64
+                ));
+            }
+            entry.tokens -= 1.0;
+        }
+        entry.open += 1;
+        state.open += 1;
+        Ok(())
+    }
+
+    fn release(&self, client: IpAddr) {
+        let mut state = self.state.lock().unwrap();
+        state.open -= 1;
+        let idle = match state.clients.get_mut(&client) {
+            Some(entry) => {
+                entry.open -= 1;
+                entry.open == 0
+            }
+            None => false,
+        };
+        if idle {
+            self.prune(&mut state, Instant::now());
+        }
+        self.open.dec();
+    }
+
+    /// Forget clients without open streams. Without a rate there is nothing
+    /// to remember about them, and with one they are forgotten once they
+    /// could burst again.
+    fn prune(&self, state: &mut State, now: Instant) {
+        let rate = self.cfg.client_connect_rate;
+        let burst = self.cfg.client_connect_burst as f64;
+        state.clients.retain(|_, entry| {
+            entry.open > 0
+                || rate.is_some_and(|rate| {
+                    let elapsed = now.duration_since(entry.refilled).as_secs_f64();
+                    entry.tokens + elapsed * rate < burst
+                })
+        });
+        state.pruned = Some(now);
+    }
+}
+
+/// An open stream, counted against the limits while it lives.
+pub(crate) struct Permit {
+    limits: Arc<StreamLimits>,
+    client: IpAddr,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.limits.release(self.client);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(cfg: StreamLimitsConfig) -> Arc<StreamLimits> {
+        Arc::new(StreamLimits::new(cfg, &Registry::new()))
+    }
+
+    #[test]
+    fn caps_open_streams_per_client_and_overall() {
+        let limits = limits(StreamLimitsConfig {
+            max_connections: Some(3),
+            max_client_connections: Some(2),
+            ..Default::default()
+        });
+        let (a, b): (IpAddr, IpAddr) = ("10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap());
+        let first = limits.acquire(a).unwrap();
+        let _second = limits.acquire(a).unwrap();
+        assert!(matches!(limits.acquire(a), Err(Rejected::ClientFull)));
+        let _third = limits.acquire(b).unwrap();
+        assert!(matches!(limits.acquire(b), Err(Rejected::Full)));
+        drop(first);
+        let _fourth = limits.acquire(a).unwrap();
+        assert_eq!(limits.open.get(), 3);
+        let rejected = |reason| limits.rejected.with_label_values(&[reason]).get();
+        assert_eq!(
+            (rejected("client_connections"), rejected("connections")),
+            (1, 1)
+        );
+    }
+
+    #[test]
+    fn limits_the_connect_rate_per_client() {
+        let limits = limits(StreamLimitsConfig {
+            client_connect_rate: Some(2.0),
+            client_connect_burst: 2,
+            ..Default::default()
+        });
+        let client: IpAddr = "10.0.0.1".parse().unwrap();
+        let start = Instant::now();
+        assert!(limits.admit(client, start).is_ok());
+        assert!(limits.admit(client, start).is_ok());
+        assert!(matches!(
+            limits.admit(client, start),
+            Err(Rejected::TooFast(1))
+        ));
+        assert!(limits
+            .admit(client, start + Duration::from_millis(500))
+            .is_ok());
+        assert!(limits.admit("10.0.0.2".parse().unwrap(), start).is_ok());
+    }
+
+    #[test]
+    fn forgets_clients_refused_for_their_rate() {
+        let limits = limits(StreamLimitsConfig {
+            client_connect_rate: Some(1.0),
+            client_connect_burst: 1,
+            ..Default::default()
+        });
+        let (a, b): (IpAddr, IpAddr) = ("10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap());
+        let start = Instant::now();
+        limits.admit(a, start).unwrap();
+        limits.release(a);
+        assert!(matches!(limits.admit(a, start), Err(Rejected::TooFast(1))));
+        assert!(limits.state.lock().unwrap().clients.contains_key(&a));
+        limits.admit(b, start + Duration::from_secs(5)).unwrap();
+        assert!(!limits.state.lock().unwrap().clients.contains_key(&a));
+    }
+}
@@ -0,0 +1,188 @@
+//! The OpenMetrics text format, served at `GET /metrics` to scrapers that
+//! ask for it with `Accept: application/openmetrics-text`. The Prometheus
+//! text format stays the default.
+//!
+//! Counter families drop their `_total` suffix, which only their samples
+//! carry, and the exposition ends with `# EOF` so a truncated scrape is
+//! told apart from a complete one.
+
+use std::fmt::Write;
+
+use prometheus::proto::{LabelPair, MetricFamily, MetricType};
+
+pub(crate) const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Whether an `Accept` header takes OpenMetrics, as Prometheus sends when
+/// scraping with it enabled.
+pub(crate) fn accepted(accept: &str) -> bool {
+    accept
+        .split(',')
+        .any(|media| media.trim().starts_with("application/openmetrics-text"))
+}
+
+pub(crate) fn encode(families: &[MetricFamily]) -> String {
+    let mut out = String::new();
+    for family in families {
+        let kind = family.get_field_type();
+        let name = match kind {
+            MetricType::COUNTER => family
+                .get_name()
+                .strip_suffix("_total")
+                .unwrap_or(family.get_name()),
+            _ => family.get_name(),
+        };
+        let type_name = match kind {
+            MetricType::COUNTER => "counter",
+            MetricType::GAUGE => "gauge",
+            MetricType::HISTOGRAM => "histogram",
+            MetricType::SUMMARY => "summary",
+            MetricType::UNTYPED => "unknown",
+        };
+        let _ = writeln!(out, "# TYPE {} {}", name, type_name);
+        if !family.get_help().is_empty() {
+            let _ = writeln!(out, "# HELP {} {}", name, escape(family.get_help()));
+        }
+        for metric in family.get_metric() {
+            let labels = metric.get_label();
+            match kind {
+                MetricType::COUNTER => {
+                    let value = metric.get_counter().get_value();
+                    sample(&mut out, name, "_total", labels, None, value);
+                }
+                MetricType::GAUGE => {
+                    sample(&mut out, name, "", labels, None, metric.get_gauge().get_value());
+                }
+                MetricType::UNTYPED => {
+                    let value = metric.get_untyped().get_value();
+                    sample(&mut out, name, "", labels, None, value);
+                }
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    // Bounds are written as the text format writes them, so
+                    // series keep their `le` whichever format scraped them.
+                    for bucket in histogram.get_bucket() {
+                        let le = ("le", float(bucket.get_upper_bound()));
+                        let count = bucket.get_cumulative_count() as f64;
+                        sample(&mut out, name, "_bucket", labels, Some(le), count);
+                    }
+                    // Prometheus leaves the `+Inf` bucket implicit.
+                    let infinite = histogram
+                        .get_bucket()
+                        .last()
+                        .is_some_and(|bucket| bucket.get_upper_bound().is_infinite());
+                    let count = histogram.get_sample_count() as f64;
+                    if !infinite {
+                        let le = Some(("le", "+Inf".to_string()));
+                        sample(&mut out, name, "_bucket", labels, le, count);
+                    }
+                    sample(&mut out, name, "_count", labels, None, count);
+                    sample(&mut out, name, "_sum", labels, None, histogram.get_sample_sum());
+                }
+                MetricType::SUMMARY => {
+                    let summary = metric.get_summary();
+                    for quantile in summary.get_quantile() {
+                        let q = ("quantile", float(quantile.get_quantile()));
+                        sample(&mut out, name, "", labels, Some(q), quantile.get_value());
+                    }
+                    let count = summary.get_sample_count() as f64;
+                    sample(&mut out, name, "_count", labels, None, count);
+                    sample(&mut out, name, "_sum", labels, None, summary.get_sample_sum());
+                }
+            }
+        }
+    }
+    out.push_str("# EOF\n");
+    out
+}
+
+/// One sample line, with `extra` after the series' labels.
+fn sample(
+    out: &mut String,
+    name: &str,
+    suffix: &str,
+    labels: &[LabelPair],
+    extra: Option<(&str, String)>,
+    value: f64,
+) {
+    let mut pairs: Vec<String> = labels
+        .iter()
+        .map(|pair| format!("{}=\"{}\"", pair.get_name(), escape(pair.get_value())))
+        .collect();
+    if let Some((name, value)) = extra {
+        pairs.push(format!("{}=\"{}\"", name, value));
+    }
+    let _ = if pairs.is_empty() {
+        writeln!(out, "{}{} {}", name, suffix, float(value))
+    } else {
+        writeln!(out, "{}{}{{{}}} {}", name, suffix, pairs.join(","), float(value))
+    };
+}
+
+fn float(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".into()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.into()
+    } else {
+        value.to_string()
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::{HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry};
+
+    #[test]
+    fn encodes_counters_gauges_and_histograms() {
+        let registry = Registry::new();
+        let events =
+            IntCounterVec::new(Opts::new("events_total", "events \"seen\""), &["venue"]).unwrap();
+        let depth = IntGauge::new("queue_depth", "queued").unwrap();
+        let latency = HistogramVec::new(
+            HistogramOpts::new("latency_ms", "latency").buckets(vec![1.0, 10.0]),
+            &["venue"],
+        )
+        .unwrap();
+        registry.register(Box::new(events.clone())).unwrap();
+        registry.register(Box::new(depth.clone())).unwrap();
+        registry.register(Box::new(latency.clone())).unwrap();
+        events.with_label_values(&["binance_spot"]).inc_by(3);
+        depth.set(7);
+        for ms in [0.5, 5.0, 50.0] {
+            latency.with_label_values(&["binance_spot"]).observe(ms);
+        }
+
+        let text = encode(&registry.gather());
+        let expected = r#"# TYPE events counter
+# HELP events events \"seen\"
+events_total{venue="binance_spot"} 3
+# TYPE latency_ms histogram
+# HELP latency_ms latency
+latency_ms_bucket{venue="binance_spot",le="1"} 1
+latency_ms_bucket{venue="binance_spot",le="10"} 2
+latency_ms_bucket{venue="binance_spot",le="+Inf"} 3
+latency_ms_count{venue="binance_spot"} 3
+latency_ms_sum{venue="binance_spot"} 55.5
+# TYPE queue_depth gauge
+# HELP queue_depth queued
+queue_depth 7
+# EOF
+"#;
+        assert_eq!(text, expected);
+    }
+
+    #[test]
+    fn openmetrics_is_served_when_asked_for() {
+        assert!(accepted(
+            "application/openmetrics-text;version=1.0.0,text/plain;version=0.0.4;q=0.5"
+        ));
+        assert!(!accepted("text/plain;version=0.0.4"));
+    }
+}
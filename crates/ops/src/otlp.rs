@@ -0,0 +1,294 @@
+//! Pushes the ops registry to an OpenTelemetry collector over OTLP/gRPC, for
+//! deployments standardized on a collector rather than Prometheus scraping.
+//!
+//! Counters become cumulative monotonic sums, gauges and untyped metrics
+//! gauges, and histograms and summaries keep their shape. Each export sends
+//! every series as it stands, so a failed export loses nothing but its
+//! timestamp.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ingest_core::{config::OtlpConfig, error::IngestError};
+use prometheus::{proto::MetricType, Registry};
+use tonic::{metadata::MetadataMap, transport::Endpoint, Request};
+
+use proto::collector::metrics::v1::{
+    metrics_service_client::MetricsServiceClient, ExportMetricsServiceRequest,
+};
+use proto::common::v1::{any_value, AnyValue, InstrumentationScope, KeyValue};
+use proto::metrics::v1::{
+    metric, number_data_point, summary_data_point::ValueAtQuantile, AggregationTemporality, Gauge,
+    Histogram, HistogramDataPoint, Metric, NumberDataPoint, ResourceMetrics, ScopeMetrics, Sum,
+    Summary, SummaryDataPoint,
+};
+use proto::resource::v1::Resource;
+
+#[allow(clippy::all)]
+mod proto {
+    pub mod common {
+        pub mod v1 {
+            tonic::include_proto!("opentelemetry.proto.common.v1");
+        }
+    }
+    pub mod resource {
+        pub mod v1 {
+            tonic::include_proto!("opentelemetry.proto.resource.v1");
+        }
+    }
+    pub mod metrics {
+        pub mod v1 {
+            tonic::include_proto!("opentelemetry.proto.metrics.v1");
+        }
+    }
+    pub mod collector {
+        pub mod metrics {
+            pub mod v1 {
+                tonic::include_proto!("opentelemetry.proto.collector.metrics.v1");
+            }
+        }
+    }
+}
+
+/// Name of the instrumentation scope exported metrics belong to.
+const SCOPE: &str = "ingest-ops";
+
+pub struct OtlpExporter {
+    cfg: OtlpConfig,
+    registry: Registry,
+    client: MetricsServiceClient<tonic::transport::Channel>,
+    headers: MetadataMap,
+    /// When the exported cumulative series started counting.
+    started: u64,
+}
+
+impl OtlpExporter {
+    /// Connects lazily, so a collector that is down at startup only fails
+    /// the exports until it is up. Fails on an invalid endpoint or header.
+    pub fn new(cfg: &OtlpConfig, registry: Registry) -> Result<Self, IngestError> {
+        let invalid = |e: &dyn std::fmt::Display| IngestError::Validation(format!("otlp: {}", e));
+        let channel = Endpoint::from_shared(cfg.endpoint.clone())
+            .map_err(|e| invalid(&e))?
+            .connect_lazy();
+        let mut headers = MetadataMap::new();
+        for (name, value) in &cfg.headers {
+            let name: tonic::metadata::MetadataKey<_> = name.parse().map_err(|e| invalid(&e))?;
+            headers.insert(name, value.parse().map_err(|e| invalid(&e))?);
+        }
+        Ok(Self {
+            cfg: cfg.clone(),
+            registry,
+            client: MetricsServiceClient::new(channel),
+            headers,
+            started: unix_nanos(SystemTime::now()),
+        })
+    }
+
+    /// Export every `interval_secs`, for as long as the task runs.
+    pub async fn run(mut self) {
+        let mut tick = tokio::time::interval(Duration::from_secs(self.cfg.interval_secs.max(1)));
+        loop {
+            tick.tick().await;
+            if let Err(e) = self.export().await {
+                tracing::warn!("otlp metrics export to {} failed: {}", self.cfg.endpoint, e);
+            }
+        }
+    }
+
+    pub async fn export(&mut self) -> Result<(), tonic::Status> {
+        let metrics = convert(&self.registry, self.started, unix_nanos(SystemTime::now()));
+        let request = ExportMetricsServiceRequest {
+            resource_metrics: vec![ResourceMetrics {
+                resource: Some(Resource {
+                    attributes: vec![attribute("service.name", &self.cfg.service_name)],
+                    dropped_attributes_count: 0,
+                }),
+                scope_metrics: vec![ScopeMetrics {
+                    scope: Some(InstrumentationScope {
+                        name: SCOPE.into(),
+                        version: env!("CARGO_PKG_VERSION").into(),
+                        ..Default::default()
+                    }),
+                    metrics,
+                    schema_url: String::new(),
+                }],
+                schema_url: String::new(),
+            }],
+        };
+        let mut request = Request::new(request);
+        *request.metadata_mut() = self.headers.clone();
+        let response = self.client.export(request).await?.into_inner();
+        if let Some(partial) = response
+            .partial_success
+            .filter(|p| p.rejected_data_points > 0)
+        {
+            tracing::warn!(
+                "otlp collector rejected {} data points: {}",
+                partial.rejected_data_points,
+                partial.error_message
+            );
+        }
+        Ok(())
+    }
+}
+
+fn unix_nanos(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_nanos() as u64)
+}
+
+fn attribute(key: &str, value: &str) -> KeyValue {
+    KeyValue {
+        key: key.into(),
+        value: Some(AnyValue {
+            value: Some(any_value::Value::StringValue(value.into())),
+        }),
+    }
+}
+
+/// The metrics of `registry` as of `now`, counting from `start`.
+fn convert(registry: &Registry, start: u64, now: u64) -> Vec<Metric> {
+    registry
+        .gather()
+        .iter()
+        .map(|family| {
+            let series = family.get_metric();
+            let attributes = |i: usize| -> Vec<KeyValue> {
+                series[i]
+                    .get_label()
+                    .iter()
+                    .map(|label| attribute(label.get_name(), label.get_value()))
+                    .collect()
+            };
+            let number = |i: usize, value: f64| NumberDataPoint {
+                attributes: attributes(i),
+                start_time_unix_nano: start,
+                time_unix_nano: now,
+                value: Some(number_data_point::Value::AsDouble(value)),
+                flags: 0,
+            };
+            let data = match family.get_field_type() {
+                MetricType::COUNTER => metric::Data::Sum(Sum {
+                    data_points: (0..series.len())
+                        .map(|i| number(i, series[i].get_counter().get_value()))
+                        .collect(),
+                    aggregation_temporality: AggregationTemporality::Cumulative as i32,
+                    is_monotonic: true,
+                }),
+                MetricType::GAUGE => metric::Data::Gauge(Gauge {
+                    data_points: (0..series.len())
+                        .map(|i| number(i, series[i].get_gauge().get_value()))
+                        .collect(),
+                }),
+                MetricType::UNTYPED => metric::Data::Gauge(Gauge {
+                    data_points: (0..series.len())
+                        .map(|i| number(i, series[i].get_untyped().get_value()))
+                        .collect(),
+                }),
+                MetricType::HISTOGRAM => metric::Data::Histogram(Histogram {
+                    data_points: (0..series.len())
+                        .map(|i| {
+                            let histogram = series[i].get_histogram();
+                            // Prometheus buckets count cumulatively, OTLP ones
+                            // each count their own range, plus one above all.
+                            let mut below = 0;
+                            let mut bucket_counts = Vec::new();
+                            let mut explicit_bounds = Vec::new();
+                            for bucket in histogram.get_bucket() {
+                                if bucket.get_upper_bound().is_infinite() {
+                                    continue;
+                                }
+                                bucket_counts.push(bucket.get_cumulative_count() - below);
+                                explicit_bounds.push(bucket.get_upper_bound());
+                                below = bucket.get_cumulative_count();
+                            }
+                            bucket_counts.push(histogram.get_sample_count() - below);
+                            HistogramDataPoint {
+                                attributes: attributes(i),
+                                start_time_unix_nano: start,
+                                time_unix_nano: now,
+                                count: histogram.get_sample_count(),
+                                sum: Some(histogram.get_sample_sum()),
+                                bucket_counts,
+                                explicit_bounds,
+                                ..Default::default()
+                            }
+                        })
+                        .collect(),
+                    aggregation_temporality: AggregationTemporality::Cumulative as i32,
+                }),
+                MetricType::SUMMARY => metric::Data::Summary(Summary {
+                    data_points: (0..series.len())
+                        .map(|i| {
+                            let summary = series[i].get_summary();
+                            SummaryDataPoint {
+                                attributes: attributes(i),
+                                start_time_unix_nano: start,
+                                time_unix_nano: now,
+                                count: summary.get_sample_count(),
+                                sum: summary.get_sample_sum(),
+                                quantile_values: summary
+                                    .get_quantile()
+                                    .iter()
+                                    .map(|q| ValueAtQuantile {
+                                        quantile: q.get_quantile(),
+                                        value: q.get_value(),
+                                    })
+                                    .collect(),
+                                flags: 0,
+                            }
+                        })
+                        .collect(),
+                }),
+            };
+            Metric {
+                name: family.get_name().into(),
+                description: family.get_help().into(),
+                unit: String::new(),
+                data: Some(data),
+                metadata: Vec::new(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::{HistogramOpts, HistogramVec, IntCounterVec, Opts};
+
+    #[test]
+    fn converts_counters_and_histograms() {
+        let registry = Registry::new();
+        let events = IntCounterVec::new(Opts::new("events_total", "events"), &["venue"]).unwrap();
+        let latency = HistogramVec::new(
+            HistogramOpts::new("latency_ms", "latency").buckets(vec![1.0, 10.0]),
+            &["venue"],
+        )
+        .unwrap();
+        registry.register(Box::new(events.clone())).unwrap();
+        registry.register(Box::new(latency.clone())).unwrap();
+        events.with_label_values(&["binance_spot"]).inc_by(3);
+        for ms in [0.5, 5.0, 50.0, 60.0] {
+            latency.with_label_values(&["binance_spot"]).observe(ms);
+        }
+
+        let metrics = convert(&registry, 1, 2);
+        assert_eq!(metrics.len(), 2);
+        let Some(metric::Data::Sum(sum)) = &metrics[0].data else {
+            panic!("events_total is not a sum: {:?}", metrics[0]);
+        };
+        assert!(sum.is_monotonic);
+        let point = &sum.data_points[0];
+        assert_eq!(point.value, Some(number_data_point::Value::AsDouble(3.0)));
+        assert_eq!(point.attributes, [attribute("venue", "binance_spot")]);
+        assert_eq!((point.start_time_unix_nano, point.time_unix_nano), (1, 2));
+
+        let Some(metric::Data::Histogram(histogram)) = &metrics[1].data else {
+            panic!("latency_ms is not a histogram: {:?}", metrics[1]);
+        };
+        let point = &histogram.data_points[0];
+        assert_eq!(point.count, 4);
+        assert_eq!(point.explicit_bounds, [1.0, 10.0]);
+        assert_eq!(point.bucket_counts, [1, 1, 2]);
+    }
+}
@@ -0,0 +1,149 @@
+//! Probes under `/healthz` with the semantics Kubernetes gives its own:
+//! `startup` answers once the engine has started, `live` while the server's
+//! event loop keeps turning, and `ready` while every component is healthy.
+//! `active` answers while the instance is the active one of a hot-standby
+//! pair, which the other instance polls as the heartbeat.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use api::EventBus;
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Serialize;
+
+/// How often the heartbeat ticks.
+const BEAT_INTERVAL: Duration = Duration::from_secs(1);
+/// How late the heartbeat may be before the server counts as not live.
+const LIVENESS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Marks the engine as started, for `GET /healthz/startup`.
+#[derive(Clone, Default)]
+pub struct Startup(Arc<AtomicBool>);
+
+impl Startup {
+    /// Answer `GET /healthz/startup` with 200 from now on.
+    pub fn complete(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Marks the instance as the active one, for `GET /healthz/active`, and
+/// the epoch of its latest promotion.
+#[derive(Clone)]
+pub struct Active(Arc<Role>);
+
+struct Role {
+    active: AtomicBool,
+    epoch: AtomicU64,
+}
+
+impl Active {
+    pub fn new(active: bool) -> Self {
+        Self(Arc::new(Role {
+            active: AtomicBool::new(active),
+            epoch: AtomicU64::new(0),
+        }))
+    }
+
+    /// Answer `GET /healthz/active` with 200 from now on, as promoted at
+    /// `epoch`.
+    pub fn promote(&self, epoch: u64) {
+        self.0.epoch.store(epoch, Ordering::Relaxed);
+        self.0.active.store(true, Ordering::Relaxed);
+    }
+
+    /// Answer `GET /healthz/active` with 503 again.
+    pub fn demote(&self) {
+        self.0.active.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.0.active.load(Ordering::Relaxed)
+    }
+
+    pub fn epoch(&self) -> u64 {
+        self.0.epoch.load(Ordering::Relaxed)
+    }
+}
+
+/// The body of `GET /healthz/active`.
+#[derive(Serialize)]
+pub(crate) struct Beat {
+    active: bool,
+    /// Sequence of the latest published event.
+    sequence: u64,
+    /// Epoch of the latest promotion of the instance.
+    epoch: u64,
+}
+
+/// A tick the server's event loop makes every [`BEAT_INTERVAL`], late
+/// when the loop is blocked or starved.
+pub(crate) struct Heartbeat {
+    started: Instant,
+    /// Milliseconds from `started` to the last tick.
+    last: AtomicU64,
+}
+
+impl Heartbeat {
+    pub(crate) fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            last: AtomicU64::new(0),
+        }
+    }
+
+    /// Tick for as long as the future is polled.
+    pub(crate) async fn run(&self) {
+        let mut tick = tokio::time::interval(BEAT_INTERVAL);
+        loop {
+            tick.tick().await;
+            let ms = self.started.elapsed().as_millis() as u64;
+            self.last.store(ms, Ordering::Relaxed);
+        }
+    }
+
+    /// Time since the last tick.
+    fn age(&self) -> Duration {
+        let last = Duration::from_millis(self.last.load(Ordering::Relaxed));
+        self.started.elapsed().saturating_sub(last)
+    }
+}
+
+pub(crate) async fn startup(State(startup): State<Startup>) -> (StatusCode, &'static str) {
+    if startup.is_complete() {
+        (StatusCode::OK, "ok")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "starting")
+    }
+}
+
+pub(crate) async fn live(State(heartbeat): State<Arc<Heartbeat>>) -> (StatusCode, String) {
+    let age = heartbeat.age();
+    if age <= LIVENESS_TIMEOUT {
+        (StatusCode::OK, "ok".to_string())
+    } else {
+        let body = format!("event loop stalled for {:.1}s", age.as_secs_f64());
+        (StatusCode::SERVICE_UNAVAILABLE, body)
+    }
+}
+
+pub(crate) async fn active(
+    State((active, bus)): State<(Active, EventBus)>,
+) -> (StatusCode, Json<Beat>) {
+    let beat = Beat {
+        active: active.is_active(),
+        sequence: bus.last_sequence(),
+        epoch: active.epoch(),
+    };
+    let status = if beat.active {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(beat))
+}
@@ -0,0 +1,203 @@
+//! CPU and heap profiles under `/debug/pprof`, served with the admin routes.
+//!
+//! With the `pprof` feature, `GET /debug/pprof/profile?seconds=30` samples
+//! the CPU for that long and answers a pprof protobuf profile. With the
+//! `jemalloc` feature, `GET /debug/pprof/heap` dumps a jemalloc heap profile
+//! for `jeprof`, provided the process allocates through jemalloc with
+//! profiling enabled.
+
+use axum::Router;
+
+pub(crate) fn routes() -> Router {
+    let routes = Router::new();
+    #[cfg(feature = "pprof")]
+    let routes = routes.route("/profile", axum::routing::get(cpu::profile));
+    #[cfg(feature = "jemalloc")]
+    let routes = routes.route("/heap", axum::routing::get(heap::profile));
+    routes
+}
+
+#[cfg(feature = "pprof")]
+mod cpu {
+    use std::time::Duration;
+
+    use axum::{
+        extract::Query,
+        http::{header, StatusCode},
+        response::IntoResponse,
+    };
+    use pprof::protos::Message;
+    use serde::Deserialize;
+    use tokio::sync::Mutex;
+
+    use crate::control::internal;
+
+    const DEFAULT_SECONDS: u64 = 30;
+    const MAX_SECONDS: u64 = 300;
+    /// Samples per second, off the round numbers periodic work runs at.
+    const DEFAULT_FREQUENCY: i32 = 99;
+    const MAX_FREQUENCY: i32 = 1000;
+    /// Frames of the signal handler and libraries unwinding is unsafe in.
+    const BLOCKLIST: &[&str] = &["libc", "libgcc", "pthread", "vdso"];
+
+    /// Held while a profile runs, as the profiler owns the process-wide
+    /// `SIGPROF` timer.
+    static RUNNING: Mutex<()> = Mutex::const_new(());
+
+    #[derive(Deserialize)]
+    pub(super) struct ProfileQuery {
+        seconds: Option<u64>,
+        frequency: Option<i32>,
+    }
+
+    impl ProfileQuery {
+        fn duration(&self) -> Result<Duration, (StatusCode, String)> {
+            match self.seconds.unwrap_or(DEFAULT_SECONDS) {
+                seconds @ 1..=MAX_SECONDS => Ok(Duration::from_secs(seconds)),
+                seconds => Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("seconds must be 1 to {}, not {}", MAX_SECONDS, seconds),
+                )),
+            }
+        }
+
+        fn frequency(&self) -> Result<i32, (StatusCode, String)> {
+            match self.frequency.unwrap_or(DEFAULT_FREQUENCY) {
+                frequency @ 1..=MAX_FREQUENCY => Ok(frequency),
+                frequency => Err((
+                    StatusCode::BAD_REQUEST,
+                    format!(
+                        "frequency must be 1 to {}, not {}",
+                        MAX_FREQUENCY, frequency
+                    ),
+                )),
+            }
+        }
+    }
+
+    pub(super) async fn profile(
+        Query(query): Query<ProfileQuery>,
+    ) -> Result<impl IntoResponse, (StatusCode, String)> {
+        let duration = query.duration()?;
+        let frequency = query.frequency()?;
+        let Ok(_running) = RUNNING.try_lock() else {
+            return Err((
+                StatusCode::CONFLICT,
+                "a cpu profile is already running".into(),
+            ));
+        };
+        let profile = tokio::task::spawn_blocking(move || sample(duration, frequency))
+            .await
+            .map_err(internal)?
+            .map_err(internal)?;
+        Ok((
+            [
+                (header::CONTENT_TYPE, "application/octet-stream"),
+                (
+                    header::CONTENT_DISPOSITION,
+                    "attachment; filename=\"profile.pb\"",
+                ),
+            ],
+            profile.encode_to_vec(),
+        ))
+    }
+
+    fn sample(duration: Duration, frequency: i32) -> pprof::Result<pprof::protos::Profile> {
+        let guard = pprof::ProfilerGuardBuilder::default()
+            .frequency(frequency)
+            .blocklist(BLOCKLIST)
+            .build()?;
+        std::thread::sleep(duration);
+        guard.report().build()?.pprof()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn query(seconds: Option<u64>, frequency: Option<i32>) -> ProfileQuery {
+            ProfileQuery { seconds, frequency }
+        }
+
+        #[test]
+        fn bounds_duration_and_frequency() {
+            assert_eq!(
+                query(None, None).duration().unwrap(),
+                Duration::from_secs(30)
+            );
+            assert_eq!(query(None, None).frequency().unwrap(), 99);
+            assert_eq!(
+                query(Some(5), Some(250)).duration().unwrap(),
+                Duration::from_secs(5)
+            );
+            assert_eq!(query(Some(5), Some(250)).frequency().unwrap(), 250);
+            for seconds in [0, 301] {
+                let (status, _) = query(Some(seconds), None).duration().unwrap_err();
+                assert_eq!(status, StatusCode::BAD_REQUEST);
+            }
+            for frequency in [0, -1, 1001] {
+                let (status, _) = query(None, Some(frequency)).frequency().unwrap_err();
+                assert_eq!(status, StatusCode::BAD_REQUEST);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "jemalloc")]
+mod heap {
+    use std::ffi::CString;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use axum::{
+        http::{header, StatusCode},
+        response::IntoResponse,
+    };
+    use tikv_jemalloc_ctl::{profiling, raw};
+
+    use crate::control::internal;
+
+    /// Numbers the dump files of the process, which jemalloc refuses to
+    /// overwrite.
+    static DUMPS: AtomicU64 = AtomicU64::new(0);
+
+    pub(super) async fn profile() -> Result<impl IntoResponse, (StatusCode, String)> {
+        if !profiling::prof::read().map_err(internal)? {
+            return Err((
+                StatusCode::SERVICE_UNAVAILABLE,
+                "jemalloc heap profiling is not enabled".into(),
+            ));
+        }
+        let body = tokio::task::spawn_blocking(dump)
+            .await
+            .map_err(internal)?
+            .map_err(internal)?;
+        Ok((
+            [
+                (header::CONTENT_TYPE, "application/octet-stream"),
+                (
+                    header::CONTENT_DISPOSITION,
+                    "attachment; filename=\"heap.prof\"",
+                ),
+            ],
+            body,
+        ))
+    }
+
+    /// Have jemalloc write its heap profile to a temporary file, and read
+    /// it back.
+    fn dump() -> Result<Vec<u8>, String> {
+        let path = std::env::temp_dir().join(format!(
+            "ingest-heap-{}-{}.prof",
+            std::process::id(),
+            DUMPS.fetch_add(1, Ordering::Relaxed)
+        ));
+        let name = CString::new(path.to_string_lossy().into_owned()).map_err(|e| e.to_string())?;
+        // SAFETY: `prof.dump` takes a NUL-terminated path, which `name`
+        // outlives the call with.
+        unsafe { raw::write(b"prof.dump\0", name.as_ptr()) }
+            .map_err(|e| format!("cannot dump heap profile: {}", e))?;
+        let body = std::fs::read(&path).map_err(|e| e.to_string());
+        let _ = std::fs::remove_file(&path);
+        body
+    }
+}
@@ -0,0 +1,77 @@
+//! Pushes the ops registry to a Prometheus Pushgateway, for short-lived or
+//! firewalled deployments that cannot be scraped.
+//!
+//! Each push replaces what the engine's group held, with `PUT` to
+//! `/metrics/job/<job>/instance/<instance>/<label>/<value>..`, so series
+//! the engine no longer has do not linger.
+
+use std::time::Duration;
+
+use ingest_core::{config::PushgatewayConfig, error::IngestError};
+use prometheus::{Encoder, Registry, TextEncoder};
+use reqwest::{header, Client, Url};
+
+pub struct PushgatewayExporter {
+    cfg: PushgatewayConfig,
+    registry: Registry,
+    client: Client,
+    /// The URL of the engine's group.
+    url: Url,
+}
+
+impl PushgatewayExporter {
+    /// Fails on a URL that does not parse or cannot take a path.
+    pub fn new(cfg: &PushgatewayConfig, registry: Registry) -> Result<Self, IngestError> {
+        let invalid = |e: &dyn std::fmt::Display| {
+            IngestError::Validation(format!("pushgateway url {}: {}", cfg.url, e))
+        };
+        let mut url = Url::parse(&cfg.url).map_err(|e| invalid(&e))?;
+        url.path_segments_mut()
+            .map_err(|()| invalid(&"cannot be a base"))?
+            .pop_if_empty()
+            .push("metrics")
+            .extend(
+                cfg.grouping()
+                    .into_iter()
+                    .flat_map(|(name, value)| [name, value]),
+            );
+        let client = Client::builder()
+            .timeout(Duration::from_secs(cfg.interval_secs.max(1)))
+            .build()
+            .map_err(|e| invalid(&e))?;
+        Ok(Self {
+            cfg: cfg.clone(),
+            registry,
+            client,
+            url,
+        })
+    }
+
+    /// Push every `interval_secs`, for as long as the task runs.
+    pub async fn run(self) {
+        let mut tick = tokio::time::interval(Duration::from_secs(self.cfg.interval_secs.max(1)));
+        loop {
+            tick.tick().await;
+            if let Err(e) = self.push().await {
+                tracing::warn!("pushgateway push to {} failed: {}", self.url, e);
+            }
+        }
+    }
+
+    pub async fn push(&self) -> Result<(), String> {
+        let encoder = TextEncoder::new();
+        let mut body = Vec::new();
+        encoder
+            .encode(&self.registry.gather(), &mut body)
+            .map_err(|e| e.to_string())?;
+        self.client
+            .put(self.url.clone())
+            .header(header::CONTENT_TYPE, encoder.format_type())
+            .body(body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+//! Server-sent events at `GET /events`, for browser dashboards.
+//!
+//! Query parameters narrow the stream to what a dashboard renders. Each takes
+//! a comma-separated list, and symbols may be globs such as `BTC*`:
+//!
+//! ```text
+//! GET /events?venue=binance&symbol=BTC-USD,ETH-USD&kind=trade
+//! ```
+//!
+//! Events are sent as JSON, named by their kind. Clients subscribe to the
+//! bus as `sse`, so `[bus.subscribers.sse]` sizes their queues. `GET /ws`
+//! takes the same parameters.
+//!
+//! So that a refreshed dashboard keeps its context, `replay` starts the
+//! stream with up to that many recent matching events, and `since` with
+//! those received at or after an RFC 3339 time, before the live ones. They
+//! come out of the bus replay buffers and, for older events, the WAL:
+//!
+//! ```text
+//! GET /events?symbol=BTC-USD&replay=500
+//! GET /events?kind=trade&since=2026-01-02T03:04:05Z
+//! ```
+//!
+//! When the engine shuts down, the stream ends
+//! with a `shutdown` event once the client has been sent what was queued.
+//!
+//! A `: keepalive` comment goes out every `keepalive_secs` of `[ops.sse]`
+//! while no events do. Writing it fails once the client has gone, closed
+//! tab or dropped connection alike, which ends the stream and takes its
+//! subscription off the bus.
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use api::EventBus;
+use axum::{
+    extract::{ConnectInfo, Query, State},
+    http::StatusCode,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+};
+use chrono::{DateTime, Utc};
+use ingest_core::event::EventKind;
+use serde::Deserialize;
+use tokio_stream::StreamExt;
+
+use crate::limits::StreamLimits;
+
+/// Bus subscriber name of SSE clients.
+const SUBSCRIBER: &str = "sse";
+/// Events a client may ask to start with, and those it gets for `since`
+/// alone.
+const MAX_REPLAY: usize = 10_000;
+
+#[derive(Deserialize)]
+pub(crate) struct EventsQuery {
+    venue: Option<String>,
+    symbol: Option<String>,
+    kind: Option<String>,
+}
+
+impl EventsQuery {
+    /// Whether no parameter was given at all.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.venue.is_none() && self.symbol.is_none() && self.kind.is_none()
+    }
+
+    pub(crate) fn venues(&self) -> impl Iterator<Item = &str> {
+        list(&self.venue)
+    }
+
+    pub(crate) fn symbols(&self) -> impl Iterator<Item = &str> {
+        list(&self.symbol)
+    }
+
+    pub(crate) fn kinds(&self) -> Result<Vec<EventKind>, (StatusCode, String)> {
+        list(&self.kind)
+            .map(|kind| {
+                serde_json::from_value::<EventKind>(kind.into()).map_err(|_| {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        format!("unknown event kind {}", kind),
+                    )
+                })
+            })
+            .collect()
+    }
+}
+
+/// What `GET /events` starts with, taken apart from [`EventsQuery`] as
+/// `GET /ws` does not replay.
+#[derive(Deserialize)]
+pub(crate) struct ReplayQuery {
+    replay: Option<usize>,
+    since: Option<DateTime<Utc>>,
+}
+
+impl ReplayQuery {
+    fn last(&self) -> Result<Option<usize>, (StatusCode, String)> {
+        match (self.replay, self.since) {
+            (Some(replay), _) if replay > MAX_REPLAY => Err((
+                StatusCode::BAD_REQUEST,
+                format!("replay must be at most {}, not {}", MAX_REPLAY, replay),
+            )),
+            (Some(replay), _) => Ok(Some(replay)),
+            (None, Some(_)) => Ok(Some(MAX_REPLAY)),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+fn list(values: &Option<String>) -> impl Iterator<Item = &str> {
+    values
+        .iter()
+        .flat_map(|values| values.split(','))
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+pub(crate) async fn events(
+    State((bus, limits, keepalive)): State<(EventBus, Arc<StreamLimits>, Duration)>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    Query(query): Query<EventsQuery>,
+    Query(replay): Query<ReplayQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let kinds = query.kinds()?;
+    let last = replay.last()?;
+    let permit = match limits.acquire(client.ip()) {
+        Ok(permit) => permit,
+        Err(rejected) => return Ok(rejected.into_response()),
+    };
+    let mut subscription = bus
+        .subscription()
+        .name(SUBSCRIBER)
+        .venues(query.venues())
+        .symbols(query.symbols())
+        .kinds(kinds);
+    if let Some(last) = last {
+        subscription = subscription.replay_last(last);
+    }
+    if let Some(since) = replay.since {
+        subscription = subscription.replay_since(since);
+    }
+    let subscription = subscription.start().await;
+    // The stream holds the permit until the client goes away.
+    let events = subscription.filter_map(move |event| {
+        let _permit = &permit;
+        let sse = Event::default()
+            .event(event.kind.as_str())
+            .json_data(&event);
+        match sse {
+            Ok(sse) => Some(Ok::<_, Infallible>(sse)),
+            Err(e) => {
+                tracing::warn!("dropping event unencodable for sse: {}", e);
+                None
+            }
+        }
+    });
+    // Checked once the subscription ends, to tell a shutdown from the
+    // client being dropped for lagging.
+    let farewell = tokio_stream::once(()).filter_map(move |()| {
+        bus.is_closed()
+            .then(|| Ok(Event::default().event("shutdown").data("shutting down")))
+    });
+    Ok(Sse::new(events.chain(farewell))
+        .keep_alive(KeepAlive::new().interval(keepalive).text("keepalive"))
+        .into_response())
+}
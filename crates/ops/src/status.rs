@@ -0,0 +1,177 @@
+//! The engine at a glance at `GET /status`: build, uptime, venues, pipeline
+//! stages and sinks in one document, the first page to check on call.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Instant;
+
+use api::control::{ControlHandle, StageStats};
+use axum::{extract::State, http::StatusCode, Json};
+use prometheus::{IntGaugeVec, Opts, Registry};
+use serde::Serialize;
+use sinks::SinkMetrics;
+
+use crate::control::internal;
+use crate::{label_values, AdapterMetrics, EventMetrics};
+
+/// Where `GET /status` gathers its document from.
+pub struct StatusSources {
+    pub build: BuildInfo,
+    pub adapters: AdapterMetrics,
+    pub events: EventMetrics,
+    pub sinks: SinkMetrics,
+    /// Answers the configured venues and the pipeline stages.
+    pub control: ControlHandle,
+}
+
+/// What the engine was built from, for `GET /status`, the `build_info`
+/// metric and `ingestd --version`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct BuildInfo {
+    /// Version of the engine.
+    pub version: String,
+    /// The commit built, if known.
+    pub commit: Option<String>,
+    /// When it was built, RFC 3339.
+    pub built_at: Option<String>,
+    /// The Cargo features enabled.
+    pub features: Vec<String>,
+}
+
+impl BuildInfo {
+    /// Register a `build_info` gauge of 1 labelled with the version, commit,
+    /// build time and features, to join onto other series.
+    pub fn register(&self, registry: &Registry) -> prometheus::Result<()> {
+        let gauge = IntGaugeVec::new(
+            Opts::new("build_info", "What the engine was built from, always 1"),
+            &["version", "commit", "built_at", "features"],
+        )?;
+        gauge
+            .with_label_values(&[
+                self.version.as_str(),
+                self.commit.as_deref().unwrap_or(""),
+                self.built_at.as_deref().unwrap_or(""),
+                &self.features.join(","),
+            ])
+            .set(1);
+        registry.register(Box::new(gauge))
+    }
+}
+
+/// `0.1.0 (4f2c9e1d0a3b, built 2026-01-02T03:04:05Z, features: kafka, wasm)`.
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (", self.version)?;
+        if let Some(commit) = &self.commit {
+            write!(f, "{commit}, ")?;
+        }
+        if let Some(built_at) = &self.built_at {
+            write!(f, "built {built_at}, ")?;
+        }
+        match self.features.as_slice() {
+            [] => write!(f, "no features)"),
+            features => write!(f, "features: {})", features.join(", ")),
+        }
+    }
+}
+
+pub(crate) struct StatusSource {
+    pub(crate) sources: StatusSources,
+    pub(crate) started: Instant,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Status {
+    #[serde(flatten)]
+    build: BuildInfo,
+    uptime_secs: u64,
+    /// Events per second across every venue.
+    events_per_second: f64,
+    venues: Vec<VenueStatus>,
+    pipeline: Vec<StageStats>,
+    sinks: Vec<SinkStatus>,
+}
+
+#[derive(Debug, Serialize)]
+struct VenueStatus {
+    name: String,
+    paused: bool,
+    connected: bool,
+    reconnects: u64,
+    /// Restarts by the watchdog.
+    restarts: u64,
+    /// Failures of the adapter task.
+    failures: u64,
+    circuit_open: bool,
+    last_message_age_secs: f64,
+    /// Symbols seen since start.
+    symbols: usize,
+    events_per_second: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct SinkStatus {
+    name: String,
+    /// Events waiting to be delivered, the sink's lag.
+    queue_depth: i64,
+    delivered: u64,
+    errors: u64,
+    dropped: u64,
+}
+
+pub(crate) async fn status(
+    State(source): State<Arc<StatusSource>>,
+) -> Result<Json<Status>, (StatusCode, String)> {
+    let sources = &source.sources;
+    let configured = sources.control.venues().await.map_err(internal)?;
+    let pipeline = sources.control.pipeline_stats().await.map_err(internal)?;
+    let adapters = sources.adapters.states();
+    let activity = sources.events.activity();
+
+    let venues: Vec<VenueStatus> = configured
+        .into_iter()
+        .map(|venue| {
+            let adapter = adapters.get(&venue.name);
+            let activity = activity.get(&venue.name);
+            VenueStatus {
+                paused: venue.paused,
+                connected: adapter.is_some_and(|adapter| adapter.connected),
+                reconnects: adapter.map_or(0, |adapter| adapter.reconnects),
+                restarts: adapter.map_or(0, |adapter| adapter.restarts),
+                failures: adapter.map_or(0, |adapter| adapter.failures),
+                circuit_open: adapter.is_some_and(|adapter| adapter.circuit_open),
+                last_message_age_secs: adapter.map_or(0.0, |adapter| adapter.last_message_age_secs),
+                symbols: activity.map_or(0, |activity| activity.symbols),
+                events_per_second: activity.map_or(0.0, |activity| activity.events_per_second),
+                name: venue.name,
+            }
+        })
+        .collect();
+
+    let status = Status {
+        build: sources.build.clone(),
+        uptime_secs: source.started.elapsed().as_secs(),
+        events_per_second: venues.iter().map(|venue| venue.events_per_second).sum(),
+        venues,
+        pipeline,
+        sinks: sinks(&sources.sinks),
+    };
+    Ok(Json(status))
+}
+
+/// Every sink with a queue, in order.
+fn sinks(metrics: &SinkMetrics) -> Vec<SinkStatus> {
+    label_values(&metrics.queue_depth, "sink")
+        .into_iter()
+        .map(|name| {
+            let sink = [name.as_str()];
+            SinkStatus {
+                queue_depth: metrics.queue_depth.with_label_values(&sink).get(),
+                delivered: metrics.delivered.with_label_values(&sink).get(),
+                errors: metrics.errors.with_label_values(&sink).get(),
+                dropped: metrics.dropped.with_label_values(&sink).get(),
+                name,
+            }
+        })
+        .collect()
+}
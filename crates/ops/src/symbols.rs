@@ -0,0 +1,59 @@
+//! What the engine thinks it ingests, at `GET /symbols`: the symbols each
+//! venue's adapter subscribed to, configured or discovered, and when each
+//! last delivered an event. `?venue=` narrows the answer to one venue.
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::adapters::{AdapterMetrics, SymbolUpdate};
+
+#[derive(Deserialize)]
+pub(crate) struct SymbolsQuery {
+    venue: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct VenueSymbols {
+    venue: String,
+    /// Whether the symbols were discovered rather than configured.
+    discovered: bool,
+    /// When the adapter subscribed, or null until it has.
+    subscribed_at: Option<DateTime<Utc>>,
+    symbols: Vec<SymbolUpdate>,
+}
+
+pub(crate) async fn symbols(
+    State(adapters): State<AdapterMetrics>,
+    Query(query): Query<SymbolsQuery>,
+) -> Result<Json<Vec<VenueSymbols>>, (StatusCode, String)> {
+    let mut subscriptions = adapters.subscriptions();
+    if let Some(venue) = &query.venue {
+        subscriptions.retain(|name, _| name == venue);
+        if subscriptions.is_empty() {
+            return Err((StatusCode::NOT_FOUND, format!("unknown venue {}", venue)));
+        }
+    }
+    let venues = subscriptions
+        .into_iter()
+        .map(|(venue, subscription)| match subscription {
+            Some(subscription) => VenueSymbols {
+                venue,
+                discovered: subscription.discovered,
+                subscribed_at: Some(subscription.subscribed_at),
+                symbols: subscription.symbols,
+            },
+            None => VenueSymbols {
+                venue,
+                discovered: false,
+                subscribed_at: None,
+                symbols: Vec::new(),
+            },
+        })
+        .collect();
+    Ok(Json(venues))
+}
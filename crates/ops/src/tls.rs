@@ -0,0 +1,92 @@
+//! HTTPS for the ops server, with optional client certificate verification.
+
+use std::sync::Arc;
+
+use axum::{extract::ConnectInfo, Extension, Router};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto,
+    service::TowerToHyperService,
+};
+use ingest_core::{config::TlsConfig, error::IngestError};
+use rustls::{
+    crypto::ring,
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    server::WebPkiClientVerifier,
+    RootCertStore, ServerConfig,
+};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+
+/// Load the certificates and key `cfg` names, failing on any that cannot be
+/// read or do not fit together.
+pub(crate) fn acceptor(cfg: &TlsConfig) -> Result<TlsAcceptor, IngestError> {
+    let invalid = |what: &str, path: &str, e: &dyn std::fmt::Display| {
+        IngestError::Validation(format!("tls {} {}: {}", what, path, e))
+    };
+    let certs = |path: &str| {
+        CertificateDer::pem_file_iter(path)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| invalid("certificates", path, &e))
+    };
+    let chain = certs(&cfg.cert_path)?;
+    let key = PrivateKeyDer::from_pem_file(&cfg.key_path)
+        .map_err(|e| invalid("key", &cfg.key_path, &e))?;
+
+    let provider = Arc::new(ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| IngestError::Validation(format!("tls: {}", e)))?;
+    let builder = match &cfg.client_ca_path {
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in certs(path)? {
+                roots
+                    .add(cert)
+                    .map_err(|e| invalid("client CA", path, &e))?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(roots.into(), provider)
+                .build()
+                .map_err(|e| invalid("client CA", path, &e))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let mut server = builder
+        .with_single_cert(chain, key)
+        .map_err(|e| invalid("certificate", &cfg.cert_path, &e))?;
+    server.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(server)))
+}
+
+/// Serve `app` over TLS on connections accepted from `listener`.
+pub(crate) async fn serve(listener: TcpListener, acceptor: TlsAcceptor, app: Router) {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                tracing::warn!("ops server accept failed: {}", e);
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        // Streaming endpoints tell clients apart by address.
+        let app = app.clone().layer(Extension(ConnectInfo(peer)));
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    tracing::debug!("tls handshake with {} failed: {}", peer, e);
+                    return;
+                }
+            };
+            // Upgrades carry WebSockets.
+            let served = auto::Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), TowerToHyperService::new(app))
+                .await;
+            if let Err(e) = served {
+                tracing::debug!("ops connection from {} failed: {}", peer, e);
+            }
+        });
+    }
+}
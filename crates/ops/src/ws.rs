@@ -0,0 +1,262 @@
+//! WebSocket fan-out at `GET /ws`.
+//!
+//! A client opens the socket and sends a subscription message:
+//!
+//! ```json
+//! {"venues": ["binance_spot"], "symbols": ["BTCUSDT"], "kinds": ["trade"], "encoding": "json"}
+//! ```
+//!
+//! Empty or missing lists match everything, and symbols may be globs such as
+//! `BTC*`. Alternatively the subscription comes from the same query
+//! parameters as `GET /events`, plus `encoding`, and streaming starts right
+//! away:
+//!
+//! ```text
+//! GET /ws?venue=binance_spot&symbol=BTCUSDT,ETHUSDT&kind=trade
+//! ```
+//!
+//! Any later subscription message replaces the current one; frames already
+//! buffered under the old one are still sent. Matching events follow as text
+//! frames for `json` and binary frames for `proto`/`avro`, using the same
+//! encodings as the sinks. Each client has its own bounded send buffer; what
+//! happens when it fills up is set by [`SlowClientPolicy`].
+
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use api::{EventBus, Filter};
+use axum::{
+    extract::{
+        ws::{close_code, CloseFrame, Message, WebSocket},
+        ConnectInfo, Query, State, WebSocketUpgrade,
+    },
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use ingest_core::{
+    config::{Encoding, SlowClientPolicy, WsConfig},
+    event::{EventKind, NormalizedEvent},
+};
+use prometheus::{IntCounter, IntGauge, Registry};
+use serde::Deserialize;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio_stream::StreamExt;
+
+use crate::limits::{Permit, StreamLimits};
+use crate::sse::EventsQuery;
+
+pub(crate) struct WsSource {
+    bus: EventBus,
+    cfg: WsConfig,
+    clients: IntGauge,
+    dropped: IntCounter,
+    slow_disconnects: IntCounter,
+}
+
+impl WsSource {
+    pub(crate) fn new(bus: EventBus, cfg: WsConfig, registry: &Registry) -> Self {
+        let clients = IntGauge::new("ws_clients", "connected websocket clients").unwrap();
+        let dropped = IntCounter::new(
+            "ws_dropped_events_total",
+            "events skipped for websocket clients with a full send buffer",
+        )
+        .unwrap();
+        let slow_disconnects = IntCounter::new(
+            "ws_slow_disconnects_total",
+            "websocket clients disconnected for falling behind",
+        )
+        .unwrap();
+        registry.register(Box::new(clients.clone())).unwrap();
+        registry.register(Box::new(dropped.clone())).unwrap();
+        registry
+            .register(Box::new(slow_disconnects.clone()))
+            .unwrap();
+        Self {
+            bus,
+            cfg,
+            clients,
+            dropped,
+            slow_disconnects,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Subscription {
+    #[serde(default)]
+    venues: HashSet<String>,
+    #[serde(default)]
+    symbols: HashSet<String>,
+    #[serde(default)]
+    kinds: HashSet<EventKind>,
+    #[serde(default)]
+    encoding: Encoding,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct WsQuery {
+    #[serde(flatten)]
+    filter: EventsQuery,
+    encoding: Option<Encoding>,
+}
+
+impl WsQuery {
+    /// The subscription the parameters describe, if any were given.
+    fn subscription(&self) -> Result<Option<Subscription>, (StatusCode, String)> {
+        if self.filter.is_empty() && self.encoding.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Subscription {
+            venues: self.filter.venues().map(String::from).collect(),
+            symbols: self.filter.symbols().map(String::from).collect(),
+            kinds: self.filter.kinds()?.into_iter().collect(),
+            encoding: self.encoding.unwrap_or_default(),
+        }))
+    }
+}
+
+impl Subscription {
+    fn filter(&self) -> Filter {
+        Filter::new()
+            .venues(self.venues.iter().cloned())
+            .symbols(self.symbols.iter().cloned())
+            .kinds(self.kinds.iter().copied())
+    }
+
+    fn frame(&self, event: &NormalizedEvent) -> Option<Message> {
+        match sinks::codec::encode(event, self.encoding) {
+            Ok(bytes) if self.encoding == Encoding::Json => {
+                String::from_utf8(bytes).ok().map(Message::Text)
+            }
+            Ok(bytes) => Some(Message::Binary(bytes)),
+            Err(e) => {
+                tracing::warn!("cannot encode event for websocket client: {}", e);
+                None
+            }
+        }
+    }
+}
+
+/// Why the pump feeding a client stopped.
+enum Stopped {
+    BusClosed,
+    ClientGone,
+    TooSlow,
+    /// A later subscription message could not be parsed.
+    Invalid(String),
+}
+
+pub(crate) async fn upgrade(
+    ws: WebSocketUpgrade,
+    State((source, limits)): State<(Arc<WsSource>, Arc<StreamLimits>)>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    Query(query): Query<WsQuery>,
+) -> Response {
+    let subscription = match query.subscription() {
+        Ok(subscription) => subscription,
+        Err(rejection) => return rejection.into_response(),
+    };
+    match limits.acquire(client.ip()) {
+        Ok(permit) => ws.on_upgrade(move |socket| serve(socket, source, subscription, permit)),
+        Err(rejected) => rejected.into_response(),
+    }
+}
+
+fn parse(text: &str) -> Result<Subscription, String> {
+    serde_json::from_str(text).map_err(|e| format!("invalid subscription: {}", e))
+}
+
+/// Serve the client until it goes away, counted against the stream limits
+/// by `_permit`.
+async fn serve(
+    mut socket: WebSocket,
+    source: Arc<WsSource>,
+    subscription: Option<Subscription>,
+    _permit: Permit,
+) {
+    let subscription = match subscription {
+        Some(subscription) => subscription,
+        None => match socket.recv().await {
+            Some(Ok(Message::Text(text))) => match parse(&text) {
+                Ok(subscription) => subscription,
+                Err(reason) => {
+                    close(socket, close_code::POLICY, reason).await;
+                    return;
+                }
+            },
+            _ => return,
+        },
+    };
+    source.clients.inc();
+    let (tx, mut rx) = mpsc::channel(source.cfg.send_buffer);
+    let mut pump = tokio::spawn(pump(source.clone(), subscription, tx.clone()));
+    let stopped = loop {
+        tokio::select! {
+            frame = rx.recv() => match frame {
+                Some(frame) => {
+                    if socket.send(frame).await.is_err() {
+                        break Stopped::ClientGone;
+                    }
+                }
+                None => break Stopped::BusClosed,
+            },
+            stopped = &mut pump => break stopped.unwrap_or(Stopped::BusClosed),
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break Stopped::ClientGone,
+                Some(Ok(Message::Text(text))) => match parse(&text) {
+                    Ok(subscription) => {
+                        pump.abort();
+                        pump = tokio::spawn(self::pump(source.clone(), subscription, tx.clone()));
+                    }
+                    Err(reason) => break Stopped::Invalid(reason),
+                },
+                // Other messages, pings included, need no reply here.
+                Some(Ok(_)) => {}
+            },
+        }
+    };
+    pump.abort();
+    source.clients.dec();
+    match stopped {
+        Stopped::TooSlow => {
+            source.slow_disconnects.inc();
+            close(socket, close_code::AGAIN, "send buffer full".into()).await;
+        }
+        Stopped::BusClosed => close(socket, close_code::AWAY, "shutting down".into()).await,
+        Stopped::Invalid(reason) => close(socket, close_code::POLICY, reason).await,
+        Stopped::ClientGone => {}
+    }
+}
+
+/// Move matching bus events into the client's send buffer.
+async fn pump(
+    source: Arc<WsSource>,
+    subscription: Subscription,
+    tx: mpsc::Sender<Message>,
+) -> Stopped {
+    let mut events = Box::pin(source.bus.subscribe_named("ws", subscription.filter()));
+    while let Some(event) = events.next().await {
+        let Some(frame) = subscription.frame(&event) else {
+            continue;
+        };
+        match tx.try_send(frame) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => match source.cfg.slow_client {
+                SlowClientPolicy::Disconnect => return Stopped::TooSlow,
+                SlowClientPolicy::DropEvents => source.dropped.inc(),
+            },
+            Err(TrySendError::Closed(_)) => return Stopped::ClientGone,
+        }
+    }
+    Stopped::BusClosed
+}
+
+async fn close(mut socket: WebSocket, code: u16, reason: String) {
+    let _ = socket
+        .send(Message::Close(Some(CloseFrame {
+            code,
+            reason: reason.into(),
+        })))
+        .await;
+}
@@ -0,0 +1,231 @@
+//! Consolidation of per-venue feeds into a synthetic `COMPOSITE` venue.
+//!
+//! Every trade is republished under the composite venue tagged with the venue
+//! it came from. Quotes are merged into a consolidated best bid/offer across
+//! venues, which is republished whenever it changes.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use ingest_core::{
+    config::CompositeConfig,
+    event::{EventKind, NormalizedEvent, Quote, COMPOSITE_VENUE},
+};
+
+use crate::Stage;
+
+pub struct Composite {
+    cfg: CompositeConfig,
+    books: HashMap<String, SymbolQuotes>,
+}
+
+#[derive(Default)]
+struct SymbolQuotes {
+    venues: HashMap<String, (Quote, DateTime<Utc>)>,
+    last: Option<ConsolidatedQuote>,
+}
+
+/// Payload of a composite `Quote` event: the consolidated BBO plus the venues
+/// currently setting each side.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct ConsolidatedQuote {
+    #[serde(flatten)]
+    pub quote: Quote,
+    pub bid_venue: String,
+    pub ask_venue: String,
+}
+
+impl Composite {
+    pub fn new(cfg: CompositeConfig) -> Self {
+        Self {
+            cfg,
+            books: HashMap::new(),
+        }
+    }
+
+    fn includes(&self, event: &NormalizedEvent) -> bool {
+        event.venue != COMPOSITE_VENUE
+            && (self.cfg.symbols.is_empty() || self.cfg.symbols.contains(&event.symbol))
+    }
+
+    fn trade(&self, event: &NormalizedEvent) -> Option<NormalizedEvent> {
+        let mut payload = event.payload.clone();
+        payload
+            .as_object_mut()?
+            .insert("source_venue".into(), event.venue.clone().into());
+        Some(NormalizedEvent {
+            venue: COMPOSITE_VENUE.to_string(),
+            payload,
+            ..event.clone()
+        })
+    }
+
+    fn quote(&mut self, event: &NormalizedEvent) -> Option<NormalizedEvent> {
+        let quote = event.quote()?;
+        let ttl = Duration::milliseconds(self.cfg.quote_ttl_ms as i64);
+        // Quotes age on the local clock: venues' clocks disagree, and some
+        // quotes carry no exchange time at all.
+        let now = event.received_at.unwrap_or(event.timestamp);
+        let book = self.books.entry(event.symbol.clone()).or_default();
+        book.venues.insert(event.venue.clone(), (quote, now));
+        book.venues.retain(|_, (_, at)| now - *at <= ttl);
+
+        let mut best: Option<ConsolidatedQuote> = None;
+        for (venue, (q, _)) in &book.venues {
+            let best = best.get_or_insert_with(|| ConsolidatedQuote {
+                quote: q.clone(),
+                bid_venue: venue.clone(),
+                ask_venue: venue.clone(),
+            });
+            if q.bid_price > best.quote.bid_price {
+                best.quote.bid_price = q.bid_price.clone();
+                best.quote.bid_quantity = q.bid_quantity.clone();
+                best.bid_venue = venue.clone();
+            }
+            if q.ask_price < best.quote.ask_price {
+                best.quote.ask_price = q.ask_price.clone();
+                best.quote.ask_quantity = q.ask_quantity.clone();
+                best.ask_venue = venue.clone();
+            }
+        }
+        let best = best?;
+        if book.last.as_ref() == Some(&best) {
+            return None;
+        }
+        book.last = Some(best.clone());
+        Some(NormalizedEvent {
+            venue: COMPOSITE_VENUE.to_string(),
+            kind: EventKind::Quote,
+            payload: serde_json::to_value(best).ok()?,
+            ..event.clone()
+        })
+    }
+}
+
+impl Stage for Composite {
+    fn process(&mut self, event: NormalizedEvent) -> Vec<NormalizedEvent> {
+        if !self.includes(&event) {
+            return vec![event];
+        }
+        let composite = match event.kind {
+            EventKind::Trade => self.trade(&event),
+            EventKind::Quote => self.quote(&event),
+            _ => None,
+        };
+        let mut out = vec![event];
+        out.extend(composite);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ingest_core::event::{Decimal, Side, Trade};
+
+    fn decimal(value: &str) -> Decimal {
+        value.parse().unwrap()
+    }
+
+    fn quote(venue: &str, ts_ms: i64, bid: &str, ask: &str) -> NormalizedEvent {
+        NormalizedEvent {
+            venue: venue.into(),
+            symbol: "BTCUSDT".into(),
+            timestamp: DateTime::from_timestamp_millis(ts_ms).unwrap(),
+            received_at: None,
+            kind: EventKind::Quote,
+            payload: serde_json::to_value(Quote {
+                bid_price: decimal(bid),
+                bid_quantity: decimal("1"),
+                ask_price: decimal(ask),
+                ask_quantity: decimal("2"),
+            })
+            .unwrap(),
+        }
+    }
+
+    #[test]
+    fn trades_are_tagged_with_source_venue() {
+        let mut stage = Composite::new(CompositeConfig::default());
+        let trade = NormalizedEvent {
+            venue: "binance_spot".into(),
+            symbol: "BTCUSDT".into(),
+            kind: EventKind::Trade,
+            payload: serde_json::to_value(Trade {
+                trade_id: 1,
+                price: decimal("10"),
+                quantity: decimal("1"),
+                side: Side::Buy,
+            })
+            .unwrap(),
+            ..Default::default()
+        };
+        let out = stage.process(trade);
+        assert_eq!(out.len(), 2);
+        assert_eq!(out[1].venue, COMPOSITE_VENUE);
+        assert_eq!(out[1].payload["source_venue"], "binance_spot");
+        assert_eq!(out[1].trade().unwrap().price, decimal("10"));
+    }
+
+    #[test]
+    fn consolidated_bbo_takes_best_side_per_venue() {
+        let mut stage = Composite::new(CompositeConfig::default());
+        stage.process(quote("a", 0, "100", "102"));
+        let out = stage.process(quote("b", 10, "101", "103"));
+        let bbo = &out[1].payload;
+        assert_eq!(bbo["bid_price"], "101");
+        assert_eq!(bbo["bid_venue"], "b");
+        assert_eq!(bbo["ask_price"], "102");
+        assert_eq!(bbo["ask_venue"], "a");
+        assert_eq!(out[1].quote().unwrap().ask_price, decimal("102"));
+    }
+
+    #[test]
+    fn unchanged_bbo_is_not_republished() {
+        let mut stage = Composite::new(CompositeConfig::default());
+        stage.process(quote("a", 0, "100", "102"));
+        stage.process(quote("b", 10, "99", "103"));
+        let out = stage.process(quote("b", 20, "98", "104"));
+        assert_eq!(out.len(), 1);
+    }
+
+    #[test]
+    fn stale_venue_quotes_expire() {
+        let mut stage = Composite::new(CompositeConfig {
+            quote_ttl_ms: 100,
+            ..Default::default()
+        });
+        stage.process(quote("a", 0, "100", "101"));
+        let out = stage.process(quote("b", 1_000, "90", "110"));
+        assert_eq!(out[1].payload["bid_venue"], "b");
+        assert_eq!(out[1].payload["bid_price"], "90");
+    }
+
+    #[test]
+    fn quotes_expire_by_their_receive_time() {
+        let mut stage = Composite::new(CompositeConfig {
+            quote_ttl_ms: 100,
+            ..Default::default()
+        });
+        let received = |mut event: NormalizedEvent, ms| {
+            event.received_at = DateTime::from_timestamp_millis(ms);
+            event
+        };
+        stage.process(received(quote("a", 0, "100", "101"), 5_000));
+        // Stamped a second later by a clock running ahead, but received
+        // right after.
+        let out = stage.process(received(quote("b", 1_000, "90", "100.5"), 5_050));
+        assert_eq!(out[1].payload["bid_venue"], "a");
+        let out = stage.process(received(quote("b", 1_000, "91", "100.5"), 5_200));
+        assert_eq!(out[1].payload["bid_venue"], "b");
+    }
+
+    #[test]
+    fn symbol_filter_limits_consolidation() {
+        let mut stage = Composite::new(CompositeConfig {
+            symbols: vec!["ETHUSDT".into()],
+            ..Default::default()
+        });
+        assert_eq!(stage.process(quote("a", 0, "1", "2")).len(), 1);
+    }
+}
@@ -1,5 +1,15 @@
 use chrono::Utc;
-use ingest_core::{event::NormalizedEvent, error::IngestError, canonical_symbol};
+use ingest_core::{event::{EventKind, NormalizedEvent}, error::IngestError, canonical_symbol};
+
+pub mod composite;
+pub mod runtime;
+pub mod skew;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use composite::Composite;
+pub use runtime::{Pipeline, PipelineBuilder, PipelineHealth, PipelineMetrics, Stage};
+pub use skew::ClockSkew;
 
 pub fn normalize(venue: &str, symbol: &str, raw: &str) -> Result<NormalizedEvent, IngestError> {
     let payload: serde_json::Value = serde_json::from_str(raw)?;
@@ -7,10 +17,22 @@ pub fn normalize(venue: &str, symbol: &str, raw: &str) -> Result<NormalizedEvent
         venue: venue.to_string(),
         symbol: canonical_symbol(symbol),
         timestamp: Utc::now(),
+        received_at: None,
+        kind: EventKind::Raw,
         payload,
     })
 }
 
+/// Stage that rewrites event symbols into the canonical format.
+pub struct Canonicalize;
+
+impl Stage for Canonicalize {
+    fn process(&mut self, mut event: NormalizedEvent) -> Vec<NormalizedEvent> {
+        event.symbol = canonical_symbol(&event.symbol);
+        vec![event]
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -23,7 +45,7 @@ mod tests {
 
     #[test]
     fn golden_replay() {
-        let data = include_str!("../../../golden/binance_spot_trades.jsonl");
+        let data = include_str!("../../../golden/binance_spot/trades.jsonl");
         for line in data.lines() {
             let evt = normalize("binance", "btcusdt", line).unwrap();
             assert_eq!(evt.venue, "binance");
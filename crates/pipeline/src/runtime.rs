@@ -0,0 +1,354 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Arc;
+
+use ingest_core::event::NormalizedEvent;
+use prometheus::{IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry};
+use tokio::sync::mpsc;
+use tokio::task::{AbortHandle, JoinHandle};
+
+/// A single processing step. A stage may drop, rewrite or fan out the events it
+/// receives by returning zero, one or many events.
+pub trait Stage: Send + 'static {
+    fn process(&mut self, event: NormalizedEvent) -> Vec<NormalizedEvent>;
+}
+
+impl<F> Stage for F
+where
+    F: FnMut(NormalizedEvent) -> Vec<NormalizedEvent> + Send + 'static,
+{
+    fn process(&mut self, event: NormalizedEvent) -> Vec<NormalizedEvent> {
+        self(event)
+    }
+}
+
+type StageFactory = Arc<dyn Fn() -> Box<dyn Stage> + Send + Sync>;
+
+struct StageSpec {
+    name: String,
+    factory: StageFactory,
+    capacity: Option<usize>,
+}
+
+/// Queue-depth gauges and supervision counters for the pipeline stages.
+#[derive(Clone)]
+pub struct PipelineMetrics {
+    pub queue_depth: IntGaugeVec,
+    pub processed: IntCounterVec,
+    pub restarts: IntCounterVec,
+    /// Events lost with the stage that panicked on them.
+    pub dropped: IntCounterVec,
+    pub clock_skew_ms: IntGaugeVec,
+}
+
+impl PipelineMetrics {
+    pub fn new() -> Self {
+        let queue_depth = IntGaugeVec::new(
+            Opts::new("pipeline_queue_depth", "events waiting in a stage input queue"),
+            &["stage"],
+        )
+        .unwrap();
+        let processed = IntCounterVec::new(
+            Opts::new("pipeline_processed_total", "events processed per stage"),
+            &["stage"],
+        )
+        .unwrap();
+        let restarts = IntCounterVec::new(
+            Opts::new("pipeline_stage_restarts_total", "stage restarts after a panic"),
+            &["stage"],
+        )
+        .unwrap();
+        let dropped = IntCounterVec::new(
+            Opts::new("pipeline_dropped_total", "events dropped by a stage that panicked on them"),
+            &["stage"],
+        )
+        .unwrap();
+        let clock_skew_ms = IntGaugeVec::new(
+            Opts::new(
+                "venue_clock_skew_ms",
+                "minimum local receive time minus exchange time over the skew window",
+            ),
+            &["venue"],
+        )
+        .unwrap();
+        Self {
+            queue_depth,
+            processed,
+            restarts,
+            dropped,
+            clock_skew_ms,
+        }
+    }
+
+    pub fn register(&self, registry: &Registry) -> prometheus::Result<()> {
+        registry.register(Box::new(self.queue_depth.clone()))?;
+        registry.register(Box::new(self.processed.clone()))?;
+        registry.register(Box::new(self.restarts.clone()))?;
+        registry.register(Box::new(self.dropped.clone()))?;
+        registry.register(Box::new(self.clock_skew_ms.clone()))?;
+        Ok(())
+    }
+}
+
+impl Default for PipelineMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Builder for a chain of supervised stages connected by bounded channels.
+pub struct PipelineBuilder {
+    stages: Vec<StageSpec>,
+    capacity: usize,
+    metrics: PipelineMetrics,
+}
+
+impl PipelineBuilder {
+    /// Default capacity of every inter-stage channel.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    pub fn metrics(mut self, metrics: PipelineMetrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Append a stage. The factory is invoked once at spawn time and again
+    /// whenever the stage panics, so it should build fresh state.
+    pub fn stage<F, S>(self, name: &str, factory: F) -> Self
+    where
+        F: Fn() -> S + Send + Sync + 'static,
+        S: Stage,
+    {
+        self.push(name, factory, None)
+    }
+
+    /// Append a stage whose input queue uses its own capacity.
+    pub fn stage_with_capacity<F, S>(self, name: &str, capacity: usize, factory: F) -> Self
+    where
+        F: Fn() -> S + Send + Sync + 'static,
+        S: Stage,
+    {
+        self.push(name, factory, Some(capacity))
+    }
+
+    fn push<F, S>(mut self, name: &str, factory: F, capacity: Option<usize>) -> Self
+    where
+        F: Fn() -> S + Send + Sync + 'static,
+        S: Stage,
+    {
+        self.stages.push(StageSpec {
+            name: name.to_string(),
+            factory: Arc::new(move || Box::new(factory()) as Box<dyn Stage>),
+            capacity,
+        });
+        self
+    }
+
+    /// Spawn one task per stage and return the pipeline endpoints.
+    pub fn spawn(self) -> Pipeline {
+        let first_capacity = self
+            .stages
+            .first()
+            .and_then(|s| s.capacity)
+            .unwrap_or(self.capacity);
+        let (input, mut rx) = mpsc::channel(first_capacity);
+        let mut tasks = Vec::with_capacity(self.stages.len());
+        for (i, spec) in self.stages.iter().enumerate() {
+            // Each queue's depth is set by the stage reading it, and the
+            // output queue's, which no stage reads, by the last stage.
+            let (next_capacity, output_depth) = match self.stages.get(i + 1) {
+                Some(next) => (next.capacity.unwrap_or(self.capacity), None),
+                None => (
+                    self.capacity,
+                    Some(self.metrics.queue_depth.with_label_values(&["output"])),
+                ),
+            };
+            let (tx, next_rx) = mpsc::channel(next_capacity);
+            let worker = StageWorker {
+                name: spec.name.clone(),
+                factory: spec.factory.clone(),
+                depth: self.metrics.queue_depth.with_label_values(&[&spec.name]),
+                output_depth,
+                metrics: self.metrics.clone(),
+            };
+            tasks.push(tokio::spawn(worker.run(rx, tx)));
+            rx = next_rx;
+        }
+        Pipeline {
+            input,
+            output: rx,
+            stages: self.stages.into_iter().map(|spec| spec.name).collect(),
+            tasks,
+        }
+    }
+}
+
+/// Running pipeline. Events sent to `input` come out of `output` after passing
+/// through every stage in order.
+pub struct Pipeline {
+    pub input: mpsc::Sender<NormalizedEvent>,
+    pub output: mpsc::Receiver<NormalizedEvent>,
+    /// Stage names, in the order of `tasks`.
+    pub stages: Vec<String>,
+    pub tasks: Vec<JoinHandle<()>>,
+}
+
+impl Pipeline {
+    pub fn builder() -> PipelineBuilder {
+        PipelineBuilder {
+            stages: Vec::new(),
+            capacity: 1024,
+            metrics: PipelineMetrics::new(),
+        }
+    }
+
+    /// Which stages are still running, tracked past the pipeline being taken
+    /// apart.
+    pub fn health(&self) -> PipelineHealth {
+        let stages = self.stages.iter().cloned();
+        PipelineHealth {
+            stages: stages
+                .zip(self.tasks.iter().map(JoinHandle::abort_handle))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PipelineHealth {
+    stages: Arc<[(String, AbortHandle)]>,
+}
+
+impl PipelineHealth {
+    /// Stages whose task has ended. Stages restart after a panic, so they
+    /// only end once their input is closed.
+    pub fn stopped(&self) -> Vec<String> {
+        self.stages
+            .iter()
+            .filter(|(_, task)| task.is_finished())
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+}
+
+struct StageWorker {
+    name: String,
+    factory: StageFactory,
+    depth: IntGauge,
+    output_depth: Option<IntGauge>,
+    metrics: PipelineMetrics,
+}
+
+impl StageWorker {
+    async fn run(
+        self,
+        mut rx: mpsc::Receiver<NormalizedEvent>,
+        tx: mpsc::Sender<NormalizedEvent>,
+    ) {
+        let processed = self.metrics.processed.with_label_values(&[&self.name]);
+        let restarts = self.metrics.restarts.with_label_values(&[&self.name]);
+        let dropped = self.metrics.dropped.with_label_values(&[&self.name]);
+        let mut stage = (self.factory)();
+        while let Some(event) = rx.recv().await {
+            self.depth.set(rx.len() as i64);
+            let kind = event.kind;
+            match catch_unwind(AssertUnwindSafe(|| stage.process(event))) {
+                Ok(out) => {
+                    processed.inc();
+                    for evt in out {
+                        if tx.send(evt).await.is_err() {
+                            return;
+                        }
+                    }
+                    if let Some(output_depth) = &self.output_depth {
+                        output_depth.set((tx.max_capacity() - tx.capacity()) as i64);
+                    }
+                }
+                Err(_) => {
+                    dropped.inc();
+                    tracing::error!(
+                        "pipeline stage {} panicked on a {:?} event, dropped it and restarting",
+                        self.name,
+                        kind
+                    );
+                    restarts.inc();
+                    stage = (self.factory)();
+                }
+            }
+            self.depth.set(rx.len() as i64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn event(symbol: &str) -> NormalizedEvent {
+        NormalizedEvent {
+            venue: "binance".into(),
+            symbol: symbol.into(),
+            timestamp: Utc::now(),
+            received_at: None,
+            kind: ingest_core::event::EventKind::Raw,
+            payload: serde_json::json!({}),
+        }
+    }
+
+    #[tokio::test]
+    async fn events_flow_through_stages_in_order() {
+        let mut pipeline = Pipeline::builder()
+            .capacity(4)
+            .stage("upper", || crate::Canonicalize)
+            .stage("dup", || {
+                |evt: NormalizedEvent| vec![evt.clone(), evt]
+            })
+            .spawn();
+        pipeline.input.send(event("btcusdt")).await.unwrap();
+        let first = pipeline.output.recv().await.unwrap();
+        let second = pipeline.output.recv().await.unwrap();
+        assert_eq!(first.symbol, "BTCUSDT");
+        assert_eq!(second.symbol, "BTCUSDT");
+    }
+
+    #[tokio::test]
+    async fn panicking_stage_is_restarted() {
+        let metrics = PipelineMetrics::new();
+        let mut pipeline = Pipeline::builder()
+            .metrics(metrics.clone())
+            .stage("flaky", || {
+                |evt: NormalizedEvent| {
+                    if evt.symbol == "BAD" {
+                        panic!("boom");
+                    }
+                    vec![evt]
+                }
+            })
+            .spawn();
+        pipeline.input.send(event("BAD")).await.unwrap();
+        pipeline.input.send(event("GOOD")).await.unwrap();
+        let evt = pipeline.output.recv().await.unwrap();
+        assert_eq!(evt.symbol, "GOOD");
+        assert_eq!(metrics.restarts.with_label_values(&["flaky"]).get(), 1);
+        assert_eq!(metrics.dropped.with_label_values(&["flaky"]).get(), 1);
+    }
+
+    #[tokio::test]
+    async fn closing_input_stops_stage_tasks() {
+        let pipeline = Pipeline::builder()
+            .stage("noop", || |evt: NormalizedEvent| vec![evt])
+            .spawn();
+        let health = pipeline.health();
+        assert!(health.stopped().is_empty());
+        let Pipeline { input, output, tasks, .. } = pipeline;
+        drop(input);
+        for task in tasks {
+            task.await.unwrap();
+        }
+        drop(output);
+        assert_eq!(health.stopped(), ["noop"]);
+    }
+}
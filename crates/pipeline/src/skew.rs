@@ -0,0 +1,175 @@
+//! Clock-skew detection between venue and local clocks.
+//!
+//! The offset `received_at - timestamp` of an event is network latency plus the
+//! difference between the local and the exchange clock. Latency is never
+//! negative, so the smallest offset seen over a window bounds the skew. That
+//! minimum is exported per venue and, once it exceeds the configured threshold,
+//! used to annotate or correct events. The threshold should sit above a venue's
+//! normal minimum latency, otherwise real latency gets corrected away.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use ingest_core::{
+    config::{ClockSkewConfig, SkewMode},
+    event::NormalizedEvent,
+};
+use prometheus::IntGaugeVec;
+
+use crate::Stage;
+
+pub struct ClockSkew {
+    cfg: ClockSkewConfig,
+    gauge: IntGaugeVec,
+    venues: HashMap<String, SkewWindow>,
+}
+
+impl ClockSkew {
+    pub fn new(cfg: ClockSkewConfig, gauge: IntGaugeVec) -> Self {
+        Self {
+            cfg,
+            gauge,
+            venues: HashMap::new(),
+        }
+    }
+
+    /// Current skew estimate for a venue in milliseconds.
+    pub fn estimate(&self, venue: &str) -> Option<i64> {
+        self.venues.get(venue).map(SkewWindow::estimate)
+    }
+}
+
+impl Stage for ClockSkew {
+    fn process(&mut self, mut event: NormalizedEvent) -> Vec<NormalizedEvent> {
+        let Some(received_at) = event.received_at else {
+            return vec![event];
+        };
+        let offset = (received_at - event.timestamp).num_milliseconds();
+        // Half of the window per bucket, so the estimate spans between half
+        // and the full window.
+        let bucket = Duration::seconds(self.cfg.window_secs.max(1) as i64) / 2;
+        let estimate = self
+            .venues
+            .entry(event.venue.clone())
+            .or_insert_with(|| SkewWindow::new(received_at, offset))
+            .observe(received_at, offset, bucket);
+        self.gauge.with_label_values(&[&event.venue]).set(estimate);
+
+        if estimate.abs() > self.cfg.threshold_ms {
+            match self.cfg.mode {
+                SkewMode::Observe => {}
+                SkewMode::Annotate => {
+                    if let Some(obj) = event.payload.as_object_mut() {
+                        obj.insert("clock_skew_ms".into(), estimate.into());
+                    }
+                }
+                SkewMode::Correct => {
+                    event.timestamp += Duration::milliseconds(estimate);
+                }
+            }
+        }
+        vec![event]
+    }
+}
+
+struct SkewWindow {
+    bucket_start: DateTime<Utc>,
+    current: i64,
+    previous: Option<i64>,
+}
+
+impl SkewWindow {
+    fn new(now: DateTime<Utc>, offset: i64) -> Self {
+        Self {
+            bucket_start: now,
+            current: offset,
+            previous: None,
+        }
+    }
+
+    fn observe(&mut self, now: DateTime<Utc>, offset: i64, bucket: Duration) -> i64 {
+        if now - self.bucket_start >= bucket {
+            self.previous = Some(self.current);
+            self.current = offset;
+            self.bucket_start = now;
+        } else {
+            self.current = self.current.min(offset);
+        }
+        self.estimate()
+    }
+
+    fn estimate(&self) -> i64 {
+        self.previous
+            .map_or(self.current, |prev| prev.min(self.current))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PipelineMetrics;
+    use ingest_core::event::EventKind;
+
+    fn event(exchange_ms: i64, received_ms: i64) -> NormalizedEvent {
+        NormalizedEvent {
+            venue: "binance".into(),
+            symbol: "BTCUSDT".into(),
+            timestamp: DateTime::from_timestamp_millis(exchange_ms).unwrap(),
+            received_at: DateTime::from_timestamp_millis(received_ms),
+            kind: EventKind::Trade,
+            payload: serde_json::json!({}),
+        }
+    }
+
+    fn stage(mode: SkewMode) -> (ClockSkew, IntGaugeVec) {
+        let gauge = PipelineMetrics::new().clock_skew_ms;
+        let cfg = ClockSkewConfig {
+            mode,
+            threshold_ms: 500,
+            window_secs: 10,
+        };
+        (ClockSkew::new(cfg, gauge.clone()), gauge)
+    }
+
+    #[test]
+    fn tracks_minimum_offset_per_venue() {
+        let (mut stage, gauge) = stage(SkewMode::Observe);
+        stage.process(event(1_000, 1_040));
+        stage.process(event(2_000, 2_010));
+        stage.process(event(3_000, 3_090));
+        assert_eq!(stage.estimate("binance"), Some(10));
+        assert_eq!(gauge.with_label_values(&["binance"]).get(), 10);
+    }
+
+    #[test]
+    fn old_minimum_expires_after_window() {
+        let (mut stage, _) = stage(SkewMode::Observe);
+        stage.process(event(0, 5));
+        stage.process(event(6_000, 6_050));
+        stage.process(event(12_000, 12_040));
+        assert_eq!(stage.estimate("binance"), Some(40));
+    }
+
+    #[test]
+    fn correct_mode_shifts_drifting_venue() {
+        let (mut stage, _) = stage(SkewMode::Correct);
+        // Exchange clock runs two seconds ahead of ours.
+        let out = stage.process(event(10_000, 8_005));
+        assert_eq!(out[0].timestamp.timestamp_millis(), 8_005);
+    }
+
+    #[test]
+    fn annotate_mode_marks_payload() {
+        let (mut stage, _) = stage(SkewMode::Annotate);
+        let out = stage.process(event(10_000, 8_005));
+        assert_eq!(out[0].payload["clock_skew_ms"], -1995);
+        assert_eq!(out[0].timestamp.timestamp_millis(), 10_000);
+    }
+
+    #[test]
+    fn small_skew_is_left_alone() {
+        let (mut stage, _) = stage(SkewMode::Correct);
+        let out = stage.process(event(10_000, 10_020));
+        assert_eq!(out[0].timestamp.timestamp_millis(), 10_000);
+    }
+}
@@ -0,0 +1,231 @@
+//! Sandboxed user transforms compiled to WebAssembly.
+//!
+//! A transform module exports `memory`, `alloc(len: i32) -> i32` and
+//! `transform(ptr: i32, len: i32) -> i64`. The host writes a JSON encoded
+//! event into the buffer returned by `alloc`; `transform` returns the location
+//! of a JSON array of output events packed as `(ptr << 32) | len`. Modules get
+//! no imports, so they can only compute on the data handed to them, and each
+//! call runs on a fuel budget, so a module that loops forever fails the event
+//! rather than stalling the pipeline.
+
+use ingest_core::{config::TransformConfig, error::IngestError, event::NormalizedEvent};
+use wasmtime::{Engine, Instance, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc};
+
+use crate::Stage;
+
+const DEFAULT_MAX_MEMORY: usize = 16 * 1024 * 1024;
+/// Fuel per event of transforms configured without a budget, roughly as many
+/// instructions, some milliseconds of work.
+const DEFAULT_FUEL: u64 = 10_000_000;
+
+/// A compiled transform module. Cloning is cheap and shares the compiled code.
+#[derive(Clone)]
+pub struct WasmTransform {
+    name: String,
+    engine: Engine,
+    module: Module,
+    fuel: u64,
+    max_memory: usize,
+}
+
+impl WasmTransform {
+    pub fn from_config(cfg: &TransformConfig) -> Result<Self, IngestError> {
+        let bytes = std::fs::read(&cfg.module)?;
+        Self::from_bytes(&cfg.name, &bytes, cfg.fuel, cfg.max_memory_bytes)
+    }
+
+    /// Compile a module from its binary or text representation, to run on
+    /// `fuel` per event, or a default budget.
+    pub fn from_bytes(
+        name: &str,
+        bytes: &[u8],
+        fuel: Option<u64>,
+        max_memory: Option<usize>,
+    ) -> Result<Self, IngestError> {
+        let mut config = wasmtime::Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config).map_err(transform_err)?;
+        let module = Module::new(&engine, bytes).map_err(transform_err)?;
+        Ok(Self {
+            name: name.to_string(),
+            engine,
+            module,
+            fuel: fuel.unwrap_or(DEFAULT_FUEL),
+            max_memory: max_memory.unwrap_or(DEFAULT_MAX_MEMORY),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Create a pipeline stage backed by a fresh instance of this module.
+    pub fn stage(&self) -> WasmStage {
+        WasmStage {
+            transform: self.clone(),
+            instance: None,
+        }
+    }
+
+    fn instantiate(&self) -> Result<Loaded, IngestError> {
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(self.max_memory)
+            .instances(1)
+            .build();
+        let mut store = Store::new(&self.engine, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(self.fuel).map_err(transform_err)?;
+        let instance = Instance::new(&mut store, &self.module, &[]).map_err(transform_err)?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| IngestError::Transform("module does not export `memory`".into()))?;
+        let alloc = instance
+            .get_typed_func::<i32, i32>(&mut store, "alloc")
+            .map_err(transform_err)?;
+        let transform = instance
+            .get_typed_func::<(i32, i32), i64>(&mut store, "transform")
+            .map_err(transform_err)?;
+        Ok(Loaded {
+            store,
+            memory,
+            alloc,
+            transform,
+        })
+    }
+}
+
+struct Loaded {
+    store: Store<StoreLimits>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    transform: TypedFunc<(i32, i32), i64>,
+}
+
+/// Pipeline stage running a [`WasmTransform`]. Events the module fails on are
+/// passed through unchanged and the instance is discarded.
+pub struct WasmStage {
+    transform: WasmTransform,
+    instance: Option<Loaded>,
+}
+
+impl WasmStage {
+    fn call(&mut self, event: &NormalizedEvent) -> Result<Vec<NormalizedEvent>, IngestError> {
+        let loaded = match self.instance.as_mut() {
+            Some(loaded) => loaded,
+            None => self.instance.insert(self.transform.instantiate()?),
+        };
+        loaded
+            .store
+            .set_fuel(self.transform.fuel)
+            .map_err(transform_err)?;
+        let input = serde_json::to_vec(event)?;
+        let len = i32::try_from(input.len())
+            .map_err(|_| IngestError::Transform("event too large".into()))?;
+        let ptr = loaded
+            .alloc
+            .call(&mut loaded.store, len)
+            .map_err(transform_err)?;
+        loaded
+            .memory
+            .write(&mut loaded.store, ptr as u32 as usize, &input)
+            .map_err(transform_err)?;
+        let packed = loaded
+            .transform
+            .call(&mut loaded.store, (ptr, len))
+            .map_err(transform_err)? as u64;
+        let out_ptr = (packed >> 32) as usize;
+        let out_len = (packed & 0xffff_ffff) as usize;
+        let data = loaded.memory.data(&loaded.store);
+        let output = data
+            .get(out_ptr..out_ptr + out_len)
+            .ok_or_else(|| IngestError::Transform("output out of bounds".into()))?;
+        Ok(serde_json::from_slice(output)?)
+    }
+}
+
+impl Stage for WasmStage {
+    fn process(&mut self, event: NormalizedEvent) -> Vec<NormalizedEvent> {
+        match self.call(&event) {
+            Ok(events) => events,
+            Err(e) => {
+                tracing::warn!("wasm transform {} failed: {}", self.transform.name, e);
+                self.instance = None;
+                vec![event]
+            }
+        }
+    }
+}
+
+fn transform_err(e: impl std::fmt::Display) -> IngestError {
+    IngestError::Transform(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    // Wraps the input event in a JSON array, i.e. an identity transform.
+    const IDENTITY: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (func (export "alloc") (param $len i32) (result i32)
+    i32.const 1024)
+  (func (export "transform") (param $ptr i32) (param $len i32) (result i64)
+    (i32.store8 (i32.const 0) (i32.const 91))
+    (memory.copy (i32.const 1) (local.get $ptr) (local.get $len))
+    (i32.store8 (i32.add (local.get $len) (i32.const 1)) (i32.const 93))
+    (i64.extend_i32_u (i32.add (local.get $len) (i32.const 2)))))
+"#;
+
+    const SPIN: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (func (export "alloc") (param $len i32) (result i32)
+    i32.const 1024)
+  (func (export "transform") (param $ptr i32) (param $len i32) (result i64)
+    (loop $l (br $l))
+    i64.const 0))
+"#;
+
+    fn event() -> NormalizedEvent {
+        NormalizedEvent {
+            venue: "binance".into(),
+            symbol: "BTCUSDT".into(),
+            timestamp: Utc::now(),
+            received_at: None,
+            kind: ingest_core::event::EventKind::Raw,
+            payload: serde_json::json!({"p": "1.0"}),
+        }
+    }
+
+    #[test]
+    fn identity_transform_roundtrips_event() {
+        let transform = WasmTransform::from_bytes("id", IDENTITY.as_bytes(), None, None).unwrap();
+        let mut stage = transform.stage();
+        let evt = event();
+        let out = stage.process(evt.clone());
+        assert_eq!(out, vec![evt]);
+        assert!(stage.instance.is_some());
+    }
+
+    #[test]
+    fn fuel_exhaustion_passes_event_through() {
+        let transform =
+            WasmTransform::from_bytes("spin", SPIN.as_bytes(), Some(10_000), None).unwrap();
+        let mut stage = transform.stage();
+        let evt = event();
+        let out = stage.process(evt.clone());
+        assert_eq!(out, vec![evt]);
+        assert!(stage.instance.is_none());
+    }
+
+    #[test]
+    fn default_fuel_stops_a_runaway_module() {
+        let transform = WasmTransform::from_bytes("spin", SPIN.as_bytes(), None, None).unwrap();
+        let mut stage = transform.stage();
+        let evt = event();
+        assert_eq!(stage.process(evt.clone()), vec![evt]);
+        assert!(stage.instance.is_none());
+    }
+}
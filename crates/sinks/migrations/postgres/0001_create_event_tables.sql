@@ -0,0 +1,19 @@
+-- One table per event kind. Payloads keep the normalized JSON shape.
+CREATE TABLE IF NOT EXISTS trades (
+    venue       TEXT        NOT NULL,
+    symbol      TEXT        NOT NULL,
+    ts          TIMESTAMPTZ NOT NULL,
+    received_at TIMESTAMPTZ,
+    payload     JSONB       NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS tickers (LIKE trades INCLUDING ALL);
+CREATE TABLE IF NOT EXISTS quotes (LIKE trades INCLUDING ALL);
+CREATE TABLE IF NOT EXISTS books (LIKE trades INCLUDING ALL);
+CREATE TABLE IF NOT EXISTS raw_events (LIKE trades INCLUDING ALL);
+
+CREATE INDEX IF NOT EXISTS trades_symbol_ts ON trades (venue, symbol, ts DESC);
+CREATE INDEX IF NOT EXISTS tickers_symbol_ts ON tickers (venue, symbol, ts DESC);
+CREATE INDEX IF NOT EXISTS quotes_symbol_ts ON quotes (venue, symbol, ts DESC);
+CREATE INDEX IF NOT EXISTS books_symbol_ts ON books (venue, symbol, ts DESC);
+CREATE INDEX IF NOT EXISTS raw_events_symbol_ts ON raw_events (venue, symbol, ts DESC);
@@ -0,0 +1,13 @@
+-- Turn the event tables into hypertables when TimescaleDB is installed;
+-- plain Postgres keeps ordinary tables.
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'timescaledb') THEN
+        PERFORM create_hypertable('trades', 'ts', if_not_exists => TRUE);
+        PERFORM create_hypertable('tickers', 'ts', if_not_exists => TRUE);
+        PERFORM create_hypertable('quotes', 'ts', if_not_exists => TRUE);
+        PERFORM create_hypertable('books', 'ts', if_not_exists => TRUE);
+        PERFORM create_hypertable('raw_events', 'ts', if_not_exists => TRUE);
+    END IF;
+END
+$$;
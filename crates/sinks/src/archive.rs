@@ -0,0 +1,823 @@
+//! Archival sink uploading closed segments to object storage.
+//!
+//! Events are first written to segments in a local spool directory, laid out
+//! as `date=YYYY-MM-DD/venue=<venue>/` (plus `kind=<kind>/` for Parquet).
+//! Closed segments are uploaded under the same relative path below the
+//! destination prefix and removed from the spool once the store has accepted
+//! them. A flush succeeds once the open segments are closed and synced, so
+//! uploads never hold up the sink: a background task uploads the spool when
+//! a segment closes and rescans it periodically.
+//!
+//! Segments are read from disk a part at a time. On S3, GCS and Azure a
+//! multipart upload records its progress in a hidden file beside the
+//! segment, so one interrupted by an error or a restart continues with the
+//! next part rather than starting over. Segments a crash left in progress
+//! are closed on start with the events that reached the disk whole.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use chrono::{NaiveDate, Utc};
+use ingest_core::{
+    config::{ArchiveFormat, ArchiveSinkConfig, Compression},
+    error::IngestError,
+    event::NormalizedEvent,
+};
+use object_store::{
+    aws::AmazonS3Builder,
+    azure::MicrosoftAzureBuilder,
+    gcp::GoogleCloudStorageBuilder,
+    multipart::{MultipartStore, PartId},
+    path::Path as ObjectPath,
+    Attribute, Attributes, MultipartId, ObjectStore, ObjectStoreScheme, PutMultipartOptions,
+    PutOptions,
+};
+use prometheus::IntCounter;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+use crate::{compress, Sink, SinkMetrics};
+
+const SCAN_INTERVAL: Duration = Duration::from_secs(1);
+/// Bytes read at a time when hashing a segment.
+const HASH_CHUNK: usize = 1 << 20;
+#[cfg(feature = "parquet")]
+const PARQUET_BATCH: usize = 10_000;
+
+pub struct ArchiveSink {
+    uploader: Arc<Uploader>,
+    /// Wakes the uploads once a segment has closed.
+    wake: Arc<Notify>,
+    uploading: Option<JoinHandle<()>>,
+    spool: Spool,
+}
+
+enum Spool {
+    Jsonl(JsonlSpool),
+    #[cfg(feature = "parquet")]
+    Parquet(crate::parquet::ParquetSink),
+}
+
+/// Moves closed segments from the spool into the object store.
+struct Uploader {
+    name: String,
+    store: Arc<dyn ObjectStore>,
+    /// The same store, for stores whose multipart uploads can be resumed.
+    multipart: Option<Arc<dyn MultipartStore>>,
+    prefix: ObjectPath,
+    spool_dir: PathBuf,
+    part_size: usize,
+    checksum_metadata: bool,
+}
+
+/// A multipart upload under way, kept beside its segment.
+#[derive(Serialize, Deserialize)]
+struct Progress {
+    id: MultipartId,
+    part_size: usize,
+    /// Content ids of the parts uploaded so far, in order.
+    parts: Vec<String>,
+}
+
+impl ArchiveSink {
+    pub fn new(
+        name: &str,
+        cfg: &ArchiveSinkConfig,
+        metrics: &SinkMetrics,
+    ) -> Result<Self, IngestError> {
+        let sink_err = |e: String| IngestError::Sink(format!("sink {}: {}", name, e));
+        let url = url::Url::parse(&cfg.url).map_err(|e| sink_err(e.to_string()))?;
+        let (scheme, _) = ObjectStoreScheme::parse(&url).map_err(|e| sink_err(e.to_string()))?;
+        let (store, multipart, prefix) =
+            open_store(&url, &cfg.options).map_err(|e| sink_err(e.to_string()))?;
+        compress::check(name, cfg.compression)?;
+        fs::create_dir_all(&cfg.spool_dir)?;
+        recover(name, Path::new(&cfg.spool_dir), cfg.format)?;
+        let spool = match cfg.format {
+            ArchiveFormat::Jsonl => Spool::Jsonl(JsonlSpool::new(cfg, metrics.delivered(name))),
+            #[cfg(feature = "parquet")]
+            ArchiveFormat::Parquet => {
+                let parquet = ingest_core::config::ParquetSinkConfig {
+                    path: cfg.spool_dir.clone(),
+                    batch_size: PARQUET_BATCH,
+                    max_file_bytes: cfg.segment_bytes,
+                    max_file_age_secs: cfg.segment_age_secs,
+                };
+                Spool::Parquet(crate::parquet::ParquetSink::new(name, &parquet, metrics)?)
+            }
+            #[cfg(not(feature = "parquet"))]
+            ArchiveFormat::Parquet => return Err(crate::unsupported(name, "parquet")),
+        };
+        let uploader = Uploader {
+            name: name.to_string(),
+            store,
+            multipart,
+            prefix,
+            spool_dir: PathBuf::from(&cfg.spool_dir),
+            part_size: cfg.part_size,
+            // The local filesystem store cannot hold object metadata.
+            checksum_metadata: scheme != ObjectStoreScheme::Local,
+        };
+        Ok(Self {
+            uploader: Arc::new(uploader),
+            wake: Arc::new(Notify::new()),
+            uploading: None,
+            spool,
+        })
+    }
+
+    /// Start the uploads if they are not running, and wake them.
+    fn upload(&mut self) {
+        if self.uploading.as_ref().is_none_or(JoinHandle::is_finished) {
+            let uploads = self.uploader.clone().run(self.wake.clone());
+            self.uploading = Some(tokio::spawn(uploads));
+        }
+        self.wake.notify_one();
+    }
+}
+
+impl Drop for ArchiveSink {
+    fn drop(&mut self) {
+        // An upload cut short continues from the spool on the next start.
+        if let Some(uploading) = &self.uploading {
+            uploading.abort();
+        }
+    }
+}
+
+/// The store at `url` configured with `options`, for S3, GCS and Azure
+/// also as a [`MultipartStore`], and the prefix within it.
+#[allow(clippy::type_complexity)]
+fn open_store(
+    url: &url::Url,
+    options: &BTreeMap<String, String>,
+) -> object_store::Result<(
+    Arc<dyn ObjectStore>,
+    Option<Arc<dyn MultipartStore>>,
+    ObjectPath,
+)> {
+    macro_rules! cloud {
+        ($builder:ty) => {{
+            let builder = options.iter().fold(
+                <$builder>::new().with_url(url.as_str()),
+                |builder, (key, value)| match key.to_ascii_lowercase().parse() {
+                    Ok(key) => builder.with_config(key, value),
+                    Err(_) => builder,
+                },
+            );
+            let store = Arc::new(builder.build()?);
+            (
+                store.clone() as Arc<dyn ObjectStore>,
+                Some(store as Arc<dyn MultipartStore>),
+            )
+        }};
+    }
+    let (scheme, prefix) = ObjectStoreScheme::parse(url)?;
+    let (store, multipart) = match scheme {
+        ObjectStoreScheme::AmazonS3 => cloud!(AmazonS3Builder),
+        ObjectStoreScheme::GoogleCloudStorage => cloud!(GoogleCloudStorageBuilder),
+        ObjectStoreScheme::MicrosoftAzure => cloud!(MicrosoftAzureBuilder),
+        _ => (
+            Arc::from(object_store::parse_url_opts(url, options)?.0),
+            None,
+        ),
+    };
+    Ok((store, multipart, prefix))
+}
+
+impl Uploader {
+    /// Upload the spool whenever woken and every [`SCAN_INTERVAL`].
+    async fn run(self: Arc<Self>, wake: Arc<Notify>) {
+        loop {
+            // Failed segments stay in the spool for the next scan.
+            let _ = self.upload_ready().await;
+            tokio::select! {
+                _ = wake.notified() => {}
+                _ = tokio::time::sleep(SCAN_INTERVAL) => {}
+            }
+        }
+    }
+
+    fn key(&self, segment: &Path) -> ObjectPath {
+        let relative = segment.strip_prefix(&self.spool_dir).unwrap_or(segment);
+        relative
+            .components()
+            .fold(self.prefix.clone(), |key, part| {
+                key.join(part.as_os_str().to_string_lossy().as_ref())
+            })
+    }
+
+    /// The checksum metadata of `segment`, if the store keeps metadata.
+    async fn attributes(&self, segment: &Path) -> Result<Attributes, IngestError> {
+        let mut attributes = Attributes::new();
+        if self.checksum_metadata {
+            let mut file = tokio::fs::File::open(segment).await?;
+            let mut digest = Sha256::new();
+            let mut chunk = vec![0; HASH_CHUNK];
+            loop {
+                let n = file.read(&mut chunk).await?;
+                if n == 0 {
+                    break;
+                }
+                digest.update(&chunk[..n]);
+            }
+            let hex: String = digest
+                .finalize()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect();
+            attributes.insert(Attribute::Metadata("sha256".into()), hex.into());
+        }
+        Ok(attributes)
+    }
+
+    async fn upload(&self, segment: &Path, key: &ObjectPath) -> Result<(), IngestError> {
+        let len = tokio::fs::metadata(segment).await?.len();
+        if len > self.part_size as u64 {
+            return match &self.multipart {
+                Some(store) => self.resume(store.as_ref(), segment, key).await,
+                None => self.stream(segment, key).await,
+            };
+        }
+        let opts = PutOptions {
+            attributes: self.attributes(segment).await?,
+            ..Default::default()
+        };
+        let data = tokio::fs::read(segment).await?;
+        self.store
+            .put_opts(key, data.into(), opts)
+            .await
+            .map_err(store_err)?;
+        Ok(())
+    }
+
+    /// Upload `segment` in parts over the generic multipart API, starting
+    /// over if it fails.
+    async fn stream(&self, segment: &Path, key: &ObjectPath) -> Result<(), IngestError> {
+        let opts = PutMultipartOptions {
+            attributes: self.attributes(segment).await?,
+            ..Default::default()
+        };
+        let mut file = tokio::fs::File::open(segment).await?;
+        let mut upload = self
+            .store
+            .put_multipart_opts(key, opts)
+            .await
+            .map_err(store_err)?;
+        loop {
+            let part = match read_part(&mut file, self.part_size).await {
+                Ok(part) if part.is_empty() => break,
+                Ok(part) => part,
+                Err(e) => {
+                    let _ = upload.abort().await;
+                    return Err(e.into());
+                }
+            };
+            if let Err(e) = upload.put_part(part.into()).await {
+                let _ = upload.abort().await;
+                return Err(store_err(e));
+            }
+        }
+        upload.complete().await.map_err(store_err)?;
+        Ok(())
+    }
+
+    /// Upload `segment` in parts, continuing the upload recorded beside it,
+    /// if any, after the parts it already holds.
+    async fn resume(
+        &self,
+        store: &dyn MultipartStore,
+        segment: &Path,
+        key: &ObjectPath,
+    ) -> Result<(), IngestError> {
+        let path = progress_path(segment);
+        let recorded = tokio::fs::read(&path)
+            .await
+            .ok()
+            .and_then(|data| serde_json::from_slice::<Progress>(&data).ok());
+        let mut progress = match recorded {
+            Some(progress) if progress.part_size == self.part_size => progress,
+            stale => {
+                if let Some(stale) = stale {
+                    let _ = store.abort_multipart(key, &stale.id).await;
+                }
+                let opts = PutMultipartOptions {
+                    attributes: self.attributes(segment).await?,
+                    ..Default::default()
+                };
+                let id = match store.create_multipart_opts(key, opts).await {
+                    // Azure takes no metadata on multipart uploads.
+                    Err(object_store::Error::NotSupported { .. }) => {
+                        store.create_multipart(key).await
+                    }
+                    created => created,
+                };
+                Progress {
+                    id: id.map_err(store_err)?,
+                    part_size: self.part_size,
+                    parts: Vec::new(),
+                }
+            }
+        };
+        let resumed = !progress.parts.is_empty();
+        let uploaded = self
+            .put_parts(store, segment, key, &mut progress, &path)
+            .await;
+        if let Err(e) = &uploaded {
+            if matches!(e, Error::Store(object_store::Error::NotFound { .. })) {
+                // The store dropped the upload, e.g. expired it; start over
+                // on the next scan.
+                let _ = tokio::fs::remove_file(&path).await;
+            }
+        } else if resumed {
+            tracing::info!("sink {} resumed the upload of {}", self.name, key);
+        }
+        uploaded.map_err(Error::into_ingest)?;
+        let _ = tokio::fs::remove_file(&path).await;
+        Ok(())
+    }
+
+    async fn put_parts(
+        &self,
+        store: &dyn MultipartStore,
+        segment: &Path,
+        key: &ObjectPath,
+        progress: &mut Progress,
+        path: &Path,
+    ) -> Result<(), Error> {
+        save(path, progress).await?;
+        let mut file = tokio::fs::File::open(segment).await?;
+        let offset = (progress.parts.len() * self.part_size) as u64;
+        file.seek(SeekFrom::Start(offset)).await?;
+        loop {
+            let part = read_part(&mut file, self.part_size).await?;
+            if part.is_empty() {
+                break;
+            }
+            let index = progress.parts.len();
+            let id = store
+                .put_part(key, &progress.id, index, part.into())
+                .await?;
+            progress.parts.push(id.content_id);
+            save(path, progress).await?;
+        }
+        let parts = progress
+            .parts
+            .iter()
+            .map(|content_id| PartId {
+                content_id: content_id.clone(),
+            })
+            .collect();
+        store.complete_multipart(key, &progress.id, parts).await?;
+        Ok(())
+    }
+
+    /// Upload every closed segment in the spool. Returns the first failure;
+    /// failed segments are left in place for the next scan.
+    async fn upload_ready(&self) -> Result<(), IngestError> {
+        let mut first_error = None;
+        for segment in ready_segments(&self.spool_dir)? {
+            let key = self.key(&segment);
+            match self.upload(&segment, &key).await {
+                Ok(()) => {
+                    tokio::fs::remove_file(&segment).await?;
+                    tracing::debug!("sink {} uploaded {}", self.name, key);
+                }
+                Err(e) => {
+                    tracing::warn!("sink {} failed to upload {}: {}", self.name, key, e);
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+}
+
+/// A failed resumable upload, telling the store's errors apart.
+#[derive(Debug)]
+enum Error {
+    Io(std::io::Error),
+    Store(object_store::Error),
+}
+
+impl Error {
+    fn into_ingest(self) -> IngestError {
+        match self {
+            Error::Io(e) => e.into(),
+            Error::Store(e) => store_err(e),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<object_store::Error> for Error {
+    fn from(e: object_store::Error) -> Self {
+        Error::Store(e)
+    }
+}
+
+fn store_err(e: object_store::Error) -> IngestError {
+    IngestError::Sink(e.to_string())
+}
+
+/// Where the progress of uploading `segment` is kept, hidden from scans.
+fn progress_path(segment: &Path) -> PathBuf {
+    let name = segment.file_name().unwrap_or_default().to_string_lossy();
+    segment.with_file_name(format!(".{}.upload", name))
+}
+
+async fn save(path: &Path, progress: &Progress) -> Result<(), Error> {
+    let data = serde_json::to_vec(progress).map_err(std::io::Error::other)?;
+    tokio::fs::write(path, data).await?;
+    Ok(())
+}
+
+/// The next `size` bytes of `file`, fewer at its end.
+async fn read_part(file: &mut tokio::fs::File, size: usize) -> std::io::Result<Vec<u8>> {
+    let mut part = Vec::with_capacity(size);
+    while part.len() < size {
+        let n = (&mut *file)
+            .take((size - part.len()) as u64)
+            .read_to_end(&mut part)
+            .await?;
+        if n == 0 {
+            break;
+        }
+    }
+    Ok(part)
+}
+
+#[async_trait]
+impl Sink for ArchiveSink {
+    async fn send(&mut self, event: &NormalizedEvent) -> Result<(), IngestError> {
+        let closed = match &mut self.spool {
+            Spool::Jsonl(spool) => spool.write(event)?,
+            #[cfg(feature = "parquet")]
+            Spool::Parquet(spool) => {
+                spool.send(event).await?;
+                false
+            }
+        };
+        if closed || self.uploading.is_none() {
+            self.upload();
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), IngestError> {
+        match &mut self.spool {
+            Spool::Jsonl(spool) => spool.close_all()?,
+            #[cfg(feature = "parquet")]
+            Spool::Parquet(spool) => spool.flush().await?,
+        }
+        self.upload();
+        Ok(())
+    }
+}
+
+/// Close the segments a crash left in progress in `root`. JSON lines keep
+/// the events written whole before the crash; Parquet files cannot be read
+/// without the footer written on closing, so they are discarded. Either
+/// way, a flush never confirmed their events.
+fn recover(name: &str, root: &Path, format: ArchiveFormat) -> Result<(), IngestError> {
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if file_name.ends_with(".recovering") {
+                // Left by a recovery cut short; its segment is still there.
+                fs::remove_file(&path)?;
+            } else if let Some(closed) = file_name
+                .strip_prefix('.')
+                .and_then(|name| name.strip_suffix(".inprogress"))
+            {
+                let closed = path.with_file_name(closed);
+                let kept = match format {
+                    ArchiveFormat::Jsonl => salvage(&path, &closed)?,
+                    ArchiveFormat::Parquet => 0,
+                };
+                fs::remove_file(&path)?;
+                tracing::warn!(
+                    "sink {} recovered {} events of the unclosed segment {}",
+                    name,
+                    kept,
+                    closed.display()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Write the whole lines of the JSON lines segment `orphan` to `closed`,
+/// compressed as its name says, and return how many there were.
+fn salvage(orphan: &Path, closed: &Path) -> Result<usize, IngestError> {
+    let name = closed.file_name().unwrap_or_default().to_string_lossy();
+    let compression = [Compression::Gzip, Compression::Zstd, Compression::Lz4]
+        .into_iter()
+        .find(|c| name.ends_with(compress::extension(*c)))
+        .unwrap_or(Compression::None);
+    let mut lines = BufReader::new(compress::decoder(File::open(orphan)?, compression)?);
+    let recovering = closed.with_file_name(format!(".{}.recovering", name));
+    let mut encoder = compress::Encoder::new(File::create(&recovering)?, compression)?;
+    let (mut line, mut kept) = (Vec::new(), 0);
+    // The crash may have cut the stream anywhere, even mid-line.
+    while matches!(lines.read_until(b'\n', &mut line), Ok(n) if n > 0) && line.ends_with(b"\n") {
+        encoder.write_all(&line)?;
+        line.clear();
+        kept += 1;
+    }
+    let file = encoder.finish()?;
+    if kept == 0 {
+        fs::remove_file(&recovering)?;
+        return Ok(0);
+    }
+    file.sync_all()?;
+    fs::rename(&recovering, closed)?;
+    Ok(kept)
+}
+
+/// Closed segments below `root`; in-progress segments are hidden files.
+fn ready_segments(root: &Path) -> Result<Vec<PathBuf>, IngestError> {
+    let mut ready = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let hidden = path
+                .file_name()
+                .is_some_and(|n| n.to_string_lossy().starts_with('.'));
+            if path.is_dir() {
+                dirs.push(path);
+            } else if !hidden {
+                ready.push(path);
+            }
+        }
+    }
+    ready.sort();
+    Ok(ready)
+}
+
+struct Segment {
+    encoder: compress::Encoder<File>,
+    bytes: u64,
+    opened: Instant,
+    tmp_path: PathBuf,
+    final_path: PathBuf,
+}
+
+/// Compressed JSON lines segments, one open segment per date and venue.
+struct JsonlSpool {
+    root: PathBuf,
+    compression: Compression,
+    segment_bytes: u64,
+    segment_age: Duration,
+    segments: HashMap<(NaiveDate, String), Segment>,
+    sequence: u64,
+    delivered: IntCounter,
+}
+
+impl JsonlSpool {
+    fn new(cfg: &ArchiveSinkConfig, delivered: IntCounter) -> Self {
+        Self {
+            root: PathBuf::from(&cfg.spool_dir),
+            compression: cfg.compression,
+            segment_bytes: cfg.segment_bytes,
+            segment_age: Duration::from_secs(cfg.segment_age_secs),
+            segments: HashMap::new(),
+            sequence: 0,
+            delivered,
+        }
+    }
+
+    fn open(&mut self, date: NaiveDate, venue: &str) -> Result<Segment, IngestError> {
+        let dir = self
+            .root
+            .join(format!("date={}", date.format("%Y-%m-%d")))
+            .join(format!("venue={}", venue));
+        fs::create_dir_all(&dir)?;
+        self.sequence += 1;
+        let file_name = format!(
+            "seg-{}-{:04}.jsonl{}",
+            Utc::now().format("%Y%m%dT%H%M%S%3f"),
+            self.sequence,
+            compress::extension(self.compression)
+        );
+        let tmp_path = dir.join(format!(".{}.inprogress", file_name));
+        Ok(Segment {
+            encoder: compress::Encoder::new(File::create(&tmp_path)?, self.compression)?,
+            bytes: 0,
+            opened: Instant::now(),
+            tmp_path,
+            final_path: dir.join(file_name),
+        })
+    }
+
+    /// Spool `event`, returning whether a segment closed.
+    fn write(&mut self, event: &NormalizedEvent) -> Result<bool, IngestError> {
+        let key = (event.timestamp.date_naive(), event.venue.clone());
+        let mut segment = match self.segments.remove(&key) {
+            Some(segment) => segment,
+            None => self.open(key.0, &key.1)?,
+        };
+        let mut line = serde_json::to_vec(event)?;
+        line.push(b'\n');
+        segment.encoder.write_all(&line)?;
+        segment.bytes += line.len() as u64;
+        self.delivered.inc();
+        self.segments.insert(key, segment);
+        self.rotate()
+    }
+
+    fn rotate(&mut self) -> Result<bool, IngestError> {
+        let due: Vec<_> = self
+            .segments
+            .iter()
+            .filter(|(_, s)| {
+                s.bytes >= self.segment_bytes || s.opened.elapsed() >= self.segment_age
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &due {
+            if let Some(segment) = self.segments.remove(key) {
+                close(segment)?;
+            }
+        }
+        Ok(!due.is_empty())
+    }
+
+    fn close_all(&mut self) -> Result<(), IngestError> {
+        for (_, segment) in std::mem::take(&mut self.segments) {
+            close(segment)?;
+        }
+        Ok(())
+    }
+}
+
+fn close(segment: Segment) -> Result<(), IngestError> {
+    let file = segment.encoder.finish()?;
+    file.sync_all()?;
+    fs::rename(&segment.tmp_path, &segment.final_path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+    use flate2::read::GzDecoder;
+    use ingest_core::event::EventKind;
+    use object_store::ObjectStoreExt;
+    use std::io::Read;
+
+    fn event(venue: &str) -> NormalizedEvent {
+        NormalizedEvent {
+            venue: venue.into(),
+            symbol: "BTCUSDT".into(),
+            timestamp: DateTime::from_timestamp_micros(1_672_515_782_136_000).unwrap(),
+            received_at: None,
+            kind: EventKind::Trade,
+            payload: serde_json::json!({"price": 1.5}),
+        }
+    }
+
+    #[tokio::test]
+    async fn uploads_segments_and_clears_spool() {
+        let root = std::env::temp_dir().join(format!("ingest-archive-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let dest = root.join("dest");
+        fs::create_dir_all(&dest).unwrap();
+        let cfg = ArchiveSinkConfig {
+            url: format!("file://{}", dest.display()),
+            format: ArchiveFormat::Jsonl,
+            compression: Compression::Gzip,
+            spool_dir: root.join("spool").to_string_lossy().into_owned(),
+            segment_bytes: u64::MAX,
+            segment_age_secs: 3_600,
+            // Force the multipart path.
+            part_size: 16,
+            options: Default::default(),
+        };
+        let metrics = SinkMetrics::new();
+        let mut sink = ArchiveSink::new("archive", &cfg, &metrics).unwrap();
+        for _ in 0..3 {
+            sink.send(&event("binance_spot")).await.unwrap();
+        }
+        sink.send(&event("coinbase")).await.unwrap();
+        sink.flush().await.unwrap();
+
+        let spool = Path::new(&cfg.spool_dir);
+        for _ in 0..100 {
+            if ready_segments(spool).unwrap().is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(ready_segments(spool).unwrap().is_empty());
+        let uploaded = ready_segments(&dest).unwrap();
+        assert_eq!(uploaded.len(), 2);
+        assert!(uploaded[0].starts_with(dest.join("date=2022-12-31/venue=binance_spot")));
+        assert!(uploaded[1].starts_with(dest.join("date=2022-12-31/venue=coinbase")));
+        let mut text = String::new();
+        GzDecoder::new(File::open(&uploaded[0]).unwrap())
+            .read_to_string(&mut text)
+            .unwrap();
+        let events: Vec<NormalizedEvent> = text
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(events, vec![event("binance_spot"); 3]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn resumes_a_multipart_upload_after_its_recorded_parts() {
+        let spool =
+            std::env::temp_dir().join(format!("ingest-archive-resume-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&spool);
+        let dir = spool.join("date=2022-12-31/venue=binance_spot");
+        fs::create_dir_all(&dir).unwrap();
+        let segment = dir.join("seg.jsonl");
+        fs::write(&segment, "0123456789").unwrap();
+        let store = Arc::new(object_store::memory::InMemory::new());
+        let uploader = Uploader {
+            name: "archive".into(),
+            store: store.clone(),
+            multipart: Some(store.clone()),
+            prefix: ObjectPath::from("archive"),
+            spool_dir: spool.clone(),
+            part_size: 4,
+            checksum_metadata: false,
+        };
+        // An earlier run uploaded the first part before failing.
+        let key = uploader.key(&segment);
+        let id = store.create_multipart(&key).await.unwrap();
+        let first = store.put_part(&key, &id, 0, "ABCD".into()).await.unwrap();
+        let progress = Progress {
+            id,
+            part_size: 4,
+            parts: vec![first.content_id],
+        };
+        save(&progress_path(&segment), &progress)
+            .await
+            .ok()
+            .unwrap();
+
+        uploader.upload_ready().await.unwrap();
+        let uploaded = store.get(&key).await.unwrap().bytes().await.unwrap();
+        assert_eq!(&uploaded[..], b"ABCD456789");
+        assert!(!segment.exists());
+        assert!(!progress_path(&segment).exists());
+        fs::remove_dir_all(&spool).unwrap();
+    }
+
+    #[test]
+    fn recovers_the_whole_lines_of_an_unclosed_segment() {
+        let spool =
+            std::env::temp_dir().join(format!("ingest-archive-recover-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&spool);
+        fs::create_dir_all(&spool).unwrap();
+        let mut line = serde_json::to_vec(&event("binance_spot")).unwrap();
+        line.push(b'\n');
+        let mut torn = line.repeat(2);
+        torn.extend_from_slice(&line[..10]);
+        let mut encoder = compress::Encoder::new(
+            File::create(spool.join(".seg.jsonl.gz.inprogress")).unwrap(),
+            Compression::Gzip,
+        )
+        .unwrap();
+        encoder.write_all(&torn).unwrap();
+        // Cut the stream short of its trailer, as a crash would.
+        encoder.flush().unwrap();
+        std::mem::forget(encoder);
+
+        recover("archive", &spool, ArchiveFormat::Jsonl).unwrap();
+        assert_eq!(
+            ready_segments(&spool).unwrap(),
+            vec![spool.join("seg.jsonl.gz")]
+        );
+        let data = fs::read(spool.join("seg.jsonl.gz")).unwrap();
+        assert_eq!(
+            compress::decompress(&data, Compression::Gzip).unwrap(),
+            line.repeat(2)
+        );
+        assert_eq!(fs::read_dir(&spool).unwrap().count(), 1);
+        fs::remove_dir_all(&spool).unwrap();
+    }
+}
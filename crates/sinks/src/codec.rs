@@ -0,0 +1,272 @@
+//! Wire encodings for events leaving the engine.
+//!
+//! Binary encodings carry the payload as a JSON string, since payload shape
+//! depends on the event kind and venue.
+
+use chrono::{DateTime, Utc};
+use ingest_core::{
+    config::Encoding,
+    error::IngestError,
+    event::{EventKind, NormalizedEvent},
+};
+
+/// The definition of [`EventRecord`] in protobuf's language, for consumers
+/// generating decoders of the `proto` encoding.
+pub const PROTO_SCHEMA: &str = r#"syntax = "proto3";
+
+package ingest.v1;
+
+// A normalized event as the `proto` encoding carries it.
+message EventRecord {
+  string venue = 1;
+  string symbol = 2;
+  // The event kind in snake case, e.g. `trade`.
+  string kind = 3;
+  // Microseconds since the Unix epoch.
+  int64 timestamp_us = 4;
+  optional int64 received_at_us = 5;
+  // Venue payload as JSON.
+  string payload_json = 6;
+}
+"#;
+
+/// Avro schema of an event as the `avro` encoding writes it; datums are
+/// written without a header.
+pub const AVRO_SCHEMA: &str = r#"{
+  "type": "record",
+  "name": "NormalizedEvent",
+  "namespace": "ingest",
+  "fields": [
+    {"name": "venue", "type": "string"},
+    {"name": "symbol", "type": "string"},
+    {"name": "kind", "type": "string"},
+    {"name": "timestamp_us", "type": "long"},
+    {"name": "received_at_us", "type": ["null", "long"], "default": null},
+    {"name": "payload", "type": "string"}
+  ]
+}"#;
+
+/// Protobuf representation of a [`NormalizedEvent`].
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct EventRecord {
+    #[prost(string, tag = "1")]
+    pub venue: String,
+    #[prost(string, tag = "2")]
+    pub symbol: String,
+    #[prost(string, tag = "3")]
+    pub kind: String,
+    #[prost(int64, tag = "4")]
+    pub timestamp_us: i64,
+    #[prost(int64, optional, tag = "5")]
+    pub received_at_us: Option<i64>,
+    #[prost(string, tag = "6")]
+    pub payload_json: String,
+}
+
+impl EventRecord {
+    pub fn from_event(event: &NormalizedEvent) -> Result<Self, IngestError> {
+        Ok(Self {
+            venue: event.venue.clone(),
+            symbol: event.symbol.clone(),
+            kind: event.kind.as_str().to_string(),
+            timestamp_us: event.timestamp.timestamp_micros(),
+            received_at_us: event.received_at.map(|t| t.timestamp_micros()),
+            payload_json: serde_json::to_string(&event.payload)?,
+        })
+    }
+
+    pub fn into_event(self) -> Result<NormalizedEvent, IngestError> {
+        Ok(NormalizedEvent {
+            venue: self.venue,
+            symbol: self.symbol,
+            timestamp: micros(self.timestamp_us)?,
+            received_at: self.received_at_us.map(micros).transpose()?,
+            kind: parse_kind(&self.kind)?,
+            payload: serde_json::from_str(&self.payload_json)?,
+        })
+    }
+}
+
+pub fn encode(event: &NormalizedEvent, encoding: Encoding) -> Result<Vec<u8>, IngestError> {
+    match encoding {
+        Encoding::Json => Ok(serde_json::to_vec(event)?),
+        Encoding::Proto => Ok(prost::Message::encode_to_vec(&EventRecord::from_event(
+            event,
+        )?)),
+        Encoding::Avro => avro::encode(event),
+    }
+}
+
+pub fn decode(bytes: &[u8], encoding: Encoding) -> Result<NormalizedEvent, IngestError> {
+    match encoding {
+        Encoding::Json => Ok(serde_json::from_slice(bytes)?),
+        Encoding::Proto => <EventRecord as prost::Message>::decode(bytes)
+            .map_err(|e| IngestError::Validation(e.to_string()))?
+            .into_event(),
+        Encoding::Avro => avro::decode(bytes),
+    }
+}
+
+fn micros(us: i64) -> Result<DateTime<Utc>, IngestError> {
+    DateTime::from_timestamp_micros(us)
+        .ok_or_else(|| IngestError::Validation(format!("timestamp {} out of range", us)))
+}
+
+fn parse_kind(kind: &str) -> Result<EventKind, IngestError> {
+    serde_json::from_value(serde_json::Value::String(kind.to_string()))
+        .map_err(|_| IngestError::Validation(format!("unknown event kind `{}`", kind)))
+}
+
+#[cfg(feature = "avro")]
+mod avro {
+    use std::sync::OnceLock;
+
+    use apache_avro::{
+        reader::datum::GenericDatumReader,
+        types::{Record, Value},
+        writer::datum::GenericDatumWriter,
+        Schema,
+    };
+
+    use super::*;
+
+    fn schema() -> &'static Schema {
+        static SCHEMA_CELL: OnceLock<Schema> = OnceLock::new();
+        SCHEMA_CELL.get_or_init(|| Schema::parse_str(AVRO_SCHEMA).expect("valid avro schema"))
+    }
+
+    fn avro_err(e: apache_avro::Error) -> IngestError {
+        IngestError::Validation(e.to_string())
+    }
+
+    pub fn encode(event: &NormalizedEvent) -> Result<Vec<u8>, IngestError> {
+        let record = EventRecord::from_event(event)?;
+        let mut datum = Record::new(schema())
+            .ok_or_else(|| IngestError::Validation("avro schema is not a record".into()))?;
+        datum.put("venue", record.venue);
+        datum.put("symbol", record.symbol);
+        datum.put("kind", record.kind);
+        datum.put("timestamp_us", record.timestamp_us);
+        datum.put(
+            "received_at_us",
+            match record.received_at_us {
+                Some(us) => Value::Union(1, Box::new(Value::Long(us))),
+                None => Value::Union(0, Box::new(Value::Null)),
+            },
+        );
+        datum.put("payload", record.payload_json);
+        GenericDatumWriter::builder(schema())
+            .build()
+            .and_then(|writer| writer.write_value_to_vec(datum))
+            .map_err(avro_err)
+    }
+
+    pub fn decode(mut bytes: &[u8]) -> Result<NormalizedEvent, IngestError> {
+        let value = GenericDatumReader::builder(schema())
+            .build()
+            .and_then(|reader| reader.read_value(&mut bytes))
+            .map_err(avro_err)?;
+        let Value::Record(fields) = value else {
+            return Err(IngestError::Validation("avro datum is not a record".into()));
+        };
+        let mut record = EventRecord::default();
+        for (name, value) in fields {
+            match (name.as_str(), value) {
+                ("venue", Value::String(s)) => record.venue = s,
+                ("symbol", Value::String(s)) => record.symbol = s,
+                ("kind", Value::String(s)) => record.kind = s,
+                ("timestamp_us", Value::Long(v)) => record.timestamp_us = v,
+                ("received_at_us", Value::Union(_, v)) => {
+                    if let Value::Long(v) = *v {
+                        record.received_at_us = Some(v);
+                    }
+                }
+                ("payload", Value::String(s)) => record.payload_json = s,
+                (name, _) => {
+                    return Err(IngestError::Validation(format!(
+                        "unexpected avro field `{}`",
+                        name
+                    )))
+                }
+            }
+        }
+        record.into_event()
+    }
+}
+
+#[cfg(not(feature = "avro"))]
+mod avro {
+    use super::*;
+
+    fn unsupported() -> IngestError {
+        IngestError::Validation("avro encoding requires the `avro` feature".into())
+    }
+
+    pub fn encode(_event: &NormalizedEvent) -> Result<Vec<u8>, IngestError> {
+        Err(unsupported())
+    }
+
+    pub fn decode(_bytes: &[u8]) -> Result<NormalizedEvent, IngestError> {
+        Err(unsupported())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event() -> NormalizedEvent {
+        NormalizedEvent {
+            venue: "binance_spot".into(),
+            symbol: "BTCUSDT".into(),
+            timestamp: DateTime::from_timestamp_micros(1_672_515_782_136_000).unwrap(),
+            received_at: DateTime::from_timestamp_micros(1_672_515_782_140_123),
+            kind: EventKind::Trade,
+            payload: serde_json::json!({"price": 1.5, "quantity": 2.0}),
+        }
+    }
+
+    #[test]
+    fn json_roundtrip() {
+        let bytes = encode(&event(), Encoding::Json).unwrap();
+        assert_eq!(decode(&bytes, Encoding::Json).unwrap(), event());
+    }
+
+    #[test]
+    fn proto_roundtrip() {
+        let bytes = encode(&event(), Encoding::Proto).unwrap();
+        assert_eq!(decode(&bytes, Encoding::Proto).unwrap(), event());
+    }
+
+    #[test]
+    fn proto_schema_declares_the_fields_of_records() {
+        let bytes = encode(&event(), Encoding::Proto).unwrap();
+        let mut buf = bytes.as_slice();
+        let mut encoded = Vec::new();
+        while !buf.is_empty() {
+            let (tag, wire_type) = prost::encoding::decode_key(&mut buf).unwrap();
+            prost::encoding::skip_field(wire_type, tag, &mut buf, Default::default()).unwrap();
+            encoded.push((tag, wire_type));
+        }
+        let declared: Vec<_> = PROTO_SCHEMA
+            .lines()
+            .filter_map(|line| {
+                let (field, tag) = line.trim().strip_suffix(';')?.split_once(" = ")?;
+                let wire_type = match field.split_whitespace().rev().nth(1)? {
+                    "string" => prost::encoding::WireType::LengthDelimited,
+                    "int64" => prost::encoding::WireType::Varint,
+                    other => panic!("unexpected type {}", other),
+                };
+                Some((tag.parse().ok()?, wire_type))
+            })
+            .collect();
+        assert_eq!(encoded, declared);
+    }
+
+    #[cfg(feature = "avro")]
+    #[test]
+    fn avro_roundtrip() {
+        let bytes = encode(&event(), Encoding::Avro).unwrap();
+        assert_eq!(decode(&bytes, Encoding::Avro).unwrap(), event());
+    }
+}
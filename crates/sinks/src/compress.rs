@@ -0,0 +1,176 @@
+//! Payload compression shared by the sinks.
+//!
+//! Gzip and LZ4 (frame format) are always available; zstd links libzstd and
+//! needs the `zstd` feature. Data compressed by any of them starts with the
+//! codec's magic number, which [`detect`] reads back.
+
+use std::io::{self, Read, Write};
+
+use flate2::{read::GzDecoder, write::GzEncoder};
+use ingest_core::{config::Compression, error::IngestError};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const LZ4_MAGIC: &[u8] = &[0x04, 0x22, 0x4d, 0x18];
+
+/// Fail early for a sink configured with a codec this build lacks.
+pub fn check(name: &str, compression: Compression) -> Result<(), IngestError> {
+    if compression == Compression::Zstd && !cfg!(feature = "zstd") {
+        return Err(crate::unsupported(name, "zstd"));
+    }
+    Ok(())
+}
+
+/// The codec `data` was compressed with, from its magic number, or
+/// [`Compression::None`] for data that starts with none of them, such as
+/// JSON. Consumers of sinks whose messages carry no headers, like Kinesis,
+/// tell compressed payloads apart with it.
+pub fn detect(data: &[u8]) -> Compression {
+    if data.starts_with(GZIP_MAGIC) {
+        Compression::Gzip
+    } else if data.starts_with(ZSTD_MAGIC) {
+        Compression::Zstd
+    } else if data.starts_with(LZ4_MAGIC) {
+        Compression::Lz4
+    } else {
+        Compression::None
+    }
+}
+
+/// Media type of data compressed with `compression`, e.g.
+/// `application/gzip`, for messages with a content type.
+pub fn media_type(compression: Compression) -> Option<&'static str> {
+    match compression {
+        Compression::None => None,
+        Compression::Gzip => Some("application/gzip"),
+        Compression::Zstd => Some("application/zstd"),
+        Compression::Lz4 => Some("application/x-lz4"),
+    }
+}
+
+/// File name suffix for data compressed with `compression`, e.g. `.gz`.
+pub fn extension(compression: Compression) -> &'static str {
+    match compression {
+        Compression::None => "",
+        Compression::Gzip => ".gz",
+        Compression::Zstd => ".zst",
+        Compression::Lz4 => ".lz4",
+    }
+}
+
+pub fn compress(data: &[u8], compression: Compression) -> Result<Vec<u8>, IngestError> {
+    if compression == Compression::None {
+        return Ok(data.to_vec());
+    }
+    let mut encoder = Encoder::new(Vec::new(), compression)?;
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+pub fn decompress(data: &[u8], compression: Compression) -> Result<Vec<u8>, IngestError> {
+    let mut out = Vec::new();
+    decoder(data, compression)?.read_to_end(&mut out)?;
+    Ok(out)
+}
+
+/// Streaming decompressor reading from `inner`.
+pub fn decoder<'a>(
+    inner: impl Read + 'a,
+    compression: Compression,
+) -> Result<Box<dyn Read + 'a>, IngestError> {
+    Ok(match compression {
+        Compression::None => Box::new(inner),
+        Compression::Gzip => Box::new(GzDecoder::new(inner)),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(inner)?),
+        #[cfg(not(feature = "zstd"))]
+        Compression::Zstd => return Err(zstd_missing()),
+        Compression::Lz4 => Box::new(lz4_flex::frame::FrameDecoder::new(inner)),
+    })
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_missing() -> IngestError {
+    IngestError::Validation("zstd requires the `zstd` feature".into())
+}
+
+/// Streaming compressor; call [`Encoder::finish`] to write the trailer.
+pub enum Encoder<W: Write> {
+    None(W),
+    Gzip(GzEncoder<W>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+    Lz4(lz4_flex::frame::FrameEncoder<W>),
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn new(inner: W, compression: Compression) -> Result<Self, IngestError> {
+        Ok(match compression {
+            Compression::None => Self::None(inner),
+            Compression::Gzip => Self::Gzip(GzEncoder::new(inner, flate2::Compression::default())),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Self::Zstd(zstd::stream::write::Encoder::new(inner, 0)?),
+            #[cfg(not(feature = "zstd"))]
+            Compression::Zstd => return Err(zstd_missing()),
+            Compression::Lz4 => Self::Lz4(lz4_flex::frame::FrameEncoder::new(inner)),
+        })
+    }
+
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Self::None(inner) => Ok(inner),
+            Self::Gzip(encoder) => encoder.finish(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(encoder) => encoder.finish(),
+            Self::Lz4(encoder) => encoder.finish().map_err(io::Error::other),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::None(inner) => inner.write(buf),
+            Self::Gzip(encoder) => encoder.write(buf),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(encoder) => encoder.write(buf),
+            Self::Lz4(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::None(inner) => inner.flush(),
+            Self::Gzip(encoder) => encoder.flush(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(encoder) => encoder.flush(),
+            Self::Lz4(encoder) => encoder.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrips_every_codec() {
+        let data = b"{\"venue\":\"binance_spot\",\"symbol\":\"BTCUSDT\"}\n".repeat(100);
+        let mut codecs = vec![Compression::None, Compression::Gzip, Compression::Lz4];
+        if cfg!(feature = "zstd") {
+            codecs.push(Compression::Zstd);
+        }
+        for compression in codecs {
+            let packed = compress(&data, compression).unwrap();
+            if compression != Compression::None {
+                assert!(
+                    packed.len() < data.len(),
+                    "{:?} did not compress",
+                    compression
+                );
+            }
+            assert_eq!(decompress(&packed, compression).unwrap(), data);
+            assert_eq!(detect(&packed), compression);
+        }
+    }
+}
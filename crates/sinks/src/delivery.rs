@@ -0,0 +1,702 @@
+//! At-least-once delivery between the bus and a sink.
+//!
+//! Events wait in a bounded retry buffer until the sink confirms them. An
+//! event the sink accepts is only delivered once a later `flush` succeeds,
+//! so events a batching sink buffered are confirmed along with its batch; a
+//! failed flush sends every unconfirmed event again. A rejected event is
+//! retried with exponential backoff, holding back the ones behind it so
+//! order is kept, and after `max_attempts` it is written to the dead-letter
+//! log. Once the buffer is full, new events overflow into the spill log and
+//! are read back in order as the buffer drains; the log is only cleared once
+//! everything read back has been confirmed, and events left in it by a
+//! previous run are delivered first. Past the shutdown deadline, whatever is
+//! still unconfirmed is kept in the spill log for the next run.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use ingest_core::{config::DeliveryConfig, error::IngestError, event::NormalizedEvent};
+use prometheus::{IntCounter, IntGauge};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use wal::{SpillLog, Wal};
+
+use crate::{Sink, SinkMetrics};
+
+/// An event awaiting delivery, and whether it was read back from the spill
+/// log.
+type Held = (NormalizedEvent, bool);
+
+pub(crate) struct Delivery {
+    name: String,
+    cfg: DeliveryConfig,
+    pending: VecDeque<Held>,
+    /// Events the sink accepted, oldest first, until a flush confirms them.
+    unconfirmed: VecDeque<Held>,
+    /// When the unconfirmed events are due to be flushed.
+    confirm_at: Option<Instant>,
+    /// Failed attempts since the last confirmation, for the event at the
+    /// front of `pending`.
+    attempts: u32,
+    retry_at: Option<Instant>,
+    /// The queue closed, so confirm without waiting for the interval.
+    closing: bool,
+    /// Overflow of the retry buffer.
+    spill: Option<SpillLog>,
+    dead_letter: Option<Wal>,
+    errors: IntCounter,
+    retries: IntCounter,
+    spilled: IntCounter,
+    dead_lettered: IntCounter,
+    depth: IntGauge,
+}
+
+impl Delivery {
+    pub(crate) fn open(
+        name: &str,
+        cfg: &DeliveryConfig,
+        metrics: &SinkMetrics,
+    ) -> Result<Self, IngestError> {
+        let context = |e: IngestError| IngestError::Sink(format!("sink {}: {}", name, e));
+        let spill = cfg
+            .spill
+            .as_ref()
+            .map(SpillLog::open_log)
+            .transpose()
+            .map_err(context)?;
+        let dead_letter = cfg
+            .dead_letter
+            .as_ref()
+            .map(Wal::open)
+            .transpose()
+            .map_err(context)?;
+        let label = [name];
+        Ok(Self {
+            name: name.to_string(),
+            cfg: cfg.clone(),
+            pending: VecDeque::new(),
+            unconfirmed: VecDeque::new(),
+            confirm_at: None,
+            attempts: 0,
+            retry_at: None,
+            closing: false,
+            spill,
+            dead_letter,
+            errors: metrics.errors(name),
+            retries: metrics.retries.with_label_values(&label),
+            spilled: metrics.spilled.with_label_values(&label),
+            dead_lettered: metrics.dead_lettered.with_label_values(&label),
+            depth: metrics.queue_depth(name),
+        })
+    }
+
+    fn buffer(&self) -> usize {
+        self.cfg.retry_buffer.max(1)
+    }
+
+    /// Events held in memory, sent or not.
+    fn held(&self) -> usize {
+        self.pending.len() + self.unconfirmed.len()
+    }
+
+    /// Whether another event can be taken off the queue.
+    fn has_room(&self) -> bool {
+        self.spill.is_some() || self.held() < self.buffer()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.held() == 0 && self.spill.as_ref().is_none_or(SpillLog::is_empty)
+    }
+
+    fn push(&mut self, event: NormalizedEvent) {
+        let full = self.held() >= self.buffer();
+        match self.spill.as_mut() {
+            // Once anything is spilled, later events follow it to keep order.
+            Some(spill) if full || !spill.is_empty() => match spill.append(&event) {
+                Ok(()) => self.spilled.inc(),
+                Err(e) => {
+                    tracing::warn!("sink {} failed to spill event: {}", self.name, e);
+                    self.pending.push_back((event, false));
+                }
+            },
+            _ => self.pending.push_back((event, false)),
+        }
+    }
+
+    fn refill(&mut self) {
+        let room = self.buffer().saturating_sub(self.held());
+        if let Some(spill) = self.spill.as_mut().filter(|spill| !spill.is_empty()) {
+            if room > 0 {
+                match spill.read_back(room) {
+                    Ok(events) => self
+                        .pending
+                        .extend(events.into_iter().map(|event| (event, true))),
+                    Err(e) => {
+                        tracing::warn!("sink {} failed to read spilled events: {}", self.name, e)
+                    }
+                }
+            }
+        }
+    }
+
+    /// `done` events read back from the spill log left the buffer.
+    fn settle(&mut self, done: usize) {
+        let Some(spill) = self.spill.as_mut() else {
+            return;
+        };
+        if let Err(e) = spill.settle(done) {
+            tracing::warn!("sink {} failed to clear its spill log: {}", self.name, e);
+        }
+    }
+
+    fn backoff(&self) -> Duration {
+        let exponent = self.attempts.saturating_sub(1).min(20);
+        let ms = self.cfg.initial_backoff_ms.saturating_mul(1 << exponent);
+        Duration::from_millis(ms.min(self.cfg.max_backoff_ms))
+    }
+
+    /// Whether the unconfirmed events should be flushed now: the interval
+    /// passed, the buffer holds nothing else, or the queue closed.
+    fn confirm_due(&self) -> bool {
+        !self.unconfirmed.is_empty()
+            && (self.closing
+                || self.unconfirmed.len() >= self.buffer()
+                || self.confirm_at.is_some_and(|at| Instant::now() >= at))
+    }
+
+    /// Send buffered events, and confirm them when due, until the sink
+    /// fails or nothing is left.
+    async fn pump(&mut self, sink: &mut dyn Sink) {
+        if self.retry_at.is_some_and(|at| Instant::now() < at) {
+            return;
+        }
+        self.retry_at = None;
+        loop {
+            self.refill();
+            if self.confirm_due() {
+                if let Err(e) = self.confirm(sink).await {
+                    if !self.failed(&e, "flush") {
+                        return;
+                    }
+                }
+                continue;
+            }
+            let Some((event, _)) = self.pending.front() else {
+                return;
+            };
+            match sink.send(event).await {
+                Ok(()) => {
+                    let held = self.pending.pop_front().unwrap();
+                    self.unconfirmed.push_back(held);
+                    let interval = Duration::from_millis(self.cfg.ack_interval_ms);
+                    self.confirm_at
+                        .get_or_insert_with(|| Instant::now() + interval);
+                }
+                Err(e) => {
+                    // A batching sink may have failed writing what it
+                    // accepted before, so that is confirmed first.
+                    let (error, what) = if self.unconfirmed.is_empty() {
+                        (e, "send event")
+                    } else {
+                        match self.confirm(sink).await {
+                            Ok(()) => (e, "send event"),
+                            Err(e) => (e, "flush"),
+                        }
+                    };
+                    if !self.failed(&error, what) {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Flush the sink to confirm the events it accepted. On failure they
+    /// go back to the front of the buffer to be sent again.
+    async fn confirm(&mut self, sink: &mut dyn Sink) -> Result<(), IngestError> {
+        self.confirm_at = None;
+        if let Err(e) = sink.flush().await {
+            while let Some(held) = self.unconfirmed.pop_back() {
+                self.pending.push_front(held);
+            }
+            return Err(e);
+        }
+        let settled = self.unconfirmed.drain(..).filter(|held| held.1).count();
+        self.attempts = 0;
+        if settled > 0 {
+            self.settle(settled);
+        }
+        Ok(())
+    }
+
+    /// Count a failed attempt for the event at the front of `pending`.
+    /// Returns whether to go on: the event was given up rather than
+    /// scheduled for a retry.
+    fn failed(&mut self, error: &IngestError, what: &str) -> bool {
+        self.errors.inc();
+        self.attempts += 1;
+        if self.attempts >= self.cfg.max_attempts {
+            let (event, spilled) = self.pending.pop_front().unwrap();
+            self.attempts = 0;
+            self.give_up(&event, error);
+            if spilled {
+                self.settle(1);
+            }
+            return true;
+        }
+        self.retries.inc();
+        let backoff = self.backoff();
+        tracing::warn!(
+            "sink {} failed to {}, retrying in {:?}: {}",
+            self.name,
+            what,
+            backoff,
+            error
+        );
+        self.retry_at = Some(Instant::now() + backoff);
+        false
+    }
+
+    fn give_up(&mut self, event: &NormalizedEvent, error: &IngestError) {
+        self.dead_lettered.inc();
+        let attempts = self.cfg.max_attempts;
+        match self.dead_letter.as_mut().map(|log| log.append(event)) {
+            Some(Ok(_)) => tracing::warn!(
+                "sink {} dead-lettered an event after {} attempts: {}",
+                self.name,
+                attempts,
+                error
+            ),
+            Some(Err(e)) => tracing::error!(
+                "sink {} dropped an event after {} attempts, dead-letter log failed: {}",
+                self.name,
+                attempts,
+                e
+            ),
+            None => tracing::warn!(
+                "sink {} dropped an event after {} attempts: {}",
+                self.name,
+                attempts,
+                error
+            ),
+        }
+    }
+
+    /// Keep what is unconfirmed, and what `rx` still queues, in the spill
+    /// log in order, so the next run delivers it first. Returns the events
+    /// lost: all of them without a spill log.
+    fn persist(&mut self, rx: &mut mpsc::Receiver<NormalizedEvent>) -> u64 {
+        let mut queued = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            queued.push(event);
+        }
+        let held = (self.held() + queued.len()) as u64;
+        let Some(spill) = self.spill.take() else {
+            if held > 0 {
+                tracing::error!(
+                    "sink {} gave up {} undelivered events at the shutdown deadline",
+                    self.name,
+                    held
+                );
+            }
+            return held;
+        };
+        let unconfirmed = self.unconfirmed.drain(..).chain(self.pending.drain(..));
+        match spill.keep(unconfirmed.map(|held| held.0).chain(queued)) {
+            Ok(()) => {
+                if held > 0 {
+                    tracing::warn!(
+                        "sink {} kept {} undelivered events in its spill log for the next run",
+                        self.name,
+                        held
+                    );
+                }
+                0
+            }
+            Err(e) => {
+                tracing::error!(
+                    "sink {} gave up {} undelivered events, spill log failed: {}",
+                    self.name,
+                    held,
+                    e
+                );
+                held
+            }
+        }
+    }
+
+    /// Stop delivering at the shutdown deadline: keep what is unconfirmed
+    /// and flush what the sink buffered.
+    async fn expire(
+        mut self,
+        mut sink: Box<dyn Sink>,
+        rx: &mut mpsc::Receiver<NormalizedEvent>,
+    ) -> u64 {
+        let lost = self.persist(rx);
+        if let Err(e) = sink.flush().await {
+            tracing::warn!("sink {} failed to flush: {}", self.name, e);
+        }
+        lost
+    }
+
+    fn sync(&mut self) {
+        let spill = self.spill.as_mut().map(SpillLog::sync_if_due);
+        let dead_letter = self.dead_letter.as_mut().map(Wal::sync_if_due);
+        for synced in [spill, dead_letter].into_iter().flatten() {
+            if let Err(e) = synced {
+                tracing::warn!("sink {} failed to sync delivery log: {}", self.name, e);
+            }
+        }
+    }
+}
+
+/// Drive `sink` from the queue `rx` with at-least-once delivery. When the
+/// queue closes, everything still held is delivered or dead-lettered and
+/// confirmed; once `expired` turns, what is unconfirmed is kept for the
+/// next run instead. Returns the events lost.
+pub(crate) fn spawn(
+    mut delivery: Delivery,
+    mut sink: Box<dyn Sink>,
+    mut rx: mpsc::Receiver<NormalizedEvent>,
+    mut expired: watch::Receiver<bool>,
+) -> JoinHandle<u64> {
+    tokio::spawn(async move {
+        let mut sync_tick = tokio::time::interval(Duration::from_millis(100));
+        let mut ticks = tokio::time::interval(crate::TICK);
+        loop {
+            let retry_at = delivery.retry_at.unwrap_or_else(Instant::now);
+            let confirm_at = delivery.confirm_at.unwrap_or_else(Instant::now);
+            tokio::select! {
+                event = rx.recv(), if delivery.has_room() => match event {
+                    Some(event) => {
+                        delivery.depth.set(rx.len() as i64);
+                        delivery.push(event);
+                    }
+                    None => break,
+                },
+                _ = tokio::time::sleep_until(retry_at.into()), if delivery.retry_at.is_some() => {}
+                _ = tokio::time::sleep_until(confirm_at.into()), if delivery.confirm_at.is_some() => {}
+                _ = sync_tick.tick() => delivery.sync(),
+                _ = ticks.tick() => {
+                    if let Err(e) = sink.tick().await {
+                        tracing::warn!("sink {} failed to write due batches: {}", delivery.name, e);
+                    }
+                }
+                Ok(()) = expired.changed() => return delivery.expire(sink, &mut rx).await,
+            }
+            delivery.pump(sink.as_mut()).await;
+        }
+        delivery.closing = true;
+        while !delivery.is_empty() {
+            tokio::select! {
+                _ = async {
+                    if let Some(at) = delivery.retry_at {
+                        tokio::time::sleep_until(at.into()).await;
+                    }
+                    delivery.pump(sink.as_mut()).await;
+                } => {}
+                Ok(()) = expired.changed() => return delivery.expire(sink, &mut rx).await,
+            }
+        }
+        0
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use ingest_core::{
+        config::{FsyncPolicy, WalConfig},
+        event::EventKind,
+    };
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+    use wal::WalReader;
+
+    /// Rejects each event the first `failures` times it is sent, and
+    /// `POISON` always.
+    struct Flaky {
+        failures: u32,
+        seen: u32,
+        delivered: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl Sink for Flaky {
+        async fn send(&mut self, event: &NormalizedEvent) -> Result<(), IngestError> {
+            if event.symbol == "POISON" || self.seen < self.failures {
+                self.seen += 1;
+                return Err(IngestError::Sink("unavailable".into()));
+            }
+            self.seen = 0;
+            self.delivered.lock().unwrap().push(event.symbol.clone());
+            Ok(())
+        }
+    }
+
+    /// Buffers events until flushed, losing the batch on the first
+    /// `failures` flushes.
+    struct Batching {
+        failures: u32,
+        batch: Vec<String>,
+        delivered: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl Sink for Batching {
+        async fn send(&mut self, event: &NormalizedEvent) -> Result<(), IngestError> {
+            self.batch.push(event.symbol.clone());
+            Ok(())
+        }
+
+        async fn flush(&mut self) -> Result<(), IngestError> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                self.batch.clear();
+                return Err(IngestError::Sink("write failed".into()));
+            }
+            self.delivered.lock().unwrap().append(&mut self.batch);
+            Ok(())
+        }
+    }
+
+    /// Writes its batch once it holds `size` events, failing the first
+    /// `failures` writes and keeping the batch for the next one, as the
+    /// batching sinks do.
+    struct Writing {
+        size: usize,
+        failures: u32,
+        batch: Vec<String>,
+        delivered: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Writing {
+        fn write(&mut self) -> Result<(), IngestError> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(IngestError::Sink("write failed".into()));
+            }
+            self.delivered.lock().unwrap().append(&mut self.batch);
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl Sink for Writing {
+        async fn send(&mut self, event: &NormalizedEvent) -> Result<(), IngestError> {
+            self.batch.push(event.symbol.clone());
+            if self.batch.len() >= self.size {
+                // Kept for the next write.
+                let _ = self.write();
+            }
+            Ok(())
+        }
+
+        async fn flush(&mut self) -> Result<(), IngestError> {
+            self.write()
+        }
+    }
+
+    fn event(symbol: &str) -> NormalizedEvent {
+        NormalizedEvent {
+            venue: "binance_spot".into(),
+            symbol: symbol.into(),
+            kind: EventKind::Trade,
+            ..Default::default()
+        }
+    }
+
+    /// Symbols of the events in the log at `path`.
+    fn logged(path: &std::path::Path) -> Vec<String> {
+        WalReader::open(path)
+            .from_sequence(0)
+            .unwrap()
+            .map(|entry| entry.unwrap().event.symbol)
+            .collect()
+    }
+
+    fn log(dir: &std::path::Path, name: &str) -> WalConfig {
+        WalConfig {
+            path: dir.join(name).to_string_lossy().into_owned(),
+            fsync: FsyncPolicy::Never,
+            fsync_interval_ms: 1000,
+            segment_bytes: 1024 * 1024,
+            retention_segments: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_in_order_and_dead_letters_poison() {
+        let dir = std::env::temp_dir().join(format!("ingest-delivery-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let cfg = DeliveryConfig {
+            retry_buffer: 2,
+            max_attempts: 3,
+            initial_backoff_ms: 1,
+            max_backoff_ms: 5,
+            ack_interval_ms: 1,
+            spill: Some(log(&dir, "spill")),
+            dead_letter: Some(log(&dir, "dlq")),
+        };
+        let metrics = SinkMetrics::new();
+        let delivery = Delivery::open("flaky", &cfg, &metrics).unwrap();
+        let delivered = Arc::new(Mutex::new(Vec::new()));
+        let sink = Flaky {
+            failures: 2,
+            seen: 0,
+            delivered: delivered.clone(),
+        };
+        let (tx, rx) = mpsc::channel(16);
+        for symbol in ["A", "POISON", "B", "C", "D"] {
+            tx.send(event(symbol)).await.unwrap();
+        }
+        drop(tx);
+        let (_expire, expired) = watch::channel(false);
+        spawn(delivery, Box::new(sink), rx, expired).await.unwrap();
+
+        assert_eq!(*delivered.lock().unwrap(), ["A", "B", "C", "D"]);
+        assert_eq!(metrics.dead_lettered.with_label_values(&["flaky"]).get(), 1);
+        assert!(metrics.spilled.with_label_values(&["flaky"]).get() > 0);
+        assert_eq!(logged(&dir.join("dlq")), ["POISON"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn keeps_undelivered_events_in_order_past_the_deadline() {
+        let dir = std::env::temp_dir().join(format!("ingest-persist-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let cfg = DeliveryConfig {
+            retry_buffer: 2,
+            max_attempts: u32::MAX,
+            initial_backoff_ms: 60_000,
+            max_backoff_ms: 60_000,
+            ack_interval_ms: 1,
+            spill: Some(log(&dir, "spill")),
+            dead_letter: None,
+        };
+        let metrics = SinkMetrics::new();
+        let delivery = Delivery::open("down", &cfg, &metrics).unwrap();
+        let sink = Flaky {
+            failures: u32::MAX,
+            seen: 0,
+            delivered: Arc::default(),
+        };
+        let (tx, rx) = mpsc::channel(16);
+        let (expire, expired) = watch::channel(false);
+        let task = spawn(delivery, Box::new(sink), rx, expired);
+        for symbol in ["A", "B", "C", "D"] {
+            tx.send(event(symbol)).await.unwrap();
+        }
+        // Held and spilled, with the sink rejecting them.
+        while metrics.spilled.with_label_values(&["down"]).get() < 2 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        expire.send(true).unwrap();
+        assert_eq!(task.await.unwrap(), 0);
+
+        assert_eq!(logged(&dir.join("spill")), ["A", "B", "C", "D"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn resends_a_batch_whose_flush_failed() {
+        let cfg = DeliveryConfig {
+            retry_buffer: 2,
+            max_attempts: 5,
+            initial_backoff_ms: 1,
+            max_backoff_ms: 5,
+            ack_interval_ms: 1,
+            spill: None,
+            dead_letter: None,
+        };
+        let metrics = SinkMetrics::new();
+        let delivery = Delivery::open("batching", &cfg, &metrics).unwrap();
+        let delivered = Arc::new(Mutex::new(Vec::new()));
+        let sink = Batching {
+            failures: 1,
+            batch: Vec::new(),
+            delivered: delivered.clone(),
+        };
+        let (tx, rx) = mpsc::channel(16);
+        for symbol in ["A", "B", "C", "D"] {
+            tx.send(event(symbol)).await.unwrap();
+        }
+        drop(tx);
+        let (_expire, expired) = watch::channel(false);
+        spawn(delivery, Box::new(sink), rx, expired).await.unwrap();
+
+        assert_eq!(*delivered.lock().unwrap(), ["A", "B", "C", "D"]);
+        assert_eq!(metrics.retries.with_label_values(&["batching"]).get(), 1);
+    }
+
+    #[tokio::test]
+    async fn delivers_a_batch_whose_write_failed_once() {
+        let cfg = DeliveryConfig {
+            retry_buffer: 8,
+            max_attempts: 5,
+            initial_backoff_ms: 1,
+            max_backoff_ms: 5,
+            ack_interval_ms: 60_000,
+            spill: None,
+            dead_letter: None,
+        };
+        let metrics = SinkMetrics::new();
+        let delivery = Delivery::open("writing", &cfg, &metrics).unwrap();
+        let delivered = Arc::new(Mutex::new(Vec::new()));
+        let sink = Writing {
+            size: 2,
+            failures: 1,
+            batch: Vec::new(),
+            delivered: delivered.clone(),
+        };
+        let (tx, rx) = mpsc::channel(16);
+        for symbol in ["A", "B", "C", "D", "E"] {
+            tx.send(event(symbol)).await.unwrap();
+        }
+        drop(tx);
+        let (_expire, expired) = watch::channel(false);
+        spawn(delivery, Box::new(sink), rx, expired).await.unwrap();
+
+        assert_eq!(*delivered.lock().unwrap(), ["A", "B", "C", "D", "E"]);
+        assert_eq!(metrics.errors("writing").get(), 0);
+    }
+
+    #[tokio::test]
+    async fn clears_the_spill_log_only_once_its_events_are_confirmed() {
+        let dir = std::env::temp_dir().join(format!("ingest-confirm-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let cfg = DeliveryConfig {
+            retry_buffer: 2,
+            max_attempts: 5,
+            initial_backoff_ms: 1,
+            max_backoff_ms: 5,
+            ack_interval_ms: 60_000,
+            spill: Some(log(&dir, "spill")),
+            dead_letter: None,
+        };
+        let metrics = SinkMetrics::new();
+        let mut delivery = Delivery::open("batching", &cfg, &metrics).unwrap();
+        let delivered = Arc::new(Mutex::new(Vec::new()));
+        let mut sink = Batching {
+            failures: 0,
+            batch: Vec::new(),
+            delivered: delivered.clone(),
+        };
+        for symbol in ["A", "B", "C"] {
+            delivery.push(event(symbol));
+        }
+        // A full buffer is confirmed at once, C is read back and sent, and
+        // waits for the interval.
+        delivery.pump(&mut sink).await;
+        assert_eq!(*delivered.lock().unwrap(), ["A", "B"]);
+        assert!(delivery.spill.as_ref().unwrap().is_empty());
+        assert_eq!(logged(&dir.join("spill")), ["C"]);
+
+        delivery.closing = true;
+        delivery.pump(&mut sink).await;
+        assert_eq!(*delivered.lock().unwrap(), ["A", "B", "C"]);
+        assert!(logged(&dir.join("spill")).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
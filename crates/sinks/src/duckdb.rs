@@ -0,0 +1,205 @@
+//! Embedded DuckDB sink for local analytics.
+//!
+//! Events are batched per kind and appended into one table per kind, named
+//! like the Postgres tables, in a local database file. Timestamps are stored
+//! as UTC `TIMESTAMP`s and payloads as JSON text, so a capture can be queried
+//! straight away, e.g. with `duckdb capture.db` and `json_extract`.
+//!
+//! Appending runs on the blocking pool, so a large batch does not hold up
+//! the runtime's workers while DuckDB writes it.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use duckdb::{params, Connection};
+use ingest_core::{
+    config::DuckDbSinkConfig,
+    error::IngestError,
+    event::{EventKind, NormalizedEvent},
+};
+use prometheus::IntCounter;
+
+use crate::{table, Sink, SinkMetrics};
+
+const KINDS: [EventKind; 5] = [
+    EventKind::Trade,
+    EventKind::Ticker,
+    EventKind::Quote,
+    EventKind::Book,
+    EventKind::Raw,
+];
+
+struct Batch {
+    rows: Vec<NormalizedEvent>,
+    started: Instant,
+}
+
+pub struct DuckDbSink {
+    conn: Arc<Mutex<Connection>>,
+    cfg: DuckDbSinkConfig,
+    batches: HashMap<EventKind, Batch>,
+    delivered: IntCounter,
+}
+
+impl DuckDbSink {
+    pub fn new(
+        name: &str,
+        cfg: &DuckDbSinkConfig,
+        metrics: &SinkMetrics,
+    ) -> Result<Self, IngestError> {
+        let err = |e: duckdb::Error| IngestError::Sink(format!("sink {}: {}", name, e));
+        let conn = Connection::open(&cfg.path).map_err(err)?;
+        for kind in KINDS {
+            conn.execute_batch(&format!(
+                "CREATE TABLE IF NOT EXISTS {} (
+                    venue VARCHAR NOT NULL,
+                    symbol VARCHAR NOT NULL,
+                    ts TIMESTAMP NOT NULL,
+                    received_at TIMESTAMP,
+                    payload VARCHAR NOT NULL
+                )",
+                table(kind)
+            ))
+            .map_err(err)?;
+        }
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            cfg: cfg.clone(),
+            batches: HashMap::new(),
+            delivered: metrics.delivered(name),
+        })
+    }
+
+    /// Append `rows` on the blocking pool.
+    async fn append(&self, kind: EventKind, rows: Vec<NormalizedEvent>) -> Result<(), IngestError> {
+        let conn = self.conn.clone();
+        let count = rows.len() as u64;
+        tokio::task::spawn_blocking(move || append(&conn.lock().unwrap(), kind, &rows))
+            .await
+            .map_err(|e| IngestError::Sink(e.to_string()))??;
+        self.delivered.inc_by(count);
+        Ok(())
+    }
+
+    /// Append every batch that is full or old enough, or all of them when
+    /// `all` is set.
+    async fn drain(&mut self, all: bool) -> Result<(), IngestError> {
+        let interval = Duration::from_millis(self.cfg.flush_interval_ms);
+        let due: Vec<EventKind> = self
+            .batches
+            .iter()
+            .filter(|(_, b)| {
+                all || b.rows.len() >= self.cfg.batch_size || b.started.elapsed() >= interval
+            })
+            .map(|(kind, _)| *kind)
+            .collect();
+        for kind in due {
+            if let Some(batch) = self.batches.remove(&kind) {
+                let rows = batch.rows.len();
+                if let Err(e) = self.append(kind, batch.rows).await {
+                    return Err(IngestError::Sink(format!(
+                        "dropped {} {} rows: {}",
+                        rows,
+                        table(kind),
+                        e
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Sink for DuckDbSink {
+    async fn send(&mut self, event: &NormalizedEvent) -> Result<(), IngestError> {
+        self.batches
+            .entry(event.kind)
+            .or_insert_with(|| Batch {
+                rows: Vec::new(),
+                started: Instant::now(),
+            })
+            .rows
+            .push(event.clone());
+        self.drain(false).await
+    }
+
+    async fn flush(&mut self) -> Result<(), IngestError> {
+        self.drain(true).await
+    }
+
+    async fn tick(&mut self) -> Result<(), IngestError> {
+        self.drain(false).await
+    }
+}
+
+fn append(conn: &Connection, kind: EventKind, rows: &[NormalizedEvent]) -> Result<(), IngestError> {
+    let mut appender = conn.appender(table(kind)).map_err(duck_err)?;
+    for event in rows {
+        appender
+            .append_row(params![
+                event.venue,
+                event.symbol,
+                event.timestamp.naive_utc(),
+                event.received_at.map(|t| t.naive_utc()),
+                serde_json::to_string(&event.payload)?,
+            ])
+            .map_err(duck_err)?;
+    }
+    appender.flush().map_err(duck_err)
+}
+
+fn duck_err(e: duckdb::Error) -> IngestError {
+    IngestError::Sink(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn appends_events_into_per_kind_tables() {
+        let path = std::env::temp_dir().join(format!("ingest-duckdb-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let cfg = DuckDbSinkConfig {
+            path: path.to_string_lossy().into_owned(),
+            batch_size: 2,
+            flush_interval_ms: 60_000,
+        };
+        let mut sink = DuckDbSink::new("duckdb", &cfg, &SinkMetrics::new()).unwrap();
+        for (kind, price) in [
+            (EventKind::Trade, 1.5),
+            (EventKind::Trade, 2.5),
+            (EventKind::Quote, 3.5),
+        ] {
+            let event = NormalizedEvent {
+                venue: "binance_spot".into(),
+                symbol: "BTCUSDT".into(),
+                kind,
+                payload: serde_json::json!({ "price": price }),
+                ..Default::default()
+            };
+            sink.send(&event).await.unwrap();
+        }
+        sink.flush().await.unwrap();
+
+        let conn = sink.conn.lock().unwrap();
+        let total: f64 = conn
+            .query_row(
+                "SELECT sum(CAST(json_extract(payload, '$.price') AS DOUBLE)) FROM trades",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(total, 4.0);
+        let quotes: i64 = conn
+            .query_row("SELECT count(*) FROM quotes", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(quotes, 1);
+        drop(conn);
+        drop(sink);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,328 @@
+//! Azure Event Hubs sink over AMQP.
+//!
+//! Events are grouped by a partition key rendered from the configured
+//! template, so each symbol is hashed to one partition and stays in order.
+//! Each group is sent as size-checked AMQP batches. Compressed events carry
+//! the codec's media type, e.g. `application/gzip`, as their content type.
+//!
+//! A group that fails to send is kept for another try after a backoff; a
+//! flush sends it or gives it up and fails.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use azeventhubs::{
+    authorization::AzureSasCredential,
+    producer::{
+        CreateBatchOptions, EventHubProducerClient, EventHubProducerClientOptions, SendEventOptions,
+    },
+    BasicRetryPolicy, EventData,
+};
+use ingest_core::{
+    config::{Compression, EventHubsSinkConfig},
+    error::IngestError,
+    event::NormalizedEvent,
+};
+use prometheus::IntCounter;
+
+use crate::retry::Retry;
+use crate::{codec, compress, render_topic, Sink, SinkMetrics};
+
+type Producer = EventHubProducerClient<BasicRetryPolicy>;
+
+pub struct EventHubsSink {
+    name: String,
+    cfg: EventHubsSinkConfig,
+    /// Connected on first use, since connecting needs the runtime.
+    producer: Option<Producer>,
+    groups: HashMap<String, Vec<Vec<u8>>>,
+    buffered: usize,
+    started: Instant,
+    retry: Retry,
+    delivered: IntCounter,
+}
+
+impl EventHubsSink {
+    pub fn new(
+        name: &str,
+        cfg: &EventHubsSinkConfig,
+        metrics: &SinkMetrics,
+    ) -> Result<Self, IngestError> {
+        compress::check(name, cfg.compression)?;
+        if cfg.connection_string.is_none() && cfg.namespace.is_none() {
+            return Err(IngestError::Validation(format!(
+                "sink {}: event hubs needs a connection_string or a namespace",
+                name
+            )));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            cfg: cfg.clone(),
+            producer: None,
+            groups: HashMap::new(),
+            buffered: 0,
+            started: Instant::now(),
+            retry: Retry::new(name, metrics),
+            delivered: metrics.delivered(name),
+        })
+    }
+
+    async fn connect(&self) -> Result<Producer, azure_core::Error> {
+        let options = EventHubProducerClientOptions::default();
+        let event_hub = self.cfg.event_hub.clone();
+        match (&self.cfg.connection_string, &self.cfg.namespace) {
+            (Some(connection_string), _) => {
+                Producer::new_from_connection_string(
+                    connection_string.clone(),
+                    Some(event_hub),
+                    options,
+                )
+                .await
+            }
+            (None, Some(namespace)) => match &self.cfg.sas_token {
+                Some(token) => {
+                    Producer::new_from_sas_credential(
+                        namespace.clone(),
+                        event_hub,
+                        AzureSasCredential::new(token.clone()),
+                        options,
+                    )
+                    .await
+                }
+                None => {
+                    let credential = azure_identity::DefaultAzureCredential::create(
+                        azure_identity::TokenCredentialOptions::default(),
+                    )?;
+                    Producer::new_from_credential(namespace.clone(), event_hub, credential, options)
+                        .await
+                }
+            },
+            (None, None) => unreachable!("checked in new"),
+        }
+    }
+
+    async fn send_group(
+        producer: &mut Producer,
+        key: &str,
+        bodies: &[Vec<u8>],
+        compression: Compression,
+    ) -> Result<(), azure_core::Error> {
+        let batch_options = || CreateBatchOptions::new().with_partition_key(key);
+        let send_options = || SendEventOptions::new().with_partition_key(key);
+        let mut batch = producer.create_batch(batch_options()).await?;
+        for body in bodies {
+            if batch.try_add(event_data(body.clone(), compression)).is_ok() {
+                continue;
+            }
+            // The batch is full: send it and start the next one with this event.
+            let full = std::mem::replace(&mut batch, producer.create_batch(batch_options()).await?);
+            if !full.is_empty() {
+                producer.send_batch(full, send_options()).await?;
+            }
+            if batch
+                .try_add(event_data(body.clone(), compression))
+                .is_err()
+            {
+                return Err(azure_core::Error::message(
+                    azure_core::error::ErrorKind::DataConversion,
+                    "event larger than the maximum batch size",
+                ));
+            }
+        }
+        if !batch.is_empty() {
+            producer.send_batch(batch, send_options()).await?;
+        }
+        Ok(())
+    }
+
+    /// Whether the groups hold a full batch or have waited long enough.
+    fn due(&self) -> bool {
+        self.buffered >= self.cfg.batch_size
+            || self.started.elapsed() >= Duration::from_millis(self.cfg.flush_interval_ms)
+    }
+
+    /// Send every group. A group that fails is kept for a retry after a
+    /// backoff; with `all`, what fails is dropped instead and the error
+    /// returned.
+    async fn send_all(&mut self, all: bool) -> Result<(), IngestError> {
+        if self.buffered == 0 {
+            return Ok(());
+        }
+        if !self.retry.ready(all) {
+            self.trim();
+            return Ok(());
+        }
+        let Err(e) = self.send_groups().await else {
+            self.retry.succeeded();
+            return Ok(());
+        };
+        if all {
+            self.groups.clear();
+            let dropped = std::mem::take(&mut self.buffered);
+            return Err(self.retry.give_up(dropped, e));
+        }
+        self.retry.failed(&e);
+        self.trim();
+        Ok(())
+    }
+
+    /// Send the groups, removing each one sent. Returns the last failure.
+    async fn send_groups(&mut self) -> Result<(), IngestError> {
+        let producer = match self.producer.as_mut() {
+            Some(producer) => producer,
+            None => {
+                let producer = self
+                    .connect()
+                    .await
+                    .map_err(|e| IngestError::Sink(e.to_string()))?;
+                tracing::info!("sink {} connected to {}", self.name, self.cfg.event_hub);
+                self.producer.insert(producer)
+            }
+        };
+        let mut failed = None;
+        let keys: Vec<String> = self.groups.keys().cloned().collect();
+        for key in keys {
+            let bodies = &self.groups[&key];
+            match Self::send_group(producer, &key, bodies, self.cfg.compression).await {
+                Ok(()) => {
+                    let count = bodies.len();
+                    self.groups.remove(&key);
+                    self.buffered -= count;
+                    self.delivered.inc_by(count as u64);
+                }
+                Err(e) => failed = Some(IngestError::Sink(e.to_string())),
+            }
+        }
+        match failed {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Drop the oldest events of the largest groups past what is kept while
+    /// sending fails.
+    fn trim(&mut self) {
+        let limit = Retry::limit(self.cfg.batch_size);
+        let mut dropped = 0;
+        while self.buffered > limit {
+            let Some(bodies) = self.groups.values_mut().max_by_key(|bodies| bodies.len()) else {
+                break;
+            };
+            let excess = (self.buffered - limit).min(bodies.len());
+            bodies.drain(..excess);
+            self.buffered -= excess;
+            dropped += excess;
+        }
+        self.groups.retain(|_, bodies| !bodies.is_empty());
+        self.retry.trimmed(dropped);
+    }
+}
+
+/// An event with `body`, marked with the media type of `compression`.
+fn event_data(body: Vec<u8>, compression: Compression) -> EventData {
+    let mut data = EventData::new(body);
+    if let Some(media_type) = compress::media_type(compression) {
+        data.set_content_type(media_type.to_string());
+    }
+    data
+}
+
+#[async_trait]
+impl Sink for EventHubsSink {
+    async fn send(&mut self, event: &NormalizedEvent) -> Result<(), IngestError> {
+        if self.buffered == 0 {
+            self.started = Instant::now();
+        }
+        let key = render_topic(&self.cfg.partition_key, event);
+        self.groups.entry(key).or_default().push(compress::compress(
+            &codec::encode(event, self.cfg.encoding)?,
+            self.cfg.compression,
+        )?);
+        self.buffered += 1;
+        if self.due() {
+            return self.send_all(false).await;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), IngestError> {
+        self.send_all(true).await
+    }
+
+    async fn tick(&mut self) -> Result<(), IngestError> {
+        if self.buffered > 0 && self.due() {
+            return self.send_all(false).await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ingest_core::{
+        config::{Compression, Encoding},
+        event::EventKind,
+    };
+
+    fn config() -> EventHubsSinkConfig {
+        EventHubsSinkConfig {
+            connection_string: None,
+            namespace: Some("ingest.servicebus.windows.net".into()),
+            sas_token: None,
+            event_hub: "ticks".into(),
+            encoding: Encoding::Json,
+            partition_key: "{venue}.{symbol}".into(),
+            batch_size: 100,
+            flush_interval_ms: 60_000,
+            compression: Compression::None,
+        }
+    }
+
+    #[tokio::test]
+    async fn groups_events_by_partition_key() {
+        let metrics = SinkMetrics::new();
+        let mut sink = EventHubsSink::new("hub", &config(), &metrics).unwrap();
+        for symbol in ["BTCUSDT", "ETHUSDT", "BTCUSDT"] {
+            let event = NormalizedEvent {
+                venue: "binance_spot".into(),
+                symbol: symbol.into(),
+                kind: EventKind::Trade,
+                ..Default::default()
+            };
+            sink.send(&event).await.unwrap();
+        }
+        assert_eq!(sink.buffered, 3);
+        assert_eq!(sink.groups["binance_spot.BTCUSDT"].len(), 2);
+        assert_eq!(sink.groups["binance_spot.ETHUSDT"].len(), 1);
+    }
+
+    #[test]
+    fn needs_a_connection_string_or_namespace() {
+        let metrics = SinkMetrics::new();
+        let cfg = EventHubsSinkConfig {
+            namespace: None,
+            ..config()
+        };
+        assert!(EventHubsSink::new("hub", &cfg, &metrics).is_err());
+    }
+
+    #[test]
+    fn trims_the_largest_groups_past_the_held_batches() {
+        let metrics = SinkMetrics::new();
+        let cfg = EventHubsSinkConfig {
+            batch_size: 1,
+            ..config()
+        };
+        let mut sink = EventHubsSink::new("hub", &cfg, &metrics).unwrap();
+        sink.groups.insert("a".into(), vec![vec![0]; 12]);
+        sink.groups.insert("b".into(), vec![vec![0]; 2]);
+        sink.buffered = 14;
+        sink.trim();
+        assert_eq!(sink.buffered, Retry::limit(1));
+        assert_eq!(sink.groups["a"].len(), 8);
+        assert_eq!(sink.groups["b"].len(), 2);
+        assert_eq!(metrics.errors("hub").get(), 4);
+    }
+}
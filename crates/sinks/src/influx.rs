@@ -0,0 +1,370 @@
+//! InfluxDB 2.x sink writing line protocol over the HTTP write API.
+//!
+//! Each event becomes one point: the measurement is the event kind, `venue`
+//! and `symbol` are tags, and top-level payload members become fields. The
+//! decimal strings trades and quotes carry their prices and quantities in
+//! are written as float fields.
+//!
+//! Points whose write fails stay buffered and are written again after a
+//! backoff; a flush that still cannot write them drops them and fails.
+
+use std::fmt::Write as _;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use ingest_core::{
+    config::{Compression, InfluxSinkConfig},
+    error::IngestError,
+    event::{EventKind, NormalizedEvent},
+};
+use prometheus::IntCounter;
+use serde_json::Value;
+
+use crate::retry::Retry;
+use crate::{compress, Sink, SinkMetrics};
+
+pub struct InfluxSink {
+    client: reqwest::Client,
+    write_url: String,
+    buckets_url: String,
+    cfg: InfluxSinkConfig,
+    body: String,
+    points: usize,
+    started: Instant,
+    retry: Retry,
+    delivered: IntCounter,
+}
+
+impl InfluxSink {
+    pub fn new(
+        name: &str,
+        cfg: &InfluxSinkConfig,
+        metrics: &SinkMetrics,
+    ) -> Result<Self, IngestError> {
+        if !matches!(cfg.compression, Compression::None | Compression::Gzip) {
+            return Err(IngestError::Validation(format!(
+                "sink {}: influx accepts gzip compression only",
+                name
+            )));
+        }
+        let api = |path: &str| api_url(name, &cfg.url, path);
+        let mut url = api("api/v2/write")?;
+        url.query_pairs_mut()
+            .append_pair("org", &cfg.org)
+            .append_pair("bucket", &cfg.bucket)
+            .append_pair("precision", "us");
+        let mut buckets_url = api("api/v2/buckets")?;
+        buckets_url
+            .query_pairs_mut()
+            .append_pair("org", &cfg.org)
+            .append_pair("name", &cfg.bucket);
+        Ok(Self {
+            client: reqwest::Client::new(),
+            write_url: url.to_string(),
+            buckets_url: buckets_url.to_string(),
+            cfg: cfg.clone(),
+            body: String::new(),
+            points: 0,
+            started: Instant::now(),
+            retry: Retry::new(name, metrics),
+            delivered: metrics.delivered(name),
+        })
+    }
+
+    /// Whether the batch is full or old enough to write.
+    fn due(&self) -> bool {
+        self.points >= self.cfg.batch_size
+            || self.started.elapsed() >= Duration::from_millis(self.cfg.flush_interval_ms)
+    }
+
+    /// Write the buffered points. A failed write keeps them for a retry
+    /// after a backoff; with `all` they are dropped instead and the error
+    /// returned.
+    async fn write(&mut self, all: bool) -> Result<(), IngestError> {
+        if self.points == 0 {
+            return Ok(());
+        }
+        if !self.retry.ready(all) {
+            self.trim();
+            return Ok(());
+        }
+        match self.post().await {
+            Ok(()) => {
+                self.delivered.inc_by(self.points as u64);
+                self.body.clear();
+                self.points = 0;
+                self.retry.succeeded();
+                Ok(())
+            }
+            Err(e) if all => {
+                self.body.clear();
+                let points = std::mem::take(&mut self.points);
+                Err(self.retry.give_up(points, e))
+            }
+            Err(e) => {
+                self.retry.failed(&e);
+                self.trim();
+                Ok(())
+            }
+        }
+    }
+
+    async fn post(&self) -> Result<(), IngestError> {
+        let mut request = self
+            .client
+            .post(&self.write_url)
+            .header("Authorization", format!("Token {}", self.cfg.token))
+            .header("Content-Type", "text/plain; charset=utf-8");
+        if self.cfg.compression == Compression::Gzip {
+            request = request.header("Content-Encoding", "gzip");
+        }
+        let body = compress::compress(self.body.as_bytes(), self.cfg.compression)?;
+        let response = request
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| IngestError::Sink(e.to_string()))?;
+        let status = response.status();
+        if !status.is_success() {
+            let detail = response.text().await.unwrap_or_default();
+            return Err(IngestError::Sink(format!(
+                "influx returned {}: {}",
+                status, detail
+            )));
+        }
+        Ok(())
+    }
+
+    /// Drop the oldest points past what is kept while writes fail.
+    fn trim(&mut self) {
+        let excess = self
+            .points
+            .saturating_sub(Retry::limit(self.cfg.batch_size));
+        if excess == 0 {
+            return;
+        }
+        let cut = self
+            .body
+            .match_indices('\n')
+            .nth(excess - 1)
+            .map_or(self.body.len(), |(at, _)| at + 1);
+        self.body.drain(..cut);
+        self.points -= excess;
+        self.retry.trimmed(excess);
+    }
+}
+
+#[async_trait]
+impl Sink for InfluxSink {
+    async fn send(&mut self, event: &NormalizedEvent) -> Result<(), IngestError> {
+        if self.points == 0 {
+            self.started = Instant::now();
+        }
+        self.body.push_str(&line(event));
+        self.body.push('\n');
+        self.points += 1;
+        if self.due() {
+            return self.write(false).await;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), IngestError> {
+        self.write(true).await
+    }
+
+    async fn tick(&mut self) -> Result<(), IngestError> {
+        if self.points > 0 && self.due() {
+            return self.write(false).await;
+        }
+        Ok(())
+    }
+
+    /// Look the bucket up with the token.
+    async fn check(&mut self) -> Result<(), IngestError> {
+        let response = self
+            .client
+            .get(&self.buckets_url)
+            .header("Authorization", format!("Token {}", self.cfg.token))
+            .send()
+            .await
+            .map_err(|e| IngestError::Sink(format!("{}: {}", self.cfg.url, e)))?;
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        if !status.is_success() {
+            return Err(IngestError::Sink(format!(
+                "influx returned {}: {}",
+                status, body
+            )));
+        }
+        let found: Value = serde_json::from_str(&body)?;
+        if found["buckets"].as_array().is_none_or(Vec::is_empty) {
+            return Err(IngestError::Sink(format!(
+                "bucket {} not found in org {}",
+                self.cfg.bucket, self.cfg.org
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Render `event` as a single line-protocol point with microsecond precision.
+pub fn line(event: &NormalizedEvent) -> String {
+    let mut out = String::new();
+    out.push_str(&escape(event.kind.as_str(), &[',', ' ']));
+    let _ = write!(
+        out,
+        ",venue={},symbol={} ",
+        escape(&event.venue, &[',', '=', ' ']),
+        escape(&event.symbol, &[',', '=', ' '])
+    );
+    let decimals = matches!(event.kind, EventKind::Trade | EventKind::Quote);
+    let mut fields = Vec::new();
+    if let Value::Object(map) = &event.payload {
+        for (key, value) in map {
+            if let Some(field) = field_value(value, decimals) {
+                fields.push(format!("{}={}", escape(key, &[',', '=', ' ']), field));
+            }
+        }
+    }
+    if fields.is_empty() {
+        // Influx requires at least one field per point.
+        fields.push(format!(
+            "payload={}",
+            string_field(&event.payload.to_string())
+        ));
+    }
+    out.push_str(&fields.join(","));
+    let _ = write!(out, " {}", event.timestamp.timestamp_micros());
+    out
+}
+
+/// A field of `value`, with strings holding a number written as floats
+/// when they are `decimals`.
+fn field_value(value: &Value, decimals: bool) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Number(n) if n.is_i64() || n.is_u64() => Some(format!("{}i", n)),
+        Value::Number(n) => Some(n.to_string()),
+        Value::String(s) if decimals && s.parse::<f64>().is_ok_and(f64::is_finite) => {
+            Some(s.clone())
+        }
+        Value::String(s) => Some(string_field(s)),
+        nested => Some(string_field(&nested.to_string())),
+    }
+}
+
+/// `path` under the server at `base`, which is taken as a directory whether
+/// or not it ends in `/`, so that an InfluxDB behind a path prefix such as
+/// `http://proxy/influx` keeps it.
+fn api_url(name: &str, base: &str, path: &str) -> Result<reqwest::Url, IngestError> {
+    let invalid = |e| IngestError::Sink(format!("sink {}: {}", name, e));
+    let mut base = reqwest::Url::parse(base).map_err(invalid)?;
+    if !base.path().ends_with('/') {
+        let dir = format!("{}/", base.path());
+        base.set_path(&dir);
+    }
+    base.join(path).map_err(invalid)
+}
+
+fn string_field(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn escape(s: &str, special: &[char]) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if special.contains(&c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+    use ingest_core::event::EventKind;
+
+    fn event(payload: Value) -> NormalizedEvent {
+        NormalizedEvent {
+            venue: "binance_spot".into(),
+            symbol: "BTCUSDT".into(),
+            timestamp: DateTime::from_timestamp_micros(1_672_515_782_136_000).unwrap(),
+            received_at: None,
+            kind: EventKind::Trade,
+            payload,
+        }
+    }
+
+    #[test]
+    fn trade_renders_typed_fields() {
+        let evt = event(serde_json::json!({
+            "trade_id": 12345,
+            "price": "16500.50",
+            "quantity": "0.25",
+            "side": "buy",
+        }));
+        assert_eq!(
+            line(&evt),
+            "trade,venue=binance_spot,symbol=BTCUSDT \
+             price=16500.50,quantity=0.25,side=\"buy\",trade_id=12345i 1672515782136000"
+        );
+    }
+
+    #[test]
+    fn api_urls_keep_the_base_path() {
+        for base in ["http://proxy:8086/influx", "http://proxy:8086/influx/"] {
+            assert_eq!(
+                api_url("influx", base, "api/v2/write").unwrap().as_str(),
+                "http://proxy:8086/influx/api/v2/write"
+            );
+        }
+        assert_eq!(
+            api_url("influx", "http://localhost:8086", "api/v2/write")
+                .unwrap()
+                .as_str(),
+            "http://localhost:8086/api/v2/write"
+        );
+    }
+
+    #[test]
+    fn escapes_tags_and_falls_back_to_payload_field() {
+        let mut evt = event(serde_json::json!(["a \"b\""]));
+        evt.symbol = "BTC USDT".into();
+        assert_eq!(
+            line(&evt),
+            r#"trade,venue=binance_spot,symbol=BTC\ USDT payload="[\"a \\\"b\\\"\"]" 1672515782136000"#
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_failed_batches_until_a_flush() {
+        let cfg = InfluxSinkConfig {
+            url: "http://127.0.0.1:1".into(),
+            org: "ingest".into(),
+            bucket: "ticks".into(),
+            token: "token".into(),
+            batch_size: 2,
+            flush_interval_ms: 60_000,
+            compression: Compression::None,
+        };
+        let metrics = SinkMetrics::new();
+        let mut sink = InfluxSink::new("influx", &cfg, &metrics).unwrap();
+        for _ in 0..3 {
+            sink.send(&event(serde_json::json!({"price": "1.5"})))
+                .await
+                .unwrap();
+        }
+        assert_eq!(sink.points, 3);
+        assert_eq!(sink.body.lines().count(), 3);
+        assert_eq!(metrics.errors("influx").get(), 0);
+
+        assert!(sink.flush().await.is_err());
+        assert_eq!(sink.points, 0);
+        assert_eq!(metrics.errors("influx").get(), 3);
+    }
+}
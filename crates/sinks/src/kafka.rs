@@ -0,0 +1,165 @@
+//! Kafka sink built on librdkafka.
+//!
+//! Messages are keyed by symbol so per-symbol ordering holds within a
+//! partition. Delivery reports arrive on librdkafka's callback thread and feed
+//! the delivered/error counters directly; a flush fails if any message failed
+//! to deliver since the last one. While the producer queue is full, sends
+//! wait for the next delivery report to make room.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use ingest_core::{
+    config::{Compression, KafkaSinkConfig},
+    error::IngestError,
+    event::NormalizedEvent,
+};
+use prometheus::IntCounter;
+use rdkafka::{
+    config::ClientConfig,
+    error::{KafkaError, RDKafkaErrorCode},
+    producer::{BaseRecord, DeliveryResult, Producer, ProducerContext, ThreadedProducer},
+    ClientContext, Message,
+};
+use tokio::sync::Notify;
+
+use crate::{codec, render_topic, Sink, SinkMetrics};
+
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest wait for a delivery report while the producer queue is full,
+/// before trying again regardless.
+const QUEUE_FULL_WAIT: Duration = Duration::from_secs(1);
+
+pub struct DeliveryCounters {
+    name: String,
+    delivered: IntCounter,
+    errors: IntCounter,
+    /// Messages that failed to deliver since the last flush.
+    failed: Arc<AtomicU64>,
+    /// Woken on every delivery report, each of which frees a queue slot.
+    reported: Arc<Notify>,
+}
+
+impl ClientContext for DeliveryCounters {}
+
+impl ProducerContext for DeliveryCounters {
+    type DeliveryOpaque = ();
+
+    fn delivery(&self, result: &DeliveryResult<'_>, _: Self::DeliveryOpaque) {
+        self.reported.notify_waiters();
+        match result {
+            Ok(_) => self.delivered.inc(),
+            Err((e, msg)) => {
+                self.errors.inc();
+                self.failed.fetch_add(1, Ordering::Relaxed);
+                tracing::warn!(
+                    "sink {} failed to deliver to {}: {}",
+                    self.name,
+                    msg.topic(),
+                    e
+                );
+            }
+        }
+    }
+}
+
+pub struct KafkaSink {
+    producer: ThreadedProducer<DeliveryCounters>,
+    cfg: KafkaSinkConfig,
+    failed: Arc<AtomicU64>,
+    reported: Arc<Notify>,
+}
+
+impl KafkaSink {
+    pub fn new(
+        name: &str,
+        cfg: &KafkaSinkConfig,
+        metrics: &SinkMetrics,
+    ) -> Result<Self, IngestError> {
+        let mut client = ClientConfig::new();
+        client.set("bootstrap.servers", &cfg.brokers).set(
+            "queue.buffering.max.messages",
+            cfg.max_buffered_messages.to_string(),
+        );
+        let codec = match cfg.compression {
+            Compression::None => "none",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+            Compression::Lz4 => "lz4",
+        };
+        client.set("compression.type", codec);
+        if let Some(batch_size) = cfg.batch_size {
+            client.set("batch.num.messages", batch_size.to_string());
+        }
+        if let Some(linger) = cfg.flush_interval_ms {
+            client.set("linger.ms", linger.to_string());
+        }
+        // Explicit properties override the settings above.
+        for (key, value) in &cfg.properties {
+            client.set(key, value);
+        }
+        let failed = Arc::new(AtomicU64::new(0));
+        let reported = Arc::new(Notify::new());
+        let context = DeliveryCounters {
+            name: name.to_string(),
+            delivered: metrics.delivered(name),
+            errors: metrics.errors(name),
+            failed: failed.clone(),
+            reported: reported.clone(),
+        };
+        let producer = client
+            .create_with_context(context)
+            .map_err(|e| IngestError::Sink(format!("sink {}: {}", name, e)))?;
+        Ok(Self {
+            producer,
+            cfg: cfg.clone(),
+            failed,
+            reported,
+        })
+    }
+}
+
+#[async_trait]
+impl Sink for KafkaSink {
+    async fn send(&mut self, event: &NormalizedEvent) -> Result<(), IngestError> {
+        let topic = render_topic(&self.cfg.topic, event);
+        let payload = codec::encode(event, self.cfg.encoding)?;
+        let mut record = BaseRecord::to(&topic).key(&event.symbol).payload(&payload);
+        loop {
+            // Listen before sending, so a report arriving in between is not
+            // missed.
+            let reported = self.reported.notified();
+            tokio::pin!(reported);
+            reported.as_mut().enable();
+            match self.producer.send(record) {
+                Ok(()) => return Ok(()),
+                // The producer queue is bounded; wait for a delivery to drain it.
+                Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), rec)) => {
+                    record = rec;
+                    let _ = tokio::time::timeout(QUEUE_FULL_WAIT, reported).await;
+                }
+                Err((e, _)) => return Err(IngestError::Sink(e.to_string())),
+            }
+        }
+    }
+
+    /// Wait for every queued message to be reported, off the runtime since
+    /// it blocks for up to [`FLUSH_TIMEOUT`]. Fails if any message sent
+    /// since the last flush failed to deliver.
+    async fn flush(&mut self) -> Result<(), IngestError> {
+        let producer = self.producer.clone();
+        tokio::task::spawn_blocking(move || producer.flush(FLUSH_TIMEOUT))
+            .await
+            .map_err(|e| IngestError::Sink(e.to_string()))?
+            .map_err(|e| IngestError::Sink(e.to_string()))?;
+        match self.failed.swap(0, Ordering::Relaxed) {
+            0 => Ok(()),
+            failed => Err(IngestError::Sink(format!(
+                "{} messages failed to deliver",
+                failed
+            ))),
+        }
+    }
+}
@@ -0,0 +1,757 @@
+//! AWS Kinesis Data Streams sink using the `PutRecords` API.
+//!
+//! Records are partitioned by a key rendered from the configured template, so
+//! each symbol lands on one shard. The shard layout is read with `ListShards`
+//! and every record is mapped to its shard from the MD5 of its partition key;
+//! when Kinesis throttles a shard, that shard's records are held back with an
+//! exponential backoff while the other shards keep flowing. The layout is
+//! listed again every minute, and at once when Kinesis rejects a request as
+//! invalid, to follow resharding; closed parent shards are left out, as
+//! records go to their children. Requests are signed with SigV4.
+//!
+//! Records that fail stay queued, and a request that fails as a whole is
+//! tried again after a backoff. Only a flush gives up on records past
+//! `max_attempts`, and it then fails so they are delivered again.
+//!
+//! Records carry no headers, so compressed ones are only told apart by the
+//! codec's magic number at the start of their data; consumers can read it
+//! with [`compress::detect`](crate::compress::detect).
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use ingest_core::{config::KinesisSinkConfig, error::IngestError, event::NormalizedEvent};
+use md5::{Digest as _, Md5};
+use prometheus::IntCounter;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+use crate::retry::Retry;
+use crate::{codec, compress, render_topic, Sink, SinkMetrics};
+
+const MAX_RECORDS: usize = 500;
+const MAX_REQUEST_BYTES: usize = 5 * 1024 * 1024;
+const THROTTLED: &str = "ProvisionedThroughputExceededException";
+const MIN_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+/// How often the shard layout is listed again.
+const SHARD_REFRESH: Duration = Duration::from_secs(60);
+/// Longest wait before listing the shards again after a failure.
+const MAX_LIST_BACKOFF: Duration = Duration::from_secs(30);
+const INVALID_ARGUMENT: &str = "InvalidArgumentException";
+
+pub struct Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+impl Credentials {
+    fn from_config(cfg: &KinesisSinkConfig) -> Result<Self, IngestError> {
+        let env = |name: &str| std::env::var(name).ok();
+        let access_key_id = cfg
+            .access_key_id
+            .clone()
+            .or_else(|| env("AWS_ACCESS_KEY_ID"));
+        let secret_access_key = cfg
+            .secret_access_key
+            .clone()
+            .or_else(|| env("AWS_SECRET_ACCESS_KEY"));
+        match (access_key_id, secret_access_key) {
+            (Some(access_key_id), Some(secret_access_key)) => Ok(Self {
+                access_key_id,
+                secret_access_key,
+                session_token: cfg
+                    .session_token
+                    .clone()
+                    .or_else(|| env("AWS_SESSION_TOKEN")),
+            }),
+            _ => Err(IngestError::Validation(
+                "kinesis needs an access key id and secret access key".into(),
+            )),
+        }
+    }
+}
+
+/// The SigV4 `Authorization` header for a request carrying `headers`, which
+/// must include `host` and `x-amz-date` and are all signed.
+pub fn authorization(
+    creds: &Credentials,
+    region: &str,
+    service: &str,
+    method: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+    now: DateTime<Utc>,
+) -> String {
+    let date = now.format("%Y%m%d").to_string();
+    let mut headers: Vec<(String, &str)> = headers
+        .iter()
+        .map(|(name, value)| (name.to_ascii_lowercase(), value.trim()))
+        .collect();
+    headers.sort();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value))
+        .collect();
+    let canonical_request = format!(
+        "{}\n/\n\n{}\n{}\n{}",
+        method,
+        canonical_headers,
+        signed_headers,
+        hex(&Sha256::digest(body))
+    );
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        now.format("%Y%m%dT%H%M%SZ"),
+        scope,
+        hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+    let mut key = hmac(
+        format!("AWS4{}", creds.secret_access_key).as_bytes(),
+        date.as_bytes(),
+    );
+    for part in [region, service, "aws4_request"] {
+        key = hmac(&key, part.as_bytes());
+    }
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        creds.access_key_id,
+        scope,
+        signed_headers,
+        hex(&hmac(&key, string_to_sign.as_bytes()))
+    )
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Position of `partition_key` in the 128-bit hash key space shards divide.
+pub fn hash_key(partition_key: &str) -> u128 {
+    u128::from_be_bytes(Md5::digest(partition_key.as_bytes()).into())
+}
+
+struct Shard {
+    id: String,
+    start: u128,
+    end: u128,
+    throttled_until: Option<Instant>,
+    backoff: Duration,
+}
+
+impl Shard {
+    fn throttled(&self, now: Instant) -> bool {
+        self.throttled_until.is_some_and(|until| now < until)
+    }
+
+    fn throttle(&mut self, now: Instant) {
+        self.backoff = (self.backoff * 2).clamp(MIN_BACKOFF, MAX_BACKOFF);
+        self.throttled_until = Some(now + self.backoff);
+    }
+}
+
+struct Record {
+    data: String,
+    partition_key: String,
+    hash: u128,
+    attempts: u32,
+}
+
+impl Record {
+    fn size(&self) -> usize {
+        self.data.len() + self.partition_key.len()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListShardsResponse {
+    shards: Vec<ShardDescription>,
+    next_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ShardDescription {
+    shard_id: String,
+    hash_key_range: HashKeyRange,
+    sequence_number_range: SequenceNumberRange,
+}
+
+impl ShardDescription {
+    /// The shard, unless it was closed by resharding.
+    fn open(self) -> Result<Option<Shard>, IngestError> {
+        if self.sequence_number_range.ending_sequence_number.is_some() {
+            return Ok(None);
+        }
+        let parse = |key: &str| {
+            key.parse::<u128>()
+                .map_err(|e| IngestError::Sink(format!("hash key {}: {}", key, e)))
+        };
+        Ok(Some(Shard {
+            start: parse(&self.hash_key_range.starting_hash_key)?,
+            end: parse(&self.hash_key_range.ending_hash_key)?,
+            id: self.shard_id,
+            throttled_until: None,
+            backoff: Duration::ZERO,
+        }))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SequenceNumberRange {
+    /// Set once the shard is closed.
+    ending_sequence_number: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct HashKeyRange {
+    starting_hash_key: String,
+    ending_hash_key: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PutRecordsResponse {
+    records: Vec<PutRecordsResult>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PutRecordsResult {
+    error_code: Option<String>,
+}
+
+pub struct KinesisSink {
+    name: String,
+    client: reqwest::Client,
+    url: String,
+    host: String,
+    cfg: KinesisSinkConfig,
+    creds: Credentials,
+    /// The open shards, empty until the layout could be read.
+    shards: Vec<Shard>,
+    /// When to list the shards next.
+    list_at: Instant,
+    /// Wait before listing the shards again after a failure.
+    list_backoff: Duration,
+    pending: VecDeque<Record>,
+    started: Instant,
+    retry: Retry,
+    delivered: IntCounter,
+}
+
+impl KinesisSink {
+    pub fn new(
+        name: &str,
+        cfg: &KinesisSinkConfig,
+        metrics: &SinkMetrics,
+    ) -> Result<Self, IngestError> {
+        let err = |e: String| IngestError::Sink(format!("sink {}: {}", name, e));
+        compress::check(name, cfg.compression)?;
+        let url = cfg
+            .endpoint
+            .clone()
+            .unwrap_or_else(|| format!("https://kinesis.{}.amazonaws.com", cfg.region));
+        let parsed = reqwest::Url::parse(&url).map_err(|e| err(e.to_string()))?;
+        let mut host = parsed
+            .host_str()
+            .ok_or_else(|| err(format!("no host in {}", url)))?
+            .to_string();
+        // The signed host header has to match what reqwest sends.
+        if let Some(port) = parsed.port() {
+            host = format!("{}:{}", host, port);
+        }
+        Ok(Self {
+            name: name.to_string(),
+            client: reqwest::Client::new(),
+            url,
+            host,
+            cfg: cfg.clone(),
+            creds: Credentials::from_config(cfg).map_err(|e| err(e.to_string()))?,
+            shards: Vec::new(),
+            list_at: Instant::now(),
+            list_backoff: Duration::ZERO,
+            pending: VecDeque::new(),
+            started: Instant::now(),
+            retry: Retry::new(name, metrics),
+            delivered: metrics.delivered(name),
+        })
+    }
+
+    async fn call(&self, action: &str, body: Value) -> Result<reqwest::Response, IngestError> {
+        let body = body.to_string();
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let target = format!("Kinesis_20131202.{}", action);
+        let mut headers = vec![
+            ("content-type", "application/x-amz-json-1.1"),
+            ("host", self.host.as_str()),
+            ("x-amz-date", amz_date.as_str()),
+            ("x-amz-target", target.as_str()),
+        ];
+        if let Some(token) = &self.creds.session_token {
+            headers.push(("x-amz-security-token", token));
+        }
+        let auth = authorization(
+            &self.creds,
+            &self.cfg.region,
+            "kinesis",
+            "POST",
+            &headers,
+            body.as_bytes(),
+            now,
+        );
+        let mut request = self.client.post(&self.url).header("authorization", auth);
+        for (name, value) in headers.iter().filter(|(name, _)| *name != "host") {
+            request = request.header(*name, *value);
+        }
+        let response = request
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| IngestError::Sink(e.to_string()))?;
+        let status = response.status();
+        if !status.is_success() {
+            let detail = response.text().await.unwrap_or_default();
+            return Err(IngestError::Sink(format!(
+                "kinesis {} returned {}: {}",
+                action, status, detail
+            )));
+        }
+        Ok(response)
+    }
+
+    async fn list_shards(&self) -> Result<Vec<Shard>, IngestError> {
+        let mut shards = Vec::new();
+        let mut next_token: Option<String> = None;
+        loop {
+            let body = match &next_token {
+                Some(token) => json!({ "NextToken": token }),
+                None => json!({ "StreamName": self.cfg.stream }),
+            };
+            let page: ListShardsResponse = self
+                .call("ListShards", body)
+                .await?
+                .json()
+                .await
+                .map_err(|e| IngestError::Sink(e.to_string()))?;
+            for shard in page.shards {
+                shards.extend(shard.open()?);
+            }
+            match page.next_token {
+                Some(token) => next_token = Some(token),
+                None => return Ok(shards),
+            }
+        }
+    }
+
+    /// List the shards once due, keeping the throttling of those still
+    /// open. After a failure the previous layout stays, and the listing is
+    /// retried with a backoff; without any layout, throttling applies per
+    /// request.
+    async fn refresh_shards(&mut self) {
+        let now = Instant::now();
+        if now < self.list_at {
+            return;
+        }
+        match self.list_shards().await {
+            Ok(mut shards) => {
+                for shard in &mut shards {
+                    if let Some(old) = self.shards.iter().find(|old| old.id == shard.id) {
+                        shard.throttled_until = old.throttled_until;
+                        shard.backoff = old.backoff;
+                    }
+                }
+                self.shards = shards;
+                self.list_backoff = Duration::ZERO;
+                self.list_at = now + SHARD_REFRESH;
+            }
+            Err(e) => {
+                self.list_backoff = (self.list_backoff * 2).clamp(MIN_BACKOFF, MAX_LIST_BACKOFF);
+                self.list_at = now + self.list_backoff;
+                tracing::warn!(
+                    "sink {} cannot list shards, retrying in {:?}: {}",
+                    self.name,
+                    self.list_backoff,
+                    e
+                );
+            }
+        }
+    }
+
+    fn shard_mut(&mut self, hash: u128) -> Option<&mut Shard> {
+        self.shards
+            .iter_mut()
+            .find(|shard| shard.start <= hash && hash <= shard.end)
+    }
+
+    /// Take up to one request's worth of records whose shard is not
+    /// throttled, keeping the rest in order.
+    fn ready(&mut self) -> Vec<Record> {
+        let now = Instant::now();
+        let mut ready = Vec::new();
+        let mut held = VecDeque::new();
+        let mut bytes = 0;
+        while let Some(record) = self.pending.pop_front() {
+            let throttled = self
+                .shard_mut(record.hash)
+                .is_some_and(|shard| shard.throttled(now));
+            if throttled || ready.len() == MAX_RECORDS || bytes + record.size() > MAX_REQUEST_BYTES
+            {
+                held.push_back(record);
+            } else {
+                bytes += record.size();
+                ready.push(record);
+            }
+        }
+        self.pending = held;
+        ready
+    }
+
+    /// Send one `PutRecords` request. Failed records go back to the front of
+    /// the queue; with `all`, those out of attempts are dropped instead. A
+    /// request that fails as a whole is tried again after a backoff, unless
+    /// `all` is set.
+    async fn put_records(&mut self, all: bool) -> Result<(), IngestError> {
+        if !self.retry.ready(all) {
+            self.trim();
+            return Ok(());
+        }
+        self.refresh_shards().await;
+        let batch = self.ready();
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let records: Vec<Value> = batch
+            .iter()
+            .map(|r| json!({ "Data": r.data, "PartitionKey": r.partition_key }))
+            .collect();
+        let body = json!({ "StreamName": self.cfg.stream, "Records": records });
+        let results = match self.call("PutRecords", body).await {
+            Ok(response) => response
+                .json::<PutRecordsResponse>()
+                .await
+                .map_err(|e| IngestError::Sink(e.to_string()))
+                .map(|r| r.records),
+            Err(e) => Err(e),
+        };
+        let results = match results {
+            Ok(results) if results.len() == batch.len() => results,
+            Ok(results) => {
+                let cause = format!("{} results for {} records", results.len(), batch.len());
+                let cause = IngestError::Sink(cause);
+                if !all {
+                    self.retry.failed(&cause);
+                }
+                return self.requeue(batch, cause, all);
+            }
+            Err(e) => {
+                if e.to_string().contains(INVALID_ARGUMENT) {
+                    // Possibly sent by a stale layout.
+                    self.list_at = Instant::now();
+                }
+                if !all {
+                    self.retry.failed(&e);
+                }
+                return self.requeue(batch, e, all);
+            }
+        };
+        self.retry.succeeded();
+
+        let now = Instant::now();
+        let mut failed = Vec::new();
+        for (record, result) in batch.into_iter().zip(results) {
+            match result.error_code {
+                None => {
+                    self.delivered.inc();
+                    if let Some(shard) = self.shard_mut(record.hash) {
+                        shard.backoff = Duration::ZERO;
+                    }
+                }
+                Some(code) => {
+                    if code == THROTTLED {
+                        if let Some(shard) = self.shard_mut(record.hash) {
+                            if !shard.throttled(now) {
+                                shard.throttle(now);
+                                tracing::debug!(
+                                    "shard {} throttled for {:?}",
+                                    shard.id,
+                                    shard.backoff
+                                );
+                            }
+                        }
+                    }
+                    failed.push(record);
+                }
+            }
+        }
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            let count = failed.len();
+            self.requeue(
+                failed,
+                IngestError::Sink(format!("{} records rejected", count)),
+                all,
+            )
+        }
+    }
+
+    fn requeue(
+        &mut self,
+        records: Vec<Record>,
+        cause: IngestError,
+        all: bool,
+    ) -> Result<(), IngestError> {
+        let mut dropped = 0;
+        for mut record in records.into_iter().rev() {
+            record.attempts += 1;
+            if all && record.attempts >= self.cfg.max_attempts {
+                dropped += 1;
+            } else {
+                self.pending.push_front(record);
+            }
+        }
+        if dropped > 0 {
+            return Err(IngestError::Sink(format!(
+                "dropped {} records after {} attempts: {}",
+                dropped, self.cfg.max_attempts, cause
+            )));
+        }
+        tracing::debug!("sink {} will retry: {}", self.name, cause);
+        if !all {
+            self.trim();
+        }
+        Ok(())
+    }
+
+    /// Drop the oldest records past what is kept while requests fail.
+    fn trim(&mut self) {
+        let excess = self
+            .pending
+            .len()
+            .saturating_sub(Retry::limit(self.cfg.batch_size));
+        self.pending.drain(..excess);
+        self.retry.trimmed(excess);
+    }
+
+    /// Whether the queue holds a full batch or has waited long enough.
+    fn due(&self) -> bool {
+        self.pending.len() >= self.cfg.batch_size
+            || self.started.elapsed() >= Duration::from_millis(self.cfg.flush_interval_ms)
+    }
+
+    /// Time until the earliest throttled shard may be retried.
+    fn next_retry(&self) -> Duration {
+        let now = Instant::now();
+        self.shards
+            .iter()
+            .filter_map(|shard| shard.throttled_until)
+            .map(|until| until.saturating_duration_since(now))
+            .min()
+            .unwrap_or(MIN_BACKOFF)
+    }
+}
+
+#[async_trait]
+impl Sink for KinesisSink {
+    async fn send(&mut self, event: &NormalizedEvent) -> Result<(), IngestError> {
+        let partition_key = render_topic(&self.cfg.partition_key, event);
+        if self.pending.is_empty() {
+            self.started = Instant::now();
+        }
+        self.pending.push_back(Record {
+            data: STANDARD.encode(compress::compress(
+                &codec::encode(event, self.cfg.encoding)?,
+                self.cfg.compression,
+            )?),
+            hash: hash_key(&partition_key),
+            partition_key,
+            attempts: 0,
+        });
+        if self.due() {
+            self.started = Instant::now();
+            return self.put_records(false).await;
+        }
+        Ok(())
+    }
+
+    async fn tick(&mut self) -> Result<(), IngestError> {
+        if !self.pending.is_empty() && self.due() {
+            self.started = Instant::now();
+            return self.put_records(false).await;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), IngestError> {
+        let mut result = Ok(());
+        while !self.pending.is_empty() {
+            let before = self.pending.len();
+            if let Err(e) = self.put_records(true).await {
+                result = Err(e);
+            }
+            if self.pending.len() >= before {
+                tokio::time::sleep(self.next_retry()).await;
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // "get-vanilla" from the AWS Signature Version 4 test suite.
+    #[test]
+    fn signs_aws_test_vector() {
+        let creds = Credentials {
+            access_key_id: "AKIDEXAMPLE".into(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".into(),
+            session_token: None,
+        };
+        let now = DateTime::parse_from_rfc3339("2015-08-30T12:36:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let headers = [
+            ("Host", "example.amazonaws.com"),
+            ("X-Amz-Date", "20150830T123600Z"),
+        ];
+        assert_eq!(
+            authorization(&creds, "us-east-1", "service", "GET", &headers, b"", now),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+    }
+
+    #[test]
+    fn throttled_shards_are_held_back() {
+        let cfg = KinesisSinkConfig {
+            stream: "events".into(),
+            region: "eu-west-1".into(),
+            endpoint: None,
+            encoding: Default::default(),
+            partition_key: "{symbol}".into(),
+            access_key_id: Some("AKID".into()),
+            secret_access_key: Some("secret".into()),
+            session_token: None,
+            batch_size: 500,
+            flush_interval_ms: 200,
+            max_attempts: 3,
+            compression: Default::default(),
+        };
+        let mut sink = KinesisSink::new("kinesis", &cfg, &SinkMetrics::new()).unwrap();
+        let half = u128::MAX / 2;
+        let shard = |id: &str, start, end| Shard {
+            id: id.into(),
+            start,
+            end,
+            throttled_until: None,
+            backoff: Duration::ZERO,
+        };
+        sink.shards = vec![shard("low", 0, half), shard("high", half + 1, u128::MAX)];
+        let keys: Vec<String> = (0..20).map(|i| format!("SYM{}", i)).collect();
+        for key in &keys {
+            sink.pending.push_back(Record {
+                data: String::new(),
+                partition_key: key.clone(),
+                hash: hash_key(key),
+                attempts: 0,
+            });
+        }
+        sink.shard_mut(0).unwrap().throttle(Instant::now());
+
+        let ready = sink.ready();
+        assert!(!ready.is_empty());
+        assert!(ready.iter().all(|r| r.hash > half));
+        assert_eq!(ready.len() + sink.pending.len(), keys.len());
+        assert!(sink.pending.iter().all(|r| r.hash <= half));
+    }
+
+    #[tokio::test]
+    async fn keeps_failed_records_until_a_flush() {
+        let cfg = KinesisSinkConfig {
+            stream: "events".into(),
+            region: "eu-west-1".into(),
+            endpoint: Some("http://127.0.0.1:1".into()),
+            encoding: Default::default(),
+            partition_key: "{symbol}".into(),
+            access_key_id: Some("AKID".into()),
+            secret_access_key: Some("secret".into()),
+            session_token: None,
+            batch_size: 1,
+            flush_interval_ms: 60_000,
+            max_attempts: 1,
+            compression: Default::default(),
+        };
+        let metrics = SinkMetrics::new();
+        let mut sink = KinesisSink::new("kinesis", &cfg, &metrics).unwrap();
+        for _ in 0..2 {
+            sink.send(&NormalizedEvent::default()).await.unwrap();
+        }
+        assert_eq!(sink.pending.len(), 2);
+
+        assert!(sink.flush().await.is_err());
+        assert!(sink.pending.is_empty());
+    }
+
+    #[test]
+    fn closed_shards_are_left_out() {
+        let page: ListShardsResponse = serde_json::from_value(json!({
+            "Shards": [
+                {
+                    "ShardId": "shardId-000000000000",
+                    "HashKeyRange": {"StartingHashKey": "0", "EndingHashKey": "99"},
+                    "SequenceNumberRange": {"StartingSequenceNumber": "1", "EndingSequenceNumber": "9"}
+                },
+                {
+                    "ShardId": "shardId-000000000001",
+                    "HashKeyRange": {"StartingHashKey": "0", "EndingHashKey": "49"},
+                    "SequenceNumberRange": {"StartingSequenceNumber": "10"}
+                },
+                {
+                    "ShardId": "shardId-000000000002",
+                    "HashKeyRange": {"StartingHashKey": "50", "EndingHashKey": "99"},
+                    "SequenceNumberRange": {"StartingSequenceNumber": "11"}
+                }
+            ]
+        }))
+        .unwrap();
+        let open: Vec<_> = page
+            .shards
+            .into_iter()
+            .filter_map(|shard| shard.open().unwrap())
+            .map(|shard| (shard.id, shard.start, shard.end))
+            .collect();
+        assert_eq!(
+            open,
+            [
+                ("shardId-000000000001".to_string(), 0, 49),
+                ("shardId-000000000002".to_string(), 50, 99)
+            ]
+        );
+    }
+}
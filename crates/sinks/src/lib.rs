@@ -0,0 +1,677 @@
+//! Output sinks that ship events from the bus to external systems.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use ingest_core::{
+    config::{SinkConfig, SinkKind, SinkRoute},
+    error::IngestError,
+    event::{EventKind, NormalizedEvent},
+};
+use prometheus::{IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry};
+use tokio::sync::{mpsc, watch};
+use tokio::task::{AbortHandle, JoinHandle};
+use tokio::time::Instant;
+use tokio_stream::{Stream, StreamExt};
+
+#[cfg(feature = "archive")]
+pub mod archive;
+pub mod codec;
+pub mod compress;
+mod delivery;
+#[cfg(feature = "duckdb")]
+pub mod duckdb;
+#[cfg(feature = "eventhubs")]
+pub mod eventhubs;
+pub mod influx;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+#[cfg(feature = "kinesis")]
+pub mod kinesis;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "pubsub")]
+pub mod pubsub;
+mod retry;
+#[cfg(feature = "shm")]
+pub mod shm;
+pub mod unix_socket;
+
+/// A destination for events.
+///
+/// Implementations bump `sink_delivered_total` once the destination has
+/// accepted an event; errors returned from `send` are counted by the runner.
+#[async_trait]
+pub trait Sink: Send {
+    async fn send(&mut self, event: &NormalizedEvent) -> Result<(), IngestError>;
+
+    /// Push out anything buffered inside the sink. Success confirms every
+    /// event sent before it, which at-least-once delivery relies on: a sink
+    /// that failed to write a batch fails the flush.
+    async fn flush(&mut self) -> Result<(), IngestError> {
+        Ok(())
+    }
+
+    /// Write out batches that are due by age alone. The runners call it
+    /// every [`TICK`] so a quiet stream does not hold a partial batch past
+    /// its flush interval.
+    async fn tick(&mut self) -> Result<(), IngestError> {
+        Ok(())
+    }
+
+    /// Check that the destination is reachable and accepts the sink's
+    /// credentials, without delivering anything, as `ingestd run
+    /// --dry-run` does. Sinks that cannot tell succeed.
+    async fn check(&mut self) -> Result<(), IngestError> {
+        Ok(())
+    }
+}
+
+/// Delivery counters and queue gauges for all sinks, labelled by sink name.
+#[derive(Clone)]
+pub struct SinkMetrics {
+    pub delivered: IntCounterVec,
+    pub errors: IntCounterVec,
+    pub dropped: IntCounterVec,
+    pub retries: IntCounterVec,
+    pub spilled: IntCounterVec,
+    pub dead_lettered: IntCounterVec,
+    pub queue_depth: IntGaugeVec,
+}
+
+impl SinkMetrics {
+    pub fn new() -> Self {
+        let counter =
+            |name: &str, help: &str| IntCounterVec::new(Opts::new(name, help), &["sink"]).unwrap();
+        Self {
+            delivered: counter("sink_delivered_total", "events accepted by the sink"),
+            errors: counter("sink_errors_total", "events the sink failed to deliver"),
+            dropped: counter("sink_dropped_total", "events dropped on a full sink queue"),
+            retries: counter(
+                "sink_retries_total",
+                "delivery attempts scheduled for retry",
+            ),
+            spilled: counter(
+                "sink_spilled_total",
+                "events spilled to disk on a full retry buffer",
+            ),
+            dead_lettered: counter(
+                "sink_dead_lettered_total",
+                "events given up on after the last delivery attempt",
+            ),
+            queue_depth: IntGaugeVec::new(
+                Opts::new("sink_queue_depth", "events waiting in the sink queue"),
+                &["sink"],
+            )
+            .unwrap(),
+        }
+    }
+
+    pub fn register(&self, registry: &Registry) -> prometheus::Result<()> {
+        registry.register(Box::new(self.delivered.clone()))?;
+        registry.register(Box::new(self.errors.clone()))?;
+        registry.register(Box::new(self.dropped.clone()))?;
+        registry.register(Box::new(self.retries.clone()))?;
+        registry.register(Box::new(self.spilled.clone()))?;
+        registry.register(Box::new(self.dead_lettered.clone()))?;
+        registry.register(Box::new(self.queue_depth.clone()))?;
+        Ok(())
+    }
+
+    pub fn delivered(&self, sink: &str) -> IntCounter {
+        self.delivered.with_label_values(&[sink])
+    }
+
+    pub fn errors(&self, sink: &str) -> IntCounter {
+        self.errors.with_label_values(&[sink])
+    }
+
+    fn queue_depth(&self, sink: &str) -> IntGauge {
+        self.queue_depth.with_label_values(&[sink])
+    }
+}
+
+impl Default for SinkMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Substitute `{venue}`, `{kind}` and `{symbol}` in a topic template.
+pub fn render_topic(template: &str, event: &NormalizedEvent) -> String {
+    template
+        .replace("{venue}", &event.venue)
+        .replace("{kind}", event.kind.as_str())
+        .replace("{symbol}", &event.symbol)
+}
+
+/// Table holding events of `kind` in the database sinks.
+pub fn table(kind: EventKind) -> &'static str {
+    match kind {
+        EventKind::Trade => "trades",
+        EventKind::Ticker => "tickers",
+        EventKind::Quote => "quotes",
+        EventKind::Book => "books",
+        EventKind::Raw => "raw_events",
+    }
+}
+
+/// Build the sink described by `cfg`.
+pub fn build(
+    name: &str,
+    cfg: &SinkConfig,
+    metrics: &SinkMetrics,
+) -> Result<Box<dyn Sink>, IngestError> {
+    match &cfg.kind {
+        #[cfg(feature = "kafka")]
+        SinkKind::Kafka(kafka) => Ok(Box::new(kafka::KafkaSink::new(name, kafka, metrics)?)),
+        #[cfg(not(feature = "kafka"))]
+        SinkKind::Kafka(_) => {
+            let _ = metrics;
+            Err(unsupported(name, "kafka"))
+        }
+        #[cfg(feature = "parquet")]
+        SinkKind::Parquet(parquet) => Ok(Box::new(self::parquet::ParquetSink::new(
+            name, parquet, metrics,
+        )?)),
+        #[cfg(not(feature = "parquet"))]
+        SinkKind::Parquet(_) => Err(unsupported(name, "parquet")),
+        #[cfg(feature = "archive")]
+        SinkKind::Archive(archive) => {
+            Ok(Box::new(archive::ArchiveSink::new(name, archive, metrics)?))
+        }
+        #[cfg(not(feature = "archive"))]
+        SinkKind::Archive(_) => Err(unsupported(name, "archive")),
+        SinkKind::Influx(influx) => Ok(Box::new(influx::InfluxSink::new(name, influx, metrics)?)),
+        #[cfg(feature = "postgres")]
+        SinkKind::Postgres(pg) => Ok(Box::new(postgres::PostgresSink::new(name, pg, metrics)?)),
+        #[cfg(not(feature = "postgres"))]
+        SinkKind::Postgres(_) => Err(unsupported(name, "postgres")),
+        #[cfg(feature = "pubsub")]
+        SinkKind::Pubsub(pubsub) => Ok(Box::new(pubsub::PubSubSink::new(name, pubsub, metrics)?)),
+        #[cfg(not(feature = "pubsub"))]
+        SinkKind::Pubsub(_) => Err(unsupported(name, "pubsub")),
+        #[cfg(feature = "kinesis")]
+        SinkKind::Kinesis(kinesis) => {
+            Ok(Box::new(kinesis::KinesisSink::new(name, kinesis, metrics)?))
+        }
+        #[cfg(not(feature = "kinesis"))]
+        SinkKind::Kinesis(_) => Err(unsupported(name, "kinesis")),
+        #[cfg(feature = "eventhubs")]
+        SinkKind::EventHubs(eventhubs) => Ok(Box::new(eventhubs::EventHubsSink::new(
+            name, eventhubs, metrics,
+        )?)),
+        #[cfg(not(feature = "eventhubs"))]
+        SinkKind::EventHubs(_) => Err(unsupported(name, "eventhubs")),
+        #[cfg(feature = "duckdb")]
+        SinkKind::Duckdb(duckdb) => Ok(Box::new(self::duckdb::DuckDbSink::new(
+            name, duckdb, metrics,
+        )?)),
+        #[cfg(not(feature = "duckdb"))]
+        SinkKind::Duckdb(_) => Err(unsupported(name, "duckdb")),
+        SinkKind::UnixSocket(socket) => Ok(Box::new(unix_socket::UnixSocketSink::new(
+            name, socket, metrics,
+        )?)),
+        #[cfg(feature = "shm")]
+        SinkKind::Shm(shm) => Ok(Box::new(shm::ShmSink::new(name, shm, metrics)?)),
+        #[cfg(not(feature = "shm"))]
+        SinkKind::Shm(_) => Err(unsupported(name, "shm")),
+    }
+}
+
+/// Build the sink described by `cfg` and [`check`](Sink::check) it. Sinks
+/// writing to local sockets or shared memory are not built, since building
+/// them would take over the paths of a running engine; they pass
+/// unchecked.
+pub async fn check(name: &str, cfg: &SinkConfig) -> Result<(), IngestError> {
+    if matches!(cfg.kind, SinkKind::UnixSocket(_) | SinkKind::Shm(_)) {
+        return Ok(());
+    }
+    build(name, cfg, &SinkMetrics::new())?.check().await
+}
+
+fn unsupported(name: &str, feature: &str) -> IngestError {
+    IngestError::Sink(format!(
+        "sink {} requires the `{}` feature, which this build does not include",
+        name, feature
+    ))
+}
+
+/// How long sinks get past the shutdown deadline to keep what they hold
+/// before they are stopped regardless.
+const PERSIST_GRACE: Duration = Duration::from_secs(10);
+
+/// How often the runners call [`Sink::tick`].
+pub const TICK: Duration = Duration::from_secs(1);
+
+/// Drive `sink` from `events` through a bounded queue. Events arriving while
+/// the queue is full are dropped and counted rather than stalling the bus.
+/// Once `expired` turns, the events still queued are given up. Returns the
+/// events given up.
+pub fn spawn<S>(
+    name: &str,
+    mut sink: Box<dyn Sink>,
+    events: S,
+    capacity: usize,
+    metrics: SinkMetrics,
+    mut expired: watch::Receiver<bool>,
+) -> JoinHandle<u64>
+where
+    S: Stream<Item = NormalizedEvent> + Send + Unpin + 'static,
+{
+    let mut rx = queue(name, events, capacity, false, &metrics);
+    let depth = metrics.queue_depth(name);
+    let errors = metrics.errors(name);
+    let name = name.to_string();
+
+    tokio::spawn(async move {
+        let mut lost = 0;
+        let mut ticks = tokio::time::interval(TICK);
+        loop {
+            let event = tokio::select! {
+                event = rx.recv() => event,
+                _ = ticks.tick() => {
+                    if let Err(e) = sink.tick().await {
+                        errors.inc();
+                        tracing::warn!("sink {} failed to write due batches: {}", name, e);
+                    }
+                    continue;
+                }
+                Ok(()) = expired.changed() => {
+                    lost = rx.len() as u64;
+                    break;
+                }
+            };
+            let Some(event) = event else { break };
+            depth.set(rx.len() as i64);
+            if let Err(e) = sink.send(&event).await {
+                errors.inc();
+                tracing::warn!("sink {} failed to send event: {}", name, e);
+            }
+        }
+        if lost > 0 {
+            tracing::error!(
+                "sink {} gave up {} queued events at the shutdown deadline",
+                name,
+                lost
+            );
+        }
+        if let Err(e) = sink.flush().await {
+            tracing::warn!("sink {} failed to flush: {}", name, e);
+        }
+        lost
+    })
+}
+
+/// Feed `events` into a bounded queue. With `backpressure` a full queue
+/// holds the stream back; otherwise what does not fit is dropped and
+/// counted.
+fn queue<S>(
+    name: &str,
+    events: S,
+    capacity: usize,
+    backpressure: bool,
+    metrics: &SinkMetrics,
+) -> mpsc::Receiver<NormalizedEvent>
+where
+    S: Stream<Item = NormalizedEvent> + Send + Unpin + 'static,
+{
+    let (tx, rx) = mpsc::channel(capacity);
+    let dropped = metrics.dropped.with_label_values(&[name]);
+    let depth = metrics.queue_depth(name);
+    tokio::spawn(async move {
+        let mut events = events;
+        while let Some(event) = events.next().await {
+            if backpressure {
+                if tx.send(event).await.is_err() {
+                    break;
+                }
+                depth.set((tx.max_capacity() - tx.capacity()) as i64);
+                continue;
+            }
+            match tx.try_send(event) {
+                Ok(()) => depth.set((tx.max_capacity() - tx.capacity()) as i64),
+                Err(mpsc::error::TrySendError::Full(_)) => dropped.inc(),
+                Err(mpsc::error::TrySendError::Closed(_)) => break,
+            }
+        }
+    });
+    rx
+}
+
+/// The running sinks, each on the events its route matches. A sink that
+/// panics stays stopped, which readiness reports through [`SinkHealth`].
+pub struct SinkSet {
+    sinks: Vec<Running>,
+    /// Turned at the shutdown deadline.
+    expire: watch::Sender<bool>,
+}
+
+struct Running {
+    name: String,
+    task: JoinHandle<u64>,
+    depth: IntGauge,
+    delivered: IntCounter,
+    errors: IntCounter,
+}
+
+impl SinkSet {
+    /// Build every sink in `sinks` and start each on a stream from
+    /// `subscribe`, called with the sink name and route, carrying the events
+    /// the route matches. Nothing is started if any sink fails to build or
+    /// its delivery logs cannot be opened.
+    pub fn start<F, S>(
+        sinks: &BTreeMap<String, SinkConfig>,
+        mut subscribe: F,
+        metrics: &SinkMetrics,
+    ) -> Result<Self, IngestError>
+    where
+        F: FnMut(&str, &SinkRoute) -> S,
+        S: Stream<Item = NormalizedEvent> + Send + Unpin + 'static,
+    {
+        let built = sinks
+            .iter()
+            .map(|(name, cfg)| {
+                let sink = build(name, cfg, metrics)?;
+                let delivery = match &cfg.delivery {
+                    Some(delivery) => Some(delivery::Delivery::open(name, delivery, metrics)?),
+                    None => None,
+                };
+                Ok((name, cfg, sink, delivery))
+            })
+            .collect::<Result<Vec<_>, IngestError>>()?;
+        let (expire, expired) = watch::channel(false);
+        let mut started = Vec::with_capacity(built.len());
+        for (name, cfg, sink, delivery) in built {
+            let events = subscribe(name, &cfg.route);
+            let expired = expired.clone();
+            let task = match delivery {
+                Some(delivery) => {
+                    let rx = queue(name, events, cfg.queue_capacity, true, metrics);
+                    delivery::spawn(delivery, sink, rx, expired)
+                }
+                None => spawn(
+                    name,
+                    sink,
+                    events,
+                    cfg.queue_capacity,
+                    metrics.clone(),
+                    expired,
+                ),
+            };
+            started.push(Running {
+                name: name.clone(),
+                task,
+                depth: metrics.queue_depth(name),
+                delivered: metrics.delivered(name),
+                errors: metrics.errors(name),
+            });
+        }
+        Ok(Self {
+            sinks: started,
+            expire,
+        })
+    }
+
+    /// Start `sink` as well, on `events`, queueing up to `capacity` of them.
+    /// Sinks built outside the config, such as an embedding application's,
+    /// are added this way.
+    pub fn add<S>(
+        &mut self,
+        name: &str,
+        sink: Box<dyn Sink>,
+        events: S,
+        capacity: usize,
+        metrics: &SinkMetrics,
+    ) where
+        S: Stream<Item = NormalizedEvent> + Send + Unpin + 'static,
+    {
+        let task = spawn(
+            name,
+            sink,
+            events,
+            capacity,
+            metrics.clone(),
+            self.expire.subscribe(),
+        );
+        self.sinks.push(Running {
+            name: name.to_string(),
+            task,
+            depth: metrics.queue_depth(name),
+            delivered: metrics.delivered(name),
+            errors: metrics.errors(name),
+        });
+    }
+
+    /// Which sinks are still running and delivering, tracked past
+    /// [`join`](Self::join).
+    pub fn health(&self) -> SinkHealth {
+        SinkHealth {
+            sinks: self
+                .sinks
+                .iter()
+                .map(|sink| Tracked {
+                    name: sink.name.clone(),
+                    task: sink.task.abort_handle(),
+                    delivered: sink.delivered.clone(),
+                    errors: sink.errors.clone(),
+                    progress: Mutex::new(Progress {
+                        delivered: sink.delivered.get(),
+                        errors: sink.errors.get(),
+                        at: Instant::now(),
+                    }),
+                })
+                .collect(),
+        }
+    }
+
+    /// Wait for every sink to drain and stop until `deadline`. Sinks still
+    /// running then keep what they hold in their spill log, if they have
+    /// one, and stop. Returns the events sinks gave up, counting the queue
+    /// of any that panicked or had to be stopped.
+    pub async fn join(self, deadline: Instant) -> u64 {
+        let aborts: Vec<_> = self
+            .sinks
+            .iter()
+            .map(|sink| sink.task.abort_handle())
+            .collect();
+        let expire = self.expire;
+        let timer = tokio::spawn(async move {
+            tokio::time::sleep_until(deadline).await;
+            let _ = expire.send(true);
+            tokio::time::sleep(PERSIST_GRACE).await;
+            for task in aborts {
+                task.abort();
+            }
+        });
+        let mut lost = 0;
+        for sink in self.sinks {
+            match sink.task.await {
+                Ok(given_up) => lost += given_up,
+                Err(e) => {
+                    let queued = sink.depth.get().max(0) as u64;
+                    tracing::error!(
+                        "sink {} stopped with {} events queued: {}",
+                        sink.name,
+                        queued,
+                        e
+                    );
+                    lost += queued;
+                }
+            }
+        }
+        timer.abort();
+        lost
+    }
+}
+
+#[derive(Clone)]
+pub struct SinkHealth {
+    sinks: Arc<[Tracked]>,
+}
+
+struct Tracked {
+    name: String,
+    task: AbortHandle,
+    delivered: IntCounter,
+    errors: IntCounter,
+    progress: Mutex<Progress>,
+}
+
+/// A sink's counters when it was last seen delivering, and when that was.
+struct Progress {
+    delivered: u64,
+    errors: u64,
+    at: Instant,
+}
+
+impl SinkHealth {
+    /// Sinks that have stopped, having drained their events or panicked.
+    pub fn stopped(&self) -> Vec<String> {
+        self.sinks
+            .iter()
+            .filter(|sink| sink.task.is_finished())
+            .map(|sink| sink.name.clone())
+            .collect()
+    }
+
+    /// Sinks that counted errors and have delivered nothing for `within`.
+    /// Deliveries are noticed as this is called, so it is meant to be called
+    /// regularly, as readiness checks are.
+    pub fn failing(&self, within: Duration) -> Vec<String> {
+        let now = Instant::now();
+        self.sinks
+            .iter()
+            .filter(|sink| {
+                let (delivered, errors) = (sink.delivered.get(), sink.errors.get());
+                let mut progress = sink.progress.lock().unwrap();
+                if delivered > progress.delivered {
+                    *progress = Progress {
+                        delivered,
+                        errors,
+                        at: now,
+                    };
+                }
+                errors > progress.errors && now.duration_since(progress.at) >= within
+            })
+            .map(|sink| sink.name.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ingest_core::event::EventKind;
+
+    struct Collect {
+        events: Arc<Mutex<Vec<NormalizedEvent>>>,
+        delivered: IntCounter,
+    }
+
+    #[async_trait]
+    impl Sink for Collect {
+        async fn send(&mut self, event: &NormalizedEvent) -> Result<(), IngestError> {
+            if event.symbol == "FAIL" {
+                return Err(IngestError::Sink("rejected".into()));
+            }
+            self.events.lock().unwrap().push(event.clone());
+            self.delivered.inc();
+            Ok(())
+        }
+    }
+
+    fn event(symbol: &str) -> NormalizedEvent {
+        NormalizedEvent {
+            venue: "binance_spot".into(),
+            symbol: symbol.into(),
+            kind: EventKind::Trade,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn topic_template_substitution() {
+        assert_eq!(
+            render_topic("md.{venue}.{kind}.{symbol}", &event("BTCUSDT")),
+            "md.binance_spot.trade.BTCUSDT"
+        );
+    }
+
+    #[tokio::test]
+    async fn runner_delivers_and_counts_errors() {
+        let metrics = SinkMetrics::new();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Collect {
+            events: events.clone(),
+            delivered: metrics.delivered("collect"),
+        };
+        let input = tokio_stream::iter(vec![event("BTCUSDT"), event("FAIL"), event("ETHUSDT")]);
+        let (_expire, expired) = watch::channel(false);
+        let lost = spawn(
+            "collect",
+            Box::new(sink),
+            input,
+            16,
+            metrics.clone(),
+            expired,
+        )
+        .await
+        .unwrap();
+        assert_eq!(lost, 0);
+        assert_eq!(events.lock().unwrap().len(), 2);
+        assert_eq!(metrics.delivered("collect").get(), 2);
+        assert_eq!(metrics.errors("collect").get(), 1);
+    }
+
+    #[tokio::test]
+    async fn sink_set_runs_added_sinks_until_joined() {
+        let metrics = SinkMetrics::new();
+        let mut sinks =
+            SinkSet::start(&BTreeMap::new(), |_, _| tokio_stream::empty(), &metrics).unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Collect {
+            events: events.clone(),
+            delivered: metrics.delivered("app"),
+        };
+        let input = tokio_stream::iter(vec![event("BTCUSDT"), event("ETHUSDT")]);
+        sinks.add("app", Box::new(sink), input, 16, &metrics);
+        assert_eq!(sinks.health().stopped().len(), 0);
+
+        let lost = sinks.join(Instant::now() + Duration::from_secs(5)).await;
+        assert_eq!(lost, 0);
+        assert_eq!(events.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn sinks_failing_without_deliveries_are_reported() {
+        let metrics = SinkMetrics::new();
+        let mut sinks =
+            SinkSet::start(&BTreeMap::new(), |_, _| tokio_stream::empty(), &metrics).unwrap();
+        let sink = Collect {
+            events: Arc::default(),
+            delivered: metrics.delivered("app"),
+        };
+        let (tx, rx) = mpsc::channel(16);
+        let input = tokio_stream::wrappers::ReceiverStream::new(rx);
+        sinks.add("app", Box::new(sink), input, 16, &metrics);
+        let health = sinks.health();
+
+        tx.send(event("FAIL")).await.unwrap();
+        while metrics.errors("app").get() == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert!(health.failing(Duration::from_secs(60)).is_empty());
+        assert_eq!(health.failing(Duration::ZERO), ["app"]);
+
+        tx.send(event("BTCUSDT")).await.unwrap();
+        while metrics.delivered("app").get() == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert!(health.failing(Duration::ZERO).is_empty());
+    }
+}
@@ -0,0 +1,514 @@
+//! Local Parquet archive.
+//!
+//! Events are written Hive style under
+//! `<path>/date=YYYY-MM-DD/venue=<venue>/kind=<kind>/part-*.parquet`, so the
+//! partition values live in the directory names rather than the files. A file
+//! is written under a hidden `.inprogress` name and renamed into place once it
+//! is closed, so readers never observe a partial file.
+//!
+//! Files are closed once over `max_file_bytes`, checked as their batches are
+//! written, or `max_file_age_secs` old. Age limits are kept in a heap of
+//! deadlines, earliest first, which is checked as events arrive and on every
+//! [`tick`](crate::Sink::tick), so idle partitions are closed too.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use arrow_array::{
+    builder::{StringBuilder, TimestampMicrosecondBuilder},
+    Array, ArrayRef, RecordBatch, StringArray, TimestampMicrosecondArray,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use ingest_core::{
+    config::ParquetSinkConfig,
+    error::IngestError,
+    event::{EventKind, NormalizedEvent},
+};
+use parquet::{
+    arrow::{arrow_reader::ParquetRecordBatchReaderBuilder, ArrowWriter},
+    basic::Compression,
+    file::properties::WriterProperties,
+};
+use prometheus::IntCounter;
+
+use crate::{Sink, SinkMetrics};
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Partition {
+    date: NaiveDate,
+    venue: String,
+    kind: EventKind,
+}
+
+impl Partition {
+    fn of(event: &NormalizedEvent) -> Self {
+        Self {
+            date: event.timestamp.date_naive(),
+            venue: event.venue.clone(),
+            kind: event.kind,
+        }
+    }
+
+    fn dir(&self, root: &Path) -> PathBuf {
+        root.join(format!("date={}", self.date.format("%Y-%m-%d")))
+            .join(format!("venue={}", self.venue))
+            .join(format!("kind={}", self.kind.as_str()))
+    }
+}
+
+struct OpenFile {
+    /// Sequence number the file was opened with, telling it apart from
+    /// later files of its partition.
+    id: u64,
+    writer: ArrowWriter<File>,
+    buffer: Vec<NormalizedEvent>,
+    rows_written: usize,
+    tmp_path: PathBuf,
+    final_path: PathBuf,
+}
+
+/// When the file `id` of `partition` reaches its age limit. Ordered so that
+/// a [`BinaryHeap`] yields the earliest first.
+struct Deadline {
+    at: Instant,
+    id: u64,
+    partition: Partition,
+}
+
+impl PartialEq for Deadline {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Deadline {}
+
+impl PartialOrd for Deadline {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Deadline {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.at, other.id).cmp(&(self.at, self.id))
+    }
+}
+
+pub struct ParquetSink {
+    name: String,
+    cfg: ParquetSinkConfig,
+    schema: SchemaRef,
+    files: HashMap<Partition, OpenFile>,
+    /// Age limits of the open files; entries of files already closed are
+    /// skipped as they come up.
+    deadlines: BinaryHeap<Deadline>,
+    sequence: u64,
+    delivered: IntCounter,
+}
+
+impl ParquetSink {
+    pub fn new(
+        name: &str,
+        cfg: &ParquetSinkConfig,
+        metrics: &SinkMetrics,
+    ) -> Result<Self, IngestError> {
+        fs::create_dir_all(&cfg.path)?;
+        Ok(Self {
+            name: name.to_string(),
+            cfg: cfg.clone(),
+            schema: schema(),
+            files: HashMap::new(),
+            deadlines: BinaryHeap::new(),
+            sequence: 0,
+            delivered: metrics.delivered(name),
+        })
+    }
+
+    fn open(&mut self, partition: &Partition) -> Result<OpenFile, IngestError> {
+        let dir = partition.dir(Path::new(&self.cfg.path));
+        fs::create_dir_all(&dir)?;
+        self.sequence += 1;
+        let file_name = format!(
+            "part-{}-{:04}.parquet",
+            Utc::now().format("%Y%m%dT%H%M%S%3f"),
+            self.sequence
+        );
+        let tmp_path = dir.join(format!(".{}.inprogress", file_name));
+        let props = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let writer =
+            ArrowWriter::try_new(File::create(&tmp_path)?, self.schema.clone(), Some(props))
+                .map_err(parquet_err)?;
+        let max_age = Duration::from_secs(self.cfg.max_file_age_secs);
+        if let Some(at) = Instant::now().checked_add(max_age) {
+            self.deadlines.push(Deadline {
+                at,
+                id: self.sequence,
+                partition: partition.clone(),
+            });
+        }
+        Ok(OpenFile {
+            id: self.sequence,
+            writer,
+            buffer: Vec::with_capacity(self.cfg.batch_size),
+            rows_written: 0,
+            tmp_path,
+            final_path: dir.join(file_name),
+        })
+    }
+
+    fn write_batch(&self, file: &mut OpenFile) -> Result<(), IngestError> {
+        if file.buffer.is_empty() {
+            return Ok(());
+        }
+        let batch = to_batch(&self.schema, &file.buffer)?;
+        file.writer.write(&batch).map_err(parquet_err)?;
+        // Close the row group so the size check below sees the bytes on disk.
+        file.writer.flush().map_err(parquet_err)?;
+        file.rows_written += file.buffer.len();
+        self.delivered.inc_by(file.buffer.len() as u64);
+        file.buffer.clear();
+        Ok(())
+    }
+
+    fn close(&self, mut file: OpenFile) -> Result<(), IngestError> {
+        self.write_batch(&mut file)?;
+        let out = file.writer.into_inner().map_err(parquet_err)?;
+        out.sync_all()?;
+        fs::rename(&file.tmp_path, &file.final_path)?;
+        tracing::debug!("sink {} closed {}", self.name, file.final_path.display());
+        Ok(())
+    }
+
+    fn full(&self, file: &OpenFile) -> bool {
+        // A fresh file already holds the header; only rotate on size once
+        // it has rows in it.
+        file.rows_written > 0 && file.writer.bytes_written() as u64 >= self.cfg.max_file_bytes
+    }
+
+    /// Close every file that reached its age limit.
+    fn expire(&mut self) -> Result<(), IngestError> {
+        let now = Instant::now();
+        while self.deadlines.peek().is_some_and(|next| next.at <= now) {
+            let deadline = self.deadlines.pop().unwrap();
+            let current = self
+                .files
+                .get(&deadline.partition)
+                .is_some_and(|file| file.id == deadline.id);
+            if current {
+                let file = self.files.remove(&deadline.partition).unwrap();
+                self.close(file)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Sink for ParquetSink {
+    async fn send(&mut self, event: &NormalizedEvent) -> Result<(), IngestError> {
+        let partition = Partition::of(event);
+        let mut file = match self.files.remove(&partition) {
+            Some(file) => file,
+            None => self.open(&partition)?,
+        };
+        file.buffer.push(event.clone());
+        if file.buffer.len() >= self.cfg.batch_size {
+            self.write_batch(&mut file)?;
+        }
+        if self.full(&file) {
+            self.close(file)?;
+        } else {
+            self.files.insert(partition, file);
+        }
+        self.expire()
+    }
+
+    async fn flush(&mut self) -> Result<(), IngestError> {
+        self.deadlines.clear();
+        for (_, file) in std::mem::take(&mut self.files) {
+            self.close(file)?;
+        }
+        Ok(())
+    }
+
+    async fn tick(&mut self) -> Result<(), IngestError> {
+        self.expire()
+    }
+}
+
+fn schema() -> SchemaRef {
+    let timestamp = DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()));
+    Arc::new(Schema::new(vec![
+        Field::new("symbol", DataType::Utf8, false),
+        Field::new("timestamp", timestamp.clone(), false),
+        Field::new("received_at", timestamp, true),
+        Field::new("payload", DataType::Utf8, false),
+    ]))
+}
+
+fn to_batch(schema: &SchemaRef, events: &[NormalizedEvent]) -> Result<RecordBatch, IngestError> {
+    let mut symbol = StringBuilder::new();
+    let mut timestamp = TimestampMicrosecondBuilder::new().with_timezone("UTC");
+    let mut received_at = TimestampMicrosecondBuilder::new().with_timezone("UTC");
+    let mut payload = StringBuilder::new();
+    for event in events {
+        symbol.append_value(&event.symbol);
+        timestamp.append_value(event.timestamp.timestamp_micros());
+        received_at.append_option(event.received_at.map(|t| t.timestamp_micros()));
+        payload.append_value(serde_json::to_string(&event.payload)?);
+    }
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(symbol.finish()),
+        Arc::new(timestamp.finish()),
+        Arc::new(received_at.finish()),
+        Arc::new(payload.finish()),
+    ];
+    RecordBatch::try_new(schema.clone(), columns).map_err(|e| IngestError::Sink(e.to_string()))
+}
+
+/// Write `events` to a standalone Parquet file at `path`. Unlike the
+/// sink's partitioned files, it carries the venue and kind of each event as
+/// columns of their own.
+pub fn write_events(path: &Path, events: &[NormalizedEvent]) -> Result<(), IngestError> {
+    let mut fields = vec![
+        Field::new("venue", DataType::Utf8, false),
+        Field::new("kind", DataType::Utf8, false),
+    ];
+    fields.extend(schema().fields().iter().map(|field| field.as_ref().clone()));
+    let file_schema = Arc::new(Schema::new(fields));
+    let mut columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter_values(
+            events.iter().map(|event| event.venue.as_str()),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            events.iter().map(|event| event.kind.as_str()),
+        )),
+    ];
+    columns.extend(to_batch(&schema(), events)?.columns().iter().cloned());
+    let batch = RecordBatch::try_new(file_schema.clone(), columns)
+        .map_err(|e| IngestError::Sink(e.to_string()))?;
+    let props = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut writer =
+        ArrowWriter::try_new(File::create(path)?, file_schema, Some(props)).map_err(parquet_err)?;
+    writer.write(&batch).map_err(parquet_err)?;
+    writer.close().map_err(parquet_err)?;
+    Ok(())
+}
+
+/// The events of a Parquet file written by [`write_events`].
+pub fn read_events(path: &Path) -> Result<Vec<NormalizedEvent>, IngestError> {
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)
+        .and_then(|builder| builder.build())
+        .map_err(parquet_err)?;
+    let mut events = Vec::new();
+    for batch in reader {
+        let batch = batch.map_err(|e| IngestError::Sink(e.to_string()))?;
+        let column = |name: &str| {
+            batch.column_by_name(name).ok_or_else(|| {
+                IngestError::Validation(format!("parquet file has no {} column", name))
+            })
+        };
+        let strings = |name: &str| {
+            column(name)?
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .cloned()
+                .ok_or_else(|| IngestError::Validation(format!("{} is not a string column", name)))
+        };
+        let timestamps = |name: &str| {
+            column(name)?
+                .as_any()
+                .downcast_ref::<TimestampMicrosecondArray>()
+                .cloned()
+                .ok_or_else(|| {
+                    IngestError::Validation(format!("{} is not a timestamp column", name))
+                })
+        };
+        let (venue, kind, symbol, payload) = (
+            strings("venue")?,
+            strings("kind")?,
+            strings("symbol")?,
+            strings("payload")?,
+        );
+        let (timestamp, received_at) = (timestamps("timestamp")?, timestamps("received_at")?);
+        let micros = |us: i64| {
+            DateTime::from_timestamp_micros(us)
+                .ok_or_else(|| IngestError::Validation(format!("timestamp {} out of range", us)))
+        };
+        for row in 0..batch.num_rows() {
+            events.push(NormalizedEvent {
+                venue: venue.value(row).to_string(),
+                symbol: symbol.value(row).to_string(),
+                timestamp: micros(timestamp.value(row))?,
+                received_at: if received_at.is_null(row) {
+                    None
+                } else {
+                    Some(micros(received_at.value(row))?)
+                },
+                kind: serde_json::from_value(kind.value(row).into()).map_err(|_| {
+                    IngestError::Validation(format!("unknown event kind `{}`", kind.value(row)))
+                })?,
+                payload: serde_json::from_str(payload.value(row))?,
+            });
+        }
+    }
+    Ok(events)
+}
+
+fn parquet_err(e: parquet::errors::ParquetError) -> IngestError {
+    IngestError::Sink(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg(name: &str) -> ParquetSinkConfig {
+        let path =
+            std::env::temp_dir().join(format!("ingest-parquet-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        ParquetSinkConfig {
+            path: path.to_string_lossy().into_owned(),
+            batch_size: 2,
+            max_file_bytes: u64::MAX,
+            max_file_age_secs: 3_600,
+        }
+    }
+
+    fn event(venue: &str, kind: EventKind) -> NormalizedEvent {
+        NormalizedEvent {
+            venue: venue.into(),
+            symbol: "BTCUSDT".into(),
+            timestamp: DateTime::from_timestamp_micros(1_672_515_782_136_000).unwrap(),
+            received_at: None,
+            kind,
+            payload: serde_json::json!({"price": 1.5}),
+        }
+    }
+
+    fn parquet_files(root: &Path) -> Vec<PathBuf> {
+        let mut found = Vec::new();
+        let mut dirs = vec![root.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else {
+                    found.push(path);
+                }
+            }
+        }
+        found.sort();
+        found
+    }
+
+    fn row_count(path: &Path) -> usize {
+        ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap()
+            .map(|batch| batch.unwrap().num_rows())
+            .sum()
+    }
+
+    #[tokio::test]
+    async fn writes_partitioned_files_on_flush() {
+        let cfg = cfg("partitions");
+        let metrics = SinkMetrics::new();
+        let mut sink = ParquetSink::new("archive", &cfg, &metrics).unwrap();
+        for _ in 0..3 {
+            sink.send(&event("binance_spot", EventKind::Trade))
+                .await
+                .unwrap();
+        }
+        sink.send(&event("coinbase", EventKind::Quote))
+            .await
+            .unwrap();
+        // Only the full batch has been written so far.
+        assert_eq!(metrics.delivered("archive").get(), 2);
+        sink.flush().await.unwrap();
+
+        let root = Path::new(&cfg.path);
+        let files = parquet_files(root);
+        assert_eq!(files.len(), 2);
+        assert!(files[0].starts_with(root.join("date=2022-12-31/venue=binance_spot/kind=trade")));
+        assert!(files[1].starts_with(root.join("date=2022-12-31/venue=coinbase/kind=quote")));
+        assert_eq!(row_count(&files[0]), 3);
+        assert_eq!(row_count(&files[1]), 1);
+        assert_eq!(metrics.delivered("archive").get(), 4);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn standalone_files_round_trip_events() {
+        let path =
+            std::env::temp_dir().join(format!("ingest-events-{}.parquet", std::process::id()));
+        let events = vec![
+            event("binance_spot", EventKind::Trade),
+            NormalizedEvent {
+                received_at: DateTime::from_timestamp_micros(1_672_515_782_140_000),
+                ..event("coinbase", EventKind::Quote)
+            },
+        ];
+        write_events(&path, &events).unwrap();
+        assert_eq!(read_events(&path).unwrap(), events);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn closes_idle_files_at_their_age_limit() {
+        let cfg = ParquetSinkConfig {
+            max_file_age_secs: 1,
+            ..cfg("age")
+        };
+        let metrics = SinkMetrics::new();
+        let mut sink = ParquetSink::new("archive", &cfg, &metrics).unwrap();
+        sink.send(&event("binance_spot", EventKind::Trade))
+            .await
+            .unwrap();
+        let root = Path::new(&cfg.path);
+        assert!(parquet_files(root)
+            .iter()
+            .all(|f| f.extension().unwrap() != "parquet"));
+
+        tokio::time::sleep(Duration::from_millis(1_100)).await;
+        sink.tick().await.unwrap();
+        let files = parquet_files(root);
+        assert_eq!(files.len(), 1);
+        assert_eq!(row_count(&files[0]), 1);
+        assert!(sink.files.is_empty() && sink.deadlines.is_empty());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rotates_when_file_exceeds_size() {
+        let cfg = ParquetSinkConfig {
+            max_file_bytes: 1,
+            ..cfg("rotation")
+        };
+        let metrics = SinkMetrics::new();
+        let mut sink = ParquetSink::new("archive", &cfg, &metrics).unwrap();
+        for _ in 0..4 {
+            sink.send(&event("binance_spot", EventKind::Trade))
+                .await
+                .unwrap();
+        }
+        // Both batches rotated out without waiting for a flush.
+        let files = parquet_files(Path::new(&cfg.path));
+        assert_eq!(files.len(), 2);
+        assert!(files.iter().all(|f| f.extension().unwrap() == "parquet"));
+        fs::remove_dir_all(&cfg.path).unwrap();
+    }
+}
@@ -0,0 +1,343 @@
+//! Postgres/TimescaleDB sink.
+//!
+//! Events are batched per kind and loaded with binary `COPY` into one table
+//! per kind. The schema is bootstrapped from the migrations under
+//! `migrations/postgres`, which become hypertables when TimescaleDB is
+//! installed.
+//!
+//! A batch whose `COPY` fails is kept and tried again with a backoff, growing
+//! with the events sent meanwhile up to [`HELD_BATCHES`] batches, past which
+//! its oldest rows are dropped and counted as errors. A flush tries every
+//! batch once more; what still fails is dropped and counted and the flush
+//! fails, so at-least-once delivery sends those events again.
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use deadpool_postgres::{Pool, PoolConfig, Runtime};
+use ingest_core::{
+    config::PostgresSinkConfig,
+    error::IngestError,
+    event::{EventKind, NormalizedEvent},
+};
+use prometheus::IntCounter;
+use tokio_postgres::{
+    binary_copy::BinaryCopyInWriter,
+    types::{Json, ToSql, Type},
+    NoTls,
+};
+
+use crate::retry::Retry;
+pub use crate::retry::HELD_BATCHES;
+pub use crate::table;
+use crate::{Sink, SinkMetrics};
+
+/// Schema migrations, applied in order and recorded in `ingest_migrations`.
+pub const MIGRATIONS: &[(&str, &str)] = &[
+    (
+        "0001_create_event_tables",
+        include_str!("../migrations/postgres/0001_create_event_tables.sql"),
+    ),
+    (
+        "0002_timescale_hypertables",
+        include_str!("../migrations/postgres/0002_timescale_hypertables.sql"),
+    ),
+];
+
+const COLUMN_TYPES: &[Type] = &[
+    Type::TEXT,
+    Type::TEXT,
+    Type::TIMESTAMPTZ,
+    Type::TIMESTAMPTZ,
+    Type::JSONB,
+];
+
+struct Batch {
+    rows: Vec<NormalizedEvent>,
+    started: Instant,
+}
+
+pub struct PostgresSink {
+    name: String,
+    pool: Pool,
+    cfg: PostgresSinkConfig,
+    migrated: bool,
+    batches: HashMap<EventKind, Batch>,
+    retry: Retry,
+    delivered: IntCounter,
+}
+
+impl PostgresSink {
+    pub fn new(
+        name: &str,
+        cfg: &PostgresSinkConfig,
+        metrics: &SinkMetrics,
+    ) -> Result<Self, IngestError> {
+        let pool_cfg = deadpool_postgres::Config {
+            url: Some(cfg.url.clone()),
+            pool: Some(PoolConfig::new(cfg.pool_size)),
+            ..Default::default()
+        };
+        let pool = pool_cfg
+            .create_pool(Some(Runtime::Tokio1), NoTls)
+            .map_err(|e| IngestError::Sink(format!("sink {}: {}", name, e)))?;
+        Ok(Self {
+            name: name.to_string(),
+            pool,
+            cfg: cfg.clone(),
+            migrated: false,
+            batches: HashMap::new(),
+            retry: Retry::new(name, metrics),
+            delivered: metrics.delivered(name),
+        })
+    }
+
+    /// Apply any migrations the database has not seen yet.
+    async fn migrate(&mut self) -> Result<(), IngestError> {
+        let mut client = self.pool.get().await.map_err(pool_err)?;
+        client
+            .batch_execute(
+                "CREATE TABLE IF NOT EXISTS ingest_migrations (
+                    name TEXT PRIMARY KEY,
+                    applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+                )",
+            )
+            .await
+            .map_err(pg_err)?;
+        let applied: HashSet<String> = client
+            .query("SELECT name FROM ingest_migrations", &[])
+            .await
+            .map_err(pg_err)?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+        for (name, sql) in MIGRATIONS {
+            if applied.contains(*name) {
+                continue;
+            }
+            let tx = client.transaction().await.map_err(pg_err)?;
+            tx.batch_execute(sql).await.map_err(pg_err)?;
+            tx.execute("INSERT INTO ingest_migrations (name) VALUES ($1)", &[name])
+                .await
+                .map_err(pg_err)?;
+            tx.commit().await.map_err(pg_err)?;
+            tracing::info!("sink {} applied migration {}", self.name, name);
+        }
+        self.migrated = true;
+        Ok(())
+    }
+
+    async fn copy(&self, kind: EventKind, rows: &[NormalizedEvent]) -> Result<(), IngestError> {
+        let client = self.pool.get().await.map_err(pool_err)?;
+        let statement = format!(
+            "COPY {} (venue, symbol, ts, received_at, payload) FROM STDIN BINARY",
+            table(kind)
+        );
+        let sink = client.copy_in(&statement).await.map_err(pg_err)?;
+        let writer = BinaryCopyInWriter::new(sink, COLUMN_TYPES);
+        tokio::pin!(writer);
+        for event in rows {
+            let payload = Json(&event.payload);
+            let row: [&(dyn ToSql + Sync); 5] = [
+                &event.venue,
+                &event.symbol,
+                &event.timestamp,
+                &event.received_at,
+                &payload,
+            ];
+            writer.as_mut().write(&row).await.map_err(pg_err)?;
+        }
+        let written = writer.finish().await.map_err(pg_err)?;
+        self.delivered.inc_by(written);
+        Ok(())
+    }
+
+    /// Copy out every batch that is full or old enough, or all of them when
+    /// `all` is set. A batch that fails is kept for a retry after a backoff;
+    /// with `all`, what fails is dropped instead and the error returned.
+    async fn drain(&mut self, all: bool) -> Result<(), IngestError> {
+        if !self.retry.ready(all) {
+            self.trim();
+            return Ok(());
+        }
+        let interval = Duration::from_millis(self.cfg.flush_interval_ms);
+        let due: Vec<EventKind> = self
+            .batches
+            .iter()
+            .filter(|(_, b)| {
+                all || b.rows.len() >= self.cfg.batch_size || b.started.elapsed() >= interval
+            })
+            .map(|(kind, _)| *kind)
+            .collect();
+        let mut failed = None;
+        for kind in due {
+            let Some(batch) = self.batches.remove(&kind) else {
+                continue;
+            };
+            match self.copy(kind, &batch.rows).await {
+                Ok(()) => {}
+                Err(e) => {
+                    self.batches.insert(kind, batch);
+                    failed = Some(e);
+                    break;
+                }
+            }
+        }
+        let Some(e) = failed else {
+            self.retry.succeeded();
+            return Ok(());
+        };
+        if all {
+            let dropped: usize = self.batches.drain().map(|(_, b)| b.rows.len()).sum();
+            return Err(self.retry.give_up(dropped, e));
+        }
+        self.retry.failed(&e);
+        self.trim();
+        Ok(())
+    }
+
+    /// Drop the oldest rows of batches held past [`HELD_BATCHES`].
+    fn trim(&mut self) {
+        let limit = Retry::limit(self.cfg.batch_size);
+        for batch in self.batches.values_mut() {
+            let excess = batch.rows.len().saturating_sub(limit);
+            batch.rows.drain(..excess);
+            self.retry.trimmed(excess);
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for PostgresSink {
+    async fn send(&mut self, event: &NormalizedEvent) -> Result<(), IngestError> {
+        if !self.migrated {
+            self.migrate().await?;
+        }
+        self.batches
+            .entry(event.kind)
+            .or_insert_with(|| Batch {
+                rows: Vec::new(),
+                started: Instant::now(),
+            })
+            .rows
+            .push(event.clone());
+        self.drain(false).await
+    }
+
+    async fn flush(&mut self) -> Result<(), IngestError> {
+        self.drain(true).await
+    }
+
+    async fn tick(&mut self) -> Result<(), IngestError> {
+        self.drain(false).await
+    }
+}
+
+fn pg_err(e: tokio_postgres::Error) -> IngestError {
+    IngestError::Sink(e.to_string())
+}
+
+fn pool_err(e: deadpool_postgres::PoolError) -> IngestError {
+    IngestError::Sink(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrations_are_ordered_and_cover_every_table() {
+        let names: Vec<&str> = MIGRATIONS.iter().map(|(name, _)| *name).collect();
+        let mut sorted = names.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(names, sorted);
+
+        let kinds = [
+            EventKind::Trade,
+            EventKind::Ticker,
+            EventKind::Quote,
+            EventKind::Book,
+            EventKind::Raw,
+        ];
+        for kind in kinds {
+            let create = format!("CREATE TABLE IF NOT EXISTS {} ", table(kind));
+            assert!(MIGRATIONS[0].1.contains(&create), "{}", table(kind));
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_failed_batches_until_a_flush() {
+        let cfg = PostgresSinkConfig {
+            url: "postgres://postgres@127.0.0.1:1/ingest".into(),
+            pool_size: 1,
+            batch_size: 2,
+            flush_interval_ms: 60_000,
+        };
+        let metrics = SinkMetrics::new();
+        let mut sink = PostgresSink::new("pg", &cfg, &metrics).unwrap();
+        sink.migrated = true;
+        let event = NormalizedEvent {
+            kind: EventKind::Trade,
+            ..Default::default()
+        };
+        for _ in 0..3 {
+            sink.send(&event).await.unwrap();
+        }
+        assert_eq!(sink.batches[&EventKind::Trade].rows.len(), 3);
+        assert_eq!(metrics.errors("pg").get(), 0);
+
+        assert!(sink.flush().await.is_err());
+        assert!(sink.batches.is_empty());
+        assert_eq!(metrics.errors("pg").get(), 3);
+    }
+
+    #[tokio::test]
+    async fn drops_the_oldest_rows_past_the_held_batches() {
+        let cfg = PostgresSinkConfig {
+            url: "postgres://postgres@127.0.0.1:1/ingest".into(),
+            pool_size: 1,
+            batch_size: 1,
+            flush_interval_ms: 60_000,
+        };
+        let metrics = SinkMetrics::new();
+        let mut sink = PostgresSink::new("pg", &cfg, &metrics).unwrap();
+        sink.migrated = true;
+        for _ in 0..HELD_BATCHES + 5 {
+            sink.send(&NormalizedEvent::default()).await.unwrap();
+        }
+        let held = &sink.batches[&EventKind::default()].rows;
+        assert_eq!(held.len(), HELD_BATCHES);
+        assert_eq!(metrics.errors("pg").get(), 5);
+    }
+
+    // Needs a disposable database, e.g.
+    // INGEST_TEST_POSTGRES=postgres://postgres@localhost/ingest_test
+    #[tokio::test]
+    #[ignore]
+    async fn copies_batches_into_kind_tables() {
+        let url = std::env::var("INGEST_TEST_POSTGRES").expect("INGEST_TEST_POSTGRES");
+        let cfg = PostgresSinkConfig {
+            url,
+            pool_size: 2,
+            batch_size: 2,
+            flush_interval_ms: 60_000,
+        };
+        let metrics = SinkMetrics::new();
+        let mut sink = PostgresSink::new("pg", &cfg, &metrics).unwrap();
+        let event = NormalizedEvent {
+            venue: "binance_spot".into(),
+            symbol: "BTCUSDT".into(),
+            kind: EventKind::Trade,
+            payload: serde_json::json!({"price": 1.5}),
+            ..Default::default()
+        };
+        for _ in 0..3 {
+            sink.send(&event).await.unwrap();
+        }
+        assert_eq!(metrics.delivered("pg").get(), 2);
+        sink.flush().await.unwrap();
+        assert_eq!(metrics.delivered("pg").get(), 3);
+    }
+}
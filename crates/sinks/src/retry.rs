@@ -0,0 +1,98 @@
+//! Backoff for batching sinks that keep a batch they failed to write.
+//!
+//! A sink keeps what it failed to write and tries again once the backoff
+//! has passed, holding up to [`HELD_BATCHES`] batches meanwhile. A flush
+//! tries once more regardless; what still fails is dropped and counted and
+//! the flush fails, so at-least-once delivery sends those events again.
+
+use std::time::{Duration, Instant};
+
+use ingest_core::error::IngestError;
+use prometheus::IntCounter;
+
+use crate::SinkMetrics;
+
+/// Batches' worth of events a sink keeps while its writes fail.
+pub const HELD_BATCHES: usize = 10;
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+pub(crate) struct Retry {
+    name: String,
+    /// Failed writes in a row, and when to try again.
+    failures: u32,
+    retry_at: Option<Instant>,
+    errors: IntCounter,
+}
+
+impl Retry {
+    pub(crate) fn new(name: &str, metrics: &SinkMetrics) -> Self {
+        Self {
+            name: name.to_string(),
+            failures: 0,
+            retry_at: None,
+            errors: metrics.errors(name),
+        }
+    }
+
+    /// Whether a write may be tried: always for a flush, otherwise once the
+    /// backoff after the last failure has passed.
+    pub(crate) fn ready(&self, flush: bool) -> bool {
+        flush || self.retry_at.is_none_or(|at| Instant::now() >= at)
+    }
+
+    /// Events of `batch_size` batches kept while writes fail.
+    pub(crate) fn limit(batch_size: usize) -> usize {
+        batch_size.max(1) * HELD_BATCHES
+    }
+
+    pub(crate) fn succeeded(&mut self) {
+        if self.failures > 0 {
+            tracing::info!(
+                "sink {} recovered after {} failed writes",
+                self.name,
+                self.failures
+            );
+        }
+        self.failures = 0;
+        self.retry_at = None;
+    }
+
+    /// Back off after a write that failed with `error`, its events kept.
+    pub(crate) fn failed(&mut self, error: &IngestError) {
+        self.failures += 1;
+        if self.failures == 1 {
+            tracing::warn!(
+                "sink {} failed to write, keeping its events: {}",
+                self.name,
+                error
+            );
+        }
+        let exponent = (self.failures - 1).min(16);
+        let delay = (RETRY_DELAY * (1 << exponent)).min(MAX_RETRY_DELAY);
+        self.retry_at = Some(Instant::now() + delay);
+    }
+
+    /// Give up on `count` events after a failed flush, returning the error
+    /// for the flush.
+    pub(crate) fn give_up(&mut self, count: usize, error: IngestError) -> IngestError {
+        self.failures += 1;
+        self.retry_at = None;
+        self.errors.inc_by(count as u64);
+        IngestError::Sink(format!("dropped {} events: {}", count, error))
+    }
+
+    /// Count `count` of the oldest events dropped to stay within
+    /// [`HELD_BATCHES`].
+    pub(crate) fn trimmed(&self, count: usize) {
+        if count == 0 {
+            return;
+        }
+        self.errors.inc_by(count as u64);
+        tracing::warn!(
+            "sink {} dropped {} events, its writes keep failing",
+            self.name,
+            count
+        );
+    }
+}
@@ -0,0 +1,377 @@
+//! Shared-memory ring for co-located consumers.
+//!
+//! The sink maps `path` (usually under `/dev/shm`) and writes every event
+//! into a single-producer, multi-consumer ring, which any number of local
+//! processes map read-only and poll without a socket or a copy through the
+//! kernel. The file starts with a 64-byte header of little-endian `u64`s:
+//!
+//! ```text
+//! 0   magic      "INGSHM01"
+//! 8   capacity   bytes in the data region, which follows the header
+//! 16  reserved   end of the frame being written
+//! 24  written    end of the last complete frame
+//! ```
+//!
+//! Positions only grow; a position modulo `capacity` is its offset in the
+//! data region. Frames are 8-byte aligned:
+//!
+//! ```text
+//! len: u32 | unused: u32 | sequence: u64 | encoded event | padding
+//! ```
+//!
+//! where `len` covers the 16-byte frame header and the event. A frame that
+//! would run past the end of the data region starts at offset 0 instead,
+//! after a `len` of `u32::MAX` marking the wrap. To read, load `written`,
+//! copy out the frames before it and then load `reserved`: a frame copied
+//! from position `p` is intact only if `reserved - p <= capacity`. Otherwise
+//! the reader has been lapped and resumes from `written`, and the frame
+//! sequence numbers, starting at 1, show how many events it missed.
+//! [`ShmReader`] implements this for Rust consumers.
+//!
+//! A new ring is set up in a file of its own and renamed over `path`, so
+//! readers still mapping the ring of a previous run keep an intact copy of
+//! it, and reopen `path` to follow the new one.
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+
+use async_trait::async_trait;
+use ingest_core::{
+    config::{Encoding, ShmSinkConfig},
+    error::IngestError,
+    event::NormalizedEvent,
+};
+use memmap2::{MmapOptions, MmapRaw};
+use prometheus::IntCounter;
+
+use crate::{codec, Sink, SinkMetrics};
+
+const MAGIC: &[u8; 8] = b"INGSHM01";
+const HEADER_LEN: usize = 64;
+const CAPACITY: usize = 8;
+const RESERVED: usize = 16;
+const WRITTEN: usize = 24;
+const FRAME_HEADER_LEN: usize = 16;
+const WRAP: u32 = u32::MAX;
+
+struct Ring {
+    map: MmapRaw,
+    capacity: u64,
+}
+
+impl Ring {
+    fn position(&self, field: usize) -> &AtomicU64 {
+        // SAFETY: the map is page aligned and at least `HEADER_LEN` long, so
+        // the header fields are aligned, in bounds and live as long as `self`.
+        unsafe { &*(self.map.as_ptr().add(field) as *const AtomicU64) }
+    }
+
+    fn data(&self) -> *mut u8 {
+        // SAFETY: the data region follows the header within the map.
+        unsafe { self.map.as_mut_ptr().add(HEADER_LEN) }
+    }
+
+    fn offset(&self, position: u64) -> usize {
+        (position % self.capacity) as usize
+    }
+}
+
+pub struct ShmSink {
+    ring: Ring,
+    encoding: Encoding,
+    position: u64,
+    sequence: u64,
+    delivered: IntCounter,
+}
+
+impl ShmSink {
+    /// Create the ring at `cfg.path`, replacing any earlier one once it is
+    /// ready.
+    pub fn new(
+        name: &str,
+        cfg: &ShmSinkConfig,
+        metrics: &SinkMetrics,
+    ) -> Result<Self, IngestError> {
+        let ring = create(Path::new(&cfg.path), cfg.capacity_bytes)
+            .map_err(|e| IngestError::Sink(format!("sink {}: {}: {}", name, cfg.path, e)))?;
+        Ok(Self {
+            ring,
+            encoding: cfg.encoding,
+            position: 0,
+            sequence: 0,
+            delivered: metrics.delivered(name),
+        })
+    }
+
+    fn write(&mut self, body: &[u8]) -> Result<(), IngestError> {
+        let len = FRAME_HEADER_LEN + body.len();
+        let padded = len.next_multiple_of(8) as u64;
+        if padded > self.ring.capacity {
+            return Err(IngestError::Sink(format!(
+                "event of {} bytes does not fit a ring of {} bytes",
+                len, self.ring.capacity
+            )));
+        }
+        let mut start = self.position;
+        let mut offset = self.ring.offset(start);
+        let wrap = offset as u64 + padded > self.ring.capacity;
+        let skipped = if wrap {
+            self.ring.capacity - offset as u64
+        } else {
+            0
+        };
+        let end = start + skipped + padded;
+        self.sequence += 1;
+
+        self.ring.position(RESERVED).store(end, Ordering::Relaxed);
+        fence(Ordering::Release);
+        let data = self.ring.data();
+        // SAFETY: frames are 8-byte aligned and `padded` fits the data region
+        // after `offset`, wrapping to 0 first when it would not.
+        unsafe {
+            if wrap {
+                write_bytes(data, offset, &WRAP.to_le_bytes());
+                start += skipped;
+                offset = 0;
+            }
+            write_bytes(data, offset, &(len as u32).to_le_bytes());
+            write_bytes(data, offset + 8, &self.sequence.to_le_bytes());
+            write_bytes(data, offset + FRAME_HEADER_LEN, body);
+        }
+        debug_assert_eq!(start + padded, end);
+        self.ring.position(WRITTEN).store(end, Ordering::Release);
+        self.position = end;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Sink for ShmSink {
+    async fn send(&mut self, event: &NormalizedEvent) -> Result<(), IngestError> {
+        let body = codec::encode(event, self.encoding)?;
+        self.write(&body)?;
+        self.delivered.inc();
+        Ok(())
+    }
+}
+
+/// # Safety
+///
+/// `offset + bytes.len()` must lie within the data region.
+unsafe fn write_bytes(data: *mut u8, offset: usize, bytes: &[u8]) {
+    std::ptr::copy_nonoverlapping(bytes.as_ptr(), data.add(offset), bytes.len());
+}
+
+fn create(path: &Path, capacity: u64) -> io::Result<Ring> {
+    let capacity = capacity - capacity % 8;
+    if capacity < 64 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "capacity_bytes must be at least 64",
+        ));
+    }
+    let tmp = path.with_extension("tmp");
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&tmp)?;
+    file.set_len(HEADER_LEN as u64 + capacity)?;
+    let ring = Ring {
+        map: MmapOptions::new().map_raw(&file)?,
+        capacity,
+    };
+    // SAFETY: the header lies within the map.
+    unsafe {
+        write_bytes(ring.map.as_mut_ptr(), CAPACITY, &capacity.to_le_bytes());
+        write_bytes(ring.map.as_mut_ptr(), 0, MAGIC);
+    }
+    // The map outlives the name, so only the finished ring is ever at `path`.
+    if let Err(e) = std::fs::rename(&tmp, path) {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e);
+    }
+    Ok(ring)
+}
+
+/// Consumer end of a ring written by [`ShmSink`], starting at the events
+/// written after it was opened.
+pub struct ShmReader {
+    ring: Ring,
+    position: u64,
+    next_sequence: u64,
+    missed: u64,
+}
+
+impl ShmReader {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let map = MmapOptions::new().map_raw_read_only(&file)?;
+        let invalid = |reason| io::Error::new(io::ErrorKind::InvalidData, reason);
+        if map.len() < HEADER_LEN {
+            return Err(invalid("truncated ring header"));
+        }
+        // SAFETY: the header lies within the map.
+        let header = unsafe { std::slice::from_raw_parts(map.as_ptr(), HEADER_LEN) };
+        if &header[..8] != MAGIC {
+            return Err(invalid("not an ingest ring"));
+        }
+        let capacity = u64::from_le_bytes(header[CAPACITY..CAPACITY + 8].try_into().unwrap());
+        if capacity == 0 || (map.len() as u64) < HEADER_LEN as u64 + capacity {
+            return Err(invalid("truncated ring"));
+        }
+        let ring = Ring { map, capacity };
+        let position = ring.position(WRITTEN).load(Ordering::Acquire);
+        Ok(Self {
+            ring,
+            position,
+            next_sequence: 0,
+            missed: 0,
+        })
+    }
+
+    /// The next event's sequence number and encoded bytes, or `None` when
+    /// the reader has caught up with the writer.
+    pub fn try_next(&mut self) -> Option<(u64, Vec<u8>)> {
+        loop {
+            let written = self.ring.position(WRITTEN).load(Ordering::Acquire);
+            if written < self.position {
+                // The ring was recreated by a restarted writer.
+                self.position = written;
+            }
+            if self.position == written {
+                return None;
+            }
+            let offset = self.ring.offset(self.position);
+            let mut len = [0; 4];
+            // SAFETY: frames are 8-byte aligned and the capacity is a
+            // multiple of 8, so at least 8 bytes remain after `offset`.
+            unsafe { read_bytes(self.ring.data(), offset, &mut len) };
+            let len = u32::from_le_bytes(len);
+            let room = self.ring.capacity as usize - offset;
+            let frame = match len {
+                WRAP => Frame::Wrap,
+                len if (FRAME_HEADER_LEN..=room).contains(&(len as usize)) => {
+                    let mut frame = vec![0; len as usize];
+                    // SAFETY: the frame fits the data region after `offset`.
+                    unsafe { read_bytes(self.ring.data(), offset, &mut frame) };
+                    let sequence = u64::from_le_bytes(frame[8..16].try_into().unwrap());
+                    frame.drain(..FRAME_HEADER_LEN);
+                    Frame::Event(len as usize, sequence, frame)
+                }
+                _ => Frame::Torn,
+            };
+            fence(Ordering::Acquire);
+            let reserved = self.ring.position(RESERVED).load(Ordering::Relaxed);
+            let (len, sequence, body) = match frame {
+                // Also taken when a restarted writer recreated the ring.
+                _ if reserved.wrapping_sub(self.position) > self.ring.capacity => {
+                    self.position = self.ring.position(WRITTEN).load(Ordering::Acquire);
+                    continue;
+                }
+                Frame::Wrap => {
+                    self.position += room as u64;
+                    continue;
+                }
+                Frame::Event(len, sequence, body) => (len, sequence, body),
+                Frame::Torn => {
+                    tracing::warn!("shared-memory ring frame is corrupt, skipping ahead");
+                    self.position = written;
+                    continue;
+                }
+            };
+            self.position += len.next_multiple_of(8) as u64;
+            if self.next_sequence != 0 {
+                self.missed += sequence.saturating_sub(self.next_sequence);
+            }
+            self.next_sequence = sequence + 1;
+            return Some((sequence, body));
+        }
+    }
+
+    /// Events skipped after being lapped by the writer.
+    pub fn missed(&self) -> u64 {
+        self.missed
+    }
+}
+
+enum Frame {
+    Wrap,
+    Event(usize, u64, Vec<u8>),
+    /// Overwritten while it was read, or corrupt.
+    Torn,
+}
+
+/// # Safety
+///
+/// `offset + buf.len()` must lie within the data region.
+unsafe fn read_bytes(data: *const u8, offset: usize, buf: &mut [u8]) {
+    std::ptr::copy_nonoverlapping(data.add(offset), buf.as_mut_ptr(), buf.len());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ingest_core::config::Encoding;
+
+    fn ring(name: &str, capacity_bytes: u64) -> (ShmSink, ShmReader, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!("ingest-shm-{}-{}", name, std::process::id()));
+        let cfg = ShmSinkConfig {
+            path: path.to_string_lossy().into_owned(),
+            encoding: Encoding::Json,
+            capacity_bytes,
+        };
+        let sink = ShmSink::new("shm", &cfg, &SinkMetrics::new()).unwrap();
+        let reader = ShmReader::open(&path).unwrap();
+        (sink, reader, path)
+    }
+
+    #[tokio::test]
+    async fn reads_events_across_wraps() {
+        let (mut sink, mut reader, path) = ring("wrap", 512);
+        for n in 0..20 {
+            let event = NormalizedEvent {
+                symbol: format!("S{}", n),
+                ..Default::default()
+            };
+            sink.send(&event).await.unwrap();
+            let (sequence, body) = reader.try_next().unwrap();
+            assert_eq!(sequence, n + 1);
+            assert_eq!(codec::decode(&body, Encoding::Json).unwrap(), event);
+            assert!(reader.try_next().is_none());
+        }
+        assert!(sink.position > sink.ring.capacity);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn lapped_reader_skips_ahead_and_counts_missed_events() {
+        let (mut sink, mut reader, path) = ring("lapped", 128);
+        sink.write(&[1; 8]).unwrap();
+        assert_eq!(reader.try_next(), Some((1, vec![1; 8])));
+        for n in 2..=12 {
+            sink.write(&[n; 8]).unwrap();
+        }
+        assert_eq!(reader.try_next(), None);
+        sink.write(&[13; 8]).unwrap();
+        assert_eq!(reader.try_next(), Some((13, vec![13; 8])));
+        assert_eq!(reader.missed(), 11);
+        assert!(sink.write(&[0; 200]).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn a_new_ring_leaves_the_previous_one_to_its_readers() {
+        let (mut sink, mut reader, path) = ring("replaced", 128);
+        sink.write(&[1; 8]).unwrap();
+        let (mut replacement, mut fresh, _) = ring("replaced", 128);
+        assert_eq!(reader.try_next(), Some((1, vec![1; 8])));
+        replacement.write(&[2; 8]).unwrap();
+        assert_eq!(fresh.try_next(), Some((1, vec![2; 8])));
+        assert_eq!(reader.try_next(), None);
+        std::fs::remove_file(path).unwrap();
+    }
+}
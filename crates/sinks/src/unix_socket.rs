@@ -0,0 +1,201 @@
+//! Local socket output for co-located consumers.
+//!
+//! The sink listens on a Unix domain socket (a named pipe such as
+//! `\\.\pipe\ingest` on Windows), and every connected client receives each
+//! event as a frame of
+//!
+//! ```text
+//! len: u32 (big-endian) | encoded event
+//! ```
+//!
+//! Each client has its own bounded buffer, and a client that fills it is
+//! disconnected rather than stalling the others.
+
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use ingest_core::{config::UnixSocketSinkConfig, error::IngestError, event::NormalizedEvent};
+use prometheus::IntCounter;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::task::JoinHandle;
+
+use crate::{codec, Sink, SinkMetrics};
+
+type Frame = Arc<[u8]>;
+type Clients = Arc<Mutex<Vec<mpsc::Sender<Frame>>>>;
+
+pub struct UnixSocketSink {
+    name: String,
+    cfg: UnixSocketSinkConfig,
+    clients: Clients,
+    listener: JoinHandle<()>,
+    delivered: IntCounter,
+}
+
+impl UnixSocketSink {
+    /// Start listening on `cfg.path`. Must be called within a Tokio runtime.
+    pub fn new(
+        name: &str,
+        cfg: &UnixSocketSinkConfig,
+        metrics: &SinkMetrics,
+    ) -> Result<Self, IngestError> {
+        let clients = Clients::default();
+        let listener = listen(name, cfg, clients.clone())
+            .map_err(|e| IngestError::Sink(format!("sink {}: {}: {}", name, cfg.path, e)))?;
+        Ok(Self {
+            name: name.to_string(),
+            cfg: cfg.clone(),
+            clients,
+            listener,
+            delivered: metrics.delivered(name),
+        })
+    }
+}
+
+impl Drop for UnixSocketSink {
+    fn drop(&mut self) {
+        self.listener.abort();
+        #[cfg(unix)]
+        let _ = std::fs::remove_file(&self.cfg.path);
+    }
+}
+
+#[async_trait]
+impl Sink for UnixSocketSink {
+    async fn send(&mut self, event: &NormalizedEvent) -> Result<(), IngestError> {
+        let body = codec::encode(event, self.cfg.encoding)?;
+        let mut frame = Vec::with_capacity(4 + body.len());
+        frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&body);
+        let frame: Frame = frame.into();
+        let mut clients = self.clients.lock().unwrap();
+        if clients.is_empty() {
+            return Ok(());
+        }
+        clients.retain(|client| match client.try_send(frame.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                tracing::warn!("sink {} disconnected a client that fell behind", self.name);
+                false
+            }
+            Err(TrySendError::Closed(_)) => false,
+        });
+        self.delivered.inc();
+        Ok(())
+    }
+}
+
+/// Register a connected client and write its frames until it goes away.
+fn serve<W>(client: W, buffer: usize, clients: &Clients)
+where
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let (tx, mut rx) = mpsc::channel::<Frame>(buffer.max(1));
+    clients.lock().unwrap().push(tx);
+    tokio::spawn(async move {
+        let mut client = client;
+        while let Some(frame) = rx.recv().await {
+            if client.write_all(&frame).await.is_err() {
+                break;
+            }
+        }
+    });
+}
+
+#[cfg(unix)]
+fn listen(
+    name: &str,
+    cfg: &UnixSocketSinkConfig,
+    clients: Clients,
+) -> std::io::Result<JoinHandle<()>> {
+    use std::os::unix::fs::FileTypeExt;
+
+    // A socket left behind by a previous run would make bind fail.
+    if std::fs::metadata(&cfg.path).is_ok_and(|m| m.file_type().is_socket()) {
+        std::fs::remove_file(&cfg.path)?;
+    }
+    let listener = tokio::net::UnixListener::bind(&cfg.path)?;
+    let name = name.to_string();
+    let buffer = cfg.client_buffer;
+    Ok(tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => serve(stream, buffer, &clients),
+                Err(e) => tracing::warn!("sink {} failed to accept a client: {}", name, e),
+            }
+        }
+    }))
+}
+
+#[cfg(windows)]
+fn listen(
+    name: &str,
+    cfg: &UnixSocketSinkConfig,
+    clients: Clients,
+) -> std::io::Result<JoinHandle<()>> {
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    let path = cfg.path.clone();
+    let mut server = ServerOptions::new()
+        .first_pipe_instance(true)
+        .create(&path)?;
+    let name = name.to_string();
+    let buffer = cfg.client_buffer;
+    Ok(tokio::spawn(async move {
+        loop {
+            if let Err(e) = server.connect().await {
+                tracing::warn!("sink {} failed to accept a client: {}", name, e);
+                continue;
+            }
+            // Keep a fresh instance listening before handing this one off.
+            let next = match ServerOptions::new().create(&path) {
+                Ok(next) => next,
+                Err(e) => {
+                    tracing::error!("sink {} stopped listening: {}", name, e);
+                    return;
+                }
+            };
+            serve(std::mem::replace(&mut server, next), buffer, &clients);
+        }
+    }))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use ingest_core::{config::Encoding, event::EventKind};
+    use std::time::Duration;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn streams_length_prefixed_events() {
+        let path = std::env::temp_dir().join(format!("ingest-uds-{}.sock", std::process::id()));
+        let cfg = UnixSocketSinkConfig {
+            path: path.to_string_lossy().into_owned(),
+            encoding: Encoding::Json,
+            client_buffer: 16,
+        };
+        let mut sink = UnixSocketSink::new("uds", &cfg, &SinkMetrics::new()).unwrap();
+        let mut client = tokio::net::UnixStream::connect(&path).await.unwrap();
+        while sink.clients.lock().unwrap().is_empty() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        let event = NormalizedEvent {
+            venue: "binance_spot".into(),
+            symbol: "BTCUSDT".into(),
+            kind: EventKind::Trade,
+            payload: serde_json::json!({"price": 1.5}),
+            ..Default::default()
+        };
+        sink.send(&event).await.unwrap();
+        let len = client.read_u32().await.unwrap();
+        let mut body = vec![0; len as usize];
+        client.read_exact(&mut body).await.unwrap();
+        assert_eq!(codec::decode(&body, Encoding::Json).unwrap(), event);
+
+        drop(sink);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,876 @@
+//! Segmented write-ahead log of published events.
+//!
+//! Segments are named after the sequence number of their first record,
+//! `<first_seq:020>.wal`. Each record is framed as
+//!
+//! ```text
+//! len: u32 | crc32: u32 | sequence: u64 | timestamp_us: i64 | event JSON
+//! ```
+//!
+//! with little-endian integers, where `len` and the CRC cover everything after
+//! the CRC. A torn record at the tail of the newest segment, left behind by a
+//! crash mid-write, is truncated when the log is reopened.
+
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use api::{EventPublisher, History, Spill};
+use chrono::{DateTime, Utc};
+use ingest_core::{
+    config::{BusSpillConfig, FsyncPolicy, WalConfig},
+    error::IngestError,
+    event::NormalizedEvent,
+};
+
+const HEADER_LEN: usize = 8;
+const PREFIX_LEN: usize = 16;
+const MAX_RECORD_LEN: usize = 64 * 1024 * 1024;
+const EXTENSION: &str = "wal";
+/// Spilled events a [`SpillLog`] reads back at a time.
+const SPILL_READ_AHEAD: usize = 1024;
+/// Segments a [`SpillLog`] splits `max_bytes` into.
+const SPILL_SEGMENTS: u64 = 8;
+/// How far past the end of a time window a record may be before reading
+/// the window stops. Records are logged as they arrive, so their
+/// timestamps are only out of order by how much venues' clocks and
+/// delivery delays differ.
+const WINDOW_SLACK_US: i64 = 60_000_000;
+
+/// A logged event together with its sequence number.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub sequence: u64,
+    /// Timestamp of the record: the event's own, or the one it was
+    /// appended at with [`Wal::append_at`].
+    pub timestamp: DateTime<Utc>,
+    pub event: NormalizedEvent,
+}
+
+/// Appending side of the log. Sequence numbers start at 1 and increase by one
+/// per record across segments and restarts, except where skipped ahead with
+/// [`Wal::skip_to`].
+pub struct Wal {
+    dir: PathBuf,
+    cfg: WalConfig,
+    file: File,
+    segment_len: u64,
+    next_sequence: u64,
+    last_sync: Instant,
+    dirty: bool,
+}
+
+impl Wal {
+    pub fn open(cfg: &WalConfig) -> Result<Self, IngestError> {
+        let dir = PathBuf::from(&cfg.path);
+        fs::create_dir_all(&dir)?;
+        let (path, next_sequence, segment_len) = match segments(&dir)?.pop() {
+            Some((first, path)) => {
+                let (valid_len, last) = recover(&path)?;
+                (path, last.map_or(first, |seq| seq + 1), valid_len)
+            }
+            None => (segment_path(&dir, 1), 1, 0),
+        };
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        if file.metadata()?.len() > segment_len {
+            tracing::warn!("truncating torn wal record in {}", path.display());
+            file.set_len(segment_len)?;
+        }
+        Ok(Self {
+            dir,
+            cfg: cfg.clone(),
+            file,
+            segment_len,
+            next_sequence,
+            last_sync: Instant::now(),
+            dirty: false,
+        })
+    }
+
+    /// Sequence number the next appended record will get.
+    pub fn next_sequence(&self) -> u64 {
+        self.next_sequence
+    }
+
+    /// Append `event` and return its sequence number.
+    pub fn append(&mut self, event: &NormalizedEvent) -> Result<u64, IngestError> {
+        self.append_at(event, event.timestamp)
+    }
+
+    /// Append `event` timestamped `at` rather than with its own timestamp,
+    /// e.g. when it was spilled, and return its sequence number.
+    pub fn append_at(
+        &mut self,
+        event: &NormalizedEvent,
+        at: DateTime<Utc>,
+    ) -> Result<u64, IngestError> {
+        let sequence = self.next_sequence;
+        let json = serde_json::to_vec(event)?;
+        let mut body = Vec::with_capacity(PREFIX_LEN + json.len());
+        body.extend_from_slice(&sequence.to_le_bytes());
+        body.extend_from_slice(&at.timestamp_micros().to_le_bytes());
+        body.extend_from_slice(&json);
+        let mut record = Vec::with_capacity(HEADER_LEN + body.len());
+        record.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        record.extend_from_slice(&crc32fast::hash(&body).to_le_bytes());
+        record.extend_from_slice(&body);
+
+        if self.segment_len > 0 && self.segment_len + record.len() as u64 > self.cfg.segment_bytes {
+            self.rotate(sequence)?;
+        }
+        self.file.write_all(&record)?;
+        self.segment_len += record.len() as u64;
+        self.next_sequence += 1;
+        self.dirty = true;
+        match self.cfg.fsync {
+            FsyncPolicy::Always => self.sync()?,
+            FsyncPolicy::Interval => self.sync_if_due()?,
+            FsyncPolicy::Never => {}
+        }
+        Ok(sequence)
+    }
+
+    /// Leave the next sequence number out of the log, for an event numbered
+    /// along with the logged ones but not logged itself, and return it.
+    pub fn skip(&mut self) -> u64 {
+        self.next_sequence += 1;
+        self.next_sequence - 1
+    }
+
+    /// Number the next record `sequence` if that is ahead of the log, e.g. to
+    /// follow on from another instance's log. The record starts a segment.
+    pub fn skip_to(&mut self, sequence: u64) -> Result<(), IngestError> {
+        if sequence <= self.next_sequence {
+            return Ok(());
+        }
+        let empty = (self.segment_len == 0).then(|| segment_path(&self.dir, self.next_sequence));
+        self.rotate(sequence)?;
+        self.next_sequence = sequence;
+        if let Some(path) = empty {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Force appended records to disk.
+    pub fn sync(&mut self) -> Result<(), IngestError> {
+        if self.dirty {
+            self.file.sync_data()?;
+            self.dirty = false;
+        }
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+
+    /// Sync if the `interval` policy is due. Call this periodically so the
+    /// tail is synced even when no further events arrive.
+    pub fn sync_if_due(&mut self) -> Result<(), IngestError> {
+        let interval = Duration::from_millis(self.cfg.fsync_interval_ms);
+        if self.cfg.fsync == FsyncPolicy::Interval && self.last_sync.elapsed() >= interval {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    fn rotate(&mut self, first_sequence: u64) -> Result<(), IngestError> {
+        if self.cfg.fsync != FsyncPolicy::Never {
+            self.file.sync_data()?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(segment_path(&self.dir, first_sequence))?;
+        self.segment_len = 0;
+        self.dirty = false;
+        if let Some(keep) = self.cfg.retention_segments {
+            let segments = segments(&self.dir)?;
+            let excess = segments.len().saturating_sub(keep.max(1));
+            for (_, path) in &segments[..excess] {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Wal {
+    fn drop(&mut self) {
+        if self.cfg.fsync != FsyncPolicy::Never {
+            let _ = self.sync();
+        }
+    }
+}
+
+/// Reading side of the log. Readers open segment files independently of the
+/// writer and see every record that has been appended so far.
+#[derive(Clone)]
+pub struct WalReader {
+    dir: PathBuf,
+}
+
+impl WalReader {
+    pub fn open(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Entries with a sequence number of at least `sequence`, in order.
+    pub fn from_sequence(&self, sequence: u64) -> Result<Entries, IngestError> {
+        let segments = segments(&self.dir)?;
+        let start = segments
+            .iter()
+            .rposition(|(first, _)| *first <= sequence)
+            .unwrap_or(0);
+        Ok(Entries {
+            pending: segments
+                .into_iter()
+                .skip(start)
+                .map(|(_, path)| path)
+                .collect(),
+            current: None,
+            min_sequence: sequence,
+            window: None,
+        })
+    }
+
+    /// Entries whose event timestamp falls in `[from, to)`, in log order;
+    /// see [`Entries::window`].
+    pub fn range(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Entries, IngestError> {
+        Ok(self.from_sequence(0)?.window(from, to))
+    }
+}
+
+impl History for WalReader {
+    fn read(
+        &self,
+        sequence: u64,
+        limit: usize,
+    ) -> Result<Vec<(u64, NormalizedEvent)>, IngestError> {
+        self.from_sequence(sequence)?
+            .take(limit)
+            .map(|entry| entry.map(|entry| (entry.sequence, entry.event)))
+            .collect()
+    }
+}
+
+/// Iterator over logged entries, see [`WalReader`].
+pub struct Entries {
+    pending: VecDeque<PathBuf>,
+    current: Option<BufReader<File>>,
+    min_sequence: u64,
+    window: Option<(i64, i64)>,
+}
+
+impl Entries {
+    /// Only the entries whose timestamp falls in `[from, to)`. Reading stops
+    /// at the first record more than a minute past `to`, rather than going
+    /// through the rest of the log.
+    pub fn window(mut self, from: DateTime<Utc>, to: DateTime<Utc>) -> Self {
+        self.window = Some((from.timestamp_micros(), to.timestamp_micros()));
+        self
+    }
+
+    /// Whether `record` is past the window by more than records run out of
+    /// order.
+    fn beyond(&self, record: &Record) -> bool {
+        self.window
+            .is_some_and(|(_, to)| record.timestamp_us >= to.saturating_add(WINDOW_SLACK_US))
+    }
+
+    fn wanted(&self, record: &Record) -> bool {
+        record.sequence >= self.min_sequence
+            && self
+                .window
+                .is_none_or(|(from, to)| record.timestamp_us >= from && record.timestamp_us < to)
+    }
+}
+
+impl Iterator for Entries {
+    type Item = Result<Entry, IngestError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let reader = match self.current.as_mut() {
+                Some(reader) => reader,
+                None => {
+                    let path = self.pending.pop_front()?;
+                    match File::open(&path) {
+                        Ok(file) => self.current.insert(BufReader::new(file)),
+                        // Removed by retention since it was listed.
+                        Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                        Err(e) => return Some(Err(e.into())),
+                    }
+                }
+            };
+            match read_record(reader) {
+                Ok(Some(record)) if self.wanted(&record) => {
+                    let sequence = record.sequence;
+                    let timestamp =
+                        DateTime::from_timestamp_micros(record.timestamp_us).unwrap_or_default();
+                    return Some(
+                        serde_json::from_slice(&record.json)
+                            .map(|event| Entry {
+                                sequence,
+                                timestamp,
+                                event,
+                            })
+                            .map_err(Into::into),
+                    );
+                }
+                Ok(Some(record)) if self.beyond(&record) => {
+                    self.pending.clear();
+                    self.current = None;
+                    return None;
+                }
+                Ok(Some(_)) => {}
+                Ok(None) => self.current = None,
+                Err(e) => {
+                    self.current = None;
+                    return Some(Err(e.into()));
+                }
+            }
+        }
+    }
+}
+
+/// Events kept on disk until they are delivered: the spill of a bus
+/// subscriber or of a sink's retry buffer. Events are read back in order,
+/// those left by a previous run first, and the log is cleared once every
+/// event read back has been settled. With the bounds of a bus spill, the
+/// oldest segments are deleted once the log outgrows `max_bytes`, and
+/// events spilled more than `max_age_secs` ago are skipped when read back.
+pub struct SpillLog {
+    cfg: WalConfig,
+    wal: Wal,
+    reader: WalReader,
+    max_age: Option<chrono::Duration>,
+    /// Sequence of the next spilled event to read back.
+    next: u64,
+    /// Events read back and not settled yet.
+    outstanding: usize,
+    read_ahead: VecDeque<NormalizedEvent>,
+    lost: u64,
+}
+
+impl SpillLog {
+    /// The spill of a bus subscriber, within the bounds of `cfg`.
+    pub fn open(cfg: &BusSpillConfig) -> Result<Self, IngestError> {
+        let wal_cfg = WalConfig {
+            path: cfg.path.clone(),
+            fsync: FsyncPolicy::Never,
+            fsync_interval_ms: 1_000,
+            segment_bytes: (cfg.max_bytes / SPILL_SEGMENTS).max(1),
+            retention_segments: Some(SPILL_SEGMENTS as usize),
+        };
+        let max_age = cfg
+            .max_age_secs
+            .map(|secs| chrono::Duration::seconds(secs as i64));
+        Self::bounded(&wal_cfg, max_age)
+    }
+
+    /// A spill kept in the log `cfg` describes, never skipping an event.
+    pub fn open_log(cfg: &WalConfig) -> Result<Self, IngestError> {
+        Self::bounded(cfg, None)
+    }
+
+    fn bounded(cfg: &WalConfig, max_age: Option<chrono::Duration>) -> Result<Self, IngestError> {
+        let wal = Wal::open(cfg)?;
+        let reader = WalReader::open(&cfg.path);
+        let next = match reader.from_sequence(0)?.next() {
+            Some(entry) => entry?.sequence,
+            None => wal.next_sequence(),
+        };
+        Ok(Self {
+            cfg: cfg.clone(),
+            wal,
+            reader,
+            max_age,
+            next,
+            outstanding: 0,
+            read_ahead: VecDeque::new(),
+            lost: 0,
+        })
+    }
+
+    /// Spill `event`, timestamped with the time it was spilled.
+    pub fn append(&mut self, event: &NormalizedEvent) -> Result<(), IngestError> {
+        self.wal.append_at(event, Utc::now()).map(drop)
+    }
+
+    /// Events not read back yet.
+    pub fn len(&self) -> u64 {
+        let unread = self.wal.next_sequence().saturating_sub(self.next);
+        self.read_ahead.len() as u64 + unread
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Read back up to `limit` spilled events, oldest first, each to be
+    /// [settled](Self::settle) once delivered.
+    pub fn read_back(&mut self, limit: usize) -> Result<Vec<NormalizedEvent>, IngestError> {
+        let mut events = Vec::new();
+        let mut read = false;
+        for entry in self.reader.from_sequence(self.next)?.take(limit) {
+            let entry = entry?;
+            read = true;
+            // Anything skipped was deleted to stay within `max_bytes`.
+            self.lost += entry.sequence - self.next;
+            self.next = entry.sequence + 1;
+            if self
+                .max_age
+                .is_some_and(|age| Utc::now() - entry.timestamp > age)
+            {
+                self.lost += 1;
+                continue;
+            }
+            events.push(entry.event);
+        }
+        if !read && limit > 0 {
+            // What was not read back is gone, e.g. a torn tail.
+            self.lost += self.wal.next_sequence().saturating_sub(self.next);
+            self.next = self.wal.next_sequence();
+        }
+        self.outstanding += events.len();
+        self.clear_if_done()?;
+        Ok(events)
+    }
+
+    /// Note that `settled` events read back were delivered or given up.
+    pub fn settle(&mut self, settled: usize) -> Result<(), IngestError> {
+        self.outstanding = self.outstanding.saturating_sub(settled);
+        self.clear_if_done()
+    }
+
+    fn clear_if_done(&mut self) -> Result<(), IngestError> {
+        if self.outstanding == 0 && self.is_empty() && self.wal.next_sequence() > 1 {
+            // Everything spilled was settled, so start over with an empty log.
+            fs::remove_dir_all(&self.cfg.path)?;
+            self.wal = Wal::open(&self.cfg)?;
+            self.next = 1;
+        }
+        Ok(())
+    }
+
+    /// Events skipped to keep within the size or age bounds since the last
+    /// call.
+    pub fn take_lost(&mut self) -> u64 {
+        std::mem::take(&mut self.lost)
+    }
+
+    /// Sync the log if its `interval` policy is due.
+    pub fn sync_if_due(&mut self) -> Result<(), IngestError> {
+        self.wal.sync_if_due()
+    }
+
+    /// Replace the log with `held` followed by the events not read back
+    /// yet, so the next run reads them back in that order. The new log is
+    /// written aside and renamed over the old one, so a crash leaves one of
+    /// them whole.
+    pub fn keep(self, held: impl Iterator<Item = NormalizedEvent>) -> Result<(), IngestError> {
+        let mut held = held.peekable();
+        if held.peek().is_none() && self.wal.next_sequence() == 1 {
+            return Ok(());
+        }
+        let path = format!("{}.next", self.cfg.path);
+        let _ = fs::remove_dir_all(&path);
+        let mut kept = Wal::open(&WalConfig {
+            path: path.clone(),
+            ..self.cfg.clone()
+        })?;
+        let now = Utc::now();
+        for event in held.chain(self.read_ahead) {
+            kept.append_at(&event, now)?;
+        }
+        if self.next < self.wal.next_sequence() {
+            for entry in self.reader.from_sequence(self.next)? {
+                let entry = entry?;
+                kept.append_at(&entry.event, entry.timestamp)?;
+            }
+        }
+        kept.sync()?;
+        drop((kept, self.wal));
+        fs::remove_dir_all(&self.cfg.path)?;
+        fs::rename(&path, &self.cfg.path)?;
+        Ok(())
+    }
+}
+
+impl Spill for SpillLog {
+    fn append(&mut self, event: &NormalizedEvent) -> Result<(), IngestError> {
+        SpillLog::append(self, event)
+    }
+
+    fn pop(&mut self) -> Result<Option<NormalizedEvent>, IngestError> {
+        while self.read_ahead.is_empty() && !self.is_empty() {
+            let events = self.read_back(SPILL_READ_AHEAD)?;
+            self.read_ahead.extend(events);
+        }
+        let Some(event) = self.read_ahead.pop_front() else {
+            return Ok(None);
+        };
+        self.settle(1)?;
+        Ok(Some(event))
+    }
+
+    fn len(&self) -> u64 {
+        SpillLog::len(self)
+    }
+
+    fn take_lost(&mut self) -> u64 {
+        SpillLog::take_lost(self)
+    }
+}
+
+/// Republish every logged event timestamped in `[from, to)` onto the bus
+/// under its logged sequence, see [`EventPublisher::republish`], and return
+/// how many were replayed.
+pub fn replay(
+    reader: &WalReader,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    publisher: &EventPublisher,
+) -> Result<usize, IngestError> {
+    let mut replayed = 0;
+    for entry in reader.range(from, to)? {
+        let entry = entry?;
+        publisher.republish(entry.sequence, entry.event);
+        replayed += 1;
+    }
+    Ok(replayed)
+}
+
+struct Record {
+    sequence: u64,
+    timestamp_us: i64,
+    json: Vec<u8>,
+}
+
+impl Record {
+    fn framed_len(&self) -> u64 {
+        (HEADER_LEN + PREFIX_LEN + self.json.len()) as u64
+    }
+}
+
+/// Read the next record. `None` marks the end of the readable data: a clean
+/// end of file, a torn tail, or a corrupt record.
+fn read_record(reader: &mut impl Read) -> io::Result<Option<Record>> {
+    let mut header = [0u8; HEADER_LEN];
+    if !read_full(reader, &mut header)? {
+        return Ok(None);
+    }
+    let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+    let crc = u32::from_le_bytes(header[4..].try_into().unwrap());
+    if !(PREFIX_LEN..=MAX_RECORD_LEN).contains(&len) {
+        tracing::warn!("wal record with invalid length {}", len);
+        return Ok(None);
+    }
+    let mut body = vec![0u8; len];
+    if !read_full(reader, &mut body)? {
+        return Ok(None);
+    }
+    if crc32fast::hash(&body) != crc {
+        tracing::warn!("wal record failed its crc check");
+        return Ok(None);
+    }
+    Ok(Some(Record {
+        sequence: u64::from_le_bytes(body[..8].try_into().unwrap()),
+        timestamp_us: i64::from_le_bytes(body[8..16].try_into().unwrap()),
+        json: body.split_off(PREFIX_LEN),
+    }))
+}
+
+/// Fill `buf`, returning false if the data ends first.
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<bool> {
+    match reader.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Length of the valid prefix of a segment and the last sequence in it.
+fn recover(path: &Path) -> Result<(u64, Option<u64>), IngestError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut valid_len = 0;
+    let mut last = None;
+    while let Some(record) = read_record(&mut reader)? {
+        valid_len += record.framed_len();
+        last = Some(record.sequence);
+    }
+    Ok((valid_len, last))
+}
+
+fn segment_path(dir: &Path, first_sequence: u64) -> PathBuf {
+    dir.join(format!("{:020}.{}", first_sequence, EXTENSION))
+}
+
+/// Segments in `dir` with their first sequence number, oldest first.
+fn segments(dir: &Path) -> Result<Vec<(u64, PathBuf)>, IngestError> {
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some(EXTENSION) {
+            continue;
+        }
+        if let Some(first) = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| s.parse().ok())
+        {
+            segments.push((first, path));
+        }
+    }
+    segments.sort();
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ingest_core::event::EventKind;
+
+    fn cfg(name: &str) -> WalConfig {
+        let path = std::env::temp_dir().join(format!("ingest-wal-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        WalConfig {
+            path: path.to_string_lossy().into_owned(),
+            fsync: FsyncPolicy::Never,
+            fsync_interval_ms: 1_000,
+            segment_bytes: 1024 * 1024,
+            retention_segments: None,
+        }
+    }
+
+    fn event(seconds: i64) -> NormalizedEvent {
+        NormalizedEvent {
+            venue: "binance_spot".into(),
+            symbol: "BTCUSDT".into(),
+            timestamp: DateTime::from_timestamp(1_672_515_782 + seconds, 0).unwrap(),
+            received_at: None,
+            kind: EventKind::Trade,
+            payload: serde_json::json!({"price": 1.5}),
+        }
+    }
+
+    fn sequences(entries: Entries) -> Vec<u64> {
+        entries.map(|e| e.unwrap().sequence).collect()
+    }
+
+    #[test]
+    fn reads_back_across_segments() {
+        let cfg = WalConfig {
+            segment_bytes: 400,
+            ..cfg("segments")
+        };
+        let mut wal = Wal::open(&cfg).unwrap();
+        for i in 0..10 {
+            assert_eq!(wal.append(&event(i)).unwrap(), i as u64 + 1);
+        }
+        assert!(segments(Path::new(&cfg.path)).unwrap().len() > 1);
+
+        let reader = WalReader::open(&cfg.path);
+        let all: Vec<Entry> = reader
+            .from_sequence(0)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(all.len(), 10);
+        assert_eq!(all[3].event, event(3));
+        assert_eq!(
+            sequences(reader.from_sequence(7).unwrap()),
+            vec![7, 8, 9, 10]
+        );
+        fs::remove_dir_all(&cfg.path).unwrap();
+    }
+
+    #[test]
+    fn reopen_truncates_torn_tail_and_continues_sequence() {
+        let cfg = cfg("torn");
+        {
+            let mut wal = Wal::open(&cfg).unwrap();
+            for i in 0..3 {
+                wal.append(&event(i)).unwrap();
+            }
+        }
+        let (_, path) = segments(Path::new(&cfg.path)).unwrap().pop().unwrap();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[42, 0, 0, 0, 1, 2]).unwrap();
+
+        let mut wal = Wal::open(&cfg).unwrap();
+        assert_eq!(wal.next_sequence(), 4);
+        wal.append(&event(3)).unwrap();
+        let reader = WalReader::open(&cfg.path);
+        assert_eq!(
+            sequences(reader.from_sequence(0).unwrap()),
+            vec![1, 2, 3, 4]
+        );
+        fs::remove_dir_all(&cfg.path).unwrap();
+    }
+
+    #[test]
+    fn skip_to_continues_from_a_later_sequence() {
+        let cfg = cfg("skip");
+        let mut wal = Wal::open(&cfg).unwrap();
+        wal.append(&event(0)).unwrap();
+        wal.skip_to(1).unwrap();
+        assert_eq!(wal.next_sequence(), 2);
+        wal.skip_to(100).unwrap();
+        assert_eq!(wal.append(&event(1)).unwrap(), 100);
+        drop(wal);
+
+        let mut wal = Wal::open(&cfg).unwrap();
+        assert_eq!(wal.next_sequence(), 101);
+        wal.skip_to(200).unwrap();
+        wal.skip_to(300).unwrap();
+        wal.append(&event(2)).unwrap();
+        assert_eq!(segments(Path::new(&cfg.path)).unwrap().len(), 3);
+        let reader = WalReader::open(&cfg.path);
+        assert_eq!(
+            sequences(reader.from_sequence(0).unwrap()),
+            vec![1, 100, 300]
+        );
+        assert_eq!(sequences(reader.from_sequence(50).unwrap()), vec![100, 300]);
+        fs::remove_dir_all(&cfg.path).unwrap();
+    }
+
+    #[test]
+    fn skipped_sequences_are_left_out() {
+        let cfg = cfg("gaps");
+        let mut wal = Wal::open(&cfg).unwrap();
+        wal.append(&event(0)).unwrap();
+        assert_eq!(wal.skip(), 2);
+        assert_eq!(wal.append(&event(1)).unwrap(), 3);
+        let reader = WalReader::open(&cfg.path);
+        assert_eq!(sequences(reader.from_sequence(0).unwrap()), vec![1, 3]);
+        fs::remove_dir_all(&cfg.path).unwrap();
+    }
+
+    #[test]
+    fn range_selects_by_event_time() {
+        let cfg = cfg("range");
+        let mut wal = Wal::open(&cfg).unwrap();
+        for i in 0..6 {
+            wal.append(&event(i)).unwrap();
+        }
+        let reader = WalReader::open(&cfg.path);
+        let entries = reader
+            .range(event(2).timestamp, event(4).timestamp)
+            .unwrap();
+        assert_eq!(sequences(entries), vec![3, 4]);
+        fs::remove_dir_all(&cfg.path).unwrap();
+    }
+
+    #[test]
+    fn range_stops_well_past_its_end() {
+        let cfg = cfg("range-stop");
+        let mut wal = Wal::open(&cfg).unwrap();
+        for seconds in [2, 50, 3, 200, 3] {
+            wal.append(&event(seconds)).unwrap();
+        }
+        let reader = WalReader::open(&cfg.path);
+        let entries = reader
+            .range(event(2).timestamp, event(4).timestamp)
+            .unwrap();
+        assert_eq!(sequences(entries), vec![1, 3]);
+        fs::remove_dir_all(&cfg.path).unwrap();
+    }
+
+    #[test]
+    fn history_reads_a_limited_chunk() {
+        let cfg = cfg("history");
+        let mut wal = Wal::open(&cfg).unwrap();
+        for i in 0..6 {
+            wal.append(&event(i)).unwrap();
+        }
+        let reader = WalReader::open(&cfg.path);
+        let chunk = History::read(&reader, 3, 2).unwrap();
+        assert_eq!(chunk, vec![(3, event(2)), (4, event(3))]);
+        fs::remove_dir_all(&cfg.path).unwrap();
+    }
+
+    #[test]
+    fn spill_log_reads_back_within_its_bounds() {
+        let spill_cfg = |name| BusSpillConfig {
+            path: cfg(name).path,
+            max_bytes: 8 * 1024 * 1024,
+            max_age_secs: Some(60),
+        };
+        let aged = spill_cfg("spill-aged");
+        {
+            let mut spill = SpillLog::open(&aged).unwrap();
+            spill.append(&event(0)).unwrap();
+            let spilled_at = Utc::now() - chrono::Duration::minutes(2);
+            spill.wal.append_at(&event(1), spilled_at).unwrap();
+            spill.append(&event(2)).unwrap();
+        }
+        // What a previous run left behind is read back, minus what was
+        // spilled too long ago, however old the events themselves are.
+        let mut spill = SpillLog::open(&aged).unwrap();
+        assert_eq!(Spill::len(&spill), 3);
+        assert_eq!(spill.pop().unwrap(), Some(event(0)));
+        assert_eq!(spill.pop().unwrap(), Some(event(2)));
+        assert_eq!(spill.take_lost(), 1);
+        assert!(spill.pop().unwrap().is_none());
+        assert!(spill.is_empty());
+        assert_eq!(spill.wal.next_sequence(), 1);
+        fs::remove_dir_all(&aged.path).unwrap();
+
+        let sized = BusSpillConfig {
+            max_bytes: 800,
+            max_age_secs: None,
+            ..spill_cfg("spill-sized")
+        };
+        let mut spill = SpillLog::open(&sized).unwrap();
+        for i in 0..12 {
+            spill.append(&event(i)).unwrap();
+        }
+        assert_eq!(spill.pop().unwrap(), Some(event(4)));
+        assert_eq!(spill.take_lost(), 4);
+        fs::remove_dir_all(&sized.path).unwrap();
+    }
+
+    #[test]
+    fn retention_drops_oldest_segments() {
+        let cfg = WalConfig {
+            segment_bytes: 200,
+            retention_segments: Some(2),
+            ..cfg("retention")
+        };
+        let mut wal = Wal::open(&cfg).unwrap();
+        for i in 0..10 {
+            wal.append(&event(i)).unwrap();
+        }
+        let remaining = segments(Path::new(&cfg.path)).unwrap();
+        assert_eq!(remaining.len(), 2);
+        let reader = WalReader::open(&cfg.path);
+        let seqs = sequences(reader.from_sequence(0).unwrap());
+        assert_eq!(seqs.last(), Some(&10));
+        assert_eq!(seqs.first().copied(), Some(remaining[0].0));
+        fs::remove_dir_all(&cfg.path).unwrap();
+    }
+
+    #[test]
+    fn spill_log_is_cleared_once_what_was_read_back_is_settled() {
+        let cfg = cfg("spill-settle");
+        let mut spill = SpillLog::open_log(&cfg).unwrap();
+        for i in 0..3 {
+            spill.append(&event(i)).unwrap();
+        }
+        assert_eq!(spill.read_back(2).unwrap(), vec![event(0), event(1)]);
+        spill.settle(2).unwrap();
+        assert_eq!(spill.read_back(2).unwrap(), vec![event(2)]);
+        assert!(spill.is_empty());
+        // Read back, but not delivered yet.
+        assert_eq!(spill.wal.next_sequence(), 4);
+        spill.keep(std::iter::once(event(2))).unwrap();
+
+        let mut spill = SpillLog::open_log(&cfg).unwrap();
+        assert_eq!(spill.read_back(10).unwrap(), vec![event(2)]);
+        spill.settle(1).unwrap();
+        assert_eq!(spill.wal.next_sequence(), 1);
+        fs::remove_dir_all(&cfg.path).unwrap();
+    }
+}
@@ -0,0 +1,13 @@
+//! The pipeline normalizer and symbol canonicalization.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pipeline::{Canonicalize, Stage};
+
+fuzz_target!(|input: (&str, &str)| {
+    let (symbol, raw) = input;
+    if let Ok(event) = pipeline::normalize("fuzz", symbol, raw) {
+        let _ = Canonicalize.process(event);
+    }
+});
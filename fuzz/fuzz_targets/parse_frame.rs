@@ -0,0 +1,15 @@
+//! Every builtin adapter's parser, on text frames as venues send them.
+
+#![no_main]
+
+use agents::AdapterRegistry;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|frame: &str| {
+    let registry = AdapterRegistry::builtin();
+    for venue_type in registry.types() {
+        if let Some(adapter) = registry.get(venue_type) {
+            let _ = adapter.parse_frame(venue_type, frame);
+        }
+    }
+});